@@ -0,0 +1,160 @@
+//! `create-accounts` and `create-transfers`.
+//!
+//! Events are sent in batches as large as the cluster accepts, in file
+//! order. Only failed events are printed: one line each, or one JSON object
+//! each with `--json`, followed by a count on stderr.
+
+use std::fmt::Debug;
+
+use serde::Serialize;
+use tb_rs::{Account, Client, ClientError, Transfer};
+
+use crate::Outcome;
+
+/// An event the cluster did not create.
+#[derive(Debug, Serialize)]
+struct Failure {
+    /// Position of the event in the input.
+    index: u32,
+    id: String,
+    result: String,
+}
+
+/// Create `accounts`.
+pub async fn accounts(
+    client: &mut Client,
+    accounts: &[Account],
+    json: bool,
+) -> Result<Outcome, ClientError> {
+    let limit = client.max_batch_count::<Account>().unwrap_or(8189);
+    let mut failures = Vec::new();
+    for (batch, chunk) in accounts.chunks(limit as usize).enumerate() {
+        let offset = batch as u32 * limit;
+        let results = client.create_accounts(chunk).await?;
+        failures.extend(batch_failures(
+            chunk,
+            offset,
+            |a| a.id,
+            results.iter().map(|r| (r.index, r.result)),
+        ));
+    }
+    Ok(report("accounts", accounts.len(), &failures, json))
+}
+
+/// Create `transfers`.
+pub async fn transfers(
+    client: &mut Client,
+    transfers: &[Transfer],
+    json: bool,
+) -> Result<Outcome, ClientError> {
+    let limit = client.max_batch_count::<Transfer>().unwrap_or(8189);
+    let mut failures = Vec::new();
+    for (batch, chunk) in transfers.chunks(limit as usize).enumerate() {
+        let offset = batch as u32 * limit;
+        let results = client.create_transfers(chunk).await?;
+        failures.extend(batch_failures(
+            chunk,
+            offset,
+            |t| t.id,
+            results.iter().map(|r| (r.index, r.result)),
+        ));
+    }
+    Ok(report("transfers", transfers.len(), &failures, json))
+}
+
+/// The failures in the replies to `batch`, which starts at `offset` in the
+/// input; `results` are the `(index, result)` pairs of the reply.
+fn batch_failures<T, R: Debug>(
+    batch: &[T],
+    offset: u32,
+    id: impl Fn(&T) -> u128,
+    results: impl IntoIterator<Item = (u32, R)>,
+) -> Vec<Failure> {
+    results
+        .into_iter()
+        .map(|(index, result)| Failure {
+            index: offset + index,
+            id: format!("{:032x}", id(&batch[index as usize])),
+            result: format!("{:?}", result),
+        })
+        .collect()
+}
+
+impl Failure {
+    /// The line printed for the failure.
+    fn line(&self, json: bool) -> String {
+        if json {
+            serde_json::to_string(self).unwrap()
+        } else {
+            format!("{} {} {}", self.index, self.id, self.result)
+        }
+    }
+}
+
+fn report(kind: &str, count: usize, failures: &[Failure], json: bool) -> Outcome {
+    for failure in failures {
+        println!("{}", failure.line(json));
+    }
+    eprintln!("Created {} of {} {}", count - failures.len(), count, kind);
+    if failures.is_empty() {
+        Outcome::Done
+    } else {
+        Outcome::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tb_rs::{CreateAccountResult, CreateTransferResult};
+
+    #[test]
+    fn test_batch_failures() {
+        let accounts: Vec<Account> = (1..=5)
+            .map(|id| Account {
+                id,
+                ..Default::default()
+            })
+            .collect();
+        // The second batch of two: indexes in the reply are in the batch.
+        let results = [
+            (0, CreateAccountResult::Exists),
+            (1, CreateAccountResult::LinkedEventFailed),
+        ];
+        let failures = batch_failures(&accounts[2..4], 2, |a| a.id, results);
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].index, 2);
+        assert_eq!(failures[0].id, format!("{:032x}", 3));
+        assert_eq!(failures[0].result, "Exists");
+        assert_eq!(failures[1].index, 3);
+        assert_eq!(failures[1].id, format!("{:032x}", 4));
+        assert_eq!(failures[1].result, "LinkedEventFailed");
+
+        let transfers = [Transfer {
+            id: 0xab,
+            ..Default::default()
+        }];
+        let results: [(u32, CreateTransferResult); 0] = [];
+        assert_eq!(batch_failures(&transfers, 0, |t| t.id, results).len(), 0);
+    }
+
+    #[test]
+    fn test_report() {
+        let failure = Failure {
+            index: 7,
+            id: format!("{:032x}", 0xab),
+            result: "ExceedsCredits".to_string(),
+        };
+        assert_eq!(
+            failure.line(false),
+            "7 000000000000000000000000000000ab ExceedsCredits"
+        );
+        assert_eq!(
+            failure.line(true),
+            r#"{"index":7,"id":"000000000000000000000000000000ab","result":"ExceedsCredits"}"#
+        );
+
+        assert_eq!(report("transfers", 3, &[], false), Outcome::Done);
+        assert_eq!(report("transfers", 3, &[failure], true), Outcome::Failed);
+    }
+}
@@ -0,0 +1,256 @@
+//! JSON accounts and transfers, as read from `--file` and printed by `--json`.
+//!
+//! Fields are named as in TigerBeetle. 128-bit values are strings, as in
+//! tb-web's API: IDs and `user_data_128` in hex (an optional `0x` prefix is
+//! accepted), amounts and balances in decimal. Missing fields are zero, so
+//! `{"id": "1", "ledger": 1, "code": 1}` is a complete account.
+//!
+//! Input is a JSON array of objects or one object per line, so the output
+//! of `--json` can be fed back in.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tb_rs::{Account, AccountFlags, Transfer, TransferFlags};
+
+/// An account as JSON.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JsonAccount {
+    pub id: String,
+    pub debits_pending: String,
+    pub debits_posted: String,
+    pub credits_pending: String,
+    pub credits_posted: String,
+    pub user_data_128: String,
+    pub user_data_64: u64,
+    pub user_data_32: u32,
+    pub ledger: u32,
+    pub code: u16,
+    pub flags: u16,
+    pub timestamp: u64,
+}
+
+impl From<&Account> for JsonAccount {
+    fn from(a: &Account) -> Self {
+        Self {
+            id: format!("{:032x}", a.id),
+            debits_pending: a.debits_pending.to_string(),
+            debits_posted: a.debits_posted.to_string(),
+            credits_pending: a.credits_pending.to_string(),
+            credits_posted: a.credits_posted.to_string(),
+            user_data_128: format!("{:032x}", a.user_data_128),
+            user_data_64: a.user_data_64,
+            user_data_32: a.user_data_32,
+            ledger: a.ledger,
+            code: a.code,
+            flags: a.flags.bits(),
+            timestamp: a.timestamp,
+        }
+    }
+}
+
+impl TryFrom<JsonAccount> for Account {
+    type Error = String;
+
+    fn try_from(a: JsonAccount) -> Result<Self, String> {
+        Ok(Account {
+            id: hex("id", &a.id)?,
+            debits_pending: decimal("debits_pending", &a.debits_pending)?,
+            debits_posted: decimal("debits_posted", &a.debits_posted)?,
+            credits_pending: decimal("credits_pending", &a.credits_pending)?,
+            credits_posted: decimal("credits_posted", &a.credits_posted)?,
+            user_data_128: hex("user_data_128", &a.user_data_128)?,
+            user_data_64: a.user_data_64,
+            user_data_32: a.user_data_32,
+            ledger: a.ledger,
+            code: a.code,
+            flags: AccountFlags::from_bits_retain(a.flags),
+            timestamp: a.timestamp,
+            reserved: 0,
+        })
+    }
+}
+
+/// A transfer as JSON.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JsonTransfer {
+    pub id: String,
+    pub debit_account_id: String,
+    pub credit_account_id: String,
+    pub amount: String,
+    pub pending_id: String,
+    pub user_data_128: String,
+    pub user_data_64: u64,
+    pub user_data_32: u32,
+    pub timeout: u32,
+    pub ledger: u32,
+    pub code: u16,
+    pub flags: u16,
+    pub timestamp: u64,
+}
+
+impl From<&Transfer> for JsonTransfer {
+    fn from(t: &Transfer) -> Self {
+        Self {
+            id: format!("{:032x}", t.id),
+            debit_account_id: format!("{:032x}", t.debit_account_id),
+            credit_account_id: format!("{:032x}", t.credit_account_id),
+            amount: t.amount.to_string(),
+            pending_id: format!("{:032x}", t.pending_id),
+            user_data_128: format!("{:032x}", t.user_data_128),
+            user_data_64: t.user_data_64,
+            user_data_32: t.user_data_32,
+            timeout: t.timeout,
+            ledger: t.ledger,
+            code: t.code,
+            flags: t.flags.bits(),
+            timestamp: t.timestamp,
+        }
+    }
+}
+
+impl TryFrom<JsonTransfer> for Transfer {
+    type Error = String;
+
+    fn try_from(t: JsonTransfer) -> Result<Self, String> {
+        Ok(Transfer {
+            id: hex("id", &t.id)?,
+            debit_account_id: hex("debit_account_id", &t.debit_account_id)?,
+            credit_account_id: hex("credit_account_id", &t.credit_account_id)?,
+            amount: decimal("amount", &t.amount)?,
+            pending_id: hex("pending_id", &t.pending_id)?,
+            user_data_128: hex("user_data_128", &t.user_data_128)?,
+            user_data_64: t.user_data_64,
+            user_data_32: t.user_data_32,
+            timeout: t.timeout,
+            ledger: t.ledger,
+            code: t.code,
+            flags: TransferFlags::from_bits_retain(t.flags),
+            timestamp: t.timestamp,
+        })
+    }
+}
+
+/// Parse a hex ID, as printed by `--json` and tb-web.
+pub fn parse_id(s: &str) -> Result<u128, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    u128::from_str_radix(digits, 16).map_err(|_| format!("invalid ID: {}", s))
+}
+
+fn hex(field: &str, s: &str) -> Result<u128, String> {
+    if s.is_empty() {
+        return Ok(0);
+    }
+    parse_id(s).map_err(|e| format!("{}: {}", field, e))
+}
+
+fn decimal(field: &str, s: &str) -> Result<u128, String> {
+    if s.is_empty() {
+        return Ok(0);
+    }
+    s.parse()
+        .map_err(|_| format!("{}: invalid amount: {}", field, s))
+}
+
+/// Parse `text` as a JSON array of records or one record per line.
+pub fn parse<J, T>(text: &str) -> Result<Vec<T>, String>
+where
+    J: DeserializeOwned,
+    T: TryFrom<J, Error = String>,
+{
+    let records: Vec<J> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(text).map_err(|e| e.to_string())?
+    } else {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e))
+            })
+            .collect::<Result<_, _>>()?
+    };
+    records
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| T::try_from(record).map_err(|e| format!("record {}: {}", i, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_roundtrip() {
+        let account = Account {
+            id: u128::MAX - 1,
+            credits_posted: 1 << 100,
+            user_data_128: 0xabc,
+            user_data_64: 7,
+            ledger: 1,
+            code: 2,
+            flags: AccountFlags::HISTORY,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&JsonAccount::from(&account)).unwrap();
+        let parsed: Vec<Account> = parse::<JsonAccount, _>(&json).unwrap();
+        assert_eq!(parsed, vec![account]);
+    }
+
+    #[test]
+    fn test_transfer_roundtrip() {
+        let transfer = Transfer {
+            id: 1,
+            debit_account_id: 2,
+            credit_account_id: 3,
+            amount: u128::MAX,
+            pending_id: 4,
+            timeout: 60,
+            ledger: 1,
+            code: 1,
+            flags: TransferFlags::PENDING,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&JsonTransfer::from(&transfer)).unwrap();
+        let parsed: Vec<Transfer> = parse::<JsonTransfer, _>(&json).unwrap();
+        assert_eq!(parsed, vec![transfer]);
+    }
+
+    #[test]
+    fn test_parse_array_and_lines() {
+        let array = r#"[{"id": "0x1", "ledger": 1, "code": 1}, {"id": "ff", "ledger": 2}]"#;
+        let lines =
+            "{\"id\": \"0x1\", \"ledger\": 1, \"code\": 1}\n\n{\"id\": \"ff\", \"ledger\": 2}\n";
+        for text in [array, lines] {
+            let accounts: Vec<Account> = parse::<JsonAccount, _>(text).unwrap();
+            assert_eq!(accounts.len(), 2);
+            assert_eq!(
+                (accounts[0].id, accounts[0].ledger, accounts[0].code),
+                (1, 1, 1)
+            );
+            assert_eq!(
+                (accounts[1].id, accounts[1].ledger, accounts[1].code),
+                (0xff, 2, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let bad_id = r#"{"id": "xyz"}"#;
+        assert_eq!(
+            parse::<JsonAccount, Account>(bad_id).unwrap_err(),
+            "record 0: id: invalid ID: xyz"
+        );
+        let bad_amount = r#"[{"id": "1"}, {"id": "2", "amount": "-5"}]"#;
+        assert_eq!(
+            parse::<JsonTransfer, Transfer>(bad_amount).unwrap_err(),
+            "record 1: amount: invalid amount: -5"
+        );
+        let unknown = "{\"id\": \"1\", \"colour\": 3}";
+        assert!(parse::<JsonAccount, Account>(unknown)
+            .unwrap_err()
+            .starts_with("line 1: unknown field `colour`"));
+    }
+}
@@ -0,0 +1,103 @@
+//! `lookup-accounts` and `lookup-transfers`.
+//!
+//! Records are printed in the order their IDs were given, one line each, or
+//! one JSON object each with `--json`. IDs that were not found are named on
+//! stderr.
+
+use tb_rs::{Account, Client, ClientError, Transfer};
+
+use crate::output::{self, Format, Record};
+use crate::Outcome;
+
+/// Look up and print the accounts with `ids`.
+pub async fn accounts(
+    client: &mut Client,
+    ids: &[u128],
+    json: bool,
+) -> Result<Outcome, ClientError> {
+    let mut found = Vec::with_capacity(ids.len());
+    let limit = client.max_batch_count::<Account>().unwrap_or(8189);
+    for chunk in ids.chunks(limit as usize) {
+        found.extend(client.lookup_accounts(chunk).await?);
+    }
+    Ok(print(ids, &found, |a| a.id, json))
+}
+
+/// Look up and print the transfers with `ids`.
+pub async fn transfers(
+    client: &mut Client,
+    ids: &[u128],
+    json: bool,
+) -> Result<Outcome, ClientError> {
+    let mut found = Vec::with_capacity(ids.len());
+    let limit = client.max_batch_count::<Transfer>().unwrap_or(8189);
+    for chunk in ids.chunks(limit as usize) {
+        found.extend(client.lookup_transfers(chunk).await?);
+    }
+    Ok(print(ids, &found, |t| t.id, json))
+}
+
+fn print<T: Record>(ids: &[u128], found: &[T], id: impl Fn(&T) -> u128, json: bool) -> Outcome {
+    let format = if json { Format::Json } else { Format::Text };
+    let mut outcome = Outcome::Done;
+    for (wanted, record) in in_order(ids, found, id) {
+        match record {
+            Some(record) => output::print(format, record),
+            None => {
+                eprintln!("Not found: {:032x}", wanted);
+                outcome = Outcome::Failed;
+            }
+        }
+    }
+    outcome
+}
+
+/// Each of `ids` with its record in `found`, if there is one.
+fn in_order<'a, T>(
+    ids: &[u128],
+    found: &'a [T],
+    id: impl Fn(&T) -> u128,
+) -> Vec<(u128, Option<&'a T>)> {
+    ids.iter()
+        .map(|&wanted| (wanted, found.iter().find(|record| id(record) == wanted)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: u128) -> Account {
+        Account {
+            id,
+            ledger: 1,
+            code: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_in_order() {
+        // Lookups reply in their own order, without the missing.
+        let found = [account(3), account(1)];
+        let records: Vec<_> = in_order(&[1, 2, 3, 1], &found, |a| a.id)
+            .into_iter()
+            .map(|(id, record)| (id, record.map(|a| a.id)))
+            .collect();
+        assert_eq!(
+            records,
+            [(1, Some(1)), (2, None), (3, Some(3)), (1, Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let found = [account(1)];
+        assert_eq!(print(&[1], &found, |a| a.id, false), Outcome::Done);
+        assert_eq!(print(&[1, 2], &found, |a| a.id, true), Outcome::Failed);
+        assert_eq!(
+            print::<Transfer>(&[5], &[], |t| t.id, false),
+            Outcome::Failed
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! tb-cli: command-line client for TigerBeetle.
+//!
+//! Every subcommand sends its requests and exits, for shell pipelines and
+//! runbooks:
+//!
+//! ```text
+//! # Create accounts or transfers from a JSON file (or stdin, with -)
+//! tb-cli create-accounts --file accounts.json
+//! tb-cli create-transfers --file transfers.json --json
+//!
+//! # Look records up by hex ID
+//! tb-cli lookup-accounts 1 2 --json
+//! tb-cli lookup-transfers 0x1a
+//!
+//! # Page through every match of a filter, as CSV or JSON (see `query`)
+//! tb-cli query transfers --ledger 1 --code 7 --since 2024-01-01 --limit 500 --reversed
+//! tb-cli query transfers --account 1a --debits --format csv > debits.csv
+//! tb-cli query accounts --user-data-64 7001 --format json
+//!
+//! # Follow new transfers on a ledger as they are created (see `watch`)
+//! tb-cli watch --ledger 1
+//! ```
+//!
+//! See `json` for the record format. `--json` prints one JSON object per
+//! line, which the create commands read back.
+//!
+//! The exit code is 0 when every event was created and every ID found, 1
+//! when some event failed or some ID was not found, and 2 on any other
+//! error.
+
+mod create;
+mod json;
+mod lookup;
+mod output;
+mod query;
+mod time;
+mod watch;
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use tb_rs::{Account, Client, Transfer};
+
+use json::{JsonAccount, JsonTransfer};
+
+/// Command-line client for TigerBeetle.
+#[derive(Parser, Debug)]
+#[command(name = "tb-cli")]
+#[command(about = "Command-line client for TigerBeetle", long_about = None)]
+struct Args {
+    /// TigerBeetle replica addresses, comma-separated
+    #[arg(short, long, default_value = "127.0.0.1:3000", global = true)]
+    address: String,
+
+    /// Cluster ID
+    #[arg(short, long, default_value_t = 0, global = true)]
+    cluster: u128,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create the accounts in a JSON file; prints the failed ones
+    CreateAccounts {
+        /// JSON array or one object per line; - reads stdin
+        #[arg(long, value_name = "PATH", default_value = "-")]
+        file: PathBuf,
+
+        /// Print failures as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Create the transfers in a JSON file; prints the failed ones
+    CreateTransfers {
+        /// JSON array or one object per line; - reads stdin
+        #[arg(long, value_name = "PATH", default_value = "-")]
+        file: PathBuf,
+
+        /// Print failures as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Look up accounts by ID
+    LookupAccounts {
+        /// Hex account IDs
+        #[arg(required = true, value_name = "ID", value_parser = json::parse_id)]
+        ids: Vec<u128>,
+
+        /// Print accounts as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Look up transfers by ID
+    LookupTransfers {
+        /// Hex transfer IDs
+        #[arg(required = true, value_name = "ID", value_parser = json::parse_id)]
+        ids: Vec<u128>,
+
+        /// Print transfers as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Find accounts or transfers by filter
+    Query {
+        #[command(subcommand)]
+        target: query::Target,
+    },
+    /// Print new transfers as they are created, until interrupted
+    Watch(watch::Watch),
+}
+
+/// How a command went, for the exit code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// Every event was created and every record found.
+    Done,
+    /// Some event failed or some record was not found.
+    Failed,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match tokio_uring::start(run(args)) {
+        Ok(Outcome::Done) => ExitCode::SUCCESS,
+        Ok(Outcome::Failed) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<Outcome, Box<dyn Error>> {
+    // Read input first, so a bad file fails before connecting.
+    let mut client;
+    let outcome = match &args.command {
+        Command::CreateAccounts { file, json } => {
+            let accounts: Vec<Account> = json::parse::<JsonAccount, _>(&read_input(file)?)?;
+            client = connect(&args).await?;
+            create::accounts(&mut client, &accounts, *json).await?
+        }
+        Command::CreateTransfers { file, json } => {
+            let transfers: Vec<Transfer> = json::parse::<JsonTransfer, _>(&read_input(file)?)?;
+            client = connect(&args).await?;
+            create::transfers(&mut client, &transfers, *json).await?
+        }
+        Command::LookupAccounts { ids, json } => {
+            client = connect(&args).await?;
+            lookup::accounts(&mut client, ids, *json).await?
+        }
+        Command::LookupTransfers { ids, json } => {
+            client = connect(&args).await?;
+            lookup::transfers(&mut client, ids, *json).await?
+        }
+        Command::Query { target } => {
+            client = connect(&args).await?;
+            query::run(&mut client, target).await?;
+            Outcome::Done
+        }
+        Command::Watch(watch) => {
+            client = connect(&args).await?;
+            watch::run(&mut client, watch).await?;
+            Outcome::Done
+        }
+    };
+    client.close().await;
+    Ok(outcome)
+}
+
+async fn connect(args: &Args) -> Result<Client, Box<dyn Error>> {
+    Client::connect(args.cluster, &args.address)
+        .await
+        .map_err(|e| format!("connecting to {}: {}", args.address, e).into())
+}
+
+/// Read `path`, or stdin for `-`.
+fn read_input(path: &Path) -> Result<String, String> {
+    if path == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("stdin: {}", e))?;
+        return Ok(text);
+    }
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(["tb-cli"].iter().chain(args))
+    }
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tb-cli-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+
+        let args = parse(&["lookup-accounts", "1", "0x1A", "ff", "--json"]).unwrap();
+        let Command::LookupAccounts { ids, json } = args.command else {
+            panic!("expected lookup-accounts");
+        };
+        assert_eq!(ids, [1, 0x1a, 0xff]);
+        assert!(json);
+
+        // Global flags go before or after the subcommand; input is stdin.
+        let args = parse(&["create-transfers", "-a", "10.0.0.1:3001", "--cluster", "7"]).unwrap();
+        assert_eq!(args.address, "10.0.0.1:3001");
+        assert_eq!(args.cluster, 7);
+        let Command::CreateTransfers { file, json } = args.command else {
+            panic!("expected create-transfers");
+        };
+        assert_eq!(file, Path::new("-"));
+        assert!(!json);
+
+        for bad in [
+            &["lookup-transfers"][..],
+            &["lookup-transfers", "xyz"],
+            &["lookup-accounts", "1", "--file", "a.json"],
+            &[],
+        ] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_read_input() {
+        let path = temp_file("input.json", "[]");
+        assert_eq!(read_input(&path).unwrap(), "[]");
+        fs::remove_file(&path).unwrap();
+
+        let error = read_input(&path).unwrap_err();
+        assert!(error.starts_with(&path.display().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn test_run_reads_input_first() {
+        // Nothing listens on the address: each fails before connecting.
+        let missing = std::env::temp_dir().join("tb-cli-missing.json");
+        let invalid = temp_file(
+            "invalid.json",
+            r#"{"id": "1", "ledger": 1, "code": 1, "nope": 2}"#,
+        );
+        let bad_id = temp_file("bad-id.json", r#"[{"id": "xyz", "ledger": 1}]"#);
+
+        let cases = [
+            ("create-accounts", &missing, "tb-cli-missing.json"),
+            ("create-accounts", &invalid, "unknown field `nope`"),
+            ("create-transfers", &bad_id, "record 0: id: invalid ID: xyz"),
+        ];
+        for (command, path, error) in cases {
+            let path = path.to_str().unwrap();
+            let args = parse(&[command, "--file", path, "-a", "127.0.0.1:1"]).unwrap();
+            let message = tokio_uring::start(run(args)).unwrap_err().to_string();
+            assert!(message.contains(error), "{}: {}", command, message);
+        }
+
+        fs::remove_file(invalid).unwrap();
+        fs::remove_file(bad_id).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+//! Printing accounts and transfers as text lines, JSON objects or CSV rows.
+//!
+//! JSON is the format of `json`, one object per line. CSV has the same
+//! columns under a header row, with the same hex IDs and decimal amounts.
+
+use clap::ValueEnum;
+use tb_rs::{Account, Transfer};
+
+use crate::json::{JsonAccount, JsonTransfer};
+
+/// How to print records.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Format {
+    /// One line per record, for reading
+    Text,
+    /// One JSON object per line
+    Json,
+    /// CSV with a header row
+    Csv,
+}
+
+/// A record that can be printed.
+pub trait Record {
+    /// CSV header row.
+    const HEADER: &'static str;
+
+    fn text(&self) -> String;
+    fn json(&self) -> String;
+    fn csv(&self) -> String;
+}
+
+impl Record for Account {
+    const HEADER: &'static str = "id,debits_pending,debits_posted,credits_pending,\
+        credits_posted,user_data_128,user_data_64,user_data_32,ledger,code,flags,timestamp";
+
+    fn text(&self) -> String {
+        format!(
+            "{:032x} ledger {} code {} debits {} ({} pending) credits {} ({} pending){}",
+            self.id,
+            self.ledger,
+            self.code,
+            self.debits_posted,
+            self.debits_pending,
+            self.credits_posted,
+            self.credits_pending,
+            flags(self.flags.iter_names().map(|(name, _)| name))
+        )
+    }
+
+    fn json(&self) -> String {
+        serde_json::to_string(&JsonAccount::from(self)).unwrap()
+    }
+
+    fn csv(&self) -> String {
+        let a = JsonAccount::from(self);
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            a.id,
+            a.debits_pending,
+            a.debits_posted,
+            a.credits_pending,
+            a.credits_posted,
+            a.user_data_128,
+            a.user_data_64,
+            a.user_data_32,
+            a.ledger,
+            a.code,
+            a.flags,
+            a.timestamp
+        )
+    }
+}
+
+impl Record for Transfer {
+    const HEADER: &'static str = "id,debit_account_id,credit_account_id,amount,pending_id,\
+        user_data_128,user_data_64,user_data_32,timeout,ledger,code,flags,timestamp";
+
+    fn text(&self) -> String {
+        format!(
+            "{:032x} {:032x} -> {:032x} amount {} ledger {} code {}{}",
+            self.id,
+            self.debit_account_id,
+            self.credit_account_id,
+            self.amount,
+            self.ledger,
+            self.code,
+            flags(self.flags.iter_names().map(|(name, _)| name))
+        )
+    }
+
+    fn json(&self) -> String {
+        serde_json::to_string(&JsonTransfer::from(self)).unwrap()
+    }
+
+    fn csv(&self) -> String {
+        let t = JsonTransfer::from(self);
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            t.id,
+            t.debit_account_id,
+            t.credit_account_id,
+            t.amount,
+            t.pending_id,
+            t.user_data_128,
+            t.user_data_64,
+            t.user_data_32,
+            t.timeout,
+            t.ledger,
+            t.code,
+            t.flags,
+            t.timestamp
+        )
+    }
+}
+
+fn flags<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    let names: Vec<String> = names.map(str::to_lowercase).collect();
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!(" flags {}", names.join("|"))
+    }
+}
+
+/// Print what comes before the records of type `T`.
+pub fn header<T: Record>(format: Format) {
+    if format == Format::Csv {
+        println!("{}", T::HEADER);
+    }
+}
+
+/// Print one record.
+pub fn print<T: Record>(format: Format, record: &T) {
+    match format {
+        Format::Text => println!("{}", record.text()),
+        Format::Json => println!("{}", record.json()),
+        Format::Csv => println!("{}", record.csv()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tb_rs::{AccountFlags, TransferFlags};
+
+    #[test]
+    fn test_account_text() {
+        let account = Account {
+            id: 1,
+            debits_posted: 5,
+            credits_posted: 7,
+            credits_pending: 2,
+            ledger: 3,
+            code: 4,
+            flags: AccountFlags::LINKED | AccountFlags::HISTORY,
+            ..Default::default()
+        };
+        assert_eq!(
+            account.text(),
+            "00000000000000000000000000000001 ledger 3 code 4 debits 5 (0 pending) \
+             credits 7 (2 pending) flags linked|history"
+        );
+    }
+
+    #[test]
+    fn test_transfer_text() {
+        let transfer = Transfer {
+            id: 0xa,
+            debit_account_id: 1,
+            credit_account_id: 2,
+            amount: 100,
+            ledger: 1,
+            code: 9,
+            ..Default::default()
+        };
+        assert_eq!(
+            transfer.text(),
+            "0000000000000000000000000000000a 00000000000000000000000000000001 -> \
+             00000000000000000000000000000002 amount 100 ledger 1 code 9"
+        );
+        let pending = Transfer {
+            flags: TransferFlags::PENDING,
+            ..transfer
+        };
+        assert!(pending.text().ends_with(" flags pending"));
+    }
+
+    #[test]
+    fn test_csv_matches_header() {
+        let account = Account {
+            id: 0xff,
+            credits_posted: 12,
+            ledger: 1,
+            timestamp: 99,
+            ..Default::default()
+        };
+        assert_eq!(
+            account.csv(),
+            "000000000000000000000000000000ff,0,0,0,12,\
+             00000000000000000000000000000000,0,0,1,0,0,99"
+        );
+        let transfer = Transfer::default();
+        for (header, row) in [
+            (Account::HEADER, account.csv()),
+            (Transfer::HEADER, transfer.csv()),
+        ] {
+            assert_eq!(header.split(',').count(), row.split(',').count());
+        }
+    }
+}
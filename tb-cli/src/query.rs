@@ -0,0 +1,390 @@
+//! `query accounts` and `query transfers`.
+//!
+//! The filter flags map onto a QueryFilter, or for `query transfers
+//! --account` onto an AccountFilter over that account's transfers. Results
+//! come a page at a time, each page starting just past the last timestamp
+//! of the one before, until `--limit` records are printed or none are left.
+//! `--since` and `--until` take the times of `time`.
+
+use clap::{Args, Subcommand};
+use tb_rs::{
+    Account, AccountFilter, AccountFilterFlags, Client, ClientError, QueryFilter, QueryFilterFlags,
+    Transfer,
+};
+
+use crate::json;
+use crate::output::{self, Format};
+use crate::time;
+
+/// What to query.
+#[derive(Subcommand, Debug)]
+pub enum Target {
+    /// Query accounts
+    Accounts {
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Only accounts on this ledger
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        ledger: Option<u32>,
+    },
+    /// Query transfers, or an account's transfers with --account
+    Transfers {
+        #[command(flatten)]
+        filters: Filters,
+
+        #[command(flatten)]
+        scope: Scope,
+    },
+}
+
+/// Fields records must match.
+#[derive(Args, Debug)]
+pub struct Fields {
+    /// Only records with this code
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    code: Option<u16>,
+
+    /// Only records with this user_data_128 (hex)
+    #[arg(long, value_name = "HEX", value_parser = json::parse_id)]
+    user_data_128: Option<u128>,
+
+    /// Only records with this user_data_64
+    #[arg(long)]
+    user_data_64: Option<u64>,
+
+    /// Only records with this user_data_32
+    #[arg(long)]
+    user_data_32: Option<u32>,
+}
+
+/// Filters shared by accounts and transfers.
+#[derive(Args, Debug)]
+pub struct Filters {
+    #[command(flatten)]
+    fields: Fields,
+
+    /// Only records created at or after this time
+    #[arg(long, value_name = "TIME", value_parser = time::parse)]
+    since: Option<u64>,
+
+    /// Only records created before this time
+    #[arg(long, value_name = "TIME", value_parser = time::parse)]
+    until: Option<u64>,
+
+    /// Print at most this many records
+    #[arg(long)]
+    limit: Option<u64>,
+
+    /// Newest first
+    #[arg(long)]
+    reversed: bool,
+
+    /// How to print the records
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+/// Which transfers: on a ledger, or of an account.
+#[derive(Args, Debug)]
+pub struct Scope {
+    /// Only transfers on this ledger
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with = "account"
+    )]
+    ledger: Option<u32>,
+
+    /// Only transfers debiting or crediting this account (hex ID)
+    #[arg(long, value_name = "ID", value_parser = json::parse_id)]
+    account: Option<u128>,
+
+    /// With --account, only transfers debiting it
+    #[arg(long, requires = "account", conflicts_with = "credits")]
+    debits: bool,
+
+    /// With --account, only transfers crediting it
+    #[arg(long, requires = "account")]
+    credits: bool,
+}
+
+/// Where transfers come from: a query, or an account's transfers.
+pub enum Transfers {
+    Query(QueryFilter),
+    Account(AccountFilter),
+}
+
+impl Transfers {
+    pub fn new(scope: &Scope, fields: &Fields, reversed: bool) -> Self {
+        let Some(account_id) = scope.account else {
+            return Transfers::Query(QueryFilter {
+                ledger: scope.ledger.unwrap_or(0),
+                ..query_filter(fields, reversed)
+            });
+        };
+        let mut flags = match (scope.debits, scope.credits) {
+            (true, false) => AccountFilterFlags::DEBITS,
+            (false, true) => AccountFilterFlags::CREDITS,
+            _ => AccountFilterFlags::DEBITS | AccountFilterFlags::CREDITS,
+        };
+        if reversed {
+            flags |= AccountFilterFlags::REVERSED;
+        }
+        Transfers::Account(AccountFilter {
+            account_id,
+            user_data_128: fields.user_data_128.unwrap_or(0),
+            user_data_64: fields.user_data_64.unwrap_or(0),
+            user_data_32: fields.user_data_32.unwrap_or(0),
+            code: fields.code.unwrap_or(0),
+            flags,
+            ..Default::default()
+        })
+    }
+
+    /// Fetch up to `limit` transfers with timestamps in `range` (inclusive;
+    /// zero is unbounded).
+    pub async fn fetch(
+        &self,
+        client: &mut Client,
+        (timestamp_min, timestamp_max): (u64, u64),
+        limit: u32,
+    ) -> Result<Vec<Transfer>, ClientError> {
+        match *self {
+            Transfers::Query(filter) => {
+                client
+                    .query_transfers(QueryFilter {
+                        timestamp_min,
+                        timestamp_max,
+                        limit,
+                        ..filter
+                    })
+                    .await
+            }
+            Transfers::Account(filter) => {
+                client
+                    .get_account_transfers(AccountFilter {
+                        timestamp_min,
+                        timestamp_max,
+                        limit,
+                        ..filter
+                    })
+                    .await
+            }
+        }
+    }
+}
+
+/// Run a query and print its results.
+pub async fn run(client: &mut Client, target: &Target) -> Result<(), ClientError> {
+    match target {
+        Target::Accounts { filters, ledger } => {
+            let filter = QueryFilter {
+                ledger: ledger.unwrap_or(0),
+                ..query_filter(&filters.fields, filters.reversed)
+            };
+            let page_max = client.max_batch_count::<Account>().unwrap_or(8189);
+            output::header::<Account>(filters.format);
+            let mut window = Window::new(filters, page_max);
+            while let Some(limit) = window.limit() {
+                let (timestamp_min, timestamp_max) = window.range();
+                let page = client
+                    .query_accounts(QueryFilter {
+                        timestamp_min,
+                        timestamp_max,
+                        limit,
+                        ..filter
+                    })
+                    .await?;
+                for account in &page {
+                    output::print(filters.format, account);
+                }
+                window.advance(limit, page.len(), page.last().map(|a| a.timestamp));
+            }
+            eprintln!("Found {} accounts", window.printed);
+            Ok(())
+        }
+        Target::Transfers { filters, scope } => {
+            let transfers = Transfers::new(scope, &filters.fields, filters.reversed);
+            let page_max = client.max_batch_count::<Transfer>().unwrap_or(8189);
+            output::header::<Transfer>(filters.format);
+            let mut window = Window::new(filters, page_max);
+            while let Some(limit) = window.limit() {
+                let page = transfers.fetch(client, window.range(), limit).await?;
+                for transfer in &page {
+                    output::print(filters.format, transfer);
+                }
+                window.advance(limit, page.len(), page.last().map(|t| t.timestamp));
+            }
+            eprintln!("Found {} transfers", window.printed);
+            Ok(())
+        }
+    }
+}
+
+/// The QueryFilter for `fields`, without a ledger, timestamp range or limit.
+fn query_filter(fields: &Fields, reversed: bool) -> QueryFilter {
+    QueryFilter {
+        user_data_128: fields.user_data_128.unwrap_or(0),
+        user_data_64: fields.user_data_64.unwrap_or(0),
+        user_data_32: fields.user_data_32.unwrap_or(0),
+        code: fields.code.unwrap_or(0),
+        flags: if reversed {
+            QueryFilterFlags::REVERSED
+        } else {
+            QueryFilterFlags::empty()
+        },
+        ..Default::default()
+    }
+}
+
+/// The timestamps left to query, moving one page at a time.
+#[derive(Debug)]
+struct Window {
+    /// Inclusive bounds; zero is unbounded, as in filters.
+    min: u64,
+    max: u64,
+    reversed: bool,
+    /// Records still wanted, if limited.
+    remaining: Option<u64>,
+    page_max: u32,
+    printed: u64,
+    done: bool,
+}
+
+impl Window {
+    fn new(filters: &Filters, page_max: u32) -> Self {
+        let (max, empty) = match filters.until {
+            // Timestamps start at 1, and a zero bound would lift the limit.
+            Some(until) if until <= 1 => (0, true),
+            Some(until) => (until - 1, filters.since.is_some_and(|since| since >= until)),
+            None => (0, false),
+        };
+        Window {
+            min: filters.since.unwrap_or(0),
+            max,
+            reversed: filters.reversed,
+            remaining: filters.limit,
+            page_max,
+            printed: 0,
+            done: empty,
+        }
+    }
+
+    /// Records to ask for in the next page, if any.
+    fn limit(&self) -> Option<u32> {
+        if self.done {
+            return None;
+        }
+        let limit = match self.remaining {
+            Some(remaining) => remaining.min(self.page_max as u64) as u32,
+            None => self.page_max,
+        };
+        (limit > 0).then_some(limit)
+    }
+
+    fn range(&self) -> (u64, u64) {
+        (self.min, self.max)
+    }
+
+    /// Move past a page of `got` records that asked for `limit`, the last
+    /// of them at `last`.
+    fn advance(&mut self, limit: u32, got: usize, last: Option<u64>) {
+        self.printed += got as u64;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= got as u64;
+        }
+        let Some(last) = last.filter(|_| got as u32 == limit) else {
+            // A short page is the last one.
+            self.done = true;
+            return;
+        };
+        if self.reversed {
+            // Zero would lift the bound instead.
+            if last <= 1 || last - 1 < self.min {
+                self.done = true;
+            } else {
+                self.max = last - 1;
+            }
+        } else if last == u64::MAX || (self.max != 0 && last + 1 > self.max) {
+            self.done = true;
+        } else {
+            self.min = last + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(
+        since: Option<u64>,
+        until: Option<u64>,
+        limit: Option<u64>,
+        reversed: bool,
+    ) -> Filters {
+        Filters {
+            fields: Fields {
+                code: None,
+                user_data_128: None,
+                user_data_64: None,
+                user_data_32: None,
+            },
+            since,
+            until,
+            limit,
+            reversed,
+            format: Format::Text,
+        }
+    }
+
+    #[test]
+    fn test_window_pages_forward() {
+        let mut window = Window::new(&filters(Some(10), None, Some(250), false), 100);
+        assert_eq!(window.limit(), Some(100));
+        assert_eq!(window.range(), (10, 0));
+        window.advance(100, 100, Some(500));
+        assert_eq!(window.range(), (501, 0));
+        window.advance(100, 100, Some(900));
+        assert_eq!(window.limit(), Some(50));
+        window.advance(50, 50, Some(950));
+        assert_eq!(window.limit(), None);
+        assert_eq!(window.printed, 250);
+    }
+
+    #[test]
+    fn test_window_pages_backward() {
+        let mut window = Window::new(&filters(None, Some(1000), None, true), 10);
+        assert_eq!(window.range(), (0, 999));
+        window.advance(10, 10, Some(700));
+        assert_eq!(window.range(), (0, 699));
+        window.advance(10, 3, Some(650));
+        assert_eq!(window.limit(), None);
+        assert_eq!(window.printed, 13);
+    }
+
+    #[test]
+    fn test_window_stops_at_bounds() {
+        let mut window = Window::new(&filters(Some(5), Some(101), None, false), 10);
+        window.advance(10, 10, Some(100));
+        assert_eq!(window.limit(), None);
+
+        let mut window = Window::new(&filters(Some(50), None, None, true), 10);
+        window.advance(10, 10, Some(50));
+        assert_eq!(window.limit(), None);
+
+        assert_eq!(
+            Window::new(&filters(None, Some(0), None, false), 10).limit(),
+            None
+        );
+        assert_eq!(
+            Window::new(&filters(Some(9), Some(9), None, false), 10).limit(),
+            None
+        );
+        assert_eq!(
+            Window::new(&filters(None, None, Some(0), false), 10).limit(),
+            None
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Times for `--since` and `--until`, and durations for `--interval`.
+//!
+//! TigerBeetle timestamps are nanoseconds since the Unix epoch, so a time
+//! is either such a number or a UTC date: `2024-01-01`, `2024-01-01T12:30`
+//! or `2024-01-01T12:30:15Z`.
+
+use std::time::Duration;
+
+/// Parse a time into a TigerBeetle timestamp.
+pub fn parse(s: &str) -> Result<u64, String> {
+    if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        return s
+            .parse()
+            .map_err(|_| format!("timestamp out of range: {}", s));
+    }
+    let invalid = || {
+        format!(
+            "invalid time: {} (use 2024-01-01, 2024-01-01T12:30:15Z or nanoseconds)",
+            s
+        )
+    };
+
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let date: Vec<u32> = numbers(date, '-').ok_or_else(invalid)?;
+    let [year, month, day] = date[..] else {
+        return Err(invalid());
+    };
+    if !(1970..=2554).contains(&year) || !(1..=12).contains(&month) {
+        return Err(invalid());
+    }
+    if day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    let seconds = match time {
+        None => 0,
+        Some(time) => match numbers(time, ':').ok_or_else(invalid)?[..] {
+            [h, m] if h < 24 && m < 60 => h * 3600 + m * 60,
+            [h, m, sec] if h < 24 && m < 60 && sec < 60 => h * 3600 + m * 60 + sec,
+            _ => return Err(invalid()),
+        },
+    };
+
+    let days = days_since_epoch(year, month, day);
+    let nanos = (days * 86_400 + seconds as u64) as u128 * 1_000_000_000;
+    u64::try_from(nanos).map_err(|_| invalid())
+}
+
+/// Parse a duration such as `500ms`, `2s`, `1m` or `1h`.
+///
+/// A bare number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+
+    let secs = match unit {
+        "ms" => return Ok(Duration::from_millis(value)),
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{}' (use ms, s, m or h)",
+                unit
+            ))
+        }
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+fn numbers(s: &str, separator: char) -> Option<Vec<u32>> {
+    s.split(separator)
+        .map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            part.parse().ok()
+        })
+        .collect()
+}
+
+fn is_leap(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to a date in or after 1970.
+fn days_since_epoch(year: u32, month: u32, day: u32) -> u64 {
+    let years: u64 = (1970..year)
+        .map(|y| if is_leap(y) { 366 } else { 365 })
+        .sum();
+    let months: u64 = (1..month).map(|m| days_in_month(year, m) as u64).sum();
+    years + months + day as u64 - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_parse_dates() {
+        assert_eq!(parse("1970-01-01"), Ok(0));
+        assert_eq!(parse("2024-01-01"), Ok(1_704_067_200 * SECOND));
+        assert_eq!(parse("2024-03-01"), Ok(1_709_251_200 * SECOND));
+        assert_eq!(parse("2024-01-01T12:30"), Ok(1_704_112_200 * SECOND));
+        assert_eq!(parse("2024-01-01T12:30:15Z"), Ok(1_704_112_215 * SECOND));
+        assert_eq!(parse("2024-01-01 12:30:15"), Ok(1_704_112_215 * SECOND));
+    }
+
+    #[test]
+    fn test_parse_nanoseconds() {
+        assert_eq!(parse("1704067200000000001"), Ok(1_704_067_200 * SECOND + 1));
+        assert!(parse("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        for s in [
+            "",
+            "yesterday",
+            "2024-1",
+            "2023-02-29",
+            "2024-13-01",
+            "1969-12-31",
+            "2024-01-01T24:00",
+            "2024-01-01T12",
+            "2024-01-01T12:-1",
+        ] {
+            assert!(parse(s).is_err(), "{}", s);
+        }
+        assert!(parse("2024-02-29").is_ok());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("fast").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! `watch`: print transfers as they are created.
+//!
+//! The client has no change feed, so watch polls: it asks query_transfers
+//! (get_account_transfers with `--account`) for transfers past the last one
+//! printed, again at once while pages come back full and every `--interval`
+//! once it has caught up. Timestamps only grow, so nothing committed later
+//! can appear behind the cursor. Watching starts after the newest matching
+//! transfer, or at `--since` to print earlier ones first.
+
+use std::time::Duration;
+
+use clap::Args;
+use tb_rs::{Client, ClientError, Transfer};
+
+use crate::output::{self, Format};
+use crate::query::{Fields, Scope, Transfers};
+use crate::time;
+
+/// What to watch and how.
+#[derive(Args, Debug)]
+pub struct Watch {
+    #[command(flatten)]
+    fields: Fields,
+
+    #[command(flatten)]
+    scope: Scope,
+
+    /// Start with the transfers created since this time instead of only
+    /// new ones
+    #[arg(long, value_name = "TIME", value_parser = time::parse)]
+    since: Option<u64>,
+
+    /// How long to wait between polls once caught up, e.g. 500ms or 2s
+    #[arg(long, value_parser = time::parse_duration, default_value = "1s")]
+    interval: Duration,
+
+    /// Exit after printing this many transfers
+    #[arg(long)]
+    limit: Option<u64>,
+
+    /// How to print the transfers
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+/// Print new transfers until `--limit`, or forever.
+pub async fn run(client: &mut Client, watch: &Watch) -> Result<(), ClientError> {
+    let transfers = Transfers::new(&watch.scope, &watch.fields, false);
+    let start = match watch.since {
+        Some(since) => since,
+        None => {
+            let newest = Transfers::new(&watch.scope, &watch.fields, true);
+            let newest = newest.fetch(client, (0, 0), 1).await?;
+            after(newest.first())
+        }
+    };
+    let page_max = client.max_batch_count::<Transfer>().unwrap_or(8189);
+    let mut cursor = Cursor::new(start, watch.limit, page_max);
+
+    output::header::<Transfer>(watch.format);
+    loop {
+        let limit = cursor.limit();
+        if limit == 0 {
+            return Ok(());
+        }
+        let page = transfers.fetch(client, (cursor.next, 0), limit).await?;
+        for transfer in &page {
+            output::print(watch.format, transfer);
+        }
+        if cursor.advance(&page, limit) {
+            tokio::time::sleep(watch.interval).await;
+        }
+    }
+}
+
+/// The timestamp just after `newest`, or zero if there is no transfer yet.
+fn after(newest: Option<&Transfer>) -> u64 {
+    newest.map_or(0, |t| t.timestamp + 1)
+}
+
+/// How far watching has got.
+struct Cursor {
+    /// Least timestamp of the transfers not yet printed.
+    next: u64,
+    /// Transfers still to print, with `--limit`.
+    left: Option<u64>,
+    /// Most transfers the cluster returns per query.
+    page_max: u32,
+}
+
+impl Cursor {
+    fn new(next: u64, limit: Option<u64>, page_max: u32) -> Self {
+        Cursor {
+            next,
+            left: limit,
+            page_max,
+        }
+    }
+
+    /// Transfers to ask for in the next poll, zero once `--limit` are printed.
+    fn limit(&self) -> u32 {
+        match self.left {
+            Some(left) => left.min(self.page_max as u64) as u32,
+            None => self.page_max,
+        }
+    }
+
+    /// Move past `page`, polled for `limit` transfers. Returns whether it
+    /// was short, so watching has caught up.
+    fn advance(&mut self, page: &[Transfer], limit: u32) -> bool {
+        if let Some(left) = &mut self.left {
+            *left -= page.len() as u64;
+        }
+        if let Some(last) = page.last() {
+            self.next = last.timestamp + 1;
+        }
+        (page.len() as u32) < limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Transfers created at `timestamps`.
+    fn page(timestamps: &[u64]) -> Vec<Transfer> {
+        timestamps
+            .iter()
+            .map(|&timestamp| Transfer {
+                id: timestamp as u128,
+                timestamp,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_after() {
+        assert_eq!(after(None), 0);
+        assert_eq!(after(page(&[41]).first()), 42);
+    }
+
+    #[test]
+    fn test_cursor_follows_pages() {
+        let mut cursor = Cursor::new(10, None, 3);
+        assert_eq!(cursor.limit(), 3);
+
+        // A full page: poll again at once, after its last transfer.
+        assert!(!cursor.advance(&page(&[10, 12, 15]), 3));
+        assert_eq!(cursor.next, 16);
+
+        // A short page has caught up.
+        assert!(cursor.advance(&page(&[20]), 3));
+        assert_eq!(cursor.next, 21);
+
+        // Nothing new: the cursor stays.
+        assert!(cursor.advance(&[], 3));
+        assert_eq!(cursor.next, 21);
+        assert_eq!(cursor.limit(), 3);
+    }
+
+    #[test]
+    fn test_cursor_limit() {
+        let mut cursor = Cursor::new(0, Some(5), 3);
+        assert_eq!(cursor.limit(), 3);
+        assert!(!cursor.advance(&page(&[1, 2, 3]), 3));
+
+        // Only as many as are left.
+        assert_eq!(cursor.limit(), 2);
+        assert!(cursor.advance(&page(&[4]), 2));
+        assert_eq!(cursor.limit(), 1);
+        assert!(!cursor.advance(&page(&[7]), 1));
+        assert_eq!(cursor.next, 8);
+        assert_eq!(cursor.limit(), 0);
+
+        assert_eq!(Cursor::new(0, Some(0), 3).limit(), 0);
+    }
+}
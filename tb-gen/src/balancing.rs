@@ -0,0 +1,147 @@
+//! Balancing workload for `--balancing`.
+//!
+//! Accounts alternate between `DEBITS_MUST_NOT_EXCEED_CREDITS` and
+//! `CREDITS_MUST_NOT_EXCEED_DEBITS`. Each transfer gets the balancing flag for
+//! every limited side it touches, so the server clamps its amount to what
+//! the limits allow instead of rejecting it: transfers from credit-limited to
+//! debit-limited accounts fund them, and everything else is balanced against
+//! that funding. After the run, every account is looked up to check that no
+//! limit was broken.
+
+use std::collections::HashMap;
+
+use tb_rs::{Account, AccountFlags, Transfer, TransferFlags};
+
+/// Limit flags of every account, by ID.
+pub struct Limits(HashMap<u128, AccountFlags>);
+
+impl Limits {
+    pub fn new(accounts: &[Account]) -> Self {
+        Limits(accounts.iter().map(|a| (a.id, a.flags)).collect())
+    }
+
+    fn get(&self, id: u128) -> AccountFlags {
+        self.0.get(&id).copied().unwrap_or(AccountFlags::empty())
+    }
+}
+
+/// Give accounts alternating debit and credit limits.
+pub fn limit_accounts(accounts: &mut [Account]) {
+    for (i, account) in accounts.iter_mut().enumerate() {
+        account.flags |= if i % 2 == 0 {
+            AccountFlags::DEBITS_MUST_NOT_EXCEED_CREDITS
+        } else {
+            AccountFlags::CREDITS_MUST_NOT_EXCEED_DEBITS
+        };
+    }
+}
+
+/// Add the balancing flags each transfer needs to respect its accounts'
+/// limits.
+pub fn balance(transfers: &mut [Transfer], limits: &Limits) {
+    for transfer in transfers {
+        let debit = limits.get(transfer.debit_account_id);
+        if debit.contains(AccountFlags::DEBITS_MUST_NOT_EXCEED_CREDITS) {
+            transfer.flags |= TransferFlags::BALANCING_DEBIT;
+        }
+        let credit = limits.get(transfer.credit_account_id);
+        if credit.contains(AccountFlags::CREDITS_MUST_NOT_EXCEED_DEBITS) {
+            transfer.flags |= TransferFlags::BALANCING_CREDIT;
+        }
+    }
+}
+
+/// Whether `account`'s balances respect its limit flags.
+pub fn within_limits(account: &Account) -> bool {
+    let debits = account.debits_posted + account.debits_pending;
+    let credits = account.credits_posted + account.credits_pending;
+    let flags = account.flags;
+    let debits_ok = !flags.contains(AccountFlags::DEBITS_MUST_NOT_EXCEED_CREDITS)
+        || debits <= account.credits_posted;
+    let credits_ok = !flags.contains(AccountFlags::CREDITS_MUST_NOT_EXCEED_DEBITS)
+        || credits <= account.debits_posted;
+    debits_ok && credits_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts(count: u128) -> Vec<Account> {
+        let mut accounts: Vec<Account> = (1..=count)
+            .map(|id| Account {
+                id,
+                ledger: 1,
+                code: 1,
+                ..Default::default()
+            })
+            .collect();
+        limit_accounts(&mut accounts);
+        accounts
+    }
+
+    fn transfer(debit: u128, credit: u128) -> Transfer {
+        Transfer {
+            id: 100,
+            debit_account_id: debit,
+            credit_account_id: credit,
+            amount: 10,
+            ledger: 1,
+            code: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_limit_accounts_alternate() {
+        let accounts = accounts(4);
+        assert_eq!(
+            accounts[0].flags,
+            AccountFlags::DEBITS_MUST_NOT_EXCEED_CREDITS
+        );
+        assert_eq!(
+            accounts[1].flags,
+            AccountFlags::CREDITS_MUST_NOT_EXCEED_DEBITS
+        );
+        assert_eq!(accounts[2].flags, accounts[0].flags);
+    }
+
+    #[test]
+    fn test_balance_flags_limited_sides() {
+        // Odd IDs limit debits, even IDs limit credits.
+        let limits = Limits::new(&accounts(4));
+        let mut transfers = [
+            transfer(2, 1), // funding: neither side limited
+            transfer(1, 2), // both sides limited
+            transfer(1, 3), // debit side limited
+            transfer(2, 4), // credit side limited
+        ];
+        balance(&mut transfers, &limits);
+
+        assert_eq!(transfers[0].flags, TransferFlags::empty());
+        assert_eq!(
+            transfers[1].flags,
+            TransferFlags::BALANCING_DEBIT | TransferFlags::BALANCING_CREDIT
+        );
+        assert_eq!(transfers[2].flags, TransferFlags::BALANCING_DEBIT);
+        assert_eq!(transfers[3].flags, TransferFlags::BALANCING_CREDIT);
+    }
+
+    #[test]
+    fn test_within_limits() {
+        let mut account = accounts(1).remove(0);
+        account.credits_posted = 10;
+        account.debits_posted = 10;
+        assert!(within_limits(&account));
+
+        account.debits_pending = 1;
+        assert!(!within_limits(&account));
+
+        let mut account = accounts(2).remove(1);
+        account.debits_posted = 5;
+        account.credits_posted = 5;
+        assert!(within_limits(&account));
+        account.credits_pending = 1;
+        assert!(!within_limits(&account));
+    }
+}
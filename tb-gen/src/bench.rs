@@ -0,0 +1,263 @@
+//! Batch-size sweeps for `tb-gen bench`.
+//!
+//! `--sweep-batch 64,512,2048,8190` sends the same workload once per batch
+//! size, in the order given, and reports throughput and request latency for
+//! each. The accounts (`--accounts`, on `--ledger`) are created once; every
+//! size then gets `--transfers` fresh transfers between them, shaped by the
+//! dataset flags, so all sizes do the same work. Sizes above the server's
+//! limit are capped to it. `--warmup` runs once, before the first size.
+
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use tb_rs::Transfer;
+
+use crate::ledger::Ledger;
+use crate::linked::Linking;
+use crate::report::{self, Tally};
+use crate::seed::Source;
+use crate::{
+    check_shape, connect, flag_history, generate_accounts, print_run_id, run_id, send_accounts,
+    send_transfers, tag_accounts, warm_up, Args, TransferPlan,
+};
+
+/// Run the workload at each of `sizes`.
+pub async fn run(args: &Args, sizes: &[u32]) -> Result<(), Box<dyn Error>> {
+    say!("TigerBeetle Batch Size Benchmark");
+    say!("================================");
+    say!("Address: {}", args.addresses());
+    say!("Cluster: {}", args.cluster);
+    say!("Accounts: {}", args.accounts);
+    say!("Transfers: {} per batch size", args.transfers);
+    say!(
+        "Batch sizes: {}",
+        sizes
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    if sizes.contains(&0) {
+        return Err("--sweep-batch sizes must be positive".into());
+    }
+    if args.transfers == 0 {
+        return Err("bench needs --transfers to send at each batch size".into());
+    }
+    if args.accounts < 2 {
+        return Err("Need at least 2 accounts to create transfers".into());
+    }
+    check_shape(args)?;
+
+    if args.dry_run {
+        say!();
+        say!("Dry run mode - not sending to server");
+        return Ok(());
+    }
+
+    let (mut client, batch_size) = connect(args).await?;
+    let limit = client.max_batch_count::<Transfer>();
+    let run_id = run_id(args)?;
+    say!("Run ID: {}", run_id);
+
+    let mut source = Source::new(args.seed);
+    let mut accounts = generate_accounts(args.accounts, args.ledger, args.code, &mut source);
+    tag_accounts(&mut accounts, run_id);
+    flag_history(&mut accounts, args.history_ratio);
+    send_accounts(&mut client, &accounts, batch_size).await?;
+
+    let plan = TransferPlan {
+        ledgers: vec![Ledger::new(
+            args.ledger,
+            accounts.iter().map(|a| a.id).collect(),
+            args.distribution,
+            args.skew,
+        )],
+        code: args.code,
+        max_amount: args.max_amount,
+        linking: Linking {
+            chain_len: args.linked_chain_len as usize,
+            ratio: args.linked_ratio,
+            poison: args.poison_chains,
+        },
+        limits: None,
+        run_id,
+    };
+
+    if let Some(warmup) = args.warmup {
+        warm_up(&mut client, &plan, batch_size, warmup, None).await?;
+    }
+
+    let mut results: Vec<(u32, Tally, Duration)> = Vec::with_capacity(sizes.len());
+    for &size in sizes {
+        let size = limit.map_or(size, |limit| size.min(limit));
+        say!();
+        say!("Batch size {}:", size);
+        let transfers = plan.generate(args.transfers, &mut source);
+        let start = Instant::now();
+        let tally = send_transfers(&mut client, &transfers, size, None).await?;
+        results.push((size, tally, start.elapsed()));
+    }
+    client.close().await;
+
+    say!();
+    for line in table(&results) {
+        say!("{}", line);
+    }
+
+    let names: Vec<String> = results
+        .iter()
+        .map(|(size, _, _)| format!("batch_{}", size))
+        .collect();
+    let phases: Vec<(&str, &Tally, Duration)> = names
+        .iter()
+        .zip(&results)
+        .map(|(name, (_, tally, elapsed))| (name.as_str(), tally, *elapsed))
+        .collect();
+    report::summary(Some(run_id), &phases);
+
+    say!();
+    print_run_id(run_id);
+    say!("Done!");
+    Ok(())
+}
+
+/// The results of each batch size, then the one with the highest throughput.
+fn table(results: &[(u32, Tally, Duration)]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:>10}  {:>12}  {:>10}  {:>10}  {:>8}",
+        "Batch size", "Transfers/s", "p50", "p99", "Failed"
+    )];
+    for (size, tally, elapsed) in results {
+        let latency = |p| {
+            tally
+                .latency(p)
+                .map_or("-".to_string(), |l| format!("{:.1?}", l))
+        };
+        lines.push(format!(
+            "{:>10}  {:>12.0}  {:>10}  {:>10}  {:>8}",
+            size,
+            throughput(tally, *elapsed),
+            latency(50.0),
+            latency(99.0),
+            tally.failed
+        ));
+    }
+    let best = results
+        .iter()
+        .max_by(|a, b| throughput(&a.1, a.2).total_cmp(&throughput(&b.1, b.2)));
+    if let Some((size, tally, elapsed)) = best {
+        lines.push(format!(
+            "Highest throughput: batch size {} ({:.0} transfers/s)",
+            size,
+            throughput(tally, *elapsed)
+        ));
+    }
+    lines
+}
+
+fn throughput(tally: &Tally, elapsed: Duration) -> f64 {
+    tally.sent() as f64 / elapsed.as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use clap::Parser;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::parse_from(["tb-gen"].iter().chain(args))
+    }
+
+    /// The results of sending `sent` transfers in requests taking `latency`
+    /// each, `failed` of them failing.
+    fn tally(sent: usize, failed: usize, latency: Duration) -> Tally {
+        let mut tally = Tally::default();
+        let codes = (0..failed).map(|_| "Exists".to_string());
+        tally.record(sent, codes, latency);
+        tally
+    }
+
+    #[test]
+    fn test_parse_sweep() {
+        let args = parse(&["bench", "--sweep-batch", "64,512,8190", "--transfers", "10"]);
+        let Some(Command::Bench { sweep_batch }) = &args.command else {
+            panic!("expected bench");
+        };
+        assert_eq!(sweep_batch, &[64, 512, 8190]);
+        assert_eq!(args.transfers, 10);
+
+        // In the order given, repeats and all.
+        let args = parse(&["bench", "--sweep-batch", "512", "--sweep-batch", "64,512"]);
+        let Some(Command::Bench { sweep_batch }) = &args.command else {
+            panic!("expected bench");
+        };
+        assert_eq!(sweep_batch, &[512, 64, 512]);
+
+        for bad in [&["bench"][..], &["bench", "--sweep-batch", "64,x"]] {
+            let args = ["tb-gen"].iter().chain(bad);
+            assert!(Args::try_parse_from(args).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_run_checks() {
+        let cases = [
+            (
+                &["--transfers", "10"][..],
+                &[64, 0][..],
+                "sizes must be positive",
+            ),
+            (&[], &[64], "needs --transfers"),
+            (
+                &["--transfers", "10", "--accounts", "1"],
+                &[64],
+                "at least 2 accounts",
+            ),
+            (
+                &["--transfers", "10", "--linked-ratio", "2"],
+                &[64],
+                "--linked-ratio",
+            ),
+        ];
+        for (flags, sizes, error) in cases {
+            let args = parse(&[flags, &["--dry-run"]].concat());
+            let result = tokio_uring::start(run(&args, sizes));
+            let message = result.expect_err(error).to_string();
+            assert!(message.contains(error), "{:?}: {}", flags, message);
+        }
+
+        let args = parse(&["--transfers", "10", "--dry-run"]);
+        assert!(tokio_uring::start(run(&args, &[64, 512])).is_ok());
+    }
+
+    #[test]
+    fn test_table() {
+        let results = [
+            (
+                64,
+                tally(1000, 0, Duration::from_millis(2)),
+                Duration::from_secs(2),
+            ),
+            (
+                512,
+                tally(1000, 3, Duration::from_millis(8)),
+                Duration::from_millis(500),
+            ),
+            (8190, Tally::default(), Duration::from_secs(1)),
+        ];
+        let lines = table(&results);
+        assert_eq!(
+            lines,
+            [
+                "Batch size   Transfers/s         p50         p99    Failed",
+                "        64           500       2.0ms       2.0ms         0",
+                "       512          2000       8.0ms       8.0ms         3",
+                "      8190             0           -           -         0",
+                "Highest throughput: batch size 512 (2000 transfers/s)",
+            ]
+        );
+        assert_eq!(table(&[]).len(), 1);
+    }
+}
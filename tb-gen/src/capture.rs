@@ -0,0 +1,447 @@
+//! Request/reply capture for `--capture`.
+//!
+//! tb-rs keeps its wire messages to itself, so the capture holds what goes
+//! into and comes out of them: the event bodies of every `create_accounts`
+//! and `create_transfers` request and the result bodies of their replies,
+//! both in wire layout, with the time each was sent or received. That is
+//! enough to study a run message by message or to replay it with
+//! `tb-gen replay`.
+//!
+//! A `.tbcap` file is the 8-byte magic `TBCAP\0\0\x01` followed by records,
+//! all little-endian:
+//!
+//! | Bytes | Field                                               |
+//! |-------|-----------------------------------------------------|
+//! | 1     | kind: 1 request, 2 reply                            |
+//! | 1     | operation: 138 create_accounts, 139 create_transfers |
+//! | 2     | reserved, zero                                      |
+//! | 4     | sender: 0 for the main client, n for worker n - 1   |
+//! | 8     | nanoseconds since the capture started               |
+//! | 4     | body length                                         |
+//! | 4     | reserved, zero                                      |
+//! | n     | body: 128-byte events, or 8-byte `(index, result)`  |
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tb_rs::{
+    Account, AccountFlags, CreateAccountsResult, CreateTransfersResult, Transfer, TransferFlags,
+};
+
+use crate::import::Dataset;
+
+pub const MAGIC: &[u8; 8] = b"TBCAP\0\0\x01";
+pub const HEADER_SIZE: usize = 24;
+pub const EVENT_SIZE: usize = 128;
+pub const RESULT_SIZE: usize = 8;
+pub const REQUEST: u8 = 1;
+pub const REPLY: u8 = 2;
+pub const CREATE_ACCOUNTS: u8 = 138;
+pub const CREATE_TRANSFERS: u8 = 139;
+
+struct Capture {
+    out: BufWriter<File>,
+    start: Instant,
+    records: u64,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+/// Start capturing to `path`.
+pub fn init(path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    *CAPTURE.lock().unwrap() = Some(Capture {
+        out,
+        start: Instant::now(),
+        records: 0,
+    });
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Capture a `create_accounts` request sent at `sent_at` and its reply.
+pub fn accounts(
+    sender: u32,
+    sent_at: Instant,
+    accounts: &[Account],
+    results: &[CreateAccountsResult],
+) -> io::Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut request = Vec::with_capacity(accounts.len() * EVENT_SIZE);
+    accounts
+        .iter()
+        .for_each(|a| encode_account(a, &mut request));
+    let mut reply = Vec::with_capacity(results.len() * RESULT_SIZE);
+    for r in results {
+        encode_result(r.index, r.result as u32, &mut reply);
+    }
+    write(sender, sent_at, CREATE_ACCOUNTS, &request, &reply)
+}
+
+/// Capture a `create_transfers` request sent at `sent_at` and its reply.
+pub fn transfers(
+    sender: u32,
+    sent_at: Instant,
+    transfers: &[Transfer],
+    results: &[CreateTransfersResult],
+) -> io::Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut request = Vec::with_capacity(transfers.len() * EVENT_SIZE);
+    transfers
+        .iter()
+        .for_each(|t| encode_transfer(t, &mut request));
+    let mut reply = Vec::with_capacity(results.len() * RESULT_SIZE);
+    for r in results {
+        encode_result(r.index, r.result as u32, &mut reply);
+    }
+    write(sender, sent_at, CREATE_TRANSFERS, &request, &reply)
+}
+
+/// Append a request and its reply, received now.
+fn write(
+    sender: u32,
+    sent_at: Instant,
+    operation: u8,
+    request: &[u8],
+    reply: &[u8],
+) -> io::Result<()> {
+    let replied_at = Instant::now();
+    let mut guard = CAPTURE.lock().unwrap();
+    let Some(capture) = guard.as_mut() else {
+        return Ok(());
+    };
+    for (kind, at, body) in [(REQUEST, sent_at, request), (REPLY, replied_at, reply)] {
+        let nanos = at.saturating_duration_since(capture.start).as_nanos() as u64;
+        let header = header(kind, operation, sender, nanos, body.len() as u32);
+        capture.out.write_all(&header)?;
+        capture.out.write_all(body)?;
+        capture.records += 1;
+    }
+    Ok(())
+}
+
+/// Flush the capture and return how many records it holds, if capturing.
+pub fn finish() -> io::Result<Option<u64>> {
+    ENABLED.store(false, Ordering::Relaxed);
+    match CAPTURE.lock().unwrap().take() {
+        Some(mut capture) => {
+            capture.out.flush()?;
+            Ok(Some(capture.records))
+        }
+        None => Ok(None),
+    }
+}
+
+fn header(kind: u8, operation: u8, sender: u32, nanos: u64, len: u32) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[0] = kind;
+    header[1] = operation;
+    header[4..8].copy_from_slice(&sender.to_le_bytes());
+    header[8..16].copy_from_slice(&nanos.to_le_bytes());
+    header[16..20].copy_from_slice(&len.to_le_bytes());
+    header
+}
+
+fn encode_result(index: u32, result: u32, out: &mut Vec<u8>) {
+    out.extend_from_slice(&index.to_le_bytes());
+    out.extend_from_slice(&result.to_le_bytes());
+}
+
+/// Append `account` in its 128-byte wire layout.
+fn encode_account(account: &Account, out: &mut Vec<u8>) {
+    for field in [
+        account.id,
+        account.debits_pending,
+        account.debits_posted,
+        account.credits_pending,
+        account.credits_posted,
+        account.user_data_128,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(&account.user_data_64.to_le_bytes());
+    out.extend_from_slice(&account.user_data_32.to_le_bytes());
+    out.extend_from_slice(&account.reserved.to_le_bytes());
+    out.extend_from_slice(&account.ledger.to_le_bytes());
+    out.extend_from_slice(&account.code.to_le_bytes());
+    out.extend_from_slice(&account.flags.bits().to_le_bytes());
+    out.extend_from_slice(&account.timestamp.to_le_bytes());
+}
+
+/// Append `transfer` in its 128-byte wire layout.
+fn encode_transfer(transfer: &Transfer, out: &mut Vec<u8>) {
+    for field in [
+        transfer.id,
+        transfer.debit_account_id,
+        transfer.credit_account_id,
+        transfer.amount,
+        transfer.pending_id,
+        transfer.user_data_128,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(&transfer.user_data_64.to_le_bytes());
+    out.extend_from_slice(&transfer.user_data_32.to_le_bytes());
+    out.extend_from_slice(&transfer.timeout.to_le_bytes());
+    out.extend_from_slice(&transfer.ledger.to_le_bytes());
+    out.extend_from_slice(&transfer.code.to_le_bytes());
+    out.extend_from_slice(&transfer.flags.bits().to_le_bytes());
+    out.extend_from_slice(&transfer.timestamp.to_le_bytes());
+}
+
+/// A captured request and the reply it got.
+pub struct Exchange {
+    pub sender: u32,
+    /// When the request was sent, from the start of the capture.
+    pub sent_at: Duration,
+    pub request: Dataset,
+    /// `(index, result)` of each event that failed.
+    pub reply: Vec<(u32, u32)>,
+}
+
+/// Read a capture written by `--capture`.
+pub fn read(path: &Path) -> Result<Vec<Exchange>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// One record of a capture.
+struct Record<'a> {
+    kind: u8,
+    operation: u8,
+    sender: u32,
+    at: Duration,
+    body: &'a [u8],
+}
+
+fn parse(bytes: &[u8]) -> Result<Vec<Exchange>, String> {
+    let mut rest = bytes
+        .strip_prefix(MAGIC.as_slice())
+        .ok_or("not a .tbcap capture")?;
+    let mut exchanges = Vec::new();
+    while !rest.is_empty() {
+        let offset = bytes.len() - rest.len();
+        let (request, after) = next_record(rest, offset)?;
+        let (reply, after) = next_record(after, offset)?;
+        rest = after;
+
+        if request.kind != REQUEST
+            || reply.kind != REPLY
+            || reply.operation != request.operation
+            || reply.sender != request.sender
+        {
+            return Err(format!("byte {}: expected a request and its reply", offset));
+        }
+        let events = request.body.chunks_exact(EVENT_SIZE);
+        if !events.remainder().is_empty() || reply.body.len() % RESULT_SIZE != 0 {
+            return Err(format!("byte {}: partial event or result", offset));
+        }
+        let request_events = match request.operation {
+            CREATE_ACCOUNTS => Dataset::Accounts(events.map(decode_account).collect()),
+            CREATE_TRANSFERS => Dataset::Transfers(events.map(decode_transfer).collect()),
+            other => return Err(format!("byte {}: unknown operation {}", offset, other)),
+        };
+        exchanges.push(Exchange {
+            sender: request.sender,
+            sent_at: request.at,
+            request: request_events,
+            reply: reply
+                .body
+                .chunks_exact(RESULT_SIZE)
+                .map(|r| (u32_at(r, 0), u32_at(r, 4)))
+                .collect(),
+        });
+    }
+    Ok(exchanges)
+}
+
+/// Split the record at the start of `bytes` from the rest.
+fn next_record(bytes: &[u8], offset: usize) -> Result<(Record<'_>, &[u8]), String> {
+    let truncated = || format!("byte {}: truncated record", offset);
+    if bytes.len() < HEADER_SIZE {
+        return Err(truncated());
+    }
+    let len = u32_at(bytes, 16) as usize;
+    let body = bytes
+        .get(HEADER_SIZE..HEADER_SIZE + len)
+        .ok_or_else(truncated)?;
+    let record = Record {
+        kind: bytes[0],
+        operation: bytes[1],
+        sender: u32_at(bytes, 4),
+        at: Duration::from_nanos(u64_at(bytes, 8)),
+        body,
+    };
+    Ok((record, &bytes[HEADER_SIZE + len..]))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn u128_at(bytes: &[u8], at: usize) -> u128 {
+    u128::from_le_bytes(bytes[at..at + 16].try_into().unwrap())
+}
+
+/// Read an account from its 128-byte wire layout.
+fn decode_account(bytes: &[u8]) -> Account {
+    Account {
+        id: u128_at(bytes, 0),
+        debits_pending: u128_at(bytes, 16),
+        debits_posted: u128_at(bytes, 32),
+        credits_pending: u128_at(bytes, 48),
+        credits_posted: u128_at(bytes, 64),
+        user_data_128: u128_at(bytes, 80),
+        user_data_64: u64_at(bytes, 96),
+        user_data_32: u32_at(bytes, 104),
+        reserved: u32_at(bytes, 108),
+        ledger: u32_at(bytes, 112),
+        code: u16_at(bytes, 116),
+        flags: AccountFlags::from_bits_retain(u16_at(bytes, 118)),
+        timestamp: u64_at(bytes, 120),
+    }
+}
+
+/// Read a transfer from its 128-byte wire layout.
+fn decode_transfer(bytes: &[u8]) -> Transfer {
+    Transfer {
+        id: u128_at(bytes, 0),
+        debit_account_id: u128_at(bytes, 16),
+        credit_account_id: u128_at(bytes, 32),
+        amount: u128_at(bytes, 48),
+        pending_id: u128_at(bytes, 64),
+        user_data_128: u128_at(bytes, 80),
+        user_data_64: u64_at(bytes, 96),
+        user_data_32: u32_at(bytes, 104),
+        timeout: u32_at(bytes, 108),
+        ledger: u32_at(bytes, 112),
+        code: u16_at(bytes, 116),
+        flags: TransferFlags::from_bits_retain(u16_at(bytes, 118)),
+        timestamp: u64_at(bytes, 120),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tb_rs::TransferFlags;
+
+    #[test]
+    fn test_encode_transfer_layout() {
+        let transfer = Transfer {
+            id: 1,
+            amount: 0x0102,
+            user_data_64: 7,
+            ledger: 9,
+            code: 3,
+            flags: TransferFlags::LINKED | TransferFlags::PENDING,
+            timestamp: 5,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        encode_transfer(&transfer, &mut out);
+
+        assert_eq!(out.len(), 128);
+        assert_eq!(out[0], 1);
+        assert_eq!(&out[48..50], &[0x02, 0x01]);
+        assert_eq!(out[96], 7);
+        assert_eq!(out[112], 9);
+        assert_eq!(out[116], 3);
+        assert_eq!(out[118], 0b11);
+        assert_eq!(out[120], 5);
+    }
+
+    #[test]
+    fn test_encode_account_layout() {
+        let account = Account {
+            id: 1,
+            credits_posted: 4,
+            ledger: 2,
+            timestamp: 8,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        encode_account(&account, &mut out);
+
+        assert_eq!(out.len(), 128);
+        assert_eq!(out[64], 4);
+        assert_eq!(out[112], 2);
+        assert_eq!(out[120], 8);
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let header = header(REPLY, CREATE_TRANSFERS, 3, 0x0102, 16);
+        assert_eq!(header[..4], [REPLY, CREATE_TRANSFERS, 0, 0]);
+        assert_eq!(header[4], 3);
+        assert_eq!(header[8..10], [0x02, 0x01]);
+        assert_eq!(header[16], 16);
+        assert_eq!(header[20..], [0; 4]);
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let account = Account {
+            id: 3,
+            ledger: 1,
+            code: 10,
+            flags: AccountFlags::HISTORY,
+            ..Default::default()
+        };
+        let transfer = Transfer {
+            id: 4,
+            debit_account_id: 3,
+            credit_account_id: 5,
+            amount: u128::MAX,
+            flags: TransferFlags::LINKED,
+            ..Default::default()
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        let mut push = |kind, operation, sender, nanos, body: &[u8]| {
+            bytes.extend(header(kind, operation, sender, nanos, body.len() as u32));
+            bytes.extend_from_slice(body);
+        };
+        let mut body = Vec::new();
+        encode_account(&account, &mut body);
+        push(REQUEST, CREATE_ACCOUNTS, 0, 10, &body);
+        push(REPLY, CREATE_ACCOUNTS, 0, 20, &[]);
+        body.clear();
+        encode_transfer(&transfer, &mut body);
+        push(REQUEST, CREATE_TRANSFERS, 2, 30, &body);
+        body.clear();
+        encode_result(0, 21, &mut body);
+        push(REPLY, CREATE_TRANSFERS, 2, 40, &body);
+
+        let exchanges = parse(&bytes).unwrap();
+        assert_eq!(exchanges.len(), 2);
+        assert!(matches!(&exchanges[0].request, Dataset::Accounts(a) if a == &[account]));
+        assert!(exchanges[0].reply.is_empty());
+        assert_eq!(exchanges[1].sender, 2);
+        assert_eq!(exchanges[1].sent_at, Duration::from_nanos(30));
+        assert!(matches!(&exchanges[1].request, Dataset::Transfers(t) if t == &[transfer]));
+        assert_eq!(exchanges[1].reply, [(0, 21)]);
+
+        assert!(parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(parse(&bytes[8..]).is_err());
+        assert!(parse(MAGIC).unwrap().is_empty());
+    }
+}
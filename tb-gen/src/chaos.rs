@@ -0,0 +1,216 @@
+//! Client misbehaviour for the `--chaos-*` flags.
+//!
+//! Before each transfer batch, a sender may:
+//!
+//! - drop its client without closing it and connect a new one
+//!   (`--chaos-reconnect`), abandoning the session mid-run;
+//! - sleep for up to `--chaos-max-delay` (`--chaos-delay`), as a stalled
+//!   client would;
+//! - send the batch, give up on the reply within `--chaos-abandon-after`
+//!   and resend it from a new client (`--chaos-abandon`).
+//!
+//! Each flag is a per-batch probability. An abandoned batch may already have
+//! been applied, so its resent copy is expected to report `Exists` for those
+//! transfers; that is how retries are checked for idempotency. Accounts are
+//! always sent normally.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use rand::Rng;
+use tb_rs::{Client, ClientError, CreateTransfersResult, Transfer};
+
+/// Probabilities and limits of injected faults.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub cluster: u128,
+    pub address: String,
+    pub reconnect: f64,
+    pub delay: f64,
+    pub max_delay: Duration,
+    pub abandon: f64,
+    pub abandon_after: Duration,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+static DELAYS: AtomicU64 = AtomicU64::new(0);
+static ABANDONED: AtomicU64 = AtomicU64::new(0);
+
+/// Inject faults as set by `config` for the rest of the run.
+pub fn init(config: Config) {
+    CONFIG
+        .set(config)
+        .expect("chaos is configured once per run");
+}
+
+/// The faults injected around one batch.
+#[derive(Debug, Default, PartialEq)]
+struct Faults {
+    reconnect: bool,
+    /// How long to stall before sending.
+    delay: Option<Duration>,
+    /// How long to wait for the reply before abandoning the batch.
+    abandon: Option<Duration>,
+}
+
+impl Faults {
+    /// Choose the faults for a batch, each with its probability in `config`.
+    fn choose(config: &Config, rng: &mut impl Rng) -> Faults {
+        let delay = rng
+            .gen_bool(config.delay)
+            .then(|| rng.gen_range(Duration::ZERO..=config.max_delay));
+        let abandon = rng
+            .gen_bool(config.abandon)
+            .then(|| rng.gen_range(Duration::ZERO..=config.abandon_after));
+        Faults {
+            reconnect: rng.gen_bool(config.reconnect),
+            delay,
+            abandon,
+        }
+    }
+}
+
+/// Create `transfers`, possibly misbehaving first.
+///
+/// `client` is replaced when the fault calls for a new connection.
+pub async fn create_transfers(
+    client: &mut Client,
+    transfers: &[Transfer],
+) -> Result<Vec<CreateTransfersResult>, ClientError> {
+    let Some(config) = CONFIG.get() else {
+        return client.create_transfers(transfers).await;
+    };
+
+    // Not held across awaits: the thread RNG is not Send.
+    let Faults {
+        reconnect,
+        delay,
+        abandon,
+    } = Faults::choose(config, &mut rand::thread_rng());
+
+    if reconnect {
+        RECONNECTS.fetch_add(1, Ordering::Relaxed);
+        replace_client(client, config).await?;
+    }
+    if let Some(delay) = delay {
+        DELAYS.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(delay).await;
+    }
+    if let Some(after) = abandon {
+        if let Ok(results) = tokio::time::timeout(after, client.create_transfers(transfers)).await {
+            return results;
+        }
+        // The request is in flight or applied; the session can't continue.
+        ABANDONED.fetch_add(1, Ordering::Relaxed);
+        replace_client(client, config).await?;
+    }
+    client.create_transfers(transfers).await
+}
+
+/// Drop `client` without closing it and connect a new one in its place.
+async fn replace_client(client: &mut Client, config: &Config) -> Result<(), ClientError> {
+    let fresh = Client::connect(config.cluster, &config.address).await?;
+    drop(std::mem::replace(client, fresh));
+    Ok(())
+}
+
+/// Report the faults injected, if any were configured.
+pub fn report() {
+    if CONFIG.get().is_none() {
+        return;
+    }
+    say!(
+        "Chaos: {} reconnects, {} delays, {} abandoned batches (resent)",
+        RECONNECTS.load(Ordering::Relaxed),
+        DELAYS.load(Ordering::Relaxed),
+        ABANDONED.load(Ordering::Relaxed)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn config(reconnect: f64, delay: f64, abandon: f64) -> Config {
+        Config {
+            cluster: 0,
+            address: "127.0.0.1:3000".to_string(),
+            reconnect,
+            delay,
+            max_delay: Duration::from_millis(50),
+            abandon,
+            abandon_after: Duration::from_millis(20),
+        }
+    }
+
+    /// The faults chosen for `batches` batches from `seed`.
+    fn choose(config: &Config, seed: u64, batches: usize) -> Vec<Faults> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..batches)
+            .map(|_| Faults::choose(config, &mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn test_choose_never() {
+        let faults = choose(&config(0.0, 0.0, 0.0), 1, 1000);
+        assert!(faults.iter().all(|f| *f == Faults::default()));
+    }
+
+    #[test]
+    fn test_choose_always() {
+        let config = config(1.0, 1.0, 1.0);
+        for faults in choose(&config, 1, 1000) {
+            assert!(faults.reconnect);
+            assert!(faults.delay.unwrap() <= config.max_delay, "{:?}", faults);
+            assert!(
+                faults.abandon.unwrap() <= config.abandon_after,
+                "{:?}",
+                faults
+            );
+        }
+    }
+
+    #[test]
+    fn test_choose_each_fault() {
+        // Each flag sets only its own fault, on about its share of batches.
+        let cases = [
+            (config(0.25, 0.0, 0.0), 0),
+            (config(0.0, 0.25, 0.0), 1),
+            (config(0.0, 0.0, 0.25), 2),
+        ];
+        for (config, fault) in cases {
+            let mut counts = [0; 3];
+            for faults in choose(&config, 7, 10_000) {
+                counts[0] += faults.reconnect as usize;
+                counts[1] += faults.delay.is_some() as usize;
+                counts[2] += faults.abandon.is_some() as usize;
+            }
+            for (i, &count) in counts.iter().enumerate() {
+                if i == fault {
+                    assert!((2_250..2_750).contains(&count), "{:?}: {}", config, count);
+                } else {
+                    assert_eq!(count, 0, "{:?}", config);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_choose_is_seeded() {
+        let config = config(0.1, 0.2, 0.3);
+        assert_eq!(choose(&config, 42, 100), choose(&config, 42, 100));
+        assert_ne!(choose(&config, 42, 100), choose(&config, 43, 100));
+
+        // Stalls and waits vary within their limits.
+        let delays: Vec<_> = choose(&config, 42, 100)
+            .into_iter()
+            .filter_map(|f| f.delay)
+            .collect();
+        assert!(delays.windows(2).any(|w| w[0] != w[1]), "{:?}", delays);
+    }
+}
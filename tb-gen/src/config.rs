@@ -0,0 +1,201 @@
+//! Workload files for `--config`.
+//!
+//! A workload file sets the same options as the command line, with keys
+//! named after the flags (`max_amount` or `max-amount` for `--max-amount`).
+//! Tables only group keys and may be named anything:
+//!
+//! ```toml
+//! accounts = 1000
+//!
+//! [dataset]
+//! ledgers = "1-10,20:5000"
+//! distribution = "zipfian"
+//! skew = 1.2
+//! linked_ratio = 0.1
+//!
+//! [pacing]
+//! ramp = "0:1000,60s:5000,120s:10000"
+//! duration = "10m"
+//! ```
+//!
+//! `true` sets a switch such as `balancing`, and arrays repeat a flag, as for
+//! `from_csv`. Flags given on the command line override the file.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use toml::{Table, Value};
+
+/// Expand `--config PATH` in `argv` into the flags the file sets, placed
+/// before the command-line flags so those take precedence.
+pub fn expand(argv: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let Some(path) = config_path(&argv) else {
+        return Ok(argv);
+    };
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("{}: {}", Path::new(&path).display(), e))?;
+    let flags = parse(&text).map_err(|e| format!("{}: {}", Path::new(&path).display(), e))?;
+    Ok(splice(argv, flags))
+}
+
+/// Insert `flags` after the program name.
+fn splice(argv: Vec<OsString>, flags: Vec<String>) -> Vec<OsString> {
+    let mut expanded = Vec::with_capacity(argv.len() + flags.len());
+    let mut argv = argv.into_iter();
+    expanded.extend(argv.next());
+    expanded.extend(flags.into_iter().map(OsString::from));
+    expanded.extend(argv);
+    expanded
+}
+
+/// The value of the last `--config` in `argv`, if any.
+fn config_path(argv: &[OsString]) -> Option<OsString> {
+    let mut path = None;
+    let mut args = argv.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            path = args.next().cloned();
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            path = Some(value.into());
+        }
+    }
+    path
+}
+
+/// Turn a workload file into command-line flags.
+fn parse(text: &str) -> Result<Vec<String>, String> {
+    let table: Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+    let mut flags = Vec::new();
+    let mut seen = Vec::new();
+    add_table(&table, &mut flags, &mut seen)?;
+    Ok(flags)
+}
+
+fn add_table(table: &Table, flags: &mut Vec<String>, seen: &mut Vec<String>) -> Result<(), String> {
+    for (key, value) in table {
+        if let Value::Table(group) = value {
+            add_table(group, flags, seen)?;
+            continue;
+        }
+
+        let name = key.replace('_', "-");
+        if name == "config" {
+            return Err("a workload file can't include another".to_string());
+        }
+        if seen.contains(&name) {
+            return Err(format!("'{}' is set twice", key));
+        }
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Integer(n) => n.to_string(),
+                Value::Float(x) => x.to_string(),
+                Value::Boolean(true) => {
+                    flags.push(format!("--{}", name));
+                    continue;
+                }
+                Value::Boolean(false) => continue,
+                _ => return Err(format!("'{}' has an unsupported value", key)),
+            };
+            flags.push(format!("--{}", name));
+            flags.push(value);
+        }
+        seen.push(name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_parse_flattens_tables() {
+        let flags = parse(
+            r#"
+            accounts = 1000
+            [dataset]
+            max_amount = 50
+            skew = 1.5
+            from-csv = ["a.csv", "b.csv"]
+            [pacing]
+            tps = 500
+            duration = "10m"
+            balancing = true
+            dry_run = false
+            "#,
+        )
+        .unwrap();
+        // Keys come out sorted within each table.
+        assert_eq!(
+            flags,
+            [
+                "--accounts",
+                "1000",
+                "--from-csv",
+                "a.csv",
+                "--from-csv",
+                "b.csv",
+                "--max-amount",
+                "50",
+                "--skew",
+                "1.5",
+                "--balancing",
+                "--duration",
+                "10m",
+                "--tps",
+                "500",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("accounts = ").is_err());
+        assert!(parse("tps = 1\n[pacing]\ntps = 2").is_err());
+        assert!(parse("config = \"other.toml\"").is_err());
+        assert!(parse("start = 1979-05-27").is_err());
+    }
+
+    #[test]
+    fn test_config_path() {
+        assert_eq!(config_path(&argv(&["tb-gen", "--tps", "5"])), None);
+        assert_eq!(
+            config_path(&argv(&["tb-gen", "--config", "a.toml"])),
+            Some("a.toml".into())
+        );
+        assert_eq!(
+            config_path(&argv(&["tb-gen", "--config=a.toml", "--seed", "1"])),
+            Some("a.toml".into())
+        );
+    }
+
+    #[test]
+    fn test_splice_puts_file_first() {
+        let expanded = splice(
+            argv(&["tb-gen", "--config", "w.toml", "--tps", "200"]),
+            vec!["--tps".to_string(), "100".to_string()],
+        );
+        assert_eq!(
+            expanded,
+            argv(&["tb-gen", "--tps", "100", "--config", "w.toml", "--tps", "200"])
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! How transfers pick their accounts.
+
+use clap::ValueEnum;
+use rand::Rng;
+
+/// Distribution of accounts across transfers.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Distribution {
+    /// Every account is equally likely.
+    #[default]
+    Uniform,
+    /// A few hot accounts take most transfers, the n-th most popular with
+    /// weight 1/n^skew.
+    Zipfian,
+}
+
+/// Picks account indexes according to a [`Distribution`].
+pub enum AccountPicker {
+    Uniform {
+        count: usize,
+    },
+    /// Cumulative probability of each rank; rank 0 is the hottest account.
+    Zipfian {
+        cdf: Vec<f64>,
+    },
+}
+
+impl AccountPicker {
+    /// Create a picker over `count` accounts.
+    ///
+    /// `skew` only applies to [`Distribution::Zipfian`]; larger values
+    /// concentrate transfers on fewer accounts.
+    pub fn new(distribution: Distribution, count: usize, skew: f64) -> Self {
+        assert!(count > 0);
+        match distribution {
+            Distribution::Uniform => AccountPicker::Uniform { count },
+            Distribution::Zipfian => {
+                assert!(skew > 0.0, "skew must be positive");
+                let mut total = 0.0;
+                let mut cdf: Vec<f64> = (1..=count)
+                    .map(|rank| {
+                        total += (rank as f64).powf(-skew);
+                        total
+                    })
+                    .collect();
+                for p in &mut cdf {
+                    *p /= total;
+                }
+                AccountPicker::Zipfian { cdf }
+            }
+        }
+    }
+
+    /// Pick an account index.
+    pub fn pick(&self, rng: &mut impl Rng) -> usize {
+        match self {
+            AccountPicker::Uniform { count } => rng.gen_range(0..*count),
+            AccountPicker::Zipfian { cdf } => {
+                let p: f64 = rng.gen();
+                // Rounding can leave the last entry just below 1.0.
+                cdf.partition_point(|&c| c <= p).min(cdf.len() - 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_picks_in_range() {
+        let picker = AccountPicker::new(Distribution::Uniform, 5, 1.1);
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            assert!(picker.pick(&mut rng) < 5);
+        }
+    }
+
+    #[test]
+    fn test_zipfian_cdf() {
+        let AccountPicker::Zipfian { cdf } = AccountPicker::new(Distribution::Zipfian, 3, 1.0)
+        else {
+            panic!("expected zipfian picker");
+        };
+        // Weights 1, 1/2, 1/3 out of 11/6.
+        assert!((cdf[0] - 6.0 / 11.0).abs() < 1e-9);
+        assert!((cdf[1] - 9.0 / 11.0).abs() < 1e-9);
+        assert!((cdf[2] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_zipfian_concentrates_on_hot_accounts() {
+        let picker = AccountPicker::new(Distribution::Zipfian, 1000, 1.1);
+        let mut rng = rand::thread_rng();
+        let picks = 10_000;
+        let hot = (0..picks).filter(|_| picker.pick(&mut rng) < 10).count();
+        // The top 1% of accounts expect nearly half of the picks; uniform
+        // picking would give them 1%.
+        assert!(hot > picks / 3, "hot picks: {}", hot);
+    }
+}
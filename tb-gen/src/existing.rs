@@ -0,0 +1,85 @@
+//! Accounts already on the cluster, for `--existing-accounts query`.
+//!
+//! Instead of creating accounts, the run pages through `query_accounts`,
+//! oldest first, and sends its transfers between the accounts found, so
+//! load can be added to a populated cluster run after run. `--existing-ledger`
+//! and `--existing-run-id` (the `user_data_64` tb-gen tags accounts with)
+//! narrow the query. Transfers stay within a ledger, so ledgers with fewer
+//! than two accounts are left out.
+
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use tb_rs::{Account, Client, ClientError, QueryFilter};
+
+/// Where existing accounts come from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Existing {
+    /// Page through query_accounts
+    Query,
+}
+
+/// Which existing accounts to use; zero means any.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Filter {
+    pub ledger: u32,
+    pub run_id: u64,
+}
+
+/// Fetch every account matching `filter`, a page at a time.
+pub async fn query(client: &mut Client, filter: Filter) -> Result<Vec<Account>, ClientError> {
+    let limit = client.max_batch_count::<Account>().unwrap_or(8189);
+    let mut accounts: Vec<Account> = Vec::new();
+    loop {
+        let page = client
+            .query_accounts(QueryFilter {
+                user_data_64: filter.run_id,
+                ledger: filter.ledger,
+                // Timestamps are unique, so the next page starts just after
+                // the last account seen.
+                timestamp_min: accounts.last().map_or(0, |a| a.timestamp + 1),
+                limit,
+                ..Default::default()
+            })
+            .await?;
+        let full = page.len() as u32 == limit;
+        accounts.extend(page);
+        if !full {
+            return Ok(accounts);
+        }
+    }
+}
+
+/// Account IDs by ledger, for the ledgers transfers can use.
+pub fn group(accounts: &[Account]) -> Vec<(u32, Vec<u128>)> {
+    let mut ledgers: BTreeMap<u32, Vec<u128>> = BTreeMap::new();
+    for account in accounts {
+        ledgers.entry(account.ledger).or_default().push(account.id);
+    }
+    ledgers
+        .into_iter()
+        .filter(|(_, ids)| ids.len() >= 2)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_drops_lonely_ledgers() {
+        let account = |id, ledger| Account {
+            id,
+            ledger,
+            ..Default::default()
+        };
+        let accounts = [
+            account(1, 7),
+            account(2, 3),
+            account(3, 7),
+            account(4, 5),
+            account(5, 3),
+        ];
+        assert_eq!(group(&accounts), vec![(3, vec![2, 5]), (7, vec![1, 3])]);
+    }
+}
@@ -0,0 +1,278 @@
+//! Writing generated accounts and transfers to files.
+//!
+//! The format follows the file extension: `.csv`, `.json` (one array) or
+//! `.ndjson`/`.jsonl` (one object per line). Columns are the fields a client
+//! sets on creation; balances and timestamps are assigned by the server.
+//! Flags are written as their raw bits. In JSON, 128-bit fields are strings
+//! because most JSON readers lose precision above 2^53.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use tb_rs::{Account, Transfer};
+
+/// A column of an exported record.
+pub struct Column {
+    pub name: &'static str,
+    /// Holds 128-bit values.
+    pub wide: bool,
+}
+
+const fn col(name: &'static str, wide: bool) -> Column {
+    Column { name, wide }
+}
+
+/// A type that can be written as a row.
+pub trait Record {
+    const COLUMNS: &'static [Column];
+
+    /// Values in [`Record::COLUMNS`] order.
+    fn values(&self) -> Vec<u128>;
+}
+
+impl Record for Account {
+    const COLUMNS: &'static [Column] = &[
+        col("id", true),
+        col("user_data_128", true),
+        col("user_data_64", false),
+        col("user_data_32", false),
+        col("ledger", false),
+        col("code", false),
+        col("flags", false),
+    ];
+
+    fn values(&self) -> Vec<u128> {
+        vec![
+            self.id,
+            self.user_data_128,
+            self.user_data_64 as u128,
+            self.user_data_32 as u128,
+            self.ledger as u128,
+            self.code as u128,
+            self.flags.bits() as u128,
+        ]
+    }
+}
+
+impl Record for Transfer {
+    const COLUMNS: &'static [Column] = &[
+        col("id", true),
+        col("debit_account_id", true),
+        col("credit_account_id", true),
+        col("amount", true),
+        col("pending_id", true),
+        col("user_data_128", true),
+        col("user_data_64", false),
+        col("user_data_32", false),
+        col("timeout", false),
+        col("ledger", false),
+        col("code", false),
+        col("flags", false),
+    ];
+
+    fn values(&self) -> Vec<u128> {
+        vec![
+            self.id,
+            self.debit_account_id,
+            self.credit_account_id,
+            self.amount,
+            self.pending_id,
+            self.user_data_128,
+            self.user_data_64 as u128,
+            self.user_data_32 as u128,
+            self.timeout as u128,
+            self.ledger as u128,
+            self.code as u128,
+            self.flags.bits() as u128,
+        ]
+    }
+}
+
+/// Export file format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Ndjson,
+}
+
+impl Format {
+    /// Pick the format from a file extension.
+    pub fn from_path(path: &Path) -> io::Result<Format> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            Some("ndjson" | "jsonl") => Ok(Format::Ndjson),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: unknown export format (use .csv, .json, .ndjson or .jsonl)",
+                    path.display()
+                ),
+            )),
+        }
+    }
+}
+
+/// Streams records of one type to a writer.
+pub struct Exporter<W: Write> {
+    out: W,
+    format: Format,
+    rows: u64,
+}
+
+impl Exporter<BufWriter<File>> {
+    /// Create `path`, choosing the format from its extension.
+    pub fn create<R: Record>(path: &Path) -> io::Result<Self> {
+        let format = Format::from_path(path)?;
+        Exporter::new::<R>(BufWriter::new(File::create(path)?), format)
+    }
+}
+
+impl<W: Write> Exporter<W> {
+    /// Start an export of `R` records, writing any header.
+    pub fn new<R: Record>(mut out: W, format: Format) -> io::Result<Self> {
+        match format {
+            Format::Csv => {
+                let names: Vec<&str> = R::COLUMNS.iter().map(|c| c.name).collect();
+                writeln!(out, "{}", names.join(","))?;
+            }
+            Format::Json => write!(out, "[")?,
+            Format::Ndjson => {}
+        }
+        Ok(Exporter {
+            out,
+            format,
+            rows: 0,
+        })
+    }
+
+    /// Append records.
+    pub fn write<R: Record>(&mut self, records: &[R]) -> io::Result<()> {
+        for record in records {
+            let values = record.values();
+            match self.format {
+                Format::Csv => {
+                    let row: Vec<String> = values.iter().map(u128::to_string).collect();
+                    writeln!(self.out, "{}", row.join(","))?;
+                }
+                Format::Json => {
+                    let sep = if self.rows == 0 { "\n  " } else { ",\n  " };
+                    write!(self.out, "{}", sep)?;
+                    write_object::<R>(&mut self.out, &values)?;
+                }
+                Format::Ndjson => {
+                    write_object::<R>(&mut self.out, &values)?;
+                    writeln!(self.out)?;
+                }
+            }
+            self.rows += 1;
+        }
+        Ok(())
+    }
+
+    /// Number of records written so far.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Write any trailer and flush.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.format == Format::Json {
+            let end = if self.rows == 0 { "]\n" } else { "\n]\n" };
+            write!(self.out, "{}", end)?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn write_object<R: Record>(out: &mut impl Write, values: &[u128]) -> io::Result<()> {
+    write!(out, "{{")?;
+    for (i, (column, value)) in R::COLUMNS.iter().zip(values).enumerate() {
+        let sep = if i == 0 { "" } else { "," };
+        if column.wide {
+            write!(out, "{}\"{}\":\"{}\"", sep, column.name, value)?;
+        } else {
+            write!(out, "{}\"{}\":{}", sep, column.name, value)?;
+        }
+    }
+    write!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tb_rs::TransferFlags;
+
+    fn transfer(id: u128) -> Transfer {
+        Transfer {
+            id,
+            debit_account_id: 1,
+            credit_account_id: u128::MAX,
+            amount: 10,
+            ledger: 2,
+            code: 3,
+            flags: TransferFlags::LINKED,
+            ..Default::default()
+        }
+    }
+
+    fn export(format: Format, transfers: &[Transfer]) -> String {
+        let mut exporter = Exporter::new::<Transfer>(Vec::new(), format).unwrap();
+        exporter.write(transfers).unwrap();
+        assert_eq!(exporter.rows(), transfers.len() as u64);
+        String::from_utf8(exporter.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("a.csv")).unwrap(), Format::Csv);
+        assert_eq!(
+            Format::from_path(Path::new("a.json")).unwrap(),
+            Format::Json
+        );
+        assert_eq!(
+            Format::from_path(Path::new("dir/a.jsonl")).unwrap(),
+            Format::Ndjson
+        );
+        assert!(Format::from_path(Path::new("a.txt")).is_err());
+        assert!(Format::from_path(Path::new("a")).is_err());
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let out = export(Format::Csv, &[transfer(7)]);
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "id,debit_account_id,credit_account_id,amount,pending_id,user_data_128,\
+             user_data_64,user_data_32,timeout,ledger,code,flags"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("7,1,{},10,0,0,0,0,0,2,3,1", u128::MAX)
+        );
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn test_export_ndjson() {
+        let out = export(Format::Ndjson, &[transfer(7), transfer(8)]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"id\":\"7\",\"debit_account_id\":\"1\","));
+        assert!(lines[1].ends_with("\"ledger\":2,\"code\":3,\"flags\":1}"));
+    }
+
+    #[test]
+    fn test_export_json() {
+        let out = export(Format::Json, &[transfer(7), transfer(8)]);
+        assert!(out.starts_with("[\n  {\"id\":\"7\""));
+        assert!(out.contains("},\n  {\"id\":\"8\""));
+        assert!(out.ends_with("}\n]\n"));
+
+        assert_eq!(export(Format::Json, &[]), "[]\n");
+    }
+}
@@ -0,0 +1,217 @@
+//! Pass/fail limits for using a run as a CI regression gate.
+//!
+//! `--max-error-rate` and `--max-p99` are judged against the run's
+//! transfers once they are all sent. Each limit is reported with the value
+//! it was held against, and if any is broken tb-gen exits non-zero.
+//! `--verdict PATH` also writes the result as a JSON object, pass or fail:
+//!
+//! ```json
+//! {"pass":false,"run_id":7001,"checks":[
+//!   {"name":"error_rate","limit":0.001,"value":0.0004,"pass":true},
+//!   {"name":"p99_ms","limit":5,"value":7.214,"pass":false}],
+//!  "transfers":{"created":99960,"failed":40,...}}
+//! ```
+//!
+//! `transfers` holds the same fields as the `--output json` summary.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::report::Tally;
+
+/// Limits a run must stay within.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Largest fraction of transfers that may fail.
+    pub max_error_rate: Option<f64>,
+    /// Largest request p99 latency.
+    pub max_p99: Option<Duration>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        self.max_error_rate.is_none() && self.max_p99.is_none()
+    }
+}
+
+/// One limit held against the run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub limit: f64,
+    pub value: f64,
+    pub pass: bool,
+}
+
+/// Hold `tally` against `limits`.
+pub fn judge(limits: &Limits, tally: &Tally) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if let Some(limit) = limits.max_error_rate {
+        let value = if tally.sent() == 0 {
+            0.0
+        } else {
+            tally.failed as f64 / tally.sent() as f64
+        };
+        checks.push(Check {
+            name: "error_rate",
+            limit,
+            value,
+            pass: value <= limit,
+        });
+    }
+    if let Some(limit) = limits.max_p99 {
+        let p99 = tally.latency(99.0).unwrap_or_default();
+        checks.push(Check {
+            name: "p99_ms",
+            limit: limit.as_secs_f64() * 1000.0,
+            value: p99.as_secs_f64() * 1000.0,
+            pass: p99 <= limit,
+        });
+    }
+    checks
+}
+
+/// Print the checks; fails if any did not pass.
+pub fn report(checks: &[Check]) -> Result<(), String> {
+    say!();
+    for check in checks {
+        let (value, limit) = match check.name {
+            "error_rate" => (
+                format!("{:.3}%", check.value * 100.0),
+                format!("{:.3}%", check.limit * 100.0),
+            ),
+            _ => (
+                format!("{:.3}ms", check.value),
+                format!("{:.3}ms", check.limit),
+            ),
+        };
+        say!(
+            "Gate {}: {} (limit {}) {}",
+            check.name,
+            value,
+            limit,
+            if check.pass { "pass" } else { "FAIL" }
+        );
+    }
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|check| !check.pass)
+        .map(|check| check.name)
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("run broke its limits: {}", failed.join(", ")))
+    }
+}
+
+/// The verdict as a JSON object.
+pub fn verdict(run_id: u64, checks: &[Check], tally: &Tally, elapsed: Duration) -> String {
+    let pass = checks.iter().all(|check| check.pass);
+    let mut out = format!("{{\"pass\":{},\"run_id\":{},\"checks\":[", pass, run_id);
+    for (i, check) in checks.iter().enumerate() {
+        let sep = if i == 0 { "" } else { "," };
+        write!(
+            out,
+            "{}{{\"name\":\"{}\",\"limit\":{},\"value\":{},\"pass\":{}}}",
+            sep, check.name, check.limit, check.value, check.pass
+        )
+        .unwrap();
+    }
+    write!(out, "],\"transfers\":{}}}", tally.json(elapsed)).unwrap();
+    out
+}
+
+/// Write the verdict to `path`.
+pub fn write_verdict(
+    path: &Path,
+    run_id: u64,
+    checks: &[Check],
+    tally: &Tally,
+    elapsed: Duration,
+) -> Result<(), String> {
+    let mut text = verdict(run_id, checks, tally, elapsed);
+    text.push('\n');
+    fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Parse a rate given as a fraction (`0.001`) or a percentage (`0.1%`).
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let rate = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => s.parse::<f64>(),
+    }
+    .map_err(|_| format!("invalid rate '{}' (use e.g. 0.001 or 0.1%)", s))?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!(
+            "rate '{}' must be between 0 and 1 (0% and 100%)",
+            s
+        ));
+    }
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(created: usize, failed: usize, latency_ms: u64) -> Tally {
+        let mut tally = Tally::default();
+        let codes = std::iter::repeat_n("ExceedsCredits".to_string(), failed);
+        tally.record(created + failed, codes, Duration::from_millis(latency_ms));
+        tally
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("0.001"), Ok(0.001));
+        assert_eq!(parse_rate("0.1%"), Ok(0.001));
+        assert_eq!(parse_rate("5 %"), Ok(0.05));
+        assert!(parse_rate("150%").is_err());
+        assert!(parse_rate("-0.1").is_err());
+        assert!(parse_rate("lots").is_err());
+    }
+
+    #[test]
+    fn test_judge() {
+        let limits = Limits {
+            max_error_rate: Some(0.01),
+            max_p99: Some(Duration::from_millis(5)),
+        };
+        let checks = judge(&limits, &tally(995, 5, 3));
+        assert!(checks.iter().all(|check| check.pass));
+        assert!(report(&checks).is_ok());
+
+        let checks = judge(&limits, &tally(980, 20, 7));
+        assert_eq!(checks[0].value, 0.02);
+        assert!(!checks[0].pass);
+        assert_eq!(checks[1].value, 7.0);
+        assert!(!checks[1].pass);
+        assert_eq!(
+            report(&checks),
+            Err("run broke its limits: error_rate, p99_ms".to_string())
+        );
+
+        assert!(judge(&Limits::default(), &tally(1, 0, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_verdict() {
+        let limits = Limits {
+            max_error_rate: Some(0.5),
+            max_p99: None,
+        };
+        let tally = tally(3, 1, 2);
+        let checks = judge(&limits, &tally);
+        let verdict = verdict(7, &checks, &tally, Duration::from_secs(1));
+        assert!(verdict.starts_with(
+            "{\"pass\":true,\"run_id\":7,\"checks\":[\
+             {\"name\":\"error_rate\",\"limit\":0.5,\"value\":0.25,\"pass\":true}],\
+             \"transfers\":{\"created\":3,\"failed\":1,"
+        ));
+        assert!(verdict.ends_with("}}"));
+    }
+}
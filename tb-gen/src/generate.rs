@@ -0,0 +1,202 @@
+//! Random accounts and transfers.
+//!
+//! The building blocks of every dataset tb-gen sends: accounts on one
+//! ledger, and transfers between accounts of the same ledger picked by each
+//! ledger's distribution.
+
+use rand::Rng;
+use tb_rs::{Account, AccountFlags, Transfer, TransferFlags};
+
+use crate::ledger::{self, Ledger};
+use crate::seed::Source;
+
+/// Generate a batch of random accounts.
+pub fn generate_accounts(count: u32, ledger: u32, code: u16, source: &mut Source) -> Vec<Account> {
+    let mut accounts = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        accounts.push(Account {
+            id: source.id(),
+            ledger,
+            code,
+            flags: AccountFlags::empty(),
+            ..Default::default()
+        });
+    }
+
+    accounts
+}
+
+/// Give `ratio` of `accounts` the HISTORY flag, spread evenly among them.
+pub fn flag_history(accounts: &mut [Account], ratio: f64) {
+    for (i, account) in accounts.iter_mut().enumerate() {
+        // Flag each account that brings the flagged share up to another one.
+        if ((i + 1) as f64 * ratio).floor() > (i as f64 * ratio).floor() {
+            account.flags |= AccountFlags::HISTORY;
+        }
+    }
+}
+
+/// Tag `accounts` with the run ID.
+pub fn tag_accounts(accounts: &mut [Account], run_id: u64) {
+    for account in accounts {
+        account.user_data_64 = run_id;
+    }
+}
+
+/// Generate a batch of random transfers between accounts of the same ledger.
+pub fn generate_transfers(
+    count: u32,
+    ledgers: &[Ledger],
+    code: u16,
+    max_amount: u128,
+    source: &mut Source,
+) -> Vec<Transfer> {
+    assert!(
+        ledgers.iter().all(|l| l.account_ids.len() >= 2),
+        "Need at least 2 accounts for transfers"
+    );
+
+    let mut transfers = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        // Pick debit and credit accounts (must be different)
+        let ledger = ledger::pick(ledgers, &mut source.rng);
+        let (debit_account_id, credit_account_id) = ledger.pick_pair(&mut source.rng);
+
+        let amount = source.rng.gen_range(1..=max_amount);
+
+        transfers.push(Transfer {
+            id: source.id(),
+            debit_account_id,
+            credit_account_id,
+            amount,
+            ledger: ledger.id,
+            code,
+            flags: TransferFlags::empty(),
+            ..Default::default()
+        });
+    }
+
+    transfers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distribution::Distribution;
+
+    #[test]
+    fn test_flag_history() {
+        let flagged = |count, ratio| {
+            let mut accounts = generate_accounts(count, 1, 1, &mut Source::new(Some(1)));
+            flag_history(&mut accounts, ratio);
+            accounts
+                .iter()
+                .filter(|a| a.flags.contains(AccountFlags::HISTORY))
+                .count()
+        };
+        assert_eq!(flagged(1000, 0.2), 200);
+        assert_eq!(flagged(10, 1.0), 10);
+        assert_eq!(flagged(10, 0.0), 0);
+        assert_eq!(flagged(3, 0.5), 1);
+    }
+
+    #[test]
+    fn test_generate_accounts() {
+        let accounts = generate_accounts(10, 1, 100, &mut Source::new(None));
+
+        assert_eq!(accounts.len(), 10);
+        for account in &accounts {
+            assert_ne!(account.id, 0);
+            assert_eq!(account.ledger, 1);
+            assert_eq!(account.code, 100);
+            assert!(account.flags.is_empty());
+        }
+
+        // Verify all IDs are unique
+        let mut ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 10);
+    }
+
+    #[test]
+    fn test_generate_transfers() {
+        let account_ids: Vec<u128> = (1..=5).map(|i| i as u128).collect();
+        let ledgers = [Ledger::new(
+            1,
+            account_ids.clone(),
+            Distribution::Uniform,
+            1.1,
+        )];
+        let transfers = generate_transfers(20, &ledgers, 50, 1000, &mut Source::new(None));
+
+        assert_eq!(transfers.len(), 20);
+        for transfer in &transfers {
+            assert_ne!(transfer.id, 0);
+            assert_eq!(transfer.ledger, 1);
+            assert_eq!(transfer.code, 50);
+            assert!(transfer.flags.is_empty());
+            assert!(transfer.amount >= 1 && transfer.amount <= 1000);
+            assert!(account_ids.contains(&transfer.debit_account_id));
+            assert!(account_ids.contains(&transfer.credit_account_id));
+            assert_ne!(transfer.debit_account_id, transfer.credit_account_id);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Need at least 2 accounts")]
+    fn test_generate_transfers_requires_two_accounts() {
+        let ledgers = [Ledger::new(1, vec![1u128], Distribution::Uniform, 1.1)];
+        generate_transfers(1, &ledgers, 1, 100, &mut Source::new(None));
+    }
+
+    #[test]
+    fn test_generate_transfers_zipfian() {
+        let account_ids: Vec<u128> = (1..=100).map(|i| i as u128).collect();
+        let ledgers = [Ledger::new(1, account_ids, Distribution::Zipfian, 2.0)];
+        let transfers = generate_transfers(1000, &ledgers, 1, 100, &mut Source::new(None));
+
+        let hottest = transfers
+            .iter()
+            .filter(|t| t.debit_account_id == 1 || t.credit_account_id == 1)
+            .count();
+        assert!(hottest > transfers.len() / 2, "hottest: {}", hottest);
+        for transfer in &transfers {
+            assert_ne!(transfer.debit_account_id, transfer.credit_account_id);
+        }
+    }
+
+    #[test]
+    fn test_generate_transfers_stay_in_ledger() {
+        let ledgers = [
+            Ledger::new(1, (10..20).collect(), Distribution::Uniform, 1.1),
+            Ledger::new(2, (20..30).collect(), Distribution::Uniform, 1.1),
+        ];
+        let transfers = generate_transfers(200, &ledgers, 1, 100, &mut Source::new(None));
+
+        for transfer in &transfers {
+            let ledger = ledgers.iter().find(|l| l.id == transfer.ledger).unwrap();
+            assert!(ledger.account_ids.contains(&transfer.debit_account_id));
+            assert!(ledger.account_ids.contains(&transfer.credit_account_id));
+        }
+        assert!(transfers.iter().any(|t| t.ledger == 1));
+        assert!(transfers.iter().any(|t| t.ledger == 2));
+    }
+
+    #[test]
+    fn test_seed_reproduces_dataset() {
+        let generate = |seed| {
+            let mut source = Source::new(Some(seed));
+            let accounts = generate_accounts(10, 1, 1, &mut source);
+            let ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
+            let ledgers = [Ledger::new(1, ids, Distribution::Zipfian, 1.1)];
+            let transfers = generate_transfers(50, &ledgers, 1, 1000, &mut source);
+            (accounts, transfers)
+        };
+
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+}
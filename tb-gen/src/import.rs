@@ -0,0 +1,318 @@
+//! Loading accounts and transfers from CSV files.
+//!
+//! The first line names the columns; the rest hold one record each. The
+//! header decides what a file contains: any of `debit_account_id`,
+//! `credit_account_id` or `amount` makes it a transfers file, otherwise it
+//! holds accounts. Column names are the ones `--out` writes, in any order and
+//! any subset:
+//!
+//! | Accounts        | Transfers           |
+//! |-----------------|---------------------|
+//! | `id`            | `id`                |
+//! | `user_data_128` | `debit_account_id`  |
+//! | `user_data_64`  | `credit_account_id` |
+//! | `user_data_32`  | `amount`            |
+//! | `ledger`        | `pending_id`        |
+//! | `code`          | `user_data_128`     |
+//! | `flags`         | `user_data_64`      |
+//! | `timestamp`     | `user_data_32`      |
+//! |                 | `timeout`           |
+//! |                 | `ledger`            |
+//! |                 | `code`              |
+//! |                 | `flags`             |
+//! |                 | `timestamp`         |
+//!
+//! Numbers are decimal or `0x` hex. Flags are raw bits or names joined by
+//! `|`, e.g. `linked|pending`. A missing or empty `id` gets a fresh ID; a
+//! missing `ledger` or `code` takes the command-line value; anything else
+//! missing is zero. `timestamp` is only accepted by the server together with
+//! the `imported` flag. Blank lines and lines starting with `#` are skipped.
+
+use std::path::Path;
+
+use tb_rs::{Account, AccountFlags, Transfer, TransferFlags};
+
+use crate::export::Record;
+
+/// Records loaded from one file.
+pub enum Dataset {
+    Accounts(Vec<Account>),
+    Transfers(Vec<Transfer>),
+}
+
+/// Values for columns a file leaves out.
+#[derive(Clone, Copy, Debug)]
+pub struct Defaults {
+    pub ledger: u32,
+    pub code: u16,
+}
+
+/// A record that can be read from a CSV row.
+trait Row: Record + Sized {
+    /// A record with a fresh ID and the default ledger and code.
+    fn template(defaults: &Defaults) -> Self;
+
+    /// Set `column` from a non-empty cell.
+    fn set(&mut self, column: &str, value: &str) -> Result<(), String>;
+}
+
+impl Row for Account {
+    fn template(defaults: &Defaults) -> Self {
+        Account {
+            id: tb_rs::id(),
+            ledger: defaults.ledger,
+            code: defaults.code,
+            ..Default::default()
+        }
+    }
+
+    fn set(&mut self, column: &str, value: &str) -> Result<(), String> {
+        match column {
+            "id" => self.id = number(value)?,
+            "user_data_128" => self.user_data_128 = number(value)?,
+            "user_data_64" => self.user_data_64 = number(value)?,
+            "user_data_32" => self.user_data_32 = number(value)?,
+            "ledger" => self.ledger = number(value)?,
+            "code" => self.code = number(value)?,
+            "flags" => self.flags = flags(value, AccountFlags::from_name, AccountFlags::from_bits)?,
+            "timestamp" => self.timestamp = number(value)?,
+            _ => unreachable!("column checked against the header"),
+        }
+        Ok(())
+    }
+}
+
+impl Row for Transfer {
+    fn template(defaults: &Defaults) -> Self {
+        Transfer {
+            id: tb_rs::id(),
+            ledger: defaults.ledger,
+            code: defaults.code,
+            ..Default::default()
+        }
+    }
+
+    fn set(&mut self, column: &str, value: &str) -> Result<(), String> {
+        match column {
+            "id" => self.id = number(value)?,
+            "debit_account_id" => self.debit_account_id = number(value)?,
+            "credit_account_id" => self.credit_account_id = number(value)?,
+            "amount" => self.amount = number(value)?,
+            "pending_id" => self.pending_id = number(value)?,
+            "user_data_128" => self.user_data_128 = number(value)?,
+            "user_data_64" => self.user_data_64 = number(value)?,
+            "user_data_32" => self.user_data_32 = number(value)?,
+            "timeout" => self.timeout = number(value)?,
+            "ledger" => self.ledger = number(value)?,
+            "code" => self.code = number(value)?,
+            "flags" => {
+                self.flags = flags(value, TransferFlags::from_name, TransferFlags::from_bits)?
+            }
+            "timestamp" => self.timestamp = number(value)?,
+            _ => unreachable!("column checked against the header"),
+        }
+        Ok(())
+    }
+}
+
+/// Read a CSV file.
+pub fn read(path: &Path, defaults: &Defaults) -> Result<Dataset, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+    parse(&text, defaults).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Parse CSV text; errors name the offending line.
+pub fn parse(text: &str, defaults: &Defaults) -> Result<Dataset, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let Some((_, header)) = lines.next() else {
+        return Err("missing header".to_string());
+    };
+    let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+    let is_transfers = columns
+        .iter()
+        .any(|c| matches!(*c, "debit_account_id" | "credit_account_id" | "amount"));
+
+    if is_transfers {
+        rows::<Transfer>(&columns, lines, defaults).map(Dataset::Transfers)
+    } else {
+        rows::<Account>(&columns, lines, defaults).map(Dataset::Accounts)
+    }
+}
+
+fn rows<'a, R: Row>(
+    columns: &[&str],
+    lines: impl Iterator<Item = (usize, &'a str)>,
+    defaults: &Defaults,
+) -> Result<Vec<R>, String> {
+    for column in columns {
+        let known = *column == "timestamp" || R::COLUMNS.iter().any(|c| c.name == *column);
+        if !known {
+            let names: Vec<&str> = R::COLUMNS.iter().map(|c| c.name).collect();
+            return Err(format!(
+                "unknown column '{}' (expected {}, timestamp)",
+                column,
+                names.join(", ")
+            ));
+        }
+    }
+
+    let mut records = Vec::new();
+    for (line_no, line) in lines {
+        let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+        if cells.len() != columns.len() {
+            return Err(format!(
+                "line {}: expected {} fields, got {}",
+                line_no,
+                columns.len(),
+                cells.len()
+            ));
+        }
+
+        let mut record = R::template(defaults);
+        for (column, cell) in columns.iter().zip(cells) {
+            if !cell.is_empty() {
+                record
+                    .set(column, cell)
+                    .map_err(|e| format!("line {}: {}: {}", line_no, column, e))?;
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn number<T: TryFrom<u128>>(value: &str) -> Result<T, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    let n = parsed.map_err(|_| format!("invalid number '{}'", value))?;
+    T::try_from(n).map_err(|_| format!("{} is out of range", value))
+}
+
+fn flags<F: std::ops::BitOr<Output = F>>(
+    value: &str,
+    from_name: fn(&str) -> Option<F>,
+    from_bits: fn(u16) -> Option<F>,
+) -> Result<F, String> {
+    if value.starts_with(|c: char| c.is_ascii_digit()) {
+        let bits = number(value)?;
+        return from_bits(bits).ok_or_else(|| format!("unknown flag bits {}", value));
+    }
+    value
+        .split('|')
+        .map(|name| {
+            let name = name.trim().to_ascii_uppercase();
+            from_name(&name).ok_or_else(|| format!("unknown flag '{}'", name.to_lowercase()))
+        })
+        .reduce(|a, b| Ok(a? | b?))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{Exporter, Format};
+
+    const DEFAULTS: Defaults = Defaults { ledger: 7, code: 3 };
+
+    #[test]
+    fn test_parse_accounts() {
+        let text = "\
+            # exported from the old system\n\
+            id,user_data_64,flags\n\
+            1,42,0\n\
+            \n\
+            0x10,,linked|history\n\
+            ,,debits_must_not_exceed_credits\n";
+        let Ok(Dataset::Accounts(accounts)) = parse(text, &DEFAULTS) else {
+            panic!("expected accounts");
+        };
+
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[0].id, 1);
+        assert_eq!(accounts[0].user_data_64, 42);
+        assert_eq!(accounts[0].ledger, 7);
+        assert_eq!(accounts[0].code, 3);
+        assert_eq!(accounts[1].id, 16);
+        assert_eq!(
+            accounts[1].flags,
+            AccountFlags::LINKED | AccountFlags::HISTORY
+        );
+        // Missing IDs are generated.
+        assert_ne!(accounts[2].id, 0);
+        assert_eq!(
+            accounts[2].flags,
+            AccountFlags::DEBITS_MUST_NOT_EXCEED_CREDITS
+        );
+    }
+
+    #[test]
+    fn test_parse_transfers() {
+        let text = "debit_account_id,credit_account_id,amount,ledger,flags,timestamp\n\
+                    1,2,100,9,0x100,12345\n";
+        let Ok(Dataset::Transfers(transfers)) = parse(text, &DEFAULTS) else {
+            panic!("expected transfers");
+        };
+
+        assert_eq!(transfers.len(), 1);
+        let t = &transfers[0];
+        assert_ne!(t.id, 0);
+        assert_eq!((t.debit_account_id, t.credit_account_id), (1, 2));
+        assert_eq!(t.amount, 100);
+        assert_eq!(t.ledger, 9);
+        assert_eq!(t.code, 3);
+        assert_eq!(t.flags, TransferFlags::IMPORTED);
+        assert_eq!(t.timestamp, 12345);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |text: &str| parse(text, &DEFAULTS).err().unwrap();
+
+        assert_eq!(err(""), "missing header");
+        assert!(err("id,balance\n1,2\n").starts_with("unknown column 'balance'"));
+        assert_eq!(err("id,code\n1\n"), "line 2: expected 2 fields, got 1");
+        assert_eq!(
+            err("id,code\n1,70000\n"),
+            "line 2: code: 70000 is out of range"
+        );
+        assert_eq!(err("id\nabc\n"), "line 2: id: invalid number 'abc'");
+        assert_eq!(
+            err("amount,flags\n1,linked|bogus\n"),
+            "line 2: flags: unknown flag 'bogus'"
+        );
+        assert_eq!(
+            err("amount,flags\n1,512\n"),
+            "line 2: flags: unknown flag bits 512"
+        );
+    }
+
+    #[test]
+    fn test_import_reads_export() {
+        let transfer = Transfer {
+            id: u128::MAX,
+            debit_account_id: 1,
+            credit_account_id: 2,
+            amount: 5,
+            user_data_32: 8,
+            ledger: 1,
+            code: 1,
+            flags: TransferFlags::LINKED | TransferFlags::PENDING,
+            ..Default::default()
+        };
+        let mut exporter = Exporter::new::<Transfer>(Vec::new(), Format::Csv).unwrap();
+        exporter.write(&[transfer]).unwrap();
+        let text = String::from_utf8(exporter.finish().unwrap()).unwrap();
+
+        let Ok(Dataset::Transfers(transfers)) = parse(&text, &DEFAULTS) else {
+            panic!("expected transfers");
+        };
+        assert_eq!(transfers, [transfer]);
+    }
+}
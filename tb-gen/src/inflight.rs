@@ -0,0 +1,184 @@
+//! Keeping several batches of transfers outstanding.
+//!
+//! A TigerBeetle session has one request in flight at a time, and tb-rs
+//! sends a client's requests one after another, so a sender that awaits
+//! each batch leaves the cluster idle between a reply and its next request.
+//! `--inflight N` gives each sender N sessions on its runtime (its own
+//! client and N-1 more) and keeps a batch outstanding on every one: a
+//! session takes the next batch as soon as its last one is answered.
+//! Batches still end on chain boundaries, but may commit out of order.
+//!
+//! The achieved concurrency is the number of batches outstanding on
+//! average, from the time spent in requests over the time taken. It falls
+//! short of N when the sender, not the cluster, is the bottleneck.
+
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Instant;
+
+use tb_rs::{Client, Transfer};
+
+use crate::report::{Progress, Tally};
+use crate::{linked, send_batch, worker_label, Args};
+
+/// Batches shared by a sender's sessions, and their results.
+struct Queue<'a> {
+    batches: Vec<&'a [Transfer]>,
+    next: Cell<usize>,
+    tally: RefCell<Tally>,
+    progress: RefCell<Progress>,
+}
+
+impl<'a> Queue<'a> {
+    fn take(&self) -> Option<&'a [Transfer]> {
+        let next = self.next.get();
+        self.next.set(next + 1);
+        self.batches.get(next).copied()
+    }
+}
+
+/// Create transfers with `args.inflight` batches outstanding, reporting
+/// failures, progress and the concurrency achieved.
+pub async fn send_transfers(
+    args: &Args,
+    client: &mut Client,
+    transfers: &[Transfer],
+    batch_size: u32,
+    worker: Option<usize>,
+) -> Result<Tally, Box<dyn Error>> {
+    if transfers.is_empty() {
+        return Ok(Tally::default());
+    }
+
+    let label = worker_label(worker);
+    let mut sessions = Vec::with_capacity(args.inflight - 1);
+    for _ in 1..args.inflight {
+        sessions.push(Client::connect(args.cluster, &args.addresses()).await?);
+    }
+    if worker.is_none() {
+        say!();
+        say!("Creating transfers with {} in flight...", args.inflight);
+    }
+
+    // Batches end on chain boundaries; a split chain would be rejected.
+    let queue = Queue {
+        batches: linked::batches(transfers, batch_size as usize)
+            .ok_or("a linked chain is longer than the batch size")?,
+        next: Cell::new(0),
+        tally: RefCell::new(Tally::default()),
+        progress: RefCell::new(Progress::new("transfers", transfers.len() as u64, worker)),
+    };
+    let start = Instant::now();
+    let lanes = std::iter::once(client)
+        .chain(sessions.iter_mut())
+        .map(|client| lane(client, &queue, worker))
+        .collect();
+    try_join_all(lanes).await?;
+    let elapsed = start.elapsed();
+    for session in sessions {
+        session.close().await;
+    }
+
+    queue.progress.borrow().finish();
+    let tally = queue.tally.into_inner();
+    say!(
+        "{}Transfers: {} created, {} failed in {:.1?} ({:.0} tps, {:.2} of {} batches in flight)",
+        label,
+        tally.created,
+        tally.failed,
+        elapsed,
+        tally.sent() as f64 / elapsed.as_secs_f64(),
+        tally.concurrency(elapsed),
+        args.inflight
+    );
+
+    Ok(tally)
+}
+
+/// Send batches from `queue` on one session until none are left.
+async fn lane(
+    client: &mut Client,
+    queue: &Queue<'_>,
+    worker: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    while let Some(chunk) = queue.take() {
+        let (codes, latency) = send_batch(client, chunk, worker).await?;
+        let mut tally = queue.tally.borrow_mut();
+        tally.record(chunk.len(), codes, latency);
+        queue.progress.borrow_mut().update(tally.sent(), None);
+    }
+    Ok(())
+}
+
+/// Run `futures` together until all are done or one fails.
+async fn try_join_all<F, E>(futures: Vec<F>) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+{
+    let mut pending: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    poll_fn(|cx| {
+        let mut i = 0;
+        while i < pending.len() {
+            match pending[i].as_mut().poll(cx) {
+                Poll::Ready(Ok(())) => drop(pending.swap_remove(i)),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => i += 1,
+            }
+        }
+        if pending.is_empty() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Logs `id`, yields once, then logs `id + 10` and finishes with
+    /// `result`.
+    async fn yield_then(
+        log: &RefCell<Vec<u32>>,
+        id: u32,
+        result: Result<(), u32>,
+    ) -> Result<(), u32> {
+        log.borrow_mut().push(id);
+        let mut yielded = false;
+        poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+        log.borrow_mut().push(id + 10);
+        result
+    }
+
+    #[test]
+    fn test_try_join_all() {
+        let log = RefCell::new(Vec::new());
+        let joined = try_join_all((0..3).map(|id| yield_then(&log, id, Ok(()))).collect());
+        assert_eq!(tokio_uring::start(joined), Ok(()));
+        // All started before any finished.
+        assert_eq!(log.borrow()[..3], [0, 1, 2]);
+        assert_eq!(log.borrow().len(), 6);
+
+        let log = RefCell::new(Vec::new());
+        let joined = try_join_all(
+            (0..3)
+                .map(|id| yield_then(&log, id, if id == 1 { Err(id) } else { Ok(()) }))
+                .collect(),
+        );
+        assert_eq!(tokio_uring::start(joined), Err(1));
+    }
+}
@@ -0,0 +1,145 @@
+//! Datasets spread over several ledgers.
+//!
+//! Every account lives in one ledger and every transfer stays inside one,
+//! since the server rejects transfers between ledgers. Busier ledgers (more
+//! accounts) get proportionally more transfers.
+
+use rand::Rng;
+
+use crate::distribution::{AccountPicker, Distribution};
+
+/// The accounts of one ledger.
+pub struct Ledger {
+    pub id: u32,
+    pub account_ids: Vec<u128>,
+    picker: AccountPicker,
+}
+
+impl Ledger {
+    /// A ledger whose transfers pick accounts by `distribution`.
+    pub fn new(id: u32, account_ids: Vec<u128>, distribution: Distribution, skew: f64) -> Self {
+        let picker = AccountPicker::new(distribution, account_ids.len(), skew);
+        Ledger {
+            id,
+            account_ids,
+            picker,
+        }
+    }
+
+    /// Pick two different accounts to debit and credit.
+    pub fn pick_pair(&self, rng: &mut impl Rng) -> (u128, u128) {
+        let debit_idx = self.picker.pick(rng);
+        let mut credit_idx = self.picker.pick(rng);
+        while credit_idx == debit_idx {
+            credit_idx = self.picker.pick(rng);
+        }
+        (self.account_ids[debit_idx], self.account_ids[credit_idx])
+    }
+}
+
+/// Pick a ledger with probability proportional to its account count.
+pub fn pick<'a>(ledgers: &'a [Ledger], rng: &mut impl Rng) -> &'a Ledger {
+    if let [ledger] = ledgers {
+        return ledger;
+    }
+    let total: usize = ledgers.iter().map(|l| l.account_ids.len()).sum();
+    let mut n = rng.gen_range(0..total);
+    for ledger in ledgers {
+        if n < ledger.account_ids.len() {
+            return ledger;
+        }
+        n -= ledger.account_ids.len();
+    }
+    unreachable!("n is below the total")
+}
+
+/// Parse `--ledgers`, e.g. `1-10`, `1,3,5` or `1-3:100,7:5000`, into
+/// `(ledger, account count)` pairs.
+///
+/// Entries without `:count` get `default_accounts`.
+pub fn parse_ledgers(spec: &str, default_accounts: u32) -> Result<Vec<(u32, u32)>, String> {
+    let mut ledgers: Vec<(u32, u32)> = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (range, count) = match entry.split_once(':') {
+            Some((range, count)) => {
+                let count = count
+                    .parse()
+                    .map_err(|_| format!("invalid account count in '{}'", entry))?;
+                (range, count)
+            }
+            None => (entry, default_accounts),
+        };
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let ledger = |s: &str| -> Result<u32, String> {
+            match s.trim().parse() {
+                Ok(0) => Err("ledger 0 is reserved".to_string()),
+                Ok(id) => Ok(id),
+                Err(_) => Err(format!("invalid ledger in '{}'", entry)),
+            }
+        };
+        let (first, last) = (ledger(first)?, ledger(last)?);
+        if first > last {
+            return Err(format!("empty ledger range '{}'", entry));
+        }
+        for id in first..=last {
+            if ledgers.iter().any(|&(l, _)| l == id) {
+                return Err(format!("ledger {} listed twice", id));
+            }
+            ledgers.push((id, count));
+        }
+    }
+    if ledgers.is_empty() {
+        return Err("no ledgers given".to_string());
+    }
+    Ok(ledgers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ledgers() {
+        assert_eq!(
+            parse_ledgers("1-3", 10),
+            Ok(vec![(1, 10), (2, 10), (3, 10)])
+        );
+        assert_eq!(
+            parse_ledgers("1-2:100, 7:5000,9", 10),
+            Ok(vec![(1, 100), (2, 100), (7, 5000), (9, 10)])
+        );
+        assert!(parse_ledgers("", 10).is_err());
+        assert!(parse_ledgers("0-2", 10).is_err());
+        assert!(parse_ledgers("3-1", 10).is_err());
+        assert!(parse_ledgers("1-3,2", 10).is_err());
+        assert!(parse_ledgers("1:many", 10).is_err());
+        assert!(parse_ledgers("usd", 10).is_err());
+    }
+
+    #[test]
+    fn test_pick_pair_within_ledger() {
+        let ledger = Ledger::new(1, vec![10, 20, 30], Distribution::Uniform, 1.1);
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let (debit, credit) = ledger.pick_pair(&mut rng);
+            assert_ne!(debit, credit);
+            assert!(ledger.account_ids.contains(&debit));
+            assert!(ledger.account_ids.contains(&credit));
+        }
+    }
+
+    #[test]
+    fn test_pick_weighted_by_accounts() {
+        let ledgers = [
+            Ledger::new(1, (0..90).collect(), Distribution::Uniform, 1.1),
+            Ledger::new(2, (100..110).collect(), Distribution::Uniform, 1.1),
+        ];
+        let mut rng = rand::thread_rng();
+        let picks = 10_000;
+        let first = (0..picks)
+            .filter(|_| pick(&ledgers, &mut rng).id == 1)
+            .count();
+        // Expect 90%.
+        assert!((8_500..9_500).contains(&first), "first: {}", first);
+    }
+}
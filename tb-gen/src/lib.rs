@@ -0,0 +1,9 @@
+//! Generation logic of tb-gen, for use as a library.
+//!
+//! The `tb-gen` binary sends what these modules generate; other tools can
+//! use them to fill a cluster with the same kind of data.
+
+pub mod distribution;
+pub mod generate;
+pub mod ledger;
+pub mod seed;
@@ -0,0 +1,227 @@
+//! Linked-chain workloads.
+//!
+//! A chain is a run of transfers where every transfer but the last carries
+//! `LINKED`: the server applies all of them or none. Poisoned chains contain
+//! one transfer that is bound to fail, so the whole chain fails with it.
+
+use rand::Rng;
+use tb_rs::{Transfer, TransferFlags};
+
+/// How to group generated transfers into linked chains.
+#[derive(Clone, Copy, Debug)]
+pub struct Linking {
+    /// Number of transfers in each chain.
+    pub chain_len: usize,
+    /// Probability that a transfer starts a chain.
+    pub ratio: f64,
+    /// Make one transfer in every chain fail.
+    pub poison: bool,
+}
+
+/// Group `transfers` into linked chains and return how many were made.
+///
+/// A poisoned transfer debits and credits the same account, which the server
+/// always rejects.
+pub fn link_chains(transfers: &mut [Transfer], linking: &Linking, rng: &mut impl Rng) -> usize {
+    if linking.chain_len < 2 || linking.ratio <= 0.0 {
+        return 0;
+    }
+
+    let mut chains = 0;
+    let mut i = 0;
+    while i + linking.chain_len <= transfers.len() {
+        if !rng.gen_bool(linking.ratio.min(1.0)) {
+            i += 1;
+            continue;
+        }
+
+        let chain = &mut transfers[i..i + linking.chain_len];
+        let (last, linked) = chain.split_last_mut().unwrap();
+        for transfer in linked {
+            transfer.flags |= TransferFlags::LINKED;
+        }
+        debug_assert!(!last.flags.contains(TransferFlags::LINKED));
+
+        if linking.poison {
+            let victim = &mut chain[rng.gen_range(0..linking.chain_len)];
+            victim.credit_account_id = victim.debit_account_id;
+        }
+
+        chains += 1;
+        i += linking.chain_len;
+    }
+    chains
+}
+
+/// Number of linked chains in `transfers`.
+pub fn count_chains(transfers: &[Transfer]) -> usize {
+    transfers
+        .windows(2)
+        .filter(|w| {
+            w[0].flags.contains(TransferFlags::LINKED)
+                && !w[1].flags.contains(TransferFlags::LINKED)
+        })
+        .count()
+}
+
+/// Split `transfers` into batches of at most `max` without breaking a chain.
+///
+/// Returns `None` if a chain is longer than `max`.
+pub fn batches(transfers: &[Transfer], max: usize) -> Option<Vec<&[Transfer]>> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    // End of the last complete chain (or unlinked transfer) in this batch.
+    let mut boundary = 0;
+
+    for (i, transfer) in transfers.iter().enumerate() {
+        if i - start == max {
+            if boundary == start {
+                return None;
+            }
+            batches.push(&transfers[start..boundary]);
+            start = boundary;
+        }
+        if !transfer.flags.contains(TransferFlags::LINKED) {
+            boundary = i + 1;
+        }
+    }
+    if start < transfers.len() {
+        batches.push(&transfers[start..]);
+    }
+    Some(batches)
+}
+
+/// Split `transfers` into `parts` runs of about equal length without
+/// breaking a chain.
+pub fn split(transfers: &[Transfer], parts: usize) -> Vec<&[Transfer]> {
+    let mut runs = Vec::with_capacity(parts);
+    let mut start = 0;
+    for part in 1..=parts {
+        let mut end = (transfers.len() * part / parts).max(start);
+        while end > 0
+            && end < transfers.len()
+            && transfers[end - 1].flags.contains(TransferFlags::LINKED)
+        {
+            end += 1;
+        }
+        runs.push(&transfers[start..end]);
+        start = end;
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfers(count: usize) -> Vec<Transfer> {
+        (1..=count)
+            .map(|i| Transfer {
+                id: i as u128,
+                debit_account_id: 1,
+                credit_account_id: 2,
+                amount: 1,
+                ledger: 1,
+                code: 1,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn linked(transfers: &[Transfer]) -> Vec<bool> {
+        transfers
+            .iter()
+            .map(|t| t.flags.contains(TransferFlags::LINKED))
+            .collect()
+    }
+
+    #[test]
+    fn test_link_chains_all() {
+        let mut t = transfers(7);
+        let linking = Linking {
+            chain_len: 3,
+            ratio: 1.0,
+            poison: false,
+        };
+        let chains = link_chains(&mut t, &linking, &mut rand::thread_rng());
+
+        assert_eq!(chains, 2);
+        assert_eq!(count_chains(&t), 2);
+        assert_eq!(linked(&t), [true, true, false, true, true, false, false]);
+        assert!(t.iter().all(|t| t.debit_account_id != t.credit_account_id));
+    }
+
+    #[test]
+    fn test_link_chains_disabled() {
+        let mut t = transfers(5);
+        let linking = Linking {
+            chain_len: 3,
+            ratio: 0.0,
+            poison: true,
+        };
+        assert_eq!(link_chains(&mut t, &linking, &mut rand::thread_rng()), 0);
+        assert!(t.iter().all(|t| t.flags.is_empty()));
+    }
+
+    #[test]
+    fn test_link_chains_poison() {
+        let mut t = transfers(6);
+        let linking = Linking {
+            chain_len: 3,
+            ratio: 1.0,
+            poison: true,
+        };
+        link_chains(&mut t, &linking, &mut rand::thread_rng());
+
+        for chain in t.chunks(3) {
+            let poisoned = chain
+                .iter()
+                .filter(|t| t.debit_account_id == t.credit_account_id)
+                .count();
+            assert_eq!(poisoned, 1);
+        }
+    }
+
+    #[test]
+    fn test_batches_keep_chains_whole() {
+        let mut t = transfers(7);
+        // Chains at 1..4 and 4..6.
+        for i in [1, 2, 4] {
+            t[i].flags = TransferFlags::LINKED;
+        }
+
+        let lens: Vec<usize> = batches(&t, 3).unwrap().iter().map(|b| b.len()).collect();
+        assert_eq!(lens, [1, 3, 3]);
+
+        let lens: Vec<usize> = batches(&t, 5).unwrap().iter().map(|b| b.len()).collect();
+        assert_eq!(lens, [4, 3]);
+    }
+
+    #[test]
+    fn test_batches_chain_too_long() {
+        let mut t = transfers(4);
+        for transfer in &mut t[..3] {
+            transfer.flags = TransferFlags::LINKED;
+        }
+        assert!(batches(&t, 3).is_none());
+        assert_eq!(batches(&t, 4).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_split_keeps_chains_whole() {
+        let mut t = transfers(10);
+        // Chain at 4..7 straddles the midpoint.
+        for i in [4, 5] {
+            t[i].flags = TransferFlags::LINKED;
+        }
+
+        let lens: Vec<usize> = split(&t, 2).iter().map(|r| r.len()).collect();
+        assert_eq!(lens, [7, 3]);
+
+        let lens: Vec<usize> = split(&t, 3).iter().map(|r| r.len()).collect();
+        assert_eq!(lens, [3, 4, 3]);
+
+        let lens: Vec<usize> = split(&t[..2], 4).iter().map(|r| r.len()).collect();
+        assert_eq!(lens, [0, 1, 0, 1]);
+    }
+}
@@ -13,317 +13,1499 @@
 //!
 //! # Use custom ledger and batch size
 //! tb-gen --accounts 100 --transfers 500 --ledger 1 --batch-size 1000
+//!
+//! # Concentrate transfers on a few hot accounts
+//! tb-gen --accounts 10000 --transfers 100000 --distribution zipfian --skew 1.1
+//!
+//! # Link a quarter of the transfers into chains of 4, each with one failure
+//! tb-gen --transfers 10000 --linked-chain-len 4 --linked-ratio 0.25 --poison-chains
+//!
+//! # Hold 5000 transfers/s for ten minutes
+//! tb-gen --accounts 1000 --tps 5000 --duration 10m
+//!
+//! # Find the knee: ramp from 1000 to 20000 transfers/s over five minutes
+//! tb-gen --accounts 1000 --tps 1000 --ramp-to 20000 --ramp-over 5m --duration 6m
+//!
+//! # Step through rates, a minute each
+//! tb-gen --accounts 1000 --ramp "0:1000,60s:5000,120s:10000,180s:0" --step
+//!
+//! # Save the generated dataset without sending it
+//! tb-gen --transfers 1000 --out accounts.csv --out-transfers transfers.ndjson --dry-run
+//!
+//! # Load an existing dataset instead of generating one (see `import` for columns)
+//! tb-gen --from-csv accounts.csv --from-csv transfers.csv
+//!
+//! # Reproduce the exact same accounts, transfers and IDs on every run
+//! tb-gen --accounts 100 --transfers 1000 --seed 42 --out-transfers run.csv --dry-run
+//!
+//! # Drive the cluster from 8 clients at once
+//! tb-gen --accounts 10000 --transfers 1000000 --workers 8
+//!
+//! # Keep 4 batches outstanding instead of waiting for each reply
+//! tb-gen --accounts 10000 --transfers 1000000 --inflight 4
+//!
+//! # Ten ledgers of 100 accounts, plus a busy ledger 20 with 5000
+//! tb-gen --ledgers 1-10,20:5000 --accounts 100 --transfers 50000
+//!
+//! # Exercise balancing transfers against limited accounts, then check limits
+//! tb-gen --accounts 100 --transfers 10000 --balancing
+//!
+//! # Print JSON progress events and a summary for CI to parse
+//! tb-gen --accounts 1000 --transfers 100000 --output json | jq 'select(.event == "summary")'
+//!
+//! # Seed, load, close some accounts and verify, reporting each phase
+//! tb-gen --scenario acceptance.toml
+//!
+//! # Gate CI on the run: exit non-zero past 0.1% failed or a 5ms p99
+//! tb-gen --accounts 1000 --transfers 100000 --max-error-rate 0.1% --max-p99 5ms --verdict verdict.json
+//!
+//! # Hold 2000 transfers/s for a day; fail if p99 triples or >0.1% fail
+//! tb-gen --tps 2000 --duration 24h --soak --max-latency-growth 3 --max-error-rate 0.001
+//!
+//! # Misbehave: reconnect, stall and abandon batches to test retries
+//! tb-gen --transfers 100000 --chaos-reconnect 0.01 --chaos-delay 0.05 --chaos-abandon 0.02
+//!
+//! # Resend transfers that failed for lack of funds up to 3 times
+//! tb-gen --accounts 100 --transfers 10000 --balancing --retry 3
+//!
+//! # Measure only after 30s of unrecorded load on warm connections
+//! tb-gen --accounts 1000 --tps 5000 --duration 10m --warmup 30s
+//!
+//! # Measure read latency under write load: 4 readers at 2000 reads/s
+//! tb-gen --accounts 10000 --tps 5000 --duration 5m --readers 4 --read-tps 2000
+//!
+//! # OLTP-style mix: 70% of each client's requests are reads
+//! tb-gen --accounts 10000 --transfers 100000 --workers 4 --read-ratio 0.7
+//!
+//! # Spread load over a three-replica cluster, failing over between them
+//! tb-gen --address 10.0.0.1:3000,10.0.0.2:3000,10.0.0.3:3000 --cluster 7 --transfers 100000
+//!
+//! # Measure the cost of balance history: a fifth of the accounts keep it
+//! tb-gen --accounts 100000 --transfers 1000000 --history-ratio 0.2
+//!
+//! # Tag the data so this run can be queried later (user_data_64 = 7001)
+//! tb-gen --accounts 100 --transfers 1000 --run-id 7001
+//!
+//! # Add transfers between the accounts run 7001 created
+//! tb-gen --existing-accounts query --existing-run-id 7001 --transfers 50000
+//!
+//! # Record every request and reply of a run for later study or replay
+//! tb-gen --accounts 1000 --transfers 100000 --capture run.tbcap
+//!
+//! # Replay a capture on another cluster, at its original pace
+//! tb-gen replay run.tbcap --address 10.0.0.2:3000 --timing original
+//!
+//! # Which batch size is fastest? Send 100000 transfers at each
+//! tb-gen bench --sweep-batch 64,512,2048,8190 --accounts 1000 --transfers 100000
+//!
+//! # Keep a complex workload in a file (see `config` for the format)
+//! tb-gen --config workload.toml --seed 7
+//!
+//! # Watch a long run on a dashboard through StatsD
+//! tb-gen --accounts 1000 --tps 5000 --duration 1h --metrics statsd://127.0.0.1:8125
 //! ```
 
-use clap::Parser;
-use rand::Rng;
-use tb_rs::{Account, AccountFlags, Transfer, TransferFlags};
+#[macro_use]
+mod report;
+
+mod balancing;
+mod bench;
+mod capture;
+mod chaos;
+mod config;
+mod existing;
+mod export;
+mod gate;
+mod import;
+mod inflight;
+mod linked;
+mod metrics;
+mod pace;
+mod reads;
+mod replay;
+mod retry;
+mod scenario;
+mod soak;
+mod workers;
+
+use clap::{Parser, Subcommand};
+use distribution::Distribution;
+use export::Exporter;
+use import::Dataset;
+use ledger::Ledger;
+use linked::Linking;
+use pace::Pacer;
+use report::{Output, Progress, Tally};
+use seed::Source;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tb_gen::generate::{flag_history, generate_accounts, generate_transfers, tag_accounts};
+use tb_gen::{distribution, ledger, seed};
+use tb_rs::{Account, Transfer};
 
 /// Test data generator for TigerBeetle
 #[derive(Parser, Debug)]
 #[command(name = "tb-gen")]
 #[command(about = "Generate test data for TigerBeetle")]
+#[command(args_override_self = true)]
 struct Args {
-    /// TigerBeetle server address
-    #[arg(short, long, default_value = "127.0.0.1:3000")]
-    address: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TigerBeetle replica addresses, comma-separated or repeated; list every
+    /// replica so requests can fail over between them
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        default_value = "127.0.0.1:3000",
+        global = true
+    )]
+    address: Vec<String>,
 
     /// Cluster ID
-    #[arg(short, long, default_value_t = 0)]
+    #[arg(short, long, default_value_t = 0, global = true)]
     cluster: u128,
 
-    /// Number of accounts to create
-    #[arg(long, default_value_t = 100)]
+    /// Number of accounts to create (per ledger with --ledgers)
+    #[arg(long, default_value_t = 100, global = true)]
     accounts: u32,
 
     /// Number of transfers to create
-    #[arg(long, default_value_t = 0)]
+    #[arg(long, default_value_t = 0, global = true)]
     transfers: u32,
 
     /// Ledger ID for all accounts and transfers
-    #[arg(short, long, default_value_t = 1)]
+    #[arg(short, long, default_value_t = 1, global = true)]
     ledger: u32,
 
+    /// Spread the dataset over several ledgers, e.g. 1-10 or 1-3:100,7:5000
+    /// (ledger:accounts); transfers never cross ledgers
+    #[arg(long, value_name = "SPEC", conflicts_with = "ledger")]
+    ledgers: Option<String>,
+
     /// Account code
-    #[arg(long, default_value_t = 1)]
+    #[arg(long, default_value_t = 1, global = true)]
     code: u16,
 
+    /// Fraction (0.0-1.0) of accounts created with the HISTORY flag, which
+    /// keeps their balances after every transfer
+    #[arg(long, default_value_t = 0.0, global = true)]
+    history_ratio: f64,
+
     /// Batch size for sending requests (will be capped by server limit)
-    #[arg(short, long, default_value_t = 8190)]
+    #[arg(short, long, default_value_t = 8190, global = true)]
     batch_size: u32,
 
     /// Maximum transfer amount
-    #[arg(long, default_value_t = 10000)]
+    #[arg(long, default_value_t = 10000, global = true)]
     max_amount: u128,
 
-    /// Dry run - generate data but don't send to server
+    /// How transfers pick their debit and credit accounts
+    #[arg(long, value_enum, default_value_t = Distribution::Uniform, global = true)]
+    distribution: Distribution,
+
+    /// Zipfian skew exponent; higher values make fewer accounts hotter
+    #[arg(long, default_value_t = 1.1, global = true)]
+    skew: f64,
+
+    /// Number of transfers in each linked chain
+    #[arg(long, default_value_t = 2, global = true)]
+    linked_chain_len: u32,
+
+    /// Probability (0.0-1.0) that a transfer starts a linked chain
+    #[arg(long, default_value_t = 0.0, global = true)]
+    linked_ratio: f64,
+
+    /// Make one transfer in every linked chain fail, failing the whole chain
+    #[arg(long, global = true)]
+    poison_chains: bool,
+
+    /// Submit transfers at this constant rate instead of as fast as possible
+    #[arg(long, group = "rate")]
+    tps: Option<u64>,
+
+    /// Ramp linearly from --tps to this rate over --ramp-over; without
+    /// --duration or --transfers the run ends when the ramp does
+    #[arg(long, requires_all = ["tps", "ramp_over"])]
+    ramp_to: Option<u64>,
+
+    /// Time taken to reach --ramp-to, e.g. 5m
+    #[arg(long, value_parser = pace::parse_duration, requires = "ramp_to")]
+    ramp_over: Option<Duration>,
+
+    /// Rate profile of time:tps points, e.g. "0:1000,60s:5000,120s:10000";
+    /// without --duration or --transfers the run ends at the last point
+    #[arg(long, group = "rate", value_name = "POINTS")]
+    ramp: Option<String>,
+
+    /// Hold each --ramp rate until the next point instead of ramping to it
+    #[arg(long, requires = "ramp")]
+    step: bool,
+
+    /// How long to keep sending at the paced rate, e.g. 90s, 10m or 1h
+    /// (overrides --transfers)
+    #[arg(long, value_parser = pace::parse_duration, requires = "rate")]
+    duration: Option<Duration>,
+
+    /// Send transfers for this long before measuring, e.g. 30s, so the
+    /// results start from warm connections and caches
+    #[arg(long, value_parser = pace::parse_duration, global = true)]
+    warmup: Option<Duration>,
+
+    /// Check stability through a long paced run: stop with an error once a
+    /// window's error rate or p99 latency growth breaks its limit
+    #[arg(long, requires = "duration")]
+    soak: bool,
+
+    /// Length of each --soak window; the first sets the latency baseline
+    #[arg(long, value_parser = pace::parse_duration, default_value = "1m", requires = "soak")]
+    soak_window: Duration,
+
+    /// Largest fraction of transfers that may fail, e.g. 0.001 or 0.1%:
+    /// checked over the run (see `gate`) and each --soak window (default 1%)
+    #[arg(long, value_name = "RATE", value_parser = gate::parse_rate)]
+    max_error_rate: Option<f64>,
+
+    /// Largest request p99 latency of the run's transfers, e.g. 5ms; a run
+    /// over it exits non-zero
+    #[arg(long, value_name = "DURATION", value_parser = pace::parse_duration)]
+    max_p99: Option<Duration>,
+
+    /// Write whether the run passed --max-error-rate and --max-p99 to this
+    /// file as JSON
+    #[arg(long, value_name = "PATH")]
+    verdict: Option<PathBuf>,
+
+    /// Largest allowed ratio of a --soak window's p99 latency to the baseline
+    #[arg(long, default_value_t = 2.0, requires = "soak")]
+    max_latency_growth: f64,
+
+    /// Probability (0.0-1.0) per transfer batch of dropping the client and
+    /// connecting a new one
+    #[arg(long, default_value_t = 0.0, value_name = "P")]
+    chaos_reconnect: f64,
+
+    /// Probability (0.0-1.0) per transfer batch of stalling before sending it
+    #[arg(long, default_value_t = 0.0, value_name = "P")]
+    chaos_delay: f64,
+
+    /// Longest --chaos-delay stall
+    #[arg(long, value_parser = pace::parse_duration, default_value = "100ms")]
+    chaos_max_delay: Duration,
+
+    /// Probability (0.0-1.0) per transfer batch of abandoning the request
+    /// before its reply and resending it from a new client
+    #[arg(long, default_value_t = 0.0, value_name = "P")]
+    chaos_abandon: f64,
+
+    /// Longest wait for a reply before a --chaos-abandon batch is abandoned
+    #[arg(long, value_parser = pace::parse_duration, default_value = "1ms")]
+    chaos_abandon_after: Duration,
+
+    /// Resend transfers that failed with a code that may clear, such as
+    /// ExceedsCredits, up to this many times (see `retry`)
+    #[arg(long, default_value_t = 0, value_name = "ATTEMPTS")]
+    retry: u32,
+
+    /// Write the generated accounts to this file (.csv, .json, .ndjson or .jsonl)
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+
+    /// Write the generated transfers to this file (.csv, .json, .ndjson or .jsonl)
+    #[arg(long, value_name = "PATH")]
+    out_transfers: Option<PathBuf>,
+
+    /// Seed for IDs and every random choice, making runs reproducible
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Tag generated accounts and transfers by setting user_data_64 to this
+    /// (default: random, or derived from --seed)
+    #[arg(long, global = true)]
+    run_id: Option<u64>,
+
+    /// Spread transfers over this many threads, each with its own client
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+
+    /// Keep this many batches outstanding per sender, each on its own
+    /// session (see `inflight`)
+    #[arg(long, default_value_t = 1, conflicts_with_all = ["rate", "scenario", "from_csv"])]
+    inflight: usize,
+
+    /// Query the run's accounts and transfers from this many extra clients
+    /// while transfers are written (see `reads`)
+    #[arg(long, default_value_t = 0)]
+    readers: usize,
+
+    /// Fraction of each sender's requests that are reads, e.g. 0.7: writers
+    /// interleave reads with their transfers (see `reads`)
+    #[arg(long, default_value_t = 0.0)]
+    read_ratio: f64,
+
+    /// Reads per second across all readers (default: as fast as replies come)
     #[arg(long)]
+    read_tps: Option<u64>,
+
+    /// Kinds of read to pick from
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = reads::ReadOp::ALL)]
+    read_ops: Vec<reads::ReadOp>,
+
+    /// Accounts per lookup and transfers per query
+    #[arg(long, default_value_t = 10)]
+    read_size: u32,
+
+    /// How reads pick accounts
+    #[arg(long, value_enum, default_value_t = Distribution::Uniform)]
+    read_distribution: Distribution,
+
+    /// Zipfian skew for --read-distribution
+    #[arg(long, default_value_t = 1.1)]
+    read_skew: f64,
+
+    /// Stop reading after this long; needed when there are no transfers
+    #[arg(long, value_parser = pace::parse_duration)]
+    read_duration: Option<Duration>,
+
+    /// Load accounts or transfers from a CSV file instead of generating them
+    /// (repeatable; files are sent in order)
+    #[arg(long, value_name = "PATH", conflicts_with = "rate")]
+    from_csv: Vec<PathBuf>,
+
+    /// Give accounts debit/credit limits and send balancing transfers, then
+    /// check that no account broke its limit
+    #[arg(long)]
+    balancing: bool,
+
+    /// Send transfers between accounts already on the cluster instead of
+    /// creating accounts (see `existing`)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SOURCE",
+        conflicts_with_all = ["from_csv", "scenario", "balancing", "ledgers", "history_ratio"]
+    )]
+    existing_accounts: Option<existing::Existing>,
+
+    /// Only use existing accounts on this ledger
+    #[arg(long, requires = "existing_accounts")]
+    existing_ledger: Option<u32>,
+
+    /// Only use existing accounts tagged with this run ID
+    #[arg(long, requires = "existing_accounts")]
+    existing_run_id: Option<u64>,
+
+    /// What to print on stdout; json prints progress events and a final
+    /// summary, one object per line, and moves the other output to stderr
+    #[arg(long, value_enum, default_value_t = Output::Text, global = true)]
+    output: Output,
+
+    /// Push live throughput, error and latency metrics while sending, to
+    /// statsd://host:port or a Prometheus Pushgateway at http://host:port
+    #[arg(long, value_name = "URL", global = true)]
+    metrics: Option<String>,
+
+    /// How often to push --metrics
+    #[arg(
+        long,
+        value_parser = pace::parse_duration,
+        default_value = "10s",
+        requires = "metrics",
+        global = true
+    )]
+    metrics_interval: Duration,
+
+    /// Run the phases of a TOML scenario file in order, reporting each (see
+    /// `scenario` for the format)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["from_csv", "rate"])]
+    scenario: Option<PathBuf>,
+
+    /// Write every create request and its reply, with timestamps, to a
+    /// .tbcap file (see `capture` for the format)
+    #[arg(long, value_name = "PATH", global = true)]
+    capture: Option<PathBuf>,
+
+    /// Read options from a TOML workload file; flags given on the command
+    /// line override it
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Dry run - generate data but don't send to server
+    #[arg(long, global = true)]
     dry_run: bool,
 }
 
-/// Generate a batch of random accounts.
-fn generate_accounts(count: u32, ledger: u32, code: u16) -> Vec<Account> {
-    let mut accounts = Vec::with_capacity(count as usize);
-
-    for _ in 0..count {
-        accounts.push(Account {
-            id: tb_rs::id(),
-            ledger,
-            code,
-            flags: AccountFlags::empty(),
-            ..Default::default()
-        });
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Re-submit exported CSV datasets or a .tbcap capture (see `replay`)
+    Replay {
+        /// CSV datasets, sent in order, or a single .tbcap capture
+        #[arg(required = true, value_name = "FILE")]
+        files: Vec<PathBuf>,
 
-    accounts
+        /// Send each captured request at its original time, or at max speed
+        #[arg(long, value_enum, default_value_t = replay::Timing::Max)]
+        timing: replay::Timing,
+    },
+    /// Send the same workload at several batch sizes and compare them (see
+    /// `bench`)
+    Bench {
+        /// Batch sizes to try, in order, e.g. 64,512,2048,8190
+        #[arg(long, value_delimiter = ',', required = true, value_name = "SIZES")]
+        sweep_batch: Vec<u32>,
+    },
 }
 
-/// Generate a batch of random transfers between accounts.
-fn generate_transfers(
-    count: u32,
-    account_ids: &[u128],
-    ledger: u32,
+impl Args {
+    /// The replica addresses as tb-rs takes them.
+    fn addresses(&self) -> String {
+        self.address.join(",")
+    }
+}
+
+/// How to generate transfers, batch by batch.
+struct TransferPlan {
+    ledgers: Vec<Ledger>,
     code: u16,
     max_amount: u128,
-) -> Vec<Transfer> {
-    assert!(
-        account_ids.len() >= 2,
-        "Need at least 2 accounts for transfers"
-    );
-
-    let mut rng = rand::thread_rng();
-    let mut transfers = Vec::with_capacity(count as usize);
+    linking: Linking,
+    /// Account limits to balance against, for --balancing.
+    limits: Option<balancing::Limits>,
+    /// Stored in each transfer's user_data_64.
+    run_id: u64,
+}
 
-    for _ in 0..count {
-        // Pick random debit and credit accounts (must be different)
-        let debit_idx = rng.gen_range(0..account_ids.len());
-        let mut credit_idx = rng.gen_range(0..account_ids.len());
-        while credit_idx == debit_idx {
-            credit_idx = rng.gen_range(0..account_ids.len());
+impl TransferPlan {
+    /// Generate `count` tagged transfers with balancing flags and linked
+    /// chains.
+    fn generate(&self, count: u32, source: &mut Source) -> Vec<Transfer> {
+        let mut transfers =
+            generate_transfers(count, &self.ledgers, self.code, self.max_amount, source);
+        for transfer in &mut transfers {
+            transfer.user_data_64 = self.run_id;
         }
-
-        let amount = rng.gen_range(1..=max_amount);
-
-        transfers.push(Transfer {
-            id: tb_rs::id(),
-            debit_account_id: account_ids[debit_idx],
-            credit_account_id: account_ids[credit_idx],
-            amount,
-            ledger,
-            code,
-            flags: TransferFlags::empty(),
-            ..Default::default()
-        });
+        if let Some(limits) = &self.limits {
+            balancing::balance(&mut transfers, limits);
+        }
+        linked::link_chains(&mut transfers, &self.linking, &mut source.rng);
+        transfers
     }
-
-    transfers
 }
 
 async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
-    println!("TigerBeetle Test Data Generator");
-    println!("================================");
-    println!("Address: {}", args.address);
-    println!("Cluster: {}", args.cluster);
-    println!("Accounts: {}", args.accounts);
-    println!("Transfers: {}", args.transfers);
-    println!("Ledger: {}", args.ledger);
-    println!("Batch size: {}", args.batch_size);
-    println!();
-
-    if args.accounts == 0 {
-        println!("No accounts to create. Exiting.");
+    match &args.command {
+        Some(Command::Replay { files, timing }) => {
+            return replay::run(&args, files, *timing).await;
+        }
+        Some(Command::Bench { sweep_batch }) => return bench::run(&args, sweep_batch).await,
+        None => {}
+    }
+    // Checked here rather than by clap: --warmup is global, and the
+    // subcommands have no --from-csv or --scenario to conflict with.
+    if args.warmup.is_some() && (!args.from_csv.is_empty() || args.scenario.is_some()) {
+        return Err("--warmup cannot be used with --from-csv or --scenario".into());
+    }
+    if !args.from_csv.is_empty() {
+        return run_import(&args, &args.from_csv).await;
+    }
+    if let Some(path) = &args.scenario {
+        return scenario::run(&args, path).await;
+    }
+
+    say!("TigerBeetle Test Data Generator");
+    say!("================================");
+    say!("Address: {}", args.addresses());
+    say!("Cluster: {}", args.cluster);
+    let pacer = pacer(&args)?;
+    let ledger_specs = match &args.ledgers {
+        Some(spec) => ledger::parse_ledgers(spec, args.accounts)?,
+        None => vec![(args.ledger, args.accounts)],
+    };
+    let account_count: u32 = ledger_specs.iter().map(|&(_, count)| count).sum();
+    // With a duration or a profile, the rate decides how many transfers
+    // there are.
+    let transfer_count = match (&pacer, args.duration) {
+        (Some(pacer), Some(duration)) => pacer.due(duration),
+        (Some(pacer), None) if args.transfers == 0 => pacer.due(pacer.end()),
+        _ => args.transfers as u64,
+    };
+
+    match args.existing_accounts {
+        Some(_) => say!("Accounts: existing, from query_accounts"),
+        None => say!("Accounts: {}", account_count),
+    }
+    match &pacer {
+        Some(pacer) => say!("Transfers: {} at {}", transfer_count, pacer),
+        None => say!("Transfers: {}", args.transfers),
+    }
+    match &args.ledgers {
+        Some(spec) => say!("Ledgers: {} ({} ledgers)", spec, ledger_specs.len()),
+        // Existing accounts bring their own ledgers.
+        None if args.existing_accounts.is_some() => {}
+        None => say!("Ledger: {}", args.ledger),
+    }
+    if let Some(seed) = args.seed {
+        say!("Seed: {}", seed);
+    }
+    let run_id = run_id(&args)?;
+    say!("Run ID: {}", run_id);
+    say!("Batch size: {}", args.batch_size);
+    if args.workers > 1 {
+        say!("Workers: {}", args.workers);
+    }
+    if args.inflight > 1 {
+        say!("In flight: {} batches per sender", args.inflight);
+    }
+    if args.retry > 0 {
+        say!("Retries: up to {} per failed transfer", args.retry);
+    }
+    if args.readers > 0 {
+        match args.read_tps {
+            Some(tps) => say!("Readers: {} at {} reads/s", args.readers, tps),
+            None => say!("Readers: {}", args.readers),
+        }
+    }
+    match args.distribution {
+        Distribution::Uniform => say!("Distribution: uniform"),
+        Distribution::Zipfian => say!("Distribution: zipfian (skew {})", args.skew),
+    }
+    if args.balancing {
+        say!("Workload: balancing transfers against limited accounts");
+    }
+    if args.history_ratio > 0.0 {
+        say!("History: {}% of accounts", args.history_ratio * 100.0);
+    }
+    if let Some(soak) = soak_thresholds(&args) {
+        say!(
+            "Soak: {:?} windows, at most {}% failed and {}x baseline p99",
+            soak.window,
+            soak.max_error_rate * 100.0,
+            soak.max_latency_growth
+        );
+    }
+    let limits = gate::Limits {
+        max_error_rate: args.max_error_rate,
+        max_p99: args.max_p99,
+    };
+    if let Some(rate) = limits.max_error_rate {
+        say!("Limit: at most {}% of transfers failed", rate * 100.0);
+    }
+    if let Some(p99) = limits.max_p99 {
+        say!("Limit: p99 latency at most {:?}", p99);
+    }
+    if args.linked_ratio > 0.0 {
+        say!(
+            "Linked chains: length {}, ratio {}{}",
+            args.linked_chain_len,
+            args.linked_ratio,
+            if args.poison_chains { ", poisoned" } else { "" }
+        );
+    }
+    say!();
+
+    if account_count == 0 && args.existing_accounts.is_none() {
+        say!("No accounts to create. Exiting.");
         return Ok(());
     }
 
-    if args.transfers > 0 && args.accounts < 2 {
-        return Err("Need at least 2 accounts to create transfers".into());
+    if args.workers == 0 {
+        return Err("--workers must be at least 1".into());
+    }
+    if args.inflight == 0 {
+        return Err("--inflight must be at least 1".into());
     }
 
-    // Generate all accounts first
-    println!("Generating {} accounts...", args.accounts);
-    let accounts = generate_accounts(args.accounts, args.ledger, args.code);
-    let account_ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
-    println!("Generated {} accounts", accounts.len());
+    if args.workers > 1 && pacer.is_some() && args.out_transfers.is_some() {
+        return Err("--out-transfers with a paced rate needs a single worker".into());
+    }
 
-    // Generate transfers if requested
-    let transfers = if args.transfers > 0 {
-        println!("Generating {} transfers...", args.transfers);
-        let t = generate_transfers(
-            args.transfers,
-            &account_ids,
-            args.ledger,
-            args.code,
-            args.max_amount,
-        );
-        println!("Generated {} transfers", t.len());
+    if transfer_count > 0
+        && args.existing_accounts.is_none()
+        && ledger_specs.iter().any(|&(_, count)| count < 2)
+    {
+        return Err("Need at least 2 accounts in each ledger to create transfers".into());
+    }
+
+    check_shape(&args)?;
+
+    if args.warmup.is_some() && transfer_count == 0 {
+        return Err("--warmup needs transfers to send".into());
+    }
+
+    if args.readers > 0 || args.read_ratio > 0.0 {
+        check_reads(&args, transfer_count)?;
+    }
+
+    let chaos = [
+        ("--chaos-reconnect", args.chaos_reconnect),
+        ("--chaos-delay", args.chaos_delay),
+        ("--chaos-abandon", args.chaos_abandon),
+    ];
+    for (flag, p) in chaos {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(format!("{} must be between 0.0 and 1.0", flag).into());
+        }
+    }
+    if chaos.iter().any(|&(_, p)| p > 0.0) {
+        chaos::init(chaos::Config {
+            cluster: args.cluster,
+            address: args.addresses(),
+            reconnect: args.chaos_reconnect,
+            delay: args.chaos_delay,
+            max_delay: args.chaos_max_delay,
+            abandon: args.chaos_abandon,
+            abandon_after: args.chaos_abandon_after,
+        });
+    }
+
+    if args.soak {
+        if args.soak_window.is_zero() {
+            return Err("--soak-window must be positive".into());
+        }
+        if args.max_latency_growth.is_nan() || args.max_latency_growth < 1.0 {
+            return Err("--max-latency-growth must be at least 1.0".into());
+        }
+    }
+
+    let mut source = Source::new(args.seed);
+    // Existing accounts have to be found on the cluster, so the client
+    // connects before anything is generated.
+    let mut connected = None;
+    let (accounts, ledgers) = if args.existing_accounts.is_some() {
+        if args.dry_run {
+            say!();
+            say!("Dry run mode - not querying existing accounts");
+            print_run_id(run_id);
+            return Ok(());
+        }
+        let (mut client, batch_size) = connect(&args).await?;
+        let found = query_existing(&args, &mut client, transfer_count).await?;
+        connected = Some((client, batch_size));
+        found
+    } else {
+        // Generate all accounts first
+        say!("Generating {} accounts...", account_count);
+        let mut accounts = Vec::with_capacity(account_count as usize);
+        let mut ledgers = Vec::with_capacity(ledger_specs.len());
+        for &(ledger, count) in &ledger_specs {
+            let mut generated = generate_accounts(count, ledger, args.code, &mut source);
+            tag_accounts(&mut generated, run_id);
+            flag_history(&mut generated, args.history_ratio);
+            if args.balancing {
+                balancing::limit_accounts(&mut generated);
+            }
+            let account_ids = generated.iter().map(|a| a.id).collect();
+            if count > 0 {
+                ledgers.push(Ledger::new(
+                    ledger,
+                    account_ids,
+                    args.distribution,
+                    args.skew,
+                ));
+            }
+            accounts.extend(generated);
+        }
+        say!("Generated {} accounts", accounts.len());
+        (accounts, ledgers)
+    };
+    if let Some(path) = &args.out {
+        let mut exporter = Exporter::create::<Account>(path)?;
+        exporter.write(&accounts)?;
+        finish_export(exporter, "accounts", path)?;
+    }
+
+    // Paced runs export transfers as they are sent.
+    let mut transfer_exporter = match &args.out_transfers {
+        Some(path) => Some(Exporter::create::<Transfer>(path)?),
+        None => None,
+    };
+
+    let plan = TransferPlan {
+        ledgers,
+        code: args.code,
+        max_amount: args.max_amount,
+        linking: Linking {
+            chain_len: args.linked_chain_len as usize,
+            ratio: args.linked_ratio,
+            poison: args.poison_chains,
+        },
+        limits: args.balancing.then(|| balancing::Limits::new(&accounts)),
+        run_id,
+    };
+
+    // Generate transfers if requested; paced runs generate them as they go.
+    let transfers = if args.transfers > 0 && pacer.is_none() {
+        say!("Generating {} transfers...", args.transfers);
+        let t = plan.generate(args.transfers, &mut source);
+        say!("Generated {} transfers", t.len());
+        let chains = linked::count_chains(&t);
+        if chains > 0 {
+            say!("Linked {} chains", chains);
+        }
+        if let Some(exporter) = &mut transfer_exporter {
+            exporter.write(&t)?;
+        }
         t
     } else {
         Vec::new()
     };
 
+    if pacer.is_none() {
+        if let (Some(exporter), Some(path)) = (transfer_exporter.take(), &args.out_transfers) {
+            finish_export(exporter, "transfers", path)?;
+        }
+    }
+
     if args.dry_run {
-        println!();
-        println!("Dry run mode - not sending to server");
-        println!("Sample account: {:032x}", accounts[0].id);
+        say!();
+        say!("Dry run mode - not sending to server");
+        say!("Sample account: {:032x}", accounts[0].id);
         if !transfers.is_empty() {
-            println!(
+            say!(
                 "Sample transfer: {:032x} ({} units)",
-                transfers[0].id, transfers[0].amount
+                transfers[0].id,
+                transfers[0].amount
             );
         }
+        if let Some(pacer) = &pacer {
+            say!("Would send {} transfers at {}", transfer_count, pacer);
+        }
+        print_run_id(run_id);
         return Ok(());
     }
 
-    // Connect to TigerBeetle
-    println!();
-    println!("Connecting to TigerBeetle at {}...", args.address);
-    let mut client = tb_rs::Client::connect(args.cluster, &args.address).await?;
-    println!("Connected! Client ID: {:032x}", client.id());
+    let (mut client, effective_batch_size) = match connected {
+        Some(connected) => connected,
+        None => connect(&args).await?,
+    };
+    let pusher = start_metrics(&args)?;
+    let accounts_start = Instant::now();
+    let account_tally = match args.existing_accounts {
+        Some(_) => Tally::default(),
+        None => send_accounts(&mut client, &accounts, effective_batch_size).await?,
+    };
+    let accounts_elapsed = accounts_start.elapsed();
+
+    if let Some(warmup) = args.warmup.filter(|_| args.workers == 1) {
+        warm_up(&mut client, &plan, effective_batch_size, warmup, None).await?;
+    }
+    if args.read_ratio > 0.0 {
+        let targets = accounts.iter().map(|a| (a.id, a.ledger)).collect();
+        reads::mix(args.read_ratio, read_config(&args, run_id), targets);
+    }
+    let readers = (args.readers > 0).then(|| {
+        let targets = accounts.iter().map(|a| (a.id, a.ledger)).collect();
+        reads::start(read_config(&args, run_id), targets)
+    });
+    let mut transfers_start = Instant::now();
+    let mut transfer_tally = if args.workers > 1 {
+        let workload = workers::Workload {
+            transfers: &transfers,
+            pacer: pacer.as_ref(),
+            paced: transfer_count,
+            plan: &plan,
+        };
+        let (tally, started) = workers::run(&args, &workload)?;
+        transfers_start = started;
+        tally
+    } else if args.inflight > 1 {
+        inflight::send_transfers(&args, &mut client, &transfers, effective_batch_size, None).await?
+    } else {
+        send_transfers(&mut client, &transfers, effective_batch_size, None).await?
+    };
+
+    if let Some(pacer) = pacer.filter(|_| transfer_count > 0 && args.workers == 1) {
+        say!();
+        say!("Creating transfers at {}...", pacer);
+        let generate = |count: u32| -> std::io::Result<Vec<Transfer>> {
+            let t = plan.generate(count, &mut source);
+            if let Some(exporter) = &mut transfer_exporter {
+                exporter.write(&t)?;
+            }
+            Ok(t)
+        };
+        let paced = run_paced(
+            &mut client,
+            &pacer,
+            transfer_count,
+            effective_batch_size,
+            None,
+            soak_thresholds(&args).as_ref(),
+            generate,
+        )
+        .await?;
+        transfer_tally.merge(paced);
+        if let (Some(exporter), Some(path)) = (transfer_exporter, &args.out_transfers) {
+            finish_export(exporter, "transfers", path)?;
+        }
+    }
+    if let Some(pusher) = pusher {
+        pusher.finish();
+    }
+
+    let transfers_elapsed = transfers_start.elapsed();
+    let (mut read_tallies, reads_elapsed) = match readers {
+        Some(readers) if transfer_count > 0 => readers.stop()?,
+        Some(readers) => readers.join()?,
+        None => (Vec::new(), transfers_elapsed),
+    };
+    for (op, mixed) in reads::mixed() {
+        match read_tallies.iter_mut().find(|(o, _)| *o == op) {
+            Some((_, tally)) => tally.merge(mixed),
+            None => read_tallies.push((op, mixed)),
+        }
+    }
+    if !read_tallies.is_empty() {
+        let reads: u64 = read_tallies.iter().map(|(_, t)| t.requests()).sum();
+        say!(
+            "Reads: {} requests in {:.1?} ({:.0}/s)",
+            reads,
+            reads_elapsed,
+            reads as f64 / reads_elapsed.as_secs_f64()
+        );
+    }
+
+    chaos::report();
+    retry::report();
+    let mut phases = vec![
+        ("accounts", &account_tally, accounts_elapsed),
+        ("transfers", &transfer_tally, transfers_elapsed),
+    ];
+    for (op, tally) in &read_tallies {
+        phases.push((op.name(), tally, reads_elapsed));
+    }
+    report::summary(Some(run_id), &phases);
+    let checks = gate::judge(&limits, &transfer_tally);
+    if let Some(path) = &args.verdict {
+        gate::write_verdict(path, run_id, &checks, &transfer_tally, transfers_elapsed)?;
+    }
+
+    if args.balancing {
+        check_limits(&mut client, &accounts, effective_batch_size).await?;
+    }
+
+    report_replicas(&args, &client);
+    // Close client
+    client.close().await;
+
+    if !limits.is_empty() {
+        gate::report(&checks)?;
+    }
+    say!();
+    print_run_id(run_id);
+    say!("Done!");
+
+    Ok(())
+}
+
+/// Find the existing accounts to send transfers between, with the ledgers
+/// they make.
+async fn query_existing(
+    args: &Args,
+    client: &mut tb_rs::Client,
+    transfer_count: u64,
+) -> Result<(Vec<Account>, Vec<Ledger>), Box<dyn std::error::Error>> {
+    // Zero means "any" in query filters.
+    if args.existing_ledger == Some(0) {
+        return Err("--existing-ledger must not be 0".into());
+    }
+    if args.existing_run_id == Some(0) {
+        return Err("--existing-run-id must not be 0".into());
+    }
+    let filter = existing::Filter {
+        ledger: args.existing_ledger.unwrap_or(0),
+        run_id: args.existing_run_id.unwrap_or(0),
+    };
+
+    say!("Querying existing accounts...");
+    let accounts = existing::query(client, filter).await?;
+    let ledgers: Vec<Ledger> = existing::group(&accounts)
+        .into_iter()
+        .map(|(ledger, ids)| Ledger::new(ledger, ids, args.distribution, args.skew))
+        .collect();
+    say!(
+        "Found {} accounts ({} ledgers with transfers)",
+        accounts.len(),
+        ledgers.len()
+    );
+    if accounts.is_empty() {
+        return Err("no existing accounts match --existing-ledger and --existing-run-id".into());
+    }
+    if transfer_count > 0 && ledgers.is_empty() {
+        return Err("Need at least 2 existing accounts in a ledger to create transfers".into());
+    }
+    Ok((accounts, ledgers))
+}
+
+/// Send the CSV datasets in `files`, in order.
+async fn run_import(args: &Args, files: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    say!("TigerBeetle Test Data Import");
+    say!("============================");
+    say!("Address: {}", args.addresses());
+    say!("Cluster: {}", args.cluster);
+    say!("Batch size: {}", args.batch_size);
+    say!();
+
+    let defaults = import::Defaults {
+        ledger: args.ledger,
+        code: args.code,
+    };
+    let mut datasets = Vec::with_capacity(files.len());
+    for path in files {
+        let dataset = import::read(path, &defaults)?;
+        match &dataset {
+            Dataset::Accounts(a) => say!("Read {} accounts from {}", a.len(), path.display()),
+            Dataset::Transfers(t) => {
+                say!("Read {} transfers from {}", t.len(), path.display())
+            }
+        }
+        datasets.push(dataset);
+    }
+
+    if args.dry_run {
+        say!();
+        say!("Dry run mode - not sending to server");
+        return Ok(());
+    }
+
+    let (mut client, effective_batch_size) = connect(args).await?;
+    let pusher = start_metrics(args)?;
+    // Files are sent in turn, so each phase's time is the sum of its files'.
+    let mut accounts = (Tally::default(), Duration::ZERO);
+    let mut transfers = (Tally::default(), Duration::ZERO);
+    for dataset in &datasets {
+        let start = Instant::now();
+        let (phase, tally) = match dataset {
+            Dataset::Accounts(a) => (
+                &mut accounts,
+                send_accounts(&mut client, a, effective_batch_size).await?,
+            ),
+            Dataset::Transfers(t) => (
+                &mut transfers,
+                send_transfers(&mut client, t, effective_batch_size, None).await?,
+            ),
+        };
+        phase.0.merge(tally);
+        phase.1 += start.elapsed();
+    }
+    client.close().await;
+    if let Some(pusher) = pusher {
+        pusher.finish();
+    }
+
+    report::summary(
+        None,
+        &[
+            ("accounts", &accounts.0, accounts.1),
+            ("transfers", &transfers.0, transfers.1),
+        ],
+    );
+
+    say!();
+    say!("Done!");
+
+    Ok(())
+}
+
+/// Readers as set by the --read-* flags.
+fn read_config(args: &Args, run_id: u64) -> reads::Config {
+    reads::Config {
+        cluster: args.cluster,
+        address: args.addresses(),
+        readers: args.readers,
+        tps: args.read_tps,
+        ops: args.read_ops.clone(),
+        size: args.read_size,
+        distribution: args.read_distribution,
+        skew: args.read_skew,
+        duration: args.read_duration,
+        run_id,
+    }
+}
+
+/// Check the --read-* flags.
+fn check_reads(args: &Args, transfer_count: u64) -> Result<(), String> {
+    if args.read_tps == Some(0) {
+        return Err("--read-tps must be positive".to_string());
+    }
+    if args.read_size == 0 {
+        return Err("--read-size must be positive".to_string());
+    }
+    if args.read_ops.is_empty() {
+        return Err("--read-ops needs at least one kind of read".to_string());
+    }
+    if args.read_distribution == Distribution::Zipfian
+        && (args.read_skew.is_nan() || args.read_skew <= 0.0)
+    {
+        return Err("--read-skew must be positive".to_string());
+    }
+    if !(0.0..1.0).contains(&args.read_ratio) {
+        return Err("--read-ratio must be at least 0.0 and below 1.0".to_string());
+    }
+    if args.read_ratio > 0.0 && transfer_count == 0 {
+        return Err("--read-ratio needs transfers to interleave reads with".to_string());
+    }
+    if args.readers > 0 && transfer_count == 0 && args.read_duration.is_none() {
+        return Err("--readers with no transfers need --read-duration".to_string());
+    }
+    Ok(())
+}
+
+/// Check the flags that shape generated transfers.
+fn check_shape(args: &Args) -> Result<(), String> {
+    if args.distribution == Distribution::Zipfian && (args.skew.is_nan() || args.skew <= 0.0) {
+        return Err("--skew must be positive".to_string());
+    }
+
+    if !(0.0..=1.0).contains(&args.linked_ratio) {
+        return Err("--linked-ratio must be between 0.0 and 1.0".to_string());
+    }
+
+    if args.linked_ratio > 0.0 && args.linked_chain_len < 2 {
+        return Err("--linked-chain-len must be at least 2".to_string());
+    }
+
+    if !(0.0..=1.0).contains(&args.history_ratio) {
+        return Err("--history-ratio must be between 0.0 and 1.0".to_string());
+    }
+    Ok(())
+}
+
+/// Pacing from --tps, --ramp-to or --ramp, if any.
+fn pacer(args: &Args) -> Result<Option<Pacer>, String> {
+    if let Some(points) = &args.ramp {
+        let points = pace::parse_profile(points)?;
+        return Pacer::profile(points, args.step).map(Some);
+    }
+    match (args.tps, args.ramp_to, args.ramp_over) {
+        (Some(0), _, _) => Err("--tps must be positive".to_string()),
+        (Some(tps), Some(to), Some(over)) => {
+            Pacer::profile(vec![(Duration::ZERO, tps), (over, to)], false).map(Some)
+        }
+        (Some(tps), _, _) => Ok(Some(Pacer::new(tps))),
+        (None, _, _) => Ok(None),
+    }
+}
+
+/// Start pushing live metrics, if --metrics is set.
+fn start_metrics(args: &Args) -> Result<Option<metrics::Pusher>, Box<dyn std::error::Error>> {
+    let Some(url) = &args.metrics else {
+        return Ok(None);
+    };
+    let target = metrics::Target::parse(url)?;
+    if args.metrics_interval.is_zero() {
+        return Err("--metrics-interval must be positive".into());
+    }
+    say!(
+        "Pushing metrics to {} every {:?}",
+        url,
+        args.metrics_interval
+    );
+    Ok(Some(metrics::Pusher::start(target, args.metrics_interval)?))
+}
+
+/// The ID to tag generated data with.
+fn run_id(args: &Args) -> Result<u64, String> {
+    match args.run_id {
+        // Zero means "any" in query filters.
+        Some(0) => Err("--run-id must not be 0".to_string()),
+        Some(run_id) => Ok(run_id),
+        None => Ok(seed::run_id(args.seed)),
+    }
+}
+
+/// Tell the user how to find this run's data later.
+fn print_run_id(run_id: u64) {
+    say!(
+        "Run ID: {} (query accounts and transfers with user_data_64 = {})",
+        run_id,
+        run_id
+    );
+}
+
+/// Stability limits for --soak, if set.
+fn soak_thresholds(args: &Args) -> Option<soak::Thresholds> {
+    args.soak.then_some(soak::Thresholds {
+        window: args.soak_window,
+        max_error_rate: args.max_error_rate.unwrap_or(0.01),
+        max_latency_growth: args.max_latency_growth,
+    })
+}
+
+/// Connect and work out the batch size the server accepts.
+async fn connect(args: &Args) -> Result<(tb_rs::Client, u32), Box<dyn std::error::Error>> {
+    say!();
+    say!("Connecting to TigerBeetle at {}...", args.addresses());
+    let client = match tb_rs::Client::connect(args.cluster, &args.addresses()).await {
+        Ok(client) => client,
+        // Replicas ignore messages for another cluster, so registering
+        // with the wrong ID looks like a silent cluster.
+        Err(tb_rs::ClientError::Timeout) => {
+            return Err(format!(
+                "no replica answered registration; is {} the cluster's ID (--cluster)?",
+                args.cluster
+            )
+            .into())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    say!("Connected! Client ID: {:032x}", client.id());
+    let replicas = client.metrics().replicas.len();
+    if replicas != args.address.len() {
+        return Err(format!(
+            "--address lists {} replicas but the client has {}",
+            args.address.len(),
+            replicas
+        )
+        .into());
+    }
 
     // Use the server's batch size limit (tb-rs will reject oversized batches)
     let effective_batch_size = client
         .max_batch_count::<Account>()
         .map(|max| std::cmp::min(args.batch_size, max))
         .unwrap_or(args.batch_size);
-    println!(
+    say!(
         "Using batch size: {} (max: {:?})",
         effective_batch_size,
         client.max_batch_count::<Account>()
     );
 
-    // Create accounts in batches
-    println!();
-    println!("Creating accounts...");
-    let mut accounts_created: u32 = 0;
-    let mut accounts_failed: u32 = 0;
+    Ok((client, effective_batch_size))
+}
+
+/// Show how the main client's traffic went to each replica, so failover
+/// and hedging can be seen at work.
+fn report_replicas(args: &Args, client: &tb_rs::Client) {
+    let replicas = client.metrics().replicas;
+    if replicas.len() < 2 {
+        return;
+    }
+    say!();
+    for (i, (address, stats)) in args.address.iter().zip(&replicas).enumerate() {
+        say!(
+            "Replica {} ({}): {} sent, {} received, {} reconnects, {} failed connects, rtt {}",
+            i,
+            address,
+            stats.messages_sent.total(),
+            stats.messages_received.total(),
+            stats.reconnects,
+            stats.connect_failures,
+            stats
+                .rtt()
+                .map_or("-".to_string(), |rtt| format!("{:.1?}", rtt))
+        );
+    }
+}
+
+/// Create accounts in batches, reporting failures and progress.
+async fn send_accounts(
+    client: &mut tb_rs::Client,
+    accounts: &[Account],
+    batch_size: u32,
+) -> Result<Tally, Box<dyn std::error::Error>> {
+    say!();
+    say!("Creating accounts...");
+    let mut tally = Tally::default();
+    let mut progress = Progress::new("accounts", accounts.len() as u64, None);
 
-    for chunk in accounts.chunks(effective_batch_size as usize) {
+    for chunk in accounts.chunks(batch_size as usize) {
+        let sent_at = Instant::now();
         let results = client.create_accounts(chunk).await?;
+        capture::accounts(0, sent_at, chunk, &results)?;
 
-        if results.is_empty() {
-            accounts_created += chunk.len() as u32;
-        } else {
-            // Some accounts failed
-            accounts_failed += results.len() as u32;
-            accounts_created += (chunk.len() - results.len()) as u32;
+        for result in &results {
+            eprintln!("  Account {} failed: {:?}", result.index, result.result);
+        }
+        let codes = results.iter().map(|r| format!("{:?}", r.result));
+        tally.record(chunk.len(), codes, sent_at.elapsed());
+
+        progress.update(tally.sent(), None);
+    }
+    progress.finish();
+    say!(
+        "Accounts: {} created, {} failed",
+        tally.created,
+        tally.failed
+    );
 
-            for result in &results {
+    Ok(tally)
+}
+
+/// Look up every account and fail if any broke its limit flags.
+async fn check_limits(
+    client: &mut tb_rs::Client,
+    accounts: &[Account],
+    batch_size: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    say!();
+    say!("Checking account limits...");
+    let ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
+    let mut checked = 0;
+    let mut violations = 0;
+    for chunk in ids.chunks(batch_size as usize) {
+        for account in client.lookup_accounts(chunk).await? {
+            checked += 1;
+            if !balancing::within_limits(&account) {
+                violations += 1;
                 eprintln!(
-                    "  Account {} failed: {:?}",
-                    result.index, result.result
+                    "  Account {:032x} broke its limit: debits {}+{} pending, credits {}+{} pending",
+                    account.id,
+                    account.debits_posted,
+                    account.debits_pending,
+                    account.credits_posted,
+                    account.credits_pending
                 );
             }
         }
+    }
+    say!(
+        "Limits: {} accounts checked, {} violations",
+        checked,
+        violations
+    );
 
-        print!(
-            "\r  Progress: {}/{} accounts",
-            accounts_created + accounts_failed,
-            args.accounts
-        );
+    if violations > 0 {
+        return Err(format!("{} accounts broke their limits", violations).into());
     }
-    println!();
-    println!(
-        "Accounts: {} created, {} failed",
-        accounts_created, accounts_failed
+    Ok(())
+}
+
+/// Prefix for output from `worker`.
+fn worker_label(worker: Option<usize>) -> String {
+    worker.map_or(String::new(), |w| format!("[worker {}] ", w))
+}
+
+/// Capture sender number of `worker`: 0 for the main client.
+fn sender(worker: Option<usize>) -> u32 {
+    worker.map_or(0, |w| w as u32 + 1)
+}
+
+/// Create transfers in batches, reporting failures and progress.
+async fn send_transfers(
+    client: &mut tb_rs::Client,
+    transfers: &[Transfer],
+    batch_size: u32,
+    worker: Option<usize>,
+) -> Result<Tally, Box<dyn std::error::Error>> {
+    if transfers.is_empty() {
+        return Ok(Tally::default());
+    }
+
+    let label = worker_label(worker);
+    if worker.is_none() {
+        say!();
+        say!("Creating transfers...");
+    }
+    let mut tally = Tally::default();
+    let mut progress = Progress::new("transfers", transfers.len() as u64, worker);
+
+    // Batches end on chain boundaries; a split chain would be rejected.
+    let batches = linked::batches(transfers, batch_size as usize)
+        .ok_or("a linked chain is longer than the batch size")?;
+    for chunk in batches {
+        let (codes, latency) = send_batch(client, chunk, worker).await?;
+        tally.record(chunk.len(), codes, latency);
+        progress.update(tally.sent(), None);
+    }
+    progress.finish();
+    say!(
+        "{}Transfers: {} created, {} failed",
+        label,
+        tally.created,
+        tally.failed
     );
 
-    // Create transfers in batches
-    if !transfers.is_empty() {
-        println!();
-        println!("Creating transfers...");
-        let mut transfers_created: u32 = 0;
-        let mut transfers_failed: u32 = 0;
-
-        for chunk in transfers.chunks(effective_batch_size as usize) {
-            let results = client.create_transfers(chunk).await?;
-
-            if results.is_empty() {
-                transfers_created += chunk.len() as u32;
-            } else {
-                // Some transfers failed
-                transfers_failed += results.len() as u32;
-                transfers_created += (chunk.len() - results.len()) as u32;
-
-                for result in &results {
-                    eprintln!(
-                        "  Transfer {} failed: {:?}",
-                        result.index, result.result
-                    );
-                }
-            }
+    Ok(tally)
+}
 
-            print!(
-                "\r  Progress: {}/{} transfers",
-                transfers_created + transfers_failed,
-                args.transfers
-            );
-        }
-        println!();
-        println!(
-            "Transfers: {} created, {} failed",
-            transfers_created, transfers_failed
+/// Send one batch of transfers and report its failures, returning their
+/// result codes and the request's latency (with any `--retry` attempts).
+///
+/// Reads mixed in with `--read-ratio` follow the batch but are not counted
+/// in its latency.
+async fn send_batch(
+    client: &mut tb_rs::Client,
+    chunk: &[Transfer],
+    worker: Option<usize>,
+) -> Result<(Vec<String>, Duration), Box<dyn std::error::Error>> {
+    let sent_at = Instant::now();
+    let results = retry::create_transfers(client, chunk, sender(worker)).await?;
+    let latency = sent_at.elapsed();
+
+    let label = worker_label(worker);
+    for result in &results {
+        eprintln!(
+            "  {}Transfer {} failed: {:?}",
+            label, result.index, result.result
         );
     }
+    let codes = results.iter().map(|r| format!("{:?}", r.result)).collect();
+    reads::interleave(client).await?;
+    Ok((codes, latency))
+}
 
-    // Close client
-    client.close().await;
-
-    println!();
-    println!("Done!");
+/// Send transfers back to back for `duration` without recording them.
+///
+/// Warmup transfers take random IDs, so a `--seed` run's own IDs stay the
+/// same with or without a warmup.
+async fn warm_up(
+    client: &mut tb_rs::Client,
+    plan: &TransferPlan,
+    batch_size: u32,
+    duration: Duration,
+    worker: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let label = worker_label(worker);
+    if worker.is_none() {
+        say!();
+    }
+    say!("{}Warming up for {:?}...", label, duration);
+    let mut source = Source::new(None);
+    let start = Instant::now();
+    let mut sent = 0;
+    while start.elapsed() < duration {
+        let transfers = plan.generate(batch_size, &mut source);
+        client.create_transfers(&transfers).await?;
+        sent += transfers.len();
+    }
+    say!("{}Warmed up with {} transfers", label, sent);
+    Ok(())
+}
 
+/// Flush an export and report where it went.
+fn finish_export(
+    exporter: Exporter<BufWriter<File>>,
+    what: &str,
+    path: &Path,
+) -> std::io::Result<()> {
+    let rows = exporter.rows();
+    exporter.finish()?;
+    say!("Wrote {} {} to {}", rows, what, path.display());
     Ok(())
 }
 
+/// Submit `total` transfers on the schedule set by `pacer`.
+///
+/// Each batch holds the transfers that fell due since the last one, so the
+/// offered load stays constant while replies are slow; the largest backlog
+/// is reported at the end. With `soak` limits, a breach stops the run with
+/// an error.
+async fn run_paced(
+    client: &mut tb_rs::Client,
+    pacer: &Pacer,
+    total: u64,
+    batch_size: u32,
+    worker: Option<usize>,
+    soak: Option<&soak::Thresholds>,
+    mut generate: impl FnMut(u32) -> std::io::Result<Vec<Transfer>>,
+) -> Result<Tally, Box<dyn std::error::Error>> {
+    let label = worker_label(worker);
+    let start = Instant::now();
+    let mut tally = Tally::default();
+    let mut progress = Progress::new("transfers", total, worker);
+    let mut max_backlog: u64 = 0;
+    let mut monitor = soak.map(|&thresholds| soak::Monitor::new(thresholds, label.clone()));
+
+    while tally.sent() < total {
+        let sent = tally.sent();
+        let elapsed = start.elapsed();
+        let due = pacer.due(elapsed).min(total) - sent;
+        if due == 0 {
+            // A profile that ends at zero may never reach `total`.
+            let Some(at) = pacer.due_at(sent + 1) else {
+                break;
+            };
+            tokio::time::sleep(at.saturating_sub(elapsed)).await;
+            continue;
+        }
+        max_backlog = max_backlog.max(due);
+
+        let transfers = generate(due.min(batch_size as u64) as u32)?;
+        let sent_at = Instant::now();
+        let results = retry::create_transfers(client, &transfers, sender(worker)).await?;
+        for result in &results {
+            eprintln!(
+                "  {}Transfer {} failed: {:?}",
+                label,
+                sent + result.index as u64,
+                result.result
+            );
+        }
+        let latency = sent_at.elapsed();
+        let codes = results.iter().map(|r| format!("{:?}", r.result));
+        tally.record(transfers.len(), codes, latency);
+        reads::interleave(client).await?;
+
+        progress.update(tally.sent(), Some(pacer.rate(start.elapsed())));
+        if let Some(monitor) = &mut monitor {
+            let checked = monitor.record(transfers.len() as u64, results.len() as u64, latency);
+            if let Err(breach) = checked {
+                progress.finish();
+                say!(
+                    "{}Soak failed after {:.1?}: {} created, {} failed",
+                    label,
+                    start.elapsed(),
+                    tally.created,
+                    tally.failed
+                );
+                return Err(breach.into());
+            }
+        }
+    }
+    progress.finish();
+    say!(
+        "{}Transfers: {} created, {} failed in {:.1?}",
+        label,
+        tally.created,
+        tally.failed,
+        start.elapsed()
+    );
+    if max_backlog > batch_size as u64 {
+        say!(
+            "{}Fell behind: up to {} transfers were overdue; the server could not keep up",
+            label,
+            max_backlog
+        );
+    }
+
+    Ok(tally)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    tokio_uring::start(async { run(args).await })
+    let args = Args::parse_from(config::expand(std::env::args_os().collect())?);
+    report::init(args.output);
+    retry::init(args.retry);
+    let capture_path = args.capture.clone();
+    if let Some(path) = &capture_path {
+        capture::init(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    // A failed run's capture is kept: it shows what led up to the failure.
+    let result = tokio_uring::start(async { run(args).await });
+    if let (Some(path), Some(records)) = (&capture_path, capture::finish()?) {
+        say!("Captured {} records to {}", records, path.display());
+    }
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::CommandFactory;
 
     #[test]
-    fn test_generate_accounts() {
-        let accounts = generate_accounts(10, 1, 100);
-
-        assert_eq!(accounts.len(), 10);
-        for account in &accounts {
-            assert_ne!(account.id, 0);
-            assert_eq!(account.ledger, 1);
-            assert_eq!(account.code, 100);
-            assert!(account.flags.is_empty());
-        }
-
-        // Verify all IDs are unique
-        let mut ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
-        ids.sort();
-        ids.dedup();
-        assert_eq!(ids.len(), 10);
-    }
-
-    #[test]
-    fn test_generate_transfers() {
-        let account_ids: Vec<u128> = (1..=5).map(|i| i as u128).collect();
-        let transfers = generate_transfers(20, &account_ids, 1, 50, 1000);
-
-        assert_eq!(transfers.len(), 20);
-        for transfer in &transfers {
-            assert_ne!(transfer.id, 0);
-            assert_eq!(transfer.ledger, 1);
-            assert_eq!(transfer.code, 50);
-            assert!(transfer.flags.is_empty());
-            assert!(transfer.amount >= 1 && transfer.amount <= 1000);
-            assert!(account_ids.contains(&transfer.debit_account_id));
-            assert!(account_ids.contains(&transfer.credit_account_id));
-            assert_ne!(transfer.debit_account_id, transfer.credit_account_id);
-        }
+    fn test_args() {
+        // Including the subcommands, which see the global flags.
+        Args::command().debug_assert();
     }
 
     #[test]
-    #[should_panic(expected = "Need at least 2 accounts")]
-    fn test_generate_transfers_requires_two_accounts() {
-        let account_ids = vec![1u128];
-        generate_transfers(1, &account_ids, 1, 1, 100);
+    fn test_warmup_conflicts() {
+        let args = Args::parse_from(["tb-gen", "--warmup", "1s", "--from-csv", "a.csv"]);
+        let error = tokio_uring::start(run(args)).unwrap_err().to_string();
+        assert!(error.contains("--warmup cannot be used"), "{}", error);
     }
 }
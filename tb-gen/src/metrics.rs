@@ -0,0 +1,305 @@
+//! Live metrics for `--metrics`, so long runs can be watched on dashboards.
+//!
+//! Every sender records its requests here; a background thread pushes what
+//! happened since the last interval to StatsD (`statsd://host:port`, over
+//! UDP) or to a Prometheus Pushgateway (`http://host:port`, as
+//! `job="tb_gen"`). Metrics cover accounts and transfers alike:
+//!
+//! - events created and failed (counters)
+//! - events per second and the failed fraction over the interval (gauges)
+//! - request latency p50, p99 and max over the interval (gauges)
+//!
+//! A failed push is reported and retried at the next interval; it never
+//! stops the run.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Requests recorded since the run started.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CREATED: AtomicU64 = AtomicU64::new(0);
+static FAILED: AtomicU64 = AtomicU64::new(0);
+/// Latencies since the last push; only collected while enabled.
+static LATENCIES: Mutex<Vec<Duration>> = Mutex::new(Vec::new());
+
+/// Record a request of `sent` events, `failed` of which failed.
+pub fn record(sent: u64, failed: u64, latency: Duration) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    CREATED.fetch_add(sent - failed, Ordering::Relaxed);
+    FAILED.fetch_add(failed, Ordering::Relaxed);
+    LATENCIES.lock().unwrap().push(latency);
+}
+
+/// Where metrics go.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    Statsd(String),
+    Pushgateway { addr: String, path: String },
+}
+
+impl Target {
+    /// Parse `statsd://host:port` or `http://host:port`.
+    pub fn parse(url: &str) -> Result<Target, String> {
+        if let Some(addr) = url.strip_prefix("statsd://") {
+            return Ok(Target::Statsd(addr.trim_end_matches('/').to_string()));
+        }
+        if let Some(rest) = url.strip_prefix("http://") {
+            let (addr, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            let prefix = prefix.trim_end_matches('/');
+            let path = if prefix.is_empty() {
+                "/metrics/job/tb_gen".to_string()
+            } else {
+                format!("/{}/metrics/job/tb_gen", prefix)
+            };
+            return Ok(Target::Pushgateway {
+                addr: addr.to_string(),
+                path,
+            });
+        }
+        Err(format!(
+            "invalid metrics target '{}' (use statsd://host:port or http://host:port)",
+            url
+        ))
+    }
+}
+
+/// What happened over one interval.
+#[derive(Debug, Default, PartialEq)]
+struct Snapshot {
+    created_total: u64,
+    failed_total: u64,
+    /// Events per second.
+    rate: f64,
+    /// Fraction of the interval's events that failed.
+    error_ratio: f64,
+    /// p50, p99 and max request latency.
+    latency: Option<[Duration; 3]>,
+}
+
+impl Snapshot {
+    fn statsd(&self, last: &Snapshot) -> String {
+        let mut out = format!(
+            "tb_gen.events.created:{}|c\ntb_gen.events.failed:{}|c\n\
+             tb_gen.events.rate:{:.1}|g\ntb_gen.events.error_ratio:{:.4}|g\n",
+            self.created_total - last.created_total,
+            self.failed_total - last.failed_total,
+            self.rate,
+            self.error_ratio
+        );
+        if let Some(latency) = self.latency {
+            for (name, value) in ["p50", "p99", "max"].iter().zip(latency) {
+                let ms = value.as_secs_f64() * 1000.0;
+                writeln!(out, "tb_gen.request_latency_ms.{}:{:.3}|g", name, ms).unwrap();
+            }
+        }
+        out
+    }
+
+    fn prometheus(&self) -> String {
+        let mut out = format!(
+            "# TYPE tb_gen_events_created_total counter\n\
+             tb_gen_events_created_total {}\n\
+             # TYPE tb_gen_events_failed_total counter\n\
+             tb_gen_events_failed_total {}\n\
+             # TYPE tb_gen_events_per_second gauge\n\
+             tb_gen_events_per_second {:.1}\n\
+             # TYPE tb_gen_error_ratio gauge\n\
+             tb_gen_error_ratio {:.4}\n",
+            self.created_total, self.failed_total, self.rate, self.error_ratio
+        );
+        if let Some(latency) = self.latency {
+            out.push_str("# TYPE tb_gen_request_latency_seconds gauge\n");
+            for (quantile, value) in ["0.5", "0.99", "1"].iter().zip(latency) {
+                writeln!(
+                    out,
+                    "tb_gen_request_latency_seconds{{quantile=\"{}\"}} {:.6}",
+                    quantile,
+                    value.as_secs_f64()
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Take a snapshot `elapsed` after the `last` one.
+fn snapshot(last: &Snapshot, elapsed: Duration) -> Snapshot {
+    let created_total = CREATED.load(Ordering::Relaxed);
+    let failed_total = FAILED.load(Ordering::Relaxed);
+    let mut latencies = std::mem::take(&mut *LATENCIES.lock().unwrap());
+    latencies.sort_unstable();
+
+    let created = created_total - last.created_total;
+    let failed = failed_total - last.failed_total;
+    let sent = created + failed;
+    Snapshot {
+        created_total,
+        failed_total,
+        rate: sent as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        error_ratio: if sent == 0 {
+            0.0
+        } else {
+            failed as f64 / sent as f64
+        },
+        latency: latencies.last().map(|&max| {
+            let at = |p: usize| latencies[(latencies.len() * p).div_ceil(100).max(1) - 1];
+            [at(50), at(99), max]
+        }),
+    }
+}
+
+/// An open [`Target`].
+enum Sink {
+    Statsd(UdpSocket),
+    Pushgateway { addr: String, path: String },
+}
+
+/// The background thread pushing metrics.
+pub struct Pusher {
+    stop: mpsc::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Pusher {
+    /// Start pushing to `target` every `interval`.
+    pub fn start(target: Target, interval: Duration) -> io::Result<Pusher> {
+        let sink = match target {
+            Target::Statsd(addr) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(addr)?;
+                Sink::Statsd(socket)
+            }
+            Target::Pushgateway { addr, path } => Sink::Pushgateway { addr, path },
+        };
+        ENABLED.store(true, Ordering::Relaxed);
+
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut last = Snapshot::default();
+            let mut last_at = Instant::now();
+            loop {
+                let done = !matches!(
+                    stopped.recv_timeout(interval),
+                    Err(RecvTimeoutError::Timeout)
+                );
+                let now = Instant::now();
+                let current = snapshot(&last, now - last_at);
+                let pushed = match &sink {
+                    Sink::Statsd(socket) => socket.send(current.statsd(&last).as_bytes()).map(drop),
+                    Sink::Pushgateway { addr, path } => {
+                        push_gateway(addr, path, &current.prometheus())
+                    }
+                };
+                if let Err(e) = pushed {
+                    eprintln!("  Metrics push failed: {}", e);
+                }
+                last = current;
+                last_at = now;
+                if done {
+                    break;
+                }
+            }
+        });
+        Ok(Pusher { stop, handle })
+    }
+
+    /// Push the final interval and stop.
+    pub fn finish(self) {
+        drop(self.stop);
+        let _ = self.handle.join();
+        ENABLED.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Replace this job's metrics on a Pushgateway.
+fn push_gateway(addr: &str, path: &str, body: &str) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write!(
+        stream,
+        "PUT {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        addr,
+        body.len(),
+        body
+    )?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    let status = reply.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "pushgateway replied '{}'",
+            status
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            Target::parse("statsd://localhost:8125"),
+            Ok(Target::Statsd("localhost:8125".to_string()))
+        );
+        assert_eq!(
+            Target::parse("http://gw:9091"),
+            Ok(Target::Pushgateway {
+                addr: "gw:9091".to_string(),
+                path: "/metrics/job/tb_gen".to_string(),
+            })
+        );
+        assert_eq!(
+            Target::parse("http://gw:9091/prefix/"),
+            Ok(Target::Pushgateway {
+                addr: "gw:9091".to_string(),
+                path: "/prefix/metrics/job/tb_gen".to_string(),
+            })
+        );
+        assert!(Target::parse("https://gw:9091").is_err());
+        assert!(Target::parse("gw:9091").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_formats() {
+        let last = Snapshot {
+            created_total: 100,
+            failed_total: 1,
+            ..Default::default()
+        };
+        let current = Snapshot {
+            created_total: 190,
+            failed_total: 11,
+            rate: 10.0,
+            error_ratio: 0.1,
+            latency: Some([
+                Duration::from_millis(2),
+                Duration::from_millis(9),
+                Duration::from_millis(12),
+            ]),
+        };
+
+        let statsd = current.statsd(&last);
+        assert!(statsd.starts_with("tb_gen.events.created:90|c\ntb_gen.events.failed:10|c\n"));
+        assert!(statsd.contains("tb_gen.events.error_ratio:0.1000|g\n"));
+        assert!(statsd.ends_with("tb_gen.request_latency_ms.max:12.000|g\n"));
+
+        let prometheus = current.prometheus();
+        assert!(prometheus.contains("tb_gen_events_created_total 190\n"));
+        assert!(prometheus.contains("tb_gen_request_latency_seconds{quantile=\"0.99\"} 0.009000\n"));
+        assert!(!Snapshot::default().prometheus().contains("latency"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Open-loop pacing for `--tps` and `--ramp`.
+//!
+//! Transfers fall due at a scheduled rate measured from the start of the run,
+//! independent of how quickly the server answers. A slow reply therefore
+//! doesn't lower the offered load: the transfers that fell due meanwhile go
+//! out in the next (larger) batch.
+//!
+//! The rate follows a profile of `(time, tps)` points. Between points it
+//! either moves linearly (a ramp) or holds until the next point (steps);
+//! after the last point it stays put. A constant `--tps` is a single point.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Schedule of transfers due over time.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    /// Points in ascending time order; the first is at zero.
+    points: Vec<(Duration, f64)>,
+    step: bool,
+}
+
+impl Pacer {
+    /// A constant rate.
+    pub fn new(tps: u64) -> Self {
+        assert!(tps > 0);
+        Pacer {
+            points: vec![(Duration::ZERO, tps as f64)],
+            step: false,
+        }
+    }
+
+    /// A rate that follows `points`, ramping between them or, with `step`,
+    /// jumping at each.
+    pub fn profile(points: Vec<(Duration, u64)>, step: bool) -> Result<Self, String> {
+        let Some(&(first, _)) = points.first() else {
+            return Err("empty profile".to_string());
+        };
+        if first != Duration::ZERO {
+            return Err("profile must start at 0".to_string());
+        }
+        if points.windows(2).any(|w| w[1].0 <= w[0].0) {
+            return Err("profile times must increase".to_string());
+        }
+        if points.iter().all(|&(_, tps)| tps == 0) {
+            return Err("profile never sends anything".to_string());
+        }
+        let points = points.into_iter().map(|(t, tps)| (t, tps as f64)).collect();
+        Ok(Pacer { points, step })
+    }
+
+    /// The same profile with every rate multiplied by `factor`.
+    pub fn scaled(&self, factor: f64) -> Self {
+        Pacer {
+            points: self.points.iter().map(|&(t, r)| (t, r * factor)).collect(),
+            step: self.step,
+        }
+    }
+
+    /// Time of the last point; later the rate no longer changes.
+    pub fn end(&self) -> Duration {
+        self.points.last().unwrap().0
+    }
+
+    /// Rate `elapsed` after the start.
+    pub fn rate(&self, elapsed: Duration) -> f64 {
+        let i = self.points.partition_point(|&(t, _)| t <= elapsed) - 1;
+        let (t0, r0) = self.points[i];
+        match self.points.get(i + 1) {
+            Some(&(t1, r1)) if !self.step => {
+                let f = (elapsed - t0).as_secs_f64() / (t1 - t0).as_secs_f64();
+                r0 + (r1 - r0) * f
+            }
+            _ => r0,
+        }
+    }
+
+    /// Number of transfers due `elapsed` after the start.
+    pub fn due(&self, elapsed: Duration) -> u64 {
+        let mut total = 0.0;
+        for (i, &(t0, r0)) in self.points.iter().enumerate() {
+            if elapsed <= t0 {
+                break;
+            }
+            let t1 = self.points.get(i + 1).map_or(elapsed, |p| p.0.min(elapsed));
+            // Trapezoid under the segment up to t1; flat when stepping.
+            let r1 = if self.step { r0 } else { self.rate(t1) };
+            total += (t1 - t0).as_secs_f64() * (r0 + r1) / 2.0;
+        }
+        total as u64
+    }
+
+    /// Time after the start at which the `count`-th transfer falls due, or
+    /// `None` if the rate drops to zero first.
+    pub fn due_at(&self, count: u64) -> Option<Duration> {
+        let mut hi = self.end().max(Duration::from_secs(1));
+        while self.due(hi) < count {
+            if self.rate(hi) == 0.0 && hi > self.end() {
+                return None;
+            }
+            hi *= 2;
+        }
+
+        // Earliest nanosecond by which `count` are due.
+        let (mut lo, mut hi) = (0u64, hi.as_nanos() as u64);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.due(Duration::from_nanos(mid)) >= count {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        Some(Duration::from_nanos(lo))
+    }
+}
+
+impl fmt::Display for Pacer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let [(_, tps)] = self.points[..] {
+            return write!(f, "{:.0} tps", tps);
+        }
+        write!(f, "{}", if self.step { "steps" } else { "ramp" })?;
+        for (i, (t, tps)) in self.points.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            if t.subsec_nanos() == 0 {
+                write!(f, "{}{}s:{:.0}", sep, t.as_secs(), tps)?;
+            } else {
+                write!(f, "{}{:?}:{:.0}", sep, t, tps)?;
+            }
+        }
+        write!(f, " tps")
+    }
+}
+
+/// Parse a profile such as `0:1000,60s:5000,120s:10000`.
+///
+/// Points are `time:tps`, separated by commas, whitespace or newlines.
+pub fn parse_profile(s: &str) -> Result<Vec<(Duration, u64)>, String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|p| !p.is_empty())
+        .map(|point| {
+            let (time, tps) = point
+                .split_once(':')
+                .ok_or_else(|| format!("invalid profile point '{}' (use time:tps)", point))?;
+            let tps = tps
+                .parse()
+                .map_err(|_| format!("invalid rate '{}' in profile", tps))?;
+            Ok((parse_duration(time)?, tps))
+        })
+        .collect()
+}
+
+/// Parse a duration such as `90s`, `10m`, `1h` or `500ms`.
+///
+/// A bare number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+
+    let secs = match unit {
+        "ms" => return Ok(Duration::from_millis(value)),
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{}' (use ms, s, m or h)",
+                unit
+            ))
+        }
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_pacer_due() {
+        let pacer = Pacer::new(1000);
+        assert_eq!(pacer.due(Duration::ZERO), 0);
+        assert_eq!(pacer.due(Duration::from_micros(999)), 0);
+        assert_eq!(pacer.due(Duration::from_millis(1)), 1);
+        assert_eq!(pacer.due(secs(10)), 10_000);
+    }
+
+    #[test]
+    fn test_pacer_due_at() {
+        let pacer = Pacer::new(3);
+        assert_eq!(pacer.due_at(0), Some(Duration::ZERO));
+        assert_eq!(pacer.due_at(3), Some(secs(1)));
+        // The earliest moment the transfer is due.
+        let at = pacer.due_at(1).unwrap();
+        assert_eq!(pacer.due(at), 1);
+        assert_eq!(pacer.due(at - Duration::from_nanos(1)), 0);
+    }
+
+    #[test]
+    fn test_pacer_ramp() {
+        let pacer = Pacer::profile(vec![(secs(0), 0), (secs(10), 1000)], false).unwrap();
+        assert_eq!(pacer.rate(secs(5)), 500.0);
+        assert_eq!(pacer.rate(secs(20)), 1000.0);
+        // Area of the ramp, then the held rate.
+        assert_eq!(pacer.due(secs(10)), 5000);
+        assert_eq!(pacer.due(secs(12)), 7000);
+        assert_eq!(pacer.due_at(5000), Some(secs(10)));
+    }
+
+    #[test]
+    fn test_pacer_steps() {
+        let pacer = Pacer::profile(vec![(secs(0), 100), (secs(10), 1000)], true).unwrap();
+        assert_eq!(pacer.rate(secs(9)), 100.0);
+        assert_eq!(pacer.rate(secs(10)), 1000.0);
+        assert_eq!(pacer.due(secs(10)), 1000);
+        assert_eq!(pacer.due(secs(11)), 2000);
+    }
+
+    #[test]
+    fn test_pacer_ramp_down_to_zero() {
+        let pacer = Pacer::profile(vec![(secs(0), 100), (secs(2), 0)], false).unwrap();
+        assert_eq!(pacer.due(secs(100)), 100);
+        assert!(pacer.due_at(100).is_some());
+        assert_eq!(pacer.due_at(101), None);
+    }
+
+    #[test]
+    fn test_pacer_scaled() {
+        let pacer = Pacer::new(1000).scaled(0.25);
+        assert_eq!(pacer.due(secs(4)), 1000);
+    }
+
+    #[test]
+    fn test_pacer_display() {
+        assert_eq!(Pacer::new(500).to_string(), "500 tps");
+        let pacer = Pacer::profile(vec![(secs(0), 10), (secs(60), 50)], true).unwrap();
+        assert_eq!(pacer.to_string(), "steps 0s:10, 60s:50 tps");
+    }
+
+    #[test]
+    fn test_pacer_profile_errors() {
+        assert!(Pacer::profile(vec![], false).is_err());
+        assert!(Pacer::profile(vec![(secs(1), 10)], false).is_err());
+        assert!(Pacer::profile(vec![(secs(0), 10), (secs(0), 20)], false).is_err());
+        assert!(Pacer::profile(vec![(secs(0), 0)], false).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!(
+            parse_profile("0:1000\n60s:5000, 2m:10000"),
+            Ok(vec![(secs(0), 1000), (secs(60), 5000), (secs(120), 10000)])
+        );
+        assert!(parse_profile("0-1000").is_err());
+        assert!(parse_profile("0:fast").is_err());
+        assert!(parse_profile("soon:10").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("1.5m").is_err());
+    }
+}
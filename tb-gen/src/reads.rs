@@ -0,0 +1,372 @@
+//! Read traffic for `--readers`.
+//!
+//! Readers are threads with clients of their own, like workers, that query
+//! the run's accounts while its transfers are written, so read latency can
+//! be measured under write load. Each read is one of `--read-ops`, picked
+//! at random:
+//!
+//! - `lookup_accounts`: `--read-size` accounts by ID;
+//! - `get_account_transfers`: an account's latest `--read-size` transfers;
+//! - `query_transfers`: the run's latest `--read-size` transfers on an
+//!   account's ledger.
+//!
+//! Accounts are picked by `--read-distribution` and `--read-skew`, apart
+//! from how transfers pick them, so reads can focus on hot accounts while
+//! writes spread evenly. `--read-tps` caps the rate of all readers together.
+//!
+//! Readers stop once the transfers are sent, or after `--read-duration`;
+//! with no transfers, that makes a read-only load.
+//!
+//! `--read-ratio` instead has the writers read: after each write request, a
+//! sender makes the reads that keep its share of read requests at the ratio,
+//! as an OLTP client mixing lookups with its updates would. The two can be
+//! combined.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tb_rs::{
+    AccountFilter, AccountFilterFlags, Client, ClientError, QueryFilter, QueryFilterFlags,
+};
+
+use crate::distribution::{AccountPicker, Distribution};
+use crate::pace::Pacer;
+use crate::report::Tally;
+
+/// A kind of read request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ReadOp {
+    #[value(name = "lookup_accounts")]
+    LookupAccounts,
+    #[value(name = "get_account_transfers")]
+    GetAccountTransfers,
+    #[value(name = "query_transfers")]
+    QueryTransfers,
+}
+
+impl ReadOp {
+    pub const ALL: [ReadOp; 3] = [
+        ReadOp::LookupAccounts,
+        ReadOp::GetAccountTransfers,
+        ReadOp::QueryTransfers,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReadOp::LookupAccounts => "lookup_accounts",
+            ReadOp::GetAccountTransfers => "get_account_transfers",
+            ReadOp::QueryTransfers => "query_transfers",
+        }
+    }
+}
+
+/// How to read.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub cluster: u128,
+    pub address: String,
+    pub readers: usize,
+    pub tps: Option<u64>,
+    pub ops: Vec<ReadOp>,
+    /// IDs per lookup and results per query.
+    pub size: u32,
+    pub distribution: Distribution,
+    pub skew: f64,
+    pub duration: Option<Duration>,
+    /// Limits `query_transfers` to the run's transfers.
+    pub run_id: u64,
+}
+
+/// What readers read: account IDs with their ledgers.
+struct Targets {
+    accounts: Vec<(u128, u32)>,
+    picker: AccountPicker,
+}
+
+impl Targets {
+    fn new(config: &Config, accounts: Vec<(u128, u32)>) -> Self {
+        let picker = AccountPicker::new(config.distribution, accounts.len(), config.skew);
+        Targets { accounts, picker }
+    }
+
+    /// Pick a read and what it reads.
+    fn choose(&self, config: &Config, rng: &mut impl Rng) -> Read {
+        let (id, ledger) = self.accounts[self.picker.pick(rng)];
+        match config.ops[rng.gen_range(0..config.ops.len())] {
+            ReadOp::LookupAccounts => Read::LookupAccounts(
+                (0..config.size)
+                    .map(|_| self.accounts[self.picker.pick(rng)].0)
+                    .collect(),
+            ),
+            ReadOp::GetAccountTransfers => Read::GetAccountTransfers(id),
+            ReadOp::QueryTransfers => Read::QueryTransfers(ledger),
+        }
+    }
+}
+
+/// A read with its accounts or ledger picked.
+enum Read {
+    LookupAccounts(Vec<u128>),
+    GetAccountTransfers(u128),
+    QueryTransfers(u32),
+}
+
+impl Read {
+    fn op(&self) -> ReadOp {
+        match self {
+            Read::LookupAccounts(_) => ReadOp::LookupAccounts,
+            Read::GetAccountTransfers(_) => ReadOp::GetAccountTransfers,
+            Read::QueryTransfers(_) => ReadOp::QueryTransfers,
+        }
+    }
+
+    /// Send the read; returns the records asked for, how many of those
+    /// were missing and the request's latency.
+    async fn send(
+        self,
+        client: &mut Client,
+        config: &Config,
+    ) -> Result<(usize, usize, Duration), ClientError> {
+        let sent_at = Instant::now();
+        let (sent, missing) = match self {
+            Read::LookupAccounts(ids) => {
+                let found = client.lookup_accounts(&ids).await?;
+                let missing = ids.iter().filter(|id| !found.iter().any(|a| a.id == **id));
+                (ids.len(), missing.count())
+            }
+            Read::GetAccountTransfers(account_id) => {
+                let filter = AccountFilter {
+                    account_id,
+                    limit: config.size,
+                    flags: AccountFilterFlags::DEBITS
+                        | AccountFilterFlags::CREDITS
+                        | AccountFilterFlags::REVERSED,
+                    ..Default::default()
+                };
+                client.get_account_transfers(filter).await?;
+                (1, 0)
+            }
+            Read::QueryTransfers(ledger) => {
+                let filter = QueryFilter {
+                    user_data_64: config.run_id,
+                    ledger,
+                    limit: config.size,
+                    flags: QueryFilterFlags::REVERSED,
+                    ..Default::default()
+                };
+                client.query_transfers(filter).await?;
+                (1, 0)
+            }
+        };
+        Ok((sent, missing, sent_at.elapsed()))
+    }
+}
+
+fn record(tally: &mut Tally, sent: usize, missing: usize, latency: Duration) {
+    let codes = std::iter::repeat_n("Missing".to_string(), missing);
+    tally.record(sent, codes, latency);
+}
+
+/// Running readers.
+pub struct Readers {
+    stop: Arc<AtomicBool>,
+    start: Instant,
+    handles: Vec<JoinHandle<Result<[Tally; 3], String>>>,
+}
+
+/// Start `config.readers` readers over `accounts` (ID and ledger).
+pub fn start(config: Config, accounts: Vec<(u128, u32)>) -> Readers {
+    say!();
+    say!(
+        "Reading with {} readers ({})...",
+        config.readers,
+        config
+            .ops
+            .iter()
+            .map(|op| op.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let targets = Arc::new(Targets::new(&config, accounts));
+    let config = Arc::new(config);
+    let stop = Arc::new(AtomicBool::new(false));
+    let handles = (0..config.readers)
+        .map(|reader| {
+            let (config, targets, stop) = (config.clone(), targets.clone(), stop.clone());
+            thread::spawn(move || {
+                tokio_uring::start(read(&config, &targets, &stop))
+                    .map_err(|e| format!("reader {}: {}", reader, e))
+            })
+        })
+        .collect();
+    Readers {
+        stop,
+        start: Instant::now(),
+        handles,
+    }
+}
+
+impl Readers {
+    /// Stop reading and return the results of each kind of read with the
+    /// time spent reading.
+    pub fn stop(self) -> Result<(Vec<(ReadOp, Tally)>, Duration), String> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+
+    /// Wait out `--read-duration` and return the results as for `stop`.
+    pub fn join(self) -> Result<(Vec<(ReadOp, Tally)>, Duration), String> {
+        let mut totals: [Tally; 3] = Default::default();
+        for handle in self.handles {
+            let tallies = handle
+                .join()
+                .unwrap_or_else(|_| Err("reader panicked".to_string()))?;
+            for (total, tally) in totals.iter_mut().zip(tallies) {
+                total.merge(tally);
+            }
+        }
+        let elapsed = self.start.elapsed();
+        let results = ReadOp::ALL
+            .into_iter()
+            .zip(totals)
+            .filter(|(_, tally)| tally.sent() > 0)
+            .collect();
+        Ok((results, elapsed))
+    }
+}
+
+/// Issue reads until stopped.
+async fn read(
+    config: &Config,
+    targets: &Targets,
+    stop: &AtomicBool,
+) -> Result<[Tally; 3], Box<dyn Error>> {
+    let mut client = Client::connect(config.cluster, &config.address).await?;
+    let mut rng = StdRng::from_entropy();
+    let pacer = config
+        .tps
+        .map(|tps| Pacer::new(tps).scaled(1.0 / config.readers as f64));
+    let mut tallies: [Tally; 3] = Default::default();
+    let start = Instant::now();
+    let mut done = 0;
+
+    while !stop.load(Ordering::Relaxed) && config.duration.is_none_or(|d| start.elapsed() < d) {
+        if let Some(pacer) = &pacer {
+            if pacer.due(start.elapsed()) <= done {
+                let at = pacer.due_at(done + 1).unwrap_or_default();
+                // Wake up now and then to notice a stop.
+                let wait = at.saturating_sub(start.elapsed());
+                tokio::time::sleep(wait.min(Duration::from_millis(100))).await;
+                continue;
+            }
+        }
+
+        let read = targets.choose(config, &mut rng);
+        let op = read.op();
+        let (sent, missing, latency) = read.send(&mut client, config).await?;
+        record(&mut tallies[op as usize], sent, missing, latency);
+        done += 1;
+    }
+
+    client.close().await;
+    Ok(tallies)
+}
+
+/// Reads interleaved with writes.
+struct Mix {
+    ratio: f64,
+    config: Config,
+    targets: Targets,
+}
+
+static MIX: OnceLock<Mix> = OnceLock::new();
+/// Results of interleaved reads, by op.
+static MIXED: Mutex<Vec<Tally>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Write and read requests made by this thread's sender.
+    static REQUESTS: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+/// Have every sender interleave reads of `accounts` (ID and ledger) with
+/// its writes, so that `ratio` of its requests are reads.
+pub fn mix(ratio: f64, config: Config, accounts: Vec<(u128, u32)>) {
+    *MIXED.lock().unwrap() = vec![Tally::default(); ReadOp::ALL.len()];
+    let targets = Targets::new(&config, accounts);
+    let mix = Mix {
+        ratio,
+        config,
+        targets,
+    };
+    assert!(MIX.set(mix).is_ok(), "reads are mixed in once per run");
+}
+
+/// Count a write request and make the reads it makes due.
+pub async fn interleave(client: &mut Client) -> Result<(), ClientError> {
+    let Some(mix) = MIX.get() else {
+        return Ok(());
+    };
+    let (writes, mut reads) = REQUESTS.get();
+    let writes = writes + 1;
+    while reads < reads_due(writes, mix.ratio) {
+        // Not held across awaits: the thread RNG is not Send.
+        let read = mix.targets.choose(&mix.config, &mut rand::thread_rng());
+        let op = read.op();
+        let (sent, missing, latency) = read.send(client, &mix.config).await?;
+        record(
+            &mut MIXED.lock().unwrap()[op as usize],
+            sent,
+            missing,
+            latency,
+        );
+        reads += 1;
+    }
+    REQUESTS.set((writes, reads));
+    Ok(())
+}
+
+/// Reads owed after `writes` write requests for `ratio` of all requests to
+/// be reads.
+fn reads_due(writes: u64, ratio: f64) -> u64 {
+    (writes as f64 * ratio / (1.0 - ratio)).round() as u64
+}
+
+/// Results of the interleaved reads so far.
+pub fn mixed() -> Vec<(ReadOp, Tally)> {
+    let tallies = std::mem::take(&mut *MIXED.lock().unwrap());
+    ReadOp::ALL
+        .into_iter()
+        .zip(tallies)
+        .filter(|(_, tally)| tally.sent() > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_op_names_parse() {
+        for (i, op) in ReadOp::ALL.into_iter().enumerate() {
+            assert_eq!(ReadOp::from_str(op.name(), false), Ok(op));
+            // Tallies are indexed by op.
+            assert_eq!(op as usize, i);
+        }
+    }
+
+    #[test]
+    fn test_reads_due() {
+        assert_eq!(reads_due(3, 0.7), 7);
+        assert_eq!(reads_due(1, 0.5), 1);
+        assert_eq!(reads_due(4, 0.2), 1);
+        assert_eq!(reads_due(1, 0.2), 0);
+        assert_eq!(reads_due(10, 0.0), 0);
+    }
+}
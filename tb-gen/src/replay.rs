@@ -0,0 +1,362 @@
+//! Re-submitting earlier workloads for `tb-gen replay`.
+//!
+//! CSV datasets are sent as `--from-csv` sends them. A `.tbcap` capture is
+//! replayed request by request with its original batches, from the same
+//! clients: the main client's requests first, then each worker's from its
+//! own thread and client, as the captured run sent them. With
+//! `--timing original` every request waits for its captured send time,
+//! measured from its sender's first request; with `--timing max` requests
+//! go out back to back.
+//!
+//! Replies are compared with the captured ones. Replaying onto the cluster
+//! that was captured is expected to differ (everything `Exists`); replaying
+//! onto a fresh cluster should not.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::capture::{self, Exchange};
+use crate::import::Dataset;
+use crate::report::{self, Progress, Tally};
+use crate::{connect, run_import, sender, start_metrics, Args};
+
+/// When to send each replayed request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Timing {
+    /// At the time it was sent in the captured run
+    Original,
+    /// As soon as the previous reply arrives
+    Max,
+}
+
+/// Replay `files`: CSV datasets in order, or a single capture.
+pub async fn run(args: &Args, files: &[PathBuf], timing: Timing) -> Result<(), Box<dyn Error>> {
+    let captures = files.iter().filter(|path| is_capture(path)).count();
+    if captures == 0 {
+        if timing == Timing::Original {
+            return Err("CSV datasets have no timing; --timing original needs a .tbcap".into());
+        }
+        return run_import(args, files).await;
+    }
+    if files.len() > 1 {
+        return Err("a .tbcap capture is replayed on its own".into());
+    }
+    replay_capture(args, &files[0], timing).await
+}
+
+fn is_capture(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "tbcap")
+}
+
+/// Results of replaying one kind of request.
+#[derive(Default)]
+struct Phase {
+    tally: Tally,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Phase {
+    fn record(&mut self, sent: usize, codes: impl Iterator<Item = String>, sent_at: Instant) {
+        let now = Instant::now();
+        self.tally.record(sent, codes, now - sent_at);
+        self.first.get_or_insert(sent_at);
+        self.last = Some(now);
+    }
+
+    fn merge(&mut self, other: Phase) {
+        self.tally.merge(other.tally);
+        self.first = self.first.into_iter().chain(other.first).min();
+        self.last = self.last.into_iter().chain(other.last).max();
+    }
+
+    /// Time from the first request sent to the last reply, across senders.
+    fn elapsed(&self) -> Duration {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Results of replaying some requests.
+#[derive(Default)]
+struct Replayed {
+    accounts: Phase,
+    transfers: Phase,
+    /// Requests whose reply differs from the captured one.
+    differing: u64,
+}
+
+impl Replayed {
+    fn merge(&mut self, other: Replayed) {
+        self.accounts.merge(other.accounts);
+        self.transfers.merge(other.transfers);
+        self.differing += other.differing;
+    }
+}
+
+async fn replay_capture(args: &Args, path: &Path, timing: Timing) -> Result<(), Box<dyn Error>> {
+    say!("TigerBeetle Capture Replay");
+    say!("==========================");
+    say!("Address: {}", args.addresses());
+    say!("Cluster: {}", args.cluster);
+    say!("Timing: {:?}", timing);
+    say!();
+
+    let exchanges = capture::read(path)?;
+    let (main, workers): (Vec<&Exchange>, Vec<&Exchange>) =
+        exchanges.iter().partition(|e| e.sender == 0);
+    let mut senders: Vec<u32> = workers.iter().map(|e| e.sender).collect();
+    senders.sort_unstable();
+    senders.dedup();
+    say!(
+        "Read {} requests from {} ({} workers)",
+        exchanges.len(),
+        path.display(),
+        senders.len()
+    );
+
+    if args.dry_run {
+        say!();
+        say!("Dry run mode - not sending to server");
+        return Ok(());
+    }
+
+    let pusher = start_metrics(args)?;
+    let mut replayed = replay_sender(args, &main, timing, None).await?;
+    if !senders.is_empty() {
+        say!();
+        say!("Replaying {} workers...", senders.len());
+    }
+    let results: Vec<Result<Replayed, String>> = thread::scope(|scope| {
+        let handles: Vec<_> = senders
+            .iter()
+            .map(|&sender| {
+                let requests: Vec<&Exchange> = workers
+                    .iter()
+                    .copied()
+                    .filter(|e| e.sender == sender)
+                    .collect();
+                let worker = sender as usize - 1;
+                scope.spawn(move || {
+                    tokio_uring::start(replay_sender(args, &requests, timing, Some(worker)))
+                        .map_err(|e| format!("worker {}: {}", worker, e))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err("worker panicked".to_string()))
+            })
+            .collect()
+    });
+    for result in results {
+        replayed.merge(result?);
+    }
+    if let Some(pusher) = pusher {
+        pusher.finish();
+    }
+
+    report::summary(
+        None,
+        &[
+            (
+                "accounts",
+                &replayed.accounts.tally,
+                replayed.accounts.elapsed(),
+            ),
+            (
+                "transfers",
+                &replayed.transfers.tally,
+                replayed.transfers.elapsed(),
+            ),
+        ],
+    );
+    say!();
+    say!(
+        "Replies: {} of {} requests got a different reply than captured",
+        replayed.differing,
+        exchanges.len()
+    );
+    say!("Done!");
+
+    Ok(())
+}
+
+/// Replay one sender's requests from a client of its own.
+async fn replay_sender(
+    args: &Args,
+    requests: &[&Exchange],
+    timing: Timing,
+    worker: Option<usize>,
+) -> Result<Replayed, Box<dyn Error>> {
+    let mut replayed = Replayed::default();
+    let Some(base) = requests.first().map(|e| e.sent_at) else {
+        return Ok(replayed);
+    };
+    let (mut client, _) = connect(args).await?;
+    let sender = sender(worker);
+    let mut progress = Progress::new("requests", requests.len() as u64, worker);
+    let start = Instant::now();
+
+    for (done, exchange) in requests.iter().enumerate() {
+        if timing == Timing::Original {
+            let due = exchange.sent_at.saturating_sub(base);
+            tokio::time::sleep(due.saturating_sub(start.elapsed())).await;
+        }
+
+        let sent_at = Instant::now();
+        let reply: Vec<(u32, u32)> = match &exchange.request {
+            Dataset::Accounts(accounts) => {
+                let results = client.create_accounts(accounts).await?;
+                let codes = results.iter().map(|r| format!("{:?}", r.result));
+                replayed.accounts.record(accounts.len(), codes, sent_at);
+                capture::accounts(sender, sent_at, accounts, &results)?;
+                results.iter().map(|r| (r.index, r.result as u32)).collect()
+            }
+            Dataset::Transfers(transfers) => {
+                let results = client.create_transfers(transfers).await?;
+                let codes = results.iter().map(|r| format!("{:?}", r.result));
+                replayed.transfers.record(transfers.len(), codes, sent_at);
+                capture::transfers(sender, sent_at, transfers, &results)?;
+                results.iter().map(|r| (r.index, r.result as u32)).collect()
+            }
+        };
+        if reply != exchange.reply {
+            replayed.differing += 1;
+        }
+        progress.update(done as u64 + 1, None);
+    }
+    progress.finish();
+
+    client.close().await;
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+
+    /// A dry run, which reads the files but sends nothing.
+    fn dry_run() -> Args {
+        Args::parse_from(["tb-gen", "--dry-run"])
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tb-gen-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_is_capture() {
+        assert!(is_capture(Path::new("run.tbcap")));
+        assert!(is_capture(Path::new("/tmp/runs/run.tbcap")));
+        assert!(!is_capture(Path::new("run.csv")));
+        assert!(!is_capture(Path::new("tbcap")));
+    }
+
+    #[test]
+    fn test_run_reads_files() {
+        let csv = temp_file("accounts.csv", b"id,ledger,code\n1,1,10\n2,1,10\n");
+        let capture = temp_file("empty.tbcap", capture::MAGIC);
+        let args = dry_run();
+
+        for (files, timing) in [
+            (vec![csv.clone()], Timing::Max),
+            (vec![csv.clone(), csv.clone()], Timing::Max),
+            (vec![capture.clone()], Timing::Max),
+            (vec![capture.clone()], Timing::Original),
+        ] {
+            let result = tokio_uring::start(run(&args, &files, timing));
+            assert!(result.is_ok(), "{:?}: {:?}", files, result.err());
+        }
+
+        fs::remove_file(csv).unwrap();
+        fs::remove_file(capture).unwrap();
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let csv = temp_file(
+            "transfers.csv",
+            b"id,debit_account_id,credit_account_id,amount\n",
+        );
+        let capture = temp_file("run.tbcap", capture::MAGIC);
+        let not_capture = temp_file("csv.tbcap", b"id,ledger,code\n1,1,10\n");
+        let missing = std::env::temp_dir().join("tb-gen-missing.tbcap");
+        let args = dry_run();
+
+        let cases = [
+            (
+                vec![csv.clone()],
+                Timing::Original,
+                "--timing original needs a .tbcap",
+            ),
+            (
+                vec![capture.clone(), csv.clone()],
+                Timing::Max,
+                "replayed on its own",
+            ),
+            (
+                vec![capture.clone(), capture.clone()],
+                Timing::Max,
+                "replayed on its own",
+            ),
+            (
+                vec![not_capture.clone()],
+                Timing::Max,
+                "not a .tbcap capture",
+            ),
+            (vec![missing.clone()], Timing::Max, "tb-gen-missing.tbcap"),
+        ];
+        for (files, timing, error) in cases {
+            let result = tokio_uring::start(run(&args, &files, timing));
+            let message = result.expect_err(error).to_string();
+            assert!(message.contains(error), "{:?}: {}", files, message);
+        }
+
+        for path in [csv, capture, not_capture] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let start = Instant::now();
+        let mut main = Replayed::default();
+        main.accounts
+            .record(2, ["Exists".to_string()].into_iter(), start);
+        main.differing = 1;
+
+        let mut worker = Replayed::default();
+        worker
+            .accounts
+            .record(3, std::iter::empty(), start + Duration::from_millis(5));
+        worker.transfers.record(4, std::iter::empty(), start);
+        worker.differing = 2;
+
+        let last = worker.accounts.last;
+        main.merge(worker);
+        assert_eq!(main.differing, 3);
+        assert_eq!(main.accounts.tally.created, 4);
+        assert_eq!(main.accounts.tally.failed, 1);
+        assert_eq!(main.accounts.tally.requests(), 2);
+        assert_eq!(main.transfers.tally.created, 4);
+        assert_eq!(main.accounts.first, Some(start));
+        assert_eq!(main.accounts.last, last);
+        assert_eq!(main.accounts.elapsed(), last.unwrap() - start);
+        assert_eq!(main.transfers.first, Some(start));
+        assert_eq!(Phase::default().elapsed(), Duration::ZERO);
+    }
+}
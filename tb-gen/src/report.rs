@@ -0,0 +1,374 @@
+//! Progress and results, for people or for scripts.
+//!
+//! With `--output json`, stdout carries one JSON object per line: `progress`
+//! events while sending (at most one a second per sender) and a `summary` at
+//! the end with counts by result code, throughput and latency percentiles.
+//! The human-readable lines move to stderr, so wrappers can parse stdout
+//! without scraping them.
+//!
+//! Latency is measured per request, from sending a batch to its reply (to
+//! the last reply, with `--retry`).
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::metrics;
+
+/// Print a human-readable line: to stdout, or to stderr when stdout carries
+/// JSON.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::report::json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// What goes to stdout.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Output {
+    /// Human-readable progress and results
+    Text,
+    /// JSON progress events and a summary, one object per line
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Choose the output for the rest of the run.
+pub fn init(output: Output) {
+    JSON.store(output == Output::Json, Ordering::Relaxed);
+}
+
+/// Whether stdout carries JSON.
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Results of the requests made by one or more senders.
+#[derive(Clone, Debug, Default)]
+pub struct Tally {
+    pub created: u64,
+    pub failed: u64,
+    /// Failures by result code.
+    failures: BTreeMap<String, u64>,
+    /// Time taken by each request.
+    latencies: Vec<Duration>,
+}
+
+impl Tally {
+    /// Count a request of `sent` events that failed with `codes` and took
+    /// `latency`.
+    pub fn record(
+        &mut self,
+        sent: usize,
+        codes: impl IntoIterator<Item = String>,
+        latency: Duration,
+    ) {
+        let mut failed = 0;
+        for code in codes {
+            *self.failures.entry(code).or_default() += 1;
+            failed += 1;
+        }
+        self.created += sent as u64 - failed;
+        self.failed += failed;
+        self.latencies.push(latency);
+        metrics::record(sent as u64, failed, latency);
+    }
+
+    /// Add the results of another sender.
+    pub fn merge(&mut self, other: Tally) {
+        self.created += other.created;
+        self.failed += other.failed;
+        for (code, count) in other.failures {
+            *self.failures.entry(code).or_default() += count;
+        }
+        self.latencies.extend(other.latencies);
+    }
+
+    /// Requests made.
+    pub fn requests(&self) -> u64 {
+        self.latencies.len() as u64
+    }
+
+    /// Events sent, whether or not they were created.
+    pub fn sent(&self) -> u64 {
+        self.created + self.failed
+    }
+
+    /// Request latency at percentile `p` (0-100), if any requests were made.
+    pub fn latency(&self, p: f64) -> Option<Duration> {
+        let mut sorted = self.latencies.clone();
+        sorted.sort_unstable();
+        percentile(&sorted, p)
+    }
+
+    /// Failures by result code, most common first, e.g.
+    /// `ExceedsCredits 12, LinkedEventFailed 3`.
+    pub fn breakdown(&self) -> String {
+        let mut failures: Vec<(&String, &u64)> = self.failures.iter().collect();
+        failures.sort_by(|a, b| b.1.cmp(a.1));
+        failures
+            .iter()
+            .map(|(code, count)| format!("{} {}", code, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Requests outstanding on average over `elapsed`: the time spent in
+    /// requests divided by the time taken (Little's law).
+    pub fn concurrency(&self, elapsed: Duration) -> f64 {
+        if elapsed.is_zero() {
+            return 0.0;
+        }
+        let busy: Duration = self.latencies.iter().sum();
+        busy.as_secs_f64() / elapsed.as_secs_f64()
+    }
+
+    pub fn json(&self, elapsed: Duration) -> String {
+        let mut out = format!(
+            "{{\"created\":{},\"failed\":{},\"failures\":{{",
+            self.created, self.failed
+        );
+        for (i, (code, count)) in self.failures.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(out, "{}\"{}\":{}", sep, code, count).unwrap();
+        }
+        write!(
+            out,
+            "}},\"elapsed_s\":{:.3},\"tps\":{:.1},\"concurrency\":{:.2}",
+            elapsed.as_secs_f64(),
+            rate(self.sent(), elapsed),
+            self.concurrency(elapsed)
+        )
+        .unwrap();
+
+        let mut sorted = self.latencies.clone();
+        sorted.sort_unstable();
+        if !sorted.is_empty() {
+            out.push_str(",\"latency_ms\":{");
+            for (i, (name, p)) in PERCENTILES.iter().enumerate() {
+                let sep = if i == 0 { "" } else { "," };
+                let ms = percentile(&sorted, *p).unwrap().as_secs_f64() * 1000.0;
+                write!(out, "{}\"{}\":{:.3}", sep, name, ms).unwrap();
+            }
+            out.push('}');
+        }
+        out.push('}');
+        out
+    }
+}
+
+/// Percentiles reported in the summary.
+const PERCENTILES: [(&str, f64); 5] = [
+    ("p50", 50.0),
+    ("p90", 90.0),
+    ("p99", 99.0),
+    ("p999", 99.9),
+    ("max", 100.0),
+];
+
+/// Nearest-rank percentile `p` (0-100) of `sorted`.
+pub fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.saturating_sub(1).min(last)])
+}
+
+fn rate(count: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    count as f64 / elapsed.as_secs_f64()
+}
+
+/// Progress of one sender through one phase.
+pub struct Progress {
+    phase: &'static str,
+    total: u64,
+    worker: Option<usize>,
+    start: Instant,
+    last_event: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(phase: &'static str, total: u64, worker: Option<usize>) -> Self {
+        Progress {
+            phase,
+            total,
+            worker,
+            start: Instant::now(),
+            last_event: None,
+        }
+    }
+
+    /// Report `done` of the total sent, against a paced `target` rate if any.
+    ///
+    /// Workers don't draw progress lines, which would overwrite each other,
+    /// but do emit JSON events.
+    pub fn update(&mut self, done: u64, target: Option<f64>) {
+        let elapsed = self.start.elapsed();
+        if !json() {
+            if self.worker.is_none() {
+                print!("\r  Progress: {}/{} {}", done, self.total, self.phase);
+                if let Some(target) = target {
+                    print!(", {:.0} tps (target {:.0})", rate(done, elapsed), target);
+                }
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        let recent = self
+            .last_event
+            .is_some_and(|last| now - last < Duration::from_secs(1));
+        if recent && done < self.total {
+            return;
+        }
+        self.last_event = Some(now);
+
+        let mut event = format!("{{\"event\":\"progress\",\"phase\":\"{}\"", self.phase);
+        if let Some(worker) = self.worker {
+            write!(event, ",\"worker\":{}", worker).unwrap();
+        }
+        write!(
+            event,
+            ",\"done\":{},\"total\":{},\"elapsed_s\":{:.3},\"tps\":{:.1}",
+            done,
+            self.total,
+            elapsed.as_secs_f64(),
+            rate(done, elapsed)
+        )
+        .unwrap();
+        if let Some(target) = target {
+            write!(event, ",\"target_tps\":{:.1}", target).unwrap();
+        }
+        event.push('}');
+        println!("{}", event);
+    }
+
+    /// End the progress line.
+    pub fn finish(&self) {
+        if !json() && self.worker.is_none() {
+            println!();
+        }
+    }
+}
+
+/// Report one finished phase of a scenario.
+pub fn phase(name: &str, tally: &Tally, elapsed: Duration) {
+    if json() {
+        println!(
+            "{{\"event\":\"phase\",\"name\":\"{}\",\"results\":{}}}",
+            name,
+            tally.json(elapsed)
+        );
+    } else {
+        println!(
+            "Phase {}: {} ok, {} failed in {:.1?} ({:.0}/s)",
+            name,
+            tally.created,
+            tally.failed,
+            elapsed,
+            rate(tally.sent(), elapsed)
+        );
+    }
+}
+
+/// Report the results of the run: latency percentiles as a line, or the
+/// whole summary as JSON.
+///
+/// `phases` are `(name, results, wall-clock time)`; `run_id` tags the
+/// generated data, if any.
+pub fn summary(run_id: Option<u64>, phases: &[(&str, &Tally, Duration)]) {
+    if !json() {
+        for (name, tally, _) in phases {
+            if let (Some(p50), Some(p99), Some(max)) = (
+                tally.latency(50.0),
+                tally.latency(99.0),
+                tally.latency(100.0),
+            ) {
+                println!(
+                    "Request latency ({}): p50 {:.1?}, p99 {:.1?}, max {:.1?}",
+                    name, p50, p99, max
+                );
+            }
+            if !tally.failures.is_empty() {
+                println!("Failures ({}): {}", name, tally.breakdown());
+            }
+        }
+        return;
+    }
+
+    let mut event = String::from("{\"event\":\"summary\"");
+    if let Some(run_id) = run_id {
+        write!(event, ",\"run_id\":{}", run_id).unwrap();
+    }
+    for (name, tally, elapsed) in phases {
+        write!(event, ",\"{}\":{}", name, tally.json(*elapsed)).unwrap();
+    }
+    event.push('}');
+    println!("{}", event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_tally_record_and_merge() {
+        let mut tally = Tally::default();
+        tally.record(10, [], ms(5));
+        tally.record(10, ["Exists".to_string(), "Exists".to_string()], ms(7));
+
+        let mut other = Tally::default();
+        other.record(4, ["LinkedEventFailed".to_string()], ms(1));
+        tally.merge(other);
+
+        assert_eq!(tally.created, 21);
+        assert_eq!(tally.failed, 3);
+        assert_eq!(tally.sent(), 24);
+        assert_eq!(tally.failures["Exists"], 2);
+        assert_eq!(tally.breakdown(), "Exists 2, LinkedEventFailed 1");
+        assert_eq!(tally.latency(100.0), Some(ms(7)));
+        assert_eq!(tally.concurrency(ms(13)), 1.0);
+        assert_eq!(tally.concurrency(Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<Duration> = (1..=100).map(ms).collect();
+        assert_eq!(percentile(&sorted, 50.0), Some(ms(50)));
+        assert_eq!(percentile(&sorted, 99.0), Some(ms(99)));
+        assert_eq!(percentile(&sorted, 100.0), Some(ms(100)));
+        assert_eq!(percentile(&sorted, 0.0), Some(ms(1)));
+        assert_eq!(percentile(&[ms(3)], 99.9), Some(ms(3)));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_tally_json() {
+        let mut tally = Tally::default();
+        tally.record(4, ["Exists".to_string()], ms(2));
+        assert_eq!(
+            tally.json(Duration::from_secs(2)),
+            "{\"created\":3,\"failed\":1,\"failures\":{\"Exists\":1},\"elapsed_s\":2.000,\
+             \"tps\":2.0,\"concurrency\":0.00,\"latency_ms\":{\"p50\":2.000,\"p90\":2.000,\"p99\":2.000,\
+             \"p999\":2.000,\"max\":2.000}}"
+        );
+        assert_eq!(
+            Tally::default().json(Duration::ZERO),
+            "{\"created\":0,\"failed\":0,\"failures\":{},\"elapsed_s\":0.000,\"tps\":0.0,\"concurrency\":0.00}"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Resending failed transfers for `--retry`.
+//!
+//! Some failures can clear: an account may be created or funded by another
+//! sender, so a transfer that failed with one of [`RETRYABLE`] may succeed
+//! later. After each batch, the transfers that failed that way are resent,
+//! up to `--retry` more times; any other code, such as `Exists` or
+//! `ExistsWithDifferentAmount`, would come back the same way every time.
+//! The codes a batch reports are those of each transfer's last attempt.
+//!
+//! The cluster remembers the ID of a failed transfer and rejects it again
+//! with `IdAlreadyFailed`, so a retry goes out under a fresh random ID (and
+//! so differs from `--out-transfers`). A linked chain is resent whole, and
+//! only when the transfer that broke it failed with a retryable code.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use tb_rs::{Client, CreateTransferResult, CreateTransfersResult, Transfer, TransferFlags};
+
+use crate::{capture, chaos};
+
+/// Codes a later attempt may not repeat.
+pub const RETRYABLE: [CreateTransferResult; 5] = [
+    CreateTransferResult::ExceedsCredits,
+    CreateTransferResult::ExceedsDebits,
+    CreateTransferResult::DebitAccountNotFound,
+    CreateTransferResult::CreditAccountNotFound,
+    CreateTransferResult::PendingTransferNotFound,
+];
+
+static ATTEMPTS: OnceLock<u32> = OnceLock::new();
+static RESENT: AtomicU64 = AtomicU64::new(0);
+static RECOVERED: AtomicU64 = AtomicU64::new(0);
+
+/// Resend retryable failures up to `attempts` times for the rest of the run.
+pub fn init(attempts: u32) {
+    ATTEMPTS
+        .set(attempts)
+        .expect("retries are configured once per run");
+}
+
+/// Create `transfers` as [`chaos::create_transfers`] does, resending
+/// retryable failures, and capture every request as sent by `sender`.
+///
+/// Result indices are into `transfers`.
+pub async fn create_transfers(
+    client: &mut Client,
+    transfers: &[Transfer],
+    sender: u32,
+) -> Result<Vec<CreateTransfersResult>, Box<dyn Error>> {
+    let sent_at = Instant::now();
+    let mut results = chaos::create_transfers(client, transfers).await?;
+    capture::transfers(sender, sent_at, transfers, &results)?;
+
+    for _ in 0..ATTEMPTS.get().copied().unwrap_or(0) {
+        let retry = retryable(transfers, &results);
+        if retry.is_empty() {
+            break;
+        }
+        let batch = fresh(transfers, &retry);
+        let sent_at = Instant::now();
+        let replies = chaos::create_transfers(client, &batch).await?;
+        capture::transfers(sender, sent_at, &batch, &replies)?;
+        RESENT.fetch_add(batch.len() as u64, Ordering::Relaxed);
+        RECOVERED.fetch_add((batch.len() - replies.len()) as u64, Ordering::Relaxed);
+
+        results.retain(|r| retry.binary_search(&(r.index as usize)).is_err());
+        results.extend(replies.iter().map(|r| CreateTransfersResult {
+            index: retry[r.index as usize] as u32,
+            result: r.result,
+        }));
+        results.sort_unstable_by_key(|r| r.index);
+    }
+    Ok(results)
+}
+
+/// Indices, ascending, of the failed transfers worth resending: whole
+/// chains whose failures are all retryable or caused by a retryable one.
+fn retryable(transfers: &[Transfer], results: &[CreateTransfersResult]) -> Vec<usize> {
+    let mut codes = vec![CreateTransferResult::Ok; transfers.len()];
+    for result in results {
+        codes[result.index as usize] = result.result;
+    }
+
+    let mut retry = Vec::new();
+    let mut start = 0;
+    for (i, transfer) in transfers.iter().enumerate() {
+        if transfer.flags.contains(TransferFlags::LINKED) && i + 1 < transfers.len() {
+            continue;
+        }
+        let chain = &codes[start..=i];
+        let failed = chain.iter().any(|&c| c != CreateTransferResult::Ok);
+        let cleared = chain
+            .iter()
+            .all(|&c| c == CreateTransferResult::LinkedEventFailed || RETRYABLE.contains(&c));
+        if failed && cleared && chain.iter().any(|c| RETRYABLE.contains(c)) {
+            retry.extend(start..=i);
+        }
+        start = i + 1;
+    }
+    retry
+}
+
+/// Copies of the transfers at `indices` under fresh IDs, with pending IDs
+/// that pointed at a resent transfer following it.
+fn fresh(transfers: &[Transfer], indices: &[usize]) -> Vec<Transfer> {
+    let mut batch: Vec<Transfer> = indices.iter().map(|&i| transfers[i]).collect();
+    let renamed: Vec<(u128, u128)> = batch
+        .iter_mut()
+        .map(|transfer| {
+            let old = transfer.id;
+            transfer.id = tb_rs::id();
+            (old, transfer.id)
+        })
+        .collect();
+    for transfer in &mut batch {
+        if let Some(&(_, new)) = renamed.iter().find(|&&(old, _)| old == transfer.pending_id) {
+            transfer.pending_id = new;
+        }
+    }
+    batch
+}
+
+/// Report the transfers resent, if retries were configured.
+pub fn report() {
+    if ATTEMPTS.get().is_none_or(|&attempts| attempts == 0) {
+        return;
+    }
+    say!(
+        "Retries: {} transfers resent, {} created on a later attempt",
+        RESENT.load(Ordering::Relaxed),
+        RECOVERED.load(Ordering::Relaxed)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(id: u128, linked: bool) -> Transfer {
+        Transfer {
+            id,
+            flags: if linked {
+                TransferFlags::LINKED
+            } else {
+                TransferFlags::empty()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn failed(index: u32, result: CreateTransferResult) -> CreateTransfersResult {
+        CreateTransfersResult { index, result }
+    }
+
+    #[test]
+    fn test_retryable() {
+        use CreateTransferResult::*;
+        let transfers = [
+            transfer(1, false),
+            transfer(2, true),
+            transfer(3, false),
+            transfer(4, false),
+            transfer(5, true),
+            transfer(6, false),
+            transfer(7, false),
+        ];
+        let results = [
+            failed(0, ExceedsCredits),
+            // A chain broken by a retryable failure.
+            failed(1, LinkedEventFailed),
+            failed(2, DebitAccountNotFound),
+            failed(3, ExistsWithDifferentAmount),
+            // A chain broken by a final one.
+            failed(4, AccountsMustBeDifferent),
+            failed(5, LinkedEventFailed),
+        ];
+        assert_eq!(retryable(&transfers, &results), vec![0, 1, 2]);
+        assert!(retryable(&transfers, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_fresh() {
+        let mut post = transfer(2, false);
+        post.pending_id = 1;
+        let mut other = transfer(3, false);
+        other.pending_id = 9;
+        let batch = fresh(&[transfer(1, true), post, other], &[0, 1, 2]);
+        assert!(batch.iter().all(|t| t.id > 3));
+        assert_eq!(batch[1].pending_id, batch[0].id);
+        assert_eq!(batch[2].pending_id, 9);
+        assert!(batch[0].flags.contains(TransferFlags::LINKED));
+    }
+}
@@ -0,0 +1,570 @@
+//! Multi-phase scenarios for `--scenario`.
+//!
+//! A scenario file lists phases that run in order against one cluster, each
+//! followed by its own report, so a whole acceptance test is one command:
+//!
+//! ```toml
+//! [[phase]]
+//! name = "seed"
+//! action = "accounts"
+//! count = 1000000
+//!
+//! [[phase]]
+//! name = "ramp"
+//! action = "transfers"
+//! ramp = "0:1000,5m:20000"
+//!
+//! [[phase]]
+//! action = "close"
+//! ratio = 0.1
+//!
+//! [[phase]]
+//! action = "verify"
+//! ```
+//!
+//! Actions:
+//!
+//! | Action      | Keys                                          |
+//! |-------------|-----------------------------------------------|
+//! | `accounts`  | `count`, `ledger` (default `--ledger`)        |
+//! | `transfers` | `count`, `tps`, `ramp`, `step`, `duration`    |
+//! | `close`     | `ratio` of the open accounts to close         |
+//! | `verify`    | none                                          |
+//!
+//! Transfers move between the open accounts created so far, shaped by the
+//! dataset flags (`--distribution`, `--max-amount`, `--linked-ratio`, ...).
+//! Closing uses a zero-amount pending transfer with `CLOSING_DEBIT`; closed
+//! accounts take no further transfers. Verification looks every account up
+//! and checks that it exists, is closed exactly when it should be, and that
+//! each ledger's debits equal its credits. A failed check fails the run.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+use tb_rs::{Account, AccountFlags, Transfer, TransferFlags};
+use toml::{Table, Value};
+
+use crate::ledger::Ledger;
+use crate::linked::Linking;
+use crate::pace::{self, Pacer};
+use crate::report::{self, Tally};
+use crate::seed::Source;
+use crate::{
+    check_shape, connect, flag_history, generate_accounts, print_run_id, run_id, run_paced,
+    send_accounts, send_transfers, start_metrics, tag_accounts, Args, TransferPlan,
+};
+
+/// One step of a scenario.
+#[derive(Debug)]
+pub struct Phase {
+    pub name: String,
+    pub action: Action,
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Accounts { count: u32, ledger: Option<u32> },
+    Transfers { count: u64, pacer: Option<Pacer> },
+    Close { ratio: f64 },
+    Verify,
+}
+
+/// Read a scenario file.
+pub fn read(path: &Path) -> Result<Vec<Phase>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Parse a scenario.
+pub fn parse(text: &str) -> Result<Vec<Phase>, String> {
+    let table: Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+    if let Some(key) = table.keys().find(|k| *k != "phase") {
+        return Err(format!("unknown key '{}' (phases go in [[phase]])", key));
+    }
+    let Some(Value::Array(entries)) = table.get("phase") else {
+        return Err("no [[phase]] entries".to_string());
+    };
+
+    let mut phases: Vec<Phase> = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let Value::Table(entry) = entry else {
+            return Err(format!("phase {} is not a table", i + 1));
+        };
+        let phase = parse_phase(entry).map_err(|e| format!("phase {}: {}", i + 1, e))?;
+        if phases.iter().any(|p| p.name == phase.name) {
+            return Err(format!(
+                "phase {}: name '{}' is used twice; give each phase a name",
+                i + 1,
+                phase.name
+            ));
+        }
+        phases.push(phase);
+    }
+    Ok(phases)
+}
+
+fn parse_phase(entry: &Table) -> Result<Phase, String> {
+    let action = string(entry, "action")?.ok_or("missing 'action'")?;
+    let keys: &[&str] = match action.as_str() {
+        "accounts" => &["count", "ledger"],
+        "transfers" => &["count", "tps", "ramp", "step", "duration"],
+        "close" => &["ratio"],
+        "verify" => &[],
+        _ => {
+            return Err(format!(
+                "unknown action '{}' (use accounts, transfers, close or verify)",
+                action
+            ))
+        }
+    };
+    if let Some(key) = entry
+        .keys()
+        .find(|k| !["name", "action"].contains(&k.as_str()) && !keys.contains(&k.as_str()))
+    {
+        return Err(format!("'{}' doesn't apply to {}", key, action));
+    }
+
+    let name = string(entry, "name")?.unwrap_or_else(|| action.clone());
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || " -_.".contains(c))
+    {
+        return Err(format!(
+            "invalid name '{}' (use letters, digits, spaces, '-', '_' or '.')",
+            name
+        ));
+    }
+
+    let action = match action.as_str() {
+        "accounts" => Action::Accounts {
+            count: integer(entry, "count")?.ok_or("missing 'count'")?,
+            ledger: match integer(entry, "ledger")? {
+                Some(0) => return Err("ledger 0 is reserved".to_string()),
+                ledger => ledger,
+            },
+        },
+        "transfers" => {
+            let pacer = match (string(entry, "ramp")?, integer::<u64>(entry, "tps")?) {
+                (Some(_), Some(_)) => return Err("use either 'tps' or 'ramp'".to_string()),
+                (Some(points), None) => {
+                    let step = matches!(entry.get("step"), Some(Value::Boolean(true)));
+                    Some(Pacer::profile(pace::parse_profile(&points)?, step)?)
+                }
+                (None, Some(0)) => return Err("'tps' must be positive".to_string()),
+                (None, Some(tps)) => Some(Pacer::new(tps)),
+                (None, None) => None,
+            };
+            let duration = string(entry, "duration")?
+                .map(|d| pace::parse_duration(&d))
+                .transpose()?;
+            let count = match (&pacer, duration, integer(entry, "count")?) {
+                (None, Some(_), _) => return Err("'duration' needs 'tps' or 'ramp'".to_string()),
+                (Some(pacer), Some(duration), _) => pacer.due(duration),
+                (_, None, Some(count)) => count,
+                (Some(pacer), None, None) => pacer.due(pacer.end()),
+                (None, None, None) => return Err("missing 'count'".to_string()),
+            };
+            Action::Transfers { count, pacer }
+        }
+        "close" => {
+            let ratio = match entry.get("ratio") {
+                Some(Value::Float(r)) => *r,
+                Some(Value::Integer(r)) => *r as f64,
+                Some(_) => return Err("'ratio' must be a number".to_string()),
+                None => return Err("missing 'ratio'".to_string()),
+            };
+            if !(ratio > 0.0 && ratio <= 1.0) {
+                return Err("'ratio' must be above 0.0 and at most 1.0".to_string());
+            }
+            Action::Close { ratio }
+        }
+        _ => Action::Verify,
+    };
+    Ok(Phase { name, action })
+}
+
+fn string(entry: &Table, key: &str) -> Result<Option<String>, String> {
+    match entry.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("'{}' must be a string", key)),
+    }
+}
+
+fn integer<T: TryFrom<i64>>(entry: &Table, key: &str) -> Result<Option<T>, String> {
+    match entry.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(n)) => T::try_from(*n)
+            .map(Some)
+            .map_err(|_| format!("'{}' is out of range", key)),
+        Some(_) => Err(format!("'{}' must be a whole number", key)),
+    }
+}
+
+impl Phase {
+    fn describe(&self) -> String {
+        match &self.action {
+            Action::Accounts { count, ledger } => match ledger {
+                Some(ledger) => format!("create {} accounts in ledger {}", count, ledger),
+                None => format!("create {} accounts", count),
+            },
+            Action::Transfers { count, pacer } => match pacer {
+                Some(pacer) => format!("send {} transfers at {}", count, pacer),
+                None => format!("send {} transfers", count),
+            },
+            Action::Close { ratio } => {
+                let percent = (ratio * 1000.0).round() / 10.0;
+                format!("close {}% of open accounts", percent)
+            }
+            Action::Verify => "verify accounts and ledger balances".to_string(),
+        }
+    }
+}
+
+/// What earlier phases left behind.
+struct State {
+    run_id: u64,
+    source: Source,
+    accounts: Vec<Account>,
+    closed: HashSet<u128>,
+}
+
+impl State {
+    /// Transfer endpoints: the open accounts of each ledger.
+    fn ledgers(&self, args: &Args) -> Vec<Ledger> {
+        let mut by_ledger: BTreeMap<u32, Vec<u128>> = BTreeMap::new();
+        for account in &self.accounts {
+            if !self.closed.contains(&account.id) {
+                by_ledger
+                    .entry(account.ledger)
+                    .or_default()
+                    .push(account.id);
+            }
+        }
+        by_ledger
+            .into_iter()
+            .filter(|(_, ids)| ids.len() >= 2)
+            .map(|(ledger, ids)| Ledger::new(ledger, ids, args.distribution, args.skew))
+            .collect()
+    }
+}
+
+/// Run the scenario at `path`.
+pub async fn run(args: &Args, path: &Path) -> Result<(), Box<dyn Error>> {
+    say!("TigerBeetle Test Scenario");
+    say!("=========================");
+    say!("Address: {}", args.addresses());
+    say!("Cluster: {}", args.cluster);
+    say!("Scenario: {}", path.display());
+    let phases = read(path)?;
+    for (i, phase) in phases.iter().enumerate() {
+        say!("  {}. {}: {}", i + 1, phase.name, phase.describe());
+    }
+
+    check_shape(args)?;
+
+    if args.dry_run {
+        say!();
+        say!("Dry run mode - not sending to server");
+        return Ok(());
+    }
+
+    let (mut client, batch_size) = connect(args).await?;
+    let pusher = start_metrics(args)?;
+    let run_id = run_id(args)?;
+    say!("Run ID: {}", run_id);
+    let mut state = State {
+        run_id,
+        source: Source::new(args.seed),
+        accounts: Vec::new(),
+        closed: HashSet::new(),
+    };
+    let mut results: Vec<(Tally, Duration)> = Vec::with_capacity(phases.len());
+
+    for (i, phase) in phases.iter().enumerate() {
+        say!();
+        say!(
+            "Phase {}/{} ({}): {}",
+            i + 1,
+            phases.len(),
+            phase.name,
+            phase.describe()
+        );
+        let start = Instant::now();
+        let tally = match &phase.action {
+            Action::Accounts { count, ledger } => {
+                let ledger = ledger.unwrap_or(args.ledger);
+                let mut accounts = generate_accounts(*count, ledger, args.code, &mut state.source);
+                tag_accounts(&mut accounts, run_id);
+                flag_history(&mut accounts, args.history_ratio);
+                let tally = send_accounts(&mut client, &accounts, batch_size).await?;
+                state.accounts.extend(accounts);
+                tally
+            }
+            Action::Transfers { count, pacer } => {
+                let plan = TransferPlan {
+                    ledgers: state.ledgers(args),
+                    code: args.code,
+                    max_amount: args.max_amount,
+                    linking: Linking {
+                        chain_len: args.linked_chain_len as usize,
+                        ratio: args.linked_ratio,
+                        poison: args.poison_chains,
+                    },
+                    limits: None,
+                    run_id,
+                };
+                if plan.ledgers.is_empty() {
+                    return Err(format!(
+                        "phase {}: no ledger has 2 open accounts to transfer between",
+                        phase.name
+                    )
+                    .into());
+                }
+                let source = &mut state.source;
+                match pacer {
+                    Some(pacer) => {
+                        let generate = |count| Ok(plan.generate(count, source));
+                        run_paced(&mut client, pacer, *count, batch_size, None, None, generate)
+                            .await?
+                    }
+                    None => {
+                        let count = u32::try_from(*count)
+                            .map_err(|_| format!("phase {}: too many transfers", phase.name))?;
+                        let transfers = plan.generate(count, source);
+                        send_transfers(&mut client, &transfers, batch_size, None).await?
+                    }
+                }
+            }
+            Action::Close { ratio } => {
+                close(&mut client, &mut state, *ratio, args, batch_size).await?
+            }
+            Action::Verify => verify(&mut client, &state, batch_size).await?,
+        };
+        let elapsed = start.elapsed();
+        report::phase(&phase.name, &tally, elapsed);
+        results.push((tally, elapsed));
+    }
+
+    client.close().await;
+    if let Some(pusher) = pusher {
+        pusher.finish();
+    }
+
+    let summary: Vec<(&str, &Tally, Duration)> = phases
+        .iter()
+        .zip(&results)
+        .map(|(phase, (tally, elapsed))| (phase.name.as_str(), tally, *elapsed))
+        .collect();
+    report::summary(Some(run_id), &summary);
+
+    let failed_checks: u64 = phases
+        .iter()
+        .zip(&results)
+        .filter(|(phase, _)| matches!(phase.action, Action::Verify))
+        .map(|(_, (tally, _))| tally.failed)
+        .sum();
+    if failed_checks > 0 {
+        return Err(format!("verification failed: {} checks failed", failed_checks).into());
+    }
+
+    say!();
+    print_run_id(run_id);
+    say!("Done!");
+    Ok(())
+}
+
+/// Close `ratio` of the open accounts with closing transfers to other open
+/// accounts of the same ledger.
+async fn close(
+    client: &mut tb_rs::Client,
+    state: &mut State,
+    ratio: f64,
+    args: &Args,
+    batch_size: u32,
+) -> Result<Tally, Box<dyn Error>> {
+    let mut transfers = Vec::new();
+    for ledger in state.ledgers(args) {
+        let mut ids = ledger.account_ids;
+        ids.shuffle(&mut state.source.rng);
+        // Keep at least one account open in each ledger to credit.
+        let count = ((ids.len() as f64 * ratio).round() as usize).min(ids.len() - 1);
+        let (closing, open) = ids.split_at(count);
+        for (i, &debit_account_id) in closing.iter().enumerate() {
+            transfers.push(Transfer {
+                id: state.source.id(),
+                debit_account_id,
+                credit_account_id: open[i % open.len()],
+                amount: 0,
+                user_data_64: state.run_id,
+                ledger: ledger.id,
+                code: args.code,
+                flags: TransferFlags::PENDING | TransferFlags::CLOSING_DEBIT,
+                ..Default::default()
+            });
+        }
+    }
+
+    let tally = send_transfers(client, &transfers, batch_size, None).await?;
+    // Whether or not they closed, verify expects them to.
+    state
+        .closed
+        .extend(transfers.iter().map(|t| t.debit_account_id));
+    say!(
+        "Closed accounts: {} of {}",
+        state.closed.len(),
+        state.accounts.len()
+    );
+    Ok(tally)
+}
+
+/// Look every account up and check it, then check each ledger's balance.
+///
+/// Each account and each ledger is one check in the tally.
+async fn verify(
+    client: &mut tb_rs::Client,
+    state: &State,
+    batch_size: u32,
+) -> Result<Tally, Box<dyn Error>> {
+    let mut tally = Tally::default();
+    let mut ledgers: BTreeMap<u32, [u128; 4]> = BTreeMap::new();
+
+    for chunk in state.accounts.chunks(batch_size as usize) {
+        let ids: Vec<u128> = chunk.iter().map(|a| a.id).collect();
+        let sent_at = Instant::now();
+        let found = client.lookup_accounts(&ids).await?;
+        let latency = sent_at.elapsed();
+
+        let mut codes = Vec::new();
+        for id in &ids {
+            let Some(account) = found.iter().find(|a| a.id == *id) else {
+                eprintln!("  Account {:032x} is missing", id);
+                codes.push("Missing".to_string());
+                continue;
+            };
+            let closed = account.flags.contains(AccountFlags::CLOSED);
+            if closed != state.closed.contains(id) {
+                eprintln!(
+                    "  Account {:032x} is {}",
+                    id,
+                    if closed { "closed" } else { "still open" }
+                );
+                codes.push(
+                    if closed {
+                        "UnexpectedlyClosed"
+                    } else {
+                        "NotClosed"
+                    }
+                    .to_string(),
+                );
+            }
+            let sums = ledgers.entry(account.ledger).or_default();
+            sums[0] += account.debits_posted;
+            sums[1] += account.credits_posted;
+            sums[2] += account.debits_pending;
+            sums[3] += account.credits_pending;
+        }
+        tally.record(ids.len(), codes, latency);
+    }
+
+    for (ledger, [debits, credits, debits_pending, credits_pending]) in &ledgers {
+        let balanced = debits == credits && debits_pending == credits_pending;
+        if !balanced {
+            eprintln!(
+                "  Ledger {} is unbalanced: debits {}+{} pending, credits {}+{} pending",
+                ledger, debits, debits_pending, credits, credits_pending
+            );
+        }
+        let codes = (!balanced).then(|| "Unbalanced".to_string());
+        tally.record(1, codes, Duration::ZERO);
+    }
+
+    say!(
+        "Checks: {} passed, {} failed ({} accounts, {} ledgers)",
+        tally.created,
+        tally.failed,
+        state.accounts.len(),
+        ledgers.len()
+    );
+    Ok(tally)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scenario() {
+        let phases = parse(
+            r#"
+            [[phase]]
+            name = "seed"
+            action = "accounts"
+            count = 1000
+
+            [[phase]]
+            action = "transfers"
+            tps = 100
+            duration = "10s"
+
+            [[phase]]
+            name = "ramp"
+            action = "transfers"
+            ramp = "0:0,10s:1000"
+
+            [[phase]]
+            action = "close"
+            ratio = 0.1
+
+            [[phase]]
+            action = "verify"
+            "#,
+        )
+        .unwrap();
+
+        let names: Vec<&str> = phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["seed", "transfers", "ramp", "close", "verify"]);
+        assert!(matches!(
+            phases[0].action,
+            Action::Accounts {
+                count: 1000,
+                ledger: None
+            }
+        ));
+        assert!(matches!(
+            phases[1].action,
+            Action::Transfers { count: 1000, .. }
+        ));
+        assert!(matches!(
+            phases[2].action,
+            Action::Transfers { count: 5000, .. }
+        ));
+        assert!(matches!(phases[3].action, Action::Close { ratio } if ratio == 0.1));
+        assert!(matches!(phases[4].action, Action::Verify));
+    }
+
+    #[test]
+    fn test_parse_scenario_errors() {
+        let phase = |body: &str| parse(&format!("[[phase]]\n{}", body));
+        assert!(parse("").is_err());
+        assert!(parse("accounts = 10").is_err());
+        assert!(phase("count = 10").is_err());
+        assert!(phase("action = \"explode\"").is_err());
+        assert!(phase("action = \"accounts\"").is_err());
+        assert!(phase("action = \"accounts\"\ncount = -1").is_err());
+        assert!(phase("action = \"accounts\"\ncount = 1\nratio = 0.5").is_err());
+        assert!(phase("action = \"transfers\"").is_err());
+        assert!(phase("action = \"transfers\"\ncount = 5\nduration = \"1m\"").is_err());
+        assert!(phase("action = \"transfers\"\ntps = 5\nramp = \"0:5\"").is_err());
+        assert!(phase("action = \"close\"\nratio = 1.5").is_err());
+        assert!(phase("action = \"verify\"\nname = \"say \\\"hi\\\"\"").is_err());
+        assert!(parse("[[phase]]\naction = \"verify\"\n[[phase]]\naction = \"verify\"").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! Stability checks for `--soak`.
+//!
+//! A soak run holds a paced rate for a long `--duration` and judges it in
+//! windows of `--soak-window`. The first window sets the baseline p99 request
+//! latency; a later window fails the run if its p99 grows past
+//! `--max-latency-growth` times the baseline, or if more than
+//! `--max-error-rate` of its transfers fail. Every window is reported as it
+//! closes, and a breach stops the run with a non-zero exit.
+//!
+//! The baseline assumes a steady load: with a ramp, latency is expected to
+//! grow with the rate.
+
+use std::time::{Duration, Instant};
+
+use crate::report;
+
+/// When a soak run counts as unstable.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+    pub window: Duration,
+    /// Largest fraction of a window's transfers that may fail.
+    pub max_error_rate: f64,
+    /// Largest allowed ratio of a window's p99 latency to the baseline's.
+    pub max_latency_growth: f64,
+}
+
+/// Watches one sender's requests window by window.
+pub struct Monitor {
+    thresholds: Thresholds,
+    label: String,
+    window_start: Instant,
+    windows: u32,
+    sent: u64,
+    failed: u64,
+    latencies: Vec<Duration>,
+    baseline: Option<Duration>,
+}
+
+impl Monitor {
+    /// Start watching; `label` prefixes the window reports.
+    pub fn new(thresholds: Thresholds, label: String) -> Self {
+        Monitor {
+            thresholds,
+            label,
+            window_start: Instant::now(),
+            windows: 0,
+            sent: 0,
+            failed: 0,
+            latencies: Vec::new(),
+            baseline: None,
+        }
+    }
+
+    /// Record a request, judging the window if it is over.
+    pub fn record(&mut self, sent: u64, failed: u64, latency: Duration) -> Result<(), String> {
+        self.sent += sent;
+        self.failed += failed;
+        self.latencies.push(latency);
+        if self.window_start.elapsed() < self.thresholds.window {
+            return Ok(());
+        }
+
+        self.latencies.sort_unstable();
+        let p99 = report::percentile(&self.latencies, 99.0);
+        let (sent, failed) = (self.sent, self.failed);
+        self.window_start = Instant::now();
+        self.sent = 0;
+        self.failed = 0;
+        self.latencies.clear();
+        self.judge(sent, failed, p99)
+    }
+
+    /// Report a finished window and check it against the thresholds.
+    fn judge(&mut self, sent: u64, failed: u64, p99: Option<Duration>) -> Result<(), String> {
+        let (Some(p99), true) = (p99, sent > 0) else {
+            return Ok(());
+        };
+        self.windows += 1;
+        let error_rate = failed as f64 / sent as f64;
+        let baseline = *self.baseline.get_or_insert(p99);
+        let growth = p99.as_secs_f64() / baseline.as_secs_f64().max(f64::EPSILON);
+        say!(
+            "{}Soak window {}: {} sent, {:.2}% failed, p99 {:.1?} ({:.2}x baseline)",
+            self.label,
+            self.windows,
+            sent,
+            error_rate * 100.0,
+            p99,
+            growth
+        );
+
+        if error_rate > self.thresholds.max_error_rate {
+            return Err(format!(
+                "soak window {}: {:.2}% of transfers failed (limit {:.2}%)",
+                self.windows,
+                error_rate * 100.0,
+                self.thresholds.max_error_rate * 100.0
+            ));
+        }
+        if growth > self.thresholds.max_latency_growth {
+            return Err(format!(
+                "soak window {}: p99 latency {:.1?} is {:.2}x the baseline {:.1?} (limit {}x)",
+                self.windows, p99, growth, baseline, self.thresholds.max_latency_growth
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> Monitor {
+        let thresholds = Thresholds {
+            window: Duration::from_secs(60),
+            max_error_rate: 0.01,
+            max_latency_growth: 2.0,
+        };
+        Monitor::new(thresholds, String::new())
+    }
+
+    fn ms(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn test_judge_latency_growth() {
+        let mut monitor = monitor();
+        assert!(monitor.judge(1000, 0, ms(10)).is_ok());
+        assert_eq!(monitor.baseline, ms(10));
+        assert!(monitor.judge(1000, 0, ms(20)).is_ok());
+        assert!(monitor.judge(1000, 0, ms(21)).is_err());
+    }
+
+    #[test]
+    fn test_judge_error_rate() {
+        let mut monitor = monitor();
+        assert!(monitor.judge(1000, 10, ms(10)).is_ok());
+        assert!(monitor.judge(1000, 11, ms(10)).is_err());
+    }
+
+    #[test]
+    fn test_judge_skips_empty_windows() {
+        let mut monitor = monitor();
+        assert!(monitor.judge(0, 0, None).is_ok());
+        assert_eq!(monitor.windows, 0);
+        assert_eq!(monitor.baseline, None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Spreading the transfer load over several clients.
+//!
+//! A client has one request in flight at a time, which is not enough to keep
+//! a multi-replica cluster busy. Each worker is a thread with its own
+//! io_uring runtime and client (clients are `!Send`). Pre-generated transfers
+//! are split between the workers at chain boundaries; paced runs split the
+//! rate, and each worker generates its own share. With `--warmup`, every
+//! worker warms up its own client before any of them is measured, and with
+//! `--inflight` every worker keeps that many batches outstanding.
+
+use std::error::Error;
+use std::sync::Barrier;
+use std::thread;
+use std::time::Instant;
+
+use tb_rs::Transfer;
+
+use crate::pace::Pacer;
+use crate::report::Tally;
+use crate::seed::Source;
+use crate::{connect, run_paced, send_transfers, soak_thresholds, warm_up, Args, TransferPlan};
+use crate::{inflight, linked};
+
+/// Transfers for the workers to send.
+pub struct Workload<'a> {
+    /// Pre-generated transfers.
+    pub transfers: &'a [Transfer],
+    /// Rate for generated transfers, if paced.
+    pub pacer: Option<&'a Pacer>,
+    /// Number of transfers to generate and send at `pacer`'s rate.
+    pub paced: u64,
+    pub plan: &'a TransferPlan,
+}
+
+/// Send `workload` from `args.workers` clients, wait for all of them and
+/// return their combined results and when they started measuring.
+///
+/// Workers connect and warm up first; measurement starts once all are
+/// ready.
+pub fn run(args: &Args, workload: &Workload) -> Result<(Tally, Instant), Box<dyn Error>> {
+    say!();
+    say!("Creating transfers with {} workers...", args.workers);
+    let ready = Barrier::new(args.workers + 1);
+    let mut start = Instant::now();
+
+    let runs = linked::split(workload.transfers, args.workers);
+    let results: Vec<Result<Tally, String>> = thread::scope(|scope| {
+        let handles: Vec<_> = runs
+            .into_iter()
+            .enumerate()
+            .map(|(worker, transfers)| {
+                let ready = &ready;
+                scope.spawn(move || {
+                    tokio_uring::start(run_worker(args, workload, worker, transfers, ready))
+                        .map_err(|e| format!("worker {}: {}", worker, e))
+                })
+            })
+            .collect();
+        ready.wait();
+        start = Instant::now();
+        handles
+            .into_iter()
+            .enumerate()
+            .map(|(worker, handle)| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(format!("worker {} panicked", worker)))
+            })
+            .collect()
+    });
+
+    let mut total = Tally::default();
+    for result in results {
+        total.merge(result?);
+    }
+    let elapsed = start.elapsed();
+    say!(
+        "All workers: {} created, {} failed in {:.1?} ({:.0} tps)",
+        total.created,
+        total.failed,
+        elapsed,
+        total.sent() as f64 / elapsed.as_secs_f64()
+    );
+    if args.inflight > 1 {
+        say!(
+            "In flight: {:.2} of {} batches",
+            total.concurrency(elapsed),
+            args.workers * args.inflight
+        );
+    }
+
+    Ok((total, start))
+}
+
+async fn run_worker(
+    args: &Args,
+    workload: &Workload<'_>,
+    worker: usize,
+    transfers: &[Transfer],
+    ready: &Barrier,
+) -> Result<Tally, Box<dyn Error>> {
+    // Reach the barrier even on failure, or the other workers would wait
+    // forever.
+    let connected = async {
+        let (mut client, batch_size) = connect(args).await?;
+        if let Some(warmup) = args.warmup {
+            warm_up(&mut client, workload.plan, batch_size, warmup, Some(worker)).await?;
+        }
+        Ok::<_, Box<dyn Error>>((client, batch_size))
+    }
+    .await;
+    ready.wait();
+    let (mut client, batch_size) = connected?;
+    let mut tally = if args.inflight > 1 {
+        inflight::send_transfers(args, &mut client, transfers, batch_size, Some(worker)).await?
+    } else {
+        send_transfers(&mut client, transfers, batch_size, Some(worker)).await?
+    };
+
+    if let Some(pacer) = workload.pacer {
+        let pacer = pacer.scaled(1.0 / args.workers as f64);
+        let count = share(workload.paced, args.workers, worker);
+        // Each worker draws its own stream from the seed.
+        let mut source = Source::new(args.seed.map(|s| s.wrapping_add(worker as u64 + 1)));
+        let generate = |count: u32| Ok(workload.plan.generate(count, &mut source));
+        let paced = run_paced(
+            &mut client,
+            &pacer,
+            count,
+            batch_size,
+            Some(worker),
+            soak_thresholds(args).as_ref(),
+            generate,
+        )
+        .await?;
+        tally.merge(paced);
+    }
+
+    client.close().await;
+    Ok(tally)
+}
+
+/// Worker `worker`'s part of `total` split `workers` ways.
+fn share(total: u64, workers: usize, worker: usize) -> u64 {
+    let workers = workers as u64;
+    total / workers + u64::from((worker as u64) < total % workers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_share() {
+        let shares: Vec<u64> = (0..3).map(|w| share(10, 3, w)).collect();
+        assert_eq!(shares, [4, 3, 3]);
+        assert_eq!((0..4).map(|w| share(2, 4, w)).sum::<u64>(), 2);
+        assert_eq!(share(7, 1, 0), 7);
+    }
+}
@@ -0,0 +1,342 @@
+//! Replica address parsing and resolution.
+//!
+//! Addresses follow TigerBeetle's conventions, extended with IPv6 and
+//! hostnames:
+//!
+//! - `3000` - port only, on `127.0.0.1`
+//! - `10.0.0.1` or `10.0.0.1:3000` - IPv4, with optional port
+//! - `::1`, `[::1]` or `[::1]:3000` - IPv6; a port requires brackets
+//! - `db.example.com:3000` - hostname, port required
+//!
+//! Addresses without a port use [`PORT_DEFAULT`]. Hostnames are resolved when
+//! the client is built. A hostname may resolve to both A and AAAA records, so
+//! each replica keeps every resolved address as a connection candidate,
+//! ordered by [`IpPreference`].
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+use crate::error::{ClientError, Result};
+
+/// Port used when an address does not specify one (TigerBeetle's default).
+pub const PORT_DEFAULT: u16 = 3001;
+
+/// Address family preference for hostnames resolving to several addresses.
+///
+/// Candidates are tried in order when connecting, so with a `*First`
+/// preference the other family is still used as a fallback.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IpPreference {
+    /// Try IPv4 addresses before IPv6 addresses.
+    ///
+    /// The default, since replicas commonly listen on IPv4 only.
+    #[default]
+    Ipv4First,
+    /// Try IPv6 addresses before IPv4 addresses.
+    Ipv6First,
+    /// Use IPv4 addresses only.
+    Ipv4Only,
+    /// Use IPv6 addresses only.
+    Ipv6Only,
+}
+
+impl IpPreference {
+    /// Filter and order resolved addresses by this preference.
+    ///
+    /// The sort is stable, so the resolver's order within a family is kept.
+    pub(crate) fn order(self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            IpPreference::Ipv4First => addrs.sort_by_key(|addr| addr.is_ipv6()),
+            IpPreference::Ipv6First => addrs.sort_by_key(|addr| addr.is_ipv4()),
+            IpPreference::Ipv4Only => addrs.retain(|addr| addr.is_ipv4()),
+            IpPreference::Ipv6Only => addrs.retain(|addr| addr.is_ipv6()),
+        }
+        addrs
+    }
+}
+
+/// Count the replicas in a comma-separated address list, checking each
+/// address as [`ClientBuilder::addresses`](crate::ClientBuilder::addresses)
+/// does.
+pub fn replica_count(input: &str) -> Result<usize> {
+    ReplicaAddress::parse_list(input).map(|list| list.len())
+}
+
+/// A replica address as given by the user, before resolution.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum ReplicaAddress {
+    /// A literal IP address and port.
+    Ip(SocketAddr),
+    /// A hostname to resolve at build time.
+    Host { host: String, port: u16 },
+}
+
+impl ReplicaAddress {
+    /// Parse a single replica address.
+    pub(crate) fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(invalid(input, "empty address"));
+        }
+
+        if let Ok(port) = input.parse::<u16>() {
+            return Ok(ReplicaAddress::Ip(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port,
+            )));
+        }
+        // Covers `ipv4:port` and `[ipv6]:port`.
+        if let Ok(addr) = input.parse::<SocketAddr>() {
+            return Ok(ReplicaAddress::Ip(addr));
+        }
+        // Covers bare `ipv4` and unbracketed `ipv6`.
+        if let Ok(ip) = input.parse::<IpAddr>() {
+            return Ok(ReplicaAddress::Ip(SocketAddr::new(ip, PORT_DEFAULT)));
+        }
+        if let Some(rest) = input.strip_prefix('[') {
+            let ip = rest
+                .strip_suffix(']')
+                .and_then(|inner| inner.parse::<Ipv6Addr>().ok())
+                .ok_or_else(|| invalid(input, "invalid bracketed IPv6 address"))?;
+            return Ok(ReplicaAddress::Ip(SocketAddr::new(
+                IpAddr::V6(ip),
+                PORT_DEFAULT,
+            )));
+        }
+
+        let (host, port) = input
+            .rsplit_once(':')
+            .ok_or_else(|| invalid(input, "hostname requires a port"))?;
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| invalid(input, "invalid port"))?;
+        if !is_valid_hostname(host) {
+            return Err(invalid(input, "invalid hostname"));
+        }
+
+        Ok(ReplicaAddress::Host {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Parse a comma-separated list of replica addresses.
+    pub(crate) fn parse_list(input: &str) -> Result<Vec<Self>> {
+        if input.trim().is_empty() {
+            return Err(ClientError::Connection("no addresses provided".into()));
+        }
+        input.split(',').map(Self::parse).collect()
+    }
+
+    /// Resolve to connection candidates, ordered by `preference`.
+    ///
+    /// Hostname resolution uses the system resolver and blocks the thread.
+    pub(crate) fn resolve(&self, preference: IpPreference) -> Result<Vec<SocketAddr>> {
+        let mut addrs: Vec<SocketAddr> = match self {
+            ReplicaAddress::Ip(addr) => vec![*addr],
+            ReplicaAddress::Host { host, port } => (host.as_str(), *port)
+                .to_socket_addrs()
+                .map_err(|e| {
+                    ClientError::Connection(format!("failed to resolve '{}': {}", self, e))
+                })?
+                .collect(),
+        };
+        // Resolvers may return the same address once per socket type.
+        let mut unique = Vec::with_capacity(addrs.len());
+        for addr in addrs.drain(..) {
+            if !unique.contains(&addr) {
+                unique.push(addr);
+            }
+        }
+
+        let candidates = preference.order(unique);
+        if candidates.is_empty() {
+            return Err(ClientError::Connection(format!(
+                "no addresses for '{}' match {:?}",
+                self, preference
+            )));
+        }
+        Ok(candidates)
+    }
+}
+
+impl From<SocketAddr> for ReplicaAddress {
+    fn from(addr: SocketAddr) -> Self {
+        ReplicaAddress::Ip(addr)
+    }
+}
+
+impl fmt::Display for ReplicaAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicaAddress::Ip(addr) => write!(f, "{}", addr),
+            ReplicaAddress::Host { host, port } => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+fn invalid(input: &str, reason: &str) -> ClientError {
+    ClientError::Connection(format!("invalid address '{}': {}", input, reason))
+}
+
+/// Check hostname syntax (RFC 1123 labels).
+fn is_valid_hostname(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> ReplicaAddress {
+        ReplicaAddress::Ip(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_parse_port_only() {
+        assert_eq!(ReplicaAddress::parse("3000").unwrap(), ip("127.0.0.1:3000"));
+    }
+
+    #[test]
+    fn test_parse_ipv4() {
+        assert_eq!(
+            ReplicaAddress::parse("10.0.0.1:3000").unwrap(),
+            ip("10.0.0.1:3000")
+        );
+        assert_eq!(
+            ReplicaAddress::parse(" 10.0.0.1 ").unwrap(),
+            ip("10.0.0.1:3001")
+        );
+    }
+
+    #[test]
+    fn test_parse_ipv6() {
+        assert_eq!(
+            ReplicaAddress::parse("[::1]:3000").unwrap(),
+            ip("[::1]:3000")
+        );
+        assert_eq!(ReplicaAddress::parse("[::1]").unwrap(), ip("[::1]:3001"));
+        assert_eq!(ReplicaAddress::parse("::1").unwrap(), ip("[::1]:3001"));
+        assert_eq!(
+            ReplicaAddress::parse("[2001:db8::7]:3002").unwrap(),
+            ip("[2001:db8::7]:3002")
+        );
+    }
+
+    #[test]
+    fn test_parse_ipv6_invalid() {
+        assert!(ReplicaAddress::parse("[::1").is_err());
+        assert!(ReplicaAddress::parse("[::1]:").is_err());
+        assert!(ReplicaAddress::parse("[::1]:70000").is_err());
+        assert!(ReplicaAddress::parse("[10.0.0.1]:3000").is_err());
+        assert!(ReplicaAddress::parse("[not::an::ip]").is_err());
+    }
+
+    #[test]
+    fn test_parse_hostname() {
+        assert_eq!(
+            ReplicaAddress::parse("db-1.example.com:3000").unwrap(),
+            ReplicaAddress::Host {
+                host: "db-1.example.com".into(),
+                port: 3000
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_hostname_invalid() {
+        assert!(ReplicaAddress::parse("").is_err());
+        assert!(ReplicaAddress::parse("not-an-address").is_err());
+        assert!(ReplicaAddress::parse("host:port").is_err());
+        assert!(ReplicaAddress::parse("-host:3000").is_err());
+        assert!(ReplicaAddress::parse("a..b:3000").is_err());
+        assert!(ReplicaAddress::parse("under_score:3000").is_err());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let list = ReplicaAddress::parse_list("3000, [::1]:3001,10.0.0.1").unwrap();
+        assert_eq!(
+            list,
+            vec![ip("127.0.0.1:3000"), ip("[::1]:3001"), ip("10.0.0.1:3001")]
+        );
+        assert!(ReplicaAddress::parse_list(" ").is_err());
+        assert!(ReplicaAddress::parse_list("3000,").is_err());
+    }
+
+    #[test]
+    fn test_replica_count() {
+        assert_eq!(replica_count("3000").unwrap(), 1);
+        assert_eq!(
+            replica_count("10.0.0.1,[::1]:3000,db.example.com:3000").unwrap(),
+            3
+        );
+        assert!(replica_count("").is_err());
+        assert!(replica_count("3000,,3001").is_err());
+        assert!(replica_count("db.example.com").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ip("[::1]:3000").to_string(), "[::1]:3000");
+        let host = ReplicaAddress::parse("localhost:3000").unwrap();
+        assert_eq!(host.to_string(), "localhost:3000");
+    }
+
+    #[test]
+    fn test_ip_preference_order() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "127.0.0.1:1", "[::2]:1", "127.0.0.2:1"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let parse = |list: &[&str]| -> Vec<SocketAddr> {
+            list.iter().map(|s| s.parse().unwrap()).collect()
+        };
+
+        assert_eq!(
+            IpPreference::Ipv4First.order(addrs.clone()),
+            parse(&["127.0.0.1:1", "127.0.0.2:1", "[::1]:1", "[::2]:1"])
+        );
+        assert_eq!(
+            IpPreference::Ipv6First.order(addrs.clone()),
+            parse(&["[::1]:1", "[::2]:1", "127.0.0.1:1", "127.0.0.2:1"])
+        );
+        assert_eq!(
+            IpPreference::Ipv4Only.order(addrs.clone()),
+            parse(&["127.0.0.1:1", "127.0.0.2:1"])
+        );
+        assert_eq!(
+            IpPreference::Ipv6Only.order(addrs),
+            parse(&["[::1]:1", "[::2]:1"])
+        );
+    }
+
+    #[test]
+    fn test_resolve_ip() {
+        let addr = ip("[::1]:3000");
+        assert_eq!(
+            addr.resolve(IpPreference::Ipv4First).unwrap(),
+            vec!["[::1]:3000".parse().unwrap()]
+        );
+        assert!(addr.resolve(IpPreference::Ipv4Only).is_err());
+    }
+
+    #[test]
+    fn test_resolve_hostname() {
+        let addr = ReplicaAddress::parse("localhost:3000").unwrap();
+        let candidates = addr.resolve(IpPreference::Ipv4First).unwrap();
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| c.port() == 3000));
+        assert!(candidates[0].is_ipv4() || candidates.iter().all(|c| c.is_ipv6()));
+    }
+}
@@ -25,22 +25,36 @@
 //! ```
 
 use std::net::SocketAddr;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use rand::{Rng, SeedableRng};
 use zerocopy::{FromBytes, IntoBytes};
 
-use crate::error::{ClientError, ProtocolError, Result};
-use crate::internal::{BufferPool, Driver, OwnedBuf};
+use crate::address::{IpPreference, ReplicaAddress};
+use crate::error::{ClientError, ProtocolError, Result, TransportErrorKind};
+use crate::internal::{
+    BufferPool, Driver, DriverCore, Inbound, PROBE_INTERVAL_DEFAULT, QUARANTINE_TIMEOUT_DEFAULT,
+    SEND_QUEUE_LIMIT_DEFAULT,
+};
+use crate::metrics::{BufferPoolStats, ClientMetrics};
 use crate::protocol::{
     Account, AccountBalance, AccountFilter, Command, CreateAccountsResult, CreateTransfersResult,
-    Header, Message, Operation, QueryFilter, RegisterRequest, RegisterResult, RequestBuilder,
-    Transfer, HEADER_SIZE, MESSAGE_SIZE_MAX,
+    HeaderError, Message, Operation, QueryFilter, RegisterRequest, RegisterResult, RequestBuilder,
+    Transfer, WireError, MESSAGE_SIZE_MAX,
 };
 
 /// Minimum client release version.
 const CLIENT_RELEASE: u32 = 1;
 
+/// Receive buffers kept beyond one per connection reader, as slack for
+/// quarantined ones.
+const BUFFER_SLACK: usize = 2;
+
+/// Multiple of the primary's smoothed round-trip time to wait for its reply
+/// before hedging a request to a backup.
+const HEDGE_DELAY_RTT_MULTIPLE: u32 = 2;
+
 /// Client state.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum State {
@@ -50,6 +64,72 @@ enum State {
     Shutdown,
 }
 
+/// When the client connects to replicas.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ConnectionMode {
+    /// Connect to a replica the first time a request is sent to it.
+    ///
+    /// Only the primary is connected while building the client, so the first
+    /// hedged send to each backup pays for connection establishment.
+    #[default]
+    OnDemand,
+    /// Connect to every replica while building the client and keep the
+    /// connections warm.
+    ///
+    /// Building fails if no replica is reachable. Lost connections are
+    /// re-established in the background between requests, so hedged sends
+    /// normally find a connection ready.
+    Eager,
+    /// Connect to a backup replica when a request is first hedged to it, and
+    /// probe connections in the background.
+    ///
+    /// Backups are dialed in the background rather than delaying the request,
+    /// which skips its hedged copy instead. Every connection is pinged each
+    /// [`ClientBuilder::probe_interval`], one that stops answering is closed,
+    /// and a closed backup is dialed again in the background, so the hedge
+    /// path stays ready without connecting to replicas that are never used.
+    Probed,
+}
+
+/// Receive resources shared by several clients on one runtime.
+///
+/// Clients built with [`ClientBuilder::shared_driver`] take receive buffers
+/// from one pool and share the bookkeeping of cancelled receives, instead of
+/// each allocating their own. This keeps test tooling that runs many
+/// clients, e.g. one per cluster session, cheap. Each client still has its
+/// own connections, since replicas bind a connection to a single client, and
+/// replies are delivered only to the client whose connection received them.
+///
+/// Like [`Client`], this type is `!Send`.
+#[derive(Clone)]
+pub struct SharedDriver {
+    core: Rc<DriverCore>,
+}
+
+impl SharedDriver {
+    /// Create a driver with an empty buffer pool.
+    ///
+    /// Each client built with it adds one buffer per replica and removes
+    /// them again on [`Client::close`].
+    pub fn new() -> Self {
+        let pool = BufferPool::new(BUFFER_SLACK, MESSAGE_SIZE_MAX as usize);
+        Self {
+            core: Rc::new(DriverCore::new(pool)),
+        }
+    }
+
+    /// Get a snapshot of the shared receive buffer pool statistics.
+    pub fn pool_stats(&self) -> BufferPoolStats {
+        self.core.pool_stats()
+    }
+}
+
+impl Default for SharedDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// TigerBeetle client.
 ///
 /// Provides methods to create accounts, create transfers, and query data.
@@ -106,12 +186,18 @@ pub struct Client {
     rng: rand::rngs::StdRng,
     /// Send buffer.
     send_buffer: Vec<u8>,
-    /// Buffer pool for receives.
-    buffer_pool: BufferPool,
     /// Request timeout.
     request_timeout: Duration,
     /// Maximum request timeout.
     request_timeout_max: Duration,
+    /// Reject replies and results with non-zero reserved fields.
+    strict_parsing: bool,
+    /// Assert that all receive buffers are returned after each request.
+    buffer_leak_detection: bool,
+    /// When replicas are connected and whether they are probed.
+    connection_mode: ConnectionMode,
+    /// Address family order used when resolving replica addresses.
+    ip_preference: IpPreference,
 }
 
 impl Client {
@@ -165,11 +251,33 @@ impl Client {
         self.cluster
     }
 
+    /// Get the current view, the latest seen in a reply. The primary is
+    /// replica `view % replica count`.
+    pub fn view(&self) -> u32 {
+        self.view
+    }
+
+    /// Get the address each replica is connected at, indexed like the
+    /// configured addresses; `None` for a replica not connected.
+    pub fn replica_addresses(&self) -> Vec<Option<SocketAddr>> {
+        (0..self.replica_count as usize)
+            .map(|idx| self.driver.connected_address(idx))
+            .collect()
+    }
+
     /// Check if the client is ready for operations.
     pub fn is_ready(&self) -> bool {
         self.state == State::Ready
     }
 
+    /// Get a snapshot of the client metrics.
+    pub fn metrics(&self) -> ClientMetrics {
+        ClientMetrics {
+            buffer_pool: self.driver.pool_stats(),
+            replicas: self.driver.replica_stats(),
+        }
+    }
+
     /// Get the batch size limit in bytes (available after registration).
     pub fn batch_size_limit(&self) -> Option<u32> {
         self.batch_size_limit
@@ -250,7 +358,9 @@ impl Client {
         let response = self.request(Operation::LookupAccounts, ids).await?;
         let payload =
             crate::protocol::multi_batch::decode(&response, std::mem::size_of::<Account>() as u32);
-        Ok(parse_results(payload))
+        let accounts = parse_results(payload);
+        self.check_wire(&accounts, Account::validate_wire)?;
+        Ok(accounts)
     }
 
     /// Lookup transfers by ID.
@@ -258,17 +368,22 @@ impl Client {
         let response = self.request(Operation::LookupTransfers, ids).await?;
         let payload =
             crate::protocol::multi_batch::decode(&response, std::mem::size_of::<Transfer>() as u32);
-        Ok(parse_results(payload))
+        let transfers = parse_results(payload);
+        self.check_wire(&transfers, Transfer::validate_wire)?;
+        Ok(transfers)
     }
 
     /// Get transfers for an account.
     pub async fn get_account_transfers(&mut self, filter: AccountFilter) -> Result<Vec<Transfer>> {
+        self.check_wire(&[filter], AccountFilter::validate_wire)?;
         let response = self
             .request(Operation::GetAccountTransfers, &[filter])
             .await?;
         let payload =
             crate::protocol::multi_batch::decode(&response, std::mem::size_of::<Transfer>() as u32);
-        Ok(parse_results(payload))
+        let transfers = parse_results(payload);
+        self.check_wire(&transfers, Transfer::validate_wire)?;
+        Ok(transfers)
     }
 
     /// Get balance history for an account.
@@ -276,6 +391,7 @@ impl Client {
         &mut self,
         filter: AccountFilter,
     ) -> Result<Vec<AccountBalance>> {
+        self.check_wire(&[filter], AccountFilter::validate_wire)?;
         let response = self
             .request(Operation::GetAccountBalances, &[filter])
             .await?;
@@ -283,36 +399,99 @@ impl Client {
             &response,
             std::mem::size_of::<AccountBalance>() as u32,
         );
-        Ok(parse_results(payload))
+        let balances = parse_results(payload);
+        self.check_wire(&balances, AccountBalance::validate_wire)?;
+        Ok(balances)
     }
 
     /// Query accounts.
     pub async fn query_accounts(&mut self, filter: QueryFilter) -> Result<Vec<Account>> {
+        self.check_wire(&[filter], QueryFilter::validate_wire)?;
         let response = self.request(Operation::QueryAccounts, &[filter]).await?;
         let payload =
             crate::protocol::multi_batch::decode(&response, std::mem::size_of::<Account>() as u32);
-        Ok(parse_results(payload))
+        let accounts = parse_results(payload);
+        self.check_wire(&accounts, Account::validate_wire)?;
+        Ok(accounts)
     }
 
     /// Query transfers.
     pub async fn query_transfers(&mut self, filter: QueryFilter) -> Result<Vec<Transfer>> {
+        self.check_wire(&[filter], QueryFilter::validate_wire)?;
         let response = self.request(Operation::QueryTransfers, &[filter]).await?;
         let payload =
             crate::protocol::multi_batch::decode(&response, std::mem::size_of::<Transfer>() as u32);
-        Ok(parse_results(payload))
+        let transfers = parse_results(payload);
+        self.check_wire(&transfers, Transfer::validate_wire)?;
+        Ok(transfers)
+    }
+
+    /// Replace the replica addresses without recreating the client.
+    ///
+    /// Takes the same comma-separated format as
+    /// [`ClientBuilder::addresses`], in replica order, and must list every
+    /// replica. Connections to replicas whose address changed are drained
+    /// and closed, and connected replicas are dialed at their new address in
+    /// the background, so replicas can be replaced one at a time while the
+    /// client stays in use.
+    pub async fn update_addresses(&mut self, addrs: &str) -> Result<()> {
+        let addresses = ReplicaAddress::parse_list(addrs)?;
+        if addresses.len() != self.replica_count as usize {
+            return Err(ClientError::Connection(format!(
+                "expected {} replica addresses, got {}",
+                self.replica_count,
+                addresses.len()
+            )));
+        }
+
+        let candidates = addresses
+            .iter()
+            .map(|addr| addr.resolve(self.ip_preference))
+            .collect::<Result<Vec<_>>>()?;
+        self.driver.update_addresses(candidates).await;
+        Ok(())
     }
 
     /// Close the client and release resources.
     pub async fn close(mut self) {
         self.state = State::Shutdown;
         self.driver.close().await;
-        self.buffer_pool.clear_quarantine();
+        self.check_buffer_leaks();
     }
 
     // ========================================================================
     // Internal methods
     // ========================================================================
 
+    /// Assert that every receive buffer has been returned to the pool.
+    ///
+    /// Only active when leak detection is enabled on the builder. Called at
+    /// points where no receive can be in flight.
+    fn check_buffer_leaks(&self) {
+        if self.buffer_leak_detection {
+            let leaked = self.driver.leaked_buffers();
+            assert_eq!(
+                leaked, 0,
+                "{} receive buffer(s) never returned to the pool",
+                leaked
+            );
+        }
+    }
+
+    /// Verify reserved fields of events or results when strict parsing is enabled.
+    fn check_wire<T>(
+        &self,
+        items: &[T],
+        validate: fn(&T) -> std::result::Result<(), WireError>,
+    ) -> Result<()> {
+        if self.strict_parsing {
+            for item in items {
+                validate(item).map_err(ProtocolError::from)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Register with the cluster.
     async fn register(&mut self) -> Result<()> {
         if self.state != State::Disconnected {
@@ -428,11 +607,18 @@ impl Client {
         let expected_checksum = msg.header().checksum;
 
         loop {
-            // Send with hedging
-            self.send_with_hedging(&msg).await?;
+            match self.connection_mode {
+                ConnectionMode::OnDemand => {}
+                ConnectionMode::Eager => self.driver.keep_warm().await,
+                ConnectionMode::Probed => self.driver.install_dials().await,
+            }
 
-            // Wait for reply
-            match self.wait_for_reply(expected_checksum, timeout).await {
+            let reply = self
+                .send_with_hedging(&msg, expected_checksum, timeout)
+                .await;
+            self.check_buffer_leaks();
+
+            match reply {
                 Ok(reply) => return Ok(reply),
                 Err(ClientError::Timeout) => {
                     // Exponential backoff with jitter
@@ -445,25 +631,116 @@ impl Client {
         }
     }
 
-    /// Send with hedging (primary + random backup).
-    async fn send_with_hedging(&mut self, msg: &Message) -> Result<()> {
+    /// Send a request to the primary, hedged to a backup if the primary has
+    /// not replied within [`Client::hedge_delay`], and wait up to `timeout`
+    /// for the reply.
+    ///
+    /// Waiting for room in the primary's send queue counts against
+    /// `timeout`, so a stalled primary is retried like an unanswered
+    /// request. The backup copy is skipped if its queue is full. The reply's
+    /// round trip is recorded for the replica that sent it.
+    async fn send_with_hedging(
+        &mut self,
+        msg: &Message,
+        expected_checksum: u128,
+        timeout: Duration,
+    ) -> Result<Message> {
+        let deadline = tokio::time::Instant::now() + timeout;
         let primary = (self.view % self.replica_count as u32) as usize;
 
-        // Ensure primary connected
         self.ensure_connected(primary).await?;
-        self.driver.send(primary, msg.as_bytes()).await?;
+        tokio::time::timeout_at(deadline, self.driver.send(primary, msg))
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        let mut sent = vec![(primary, Instant::now())];
 
-        // Send to backup (hedging)
         if self.replica_count > 1 {
-            let backup_offset = self.rng.random_range(1..self.replica_count as usize);
-            let backup = (primary + backup_offset) % self.replica_count as usize;
-
-            if self.ensure_connected(backup).await.is_ok() {
-                let _ = self.driver.send(backup, msg.as_bytes()).await;
+            let delay = self.hedge_delay(primary);
+            if !delay.is_zero() {
+                let hedge_at = (tokio::time::Instant::now() + delay).min(deadline);
+                match self.wait_for_reply(expected_checksum, hedge_at).await {
+                    Err(ClientError::Timeout) => {}
+                    reply => return self.record_reply(reply, &sent),
+                }
+            }
+            if let Some(backup) = self.hedge(primary, msg).await {
+                sent.push((backup, Instant::now()));
             }
         }
 
-        Ok(())
+        let reply = self.wait_for_reply(expected_checksum, deadline).await;
+        self.record_reply(reply, &sent)
+    }
+
+    /// How long to wait for the primary's reply before hedging: a multiple
+    /// of its smoothed round-trip time, or none until that is measured.
+    fn hedge_delay(&self, primary: usize) -> Duration {
+        self.driver
+            .rtt(primary)
+            .map_or(Duration::ZERO, |rtt| rtt * HEDGE_DELAY_RTT_MULTIPLE)
+    }
+
+    /// Send a copy of a request to a backup, returning the backup if sent.
+    async fn hedge(&mut self, primary: usize, msg: &Message) -> Option<usize> {
+        let backup = self.pick_backup(primary);
+        if self.connection_mode == ConnectionMode::Probed && !self.driver.is_connected(backup) {
+            // Skip this hedge rather than wait for the backup to connect.
+            self.driver.dial_in_background(backup);
+            return None;
+        }
+        self.ensure_connected(backup).await.ok()?;
+        matches!(self.driver.try_send(backup, msg), Ok(true)).then_some(backup)
+    }
+
+    /// Record the round trip of a reply from the time its replica was
+    /// `sent` the request, returning the reply.
+    fn record_reply(
+        &self,
+        reply: Result<(usize, Message)>,
+        sent: &[(usize, Instant)],
+    ) -> Result<Message> {
+        let (replica, reply) = reply?;
+        // A late reply to an earlier attempt may come from a replica that
+        // was not sent this one.
+        if let Some((_, sent_at)) = sent.iter().find(|&&(idx, _)| idx == replica) {
+            self.driver.record_rtt(replica, sent_at.elapsed());
+        }
+        Ok(reply)
+    }
+
+    /// Pick the backup replica to hedge a request to.
+    ///
+    /// Once every backup's round-trip time has been measured, the fastest
+    /// connected one is preferred. Until then, or if none is connected,
+    /// backups are picked at random so each gets connected, which measures
+    /// it.
+    fn pick_backup(&mut self, primary: usize) -> usize {
+        let replica_count = self.replica_count as usize;
+        let backups = (1..replica_count).map(|offset| (primary + offset) % replica_count);
+        let rtts: Option<Vec<_>> = backups
+            .map(|backup| self.driver.rtt(backup).map(|rtt| (rtt, backup)))
+            .collect();
+        let fastest = rtts.and_then(|rtts| {
+            rtts.into_iter()
+                .filter(|&(_, backup)| self.driver.is_connected(backup))
+                .min()
+        });
+        match fastest {
+            Some((_, backup)) => backup,
+            None => (primary + self.rng.random_range(1..replica_count)) % replica_count,
+        }
+    }
+
+    /// Build the `PingClient` message used for health probes.
+    fn ping_message(&self) -> Message {
+        let mut ping = Message::new();
+        let header = ping.header_mut();
+        header.cluster = self.cluster;
+        header.release = CLIENT_RELEASE;
+        header.set_command(Command::PingClient);
+        header.as_ping_client_mut().client = self.id;
+        ping.finalize();
+        ping
     }
 
     /// Ensure connected to a replica.
@@ -475,84 +752,90 @@ impl Client {
     }
 
     /// Wait for a reply matching the expected checksum.
+    ///
+    /// Consumes events from the readers of all connections. A reply is
+    /// accepted from any replica, replies to other requests and pongs are
+    /// skipped, and a failed connection only fails the request if it is the
+    /// current connection to the primary. Returns the reply and the replica
+    /// that sent it, or [`ClientError::Timeout`] at `deadline`.
     async fn wait_for_reply(
         &mut self,
         expected_checksum: u128,
-        timeout: Duration,
-    ) -> Result<Message> {
-        let start = Instant::now();
+        deadline: tokio::time::Instant,
+    ) -> Result<(usize, Message)> {
         let primary = (self.view % self.replica_count as u32) as usize;
 
         loop {
-            if start.elapsed() >= timeout {
-                return Err(ClientError::Timeout);
-            }
-
-            // Get a buffer
-            let buf = self
-                .buffer_pool
-                .acquire()
-                .ok_or(ClientError::Connection("buffer pool exhausted".into()))?;
-
-            // Try to receive from primary
-            let buf = match self.driver.recv(primary, buf).await {
-                Ok(b) => b,
-                Err(e) => {
-                    // Connection error - try to reconnect
-                    self.driver.disconnect(primary).await;
-                    return Err(e);
+            let inbound = tokio::time::timeout_at(deadline, self.driver.next_inbound())
+                .await
+                .map_err(|_| ClientError::Timeout)?;
+
+            let (replica, message) = match inbound {
+                Inbound::Message { replica, message } => (replica, message),
+                Inbound::Closed {
+                    replica,
+                    connection,
+                    error,
+                } => {
+                    // Closes of connections that were already replaced are
+                    // stale.
+                    if !self.driver.is_current(replica, connection) {
+                        continue;
+                    }
+                    // A replica that shut the connection down is usually
+                    // restarting, so redial it right away. Backups otherwise
+                    // reconnect on the next hedged send.
+                    let peer_closed = matches!(
+                        &error,
+                        ClientError::Io(e) if e.kind() == TransportErrorKind::PeerClosed
+                    );
+                    if peer_closed || replica == primary {
+                        self.driver.disconnect(replica).await;
+                    }
+                    if peer_closed {
+                        self.driver.dial_in_background(replica);
+                    }
+                    if replica == primary {
+                        return Err(error);
+                    }
+                    continue;
                 }
             };
 
-            // Try to parse
-            match self.try_parse_reply(&buf, expected_checksum) {
-                Ok(msg) => {
-                    self.buffer_pool.release(buf);
-                    return Ok(msg);
-                }
-                Err(ParseError::NeedMoreData) => {
-                    // TODO: Handle partial messages
-                    self.buffer_pool.release(buf);
-                    continue;
-                }
-                Err(ParseError::WrongReply) => {
-                    self.buffer_pool.release(buf);
-                    continue;
-                }
-                Err(ParseError::Evicted(reason)) => {
-                    self.buffer_pool.release(buf);
-                    return Err(ClientError::Evicted(reason));
-                }
+            match self.check_reply(&message, expected_checksum) {
+                Ok(()) => return Ok((replica, message)),
+                Err(ParseError::WrongReply) => continue,
+                Err(ParseError::Evicted(reason)) => return Err(ClientError::Evicted(reason)),
                 Err(ParseError::Protocol(e)) => {
-                    self.buffer_pool.release(buf);
-                    self.driver.disconnect(primary).await;
+                    self.driver.disconnect(replica).await;
                     return Err(ClientError::Protocol(e));
                 }
             }
         }
     }
 
-    /// Try to parse a reply.
-    fn try_parse_reply(
+    /// Check that a message is the reply to the expected request.
+    ///
+    /// Checksums were already verified by the connection reader.
+    fn check_reply(
         &self,
-        buf: &OwnedBuf,
+        message: &Message,
         expected_checksum: u128,
-    ) -> std::result::Result<Message, ParseError> {
-        let data = buf.as_slice();
-
-        if data.len() < HEADER_SIZE as usize {
-            return Err(ParseError::NeedMoreData);
+    ) -> std::result::Result<(), ParseError> {
+        let header = message.header();
+
+        if self.strict_parsing {
+            header.validate_wire().map_err(|e| {
+                ParseError::Protocol(match e {
+                    HeaderError::InvalidPadding(_) => ProtocolError::ReservedField,
+                    _ => ProtocolError::InvalidHeader,
+                })
+            })?;
         }
 
-        let header_bytes: &[u8; HEADER_SIZE as usize] = data[..HEADER_SIZE as usize]
-            .try_into()
-            .map_err(|_| ParseError::Protocol(ProtocolError::InvalidHeader))?;
-        let header = Header::from_bytes(header_bytes);
-
-        if !header.valid_checksum() {
-            return Err(ParseError::Protocol(ProtocolError::InvalidHeaderChecksum));
+        if header.command == Command::PongClient as u8 {
+            return Err(ParseError::WrongReply);
         }
-
         if header.command != Command::Reply as u8 {
             if header.command == Command::Eviction as u8 {
                 let reason = header.as_eviction().reason;
@@ -565,11 +848,6 @@ impl Client {
             return Err(ParseError::Protocol(ProtocolError::UnexpectedReply));
         }
 
-        let total_size = header.size as usize;
-        if data.len() < total_size {
-            return Err(ParseError::NeedMoreData);
-        }
-
         let reply_header = header.as_reply();
         if reply_header.request_checksum != expected_checksum {
             return Err(ParseError::WrongReply);
@@ -578,22 +856,12 @@ impl Client {
             return Err(ParseError::WrongReply);
         }
 
-        let body_data = &data[HEADER_SIZE as usize..total_size];
-        if !header.valid_checksum_body(body_data) {
-            return Err(ParseError::Protocol(ProtocolError::InvalidBodyChecksum));
-        }
-
-        let msg_data = data[..total_size].to_vec();
-        let msg = Message::from_bytes(msg_data)
-            .ok_or(ParseError::Protocol(ProtocolError::InvalidHeader))?;
-
-        Ok(msg)
+        Ok(())
     }
 }
 
 /// Reply parsing errors.
 enum ParseError {
-    NeedMoreData,
     WrongReply,
     Evicted(crate::protocol::header::EvictionReason),
     Protocol(ProtocolError),
@@ -640,10 +908,19 @@ fn parse_results<R: Copy>(data: &[u8]) -> Vec<R> {
 /// ```
 pub struct ClientBuilder {
     cluster: u128,
-    addresses: Vec<SocketAddr>,
+    addresses: Vec<ReplicaAddress>,
+    ip_preference: IpPreference,
     connect_timeout: Duration,
     request_timeout: Duration,
     request_timeout_max: Duration,
+    strict_parsing: bool,
+    buffer_leak_detection: bool,
+    quarantine_timeout: Duration,
+    send_zero_copy: bool,
+    send_queue_limit: u32,
+    connection_mode: ConnectionMode,
+    probe_interval: Duration,
+    shared_driver: Option<SharedDriver>,
 }
 
 impl ClientBuilder {
@@ -652,9 +929,18 @@ impl ClientBuilder {
         Self {
             cluster: 0,
             addresses: Vec::new(),
+            ip_preference: IpPreference::default(),
             connect_timeout: Duration::from_secs(5),
             request_timeout: Duration::from_millis(500),
             request_timeout_max: Duration::from_secs(30),
+            strict_parsing: false,
+            buffer_leak_detection: false,
+            quarantine_timeout: QUARANTINE_TIMEOUT_DEFAULT,
+            send_zero_copy: false,
+            send_queue_limit: SEND_QUEUE_LIMIT_DEFAULT,
+            connection_mode: ConnectionMode::default(),
+            probe_interval: PROBE_INTERVAL_DEFAULT,
+            shared_driver: None,
         }
     }
 
@@ -665,30 +951,51 @@ impl ClientBuilder {
     }
 
     /// Set replica addresses from a comma-separated string.
+    ///
+    /// Each address is a port (`3000`), an IPv4 address (`10.0.0.1:3000`),
+    /// an IPv6 address (`[::1]:3000`, or `::1` without a port), or a hostname
+    /// with a port (`db.example.com:3000`). See [`PORT_DEFAULT`](crate::PORT_DEFAULT)
+    /// for addresses without a port.
     pub fn addresses(mut self, addrs: &str) -> Result<Self> {
-        if addrs.trim().is_empty() {
-            return Err(ClientError::Connection("no addresses provided".into()));
-        }
-
-        self.addresses = addrs
-            .split(',')
-            .map(|s| {
-                s.trim().parse().map_err(|e| {
-                    ClientError::Connection(format!("invalid address '{}': {}", s.trim(), e))
-                })
-            })
-            .collect::<Result<Vec<_>>>()?;
-
+        self.addresses = ReplicaAddress::parse_list(addrs)?;
         Ok(self)
     }
 
     /// Set replica addresses from a vector.
     pub fn addresses_vec(mut self, addrs: Vec<SocketAddr>) -> Self {
-        self.addresses = addrs;
+        self.addresses = addrs.into_iter().map(ReplicaAddress::from).collect();
+        self
+    }
+
+    /// Set the address family preference for hostnames.
+    ///
+    /// When a hostname resolves to both IPv4 and IPv6 addresses, candidates
+    /// are tried in this order (defaults to [`IpPreference::Ipv4First`]).
+    pub fn ip_preference(mut self, preference: IpPreference) -> Self {
+        self.ip_preference = preference;
+        self
+    }
+
+    /// Set when replicas are connected (defaults to
+    /// [`ConnectionMode::OnDemand`]).
+    pub fn connection_mode(mut self, mode: ConnectionMode) -> Self {
+        self.connection_mode = mode;
+        self
+    }
+
+    /// Set the interval between health probes in [`ConnectionMode::Probed`]
+    /// (defaults to one second).
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "probe interval must be positive");
+        self.probe_interval = interval;
         self
     }
 
     /// Set connection timeout.
+    ///
+    /// Applies to each address tried when connecting to a replica. A replica
+    /// that does not accept the connection in time fails with
+    /// [`ClientError::ConnectTimeout`].
     pub fn connect_timeout(mut self, timeout: Duration) -> Self {
         self.connect_timeout = timeout;
         self
@@ -706,9 +1013,79 @@ impl ClientBuilder {
         self
     }
 
+    /// Enable strict parsing of replies.
+    ///
+    /// When enabled, reply headers, returned accounts, transfers and balances,
+    /// and outgoing query filters are checked for non-zero reserved fields and
+    /// unknown flag bits. Violations fail the request with
+    /// [`ProtocolError::ReservedField`], catching corrupt or version-skewed
+    /// data early instead of silently ignoring it.
+    pub fn strict_parsing(mut self, enabled: bool) -> Self {
+        self.strict_parsing = enabled;
+        self
+    }
+
+    /// Enable receive buffer leak detection.
+    ///
+    /// When enabled, the client asserts after every request and on close
+    /// that all receive buffers were returned to the pool, panicking with the
+    /// number of leaked buffers otherwise. Intended for tests and debugging;
+    /// see [`Client::metrics`] for the underlying counters.
+    pub fn buffer_leak_detection(mut self, enabled: bool) -> Self {
+        self.buffer_leak_detection = enabled;
+        self
+    }
+
+    /// Set how long a buffer from a cancelled receive stays quarantined.
+    ///
+    /// Such buffers are normally reused as soon as the kernel confirms the
+    /// cancelled operation completed. The timeout is a last resort for
+    /// completions that never arrive; until it elapses the pool allocates new
+    /// buffers instead of reusing quarantined ones.
+    pub fn quarantine_timeout(mut self, timeout: Duration) -> Self {
+        self.quarantine_timeout = timeout;
+        self
+    }
+
+    /// Enable zero-copy sends for large requests.
+    ///
+    /// Requests of 64 KiB or more are sent with `IORING_OP_SEND_ZC`, which
+    /// transmits straight from the send buffer instead of copying it into the
+    /// kernel, saving CPU on bulk imports. Requires Linux 6.0 or newer; on
+    /// older kernels the client falls back to normal sends after the first
+    /// attempt. See [`ReplicaStats::zero_copy_sends`](crate::ReplicaStats::zero_copy_sends).
+    pub fn send_zero_copy(mut self, enabled: bool) -> Self {
+        self.send_zero_copy = enabled;
+        self
+    }
+
+    /// Set how many bytes may be queued per replica connection.
+    ///
+    /// Once a replica's queue is full, a request to it waits for room (up to
+    /// its timeout) and hedged copies to it are skipped, so a stalled replica
+    /// cannot grow memory without bound. A single request larger than the
+    /// limit is still sent. Defaults to four maximum-size messages.
+    pub fn send_queue_limit(mut self, bytes: u32) -> Self {
+        assert!(bytes > 0, "send queue limit must be positive");
+        self.send_queue_limit = bytes;
+        self
+    }
+
+    /// Share receive buffers with other clients built with `driver`.
+    ///
+    /// The [`quarantine_timeout`](Self::quarantine_timeout) of a shared pool
+    /// is the default, and [`Client::metrics`] reports pool statistics for
+    /// every client sharing it.
+    pub fn shared_driver(mut self, driver: &SharedDriver) -> Self {
+        self.shared_driver = Some(driver.clone());
+        self
+    }
+
     /// Build the client.
     ///
-    /// This connects to the cluster and registers the client.
+    /// This resolves hostnames, connects to the cluster and registers the
+    /// client. Hostname resolution uses the system resolver and blocks the
+    /// runtime thread while it runs.
     pub async fn build(self) -> Result<Client> {
         if self.addresses.is_empty() {
             return Err(ClientError::Connection("no addresses provided".into()));
@@ -720,10 +1097,34 @@ impl ClientBuilder {
         }
 
         let replica_count = self.addresses.len() as u8;
-        let driver = Driver::new(self.addresses, self.connect_timeout);
-
-        let buffer_count = replica_count as usize + 2;
-        let buffer_pool = BufferPool::new(buffer_count, MESSAGE_SIZE_MAX as usize);
+        let candidates = self
+            .addresses
+            .iter()
+            .map(|addr| addr.resolve(self.ip_preference))
+            .collect::<Result<Vec<_>>>()?;
+        // One buffer per connection reader, plus slack for quarantined ones.
+        let readers = replica_count as usize;
+        let driver = match &self.shared_driver {
+            Some(shared) => Driver::with_core(
+                candidates,
+                self.connect_timeout,
+                shared.core.clone(),
+                readers,
+            ),
+            None => {
+                let buffer_pool =
+                    BufferPool::new(readers + BUFFER_SLACK, MESSAGE_SIZE_MAX as usize)
+                        .with_quarantine_timeout(self.quarantine_timeout);
+                Driver::new(candidates, self.connect_timeout, buffer_pool)
+            }
+        };
+        let mut driver = driver
+            .with_send_zero_copy(self.send_zero_copy)
+            .with_send_queue_limit(self.send_queue_limit);
+        if self.connection_mode == ConnectionMode::Eager {
+            // Dial every replica at once and fail fast if none answers.
+            driver.connect_all().await?;
+        }
 
         let mut client = Client {
             id,
@@ -738,10 +1139,17 @@ impl ClientBuilder {
             batch_size_limit: None,
             rng: rand::rngs::StdRng::from_os_rng(),
             send_buffer: vec![0u8; MESSAGE_SIZE_MAX as usize],
-            buffer_pool,
             request_timeout: self.request_timeout,
             request_timeout_max: self.request_timeout_max,
+            strict_parsing: self.strict_parsing,
+            buffer_leak_detection: self.buffer_leak_detection,
+            connection_mode: self.connection_mode,
+            ip_preference: self.ip_preference,
         };
+        if self.connection_mode == ConnectionMode::Probed {
+            let ping = client.ping_message();
+            client.driver.start_probing(self.probe_interval, ping);
+        }
 
         // Register with cluster
         client.register().await?;
@@ -759,6 +1167,103 @@ impl Default for ClientBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::TcpListener;
+
+    /// An unregistered client of replicas at `addrs`, seeded so its random
+    /// picks repeat.
+    fn test_client(addrs: &[SocketAddr], connection_mode: ConnectionMode) -> Client {
+        let candidates = addrs.iter().map(|&addr| vec![addr]).collect();
+        let pool = BufferPool::new(addrs.len() + BUFFER_SLACK, MESSAGE_SIZE_MAX as usize);
+        Client {
+            id: 1,
+            cluster: 0,
+            replica_count: addrs.len() as u8,
+            driver: Driver::new(candidates, Duration::from_secs(1), pool),
+            state: State::Ready,
+            view: 0,
+            session: 0,
+            request_number: 0,
+            parent: 0,
+            batch_size_limit: None,
+            rng: rand::rngs::StdRng::seed_from_u64(7),
+            send_buffer: Vec::new(),
+            request_timeout: Duration::from_millis(5),
+            request_timeout_max: Duration::from_millis(5),
+            strict_parsing: false,
+            buffer_leak_detection: false,
+            connection_mode,
+            ip_preference: IpPreference::default(),
+        }
+    }
+
+    /// Replicas that accept connections and never answer.
+    fn silent_replicas(count: usize) -> (Vec<TcpListener>, Vec<SocketAddr>) {
+        let listeners: Vec<_> = (0..count)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+        (listeners, addrs)
+    }
+
+    /// Requests sent to each replica.
+    fn requests_sent(client: &Client) -> Vec<u64> {
+        client
+            .driver
+            .replica_stats()
+            .iter()
+            .map(|stats| stats.messages_sent.total())
+            .collect()
+    }
+
+    #[test]
+    fn test_hedge_delay() {
+        let (_listeners, addrs) = silent_replicas(3);
+        tokio_uring::start(async {
+            let mut client = test_client(&addrs, ConnectionMode::default());
+            // Unmeasured, the primary is hedged right away.
+            assert_eq!(client.hedge_delay(0), Duration::ZERO);
+            client.ensure_connected(0).await.unwrap();
+            let rtt = client.driver.rtt(0).unwrap();
+            assert_eq!(client.hedge_delay(0), rtt * HEDGE_DELAY_RTT_MULTIPLE);
+            client.close().await;
+        });
+    }
+
+    #[test]
+    fn test_hedge_to_fastest_backup() {
+        let (_listeners, addrs) = silent_replicas(3);
+        tokio_uring::start(async {
+            let mut client = test_client(&addrs, ConnectionMode::default());
+            let msg = client.ping_message();
+
+            // Backups are picked at random until each has been connected,
+            // which measures it, without waiting for requests or probes.
+            for _ in 0..100 {
+                if client.driver.is_connected(1) && client.driver.is_connected(2) {
+                    break;
+                }
+                let reply = client.send_with_hedging(&msg, 0, Duration::from_millis(5));
+                assert!(matches!(reply.await, Err(ClientError::Timeout)));
+            }
+            assert!(client.driver.rtt(1).is_some());
+            assert!(client.driver.rtt(2).is_some());
+
+            // Replica 1 turns slow; every hedge goes to replica 2.
+            client.driver.record_rtt(1, Duration::from_secs(1));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let before = requests_sent(&client);
+            for _ in 0..10 {
+                let reply = client.send_with_hedging(&msg, 0, Duration::from_millis(5));
+                assert!(matches!(reply.await, Err(ClientError::Timeout)));
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let after = requests_sent(&client);
+            assert_eq!(after[0] - before[0], 10);
+            assert_eq!(after[1] - before[1], 0);
+            assert_eq!(after[2] - before[2], 10);
+            client.close().await;
+        });
+    }
 
     #[test]
     fn test_builder_defaults() {
@@ -766,6 +1271,67 @@ mod tests {
         assert_eq!(builder.cluster, 0);
         assert!(builder.addresses.is_empty());
         assert_eq!(builder.connect_timeout, Duration::from_secs(5));
+        assert!(!builder.strict_parsing);
+        assert!(!builder.buffer_leak_detection);
+        assert_eq!(builder.quarantine_timeout, QUARANTINE_TIMEOUT_DEFAULT);
+        assert!(!builder.send_zero_copy);
+        assert_eq!(builder.connection_mode, ConnectionMode::OnDemand);
+        assert_eq!(builder.probe_interval, PROBE_INTERVAL_DEFAULT);
+        assert_eq!(builder.send_queue_limit, SEND_QUEUE_LIMIT_DEFAULT);
+        assert!(builder.shared_driver.is_none());
+    }
+
+    #[test]
+    fn test_builder_shared_driver() {
+        let driver = SharedDriver::new();
+        let builder = ClientBuilder::new().shared_driver(&driver);
+        let shared = builder.shared_driver.unwrap();
+        assert!(Rc::ptr_eq(&shared.core, &driver.core));
+    }
+
+    #[test]
+    fn test_builder_quarantine_timeout() {
+        let builder = ClientBuilder::new().quarantine_timeout(Duration::from_secs(1));
+        assert_eq!(builder.quarantine_timeout, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_builder_buffer_leak_detection() {
+        let builder = ClientBuilder::new().buffer_leak_detection(true);
+        assert!(builder.buffer_leak_detection);
+    }
+
+    #[test]
+    fn test_builder_send_zero_copy() {
+        let builder = ClientBuilder::new().send_zero_copy(true);
+        assert!(builder.send_zero_copy);
+    }
+
+    #[test]
+    fn test_builder_send_queue_limit() {
+        let builder = ClientBuilder::new().send_queue_limit(1 << 20);
+        assert_eq!(builder.send_queue_limit, 1 << 20);
+    }
+
+    #[test]
+    fn test_builder_connection_mode() {
+        let builder = ClientBuilder::new().connection_mode(ConnectionMode::Eager);
+        assert_eq!(builder.connection_mode, ConnectionMode::Eager);
+    }
+
+    #[test]
+    fn test_builder_probe_interval() {
+        let builder = ClientBuilder::new()
+            .connection_mode(ConnectionMode::Probed)
+            .probe_interval(Duration::from_millis(250));
+        assert_eq!(builder.connection_mode, ConnectionMode::Probed);
+        assert_eq!(builder.probe_interval, Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_builder_strict_parsing() {
+        let builder = ClientBuilder::new().strict_parsing(true);
+        assert!(builder.strict_parsing);
     }
 
     #[test]
@@ -788,6 +1354,26 @@ mod tests {
         assert_eq!(builder.addresses.len(), 2);
     }
 
+    #[test]
+    fn test_builder_addresses_ipv6() {
+        let builder = ClientBuilder::new()
+            .addresses("[::1]:3000,[::1]:3001")
+            .unwrap();
+        assert_eq!(
+            builder.addresses[0],
+            ReplicaAddress::Ip("[::1]:3000".parse().unwrap())
+        );
+        assert_eq!(builder.addresses.len(), 2);
+    }
+
+    #[test]
+    fn test_builder_ip_preference() {
+        let builder = ClientBuilder::new();
+        assert_eq!(builder.ip_preference, IpPreference::Ipv4First);
+        let builder = builder.ip_preference(IpPreference::Ipv6Only);
+        assert_eq!(builder.ip_preference, IpPreference::Ipv6Only);
+    }
+
     #[test]
     fn test_parse_results_empty() {
         let data: &[u8] = &[];
@@ -4,8 +4,10 @@
 //! with error handling frameworks like `anyhow` and `thiserror`.
 
 use crate::protocol::header::EvictionReason;
+use crate::protocol::WireError;
 use std::error::Error;
 use std::fmt;
+use std::net::SocketAddr;
 
 /// Result type for client operations.
 pub type Result<T> = std::result::Result<T, ClientError>;
@@ -21,6 +23,10 @@ pub enum ClientError {
     Evicted(EvictionReason),
     /// Operation timed out.
     Timeout,
+    /// Connecting to a replica did not complete within the connect timeout.
+    ConnectTimeout(SocketAddr),
+    /// I/O on a replica connection failed.
+    Io(TransportError),
     /// Client is not registered.
     NotRegistered,
     /// Client is shutting down.
@@ -46,6 +52,8 @@ impl fmt::Display for ClientError {
             ClientError::Protocol(e) => write!(f, "protocol error: {}", e),
             ClientError::Evicted(reason) => write!(f, "client evicted: {:?}", reason),
             ClientError::Timeout => write!(f, "operation timed out"),
+            ClientError::ConnectTimeout(addr) => write!(f, "connect to {} timed out", addr),
+            ClientError::Io(e) => write!(f, "{}", e),
             ClientError::NotRegistered => write!(f, "client not registered"),
             ClientError::Shutdown => write!(f, "client is shutting down"),
             ClientError::RequestTooLarge { size, limit } => {
@@ -62,6 +70,7 @@ impl Error for ClientError {
         match self {
             ClientError::Transport(e) => Some(e.as_ref()),
             ClientError::Protocol(e) => Some(e),
+            ClientError::Io(e) => Some(e),
             _ => None,
         }
     }
@@ -79,6 +88,124 @@ impl From<std::io::Error> for ClientError {
     }
 }
 
+impl From<TransportError> for ClientError {
+    fn from(err: TransportError) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+/// What went wrong on a replica connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TransportErrorKind {
+    /// Establishing the connection failed (refused, unreachable, ...).
+    ConnectFailed,
+    /// The replica is not connected, or the connection was already lost.
+    NotConnected,
+    /// The connection was reset or aborted.
+    Reset,
+    /// The replica closed the connection in an orderly way, with end of
+    /// stream rather than a reset.
+    PeerClosed,
+    /// Reading from the connection failed.
+    Read,
+    /// Writing to the connection failed.
+    Write,
+    /// The replica stopped responding.
+    Timeout,
+    /// No receive buffer was available.
+    BuffersExhausted,
+}
+
+impl TransportErrorKind {
+    /// Classify an I/O error, using `default` for errors without a more
+    /// specific kind.
+    pub fn classify(error: &std::io::Error, default: TransportErrorKind) -> Self {
+        use std::io::ErrorKind;
+        match error.kind() {
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+                TransportErrorKind::Reset
+            }
+            ErrorKind::UnexpectedEof => TransportErrorKind::PeerClosed,
+            ErrorKind::TimedOut => TransportErrorKind::Timeout,
+            _ => default,
+        }
+    }
+}
+
+impl fmt::Display for TransportErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportErrorKind::ConnectFailed => write!(f, "connect failed"),
+            TransportErrorKind::NotConnected => write!(f, "not connected"),
+            TransportErrorKind::Reset => write!(f, "connection reset"),
+            TransportErrorKind::PeerClosed => write!(f, "connection closed by peer"),
+            TransportErrorKind::Read => write!(f, "read failed"),
+            TransportErrorKind::Write => write!(f, "write failed"),
+            TransportErrorKind::Timeout => write!(f, "replica stopped responding"),
+            TransportErrorKind::BuffersExhausted => write!(f, "receive buffers exhausted"),
+        }
+    }
+}
+
+/// An I/O failure on the connection to a replica.
+///
+/// Lets retry policies branch on [`TransportError::kind`] instead of
+/// parsing messages.
+#[derive(Debug)]
+pub struct TransportError {
+    replica: u8,
+    kind: TransportErrorKind,
+    source: Option<std::io::Error>,
+}
+
+impl TransportError {
+    /// Create an error without an underlying I/O error.
+    pub fn new(replica: u8, kind: TransportErrorKind) -> Self {
+        Self {
+            replica,
+            kind,
+            source: None,
+        }
+    }
+
+    /// Create an error from an I/O error, classified with
+    /// [`TransportErrorKind::classify`].
+    pub fn from_io(replica: u8, default: TransportErrorKind, error: std::io::Error) -> Self {
+        Self {
+            replica,
+            kind: TransportErrorKind::classify(&error, default),
+            source: Some(error),
+        }
+    }
+
+    /// Index of the replica whose connection failed.
+    pub fn replica(&self) -> u8 {
+        self.replica
+    }
+
+    /// What went wrong.
+    pub fn kind(&self) -> TransportErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replica {}: {}", self.replica, self.kind)?;
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for TransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
 /// Protocol-level errors.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ProtocolError {
@@ -98,6 +225,8 @@ pub enum ProtocolError {
     InvalidSize,
     /// Invalid command.
     InvalidCommand,
+    /// Reserved field or flag bit was non-zero (strict parsing only).
+    ReservedField,
 }
 
 impl fmt::Display for ProtocolError {
@@ -111,12 +240,19 @@ impl fmt::Display for ProtocolError {
             ProtocolError::VersionMismatch => write!(f, "version mismatch"),
             ProtocolError::InvalidSize => write!(f, "invalid message size"),
             ProtocolError::InvalidCommand => write!(f, "invalid command"),
+            ProtocolError::ReservedField => write!(f, "non-zero reserved field"),
         }
     }
 }
 
 impl Error for ProtocolError {}
 
+impl From<WireError> for ProtocolError {
+    fn from(_: WireError) -> Self {
+        ProtocolError::ReservedField
+    }
+}
+
 /// Packet-level status codes (from C client API).
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PacketStatus {
@@ -198,6 +334,9 @@ mod tests {
     fn test_client_error_display() {
         let err = ClientError::Timeout;
         assert_eq!(format!("{}", err), "operation timed out");
+
+        let err = ClientError::ConnectTimeout("[::1]:3000".parse().unwrap());
+        assert_eq!(format!("{}", err), "connect to [::1]:3000 timed out");
     }
 
     #[test]
@@ -213,6 +352,39 @@ mod tests {
         assert!(matches!(client_err, ClientError::Transport(_)));
     }
 
+    #[test]
+    fn test_transport_error() {
+        let io_err = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        let err = TransportError::from_io(2, TransportErrorKind::Read, io_err);
+        assert_eq!(err.replica(), 2);
+        assert_eq!(err.kind(), TransportErrorKind::Reset);
+        assert!(err.source().unwrap().is::<std::io::Error>());
+
+        let err: ClientError = TransportError::new(1, TransportErrorKind::PeerClosed).into();
+        assert_eq!(format!("{}", err), "replica 1: connection closed by peer");
+        assert!(err.source().unwrap().is::<TransportError>());
+    }
+
+    #[test]
+    fn test_transport_error_kind_classify() {
+        use std::io::{Error, ErrorKind};
+        let classify =
+            |kind| TransportErrorKind::classify(&Error::from(kind), TransportErrorKind::Write);
+        assert_eq!(classify(ErrorKind::BrokenPipe), TransportErrorKind::Reset);
+        assert_eq!(
+            classify(ErrorKind::UnexpectedEof),
+            TransportErrorKind::PeerClosed
+        );
+        assert_eq!(classify(ErrorKind::TimedOut), TransportErrorKind::Timeout);
+        assert_eq!(classify(ErrorKind::Other), TransportErrorKind::Write);
+    }
+
+    #[test]
+    fn test_protocol_error_from_wire() {
+        let err: ProtocolError = WireError::ReservedField("reserved").into();
+        assert_eq!(err, ProtocolError::ReservedField);
+    }
+
     #[test]
     fn test_error_source_chain() {
         let protocol_err = ProtocolError::InvalidHeaderChecksum;
@@ -1,9 +1,38 @@
 //! Buffer management for io_uring operations.
 //!
 //! io_uring requires stable buffer addresses during async operations.
-//! This module provides owned buffers and a pool for efficient reuse.
+//! This module provides owned buffers and a pool for efficient reuse. Pooled
+//! buffers are lent to the kernel directly, so a receive copies data once.
+//!
+//! A buffer whose operation was cancelled may still be written by the kernel
+//! until the cancelled operation completes. Such buffers are poisoned and held
+//! in quarantine until the owning operation is confirmed complete, or until
+//! the quarantine timeout elapses as a last resort.
 
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use tokio_uring::buf::{IoBuf, IoBufMut};
+
+use crate::metrics::BufferPoolStats;
+
+/// Default time a poisoned buffer stays quarantined without a completion.
+pub const QUARANTINE_TIMEOUT_DEFAULT: Duration = Duration::from_secs(5);
+
+/// Identifier of an in-flight I/O operation that owns a buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct OpId(pub u64);
+
+/// Poison record for a buffer involved in a cancelled operation.
+#[derive(Clone, Copy, Debug)]
+struct Poison {
+    /// Operation that was using the buffer when it was cancelled.
+    op: Option<OpId>,
+    /// When the buffer was poisoned.
+    at: Instant,
+}
 
 /// Owned buffer for I/O operations.
 ///
@@ -12,7 +41,8 @@ use std::collections::VecDeque;
 pub struct OwnedBuf {
     data: Vec<u8>,
     len: usize,
-    poisoned: bool,
+    owner: Option<OpId>,
+    poison: Option<Poison>,
 }
 
 impl OwnedBuf {
@@ -21,13 +51,14 @@ impl OwnedBuf {
         Self {
             data: vec![0u8; capacity],
             len: 0,
-            poisoned: false,
+            owner: None,
+            poison: None,
         }
     }
 
     /// Get the capacity.
     pub fn capacity(&self) -> usize {
-        self.data.capacity()
+        self.data.len()
     }
 
     /// Get the logical length of valid data.
@@ -37,7 +68,7 @@ impl OwnedBuf {
 
     /// Set the logical length.
     pub fn set_len(&mut self, len: usize) {
-        assert!(len <= self.data.capacity());
+        assert!(len <= self.data.len());
         self.len = len;
     }
 
@@ -46,25 +77,138 @@ impl OwnedBuf {
         &self.data[..self.len]
     }
 
-    /// Get the full buffer as a mutable slice.
-    pub fn as_mut_slice(&mut self) -> &mut [u8] {
-        &mut self.data
+    /// Get the in-flight operation using this buffer, if any.
+    pub fn owner(&self) -> Option<OpId> {
+        self.owner
+    }
+
+    /// Record that an operation is using this buffer.
+    pub fn set_owner(&mut self, op: OpId) {
+        assert!(
+            self.owner.is_none(),
+            "buffer already owned by {:?}",
+            self.owner
+        );
+        self.owner = Some(op);
+    }
+
+    /// Record that the owning operation completed.
+    pub fn clear_owner(&mut self) {
+        self.owner = None;
     }
 
     /// Check if poisoned (involved in cancelled operation).
     pub fn is_poisoned(&self) -> bool {
-        self.poisoned
+        self.poison.is_some()
     }
 
     /// Mark as poisoned.
+    ///
+    /// The current owner is remembered so the buffer can leave quarantine as
+    /// soon as that operation is confirmed complete.
     pub fn poison(&mut self) {
-        self.poisoned = true;
+        self.poison = Some(Poison {
+            op: self.owner,
+            at: Instant::now(),
+        });
     }
 
     /// Reset for reuse.
     pub fn reset(&mut self) {
         self.len = 0;
-        self.poisoned = false;
+        self.owner = None;
+        self.poison = None;
+    }
+}
+
+/// Operations confirmed complete after their future was dropped.
+pub type Completions = Rc<RefCell<Vec<OpId>>>;
+
+/// Buffers whose operation was cancelled, waiting to be returned to a pool.
+pub type Parked = Rc<RefCell<Vec<OwnedBuf>>>;
+
+/// A pooled buffer lent to the kernel for one operation.
+///
+/// The kernel reads or writes the pooled buffer directly. If the future
+/// using it is dropped before the operation completes, tokio_uring keeps
+/// this value alive until the completion arrives and only then drops it.
+/// Dropping an unfinished buffer poisons it, parks it in `cancelled` and
+/// records its operation in `completions`, which is the confirmation the
+/// pool needs to release it from quarantine.
+pub struct RecvBuf {
+    buf: Option<OwnedBuf>,
+    cancelled: Parked,
+    completions: Completions,
+}
+
+impl RecvBuf {
+    /// Lend `buf` to operation `op`, discarding its contents.
+    pub fn new(mut buf: OwnedBuf, op: OpId, cancelled: Parked, completions: Completions) -> Self {
+        buf.set_owner(op);
+        buf.set_len(0);
+        Self {
+            buf: Some(buf),
+            cancelled,
+            completions,
+        }
+    }
+
+    /// Take the buffer back once the operation has completed.
+    pub fn into_inner(mut self) -> OwnedBuf {
+        let mut buf = self.buf.take().expect("buffer already taken");
+        buf.clear_owner();
+        buf
+    }
+
+    fn buf(&self) -> &OwnedBuf {
+        self.buf.as_ref().expect("buffer already taken")
+    }
+
+    fn buf_mut(&mut self) -> &mut OwnedBuf {
+        self.buf.as_mut().expect("buffer already taken")
+    }
+}
+
+impl Drop for RecvBuf {
+    fn drop(&mut self) {
+        if let Some(mut buf) = self.buf.take() {
+            let op = buf.owner().expect("lent buffer has an owner");
+            buf.poison();
+            self.cancelled.borrow_mut().push(buf);
+            self.completions.borrow_mut().push(op);
+        }
+    }
+}
+
+// SAFETY: The pooled buffer's vector is allocated at full size and never
+// reallocated, so the pointer stays stable while the kernel uses it, and
+// every byte up to its length is initialized.
+unsafe impl IoBuf for RecvBuf {
+    fn stable_ptr(&self) -> *const u8 {
+        self.buf().data.as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.buf().len
+    }
+
+    fn bytes_total(&self) -> usize {
+        self.buf().capacity()
+    }
+}
+
+// SAFETY: See `IoBuf`. `set_init` only moves the logical length, which the
+// kernel reported as written and which stays within the vector.
+unsafe impl IoBufMut for RecvBuf {
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.buf_mut().data.as_mut_ptr()
+    }
+
+    unsafe fn set_init(&mut self, pos: usize) {
+        let buf = self.buf_mut();
+        if pos > buf.len {
+            buf.set_len(pos);
+        }
     }
 }
 
@@ -72,7 +216,9 @@ impl OwnedBuf {
 pub struct BufferPool {
     available: Vec<OwnedBuf>,
     quarantine: VecDeque<OwnedBuf>,
+    quarantine_timeout: Duration,
     buffer_size: usize,
+    stats: BufferPoolStats,
 }
 
 impl BufferPool {
@@ -85,43 +231,142 @@ impl BufferPool {
         Self {
             available,
             quarantine: VecDeque::new(),
+            quarantine_timeout: QUARANTINE_TIMEOUT_DEFAULT,
             buffer_size,
+            stats: BufferPoolStats::default(),
         }
     }
 
+    /// Set how long a poisoned buffer stays quarantined without a completion.
+    pub fn with_quarantine_timeout(mut self, timeout: Duration) -> Self {
+        self.quarantine_timeout = timeout;
+        self
+    }
+
     /// Acquire a buffer from the pool.
+    ///
+    /// Quarantined buffers are only handed out once their timeout has
+    /// elapsed. Otherwise the pool grows rather than risk handing out memory
+    /// the kernel may still write into.
     pub fn acquire(&mut self) -> Option<OwnedBuf> {
-        if let Some(mut buf) = self.available.pop() {
-            buf.reset();
-            return Some(buf);
-        }
+        let mut buf = if let Some(buf) = self.available.pop() {
+            buf
+        } else if let Some(buf) = self.take_expired(Instant::now()) {
+            self.stats.quarantine_expired += 1;
+            buf
+        } else {
+            // Grow the pool
+            self.stats.grown += 1;
+            OwnedBuf::with_capacity(self.buffer_size)
+        };
+        buf.reset();
 
-        // Try quarantine if old enough
-        if let Some(mut buf) = self.quarantine.pop_front() {
-            buf.reset();
-            return Some(buf);
+        self.stats.acquired += 1;
+        self.stats.outstanding_high_water =
+            std::cmp::max(self.stats.outstanding_high_water, self.outstanding());
+
+        Some(buf)
+    }
+
+    /// Add `count` buffers to the available set.
+    pub fn grow(&mut self, count: usize) {
+        for _ in 0..count {
+            self.available
+                .push(OwnedBuf::with_capacity(self.buffer_size));
         }
+    }
 
-        // Grow the pool
-        Some(OwnedBuf::with_capacity(self.buffer_size))
+    /// Free up to `count` available buffers.
+    ///
+    /// Buffers that are acquired or quarantined are kept. Returns the number
+    /// freed.
+    pub fn shrink(&mut self, count: usize) -> usize {
+        let count = count.min(self.available.len());
+        self.available.truncate(self.available.len() - count);
+        count
     }
 
     /// Release a buffer back to the pool.
     pub fn release(&mut self, buf: OwnedBuf) {
+        assert!(
+            self.outstanding() > 0,
+            "released more buffers than acquired"
+        );
+        self.stats.released += 1;
+
         if buf.is_poisoned() {
+            self.stats.quarantined += 1;
             self.quarantine.push_back(buf);
         } else {
+            assert!(
+                buf.owner().is_none(),
+                "released buffer still owned by {:?}",
+                buf.owner()
+            );
             self.available.push(buf);
         }
     }
 
+    /// Confirm that a cancelled operation has completed.
+    ///
+    /// Any buffer quarantined on behalf of `op` is returned to the available
+    /// set. Returns true if a buffer was released.
+    pub fn complete_op(&mut self, op: OpId) -> bool {
+        let position = self
+            .quarantine
+            .iter()
+            .position(|buf| buf.poison.is_some_and(|p| p.op == Some(op)));
+
+        match position.and_then(|i| self.quarantine.remove(i)) {
+            Some(mut buf) => {
+                buf.reset();
+                self.available.push(buf);
+                self.stats.quarantine_completed += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of buffers currently held in quarantine.
+    pub fn quarantined(&self) -> usize {
+        self.quarantine.len()
+    }
+
+    /// Number of buffers acquired but not yet released.
+    pub fn outstanding(&self) -> u64 {
+        self.stats.outstanding()
+    }
+
+    /// Get a snapshot of the pool statistics.
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            quarantine_depth: self.quarantined() as u64,
+            ..self.stats
+        }
+    }
+
     /// Mark all quarantined buffers as safe.
+    ///
+    /// Only valid once no operation can still be using them, e.g. after all
+    /// connections are closed.
     pub fn clear_quarantine(&mut self) {
         while let Some(mut buf) = self.quarantine.pop_front() {
             buf.reset();
             self.available.push(buf);
         }
     }
+
+    /// Take the oldest quarantined buffer if its timeout has elapsed.
+    ///
+    /// Buffers are quarantined in order, so only the front needs checking.
+    fn take_expired(&mut self, now: Instant) -> Option<OwnedBuf> {
+        let poison = self.quarantine.front()?.poison?;
+        if now.duration_since(poison.at) < self.quarantine_timeout {
+            return None;
+        }
+        self.quarantine.pop_front()
+    }
 }
 
 #[cfg(test)]
@@ -134,11 +379,31 @@ mod tests {
         assert_eq!(buf.capacity(), 1024);
         assert_eq!(buf.len(), 0);
 
-        buf.as_mut_slice()[..5].copy_from_slice(b"hello");
+        buf.data[..5].copy_from_slice(b"hello");
         buf.set_len(5);
         assert_eq!(buf.as_slice(), b"hello");
     }
 
+    #[test]
+    fn test_owned_buf_owner() {
+        let mut buf = OwnedBuf::with_capacity(16);
+        assert_eq!(buf.owner(), None);
+
+        buf.set_owner(OpId(7));
+        assert_eq!(buf.owner(), Some(OpId(7)));
+
+        buf.clear_owner();
+        assert_eq!(buf.owner(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer already owned")]
+    fn test_owned_buf_double_owner() {
+        let mut buf = OwnedBuf::with_capacity(16);
+        buf.set_owner(OpId(1));
+        buf.set_owner(OpId(2));
+    }
+
     #[test]
     fn test_buffer_pool() {
         let mut pool = BufferPool::new(2, 1024);
@@ -152,6 +417,19 @@ mod tests {
         pool.release(buf2);
     }
 
+    #[test]
+    fn test_buffer_pool_grow_shrink() {
+        let mut pool = BufferPool::new(1, 1024);
+        pool.grow(2);
+        assert_eq!(pool.available.len(), 3);
+
+        let buf = pool.acquire().unwrap();
+        assert_eq!(pool.shrink(3), 2);
+        assert_eq!(pool.stats().grown, 0);
+        pool.release(buf);
+        assert_eq!(pool.available.len(), 1);
+    }
+
     #[test]
     fn test_poisoned_buffer() {
         let mut pool = BufferPool::new(1, 1024);
@@ -160,8 +438,153 @@ mod tests {
         buf.poison();
         pool.release(buf);
 
-        // Should get from quarantine
+        // Should not reuse the quarantined buffer, grows instead
+        let buf2 = pool.acquire().unwrap();
+        assert!(!buf2.is_poisoned());
+        assert_eq!(pool.quarantined(), 1);
+        assert_eq!(pool.stats().grown, 1);
+        pool.release(buf2);
+    }
+
+    #[test]
+    fn test_poisoned_buffer_released_on_completion() {
+        let mut pool = BufferPool::new(1, 1024);
+
+        let mut buf = pool.acquire().unwrap();
+        buf.set_owner(OpId(42));
+        buf.poison();
+        pool.release(buf);
+        assert_eq!(pool.quarantined(), 1);
+
+        // Completion of an unrelated op does not release it
+        assert!(!pool.complete_op(OpId(41)));
+        assert_eq!(pool.quarantined(), 1);
+
+        assert!(pool.complete_op(OpId(42)));
+        assert_eq!(pool.quarantined(), 0);
+        assert_eq!(pool.stats().quarantine_completed, 1);
+
+        // Reused without growing
+        let buf = pool.acquire().unwrap();
+        assert!(!buf.is_poisoned());
+        assert_eq!(buf.owner(), None);
+        assert_eq!(pool.stats().grown, 0);
+        pool.release(buf);
+    }
+
+    #[test]
+    fn test_poisoned_buffer_released_after_timeout() {
+        let mut pool = BufferPool::new(0, 1024).with_quarantine_timeout(Duration::ZERO);
+
+        let mut buf = pool.acquire().unwrap();
+        buf.set_owner(OpId(1));
+        buf.poison();
+        pool.release(buf);
+
+        let buf = pool.acquire().unwrap();
+        assert!(!buf.is_poisoned());
+        assert_eq!(pool.quarantined(), 0);
+        assert_eq!(pool.stats().quarantine_expired, 1);
+        assert_eq!(pool.stats().grown, 1);
+        pool.release(buf);
+    }
+
+    #[test]
+    fn test_clear_quarantine() {
+        let mut pool = BufferPool::new(1, 1024);
+
+        let mut buf = pool.acquire().unwrap();
+        buf.poison();
+        pool.release(buf);
+
+        pool.clear_quarantine();
+        assert_eq!(pool.quarantined(), 0);
+
+        let buf = pool.acquire().unwrap();
+        assert_eq!(pool.stats().grown, 0);
+        pool.release(buf);
+    }
+
+    #[test]
+    fn test_buffer_pool_stats() {
+        let mut pool = BufferPool::new(1, 1024);
+
+        let buf1 = pool.acquire().unwrap();
         let buf2 = pool.acquire().unwrap();
-        assert!(!buf2.is_poisoned()); // Reset clears poison
+        assert_eq!(pool.outstanding(), 2);
+        assert_eq!(pool.stats().grown, 1);
+
+        pool.release(buf1);
+        pool.release(buf2);
+        assert_eq!(pool.outstanding(), 0);
+
+        let stats = pool.stats();
+        assert_eq!(stats.acquired, 2);
+        assert_eq!(stats.released, 2);
+        assert_eq!(stats.outstanding_high_water, 2);
+        assert_eq!(stats.quarantined, 0);
+        assert_eq!(stats.quarantine_depth, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "released more buffers than acquired")]
+    fn test_buffer_pool_release_foreign_buffer() {
+        let mut pool = BufferPool::new(1, 1024);
+        pool.release(OwnedBuf::with_capacity(1024));
+    }
+
+    #[test]
+    #[should_panic(expected = "released buffer still owned")]
+    fn test_buffer_pool_release_owned_buffer() {
+        let mut pool = BufferPool::new(1, 1024);
+        let mut buf = pool.acquire().unwrap();
+        buf.set_owner(OpId(1));
+        pool.release(buf);
+    }
+
+    #[test]
+    fn test_recv_buf_into_inner() {
+        let parked: Parked = Rc::new(RefCell::new(Vec::new()));
+        let completions: Completions = Rc::new(RefCell::new(Vec::new()));
+        let mut buf = OwnedBuf::with_capacity(16);
+        buf.set_len(4);
+
+        let mut lent = RecvBuf::new(buf, OpId(3), parked.clone(), completions.clone());
+        assert_eq!(lent.bytes_init(), 0);
+        assert_eq!(lent.bytes_total(), 16);
+        // SAFETY: The buffer is zero-initialized.
+        unsafe { lent.set_init(5) };
+
+        let buf = lent.into_inner();
+        assert_eq!(buf.len(), 5);
+        assert_eq!(buf.owner(), None);
+        assert!(!buf.is_poisoned());
+        assert!(parked.borrow().is_empty());
+        assert!(completions.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_recv_buf_dropped_parks_buffer() {
+        let parked: Parked = Rc::new(RefCell::new(Vec::new()));
+        let completions: Completions = Rc::new(RefCell::new(Vec::new()));
+        let mut pool = BufferPool::new(1, 16);
+        let buf = pool.acquire().unwrap();
+
+        drop(RecvBuf::new(
+            buf,
+            OpId(3),
+            parked.clone(),
+            completions.clone(),
+        ));
+        assert_eq!(*completions.borrow(), vec![OpId(3)]);
+        let buf = parked.borrow_mut().pop().unwrap();
+        assert!(buf.is_poisoned());
+
+        // The recorded completion releases the parked buffer.
+        pool.release(buf);
+        assert_eq!(pool.quarantined(), 1);
+        assert!(pool.complete_op(OpId(3)));
+        assert_eq!(pool.quarantined(), 0);
+        assert_eq!(pool.outstanding(), 0);
     }
 }
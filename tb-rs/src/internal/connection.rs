@@ -1,13 +1,19 @@
 //! TCP connection wrapper for io_uring.
 
-use std::cell::RefCell;
-use std::net::SocketAddr;
+use std::cell::Cell;
+use std::future::Future;
+use std::net::{Shutdown, SocketAddr};
+use std::os::fd::{AsRawFd, BorrowedFd};
 use std::rc::Rc;
 use std::time::Duration;
 
-use tokio_uring::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio_uring::buf::IoBufMut;
+use tokio_uring::net::{TcpStream, UdpSocket};
 
-use crate::error::{ClientError, Result};
+use super::writer::SendQueue;
+use crate::error::{ClientError, Result, TransportError, TransportErrorKind};
+use crate::protocol::Message;
 
 /// Connection state.
 pub enum ConnectionState {
@@ -17,7 +23,7 @@ pub enum ConnectionState {
 
 impl ConnectionState {
     pub fn is_connected(&self) -> bool {
-        matches!(self, ConnectionState::Connected(_))
+        matches!(self, ConnectionState::Connected(conn) if conn.is_alive())
     }
 
     pub fn take(&mut self) -> Option<Connection> {
@@ -28,26 +34,70 @@ impl ConnectionState {
     }
 }
 
+/// Settings for establishing a connection.
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectionConfig {
+    /// Time allowed for the connection to be established.
+    pub timeout: Duration,
+    /// Whether the stream can also send with `IORING_OP_SEND_ZC`.
+    pub zero_copy: bool,
+    /// Bytes the send queue holds before senders wait.
+    pub send_queue_limit: u32,
+}
+
 /// A TCP connection to a TigerBeetle replica.
+///
+/// Sends are queued for a writer task and receives happen on a reader task,
+/// both sharing the stream through a [`StreamHandle`].
 pub struct Connection {
-    stream: Rc<RefCell<Option<TcpStream>>>,
+    stream: Rc<TcpStream>,
     addr: SocketAddr,
+    replica: u8,
+    id: u64,
+    alive: Rc<Cell<bool>>,
+    zero_copy: Option<Rc<UdpSocket>>,
+    queue: Rc<SendQueue>,
+    reader: Option<JoinHandle<()>>,
+    writer: Option<JoinHandle<()>>,
 }
 
 impl Connection {
     /// Connect to the given address.
-    pub async fn connect(addr: SocketAddr, _timeout: Duration) -> Result<Self> {
-        let stream = TcpStream::connect(addr)
-            .await
-            .map_err(|e| ClientError::Connection(format!("failed to connect to {}: {}", addr, e)))?;
+    ///
+    /// `replica` is the index of the replica at `addr`, and `id`
+    /// distinguishes this connection from earlier connections to the same
+    /// replica. Fails with [`ClientError::ConnectTimeout`] if the connection
+    /// is not established within the configured timeout, since a blackholed
+    /// address would otherwise never complete.
+    pub async fn connect(
+        replica: u8,
+        addr: SocketAddr,
+        config: ConnectionConfig,
+        id: u64,
+    ) -> Result<Self> {
+        let connect = TcpStream::connect(addr);
+        let stream = connect_within(replica, addr, config.timeout, connect).await?;
+
+        let connect_failed =
+            |e| TransportError::from_io(replica, TransportErrorKind::ConnectFailed, e);
+        stream.set_nodelay(true).map_err(connect_failed)?;
 
-        stream
-            .set_nodelay(true)
-            .map_err(|e| ClientError::Connection(format!("failed to set nodelay: {}", e)))?;
+        let zero_copy = if config.zero_copy {
+            Some(Rc::new(zero_copy_socket(&stream).map_err(connect_failed)?))
+        } else {
+            None
+        };
 
         Ok(Self {
-            stream: Rc::new(RefCell::new(Some(stream))),
+            stream: Rc::new(stream),
             addr,
+            replica,
+            id,
+            alive: Rc::new(Cell::new(true)),
+            zero_copy,
+            queue: Rc::new(SendQueue::new(config.send_queue_limit)),
+            reader: None,
+            writer: None,
         })
     }
 
@@ -56,65 +106,87 @@ impl Connection {
         self.addr
     }
 
-    /// Send data.
+    /// Get the connection identifier.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Check whether the connection is still usable.
     ///
-    /// # Safety Note
-    /// The RefCell borrow held across await is safe because tokio_uring is single-threaded
-    /// and Connection is !Send, so the Future cannot be polled from different threads.
-    #[allow(clippy::await_holding_refcell_ref)]
-    pub async fn send(&self, data: &[u8]) -> Result<()> {
-        let stream_ref = self.stream.borrow();
-        let stream = stream_ref
-            .as_ref()
-            .ok_or_else(|| ClientError::Connection("connection closed".into()))?;
-
-        let mut written = 0;
-        while written < data.len() {
-            let buf: Vec<u8> = data[written..].to_vec();
-            let (result, _buf): (std::io::Result<usize>, Vec<u8>) =
-                stream.write(buf).submit().await;
-            let n = result
-                .map_err(|e| ClientError::Connection(format!("write failed: {}", e)))?;
-            if n == 0 {
-                return Err(ClientError::Connection("connection closed".into()));
-            }
-            written += n;
+    /// Becomes false once the reader observes an error or end of stream.
+    pub fn is_alive(&self) -> bool {
+        self.alive.get()
+    }
+
+    /// Create a handle to the stream for a reader or writer task.
+    pub fn handle(&self) -> StreamHandle {
+        StreamHandle {
+            stream: self.stream.clone(),
+            zero_copy: self.zero_copy.clone(),
+            replica: self.replica,
+            id: self.id,
+            alive: self.alive.clone(),
         }
+    }
 
-        Ok(())
+    /// Get the queue drained by the writer task.
+    pub fn queue(&self) -> Rc<SendQueue> {
+        self.queue.clone()
     }
 
-    /// Receive data into a buffer.
+    /// Attach the reader and writer tasks, which are awaited on close.
+    pub fn set_tasks(&mut self, reader: JoinHandle<()>, writer: JoinHandle<()>) {
+        assert!(self.reader.is_none(), "connection already has tasks");
+        self.reader = Some(reader);
+        self.writer = Some(writer);
+    }
+
+    /// Queue a message for the writer task, waiting while the queue is full.
     ///
-    /// Returns (bytes_read, buffer).
+    /// Returns once the message is queued. A failed write surfaces later as
+    /// a closed connection.
+    pub async fn send(&self, message: &Message) -> Result<()> {
+        if !self.is_alive() {
+            return Err(self.not_connected());
+        }
+        self.queue
+            .push(message)
+            .await
+            .map_err(|_| self.not_connected())
+    }
+
+    /// Queue a message unless the queue is full.
     ///
-    /// # Safety Note
-    /// The RefCell borrow held across await is safe because tokio_uring is single-threaded
-    /// and Connection is !Send, so the Future cannot be polled from different threads.
-    #[allow(clippy::await_holding_refcell_ref)]
-    pub async fn recv(&self, buf: Vec<u8>) -> Result<(usize, Vec<u8>)> {
-        let stream_ref = self.stream.borrow();
-        let stream = stream_ref
-            .as_ref()
-            .ok_or_else(|| ClientError::Connection("connection closed".into()))?;
-
-        let (result, buf): (std::io::Result<usize>, Vec<u8>) = stream.read(buf).await;
-        let n = result.map_err(|e| {
-            if e.kind() == std::io::ErrorKind::UnexpectedEof
-                || e.kind() == std::io::ErrorKind::ConnectionReset
-            {
-                ClientError::Connection("connection closed".into())
-            } else {
-                ClientError::Connection(format!("read failed: {}", e))
-            }
-        })?;
+    /// Returns whether the message was queued.
+    pub fn try_send(&self, message: &Message) -> Result<bool> {
+        if !self.is_alive() {
+            return Err(self.not_connected());
+        }
+        self.queue
+            .try_push(message)
+            .map_err(|_| self.not_connected())
+    }
 
-        Ok((n, buf))
+    fn not_connected(&self) -> ClientError {
+        TransportError::new(self.replica, TransportErrorKind::NotConnected).into()
     }
 
     /// Close the connection.
-    pub async fn close(self) {
-        let _ = self.stream.borrow_mut().take();
+    ///
+    /// Closing the queue stops the writer, and shutting the socket down
+    /// completes any pending receive or write, so both tasks exit and the
+    /// reader returns its buffer before this returns. Unwritten messages are
+    /// dropped.
+    pub async fn close(mut self) {
+        self.alive.set(false);
+        self.queue.close();
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.await;
+        }
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.await;
+        }
     }
 }
 
@@ -122,6 +194,266 @@ impl std::fmt::Debug for Connection {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Connection")
             .field("addr", &self.addr)
+            .field("id", &self.id)
+            .field("alive", &self.alive.get())
             .finish()
     }
 }
+
+/// Shared handle to a connection's stream, owned by its reader and writer
+/// tasks.
+#[derive(Clone)]
+pub struct StreamHandle {
+    stream: Rc<TcpStream>,
+    zero_copy: Option<Rc<UdpSocket>>,
+    replica: u8,
+    id: u64,
+    alive: Rc<Cell<bool>>,
+}
+
+impl StreamHandle {
+    /// Get the connection identifier.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Check whether the connection has not been closed or failed.
+    pub fn is_alive(&self) -> bool {
+        self.alive.get()
+    }
+
+    /// Mark the connection unusable and shut the socket down, so the other
+    /// task's pending operation completes too.
+    pub fn fail(&self) {
+        self.alive.set(false);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Receive data into a buffer.
+    ///
+    /// Returns the number of bytes read and the buffer, which is handed back
+    /// on error too. Zero bytes means the peer closed the connection.
+    pub async fn recv<B: IoBufMut>(&self, buf: B) -> (Result<usize>, B) {
+        let (result, buf) = self.stream.read(buf).await;
+        let result = result
+            .map_err(|e| TransportError::from_io(self.replica, TransportErrorKind::Read, e).into());
+        (result, buf)
+    }
+
+    /// Write every buffer in order, issuing as few vectored writes as
+    /// partial writes allow.
+    ///
+    /// Returns the number of write submissions.
+    pub async fn writev_all(&self, mut bufs: Vec<Vec<u8>>) -> Result<u32> {
+        let mut writes = 0;
+        while !bufs.is_empty() {
+            let (result, returned) = self.stream.writev(bufs).await;
+            bufs = returned;
+            writes += 1;
+
+            let n = result
+                .map_err(|e| TransportError::from_io(self.replica, TransportErrorKind::Write, e))?;
+            if n == 0 {
+                return Err(
+                    TransportError::new(self.replica, TransportErrorKind::PeerClosed).into(),
+                );
+            }
+            advance(&mut bufs, n);
+        }
+        Ok(writes)
+    }
+
+    /// Check whether the stream can send with `IORING_OP_SEND_ZC`.
+    pub fn has_zero_copy(&self) -> bool {
+        self.zero_copy.is_some()
+    }
+
+    /// Write a buffer with zero-copy sends.
+    ///
+    /// The kernel transmits straight from `buf` and each send completes only
+    /// after its notification that the kernel released the pages, so the
+    /// buffer is safe to reuse once returned. Returns the number of sends, or
+    /// `None` with nothing written if the kernel does not support zero-copy
+    /// sends (before 6.0), so the caller can fall back to a normal write.
+    pub async fn send_zc_all(&self, mut buf: Vec<u8>) -> (Result<Option<u32>>, Vec<u8>) {
+        let socket = self.zero_copy.as_ref().expect("zero-copy not enabled");
+        let mut sends = 0;
+        while !buf.is_empty() {
+            let (result, returned) = socket.send_zc(buf).await;
+            buf = returned;
+            sends += 1;
+
+            let n = match result {
+                Ok(n) => n,
+                Err(e) if sends == 1 && zero_copy_unsupported(&e) => return (Ok(None), buf),
+                Err(e) => {
+                    let error = TransportError::from_io(self.replica, TransportErrorKind::Write, e);
+                    return (Err(error.into()), buf);
+                }
+            };
+            if n == 0 {
+                let error = TransportError::new(self.replica, TransportErrorKind::PeerClosed);
+                return (Err(error.into()), buf);
+            }
+            buf.drain(..n);
+        }
+        (Ok(Some(sends)), buf)
+    }
+}
+
+/// Duplicate a stream's descriptor as a socket exposing zero-copy sends.
+///
+/// tokio-uring only offers `send_zc` on its UDP socket type. The operation
+/// itself is a plain send on a connected socket, so a duplicate of the TCP
+/// descriptor behaves as a second handle to the same stream.
+fn zero_copy_socket(stream: &TcpStream) -> std::io::Result<UdpSocket> {
+    // SAFETY: the descriptor belongs to `stream`, which outlives the borrow;
+    // `try_clone_to_owned` duplicates it before the borrow ends.
+    let fd = unsafe { BorrowedFd::borrow_raw(stream.as_raw_fd()) }.try_clone_to_owned()?;
+    Ok(UdpSocket::from_std(std::net::UdpSocket::from(fd)))
+}
+
+/// Check whether a send failed because zero-copy is unsupported.
+fn zero_copy_unsupported(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::InvalidInput | std::io::ErrorKind::Unsupported
+    )
+}
+
+/// Drop the first `n` bytes from a list of buffers.
+fn advance(bufs: &mut Vec<Vec<u8>>, mut n: usize) {
+    let mut done = 0;
+    while done < bufs.len() && n >= bufs[done].len() {
+        n -= bufs[done].len();
+        done += 1;
+    }
+    bufs.drain(..done);
+    if n > 0 {
+        bufs[0].drain(..n);
+    }
+}
+
+/// Await a connect future, giving up after `timeout`.
+///
+/// Dropping the connect future cancels the in-flight io_uring operation.
+async fn connect_within<T>(
+    replica: u8,
+    addr: SocketAddr,
+    timeout: Duration,
+    connect: impl Future<Output = std::io::Result<T>>,
+) -> Result<T> {
+    tokio::time::timeout(timeout, connect)
+        .await
+        .map_err(|_| ClientError::ConnectTimeout(addr))?
+        .map_err(|e| TransportError::from_io(replica, TransportErrorKind::ConnectFailed, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        "192.0.2.1:3000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut bufs = vec![vec![1, 2], vec![3, 4, 5], vec![6]];
+        advance(&mut bufs, 0);
+        assert_eq!(bufs.len(), 3);
+        advance(&mut bufs, 3);
+        assert_eq!(bufs, vec![vec![4, 5], vec![6]]);
+        advance(&mut bufs, 2);
+        assert_eq!(bufs, vec![vec![6]]);
+        advance(&mut bufs, 1);
+        assert!(bufs.is_empty());
+    }
+
+    #[test]
+    fn test_send_zc_all() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let data: Vec<u8> = (0..256 * 1024).map(|i| i as u8).collect();
+        let expected = data.clone();
+
+        let peer = std::thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            let mut received = vec![0u8; expected.len()];
+            peer.read_exact(&mut received).unwrap();
+            assert_eq!(received, expected);
+        });
+
+        tokio_uring::start(async {
+            let config = ConnectionConfig {
+                timeout: Duration::from_secs(5),
+                zero_copy: true,
+                send_queue_limit: 1 << 20,
+            };
+            let conn = Connection::connect(0, addr, config, 0).await.unwrap();
+            let handle = conn.handle();
+            assert!(handle.has_zero_copy());
+
+            let (result, buf) = handle.send_zc_all(data).await;
+            match result.unwrap() {
+                Some(sends) => {
+                    assert!(sends >= 1);
+                    assert!(buf.is_empty());
+                }
+                // Kernel without zero-copy sends: nothing was written.
+                None => {
+                    assert!(handle.writev_all(vec![buf]).await.unwrap() >= 1);
+                }
+            }
+            conn.close().await;
+        });
+        peer.join().unwrap();
+    }
+
+    #[test]
+    fn test_zero_copy_unsupported() {
+        use std::io::{Error, ErrorKind};
+        assert!(zero_copy_unsupported(&Error::from_raw_os_error(22)));
+        assert!(zero_copy_unsupported(&Error::from(ErrorKind::Unsupported)));
+        assert!(!zero_copy_unsupported(&Error::from(
+            ErrorKind::ConnectionReset
+        )));
+    }
+
+    #[test]
+    fn test_connect_within_times_out() {
+        tokio_uring::start(async {
+            let connect = std::future::pending::<std::io::Result<()>>();
+            let err = connect_within(0, addr(), Duration::from_millis(10), connect)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ClientError::ConnectTimeout(a) if a == addr()));
+        });
+    }
+
+    #[test]
+    fn test_connect_within_error() {
+        tokio_uring::start(async {
+            let connect =
+                async { Err::<(), _>(std::io::Error::from(std::io::ErrorKind::ConnectionRefused)) };
+            let err = connect_within(0, addr(), Duration::from_secs(5), connect)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                err,
+                ClientError::Io(e) if e.kind() == TransportErrorKind::ConnectFailed
+            ));
+        });
+    }
+
+    #[test]
+    fn test_connect_within_success() {
+        tokio_uring::start(async {
+            let connect = async { Ok(7u32) };
+            let value = connect_within(0, addr(), Duration::from_secs(5), connect).await;
+            assert_eq!(value.unwrap(), 7);
+        });
+    }
+}
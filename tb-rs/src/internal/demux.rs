@@ -0,0 +1,118 @@
+//! Demultiplexing queue between connection readers and the client.
+//!
+//! Every connection runs a reader task that pushes complete messages here as
+//! they arrive. The client consumes the queue while waiting for a reply, so
+//! replies from hedged replicas, late replies to earlier requests, and
+//! evictions are all observed regardless of which request is waiting.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::task::{Poll, Waker};
+
+use crate::error::ClientError;
+use crate::protocol::Message;
+
+/// An event produced by a connection reader.
+#[derive(Debug)]
+pub enum Inbound {
+    /// A complete message with valid header and body checksums.
+    Message {
+        /// Replica index the message arrived from.
+        replica: usize,
+        message: Message,
+    },
+    /// The reader stopped and the connection can no longer be used.
+    Closed {
+        /// Replica index of the connection.
+        replica: usize,
+        /// Identifier of the connection, so a close of a connection that has
+        /// since been replaced can be told apart from the current one.
+        connection: u64,
+        error: ClientError,
+    },
+}
+
+/// Single-consumer queue fed by connection readers.
+#[derive(Debug, Default)]
+pub struct Demux {
+    queue: RefCell<VecDeque<Inbound>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Demux {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue an event and wake the consumer.
+    pub fn push(&self, inbound: Inbound) {
+        self.queue.borrow_mut().push_back(inbound);
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+
+    /// Wait for the next event.
+    pub async fn next(&self) -> Inbound {
+        poll_fn(|cx| match self.queue.borrow_mut().pop_front() {
+            Some(inbound) => Poll::Ready(inbound),
+            None => {
+                *self.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closed(replica: usize) -> Inbound {
+        Inbound::Closed {
+            replica,
+            connection: 0,
+            error: ClientError::Timeout,
+        }
+    }
+
+    #[test]
+    fn test_demux_fifo() {
+        let demux = Demux::new();
+        demux.push(closed(0));
+        demux.push(Inbound::Message {
+            replica: 1,
+            message: Message::new(),
+        });
+
+        tokio_uring::start(async {
+            assert!(matches!(
+                demux.next().await,
+                Inbound::Closed { replica: 0, .. }
+            ));
+            assert!(matches!(
+                demux.next().await,
+                Inbound::Message { replica: 1, .. }
+            ));
+        });
+        assert!(demux.queue.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_demux_wakes_waiting_consumer() {
+        tokio_uring::start(async {
+            let demux = std::rc::Rc::new(Demux::new());
+            let producer = demux.clone();
+            tokio_uring::spawn(async move {
+                producer.push(closed(2));
+            });
+            assert!(matches!(
+                demux.next().await,
+                Inbound::Closed { replica: 2, .. }
+            ));
+        });
+    }
+}
@@ -1,46 +1,142 @@
 //! I/O driver managing connections to cluster replicas.
 
+use std::cell::{Cell, RefCell};
 use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use super::buffer::OwnedBuf;
-use super::connection::{Connection, ConnectionState};
-use crate::error::{ClientError, Result};
+use tokio::task::JoinHandle;
+
+use super::buffer::{BufferPool, Completions, OpId, OwnedBuf, Parked, RecvBuf};
+use super::connection::{Connection, ConnectionConfig, ConnectionState, StreamHandle};
+use super::demux::{Demux, Inbound};
+use super::probe;
+use super::reader;
+use super::writer::{self, SendQueue};
+use crate::error::{ClientError, Result, TransportError, TransportErrorKind};
+use crate::metrics::{BufferPoolStats, ReplicaStats};
+use crate::protocol::Message;
+
+/// Delay before a replica that failed to connect is dialed again in the
+/// background.
+pub const REDIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest a removed replica's connection may take to write what was
+/// already queued before it is closed.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A connection attempt running as a separate task.
+type Dial = JoinHandle<Result<Connection>>;
 
 /// I/O driver for TigerBeetle cluster communication.
 ///
-/// Manages connections to all replicas and handles send/recv operations.
+/// Manages connections to all replicas. Each connection runs a writer task
+/// that drains its send queue and a reader task that delivers complete
+/// messages to a shared demux queue, consumed with [`Driver::next_inbound`].
+/// Connections are dialed on tasks too, so several replicas can be dialed at
+/// once and reconnects can run in the background.
 /// This type is `!Send` because io_uring is thread-local.
 pub struct Driver {
     connections: Vec<ConnectionState>,
-    addresses: Vec<SocketAddr>,
+    /// Connection attempt in progress per replica.
+    dials: Vec<Option<Dial>>,
+    /// Earliest time each replica that failed to connect is dialed again by
+    /// [`Driver::keep_warm`].
+    redial_at: Vec<Option<Instant>>,
+    /// Connection candidates per replica, in preference order.
+    addresses: Vec<Vec<SocketAddr>>,
     connect_timeout: Duration,
-    start_time: Instant,
+    send_queue_limit: u32,
+    next_connection: u64,
+    /// Background probe task, if probing.
+    probe: Option<JoinHandle<()>>,
+    /// Buffers this driver added to a shared pool.
+    reserved: usize,
+    shared: Rc<Shared>,
     _not_send: PhantomData<Rc<()>>,
 }
 
 impl Driver {
     /// Create a new driver.
-    pub fn new(addresses: Vec<SocketAddr>, connect_timeout: Duration) -> Self {
-        let connections = addresses.iter().map(|_| ConnectionState::Disconnected).collect();
+    ///
+    /// Each replica has one or more resolved candidate addresses, e.g. the
+    /// A and AAAA records of a hostname. Reader tasks take their receive
+    /// buffers from `pool`.
+    pub fn new(
+        addresses: Vec<Vec<SocketAddr>>,
+        connect_timeout: Duration,
+        pool: BufferPool,
+    ) -> Self {
+        Self::with_core(
+            addresses,
+            connect_timeout,
+            Rc::new(DriverCore::new(pool)),
+            0,
+        )
+    }
+
+    /// Create a driver sharing receive buffers with other drivers.
+    ///
+    /// `reserved` buffers, one per reader, are added to the core's pool and
+    /// removed again by [`Driver::close`].
+    pub fn with_core(
+        addresses: Vec<Vec<SocketAddr>>,
+        connect_timeout: Duration,
+        core: Rc<DriverCore>,
+        reserved: usize,
+    ) -> Self {
+        assert!(addresses.iter().all(|candidates| !candidates.is_empty()));
+        let connections = addresses
+            .iter()
+            .map(|_| ConnectionState::Disconnected)
+            .collect();
+        core.pool.borrow_mut().grow(reserved);
+        let shared = Rc::new(Shared::new(core, addresses.len()));
 
         Self {
             connections,
+            dials: addresses.iter().map(|_| None).collect(),
+            redial_at: vec![None; addresses.len()],
             addresses,
             connect_timeout,
-            start_time: Instant::now(),
+            send_queue_limit: writer::SEND_QUEUE_LIMIT_DEFAULT,
+            next_connection: 0,
+            probe: None,
+            reserved,
+            shared,
             _not_send: PhantomData,
         }
     }
 
+    /// Send large messages with zero-copy sends.
+    ///
+    /// Messages of at least
+    /// [`SEND_ZERO_COPY_SIZE_MIN`](writer::SEND_ZERO_COPY_SIZE_MIN) bytes are
+    /// sent with `IORING_OP_SEND_ZC`, avoiding the copy into kernel socket
+    /// buffers. If the kernel rejects zero-copy sends, every connection falls
+    /// back to normal writes.
+    pub fn with_send_zero_copy(self, enabled: bool) -> Self {
+        self.shared.zero_copy.set(enabled);
+        self
+    }
+
+    /// Limit the bytes queued per connection before senders wait.
+    pub fn with_send_queue_limit(mut self, limit: u32) -> Self {
+        assert!(limit > 0);
+        self.send_queue_limit = limit;
+        self
+    }
+
     /// Get the number of replicas.
     pub fn replica_count(&self) -> usize {
         self.addresses.len()
     }
 
-    /// Connect to a replica.
+    /// Connect to a replica and start its reader and writer tasks.
+    ///
+    /// Waits for a connection attempt already running in the background
+    /// rather than starting another.
     pub async fn connect(&mut self, idx: usize) -> Result<()> {
         if idx >= self.addresses.len() {
             return Err(ClientError::Connection(format!(
@@ -52,11 +148,202 @@ impl Driver {
         if self.connections[idx].is_connected() {
             return Ok(());
         }
+        self.start_dial(idx);
+        self.finish_dial(idx).await
+    }
 
-        let addr = self.addresses[idx];
-        let conn = Connection::connect(addr, self.connect_timeout).await?;
-        self.connections[idx] = ConnectionState::Connected(conn);
+    /// Connect to every replica at once.
+    ///
+    /// Succeeds if at least one replica is connected, otherwise fails with
+    /// the first replica's error.
+    pub async fn connect_all(&mut self) -> Result<()> {
+        for idx in 0..self.addresses.len() {
+            if !self.connections[idx].is_connected() {
+                self.start_dial(idx);
+            }
+        }
+
+        let mut error = None;
+        for idx in 0..self.addresses.len() {
+            if self.dials[idx].is_some() {
+                if let Err(e) = self.finish_dial(idx).await {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        match error {
+            Some(e) if !self.connections.iter().any(|c| c.is_connected()) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Keep every replica connected without waiting for connections.
+    ///
+    /// Installs connections dialed in the background and starts dialing
+    /// replicas whose connection was lost. A replica that failed to connect
+    /// is dialed again after [`REDIAL_DELAY`].
+    pub async fn keep_warm(&mut self) {
+        self.install_dials().await;
+        for idx in 0..self.addresses.len() {
+            self.dial_in_background(idx);
+        }
+    }
+
+    /// Install connections whose background dial has finished.
+    pub async fn install_dials(&mut self) {
+        for idx in 0..self.addresses.len() {
+            if self.dials[idx]
+                .as_ref()
+                .is_some_and(|dial| dial.is_finished())
+            {
+                // Failures are counted in the replica's statistics.
+                let _ = self.finish_dial(idx).await;
+            }
+        }
+    }
+
+    /// Start dialing a replica in the background if it is not connected.
+    ///
+    /// Does nothing while a dial is in progress or within [`REDIAL_DELAY`]
+    /// of a failed one. The connection is installed by a later
+    /// [`Driver::install_dials`], [`Driver::keep_warm`] or
+    /// [`Driver::connect`].
+    pub fn dial_in_background(&mut self, idx: usize) {
+        let due = self.redial_at[idx].map_or(true, |at| at <= Instant::now());
+        if due && !self.connections[idx].is_connected() {
+            self.start_dial(idx);
+        }
+    }
+
+    /// Replace the candidate addresses of every replica.
+    ///
+    /// Replicas whose candidates are unchanged, in any order, are left
+    /// alone, as is a connection whose address is still a candidate. Any
+    /// other connection is drained for up to [`DRAIN_TIMEOUT`] and closed,
+    /// and if it was connected the replica is dialed again in the
+    /// background. Returns the indexes of replicas whose candidates changed.
+    pub async fn update_addresses(&mut self, addresses: Vec<Vec<SocketAddr>>) -> Vec<usize> {
+        assert_eq!(addresses.len(), self.addresses.len());
+        assert!(addresses.iter().all(|candidates| !candidates.is_empty()));
+
+        let mut changed = Vec::new();
+        for (idx, mut candidates) in addresses.into_iter().enumerate() {
+            let mut old = self.addresses[idx].clone();
+            old.sort();
+            let mut new = candidates.clone();
+            new.sort();
+            if old == new {
+                continue;
+            }
+            changed.push(idx);
+
+            // A dial in progress may reach a removed address.
+            if let Some(dial) = self.dials[idx].take() {
+                dial.abort();
+            }
+            self.redial_at[idx] = None;
+
+            let removed = match &self.connections[idx] {
+                ConnectionState::Connected(conn) => {
+                    match candidates.iter().position(|&addr| addr == conn.addr()) {
+                        Some(kept) => {
+                            candidates[..=kept].rotate_right(1);
+                            None
+                        }
+                        None => Some((conn.queue(), conn.is_alive())),
+                    }
+                }
+                ConnectionState::Disconnected => None,
+            };
+            self.addresses[idx] = candidates;
+
+            if let Some((queue, alive)) = removed {
+                let _ = tokio::time::timeout(DRAIN_TIMEOUT, queue.flushed()).await;
+                self.disconnect(idx).await;
+                if alive {
+                    self.start_dial(idx);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Probe every connection in the background.
+    ///
+    /// Each live connection is sent `ping` every `interval`, and one that
+    /// stays silent for [`PROBE_MISSES_MAX`](probe::PROBE_MISSES_MAX)
+    /// intervals is closed with an [`Inbound::Closed`] event.
+    pub fn start_probing(&mut self, interval: Duration, ping: Message) {
+        assert!(self.probe.is_none(), "already probing");
+        assert!(!interval.is_zero());
+        self.probe = Some(tokio_uring::spawn(probe::probe_loop(
+            self.shared.clone(),
+            interval,
+            ping,
+        )));
+    }
+
+    /// Start dialing a replica on a separate task, unless already dialing.
+    fn start_dial(&mut self, idx: usize) {
+        if self.dials[idx].is_some() {
+            return;
+        }
+
+        let id = self.next_connection;
+        self.next_connection += 1;
+        let config = ConnectionConfig {
+            timeout: self.connect_timeout,
+            zero_copy: self.shared.zero_copy.get(),
+            send_queue_limit: self.send_queue_limit,
+        };
+        self.dials[idx] = Some(tokio_uring::spawn(dial(
+            idx,
+            self.addresses[idx].clone(),
+            config,
+            id,
+            self.shared.clone(),
+        )));
+    }
+
+    /// Wait for a replica's dial and install the connection it produced.
+    async fn finish_dial(&mut self, idx: usize) -> Result<()> {
+        let dial = self.dials[idx].take().expect("replica is not being dialed");
+        let mut conn = match dial.await.expect("dial task failed") {
+            Ok(conn) => conn,
+            Err(e) => {
+                self.redial_at[idx] = Some(Instant::now() + REDIAL_DELAY);
+                return Err(e);
+            }
+        };
+        self.redial_at[idx] = None;
 
+        // A connection whose reader or writer failed is replaced.
+        self.disconnect(idx).await;
+
+        // The candidate that connected moves to the front so reconnects skip
+        // candidates that are known not to work.
+        let candidates = &mut self.addresses[idx];
+        let winner = candidates
+            .iter()
+            .position(|&addr| addr == conn.addr())
+            .expect("connected to a candidate address");
+        candidates[..=winner].rotate_right(1);
+
+        let reader = tokio_uring::spawn(reader::read_loop(idx, conn.handle(), self.shared.clone()));
+        let writer = tokio_uring::spawn(writer::write_loop(
+            idx,
+            conn.handle(),
+            conn.queue(),
+            self.shared.clone(),
+        ));
+        conn.set_tasks(reader, writer);
+        self.shared.links.borrow_mut()[idx] = Some(Link {
+            handle: conn.handle(),
+            queue: conn.queue(),
+            last_seen: Rc::new(Cell::new(Instant::now())),
+        });
+        self.connections[idx] = ConnectionState::Connected(conn);
         Ok(())
     }
 
@@ -65,73 +352,801 @@ impl Driver {
         idx < self.connections.len() && self.connections[idx].is_connected()
     }
 
+    /// Get the address a replica is connected at, if connected.
+    pub fn connected_address(&self, idx: usize) -> Option<SocketAddr> {
+        match self.connections.get(idx) {
+            Some(ConnectionState::Connected(conn)) if conn.is_alive() => Some(conn.addr()),
+            _ => None,
+        }
+    }
+
+    /// Check whether `connection` is the current connection to a replica.
+    pub fn is_current(&self, idx: usize, connection: u64) -> bool {
+        match self.connections.get(idx) {
+            Some(ConnectionState::Connected(conn)) => conn.id() == connection,
+            _ => false,
+        }
+    }
+
     /// Disconnect from a replica.
     pub async fn disconnect(&mut self, idx: usize) {
         if idx >= self.connections.len() {
             return;
         }
 
+        self.shared.links.borrow_mut()[idx] = None;
         if let Some(conn) = self.connections[idx].take() {
             conn.close().await;
         }
     }
 
-    /// Send data to a replica.
-    pub async fn send(&self, idx: usize, data: &[u8]) -> Result<()> {
-        let conn = match &self.connections[idx] {
-            ConnectionState::Connected(c) => c,
-            ConnectionState::Disconnected => {
-                return Err(ClientError::Connection("not connected".into()));
-            }
-        };
-
-        conn.send(data).await
+    /// Send a message to a replica.
+    ///
+    /// The message is queued for the connection's writer task, which
+    /// coalesces everything queued into one vectored write. A failed write
+    /// arrives later through [`Driver::next_inbound`] as a closed connection.
+    ///
+    /// Waits while the connection's send queue is full, which applies
+    /// backpressure when a replica stops reading.
+    pub async fn send(&self, idx: usize, message: &Message) -> Result<()> {
+        let conn = self.connection(idx)?;
+        if conn.try_send(message)? {
+            return Ok(());
+        }
+        self.shared.replicas.borrow_mut()[idx].send_queue_full += 1;
+        conn.send(message).await
     }
 
-    /// Receive data from a replica.
+    /// Send a message to a replica unless its send queue is full.
     ///
-    /// Takes ownership of the buffer and returns it with received data.
-    pub async fn recv(&self, idx: usize, mut buf: OwnedBuf) -> Result<OwnedBuf> {
-        let conn = match &self.connections[idx] {
-            ConnectionState::Connected(c) => c,
+    /// Returns whether the message was queued. Used for sends that are not
+    /// worth waiting for, such as hedged copies of a request.
+    pub fn try_send(&self, idx: usize, message: &Message) -> Result<bool> {
+        let queued = self.connection(idx)?.try_send(message)?;
+        if !queued {
+            self.shared.replicas.borrow_mut()[idx].send_queue_full += 1;
+        }
+        Ok(queued)
+    }
+
+    fn connection(&self, idx: usize) -> Result<&Connection> {
+        match &self.connections[idx] {
+            ConnectionState::Connected(conn) => Ok(conn),
             ConnectionState::Disconnected => {
-                return Err(ClientError::Connection("not connected".into()));
+                Err(TransportError::new(idx as u8, TransportErrorKind::NotConnected).into())
             }
-        };
+        }
+    }
+
+    /// Wait for the next message or connection failure from any replica.
+    pub async fn next_inbound(&self) -> Inbound {
+        self.shared.demux.next().await
+    }
+
+    /// Get a snapshot of the receive buffer pool statistics.
+    pub fn pool_stats(&self) -> BufferPoolStats {
+        self.shared.core.pool_stats()
+    }
 
-        let capacity = buf.capacity();
-        let recv_buf = vec![0u8; capacity];
+    /// Get a snapshot of the transport statistics of every replica.
+    pub fn replica_stats(&self) -> Vec<ReplicaStats> {
+        self.shared.replicas.borrow().clone()
+    }
 
-        let (n, recv_buf) = conn.recv(recv_buf).await?;
+    /// Number of receive buffers outstanding beyond those held by readers.
+    ///
+    /// Each running reader holds at most one buffer, so anything beyond that
+    /// was never returned to the pool.
+    pub fn leaked_buffers(&self) -> u64 {
+        let core = &self.shared.core;
+        core.reclaim();
+        let outstanding = core.pool.borrow().outstanding();
+        outstanding.saturating_sub(core.readers.get() as u64)
+    }
 
-        buf.as_mut_slice()[..n].copy_from_slice(&recv_buf[..n]);
-        buf.set_len(n);
+    /// Get the smoothed round-trip time of a replica, if measured.
+    pub fn rtt(&self, idx: usize) -> Option<Duration> {
+        self.shared.replicas.borrow()[idx].rtt()
+    }
 
-        Ok(buf)
+    /// Record a round-trip time measured for a replica, e.g. of a request.
+    pub fn record_rtt(&self, idx: usize, sample: Duration) {
+        self.shared.replicas.borrow_mut()[idx].record_rtt(sample);
     }
 
     /// Get monotonic time in nanoseconds.
     pub fn now_ns(&self) -> u64 {
-        self.start_time.elapsed().as_nanos() as u64
+        self.shared.now_ns()
     }
 
     /// Disconnect all connections.
+    ///
+    /// Once every reader sharing the pool has stopped no operation can use a
+    /// buffer, so the quarantine is cleared.
     pub async fn close(&mut self) {
+        if let Some(probe) = self.probe.take() {
+            probe.abort();
+        }
+        for dial in self.dials.iter_mut().filter_map(Option::take) {
+            dial.abort();
+        }
         for idx in 0..self.connections.len() {
             self.disconnect(idx).await;
         }
+        let core = &self.shared.core;
+        core.reclaim();
+        let mut pool = core.pool.borrow_mut();
+        pool.shrink(std::mem::take(&mut self.reserved));
+        if core.readers.get() == 0 {
+            pool.clear_quarantine();
+        }
+    }
+}
+
+/// Try a replica's candidate addresses in order until one connects.
+async fn dial(
+    idx: usize,
+    candidates: Vec<SocketAddr>,
+    config: ConnectionConfig,
+    id: u64,
+    shared: Rc<Shared>,
+) -> Result<Connection> {
+    let mut error = None;
+    for addr in candidates {
+        let started = Instant::now();
+        let connected = Connection::connect(idx as u8, addr, config, id).await;
+
+        let stats = &mut shared.replicas.borrow_mut()[idx];
+        let connects = stats.connect_attempts - stats.connect_failures;
+        stats.connect_attempts += 1;
+        match connected {
+            Ok(conn) => {
+                if connects > 0 {
+                    stats.reconnects += 1;
+                }
+                // The handshake took one round trip, which measures a
+                // backup that neither replies nor is probed.
+                stats.record_rtt(started.elapsed());
+                return Ok(conn);
+            }
+            Err(e) => {
+                stats.connect_failures += 1;
+                error = Some(e);
+            }
+        }
+    }
+
+    Err(error.expect("replica has no candidate addresses"))
+}
+
+/// Receive buffers and operation tracking, shared by every driver of a
+/// [`SharedDriver`](crate::SharedDriver).
+///
+/// Replicas bind each connection to one client, so connections are never
+/// shared; only what reader tasks need to receive is.
+pub struct DriverCore {
+    pool: RefCell<BufferPool>,
+    next_op: Cell<u64>,
+    /// Buffers whose receive was cancelled, awaiting quarantine.
+    cancelled: Parked,
+    /// Cancelled operations the kernel has since completed.
+    completions: Completions,
+    /// Number of running reader tasks.
+    readers: Cell<u32>,
+    start_time: Instant,
+}
+
+impl DriverCore {
+    /// Create a core whose readers take receive buffers from `pool`.
+    pub fn new(pool: BufferPool) -> Self {
+        Self {
+            pool: RefCell::new(pool),
+            next_op: Cell::new(0),
+            cancelled: Rc::new(RefCell::new(Vec::new())),
+            completions: Rc::new(RefCell::new(Vec::new())),
+            readers: Cell::new(0),
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Get a snapshot of the receive buffer pool statistics.
+    pub fn pool_stats(&self) -> BufferPoolStats {
+        self.reclaim();
+        self.pool.borrow().stats()
+    }
+
+    /// Return buffers from cancelled receives to the pool.
+    ///
+    /// Parked buffers enter quarantine, and those whose operation has since
+    /// completed are released for reuse.
+    fn reclaim(&self) {
+        let mut pool = self.pool.borrow_mut();
+        for buf in self.cancelled.borrow_mut().drain(..) {
+            pool.release(buf);
+        }
+        for op in self.completions.borrow_mut().drain(..) {
+            pool.complete_op(op);
+        }
+    }
+
+    /// Allocate an identifier for a new I/O operation.
+    fn allocate_op(&self) -> OpId {
+        let op = self.next_op.get();
+        self.next_op.set(op + 1);
+        OpId(op)
+    }
+}
+
+/// Driver state shared with connection reader tasks.
+pub struct Shared {
+    core: Rc<DriverCore>,
+    /// Whether large messages are sent with zero-copy sends. Cleared when
+    /// the kernel turns out not to support them.
+    pub zero_copy: Cell<bool>,
+    /// Transport statistics per replica.
+    pub replicas: RefCell<Vec<ReplicaStats>>,
+    /// Current connection per replica, for the probe task.
+    pub links: RefCell<Vec<Option<Link>>>,
+    pub demux: Demux,
+}
+
+/// A replica's current connection as seen by background tasks.
+#[derive(Clone)]
+pub struct Link {
+    pub handle: StreamHandle,
+    pub queue: Rc<SendQueue>,
+    /// When a message last arrived on the connection.
+    pub last_seen: Rc<Cell<Instant>>,
+}
+
+impl Shared {
+    fn new(core: Rc<DriverCore>, replica_count: usize) -> Self {
+        Self {
+            core,
+            zero_copy: Cell::new(false),
+            replicas: RefCell::new(vec![ReplicaStats::default(); replica_count]),
+            links: RefCell::new(vec![None; replica_count]),
+            demux: Demux::new(),
+        }
+    }
+
+    /// Get monotonic time in nanoseconds since the core was created.
+    pub fn now_ns(&self) -> u64 {
+        self.core.start_time.elapsed().as_nanos() as u64
+    }
+
+    /// Record that a message arrived on a replica's connection.
+    pub fn seen(&self, replica: usize, connection: u64) {
+        if let Some(link) = &self.links.borrow()[replica] {
+            if link.handle.id() == connection {
+                link.last_seen.set(Instant::now());
+            }
+        }
+    }
+
+    /// Record the round trip of the probe a replica answered with `pong`.
+    pub fn pong(&self, replica: usize, pong: &Message) {
+        let sent = pong.header().as_pong_client().ping_timestamp_monotonic;
+        let now = self.now_ns();
+        // Pings sent without a timestamp from this clock cannot be timed.
+        if sent == 0 || sent > now {
+            return;
+        }
+        self.replicas.borrow_mut()[replica].record_rtt(Duration::from_nanos(now - sent));
+    }
+
+    /// Acquire a receive buffer, first reclaiming any from cancelled receives.
+    pub fn acquire(&self) -> Option<OwnedBuf> {
+        self.core.reclaim();
+        self.core.pool.borrow_mut().acquire()
+    }
+
+    /// Return a receive buffer to the pool.
+    pub fn release(&self, buf: OwnedBuf) {
+        self.core.pool.borrow_mut().release(buf);
+    }
+
+    /// Record that a reader task started.
+    pub fn reader_started(&self) {
+        let readers = &self.core.readers;
+        readers.set(readers.get() + 1);
+    }
+
+    /// Record that a reader task stopped.
+    pub fn reader_stopped(&self) {
+        let readers = &self.core.readers;
+        assert!(readers.get() > 0);
+        readers.set(readers.get() - 1);
+    }
+
+    /// Receive data from a connection into a pooled buffer.
+    ///
+    /// Takes ownership of the buffer and returns it with received data. The
+    /// buffer is handed back on error too, so the caller can return it to
+    /// the pool.
+    pub async fn recv(&self, half: &StreamHandle, buf: OwnedBuf) -> (Result<()>, OwnedBuf) {
+        // The kernel reads straight into the pooled buffer. If the future is
+        // dropped first, the buffer is parked poisoned once the kernel is done
+        // with it, see `reclaim`.
+        let op = self.core.allocate_op();
+        let core = &self.core;
+        let lent = RecvBuf::new(buf, op, core.cancelled.clone(), core.completions.clone());
+        let (result, lent) = half.recv(lent).await;
+        (result.map(|_| ()), lent.into_inner())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+
+    fn test_driver(addrs: Vec<Vec<SocketAddr>>) -> Driver {
+        Driver::new(addrs, Duration::from_secs(5), BufferPool::new(2, 4096))
+    }
+
+    /// Bind and drop a listener, leaving a port that refuses connections.
+    fn refused_addr() -> SocketAddr {
+        let refused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        refused.local_addr().unwrap()
+    }
 
     #[test]
     fn test_driver_creation() {
-        let addrs = vec!["127.0.0.1:3001".parse().unwrap()];
-        let driver = Driver::new(addrs, Duration::from_secs(5));
+        let driver = test_driver(vec![vec!["127.0.0.1:3001".parse().unwrap()]]);
         assert_eq!(driver.replica_count(), 1);
         assert!(!driver.is_connected(0));
+        assert!(!driver.is_current(0, 0));
+        assert_eq!(driver.connected_address(0), None);
+    }
+
+    #[test]
+    fn test_driver_allocate_op() {
+        let driver = test_driver(vec![vec!["127.0.0.1:3001".parse().unwrap()]]);
+        assert_eq!(driver.shared.core.allocate_op(), OpId(0));
+        assert_eq!(driver.shared.core.allocate_op(), OpId(1));
+    }
+
+    #[test]
+    fn test_driver_connect_falls_back_to_next_candidate() {
+        let refused_addr = refused_addr();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listening_addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![refused_addr, listening_addr]]);
+            driver.connect(0).await.unwrap();
+            assert!(driver.is_connected(0));
+            assert!(driver.is_current(0, 0));
+            assert_eq!(driver.connected_address(0), Some(listening_addr));
+            assert_eq!(driver.addresses[0], vec![listening_addr, refused_addr]);
+            driver.close().await;
+            assert_eq!(driver.leaked_buffers(), 0);
+            assert_eq!(driver.pool_stats().outstanding(), 0);
+        });
+    }
+
+    #[test]
+    fn test_driver_connect_all_candidates_fail() {
+        let refused_addr = refused_addr();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![refused_addr]]);
+            assert!(driver.connect(0).await.is_err());
+            assert!(!driver.is_connected(0));
+        });
+    }
+
+    #[test]
+    fn test_driver_reader_reports_peer_close() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![addr]]);
+            driver.connect(0).await.unwrap();
+            let (peer, _) = listener.accept().unwrap();
+            drop(peer);
+
+            match driver.next_inbound().await {
+                Inbound::Closed {
+                    replica,
+                    connection,
+                    error,
+                } => {
+                    assert_eq!(replica, 0);
+                    assert!(driver.is_current(0, connection));
+                    assert!(matches!(
+                        error,
+                        ClientError::Io(e) if e.kind() == TransportErrorKind::PeerClosed
+                    ));
+                }
+                Inbound::Message { .. } => panic!("expected close"),
+            }
+            assert!(!driver.is_connected(0));
+
+            // Reconnecting replaces the dead connection.
+            driver.connect(0).await.unwrap();
+            assert!(driver.is_current(0, 1));
+
+            let stats = driver.replica_stats()[0];
+            assert_eq!(stats.connect_attempts, 2);
+            assert_eq!(stats.reconnects, 1);
+            assert_eq!(stats.receive_errors, 1);
+            driver.close().await;
+            assert_eq!(driver.pool_stats().outstanding(), 0);
+        });
+    }
+
+    #[test]
+    fn test_driver_reader_reports_half_close() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![addr]]);
+            driver.connect(0).await.unwrap();
+            let (peer, _) = listener.accept().unwrap();
+            // Only the peer's sending side closes, so writes would still
+            // succeed; end of stream alone must fail the connection.
+            peer.shutdown(std::net::Shutdown::Write).unwrap();
+
+            match driver.next_inbound().await {
+                Inbound::Closed { error, .. } => assert!(matches!(
+                    error,
+                    ClientError::Io(e) if e.kind() == TransportErrorKind::PeerClosed
+                )),
+                Inbound::Message { .. } => panic!("expected close"),
+            }
+            assert!(!driver.is_connected(0));
+            assert!(matches!(
+                driver.send(0, &Message::new()).await,
+                Err(ClientError::Io(e)) if e.kind() == TransportErrorKind::NotConnected
+            ));
+            drop(peer);
+            driver.close().await;
+            assert_eq!(driver.pool_stats().outstanding(), 0);
+        });
+    }
+
+    #[test]
+    fn test_driver_reader_rejects_corrupt_stream() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![addr]]);
+            driver.connect(0).await.unwrap();
+            let (mut peer, _) = listener.accept().unwrap();
+            peer.write_all(&[0xff; 256]).unwrap();
+
+            match driver.next_inbound().await {
+                Inbound::Closed { error, .. } => assert!(matches!(
+                    error,
+                    ClientError::Protocol(crate::error::ProtocolError::InvalidHeaderChecksum)
+                )),
+                Inbound::Message { .. } => panic!("expected close"),
+            }
+            driver.close().await;
+        });
+    }
+
+    #[test]
+    fn test_driver_reply_larger_than_pool_buffer() {
+        use crate::protocol::{Command, Header, HEADER_SIZE};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let body = vec![7u8; 64 * 1024];
+        let mut header = Header::new(0);
+        header.command = Command::Reply as u8;
+        header.size = HEADER_SIZE + body.len() as u32;
+        header.set_checksum_body(&body);
+        header.set_checksum();
+        let mut reply = header.as_bytes().to_vec();
+        reply.extend_from_slice(&body);
+
+        let peer = std::thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            peer.write_all(&reply).unwrap();
+            peer
+        });
+
+        tokio_uring::start(async {
+            // Pool buffers of 4 KiB, far smaller than the reply.
+            let mut driver = test_driver(vec![vec![addr]]);
+            driver.connect(0).await.unwrap();
+            match driver.next_inbound().await {
+                Inbound::Message { message, .. } => assert_eq!(message.body(), &body[..]),
+                Inbound::Closed { error, .. } => panic!("unexpected close: {:?}", error),
+            }
+            assert!(driver.replica_stats()[0].bytes_received > 64 * 1024);
+            driver.close().await;
+            assert_eq!(driver.pool_stats().outstanding(), 0);
+        });
+        drop(peer.join().unwrap());
+    }
+
+    #[test]
+    fn test_driver_transport_stats() {
+        use crate::protocol::{Command, Header, HEADER_SIZE};
+        use std::io::Read;
+
+        let refused_addr = refused_addr();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut header = Header::new(0);
+        header.command = Command::Reply as u8;
+        header.size = HEADER_SIZE;
+        header.set_checksum_body(&[]);
+        header.set_checksum();
+        let reply = header.as_bytes().to_vec();
+
+        let mut request = Message::new();
+        request.header_mut().command = Command::Request as u8;
+        let mut ping = Message::new();
+        ping.header_mut().command = Command::PingClient as u8;
+        let request_size = request.as_bytes().len();
+        let sent = 2 * request_size + ping.as_bytes().len();
+
+        // The peer runs on its own thread, since blocking the runtime thread
+        // would stall the writer task.
+        let peer = std::thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            let mut received = vec![0u8; sent];
+            peer.read_exact(&mut received).unwrap();
+            peer.write_all(&reply).unwrap();
+            received
+        });
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![refused_addr, addr]]);
+            driver.connect(0).await.unwrap();
+
+            // Queued before the writer task runs, so written together.
+            driver.send(0, &request).await.unwrap();
+            driver.send(0, &ping).await.unwrap();
+            driver.send(0, &request).await.unwrap();
+            assert!(matches!(
+                driver.next_inbound().await,
+                Inbound::Message { replica: 0, .. }
+            ));
+
+            let stats = driver.replica_stats()[0];
+            assert_eq!(stats.connect_attempts, 2);
+            assert_eq!(stats.connect_failures, 1);
+            assert_eq!(stats.reconnects, 0);
+            assert_eq!(stats.bytes_sent, sent as u64);
+            assert_eq!(stats.writes, 1);
+            assert_eq!(stats.messages_sent.get(Command::Request), 2);
+            assert_eq!(stats.messages_sent.get(Command::PingClient), 1);
+            assert_eq!(stats.bytes_received, HEADER_SIZE as u64);
+            assert_eq!(stats.messages_received.get(Command::Reply), 1);
+            assert_eq!(stats.errors(), 1);
+
+            // A local close is not a receive error.
+            driver.close().await;
+            assert_eq!(driver.replica_stats()[0].receive_errors, 0);
+        });
+
+        let received = peer.join().unwrap();
+        assert_eq!(&received[..request_size], request.as_bytes());
+        assert_eq!(&received[sent - request_size..], request.as_bytes());
+    }
+
+    #[test]
+    fn test_driver_send_zero_copy() {
+        use crate::protocol::Command;
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut small = Message::new();
+        small.header_mut().command = Command::PingClient as u8;
+        let mut large = Message::new();
+        large.header_mut().command = Command::Request as u8;
+        large.set_body(&vec![7u8; writer::SEND_ZERO_COPY_SIZE_MIN as usize]);
+        let mut expected = small.as_bytes().to_vec();
+        expected.extend_from_slice(large.as_bytes());
+        expected.extend_from_slice(small.as_bytes());
+        let sent = expected.len();
+
+        let peer = std::thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            let mut received = vec![0u8; sent];
+            peer.read_exact(&mut received).unwrap();
+            assert!(received == expected, "bytes sent out of order");
+            // Closing tells the driver everything was received.
+        });
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![addr]]).with_send_zero_copy(true);
+            driver.connect(0).await.unwrap();
+            driver.send(0, &small).await.unwrap();
+            driver.send(0, &large).await.unwrap();
+            driver.send(0, &small).await.unwrap();
+            assert!(matches!(
+                driver.next_inbound().await,
+                Inbound::Closed { replica: 0, .. }
+            ));
+
+            let stats = driver.replica_stats()[0];
+            assert_eq!(stats.bytes_sent, sent as u64);
+            assert_eq!(stats.messages_sent.total(), 3);
+            assert_eq!(stats.send_errors, 0);
+            // Zero if the kernel lacks zero-copy sends and the driver fell back.
+            if driver.shared.zero_copy.get() {
+                assert!(stats.zero_copy_sends >= 1);
+                assert_eq!(stats.writes, 2 + stats.zero_copy_sends);
+            } else {
+                assert_eq!(stats.zero_copy_sends, 0);
+            }
+            driver.close().await;
+        });
+        peer.join().unwrap();
+    }
+
+    #[test]
+    fn test_driver_send_queue_full() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![addr]]).with_send_queue_limit(1);
+            driver.connect(0).await.unwrap();
+            let (_peer, _) = listener.accept().unwrap();
+
+            // The writer has not run yet, so the first message fills the queue.
+            let message = Message::new();
+            assert!(driver.try_send(0, &message).unwrap());
+            assert!(!driver.try_send(0, &message).unwrap());
+            assert_eq!(driver.replica_stats()[0].send_queue_full, 1);
+
+            // Waits for the writer to drain the queue.
+            driver.send(0, &message).await.unwrap();
+            assert_eq!(driver.replica_stats()[0].send_queue_full, 2);
+            driver.close().await;
+            assert!(driver.try_send(0, &message).is_err());
+        });
+    }
+
+    #[test]
+    fn test_driver_connect_all() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![refused_addr()], vec![addr]]);
+            driver.connect_all().await.unwrap();
+            assert!(!driver.is_connected(0));
+            assert!(driver.is_connected(1));
+            assert_eq!(driver.replica_stats()[0].connect_failures, 1);
+            driver.close().await;
+
+            let mut driver = test_driver(vec![vec![refused_addr()], vec![refused_addr()]]);
+            assert!(driver.connect_all().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_driver_keep_warm_reconnects() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![addr]]);
+            driver.connect_all().await.unwrap();
+            drop(listener.accept().unwrap());
+            assert!(matches!(
+                driver.next_inbound().await,
+                Inbound::Closed { connection: 0, .. }
+            ));
+            assert!(!driver.is_connected(0));
+
+            // The first call starts dialing, a later one installs the result.
+            for _ in 0..100 {
+                driver.keep_warm().await;
+                if driver.is_connected(0) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(driver.is_current(0, 1));
+            assert_eq!(driver.replica_stats()[0].reconnects, 1);
+            driver.close().await;
+            assert_eq!(driver.pool_stats().outstanding(), 0);
+        });
+    }
+
+    #[test]
+    fn test_driver_keep_warm_delays_redial() {
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![refused_addr()]]);
+            assert!(driver.connect(0).await.is_err());
+
+            driver.keep_warm().await;
+            assert!(driver.dials[0].is_none());
+            driver.redial_at[0] = Some(Instant::now());
+            driver.keep_warm().await;
+            assert!(driver.dials[0].is_some());
+            driver.close().await;
+        });
+    }
+
+    #[test]
+    fn test_driver_update_addresses() {
+        let old = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let new = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let old_addr = old.local_addr().unwrap();
+        let new_addr = new.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = test_driver(vec![vec![old_addr], vec![old_addr]]);
+            driver.connect(0).await.unwrap();
+            driver.connect(1).await.unwrap();
+
+            // Replica 0 keeps its connection, replica 1 moves to a new address.
+            let changed = driver
+                .update_addresses(vec![vec![new_addr, old_addr], vec![new_addr]])
+                .await;
+            assert_eq!(changed, vec![0, 1]);
+            assert!(driver.is_current(0, 0));
+            assert_eq!(driver.addresses[0], vec![old_addr, new_addr]);
+            assert!(!driver.is_connected(1));
+            assert!(driver.dials[1].is_some());
+
+            driver.connect(1).await.unwrap();
+            assert!(driver.is_current(1, 2));
+            assert_eq!(driver.replica_stats()[1].reconnects, 1);
+
+            // Reordered candidates are not a change.
+            let changed = driver
+                .update_addresses(vec![vec![old_addr, new_addr], vec![new_addr]])
+                .await;
+            assert!(changed.is_empty());
+            driver.close().await;
+        });
+    }
+
+    #[test]
+    fn test_driver_shared_core() {
+        let listener_a = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener_b = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr_a = listener_a.local_addr().unwrap();
+        let addr_b = listener_b.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let core = Rc::new(DriverCore::new(BufferPool::new(0, 4096)));
+            let timeout = Duration::from_secs(5);
+            let mut a = Driver::with_core(vec![vec![addr_a]], timeout, core.clone(), 1);
+            let mut b = Driver::with_core(vec![vec![addr_b]], timeout, core.clone(), 1);
+
+            a.connect(0).await.unwrap();
+            b.connect(0).await.unwrap();
+            let (peer_a, _) = listener_a.accept().unwrap();
+            let (_peer_b, _) = listener_b.accept().unwrap();
+            drop(peer_a);
+
+            // Events reach only the driver whose connection produced them.
+            assert!(matches!(a.next_inbound().await, Inbound::Closed { .. }));
+            let next = tokio::time::timeout(Duration::from_millis(50), b.next_inbound()).await;
+            assert!(next.is_err());
+            assert!(b.is_connected(0));
+
+            a.close().await;
+            assert_eq!(core.readers.get(), 1);
+            b.close().await;
+            assert_eq!(core.readers.get(), 0);
+            // Both readers used a reserved buffer, and closing removed them.
+            let stats = core.pool_stats();
+            assert_eq!(stats.outstanding(), 0);
+            assert_eq!(stats.grown, 0);
+            let buf = core.pool.borrow_mut().acquire().unwrap();
+            assert_eq!(core.pool_stats().grown, 1);
+            core.pool.borrow_mut().release(buf);
+        });
     }
 }
@@ -1,11 +1,19 @@
 //! Internal implementation details.
 //!
-//! This module contains the io_uring driver, connection handling, and buffer
-//! management. These are implementation details and not part of the public API.
+//! This module contains the io_uring driver, connection handling, reader,
+//! writer and probe tasks, and buffer management. These are implementation
+//! details and not part of the public API.
 
 pub(crate) mod buffer;
 pub(crate) mod connection;
+pub(crate) mod demux;
 pub(crate) mod driver;
+pub(crate) mod probe;
+pub(crate) mod reader;
+pub(crate) mod writer;
 
-pub(crate) use buffer::{BufferPool, OwnedBuf};
-pub(crate) use driver::Driver;
+pub(crate) use buffer::{BufferPool, QUARANTINE_TIMEOUT_DEFAULT};
+pub(crate) use demux::Inbound;
+pub(crate) use driver::{Driver, DriverCore};
+pub(crate) use probe::PROBE_INTERVAL_DEFAULT;
+pub(crate) use writer::SEND_QUEUE_LIMIT_DEFAULT;
@@ -0,0 +1,187 @@
+//! Background health probing of replica connections.
+//!
+//! A probe task pings every live connection at a fixed interval. Pongs, like
+//! any other message, show that the replica is still there. A connection
+//! that stays silent for several intervals is failed, so a dead backup is
+//! noticed and replaced before a hedged request depends on it.
+
+use std::rc::Rc;
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+
+use super::demux::Inbound;
+use super::driver::Shared;
+use crate::error::{TransportError, TransportErrorKind};
+use crate::protocol::Message;
+
+/// Default interval between probes of a connection.
+pub const PROBE_INTERVAL_DEFAULT: Duration = Duration::from_secs(1);
+
+/// Probe intervals a connection may stay silent before it is failed.
+pub const PROBE_MISSES_MAX: u32 = 3;
+
+/// Ping every live connection each `interval` until aborted.
+///
+/// `ping` is a `PingClient` message for this client; its timestamp and
+/// checksums are updated for every probe.
+pub async fn probe_loop(shared: Rc<Shared>, interval: Duration, mut ping: Message) {
+    let silence_max = interval * PROBE_MISSES_MAX;
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticks.tick().await;
+
+        let replica_count = shared.links.borrow().len();
+        for replica in 0..replica_count {
+            let Some(link) = shared.links.borrow()[replica].clone() else {
+                continue;
+            };
+            if !link.handle.is_alive() {
+                continue;
+            }
+
+            if link.last_seen.get().elapsed() > silence_max {
+                shared.replicas.borrow_mut()[replica].probe_failures += 1;
+                link.handle.fail();
+                shared.demux.push(Inbound::Closed {
+                    replica,
+                    connection: link.handle.id(),
+                    error: TransportError::new(replica as u8, TransportErrorKind::Timeout).into(),
+                });
+                continue;
+            }
+
+            ping.header_mut()
+                .as_ping_client_mut()
+                .ping_timestamp_monotonic = shared.now_ns();
+            ping.finalize();
+            // A full queue already has traffic on the way, no probe needed.
+            if let Ok(true) = link.queue.try_push(&ping) {
+                shared.replicas.borrow_mut()[replica].probes_sent += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::error::ClientError;
+    use crate::internal::{BufferPool, Driver};
+    use crate::protocol::{Command, Header, HEADER_SIZE};
+    use zerocopy::FromBytes;
+
+    fn ping() -> Message {
+        let mut ping = Message::new();
+        ping.header_mut().set_command(Command::PingClient);
+        ping.header_mut().as_ping_client_mut().client = 7;
+        ping
+    }
+
+    fn driver(addr: std::net::SocketAddr) -> Driver {
+        Driver::new(
+            vec![vec![addr]],
+            Duration::from_secs(5),
+            BufferPool::new(2, 4096),
+        )
+    }
+
+    #[test]
+    fn test_probe_sends_pings() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = driver(addr);
+            driver.connect(0).await.unwrap();
+            let (mut peer, _) = listener.accept().unwrap();
+            driver.start_probing(Duration::from_millis(10), ping());
+
+            // Let the probe and writer tasks run before blocking on the peer.
+            while driver.replica_stats()[0].writes == 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let mut bytes = [0u8; HEADER_SIZE as usize];
+            peer.read_exact(&mut bytes).unwrap();
+            let header = Header::read_from_bytes(&bytes[..]).unwrap();
+            assert!(header.valid_checksum());
+            assert_eq!(header.command(), Some(Command::PingClient));
+            assert_eq!(header.as_ping_client().client, 7);
+            assert!(driver.replica_stats()[0].probes_sent >= 1);
+            driver.close().await;
+        });
+    }
+
+    #[test]
+    fn test_probe_measures_rtt() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Answer the first ping on the peer's own thread.
+        let peer = std::thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            let mut bytes = [0u8; HEADER_SIZE as usize];
+            peer.read_exact(&mut bytes).unwrap();
+            let ping = Header::read_from_bytes(&bytes[..]).unwrap();
+
+            let mut pong = Header::new(0);
+            pong.set_command(Command::PongClient);
+            pong.size = HEADER_SIZE;
+            pong.as_pong_client_mut().ping_timestamp_monotonic =
+                ping.as_ping_client().ping_timestamp_monotonic;
+            pong.set_checksum_body(&[]);
+            pong.set_checksum();
+            peer.write_all(pong.as_bytes()).unwrap();
+            peer
+        });
+
+        tokio_uring::start(async {
+            let mut driver = driver(addr);
+            driver.connect(0).await.unwrap();
+            // The handshake is the first sample.
+            assert_eq!(driver.replica_stats()[0].rtt_samples, 1);
+            driver.start_probing(Duration::from_millis(10), ping());
+
+            assert!(matches!(
+                driver.next_inbound().await,
+                Inbound::Message { replica: 0, .. }
+            ));
+            let stats = driver.replica_stats()[0];
+            assert_eq!(stats.rtt_samples, 2);
+            assert!(driver.rtt(0).unwrap() > Duration::ZERO);
+            driver.close().await;
+        });
+        drop(peer.join().unwrap());
+    }
+
+    #[test]
+    fn test_probe_fails_silent_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio_uring::start(async {
+            let mut driver = driver(addr);
+            driver.connect(0).await.unwrap();
+            let (_peer, _) = listener.accept().unwrap();
+            driver.start_probing(Duration::from_millis(10), ping());
+
+            match driver.next_inbound().await {
+                Inbound::Closed {
+                    replica: 0,
+                    connection: 0,
+                    error: ClientError::Io(e),
+                } if e.kind() == TransportErrorKind::Timeout => {}
+                other => panic!("expected probe failure, got {:?}", other),
+            }
+            assert!(!driver.is_connected(0));
+            assert_eq!(driver.replica_stats()[0].probe_failures, 1);
+            driver.close().await;
+            assert_eq!(driver.pool_stats().outstanding(), 0);
+        });
+    }
+}
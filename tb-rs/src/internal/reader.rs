@@ -0,0 +1,251 @@
+//! Per-connection reader task.
+//!
+//! Each connection has a task that reads from the socket for as long as the
+//! connection lives, assembles complete messages from the byte stream, and
+//! pushes them into the driver's demux queue. Reads are therefore never tied
+//! to the request that happens to be waiting.
+
+use std::rc::Rc;
+
+use zerocopy::FromBytes;
+
+use super::connection::StreamHandle;
+use super::demux::Inbound;
+use super::driver::Shared;
+use crate::error::{ClientError, ProtocolError, TransportError, TransportErrorKind};
+use crate::protocol::{Command, Header, Message, HEADER_SIZE, MESSAGE_SIZE_MAX};
+
+/// Accumulates received bytes and splits them into messages.
+///
+/// TCP delivers a byte stream, so a read may hold part of a message or
+/// several messages at once. A read is at most one pool buffer, but the frame
+/// grows to the size each header declares, so messages up to
+/// `MESSAGE_SIZE_MAX` are assembled from as many reads as they take.
+pub struct Frame {
+    data: Vec<u8>,
+}
+
+impl Frame {
+    /// Create an empty frame.
+    ///
+    /// Memory is allocated as messages arrive rather than up front, so idle
+    /// connections do not each hold a maximum-size buffer.
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    /// Append received bytes.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Take the next complete message, if one is buffered.
+    ///
+    /// Both checksums are verified. An error means the stream is corrupt and
+    /// cannot be resynchronized, so the connection must be dropped.
+    pub fn next_message(&mut self) -> Result<Option<Message>, ProtocolError> {
+        if self.data.len() < HEADER_SIZE as usize {
+            return Ok(None);
+        }
+
+        // Copy the header out, the buffer has no alignment guarantee.
+        let header = Header::read_from_bytes(&self.data[..HEADER_SIZE as usize])
+            .map_err(|_| ProtocolError::InvalidHeader)?;
+        if !header.valid_checksum() {
+            return Err(ProtocolError::InvalidHeaderChecksum);
+        }
+        if header.size < HEADER_SIZE || header.size > MESSAGE_SIZE_MAX {
+            return Err(ProtocolError::InvalidSize);
+        }
+
+        let size = header.size as usize;
+        if self.data.len() < size {
+            // Grow once to the whole message instead of on every read.
+            self.data.reserve_exact(size - self.data.len());
+            return Ok(None);
+        }
+        if !header.valid_checksum_body(&self.data[HEADER_SIZE as usize..size]) {
+            return Err(ProtocolError::InvalidBodyChecksum);
+        }
+
+        // A frame holding exactly one message hands its storage over.
+        let bytes = if self.data.len() == size {
+            std::mem::take(&mut self.data)
+        } else {
+            self.data.drain(..size).collect()
+        };
+        Ok(Message::from_bytes(bytes))
+    }
+}
+
+/// Read from a connection until it closes, feeding the demux queue.
+///
+/// Exits silently when the connection was closed locally. Otherwise a
+/// [`Inbound::Closed`] event tells the client why the connection died.
+pub async fn read_loop(replica: usize, half: StreamHandle, shared: Rc<Shared>) {
+    shared.reader_started();
+    let transport = |kind| ClientError::from(TransportError::new(replica as u8, kind));
+    let mut frame = Frame::new();
+
+    let error = 'read: loop {
+        let Some(buf) = shared.acquire() else {
+            break transport(TransportErrorKind::BuffersExhausted);
+        };
+
+        let (result, buf) = shared.recv(&half, buf).await;
+        if let Err(e) = result {
+            shared.release(buf);
+            break e;
+        }
+        if buf.len() == 0 {
+            shared.release(buf);
+            break transport(TransportErrorKind::PeerClosed);
+        }
+        shared.replicas.borrow_mut()[replica].bytes_received += buf.len() as u64;
+        frame.extend(buf.as_slice());
+        shared.release(buf);
+
+        loop {
+            match frame.next_message() {
+                Ok(Some(message)) => {
+                    shared.replicas.borrow_mut()[replica]
+                        .messages_received
+                        .record(message.header().command);
+                    shared.seen(replica, half.id());
+                    if message.header().command == Command::PongClient as u8 {
+                        shared.pong(replica, &message);
+                    }
+                    shared.demux.push(Inbound::Message { replica, message });
+                }
+                Ok(None) => break,
+                Err(e) => break 'read ClientError::Protocol(e),
+            }
+        }
+    };
+
+    if half.is_alive() {
+        // Shutting down also fails a write blocked on the dead connection.
+        half.fail();
+        shared.replicas.borrow_mut()[replica].receive_errors += 1;
+        shared.demux.push(Inbound::Closed {
+            replica,
+            connection: half.id(),
+            error,
+        });
+    }
+    shared.reader_stopped();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_bytes(body: &[u8]) -> Vec<u8> {
+        let mut header = Header::new(7);
+        header.command = Command::Reply as u8;
+        header.size = HEADER_SIZE + body.len() as u32;
+        header.set_checksum_body(body);
+        header.set_checksum();
+
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn test_frame_empty() {
+        let mut frame = Frame::new();
+        assert!(frame.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_frame_partial_message() {
+        let bytes = message_bytes(b"hello");
+        let mut frame = Frame::new();
+
+        frame.extend(&bytes[..100]);
+        assert!(frame.next_message().unwrap().is_none());
+        frame.extend(&bytes[100..bytes.len() - 1]);
+        assert!(frame.next_message().unwrap().is_none());
+        frame.extend(&bytes[bytes.len() - 1..]);
+
+        let message = frame.next_message().unwrap().unwrap();
+        assert_eq!(message.body(), b"hello");
+        assert!(frame.data.is_empty());
+    }
+
+    #[test]
+    fn test_frame_large_message() {
+        let body = vec![0xab; MESSAGE_SIZE_MAX as usize - HEADER_SIZE as usize];
+        let bytes = message_bytes(&body);
+        let mut frame = Frame::new();
+
+        frame.extend(&bytes[..HEADER_SIZE as usize]);
+        assert!(frame.next_message().unwrap().is_none());
+        assert!(frame.data.capacity() >= MESSAGE_SIZE_MAX as usize);
+        for chunk in bytes[HEADER_SIZE as usize..].chunks(4096) {
+            frame.extend(chunk);
+        }
+
+        let message = frame.next_message().unwrap().unwrap();
+        assert_eq!(message.body(), &body[..]);
+        assert_eq!(frame.data.capacity(), 0);
+    }
+
+    #[test]
+    fn test_frame_multiple_messages() {
+        let mut bytes = message_bytes(b"one");
+        bytes.extend(message_bytes(b"two"));
+        let three = message_bytes(b"three");
+        bytes.extend(&three[..10]);
+
+        let mut frame = Frame::new();
+        frame.extend(&bytes);
+        assert_eq!(frame.next_message().unwrap().unwrap().body(), b"one");
+        assert_eq!(frame.next_message().unwrap().unwrap().body(), b"two");
+        assert!(frame.next_message().unwrap().is_none());
+        frame.extend(&three[10..]);
+        assert_eq!(frame.next_message().unwrap().unwrap().body(), b"three");
+    }
+
+    #[test]
+    fn test_frame_invalid_header_checksum() {
+        let mut bytes = message_bytes(b"");
+        bytes[20] ^= 0xff;
+
+        let mut frame = Frame::new();
+        frame.extend(&bytes);
+        assert_eq!(
+            frame.next_message().unwrap_err(),
+            ProtocolError::InvalidHeaderChecksum
+        );
+    }
+
+    #[test]
+    fn test_frame_invalid_body_checksum() {
+        let mut bytes = message_bytes(b"body");
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let mut frame = Frame::new();
+        frame.extend(&bytes);
+        assert_eq!(
+            frame.next_message().unwrap_err(),
+            ProtocolError::InvalidBodyChecksum
+        );
+    }
+
+    #[test]
+    fn test_frame_invalid_size() {
+        let mut header = Header::new(7);
+        header.size = HEADER_SIZE - 1;
+        header.set_checksum();
+
+        let mut frame = Frame::new();
+        frame.extend(header.as_bytes());
+        assert_eq!(
+            frame.next_message().unwrap_err(),
+            ProtocolError::InvalidSize
+        );
+    }
+}
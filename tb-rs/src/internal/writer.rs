@@ -0,0 +1,405 @@
+//! Per-connection writer task.
+//!
+//! Messages sent to a replica are queued and written by a task that owns the
+//! sending side of the connection. Everything queued while a write is in
+//! flight is coalesced into the next vectored write, so pipelined requests
+//! and pings cost one submission instead of one per message. Large messages
+//! can instead be sent with zero-copy sends, which skip the copy into kernel
+//! socket buffers.
+//!
+//! The queue is bounded in bytes. A replica that stops reading fills it, and
+//! senders then wait instead of buffering without limit.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+use super::connection::StreamHandle;
+use super::demux::Inbound;
+use super::driver::Shared;
+use crate::error::Result;
+use crate::protocol::{Message, MESSAGE_SIZE_MAX};
+
+/// Maximum messages coalesced into one write, well below `IOV_MAX`.
+pub const WRITE_BATCH_MAX: u32 = 64;
+
+/// Smallest message sent with a zero-copy send when enabled.
+///
+/// Zero-copy sends pin pages and complete with an extra notification, which
+/// only pays off for large transfers.
+pub const SEND_ZERO_COPY_SIZE_MIN: u32 = 64 * 1024;
+
+/// Default bytes a send queue holds before senders wait.
+pub const SEND_QUEUE_LIMIT_DEFAULT: u32 = 4 * MESSAGE_SIZE_MAX;
+
+/// A message waiting to be written.
+pub struct Outgoing {
+    command: u8,
+    data: Vec<u8>,
+}
+
+/// Bounded queue of messages waiting for a connection's writer task.
+pub struct SendQueue {
+    pending: RefCell<VecDeque<Outgoing>>,
+    /// Bytes queued or being written.
+    bytes: Cell<u32>,
+    limit: u32,
+    writer: RefCell<Option<Waker>>,
+    sender: RefCell<Option<Waker>>,
+    closed: Cell<bool>,
+}
+
+/// The queue was closed and accepts no more messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueClosed;
+
+impl SendQueue {
+    /// Create an empty queue holding up to `limit` bytes.
+    ///
+    /// A message larger than the limit is still accepted into an empty
+    /// queue, so no message is refused outright.
+    pub fn new(limit: u32) -> Self {
+        assert!(limit > 0);
+        Self {
+            pending: RefCell::new(VecDeque::new()),
+            bytes: Cell::new(0),
+            limit,
+            writer: RefCell::new(None),
+            sender: RefCell::new(None),
+            closed: Cell::new(false),
+        }
+    }
+
+    /// Queue a copy of a message, waiting while the queue is full.
+    ///
+    /// Fails if the queue is closed, including while waiting.
+    pub async fn push(&self, message: &Message) -> std::result::Result<(), QueueClosed> {
+        poll_fn(|cx| match self.try_push(message) {
+            Ok(false) => {
+                *self.sender.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Ok(true) => Poll::Ready(Ok(())),
+            Err(e) => Poll::Ready(Err(e)),
+        })
+        .await
+    }
+
+    /// Queue a copy of a message unless the queue is full.
+    ///
+    /// Returns whether the message was queued.
+    pub fn try_push(&self, message: &Message) -> std::result::Result<bool, QueueClosed> {
+        if self.closed.get() {
+            return Err(QueueClosed);
+        }
+        let size = message.as_bytes().len() as u32;
+        let bytes = self.bytes.get();
+        if bytes > 0 && bytes + size > self.limit {
+            return Ok(false);
+        }
+
+        self.bytes.set(bytes + size);
+        self.pending.borrow_mut().push_back(Outgoing {
+            command: message.header().command,
+            data: message.as_bytes().to_vec(),
+        });
+        wake(&self.writer);
+        Ok(true)
+    }
+
+    /// Stop the writer, dropping anything not yet written.
+    pub fn close(&self) {
+        self.closed.set(true);
+        self.pending.borrow_mut().clear();
+        self.bytes.set(0);
+        wake(&self.writer);
+        wake(&self.sender);
+    }
+
+    /// Wait for queued messages and take up to `max` of them.
+    ///
+    /// The messages count against the limit until [`SendQueue::written`].
+    /// Returns `None` once the queue is closed.
+    pub async fn next_batch(&self, max: u32) -> Option<Vec<Outgoing>> {
+        assert!(max > 0);
+        poll_fn(|cx| {
+            if self.closed.get() {
+                return Poll::Ready(None);
+            }
+            let mut pending = self.pending.borrow_mut();
+            if pending.is_empty() {
+                *self.writer.borrow_mut() = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let count = pending.len().min(max as usize);
+            Poll::Ready(Some(pending.drain(..count).collect()))
+        })
+        .await
+    }
+
+    /// Wait until everything queued has been written or the queue is closed.
+    pub async fn flushed(&self) {
+        poll_fn(|cx| {
+            if self.closed.get() || self.bytes.get() == 0 {
+                return Poll::Ready(());
+            }
+            *self.sender.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Release the space of written messages and wake a waiting sender.
+    pub fn written(&self, bytes: u32) {
+        if self.closed.get() {
+            return;
+        }
+        assert!(bytes <= self.bytes.get());
+        self.bytes.set(self.bytes.get() - bytes);
+        wake(&self.sender);
+    }
+}
+
+fn wake(waker: &RefCell<Option<Waker>>) {
+    if let Some(waker) = waker.borrow_mut().take() {
+        waker.wake();
+    }
+}
+
+/// Write queued messages until the queue is closed or a write fails.
+///
+/// On failure the connection is marked dead and shut down, and an
+/// [`Inbound::Closed`] event tells the client why.
+pub async fn write_loop(
+    replica: usize,
+    handle: StreamHandle,
+    queue: Rc<SendQueue>,
+    shared: Rc<Shared>,
+) {
+    while let Some(batch) = queue.next_batch(WRITE_BATCH_MAX).await {
+        let bytes: usize = batch.iter().map(|outgoing| outgoing.data.len()).sum();
+        match write_batch(replica, &handle, &shared, batch).await {
+            Ok(()) => queue.written(bytes as u32),
+            Err(error) => {
+                // Fail senders waiting for room instead of leaving them
+                // blocked on a writer that is gone.
+                queue.close();
+                if handle.is_alive() {
+                    shared.replicas.borrow_mut()[replica].send_errors += 1;
+                    handle.fail();
+                    shared.demux.push(Inbound::Closed {
+                        replica,
+                        connection: handle.id(),
+                        error,
+                    });
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Write a batch in order.
+///
+/// Consecutive messages share a vectored write, except that large messages
+/// go out on their own as zero-copy sends when enabled.
+async fn write_batch(
+    replica: usize,
+    handle: &StreamHandle,
+    shared: &Shared,
+    batch: Vec<Outgoing>,
+) -> Result<()> {
+    let mut run = Vec::new();
+    for outgoing in batch {
+        let zero_copy = shared.zero_copy.get()
+            && handle.has_zero_copy()
+            && outgoing.data.len() >= SEND_ZERO_COPY_SIZE_MIN as usize;
+        if !zero_copy {
+            run.push(outgoing);
+            continue;
+        }
+
+        write_run(replica, handle, shared, std::mem::take(&mut run)).await?;
+        let bytes = outgoing.data.len();
+        let (result, data) = handle.send_zc_all(outgoing.data).await;
+        match result? {
+            Some(sends) => {
+                let stats = &mut shared.replicas.borrow_mut()[replica];
+                stats.bytes_sent += bytes as u64;
+                stats.writes += sends as u64;
+                stats.zero_copy_sends += sends as u64;
+                stats.messages_sent.record(outgoing.command);
+            }
+            None => {
+                // The kernel lacks zero-copy sends; stop trying everywhere.
+                shared.zero_copy.set(false);
+                run.push(Outgoing {
+                    command: outgoing.command,
+                    data,
+                });
+            }
+        }
+    }
+    write_run(replica, handle, shared, run).await
+}
+
+/// Write messages with as few vectored writes as possible.
+async fn write_run(
+    replica: usize,
+    handle: &StreamHandle,
+    shared: &Shared,
+    run: Vec<Outgoing>,
+) -> Result<()> {
+    if run.is_empty() {
+        return Ok(());
+    }
+
+    let mut commands = Vec::with_capacity(run.len());
+    let mut bufs = Vec::with_capacity(run.len());
+    for outgoing in run {
+        commands.push(outgoing.command);
+        bufs.push(outgoing.data);
+    }
+    let bytes: usize = bufs.iter().map(Vec::len).sum();
+
+    let writes = handle.writev_all(bufs).await?;
+    let stats = &mut shared.replicas.borrow_mut()[replica];
+    stats.bytes_sent += bytes as u64;
+    stats.writes += writes as u64;
+    for command in commands {
+        stats.messages_sent.record(command);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Command;
+
+    fn message(command: Command) -> Message {
+        let mut message = Message::new();
+        message.header_mut().command = command as u8;
+        message
+    }
+
+    #[test]
+    fn test_send_queue_batches() {
+        let queue = SendQueue::new(SEND_QUEUE_LIMIT_DEFAULT);
+        assert!(queue.try_push(&message(Command::Request)).unwrap());
+        assert!(queue.try_push(&message(Command::PingClient)).unwrap());
+        assert!(queue.try_push(&message(Command::Request)).unwrap());
+
+        tokio_uring::start(async {
+            let batch = queue.next_batch(2).await.unwrap();
+            assert_eq!(batch.len(), 2);
+            assert_eq!(batch[0].command, Command::Request as u8);
+            assert_eq!(batch[1].command, Command::PingClient as u8);
+
+            let batch = queue.next_batch(2).await.unwrap();
+            assert_eq!(batch.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_send_queue_limit() {
+        let size = message(Command::Request).as_bytes().len() as u32;
+        let queue = SendQueue::new(2 * size);
+        assert!(queue.try_push(&message(Command::Request)).unwrap());
+        assert!(queue.try_push(&message(Command::Request)).unwrap());
+        assert!(!queue.try_push(&message(Command::Request)).unwrap());
+        assert_eq!(queue.bytes.get(), 2 * size);
+
+        // Taken messages count until written.
+        tokio_uring::start(async {
+            queue.next_batch(WRITE_BATCH_MAX).await.unwrap();
+        });
+        assert!(!queue.try_push(&message(Command::Request)).unwrap());
+        queue.written(size);
+        assert!(queue.try_push(&message(Command::Request)).unwrap());
+    }
+
+    #[test]
+    fn test_send_queue_oversized_message() {
+        let queue = SendQueue::new(1);
+        assert!(queue.try_push(&message(Command::Request)).unwrap());
+        assert!(!queue.try_push(&message(Command::Request)).unwrap());
+    }
+
+    #[test]
+    fn test_send_queue_push_waits_for_room() {
+        tokio_uring::start(async {
+            let queue = Rc::new(SendQueue::new(1));
+            queue.push(&message(Command::Request)).await.unwrap();
+
+            let writer = queue.clone();
+            tokio_uring::spawn(async move {
+                let batch = writer.next_batch(WRITE_BATCH_MAX).await.unwrap();
+                let bytes: usize = batch.iter().map(|outgoing| outgoing.data.len()).sum();
+                writer.written(bytes as u32);
+            });
+            queue.push(&message(Command::Request)).await.unwrap();
+            assert_eq!(queue.pending.borrow().len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_send_queue_close() {
+        let queue = SendQueue::new(1);
+        queue.try_push(&message(Command::Request)).unwrap();
+        queue.close();
+        assert_eq!(queue.bytes.get(), 0);
+        assert_eq!(queue.try_push(&message(Command::Request)), Err(QueueClosed));
+
+        tokio_uring::start(async {
+            assert!(queue.next_batch(WRITE_BATCH_MAX).await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_send_queue_close_fails_waiting_sender() {
+        tokio_uring::start(async {
+            let queue = Rc::new(SendQueue::new(1));
+            queue.push(&message(Command::Request)).await.unwrap();
+
+            let closer = queue.clone();
+            tokio_uring::spawn(async move { closer.close() });
+            assert_eq!(
+                queue.push(&message(Command::Request)).await,
+                Err(QueueClosed)
+            );
+        });
+    }
+
+    #[test]
+    fn test_send_queue_flushed() {
+        tokio_uring::start(async {
+            let queue = Rc::new(SendQueue::new(SEND_QUEUE_LIMIT_DEFAULT));
+            queue.flushed().await;
+            queue.push(&message(Command::Request)).await.unwrap();
+
+            let writer = queue.clone();
+            tokio_uring::spawn(async move {
+                let batch = writer.next_batch(WRITE_BATCH_MAX).await.unwrap();
+                let bytes: usize = batch.iter().map(|outgoing| outgoing.data.len()).sum();
+                writer.written(bytes as u32);
+            });
+            queue.flushed().await;
+            assert_eq!(queue.bytes.get(), 0);
+        });
+    }
+
+    #[test]
+    fn test_send_queue_wakes_writer() {
+        tokio_uring::start(async {
+            let queue = Rc::new(SendQueue::new(SEND_QUEUE_LIMIT_DEFAULT));
+            let producer = queue.clone();
+            tokio_uring::spawn(async move {
+                producer.push(&message(Command::Request)).await.unwrap();
+            });
+            assert_eq!(queue.next_batch(WRITE_BATCH_MAX).await.unwrap().len(), 1);
+        });
+    }
+}
@@ -61,7 +61,7 @@
 //!
 //! let client = Client::builder()
 //!     .cluster(0)
-//!     .addresses("127.0.0.1:3000,127.0.0.1:3001")?
+//!     .addresses("127.0.0.1:3000,[::1]:3001,db.example.com:3002")?
 //!     .connect_timeout(Duration::from_secs(10))
 //!     .request_timeout(Duration::from_millis(100))
 //!     .build()
@@ -76,16 +76,20 @@
 compile_error!("tb-rs requires Linux with io_uring support (kernel 5.6+). This crate does not support other platforms.");
 
 // Public modules
+mod address;
 mod client;
 mod error;
+mod metrics;
 pub mod protocol;
 
 // Internal implementation (not public)
 mod internal;
 
 // Re-export main types
-pub use client::{Client, ClientBuilder};
-pub use error::{ClientError, ProtocolError, Result};
+pub use address::{replica_count, IpPreference, PORT_DEFAULT};
+pub use client::{Client, ClientBuilder, ConnectionMode, SharedDriver};
+pub use error::{ClientError, ProtocolError, Result, TransportError, TransportErrorKind};
+pub use metrics::{BufferPoolStats, ClientMetrics, CommandCounts, ReplicaStats};
 
 /// TigerBeetle server version this client is compatible with.
 ///
@@ -0,0 +1,217 @@
+//! Client metrics.
+//!
+//! Metrics are plain counters collected by the client as it runs. Call
+//! [`Client::metrics`](crate::Client::metrics) to take a snapshot.
+
+use std::time::Duration;
+
+use crate::protocol::Command;
+
+/// Inverse weight of a new round-trip sample in the moving average, as in
+/// TCP's smoothed RTT.
+const RTT_EWMA_WEIGHT: u64 = 8;
+
+/// Number of command slots, covering every defined VSR command value.
+const COMMAND_SLOTS: usize = 32;
+const _: () = assert!((Command::StartView as usize) < COMMAND_SLOTS);
+
+/// Snapshot of client metrics.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClientMetrics {
+    /// Receive buffer pool statistics.
+    pub buffer_pool: BufferPoolStats,
+    /// Transport statistics per replica, indexed like the configured
+    /// addresses.
+    pub replicas: Vec<ReplicaStats>,
+}
+
+/// Receive buffer pool statistics.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BufferPoolStats {
+    /// Total buffers handed out by the pool.
+    pub acquired: u64,
+    /// Total buffers returned to the pool.
+    pub released: u64,
+    /// Buffers returned poisoned and placed in quarantine.
+    pub quarantined: u64,
+    /// Quarantined buffers released after their owning operation completed.
+    pub quarantine_completed: u64,
+    /// Quarantined buffers released after the quarantine timeout elapsed.
+    pub quarantine_expired: u64,
+    /// Buffers allocated beyond the initial pool size.
+    pub grown: u64,
+    /// Maximum number of buffers outstanding at the same time.
+    pub outstanding_high_water: u64,
+    /// Buffers currently held in quarantine.
+    pub quarantine_depth: u64,
+}
+
+impl BufferPoolStats {
+    /// Number of buffers currently acquired and not yet released.
+    pub fn outstanding(&self) -> u64 {
+        self.acquired - self.released
+    }
+}
+
+/// Transport statistics for a single replica.
+///
+/// Comparing replicas separates a slow or flaky replica from problems on the
+/// client side, which would show up on every replica alike.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReplicaStats {
+    /// Bytes written to the replica.
+    pub bytes_sent: u64,
+    /// Bytes read from the replica.
+    pub bytes_received: u64,
+    /// Messages sent, by command.
+    pub messages_sent: CommandCounts,
+    /// Write submissions. Queued messages are coalesced into one write, so
+    /// this grows slower than `messages_sent` under pipelined load.
+    pub writes: u64,
+    /// Zero-copy sends, counted in `writes` too.
+    pub zero_copy_sends: u64,
+    /// Complete messages received, by command.
+    pub messages_received: CommandCounts,
+    /// Connection attempts, one per candidate address tried.
+    pub connect_attempts: u64,
+    /// Connection attempts that failed.
+    pub connect_failures: u64,
+    /// Connections established after the first one.
+    pub reconnects: u64,
+    /// Sends that found the send queue full. The request waited for room,
+    /// or a hedged copy was skipped.
+    pub send_queue_full: u64,
+    /// Connections lost while writing.
+    pub send_errors: u64,
+    /// Health probes (pings) sent.
+    pub probes_sent: u64,
+    /// Connections closed because the replica stopped answering probes.
+    pub probe_failures: u64,
+    /// Connections lost while reading (reset, closed by peer, corrupt data).
+    pub receive_errors: u64,
+    /// Round-trip times measured, from connection handshakes, pongs and
+    /// replies.
+    pub rtt_samples: u64,
+    /// Moving average of the round-trip time in nanoseconds, each sample
+    /// weighted 1/8. Zero until the first sample.
+    pub rtt_ewma_ns: u64,
+}
+
+impl ReplicaStats {
+    /// Smoothed round-trip time, if any was measured.
+    pub fn rtt(&self) -> Option<Duration> {
+        (self.rtt_samples > 0).then(|| Duration::from_nanos(self.rtt_ewma_ns))
+    }
+
+    /// Fold a round-trip time sample into the moving average.
+    pub(crate) fn record_rtt(&mut self, sample: Duration) {
+        let sample = sample.as_nanos().min(u64::MAX as u128) as u64;
+        self.rtt_ewma_ns = if self.rtt_samples == 0 {
+            sample
+        } else {
+            let ewma = self.rtt_ewma_ns;
+            ewma - ewma / RTT_EWMA_WEIGHT + sample / RTT_EWMA_WEIGHT
+        };
+        self.rtt_samples += 1;
+    }
+
+    /// Total errors of any kind.
+    pub fn errors(&self) -> u64 {
+        self.connect_failures + self.send_errors + self.receive_errors + self.probe_failures
+    }
+}
+
+/// Message counts by VSR command.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CommandCounts {
+    counts: [u64; COMMAND_SLOTS],
+    unknown: u64,
+}
+
+impl CommandCounts {
+    /// Number of messages with the given command.
+    pub fn get(&self, command: Command) -> u64 {
+        self.counts[command as usize]
+    }
+
+    /// Number of messages with a command this client does not know.
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+
+    /// Number of messages of all commands.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum::<u64>() + self.unknown
+    }
+
+    /// Count a message by its raw command byte.
+    pub(crate) fn record(&mut self, command: u8) {
+        match Command::try_from(command) {
+            Ok(command) => self.counts[command as usize] += 1,
+            Err(_) => self.unknown += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool_stats_outstanding() {
+        let stats = BufferPoolStats {
+            acquired: 5,
+            released: 3,
+            ..Default::default()
+        };
+        assert_eq!(stats.outstanding(), 2);
+    }
+
+    #[test]
+    fn test_client_metrics_default() {
+        let metrics = ClientMetrics::default();
+        assert_eq!(metrics.buffer_pool.outstanding(), 0);
+        assert!(metrics.replicas.is_empty());
+    }
+
+    #[test]
+    fn test_command_counts() {
+        let mut counts = CommandCounts::default();
+        counts.record(Command::Reply as u8);
+        counts.record(Command::Reply as u8);
+        counts.record(Command::Eviction as u8);
+        counts.record(Command::StartView as u8);
+        counts.record(12);
+        counts.record(255);
+
+        assert_eq!(counts.get(Command::Reply), 2);
+        assert_eq!(counts.get(Command::Eviction), 1);
+        assert_eq!(counts.get(Command::StartView), 1);
+        assert_eq!(counts.get(Command::PongClient), 0);
+        assert_eq!(counts.unknown(), 2);
+        assert_eq!(counts.total(), 6);
+    }
+
+    #[test]
+    fn test_replica_stats_errors() {
+        let stats = ReplicaStats {
+            connect_failures: 1,
+            send_errors: 2,
+            receive_errors: 3,
+            ..Default::default()
+        };
+        assert_eq!(stats.errors(), 6);
+    }
+
+    #[test]
+    fn test_replica_stats_rtt() {
+        let mut stats = ReplicaStats::default();
+        assert_eq!(stats.rtt(), None);
+
+        stats.record_rtt(Duration::from_micros(800));
+        assert_eq!(stats.rtt(), Some(Duration::from_micros(800)));
+        stats.record_rtt(Duration::from_micros(1600));
+        assert_eq!(stats.rtt(), Some(Duration::from_micros(900)));
+        assert_eq!(stats.rtt_samples, 2);
+    }
+}
@@ -134,6 +134,11 @@ impl Header {
         PongClientHeader::ref_from_bytes(&self.reserved_command).unwrap()
     }
 
+    /// Get this header as a mutable PongClient header view.
+    pub fn as_pong_client_mut(&mut self) -> &mut PongClientHeader {
+        PongClientHeader::mut_from_bytes(&mut self.reserved_command).unwrap()
+    }
+
     /// Get this header as an Eviction header view.
     pub fn as_eviction(&self) -> &EvictionHeader {
         EvictionHeader::ref_from_bytes(&self.reserved_command).unwrap()
@@ -240,6 +245,23 @@ impl Header {
         }
         Ok(())
     }
+
+    /// Validate the header frame and the command-specific overlay.
+    ///
+    /// Extends [`Header::validate`] by checking the reserved and padding
+    /// fields of the overlay selected by `command`. Commands without an
+    /// overlay defined here only have their frame checked.
+    pub fn validate_wire(&self) -> Result<(), HeaderError> {
+        self.validate()?;
+        match self.command() {
+            Some(Command::Request) => self.as_request().validate_wire(),
+            Some(Command::Reply) => self.as_reply().validate_wire(),
+            Some(Command::PingClient) => self.as_ping_client().validate_wire(),
+            Some(Command::PongClient) => self.as_pong_client().validate_wire(),
+            Some(Command::Eviction) => self.as_eviction().validate_wire(),
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Request-specific header fields (overlay on reserved_command).
@@ -297,6 +319,22 @@ impl RequestHeader {
     pub fn set_operation(&mut self, operation: Operation) {
         self.operation = operation as u8;
     }
+
+    /// Verify that padding and reserved fields are zero.
+    pub fn validate_wire(&self) -> Result<(), HeaderError> {
+        if self.parent_padding != 0 {
+            return Err(HeaderError::InvalidPadding("parent_padding"));
+        }
+        if self.previous_request_latency_padding != [0; 3] {
+            return Err(HeaderError::InvalidPadding(
+                "previous_request_latency_padding",
+            ));
+        }
+        if self.reserved != [0; 52] {
+            return Err(HeaderError::InvalidPadding("reserved"));
+        }
+        Ok(())
+    }
 }
 
 /// Reply-specific header fields (overlay on reserved_command).
@@ -334,6 +372,20 @@ impl ReplyHeader {
     pub fn operation(&self) -> Option<Operation> {
         Operation::try_from(self.operation).ok()
     }
+
+    /// Verify that padding and reserved fields are zero.
+    pub fn validate_wire(&self) -> Result<(), HeaderError> {
+        if self.request_checksum_padding != 0 {
+            return Err(HeaderError::InvalidPadding("request_checksum_padding"));
+        }
+        if self.context_padding != 0 {
+            return Err(HeaderError::InvalidPadding("context_padding"));
+        }
+        if self.reserved != [0; 19] {
+            return Err(HeaderError::InvalidPadding("reserved"));
+        }
+        Ok(())
+    }
 }
 
 /// PingClient-specific header fields.
@@ -360,6 +412,16 @@ impl Default for PingClientHeader {
 
 const _: () = assert!(std::mem::size_of::<PingClientHeader>() == 128);
 
+impl PingClientHeader {
+    /// Verify that reserved fields are zero.
+    pub fn validate_wire(&self) -> Result<(), HeaderError> {
+        if self.reserved != [0; 104] {
+            return Err(HeaderError::InvalidPadding("reserved"));
+        }
+        Ok(())
+    }
+}
+
 /// PongClient-specific header fields.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, FromBytes, IntoBytes, Immutable, KnownLayout)]
@@ -384,6 +446,16 @@ impl Default for PongClientHeader {
 
 const _: () = assert!(std::mem::size_of::<PongClientHeader>() == 128);
 
+impl PongClientHeader {
+    /// Verify that reserved fields are zero.
+    pub fn validate_wire(&self) -> Result<(), HeaderError> {
+        if self.reserved != [0; 112] {
+            return Err(HeaderError::InvalidPadding("reserved"));
+        }
+        Ok(())
+    }
+}
+
 /// Eviction-specific header fields.
 /// Layout: client (16 bytes) + reserved (111 bytes) + reason (1 byte) = 128 bytes
 #[repr(C)]
@@ -409,6 +481,16 @@ impl Default for EvictionHeader {
 
 const _: () = assert!(std::mem::size_of::<EvictionHeader>() == 128);
 
+impl EvictionHeader {
+    /// Verify that reserved fields are zero.
+    pub fn validate_wire(&self) -> Result<(), HeaderError> {
+        if self.reserved != [0; 111] {
+            return Err(HeaderError::InvalidPadding("reserved"));
+        }
+        Ok(())
+    }
+}
+
 /// Eviction reason codes.
 /// Note: These start at 1, not 0, matching the TigerBeetle Zig enum.
 #[repr(u8)]
@@ -558,4 +640,63 @@ mod tests {
         assert_eq!(restored.command, Command::Request as u8);
         assert_eq!(restored.size, 512);
     }
+
+    #[test]
+    fn test_header_validate_wire_request() {
+        let mut header = Header::default();
+        header.set_command(Command::Request);
+        assert!(header.validate_wire().is_ok());
+
+        header.as_request_mut().parent_padding = 1;
+        assert_eq!(
+            header.validate_wire(),
+            Err(HeaderError::InvalidPadding("parent_padding"))
+        );
+    }
+
+    #[test]
+    fn test_header_validate_wire_reply() {
+        let mut header = Header::default();
+        header.set_command(Command::Reply);
+        assert!(header.validate_wire().is_ok());
+
+        header.as_reply_mut().reserved[18] = 1;
+        assert_eq!(
+            header.validate_wire(),
+            Err(HeaderError::InvalidPadding("reserved"))
+        );
+    }
+
+    #[test]
+    fn test_header_validate_wire_frame_first() {
+        let mut header = Header::default();
+        header.set_command(Command::Reply);
+        header.reserved_frame[0] = 1;
+        header.as_reply_mut().context_padding = 1;
+        assert_eq!(
+            header.validate_wire(),
+            Err(HeaderError::InvalidPadding("reserved_frame"))
+        );
+    }
+
+    #[test]
+    fn test_overlay_validate_wire() {
+        let mut ping = PingClientHeader::default();
+        assert!(ping.validate_wire().is_ok());
+        ping.reserved[0] = 1;
+        assert!(ping.validate_wire().is_err());
+
+        let mut pong = PongClientHeader::default();
+        assert!(pong.validate_wire().is_ok());
+        pong.reserved[111] = 1;
+        assert!(pong.validate_wire().is_err());
+
+        let mut eviction = EvictionHeader {
+            reason: EvictionReason::NoSession as u8,
+            ..Default::default()
+        };
+        assert!(eviction.validate_wire().is_ok());
+        eviction.reserved[0] = 1;
+        assert!(eviction.validate_wire().is_err());
+    }
 }
@@ -21,5 +21,5 @@ pub use operation::{Command, Operation, VSR_OPERATIONS_RESERVED};
 pub use types::{
     Account, AccountBalance, AccountFilter, AccountFilterFlags, AccountFlags, CreateAccountResult,
     CreateAccountsResult, CreateTransferResult, CreateTransfersResult, QueryFilter,
-    QueryFilterFlags, RegisterRequest, RegisterResult, Transfer, TransferFlags,
+    QueryFilterFlags, RegisterRequest, RegisterResult, Transfer, TransferFlags, WireError,
 };
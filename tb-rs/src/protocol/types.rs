@@ -49,6 +49,22 @@ pub struct Account {
 
 const _: () = assert!(std::mem::size_of::<Account>() == 128);
 
+impl Account {
+    /// Verify that reserved fields and unknown flag bits are zero.
+    ///
+    /// A non-zero value indicates corruption or a server running a newer
+    /// protocol version than this client understands.
+    pub fn validate_wire(&self) -> Result<(), WireError> {
+        if self.reserved != 0 {
+            return Err(WireError::ReservedField("reserved"));
+        }
+        if self.flags.bits() & !AccountFlags::all().bits() != 0 {
+            return Err(WireError::ReservedFlag("flags"));
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     /// Flags for Account configuration.
     #[repr(transparent)]
@@ -105,6 +121,18 @@ pub struct Transfer {
 
 const _: () = assert!(std::mem::size_of::<Transfer>() == 128);
 
+impl Transfer {
+    /// Verify that unknown flag bits are zero.
+    ///
+    /// Transfers carry no reserved bytes, so only the flags are checked.
+    pub fn validate_wire(&self) -> Result<(), WireError> {
+        if self.flags.bits() & !TransferFlags::all().bits() != 0 {
+            return Err(WireError::ReservedFlag("flags"));
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     /// Flags for Transfer configuration.
     #[repr(transparent)]
@@ -166,6 +194,16 @@ impl Default for AccountBalance {
 
 const _: () = assert!(std::mem::size_of::<AccountBalance>() == 128);
 
+impl AccountBalance {
+    /// Verify that the reserved bytes are zero.
+    pub fn validate_wire(&self) -> Result<(), WireError> {
+        if self.reserved != [0; 56] {
+            return Err(WireError::ReservedField("reserved"));
+        }
+        Ok(())
+    }
+}
+
 /// Filter for account-related queries (128 bytes).
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -211,6 +249,19 @@ impl Default for AccountFilter {
 
 const _: () = assert!(std::mem::size_of::<AccountFilter>() == 128);
 
+impl AccountFilter {
+    /// Verify that the reserved bytes and unknown flag bits are zero.
+    pub fn validate_wire(&self) -> Result<(), WireError> {
+        if self.reserved != [0; 58] {
+            return Err(WireError::ReservedField("reserved"));
+        }
+        if self.flags.bits() & !AccountFilterFlags::all().bits() != 0 {
+            return Err(WireError::ReservedFlag("flags"));
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     /// Flags for AccountFilter queries.
     #[repr(transparent)]
@@ -253,6 +304,19 @@ pub struct QueryFilter {
 
 const _: () = assert!(std::mem::size_of::<QueryFilter>() == 64);
 
+impl QueryFilter {
+    /// Verify that the reserved bytes and unknown flag bits are zero.
+    pub fn validate_wire(&self) -> Result<(), WireError> {
+        if self.reserved != [0; 6] {
+            return Err(WireError::ReservedField("reserved"));
+        }
+        if self.flags.bits() & !QueryFilterFlags::all().bits() != 0 {
+            return Err(WireError::ReservedFlag("flags"));
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     /// Flags for QueryFilter queries.
     #[repr(transparent)]
@@ -536,6 +600,26 @@ pub enum CreateTransferResult {
     IdAlreadyFailed = 68,
 }
 
+/// Wire validation errors for reserved fields and flags.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WireError {
+    /// A reserved or padding field was non-zero.
+    ReservedField(&'static str),
+    /// A flag bit not defined by this protocol version was set.
+    ReservedFlag(&'static str),
+}
+
+impl std::fmt::Display for WireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireError::ReservedField(field) => write!(f, "reserved field '{}' is non-zero", field),
+            WireError::ReservedFlag(field) => write!(f, "unknown bits set in '{}'", field),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -598,4 +682,103 @@ mod tests {
         let flags = TransferFlags::PENDING | TransferFlags::LINKED;
         assert_eq!(flags.bits(), 0b11);
     }
+
+    #[test]
+    fn test_account_validate_wire() {
+        let account = Account {
+            id: 1,
+            flags: AccountFlags::HISTORY,
+            ..Default::default()
+        };
+        assert_eq!(account.validate_wire(), Ok(()));
+
+        let reserved = Account {
+            reserved: 1,
+            ..account
+        };
+        assert_eq!(
+            reserved.validate_wire(),
+            Err(WireError::ReservedField("reserved"))
+        );
+
+        let unknown_flag = Account {
+            flags: AccountFlags::from_bits_retain(1 << 15),
+            ..account
+        };
+        assert_eq!(
+            unknown_flag.validate_wire(),
+            Err(WireError::ReservedFlag("flags"))
+        );
+    }
+
+    #[test]
+    fn test_transfer_validate_wire() {
+        let transfer = Transfer {
+            flags: TransferFlags::PENDING | TransferFlags::LINKED,
+            ..Default::default()
+        };
+        assert_eq!(transfer.validate_wire(), Ok(()));
+
+        let unknown_flag = Transfer {
+            flags: TransferFlags::from_bits_retain(1 << 9),
+            ..transfer
+        };
+        assert_eq!(
+            unknown_flag.validate_wire(),
+            Err(WireError::ReservedFlag("flags"))
+        );
+    }
+
+    #[test]
+    fn test_account_balance_validate_wire() {
+        let mut balance = AccountBalance::default();
+        assert_eq!(balance.validate_wire(), Ok(()));
+
+        balance.reserved[55] = 1;
+        assert_eq!(
+            balance.validate_wire(),
+            Err(WireError::ReservedField("reserved"))
+        );
+    }
+
+    #[test]
+    fn test_account_filter_validate_wire() {
+        let mut filter = AccountFilter {
+            flags: AccountFilterFlags::all(),
+            ..Default::default()
+        };
+        assert_eq!(filter.validate_wire(), Ok(()));
+
+        filter.flags = AccountFilterFlags::from_bits_retain(1 << 3);
+        assert_eq!(
+            filter.validate_wire(),
+            Err(WireError::ReservedFlag("flags"))
+        );
+
+        filter.flags = AccountFilterFlags::empty();
+        filter.reserved[0] = 1;
+        assert_eq!(
+            filter.validate_wire(),
+            Err(WireError::ReservedField("reserved"))
+        );
+    }
+
+    #[test]
+    fn test_query_filter_validate_wire() {
+        let mut filter = QueryFilter::default();
+        assert_eq!(filter.validate_wire(), Ok(()));
+
+        filter.reserved[5] = 0xFF;
+        assert_eq!(
+            filter.validate_wire(),
+            Err(WireError::ReservedField("reserved"))
+        );
+
+        filter.reserved = [0; 6];
+        filter.flags = QueryFilterFlags::from_bits_retain(1 << 31);
+        assert_eq!(
+            filter.validate_wire(),
+            Err(WireError::ReservedFlag("flags"))
+        );
+    }
 }
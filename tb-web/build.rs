@@ -1,12 +1,15 @@
 //! Build script for tb-web.
 //!
-//! Compiles TypeScript frontend before embedding assets.
+//! Compiles the gRPC service definitions, and the TypeScript frontend
+//! before embedding assets.
 
 use std::env;
 use std::path::Path;
 use std::process::Command;
 
 fn main() {
+    compile_protos();
+
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let frontend_dir = Path::new(&manifest_dir).join("frontend");
 
@@ -44,3 +47,17 @@ fn main() {
         panic!("npm build failed");
     }
 }
+
+/// Generate the gRPC server from `proto/`, with a bundled `protoc` so none
+/// needs installing.
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/");
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform");
+    env::set_var("PROTOC", protoc);
+    tonic_build::configure()
+        .build_client(false)
+        .build_transport(false)
+        .compile_protos(&["proto/tb_web.proto"], &["proto"])
+        .expect("Failed to compile protos");
+}
@@ -6,18 +6,23 @@ import { formatTimestamp } from './formatters';
 
 declare const Chart: any;
 
-interface AccountBalance {
-    debits_pending: string;
-    debits_posted: string;
-    credits_pending: string;
-    credits_posted: string;
+interface BalancePoint {
     timestamp: number;
+    min: string;
+    max: string;
+    last: string;
+    credits_posted: string;
+    debits_posted: string;
 }
 
-interface BalancesResponse {
-    balances: AccountBalance[];
+interface BalanceChartResponse {
+    points: BalancePoint[];
+    rows: number;
 }
 
+/** Buckets to downsample the history into. */
+const CHART_POINTS = 200;
+
 let balanceChart: any = null;
 
 /**
@@ -34,31 +39,26 @@ export async function renderBalanceChart(
     }
 
     try {
-        const response = await fetch(`/api/v1/accounts/${accountId}/balances?limit=1000`);
+        const response = await fetch(`/api/v1/accounts/${accountId}/balances/chart?points=${CHART_POINTS}`);
         if (!response.ok) {
             throw new Error(`Failed to fetch balances: ${response.statusText}`);
         }
 
-        const data: BalancesResponse = await response.json();
+        const data: BalanceChartResponse = await response.json();
 
-        if (data.balances.length === 0) {
+        if (data.points.length === 0) {
             canvas.parentElement!.innerHTML = '<p class="loading">No balance history available. Account may not have HISTORY flag enabled.</p>';
             return;
         }
 
-        // Sort by timestamp (oldest first for chronological display)
-        const sortedBalances = [...data.balances].sort((a, b) => a.timestamp - b.timestamp);
-
-        // Prepare chart data
-        const labels = sortedBalances.map(b => formatTimestamp(b.timestamp));
-        const netBalances = sortedBalances.map(b => {
-            const credits = BigInt(b.credits_posted);
-            const debits = BigInt(b.debits_posted);
-            // Convert to number for Chart.js (may lose precision for very large numbers)
-            return Number(credits - debits);
-        });
-        const creditsData = sortedBalances.map(b => Number(BigInt(b.credits_posted)));
-        const debitsData = sortedBalances.map(b => Number(BigInt(b.debits_posted)));
+        // Points come oldest first, one per bucket with history.
+        // Convert to number for Chart.js (may lose precision for very large numbers)
+        const labels = data.points.map(p => formatTimestamp(p.timestamp));
+        const netBalances = data.points.map(p => Number(BigInt(p.last)));
+        const minBalances = data.points.map(p => Number(BigInt(p.min)));
+        const maxBalances = data.points.map(p => Number(BigInt(p.max)));
+        const creditsData = data.points.map(p => Number(BigInt(p.credits_posted)));
+        const debitsData = data.points.map(p => Number(BigInt(p.debits_posted)));
 
         // Destroy existing chart if any
         if (balanceChart) {
@@ -75,8 +75,25 @@ export async function renderBalanceChart(
                         label: 'Net Balance',
                         data: netBalances,
                         borderColor: '#f7931a',
+                        backgroundColor: 'transparent',
+                        tension: 0.1,
+                    },
+                    {
+                        // The band between each bucket's lowest and highest balance
+                        label: 'Net Balance Range',
+                        data: maxBalances,
+                        borderColor: 'transparent',
                         backgroundColor: 'rgba(247, 147, 26, 0.1)',
-                        fill: true,
+                        pointRadius: 0,
+                        fill: '+1',
+                        tension: 0.1,
+                    },
+                    {
+                        label: 'Net Balance Min',
+                        data: minBalances,
+                        borderColor: 'transparent',
+                        backgroundColor: 'transparent',
+                        pointRadius: 0,
                         tension: 0.1,
                     },
                     {
@@ -104,6 +121,7 @@ export async function renderBalanceChart(
                     legend: {
                         labels: {
                             color: '#e7e9ea',
+                            filter: (item: any) => item.text !== 'Net Balance Min',
                         },
                     },
                     tooltip: {
@@ -0,0 +1,31 @@
+/**
+ * Live updates pushed over a WebSocket from /api/v1/live.
+ */
+
+/** Event as sent by the server; see `LiveEvent` in api/types.rs. */
+export type LiveEvent<A, T> =
+    | { type: 'account'; account: A }
+    | { type: 'transfer'; transfer: T };
+
+/** Delay before reconnecting after the socket closes. */
+const RECONNECT_MS = 3000;
+
+/**
+ * Connect and pass every event to `onEvent`, reconnecting whenever the
+ * connection drops (e.g. while tb-web restarts).
+ */
+export function connectLive<A, T>(onEvent: (event: LiveEvent<A, T>) => void): void {
+    const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+    const socket = new WebSocket(`${scheme}://${window.location.host}/api/v1/live`);
+
+    socket.addEventListener('message', (message: MessageEvent) => {
+        try {
+            onEvent(JSON.parse(message.data) as LiveEvent<A, T>);
+        } catch (e) {
+            console.error('Error handling live update:', e);
+        }
+    });
+    socket.addEventListener('close', () => {
+        setTimeout(() => connectLive(onEvent), RECONNECT_MS);
+    });
+}
@@ -12,6 +12,7 @@ import {
     formatBalance,
 } from './formatters';
 import { renderBalanceChart, destroyChart } from './charts';
+import { connectLive, LiveEvent } from './live';
 
 // Make functions available globally for HTMX integration
 declare global {
@@ -45,6 +46,14 @@ interface ApiAccount {
     code: number;
     flags: number;
     timestamp: number;
+    // Amounts in the ledger's currency, if it has one
+    display?: {
+        debits_pending: string;
+        debits_posted: string;
+        credits_pending: string;
+        credits_posted: string;
+        net_balance: string;
+    };
 }
 
 interface ApiTransfer {
@@ -61,16 +70,128 @@ interface ApiTransfer {
     code: number;
     flags: number;
     timestamp: number;
+    // Amount in the ledger's currency, if it has one
+    display?: {
+        amount: string;
+    };
 }
 
 interface AccountsResponse {
     accounts: ApiAccount[];
     next_timestamp?: number;
+    prev_cursor?: number;
+    next_cursor?: number;
 }
 
 interface TransfersResponse {
     transfers: ApiTransfer[];
     next_timestamp?: number;
+    prev_cursor?: number;
+    next_cursor?: number;
+}
+
+interface AliasesResponse {
+    aliases: { account_id: string; name: string }[];
+}
+
+// Account aliases by ID, loaded once per page; empty without a registry
+const aliases = new Map<string, string>();
+
+/**
+ * Load the account aliases, if tb-web keeps any.
+ */
+async function loadAliases(): Promise<void> {
+    try {
+        const response = await fetch('/api/v1/aliases');
+        if (!response.ok) return;
+        const data: AliasesResponse = await response.json();
+        data.aliases.forEach(alias => aliases.set(alias.account_id, alias.name));
+    } catch (e) {
+        console.warn('Loading account aliases failed:', e);
+    }
+}
+
+/**
+ * Escape text for inclusion in HTML.
+ */
+function escapeHtml(text: string): string {
+    return text
+        .replace(/&/g, '&amp;')
+        .replace(/</g, '&lt;')
+        .replace(/>/g, '&gt;')
+        .replace(/"/g, '&quot;');
+}
+
+/**
+ * Link to an account, showing its alias if it has one.
+ */
+function accountLink(id: string): string {
+    const alias = aliases.get(id);
+    const text = alias === undefined ? formatId(id) : escapeHtml(alias);
+    return `<a href="/account/${id}" class="id" title="${id}">${text}</a>`;
+}
+
+/**
+ * Render an account as a table row, keyed by ID for live updates.
+ */
+function accountRow(account: ApiAccount): string {
+    const netBalance = calculateNetBalance(account.credits_posted, account.debits_posted);
+    const balanceClass = netBalance >= 0n ? 'positive' : 'negative';
+    return `
+        <tr data-account-id="${account.id}">
+            <td>${accountLink(account.id)}</td>
+            <td><a href="/ledger/${account.ledger}" class="ledger">${account.ledger}</a></td>
+            <td>${account.code}</td>
+            <td class="amount ${balanceClass}">${account.display?.net_balance ?? formatBalance(netBalance)}</td>
+            <td class="amount">${account.display?.credits_posted ?? formatAmount(account.credits_posted)}</td>
+            <td class="amount">${account.display?.debits_posted ?? formatAmount(account.debits_posted)}</td>
+            <td>${formatTimestamp(account.timestamp)}</td>
+        </tr>
+    `;
+}
+
+/**
+ * Render a transfer as a table row.
+ */
+function transferRow(transfer: ApiTransfer): string {
+    return `
+        <tr>
+            <td><a href="/transfer/${transfer.id}" class="id" title="${transfer.id}">${formatId(transfer.id)}</a></td>
+            <td>${accountLink(transfer.debit_account_id)}</td>
+            <td>${accountLink(transfer.credit_account_id)}</td>
+            <td class="amount">${transfer.display?.amount ?? formatAmount(transfer.amount)}</td>
+            <td><a href="/ledger/${transfer.ledger}" class="ledger">${transfer.ledger}</a></td>
+            <td>${transfer.code}</td>
+            <td>${formatTimestamp(transfer.timestamp)}</td>
+        </tr>
+    `;
+}
+
+/**
+ * Previous and Next buttons for a page of the list at `url`, each replacing
+ * the page in the container with its own.
+ */
+function pagerHtml(
+    url: string,
+    containerId: string,
+    page: { prev_cursor?: number; next_cursor?: number }
+): string {
+    const button = (label: string, param: string, cursor?: number) =>
+        cursor === undefined
+            ? ''
+            : `
+                <button class="btn btn-secondary"
+                        hx-get="${url}&${param}=${cursor}"
+                        hx-target="#${containerId}">
+                    ${label}
+                </button>`;
+    if (page.prev_cursor === undefined && page.next_cursor === undefined) return '';
+    return `
+        <div class="pagination">
+            ${button('Previous', 'before_timestamp', page.prev_cursor)}
+            ${button('Next', 'after_timestamp', page.next_cursor)}
+        </div>
+    `;
 }
 
 /**
@@ -79,9 +200,12 @@ interface TransfersResponse {
 function renderAccountsTable(containerId: string, data: AccountsResponse): void {
     const container = document.getElementById(containerId);
     if (!container) return;
+    const url = '/api/v1/accounts?limit=100';
 
     if (data.accounts.length === 0) {
-        container.innerHTML = '<p class="loading">No accounts found</p>';
+        container.innerHTML =
+            '<p class="loading">No accounts found</p>' + pagerHtml(url, containerId, data);
+        (window as any).htmx?.process(container);
         return;
     }
 
@@ -99,32 +223,10 @@ function renderAccountsTable(containerId: string, data: AccountsResponse): void
                 </tr>
             </thead>
             <tbody>
-                ${data.accounts.map(account => {
-                    const netBalance = calculateNetBalance(account.credits_posted, account.debits_posted);
-                    const balanceClass = netBalance >= 0n ? 'positive' : 'negative';
-                    return `
-                        <tr>
-                            <td><a href="/account/${account.id}" class="id" title="${account.id}">${formatId(account.id)}</a></td>
-                            <td>${account.ledger}</td>
-                            <td>${account.code}</td>
-                            <td class="amount ${balanceClass}">${formatBalance(netBalance)}</td>
-                            <td class="amount">${formatAmount(account.credits_posted)}</td>
-                            <td class="amount">${formatAmount(account.debits_posted)}</td>
-                            <td>${formatTimestamp(account.timestamp)}</td>
-                        </tr>
-                    `;
-                }).join('')}
+                ${data.accounts.map(accountRow).join('')}
             </tbody>
         </table>
-        ${data.next_timestamp ? `
-            <div class="pagination">
-                <button class="btn btn-secondary"
-                        hx-get="/api/v1/accounts?limit=100&after_timestamp=${data.next_timestamp}"
-                        hx-target="#${containerId}">
-                    Load More
-                </button>
-            </div>
-        ` : ''}
+        ${pagerHtml(url, containerId, data)}
     `;
 
     container.innerHTML = html;
@@ -138,9 +240,12 @@ function renderAccountsTable(containerId: string, data: AccountsResponse): void
 function renderTransfersTable(containerId: string, data: TransfersResponse): void {
     const container = document.getElementById(containerId);
     if (!container) return;
+    const url = '/api/v1/transfers?limit=100&reversed=true';
 
     if (data.transfers.length === 0) {
-        container.innerHTML = '<p class="loading">No transfers found</p>';
+        container.innerHTML =
+            '<p class="loading">No transfers found</p>' + pagerHtml(url, containerId, data);
+        (window as any).htmx?.process(container);
         return;
     }
 
@@ -158,28 +263,10 @@ function renderTransfersTable(containerId: string, data: TransfersResponse): voi
                 </tr>
             </thead>
             <tbody>
-                ${data.transfers.map(transfer => `
-                    <tr>
-                        <td><a href="/transfer/${transfer.id}" class="id" title="${transfer.id}">${formatId(transfer.id)}</a></td>
-                        <td><a href="/account/${transfer.debit_account_id}" class="id" title="${transfer.debit_account_id}">${formatId(transfer.debit_account_id)}</a></td>
-                        <td><a href="/account/${transfer.credit_account_id}" class="id" title="${transfer.credit_account_id}">${formatId(transfer.credit_account_id)}</a></td>
-                        <td class="amount">${formatAmount(transfer.amount)}</td>
-                        <td>${transfer.ledger}</td>
-                        <td>${transfer.code}</td>
-                        <td>${formatTimestamp(transfer.timestamp)}</td>
-                    </tr>
-                `).join('')}
+                ${data.transfers.map(transferRow).join('')}
             </tbody>
         </table>
-        ${data.next_timestamp ? `
-            <div class="pagination">
-                <button class="btn btn-secondary"
-                        hx-get="/api/v1/transfers?limit=100&reversed=true&after_timestamp=${data.next_timestamp}"
-                        hx-target="#${containerId}">
-                    Load More
-                </button>
-            </div>
-        ` : ''}
+        ${pagerHtml(url, containerId, data)}
     `;
 
     container.innerHTML = html;
@@ -187,6 +274,243 @@ function renderTransfersTable(containerId: string, data: TransfersResponse): voi
     (window as any).htmx?.process(container);
 }
 
+// Totals of the transfers seen live since the page loaded
+let liveTransfers = 0;
+let liveVolume = 0n;
+
+/**
+ * Apply a live update in place: refresh the rows of an account whose
+ * balances changed, or add a new transfer to the dashboard.
+ */
+function applyLiveEvent(event: LiveEvent<ApiAccount, ApiTransfer>): void {
+    if (event.type === 'account') {
+        const account = event.account;
+        document
+            .querySelectorAll(`tr[data-account-id="${account.id}"]`)
+            .forEach(row => { row.outerHTML = accountRow(account); });
+        return;
+    }
+
+    liveTransfers += 1;
+    liveVolume += BigInt(event.transfer.amount);
+    const count = document.getElementById('live-transfers');
+    if (count) count.textContent = liveTransfers.toLocaleString();
+    const volume = document.getElementById('live-volume');
+    if (volume) volume.textContent = formatAmount(liveVolume.toString());
+
+    // The dashboard lists the newest transfers first; keep its length.
+    const recent = document.querySelector('#recent-transfers tbody');
+    if (recent) {
+        recent.insertAdjacentHTML('afterbegin', transferRow(event.transfer));
+        recent.lastElementChild?.remove();
+    }
+}
+
+/**
+ * Add a summary card for a ledger to the dashboard, or reload its card if
+ * it is already there.
+ */
+function showLedgerSummary(ledger: number): void {
+    const container = document.getElementById('ledger-summaries');
+    if (!container) return;
+
+    let card = document.getElementById(`ledger-${ledger}`);
+    if (!card) {
+        card = document.createElement('div');
+        card.id = `ledger-${ledger}`;
+        card.className = 'stat-card';
+        card.innerHTML = `<div class="label">Ledger ${ledger}</div><div class="value">...</div>`;
+        container.appendChild(card);
+    }
+    (window as any).htmx?.ajax('GET', `/api/v1/ledgers/${ledger}/summary`, { target: card, swap: 'innerHTML' });
+}
+
+interface SavedQuery {
+    name: string;
+    kind: 'accounts' | 'transfers';
+    ledger?: number;
+    codes?: number[];
+    timestamp_min?: number;
+    timestamp_max?: number;
+    user_data_128?: string;
+    user_data_64?: number;
+    user_data_32?: number;
+    account?: string;
+    direction?: 'debits' | 'credits' | 'both';
+    reversed?: boolean;
+    limit?: number;
+}
+
+/**
+ * A datetime-local input value as a TigerBeetle timestamp, in decimal
+ * nanoseconds (beyond a JavaScript number's precision).
+ */
+function toTimestamp(value: string): string {
+    return (BigInt(new Date(value).getTime()) * 1_000_000n).toString();
+}
+
+/**
+ * A TigerBeetle timestamp as a datetime-local input value.
+ */
+function fromTimestamp(timestamp: number): string {
+    const date = new Date(timestamp / 1_000_000);
+    const local = new Date(date.getTime() - date.getTimezoneOffset() * 60_000);
+    return local.toISOString().slice(0, 16);
+}
+
+/**
+ * Build a saved query from the query form, as JSON text.
+ */
+function queryFromForm(form: HTMLFormElement): string {
+    const data = new FormData(form);
+    const text = (name: string) => String(data.get(name) ?? '').trim();
+    const query: Record<string, unknown> = {
+        name: text('name'),
+        kind: text('kind'),
+        direction: text('direction'),
+        reversed: data.get('reversed') === 'true',
+        limit: Number(text('limit') || 100),
+    };
+    ['ledger', 'user_data_64', 'user_data_32'].forEach(name => {
+        if (text(name)) query[name] = Number(text(name));
+    });
+    ['user_data_128', 'account'].forEach(name => {
+        if (text(name)) query[name] = text(name);
+    });
+    if (text('codes')) {
+        query.codes = text('codes').split(',').map(code => Number(code.trim()));
+    }
+    // Timestamps go in as strings and come out as bare numbers.
+    if (text('from')) query.timestamp_min = toTimestamp(text('from'));
+    if (text('until')) query.timestamp_max = toTimestamp(text('until'));
+    return JSON.stringify(query).replace(/"(timestamp_m(?:in|ax))":"(\d+)"/g, '"$1":$2');
+}
+
+/**
+ * Fill the query form with a saved query, to run as is or change.
+ */
+function fillQueryForm(form: HTMLFormElement, query: SavedQuery): void {
+    const set = (name: string, value: unknown) => {
+        const input = form.elements.namedItem(name) as HTMLInputElement | null;
+        if (input) input.value = value === undefined ? '' : String(value);
+    };
+    set('name', query.name);
+    set('kind', query.kind);
+    set('ledger', query.ledger);
+    set('codes', query.codes?.join(', '));
+    set('from', query.timestamp_min === undefined ? undefined : fromTimestamp(query.timestamp_min));
+    set('until', query.timestamp_max === undefined ? undefined : fromTimestamp(query.timestamp_max));
+    set('user_data_128', query.user_data_128);
+    set('user_data_64', query.user_data_64);
+    set('user_data_32', query.user_data_32);
+    set('account', query.account);
+    set('direction', query.direction ?? 'both');
+    set('limit', query.limit ?? 100);
+    const reversed = form.elements.namedItem('reversed') as HTMLInputElement | null;
+    if (reversed) reversed.checked = query.reversed ?? false;
+}
+
+/**
+ * The message of an API error response.
+ */
+async function errorMessage(response: Response): Promise<string> {
+    try {
+        const data = await response.json();
+        return data.error ?? response.statusText;
+    } catch {
+        return response.statusText;
+    }
+}
+
+/**
+ * Show why a saved query request failed, or clear the message.
+ */
+function showQueryError(message: string): void {
+    const error = document.getElementById('query-error');
+    if (error) error.textContent = message;
+}
+
+/**
+ * Save the query in the form, then reload the saved list.
+ */
+async function saveQuery(form: HTMLFormElement): Promise<void> {
+    const response = await fetch('/api/v1/queries', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: queryFromForm(form),
+    });
+    if (!response.ok) {
+        showQueryError(await errorMessage(response));
+        return;
+    }
+    showQueryError('');
+    document.body.dispatchEvent(new Event('queries-changed'));
+}
+
+/**
+ * Handle the Edit and Delete buttons of the saved query list.
+ */
+async function onQueryButton(button: HTMLElement): Promise<void> {
+    const edit = button.dataset.queryEdit;
+    const remove = button.dataset.queryDelete;
+    if (edit !== undefined) {
+        const response = await fetch(`/api/v1/queries/${encodeURIComponent(edit)}`);
+        const form = document.getElementById('query-form') as HTMLFormElement | null;
+        if (response.ok && form) fillQueryForm(form, await response.json());
+    } else if (remove !== undefined && confirm(`Delete saved query ${remove}?`)) {
+        const response = await fetch(`/api/v1/queries/${encodeURIComponent(remove)}`, {
+            method: 'DELETE',
+        });
+        if (!response.ok) {
+            showQueryError(await errorMessage(response));
+            return;
+        }
+        document.body.dispatchEvent(new Event('queries-changed'));
+    }
+}
+
+/**
+ * Milliseconds left as "1h 02m 03s", "4m 05s" or "6s".
+ */
+function formatCountdown(ms: number): string {
+    const secs = Math.floor(ms / 1000);
+    const hours = Math.floor(secs / 3600);
+    const minutes = Math.floor((secs % 3600) / 60);
+    const seconds = secs % 60;
+    const pad = (n: number) => String(n).padStart(2, '0');
+    if (hours > 0) return `${hours}h ${pad(minutes)}m ${pad(seconds)}s`;
+    if (minutes > 0) return `${minutes}m ${pad(seconds)}s`;
+    return `${seconds}s`;
+}
+
+/**
+ * Count down the time left on each pending transfer shown.
+ */
+function tickCountdowns(): void {
+    document.querySelectorAll<HTMLElement>('[data-expires-at]').forEach(cell => {
+        const left = Number(cell.dataset.expiresAt) - Date.now();
+        cell.textContent = left > 0 ? formatCountdown(left) : 'expired';
+    });
+}
+
+/**
+ * After a post or void button's request, report any failure and reload
+ * the pending list.
+ */
+function onPendingAction(xhr: XMLHttpRequest): void {
+    try {
+        const data = JSON.parse(xhr.responseText);
+        if (data.error) {
+            alert(data.error);
+        } else if (data.failed > 0) {
+            alert(`Failed: ${data.results[0].result}`);
+        }
+    } catch (e) {
+        console.error('Error parsing JSON response:', e);
+    }
+    document.body.dispatchEvent(new Event('pending-changed'));
+}
+
 // Initialize
 document.addEventListener('DOMContentLoaded', () => {
     console.log('TigerBeetle Web initialized');
@@ -206,11 +530,63 @@ document.addEventListener('DOMContentLoaded', () => {
         renderTransfersTable,
     };
 
+    loadAliases();
+    connectLive(applyLiveEvent);
+
+    document.getElementById('ledger-form')?.addEventListener('submit', (event: Event) => {
+        event.preventDefault();
+        const form = event.target as HTMLFormElement;
+        const ledger = Number(new FormData(form).get('ledger'));
+        if (ledger > 0) showLedgerSummary(ledger);
+    });
+
+    // The queries page is swapped in by HTMX, so listen on the document.
+    document.addEventListener('submit', (event: Event) => {
+        const form = event.target as HTMLFormElement;
+        if (form.id !== 'query-form') return;
+        event.preventDefault();
+        saveQuery(form);
+    });
+    document.addEventListener('click', (event: Event) => {
+        const button = (event.target as HTMLElement).closest<HTMLElement>(
+            '[data-query-edit], [data-query-delete]'
+        );
+        if (button) onQueryButton(button);
+    });
+
+    setInterval(tickCountdowns, 1000);
+    document.body.addEventListener('htmx:afterRequest', (event: any) => {
+        if (event.detail.elt?.hasAttribute('data-pending-action')) {
+            onPendingAction(event.detail.xhr);
+        }
+    });
+
     // Handle HTMX events to transform JSON responses into HTML
     document.body.addEventListener('htmx:beforeSwap', (event: any) => {
         const target = event.detail.target;
         const xhr = event.detail.xhr;
 
+        // The query builder explains an invalid filter in its results.
+        if (xhr.status === 422 && target.id === 'explore-results') {
+            event.detail.shouldSwap = true;
+            event.detail.isError = false;
+            return;
+        }
+
+        // So does the transfer form, with each field's error.
+        if (xhr.status === 422 && target.id === 'new-transfer') {
+            event.detail.shouldSwap = true;
+            event.detail.isError = false;
+            return;
+        }
+
+        // And the ID search, for an ID it can't search by.
+        if (xhr.status === 422 && target.id === 'id-search-results') {
+            event.detail.shouldSwap = true;
+            event.detail.isError = false;
+            return;
+        }
+
         // Check if this is an API response
         if (xhr.getResponseHeader('content-type')?.includes('application/json')) {
             try {
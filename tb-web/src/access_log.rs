@@ -0,0 +1,207 @@
+//! Access logging.
+//!
+//! One `tb_web::access` tracing event per request, with the method, path,
+//! status, latency (until the response headers), TigerBeetle operations the
+//! handler performed and the client's address. Error responses are always
+//! logged; others can be sampled with `--access-log-sample N` to log one in
+//! every N.
+//!
+//! Operations run by tasks the handler spawns, such as export paging, are
+//! not counted.
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+tokio::task_local! {
+    /// TigerBeetle operations performed by the current request.
+    static TB_OPS: Cell<u32>;
+}
+
+/// Count a TigerBeetle operation against the current request, if any.
+pub fn record_tb_op() {
+    let _ = TB_OPS.try_with(|ops| ops.set(ops.get() + 1));
+}
+
+/// Access log sampling.
+pub struct AccessLog {
+    /// Log one in this many successful requests; zero logs only errors.
+    sample: u64,
+    /// Successful requests seen.
+    seen: AtomicU64,
+}
+
+impl AccessLog {
+    pub fn new(sample: u64) -> Arc<Self> {
+        Arc::new(Self {
+            sample,
+            seen: AtomicU64::new(0),
+        })
+    }
+
+    fn sampled(&self) -> bool {
+        self.sample != 0
+            && self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.sample)
+    }
+}
+
+/// Middleware logging each request.
+pub async fn log(
+    State(log): State<Arc<AccessLog>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let forwarded_for = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let (response, tb_ops) = TB_OPS
+        .scope(Cell::new(0), async {
+            let response = next.run(request).await;
+            (response, TB_OPS.with(Cell::get))
+        })
+        .await;
+
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() || log.sampled() {
+        tracing::info!(
+            target: "tb_web::access",
+            method = %method,
+            path = %path,
+            status = status.as_u16(),
+            latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+            tb_ops,
+            client = %peer.ip(),
+            forwarded_for = forwarded_for.as_deref(),
+            "request"
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, account, FakeCluster};
+    use axum::body::Body;
+    use axum::middleware;
+    use std::io;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    /// Where the log lines of a test go.
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Lines {
+        fn take(&self) -> Vec<String> {
+            let bytes = std::mem::take(&mut *self.0.lock().unwrap());
+            String::from_utf8(bytes)
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_sampled() {
+        let sampled = |sample, requests| {
+            let log = AccessLog::new(sample);
+            (0..requests).map(|_| log.sampled()).collect::<Vec<_>>()
+        };
+        assert_eq!(sampled(0, 3), [false, false, false]);
+        assert_eq!(sampled(1, 3), [true, true, true]);
+        assert_eq!(
+            sampled(3, 7),
+            [true, false, false, true, false, false, true]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_tb_op() {
+        // Outside a request, nothing is counted, and nothing breaks.
+        record_tb_op();
+        let ops = TB_OPS
+            .scope(Cell::new(0), async {
+                record_tb_op();
+                record_tb_op();
+                TB_OPS.with(Cell::get)
+            })
+            .await;
+        assert_eq!(ops, 2);
+    }
+
+    #[tokio::test]
+    async fn test_log() {
+        let lines = Lines::default();
+        let writer = lines.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1)], &[]);
+        // Only errors, and one in two of the rest.
+        let router = testing::router(testing::config(), &cluster)
+            .layer(middleware::from_fn_with_state(AccessLog::new(2), log));
+        let request = |uri: &str| {
+            Request::get(uri)
+                .header("x-forwarded-for", "203.0.113.7")
+                .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let mut statuses = Vec::new();
+        for uri in [
+            "/api/v1/accounts/1",
+            "/api/v1/accounts/1",
+            "/api/v1/accounts/1",
+            "/api/v1/accounts/2",
+        ] {
+            let response = router.clone().oneshot(request(uri)).await.unwrap();
+            statuses.push(response.status().as_u16());
+        }
+        assert_eq!(statuses, [200, 200, 200, 404]);
+
+        let lines = lines.take();
+        assert_eq!(lines.len(), 3, "{:#?}", lines);
+        for (line, status) in lines.iter().zip([200, 200, 404]) {
+            assert!(line.contains("tb_web::access: request"), "{}", line);
+            assert!(line.contains("method=GET"), "{}", line);
+            assert!(line.contains(&format!("status={}", status)), "{}", line);
+            // Each lookup is one operation.
+            assert!(line.contains("tb_ops=1"), "{}", line);
+            assert!(line.contains("client=10.0.0.1"), "{}", line);
+            assert!(line.contains(r#"forwarded_for="203.0.113.7""#), "{}", line);
+        }
+        assert!(lines[2].contains("path=/api/v1/accounts/2"), "{}", lines[2]);
+    }
+}
@@ -0,0 +1,295 @@
+//! Account alias registry.
+//!
+//! With `--aliases-db`, tb-web keeps a SQLite database of names for
+//! accounts: an account may have one alias, unique across accounts, and
+//! any number of labels (free-form tags such as `customer` or `eu`). The UI
+//! shows the alias in place of the shortened hex ID wherever the account
+//! appears, and the labels on its detail page. TigerBeetle knows nothing
+//! of the registry; it is tb-web's own.
+//!
+//! Every alias is held in memory, so rendering a table runs no query. The
+//! database is read at startup and written through on each change.
+
+use crate::error::AppError;
+use rusqlite::{params, Connection, ErrorCode};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Longest alias, in characters.
+pub const NAME_LEN_MAX: usize = 64;
+
+/// Longest label, in characters.
+pub const LABEL_LEN_MAX: usize = 32;
+
+/// An account's alias.
+#[derive(Debug, Clone)]
+pub struct Alias {
+    pub account_id: u128,
+    pub name: String,
+    pub labels: Vec<String>,
+}
+
+/// The registry, or a stand-in without a database that names nothing.
+pub struct Aliases {
+    registry: Option<Arc<Registry>>,
+}
+
+struct Registry {
+    db: Mutex<Connection>,
+    by_id: RwLock<HashMap<u128, Alias>>,
+}
+
+impl Aliases {
+    /// Open (creating if need be) the database at `path`.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let error = |e: rusqlite::Error| format!("{}: {}", path.display(), e);
+        let db = Connection::open(path).map_err(error)?;
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS aliases (
+                account_id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                labels TEXT NOT NULL DEFAULT '[]'
+            )",
+        )
+        .map_err(error)?;
+
+        let mut by_id = HashMap::new();
+        {
+            let mut select = db
+                .prepare("SELECT account_id, name, labels FROM aliases")
+                .map_err(error)?;
+            let rows = select
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })
+                .map_err(error)?;
+            for row in rows {
+                let (account_id, name, labels) = row.map_err(error)?;
+                let account_id = u128::from_str_radix(&account_id, 16)
+                    .map_err(|_| format!("{}: bad account ID '{}'", path.display(), account_id))?;
+                let labels = serde_json::from_str(&labels)
+                    .map_err(|e| format!("{}: bad labels of '{}': {}", path.display(), name, e))?;
+                by_id.insert(
+                    account_id,
+                    Alias {
+                        account_id,
+                        name,
+                        labels,
+                    },
+                );
+            }
+        }
+
+        Ok(Self {
+            registry: Some(Arc::new(Registry {
+                db: Mutex::new(db),
+                by_id: RwLock::new(by_id),
+            })),
+        })
+    }
+
+    /// A registry that names nothing, for when there is no database.
+    pub fn disabled() -> Self {
+        Self { registry: None }
+    }
+
+    /// The alias of account `id`.
+    pub fn get(&self, id: u128) -> Option<Alias> {
+        let registry = self.registry.as_ref()?;
+        registry.by_id.read().unwrap().get(&id).cloned()
+    }
+
+    /// The alias of the account with hex ID `id`.
+    pub fn get_hex(&self, id: &str) -> Option<Alias> {
+        self.get(u128::from_str_radix(id, 16).ok()?)
+    }
+
+    /// The alias named `name`.
+    pub fn find(&self, name: &str) -> Option<Alias> {
+        let registry = self.registry.as_ref()?;
+        let by_id = registry.by_id.read().unwrap();
+        by_id.values().find(|alias| alias.name == name).cloned()
+    }
+
+    /// Every alias, by name.
+    pub fn list(&self) -> Vec<Alias> {
+        let Some(registry) = &self.registry else {
+            return Vec::new();
+        };
+        let mut aliases: Vec<Alias> = registry.by_id.read().unwrap().values().cloned().collect();
+        aliases.sort_by(|a, b| a.name.cmp(&b.name));
+        aliases
+    }
+
+    /// Set an account's alias, replacing any it had.
+    pub async fn set(&self, alias: Alias) -> Result<(), AppError> {
+        let registry = self.registry()?;
+        tokio::task::spawn_blocking(move || {
+            let db = registry.db.lock().unwrap();
+            let labels = serde_json::to_string(&alias.labels)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            db.execute(
+                "INSERT INTO aliases (account_id, name, labels) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (account_id) DO UPDATE SET name = ?2, labels = ?3",
+                params![format!("{:032x}", alias.account_id), alias.name, labels],
+            )
+            .map_err(|e| match e.sqlite_error_code() {
+                Some(ErrorCode::ConstraintViolation) => {
+                    AppError::BadRequest(format!("Alias '{}' is already in use", alias.name))
+                }
+                _ => AppError::Internal(format!("Saving alias failed: {}", e)),
+            })?;
+            registry
+                .by_id
+                .write()
+                .unwrap()
+                .insert(alias.account_id, alias);
+            Ok(())
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+    }
+
+    /// Remove an account's alias, returning whether it had one.
+    pub async fn remove(&self, id: u128) -> Result<bool, AppError> {
+        let registry = self.registry()?;
+        tokio::task::spawn_blocking(move || {
+            let db = registry.db.lock().unwrap();
+            db.execute(
+                "DELETE FROM aliases WHERE account_id = ?1",
+                params![format!("{:032x}", id)],
+            )
+            .map_err(|e| AppError::Internal(format!("Removing alias failed: {}", e)))?;
+            Ok(registry.by_id.write().unwrap().remove(&id).is_some())
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+    }
+
+    fn registry(&self) -> Result<Arc<Registry>, AppError> {
+        self.registry
+            .clone()
+            .ok_or_else(|| AppError::Internal("No alias database configured".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn alias(account_id: u128, name: &str) -> Alias {
+        Alias {
+            account_id,
+            name: name.to_string(),
+            labels: Vec::new(),
+        }
+    }
+
+    fn names(aliases: &Aliases) -> Vec<String> {
+        aliases.list().into_iter().map(|a| a.name).collect()
+    }
+
+    fn in_memory() -> Aliases {
+        Aliases::open(Path::new(":memory:")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_set_get_remove() {
+        let aliases = in_memory();
+        assert!(aliases.get(1).is_none());
+        aliases.set(alias(1, "savings")).await.unwrap();
+        let mut labelled = alias(0xff, "checking");
+        labelled.labels = vec!["eu".to_string(), "customer".to_string()];
+        aliases.set(labelled).await.unwrap();
+
+        assert_eq!(aliases.get(1).unwrap().name, "savings");
+        assert_eq!(aliases.get_hex("ff").unwrap().labels, ["eu", "customer"]);
+        assert_eq!(aliases.find("savings").unwrap().account_id, 1);
+        assert!(aliases.find("Savings").is_none());
+        assert!(aliases.get_hex("not hex").is_none());
+        // By name.
+        assert_eq!(names(&aliases), ["checking", "savings"]);
+
+        // An account has one alias: setting another renames it.
+        aliases.set(alias(1, "reserve")).await.unwrap();
+        assert_eq!(aliases.get(1).unwrap().name, "reserve");
+        assert!(aliases.find("savings").is_none());
+        assert_eq!(names(&aliases), ["checking", "reserve"]);
+
+        assert!(aliases.remove(1).await.unwrap());
+        assert!(!aliases.remove(1).await.unwrap());
+        assert!(aliases.get(1).is_none());
+        assert_eq!(names(&aliases), ["checking"]);
+    }
+
+    #[tokio::test]
+    async fn test_names_are_unique() {
+        let aliases = in_memory();
+        aliases.set(alias(1, "savings")).await.unwrap();
+        let error = aliases.set(alias(2, "savings")).await.unwrap_err();
+        assert!(
+            matches!(&error, AppError::BadRequest(message) if message == "Alias 'savings' is already in use"),
+            "{:?}",
+            error
+        );
+        // Neither the database nor the memory changed.
+        assert!(aliases.get(2).is_none());
+        assert_eq!(aliases.find("savings").unwrap().account_id, 1);
+
+        // Freed by renaming, the name can be taken.
+        aliases.set(alias(1, "reserve")).await.unwrap();
+        aliases.set(alias(2, "savings")).await.unwrap();
+        assert_eq!(aliases.find("savings").unwrap().account_id, 2);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let aliases = Aliases::disabled();
+        assert!(aliases.get(1).is_none());
+        assert!(aliases.find("savings").is_none());
+        assert!(aliases.list().is_empty());
+        assert!(matches!(
+            aliases.set(alias(1, "savings")).await,
+            Err(AppError::Internal(_))
+        ));
+        assert!(aliases.remove(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reopen() {
+        let path = env::temp_dir().join(format!("tb-web-aliases-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let aliases = Aliases::open(&path).unwrap();
+        let mut labelled = alias(u128::MAX, "max");
+        labelled.labels = vec!["eu".to_string()];
+        aliases.set(labelled).await.unwrap();
+        aliases.set(alias(1, "gone")).await.unwrap();
+        aliases.remove(1).await.unwrap();
+        drop(aliases);
+
+        let aliases = Aliases::open(&path).unwrap();
+        assert_eq!(names(&aliases), ["max"]);
+        assert_eq!(aliases.get(u128::MAX).unwrap().labels, ["eu"]);
+
+        // A row that can't be read is an error, naming it.
+        aliases
+            .registry
+            .as_ref()
+            .unwrap()
+            .db
+            .lock()
+            .unwrap()
+            .execute("INSERT INTO aliases VALUES ('1', 'bad', 'eu')", [])
+            .unwrap();
+        drop(aliases);
+        let error = Aliases::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(error.contains("bad labels of 'bad'"), "{}", error);
+    }
+}
@@ -0,0 +1,229 @@
+//! Stable, machine-readable names for TigerBeetle result codes.
+//!
+//! The names are the snake_case names TigerBeetle itself uses, spelled out
+//! here rather than derived from the Rust variant names so that renaming a
+//! variant in tb-rs cannot change the API.
+
+use tb_rs::{CreateAccountResult, CreateTransferResult};
+
+/// Name of an account creation result.
+pub fn account_result(result: CreateAccountResult) -> &'static str {
+    use CreateAccountResult::*;
+    match result {
+        Ok => "ok",
+        LinkedEventFailed => "linked_event_failed",
+        LinkedEventChainOpen => "linked_event_chain_open",
+        TimestampMustBeZero => "timestamp_must_be_zero",
+        ReservedField => "reserved_field",
+        ReservedFlag => "reserved_flag",
+        IdMustNotBeZero => "id_must_not_be_zero",
+        IdMustNotBeIntMax => "id_must_not_be_int_max",
+        FlagsAreMutuallyExclusive => "flags_are_mutually_exclusive",
+        DebitsPendingMustBeZero => "debits_pending_must_be_zero",
+        DebitsPostedMustBeZero => "debits_posted_must_be_zero",
+        CreditsPendingMustBeZero => "credits_pending_must_be_zero",
+        CreditsPostedMustBeZero => "credits_posted_must_be_zero",
+        LedgerMustNotBeZero => "ledger_must_not_be_zero",
+        CodeMustNotBeZero => "code_must_not_be_zero",
+        ExistsWithDifferentFlags => "exists_with_different_flags",
+        ExistsWithDifferentUserData128 => "exists_with_different_user_data_128",
+        ExistsWithDifferentUserData64 => "exists_with_different_user_data_64",
+        ExistsWithDifferentUserData32 => "exists_with_different_user_data_32",
+        ExistsWithDifferentLedger => "exists_with_different_ledger",
+        ExistsWithDifferentCode => "exists_with_different_code",
+        Exists => "exists",
+        ImportedEventExpected => "imported_event_expected",
+        ImportedEventNotExpected => "imported_event_not_expected",
+        ImportedEventTimestampOutOfRange => "imported_event_timestamp_out_of_range",
+        ImportedEventTimestampMustNotAdvance => "imported_event_timestamp_must_not_advance",
+        ImportedEventTimestampMustNotRegress => "imported_event_timestamp_must_not_regress",
+    }
+}
+
+/// Name of a transfer creation result.
+pub fn transfer_result(result: CreateTransferResult) -> &'static str {
+    use CreateTransferResult::*;
+    match result {
+        Ok => "ok",
+        LinkedEventFailed => "linked_event_failed",
+        LinkedEventChainOpen => "linked_event_chain_open",
+        TimestampMustBeZero => "timestamp_must_be_zero",
+        ReservedFlag => "reserved_flag",
+        IdMustNotBeZero => "id_must_not_be_zero",
+        IdMustNotBeIntMax => "id_must_not_be_int_max",
+        FlagsAreMutuallyExclusive => "flags_are_mutually_exclusive",
+        DebitAccountIdMustNotBeZero => "debit_account_id_must_not_be_zero",
+        DebitAccountIdMustNotBeIntMax => "debit_account_id_must_not_be_int_max",
+        CreditAccountIdMustNotBeZero => "credit_account_id_must_not_be_zero",
+        CreditAccountIdMustNotBeIntMax => "credit_account_id_must_not_be_int_max",
+        AccountsMustBeDifferent => "accounts_must_be_different",
+        PendingIdMustBeZero => "pending_id_must_be_zero",
+        PendingIdMustNotBeZero => "pending_id_must_not_be_zero",
+        PendingIdMustNotBeIntMax => "pending_id_must_not_be_int_max",
+        PendingIdMustBeDifferent => "pending_id_must_be_different",
+        TimeoutReservedForPendingTransfer => "timeout_reserved_for_pending_transfer",
+        LedgerMustNotBeZero => "ledger_must_not_be_zero",
+        CodeMustNotBeZero => "code_must_not_be_zero",
+        DebitAccountNotFound => "debit_account_not_found",
+        CreditAccountNotFound => "credit_account_not_found",
+        AccountsMustHaveTheSameLedger => "accounts_must_have_the_same_ledger",
+        TransferMustHaveTheSameLedgerAsAccounts => "transfer_must_have_the_same_ledger_as_accounts",
+        PendingTransferNotFound => "pending_transfer_not_found",
+        PendingTransferNotPending => "pending_transfer_not_pending",
+        PendingTransferHasDifferentDebitAccountId => {
+            "pending_transfer_has_different_debit_account_id"
+        }
+        PendingTransferHasDifferentCreditAccountId => {
+            "pending_transfer_has_different_credit_account_id"
+        }
+        PendingTransferHasDifferentLedger => "pending_transfer_has_different_ledger",
+        PendingTransferHasDifferentCode => "pending_transfer_has_different_code",
+        ExceedsPendingTransferAmount => "exceeds_pending_transfer_amount",
+        PendingTransferHasDifferentAmount => "pending_transfer_has_different_amount",
+        PendingTransferAlreadyPosted => "pending_transfer_already_posted",
+        PendingTransferAlreadyVoided => "pending_transfer_already_voided",
+        PendingTransferExpired => "pending_transfer_expired",
+        ExistsWithDifferentFlags => "exists_with_different_flags",
+        ExistsWithDifferentDebitAccountId => "exists_with_different_debit_account_id",
+        ExistsWithDifferentCreditAccountId => "exists_with_different_credit_account_id",
+        ExistsWithDifferentAmount => "exists_with_different_amount",
+        ExistsWithDifferentPendingId => "exists_with_different_pending_id",
+        ExistsWithDifferentUserData128 => "exists_with_different_user_data_128",
+        ExistsWithDifferentUserData64 => "exists_with_different_user_data_64",
+        ExistsWithDifferentUserData32 => "exists_with_different_user_data_32",
+        ExistsWithDifferentTimeout => "exists_with_different_timeout",
+        ExistsWithDifferentCode => "exists_with_different_code",
+        Exists => "exists",
+        OverflowsDebitsPending => "overflows_debits_pending",
+        OverflowsCreditsPending => "overflows_credits_pending",
+        OverflowsDebitsPosted => "overflows_debits_posted",
+        OverflowsCreditsPosted => "overflows_credits_posted",
+        OverflowsDebits => "overflows_debits",
+        OverflowsCredits => "overflows_credits",
+        OverflowsTimeout => "overflows_timeout",
+        ExceedsCredits => "exceeds_credits",
+        ExceedsDebits => "exceeds_debits",
+        ImportedEventExpected => "imported_event_expected",
+        ImportedEventNotExpected => "imported_event_not_expected",
+        ImportedEventTimestampOutOfRange => "imported_event_timestamp_out_of_range",
+        ImportedEventTimestampMustNotAdvance => "imported_event_timestamp_must_not_advance",
+        ImportedEventTimestampMustNotRegress => "imported_event_timestamp_must_not_regress",
+        ImportedEventTimestampMustPostdateDebitAccount => {
+            "imported_event_timestamp_must_postdate_debit_account"
+        }
+        ImportedEventTimestampMustPostdateCreditAccount => {
+            "imported_event_timestamp_must_postdate_credit_account"
+        }
+        ImportedEventTimeoutMustBeZero => "imported_event_timeout_must_be_zero",
+        ClosingTransferMustBePending => "closing_transfer_must_be_pending",
+        DebitAccountAlreadyClosed => "debit_account_already_closed",
+        CreditAccountAlreadyClosed => "credit_account_already_closed",
+        ExistsWithDifferentLedger => "exists_with_different_ledger",
+        IdAlreadyFailed => "id_already_failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// TigerBeetle's spelling of a variant name: snake_case, with a number
+    /// split off as its own word.
+    fn snake_case(variant: &str) -> String {
+        let mut name = String::new();
+        let mut prev: Option<char> = None;
+        for c in variant.chars() {
+            let boundary = match prev {
+                Some(p) => c.is_ascii_uppercase() || (c.is_ascii_digit() && !p.is_ascii_digit()),
+                None => false,
+            };
+            if boundary {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+            prev = Some(c);
+        }
+        name
+    }
+
+    #[test]
+    fn test_account_result() {
+        use CreateAccountResult::*;
+        let cases = [
+            (Ok, 0, "ok"),
+            (LinkedEventFailed, 1, "linked_event_failed"),
+            (IdMustNotBeIntMax, 7, "id_must_not_be_int_max"),
+            (
+                ExistsWithDifferentUserData128,
+                16,
+                "exists_with_different_user_data_128",
+            ),
+            (
+                ExistsWithDifferentUserData32,
+                18,
+                "exists_with_different_user_data_32",
+            ),
+            (Exists, 21, "exists"),
+            (
+                ImportedEventTimestampMustNotRegress,
+                26,
+                "imported_event_timestamp_must_not_regress",
+            ),
+        ];
+        for (result, code, name) in cases {
+            assert_eq!(result as u32, code, "{:?}", result);
+            assert_eq!(account_result(result), name, "{:?}", result);
+            assert_eq!(snake_case(&format!("{:?}", result)), name);
+        }
+    }
+
+    #[test]
+    fn test_transfer_result() {
+        use CreateTransferResult::*;
+        let cases = [
+            (Ok, 0, "ok"),
+            (
+                DebitAccountIdMustNotBeIntMax,
+                9,
+                "debit_account_id_must_not_be_int_max",
+            ),
+            (
+                PendingTransferHasDifferentDebitAccountId,
+                27,
+                "pending_transfer_has_different_debit_account_id",
+            ),
+            (
+                ExistsWithDifferentUserData64,
+                42,
+                "exists_with_different_user_data_64",
+            ),
+            (ExceedsCredits, 54, "exceeds_credits"),
+            (
+                ImportedEventTimestampMustPostdateCreditAccount,
+                62,
+                "imported_event_timestamp_must_postdate_credit_account",
+            ),
+            (
+                ExistsWithDifferentLedger,
+                67,
+                "exists_with_different_ledger",
+            ),
+            (IdAlreadyFailed, 68, "id_already_failed"),
+        ];
+        for (result, code, name) in cases {
+            assert_eq!(result as u32, code, "{:?}", result);
+            assert_eq!(transfer_result(result), name, "{:?}", result);
+            assert_eq!(snake_case(&format!("{:?}", result)), name);
+        }
+    }
+
+    #[test]
+    fn test_snake_case() {
+        assert_eq!(snake_case("Ok"), "ok");
+        assert_eq!(snake_case("IdMustNotBeIntMax"), "id_must_not_be_int_max");
+        assert_eq!(
+            snake_case("ExistsWithDifferentUserData128"),
+            "exists_with_different_user_data_128"
+        );
+    }
+}
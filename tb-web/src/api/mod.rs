@@ -1,5 +1,6 @@
 //! API types and utilities.
 
+pub mod codes;
 pub mod types;
 
 pub use types::*;
@@ -1,9 +1,18 @@
-//! JSON-serializable API response types.
+//! JSON-serializable API request and response types.
 //!
-//! u128 values are serialized as strings to avoid JavaScript precision issues.
+//! u128 values are serialized as strings to avoid JavaScript precision issues:
+//! IDs and `user_data_128` as hex, amounts as decimal.
 
-use serde::Serialize;
-use tb_rs::{Account, AccountBalance, Transfer};
+use crate::aliases::Alias;
+use crate::currency::Currencies;
+use crate::error::ErrorResponse;
+use crate::transport::ClientHealth;
+use crate::validate::hex_id;
+use crate::webhooks::{DeliveryStatus, WebhookStatus};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use tb_rs::{Account, AccountBalance, AccountFlags, Transfer, TransferFlags};
 
 /// Account response type.
 #[derive(Debug, Serialize)]
@@ -20,6 +29,20 @@ pub struct ApiAccount {
     pub code: u16,
     pub flags: u16,
     pub timestamp: u64,
+    /// Amounts in the ledger's currency, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<AccountDisplay>,
+}
+
+/// An account's amounts formatted in its ledger's currency.
+#[derive(Debug, Serialize)]
+pub struct AccountDisplay {
+    pub debits_pending: String,
+    pub debits_posted: String,
+    pub credits_pending: String,
+    pub credits_posted: String,
+    /// Credits posted less debits posted.
+    pub net_balance: String,
 }
 
 impl From<&Account> for ApiAccount {
@@ -37,7 +60,36 @@ impl From<&Account> for ApiAccount {
             code: a.code,
             flags: a.flags.bits(),
             timestamp: a.timestamp,
+            display: None,
+        }
+    }
+}
+
+impl ApiAccount {
+    /// Add the amounts formatted in the ledger's currency, if it has one.
+    pub fn with_display(mut self, currencies: &Currencies) -> Self {
+        if let Some(currency) = currencies.get(self.ledger) {
+            self.display = Some(AccountDisplay {
+                debits_pending: currency.format(&self.debits_pending),
+                debits_posted: currency.format(&self.debits_posted),
+                credits_pending: currency.format(&self.credits_pending),
+                credits_posted: currency.format(&self.credits_posted),
+                net_balance: currency
+                    .format(&net_balance(&self.credits_posted, &self.debits_posted)),
+            });
         }
+        self
+    }
+}
+
+/// `credits - debits` of two decimal u128 strings, as a decimal string.
+fn net_balance(credits: &str, debits: &str) -> String {
+    let credits: u128 = credits.parse().unwrap_or(0);
+    let debits: u128 = debits.parse().unwrap_or(0);
+    if credits >= debits {
+        (credits - debits).to_string()
+    } else {
+        format!("-{}", debits - credits)
     }
 }
 
@@ -57,6 +109,15 @@ pub struct ApiTransfer {
     pub code: u16,
     pub flags: u16,
     pub timestamp: u64,
+    /// Amount in the ledger's currency, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<TransferDisplay>,
+}
+
+/// A transfer's amount formatted in its ledger's currency.
+#[derive(Debug, Serialize)]
+pub struct TransferDisplay {
+    pub amount: String,
 }
 
 impl From<&Transfer> for ApiTransfer {
@@ -75,10 +136,23 @@ impl From<&Transfer> for ApiTransfer {
             code: t.code,
             flags: t.flags.bits(),
             timestamp: t.timestamp,
+            display: None,
         }
     }
 }
 
+impl ApiTransfer {
+    /// Add the amount formatted in the ledger's currency, if it has one.
+    pub fn with_display(mut self, currencies: &Currencies) -> Self {
+        if let Some(currency) = currencies.get(self.ledger) {
+            self.display = Some(TransferDisplay {
+                amount: currency.format(&self.amount),
+            });
+        }
+        self
+    }
+}
+
 /// Account balance response type.
 #[derive(Debug, Serialize)]
 pub struct ApiAccountBalance {
@@ -107,6 +181,12 @@ pub struct AccountsResponse {
     pub accounts: Vec<ApiAccount>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next_timestamp: Option<u64>,
+    /// `before_timestamp` of the previous page, if there may be one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<u64>,
+    /// `after_timestamp` of the next page, if there may be one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
 }
 
 /// Paginated transfers response.
@@ -115,6 +195,37 @@ pub struct TransfersResponse {
     pub transfers: Vec<ApiTransfer>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next_timestamp: Option<u64>,
+    /// `before_timestamp` of the previous page, if there may be one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<u64>,
+    /// `after_timestamp` of the next page, if there may be one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// A transfer that is still pending: neither posted, voided nor expired.
+#[derive(Debug, Serialize)]
+pub struct ApiPendingTransfer {
+    #[serde(flatten)]
+    pub transfer: ApiTransfer,
+    /// Cluster time it expires and is voided, in nanoseconds; absent if it
+    /// never expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Seconds left until it expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in_secs: Option<u64>,
+}
+
+/// Pending transfer list response, newest first.
+#[derive(Debug, Serialize)]
+pub struct PendingTransfersResponse {
+    pub transfers: Vec<ApiPendingTransfer>,
+    /// Transfers looked at to find them.
+    pub scanned: u32,
+    /// Whether the scan or the limit stopped before the oldest transfer, so
+    /// older pending transfers may be missing.
+    pub truncated: bool,
 }
 
 /// Account balances response.
@@ -123,9 +234,912 @@ pub struct BalancesResponse {
     pub balances: Vec<ApiAccountBalance>,
 }
 
+/// Long-polled account balance response.
+#[derive(Debug, Serialize)]
+pub struct BalanceWaitResponse {
+    pub account: ApiAccount,
+    /// Whether a transfer touched the account since `since_timestamp`, or
+    /// while waiting; false if the wait ran out.
+    pub changed: bool,
+    /// Timestamp of the newest transfer known to touch the account (zero if
+    /// none), to pass as `since_timestamp` to wait for the next one.
+    pub timestamp: u64,
+}
+
+/// One bucket of downsampled balance history.
+#[derive(Debug, Serialize)]
+pub struct ApiBalancePoint {
+    /// Start of the bucket.
+    pub timestamp: u64,
+    /// Lowest, highest and last net balance (credits minus debits posted)
+    /// in the bucket.
+    pub min: String,
+    pub max: String,
+    pub last: String,
+    /// Posted totals as of the last balance in the bucket.
+    pub credits_posted: String,
+    pub debits_posted: String,
+}
+
+/// Downsampled balance history response.
+#[derive(Debug, Serialize)]
+pub struct BalanceChartResponse {
+    pub points: Vec<ApiBalancePoint>,
+    /// History rows the points summarize.
+    pub rows: u64,
+}
+
+/// Account creation request item.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewAccount {
+    pub id: String,
+    #[serde(default)]
+    pub user_data_128: Option<String>,
+    #[serde(default)]
+    pub user_data_64: u64,
+    #[serde(default)]
+    pub user_data_32: u32,
+    pub ledger: u32,
+    pub code: u16,
+    /// Flag names, e.g. `["linked", "history"]`.
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+impl TryFrom<&NewAccount> for Account {
+    type Error = String;
+
+    fn try_from(a: &NewAccount) -> Result<Self, String> {
+        Ok(Account {
+            id: hex_id("id", &a.id)?,
+            user_data_128: match &a.user_data_128 {
+                Some(value) => hex_id("user_data_128", value)?,
+                None => 0,
+            },
+            user_data_64: a.user_data_64,
+            user_data_32: a.user_data_32,
+            ledger: a.ledger,
+            code: a.code,
+            flags: account_flags(&a.flags)?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Transfer creation request item.
+///
+/// A pending transfer sets the `pending` flag and may set `timeout`; a
+/// linked chain is consecutive items flagged `linked` but the last.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewTransfer {
+    pub id: String,
+    pub debit_account_id: String,
+    pub credit_account_id: String,
+    /// Decimal amount.
+    pub amount: String,
+    #[serde(default)]
+    pub pending_id: Option<String>,
+    #[serde(default)]
+    pub user_data_128: Option<String>,
+    #[serde(default)]
+    pub user_data_64: u64,
+    #[serde(default)]
+    pub user_data_32: u32,
+    /// Seconds until a pending transfer expires; zero never expires.
+    #[serde(default)]
+    pub timeout: u32,
+    pub ledger: u32,
+    pub code: u16,
+    /// Flag names, e.g. `["linked", "pending"]`.
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+impl TryFrom<&NewTransfer> for Transfer {
+    type Error = String;
+
+    fn try_from(t: &NewTransfer) -> Result<Self, String> {
+        Ok(Transfer {
+            id: hex_id("id", &t.id)?,
+            debit_account_id: hex_id("debit_account_id", &t.debit_account_id)?,
+            credit_account_id: hex_id("credit_account_id", &t.credit_account_id)?,
+            amount: parse_amount(&t.amount)?,
+            pending_id: match &t.pending_id {
+                Some(value) => hex_id("pending_id", value)?,
+                None => 0,
+            },
+            user_data_128: match &t.user_data_128 {
+                Some(value) => hex_id("user_data_128", value)?,
+                None => 0,
+            },
+            user_data_64: t.user_data_64,
+            user_data_32: t.user_data_32,
+            timeout: t.timeout,
+            ledger: t.ledger,
+            code: t.code,
+            flags: transfer_flags(&t.flags)?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Body of a post or void of a pending transfer; may be omitted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PendingAction {
+    /// ID of the posting or voiding transfer (default: a new ID).
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Decimal amount to post, for a partial post (default: all of it).
+    #[serde(default)]
+    pub amount: Option<String>,
+}
+
+impl PendingAction {
+    /// The transfer that posts or voids (per `flags`) pending transfer
+    /// `pending_id`.
+    ///
+    /// Accounts, ledger and code are left zero, which TigerBeetle takes
+    /// from the pending transfer.
+    pub fn transfer(&self, pending_id: u128, flags: TransferFlags) -> Result<Transfer, String> {
+        let id = match &self.id {
+            Some(value) => hex_id("id", value)?,
+            None => tb_rs::id(),
+        };
+        let amount = match &self.amount {
+            Some(value) => parse_amount(value)?,
+            // The whole pending amount.
+            None if flags.contains(TransferFlags::POST_PENDING_TRANSFER) => u128::MAX,
+            None => 0,
+        };
+        Ok(Transfer {
+            id,
+            pending_id,
+            amount,
+            flags,
+            ..Default::default()
+        })
+    }
+}
+
+/// Parse a decimal amount.
+fn parse_amount(value: &str) -> Result<u128, String> {
+    value
+        .parse()
+        .map_err(|_| format!("amount: invalid decimal value {:?}", value))
+}
+
+/// Parse account flag names.
+fn account_flags(names: &[String]) -> Result<AccountFlags, String> {
+    names.iter().try_fold(AccountFlags::empty(), |flags, name| {
+        let flag = match name.as_str() {
+            "linked" => AccountFlags::LINKED,
+            "debits_must_not_exceed_credits" => AccountFlags::DEBITS_MUST_NOT_EXCEED_CREDITS,
+            "credits_must_not_exceed_debits" => AccountFlags::CREDITS_MUST_NOT_EXCEED_DEBITS,
+            "history" => AccountFlags::HISTORY,
+            "imported" => AccountFlags::IMPORTED,
+            "closed" => AccountFlags::CLOSED,
+            _ => return Err(format!("flags: unknown account flag {:?}", name)),
+        };
+        Ok(flags | flag)
+    })
+}
+
+/// Parse transfer flag names.
+fn transfer_flags(names: &[String]) -> Result<TransferFlags, String> {
+    names
+        .iter()
+        .try_fold(TransferFlags::empty(), |flags, name| {
+            let flag = match name.as_str() {
+                "linked" => TransferFlags::LINKED,
+                "pending" => TransferFlags::PENDING,
+                "post_pending_transfer" => TransferFlags::POST_PENDING_TRANSFER,
+                "void_pending_transfer" => TransferFlags::VOID_PENDING_TRANSFER,
+                "balancing_debit" => TransferFlags::BALANCING_DEBIT,
+                "balancing_credit" => TransferFlags::BALANCING_CREDIT,
+                "closing_debit" => TransferFlags::CLOSING_DEBIT,
+                "closing_credit" => TransferFlags::CLOSING_CREDIT,
+                "imported" => TransferFlags::IMPORTED,
+                _ => return Err(format!("flags: unknown transfer flag {:?}", name)),
+            };
+            Ok(flags | flag)
+        })
+}
+
+/// Outcome of one item of a create request.
+#[derive(Debug, Serialize)]
+pub struct CreateResult {
+    pub index: u32,
+    pub id: String,
+    /// `ok`, or a result name from [`codes`](super::codes).
+    pub result: &'static str,
+}
+
+/// Create request response.
+#[derive(Debug, Serialize)]
+pub struct CreateResponse {
+    pub created: usize,
+    pub failed: usize,
+    pub results: Vec<CreateResult>,
+}
+
+/// Live update event, sent over the WebSocket as JSON text.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// A transfer was created.
+    Transfer { transfer: ApiTransfer },
+    /// An account's balances changed.
+    Account { account: ApiAccount },
+}
+
+/// Body of a webhook request: the new transfers matching the endpoint's
+/// filter.
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload {
+    /// Hex ID of this delivery, the same in every attempt at it.
+    pub delivery: String,
+    pub transfers: Vec<ApiTransfer>,
+}
+
+/// A webhook endpoint and how its deliveries are going.
+#[derive(Debug, Serialize)]
+pub struct ApiWebhook {
+    pub url: String,
+    pub ledger: Option<u32>,
+    pub code: Option<u16>,
+    pub account: Option<String>,
+    pub delivered: u64,
+    /// Deliveries given up on after every attempt failed.
+    pub failed: u64,
+    /// Deliveries dropped because too many were waiting.
+    pub dropped: u64,
+    pub queued: usize,
+    /// Milliseconds since a delivery last succeeded, if one has.
+    pub last_success_ms_ago: Option<u64>,
+    pub last_error: Option<String>,
+    /// The delivery in progress, then recent ones, newest first.
+    pub deliveries: Vec<ApiWebhookDelivery>,
+}
+
+impl From<WebhookStatus> for ApiWebhook {
+    fn from(w: WebhookStatus) -> Self {
+        Self {
+            url: w.config.url.to_string(),
+            ledger: w.config.ledger,
+            code: w.config.code,
+            account: w.config.account.map(|id| format!("{:032x}", id)),
+            delivered: w.delivered,
+            failed: w.failed,
+            dropped: w.dropped,
+            queued: w.queued,
+            last_success_ms_ago: w.last_success.map(ms_ago),
+            last_error: w.last_error,
+            deliveries: w
+                .deliveries
+                .into_iter()
+                .map(ApiWebhookDelivery::from)
+                .collect(),
+        }
+    }
+}
+
+/// One webhook delivery.
+#[derive(Debug, Serialize)]
+pub struct ApiWebhookDelivery {
+    pub id: String,
+    pub transfers: usize,
+    /// `pending`, `delivered` or `failed`.
+    pub status: &'static str,
+    pub attempts: u32,
+    pub last_attempt_ms_ago: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl From<DeliveryStatus> for ApiWebhookDelivery {
+    fn from(d: DeliveryStatus) -> Self {
+        Self {
+            id: format!("{:032x}", d.id),
+            transfers: d.transfers,
+            status: match d.delivered {
+                None => "pending",
+                Some(true) => "delivered",
+                Some(false) => "failed",
+            },
+            attempts: d.attempts,
+            last_attempt_ms_ago: d.last_attempt.map(ms_ago),
+            last_error: d.last_error,
+        }
+    }
+}
+
+/// Webhook status response.
+#[derive(Debug, Serialize)]
+pub struct WebhooksResponse {
+    pub webhooks: Vec<ApiWebhook>,
+}
+
+fn ms_ago(time: SystemTime) -> u64 {
+    time.elapsed().unwrap_or_default().as_millis() as u64
+}
+
+/// `time` in RFC 3339, in UTC to the millisecond.
+pub fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// An account alias.
+#[derive(Debug, Serialize)]
+pub struct ApiAlias {
+    pub account_id: String,
+    pub name: String,
+    pub labels: Vec<String>,
+}
+
+impl From<&Alias> for ApiAlias {
+    fn from(a: &Alias) -> Self {
+        Self {
+            account_id: format!("{:032x}", a.account_id),
+            name: a.name.clone(),
+            labels: a.labels.clone(),
+        }
+    }
+}
+
+/// Body of setting an account's alias.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetAlias {
+    pub name: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// Alias list response.
+#[derive(Debug, Serialize)]
+pub struct AliasesResponse {
+    pub aliases: Vec<ApiAlias>,
+}
+
+/// What a saved query lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryKind {
+    Accounts,
+    Transfers,
+}
+
+/// Which of an account's transfers a saved query lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Debits,
+    Credits,
+    #[default]
+    Both,
+}
+
+/// A saved query: a named filter over accounts or transfers (see
+/// [`crate::queries`]). Unset fields match anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedQuery {
+    pub name: String,
+    pub kind: QueryKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<u32>,
+    /// Any of these codes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub codes: Vec<u16>,
+    /// Created at or after this timestamp (nanoseconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_min: Option<u64>,
+    /// Created at or before this timestamp (nanoseconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_max: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data_128: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data_64: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data_32: Option<u32>,
+    /// Transfers debiting or crediting this account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// With `account`, only its debits or only its credits.
+    #[serde(default)]
+    pub direction: Direction,
+    /// Newest first.
+    #[serde(default)]
+    pub reversed: bool,
+    /// Most records per run.
+    #[serde(default = "default_query_limit")]
+    pub limit: u32,
+}
+
+fn default_query_limit() -> u32 {
+    100
+}
+
+/// Saved query list response.
+#[derive(Debug, Serialize)]
+pub struct QueriesResponse {
+    pub queries: Vec<SavedQuery>,
+}
+
+/// Outcome of one row of an import.
+#[derive(Debug, Serialize)]
+pub struct ImportRow {
+    /// 1-based record number in the upload, not counting a CSV header.
+    pub row: u64,
+    /// The record's ID, if it parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// `ok`, a result name from [`codes`](super::codes), or `invalid`.
+    pub result: &'static str,
+    /// Why an invalid row was rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Import response: how many rows there were and how they went, and the
+/// outcome of each of the first `--import-report-rows-max`.
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub rows: u64,
+    pub created: u64,
+    pub failed: u64,
+    /// Whether there were more rows than `results` lists.
+    pub truncated: bool,
+    pub results: Vec<ImportRow>,
+}
+
+/// Seeding request: how much demo data to generate.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeedRequest {
+    #[serde(default = "default_seed_accounts")]
+    pub accounts: u32,
+    #[serde(default = "default_seed_transfers")]
+    pub transfers: u32,
+    #[serde(default = "default_seed_ledger")]
+    pub ledger: u32,
+    #[serde(default = "default_seed_code")]
+    pub code: u16,
+    /// Largest transfer amount; each is drawn from 1 to this.
+    #[serde(default = "default_seed_max_amount")]
+    pub max_amount: u64,
+    /// How transfers pick accounts: `uniform` or `zipfian`.
+    #[serde(default)]
+    pub distribution: Option<String>,
+    /// Generate the same IDs and amounts on every request with this seed.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_seed_accounts() -> u32 {
+    100
+}
+
+fn default_seed_transfers() -> u32 {
+    1000
+}
+
+fn default_seed_ledger() -> u32 {
+    1
+}
+
+fn default_seed_code() -> u16 {
+    1
+}
+
+fn default_seed_max_amount() -> u64 {
+    10_000
+}
+
+/// Seeding response.
+#[derive(Debug, Serialize)]
+pub struct SeedReport {
+    pub ledger: u32,
+    /// Stored in the `user_data_64` of everything generated.
+    pub run_id: u64,
+    pub accounts_created: u64,
+    pub accounts_failed: u64,
+    pub transfers_created: u64,
+    pub transfers_failed: u64,
+    /// The error seeding stopped at, if it did; the counts are of the
+    /// batches before it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+/// What a snapshot archive holds, as its `manifest.json`.
+#[derive(Debug, Serialize)]
+pub struct SnapshotManifest {
+    /// When the snapshot was taken (RFC 3339).
+    pub created_at: String,
+    /// `ndjson` or `csv`.
+    pub format: &'static str,
+    /// The filters the records match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_data_128: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_data_64: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_data_32: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_min: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_max: Option<u64>,
+    /// Records in each file.
+    pub accounts: u64,
+    pub transfers: u64,
+}
+
+/// Ledger summary response.
+#[derive(Debug, Serialize)]
+pub struct LedgerSummary {
+    pub ledger: u32,
+    pub accounts: u64,
+    pub debits_posted: String,
+    pub credits_posted: String,
+    pub debits_pending: String,
+    pub credits_pending: String,
+    /// Length of the recent window, in seconds.
+    pub window_secs: u64,
+    pub recent_transfers: u64,
+    pub recent_volume: String,
+    /// Amounts in the ledger's currency, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<LedgerSummaryDisplay>,
+}
+
+/// A ledger summary's amounts formatted in the ledger's currency.
+#[derive(Debug, Serialize)]
+pub struct LedgerSummaryDisplay {
+    pub debits_posted: String,
+    pub credits_posted: String,
+    pub debits_pending: String,
+    pub credits_pending: String,
+    pub recent_volume: String,
+}
+
+impl LedgerSummary {
+    /// Add the amounts formatted in the ledger's currency, if it has one.
+    pub fn with_display(mut self, currencies: &Currencies) -> Self {
+        if let Some(currency) = currencies.get(self.ledger) {
+            self.display = Some(LedgerSummaryDisplay {
+                debits_posted: currency.format(&self.debits_posted),
+                credits_posted: currency.format(&self.credits_posted),
+                debits_pending: currency.format(&self.debits_pending),
+                credits_pending: currency.format(&self.credits_pending),
+                recent_volume: currency.format(&self.recent_volume),
+            });
+        }
+        self
+    }
+}
+
+/// Dashboard statistics, as of the last refresh (see [`crate::stats`]).
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    /// When the statistics were refreshed; absent until the first refresh
+    /// is done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    pub accounts: u64,
+    pub transfers: u64,
+    pub transfers_last_hour: u64,
+    pub transfers_last_day: u64,
+    pub ledgers: Vec<LedgerStats>,
+}
+
+/// Statistics of one ledger.
+#[derive(Debug, Serialize)]
+pub struct LedgerStats {
+    pub ledger: u32,
+    pub accounts: u64,
+    pub transfers: u64,
+    pub transfers_last_hour: u64,
+    pub transfers_last_day: u64,
+    /// Sum of every transfer's amount.
+    pub volume: String,
+    /// Amounts in the ledger's currency, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<LedgerStatsDisplay>,
+}
+
+/// A ledger's statistics formatted in its currency.
+#[derive(Debug, Serialize)]
+pub struct LedgerStatsDisplay {
+    pub volume: String,
+}
+
+impl LedgerStats {
+    /// Add the amounts formatted in the ledger's currency, if it has one.
+    pub fn with_display(mut self, currencies: &Currencies) -> Self {
+        if let Some(currency) = currencies.get(self.ledger) {
+            self.display = Some(LedgerStatsDisplay {
+                volume: currency.format(&self.volume),
+            });
+        }
+        self
+    }
+}
+
+/// The full IDs a shortened one may stand for.
+#[derive(Debug, Serialize)]
+pub struct ResolveResponse {
+    pub matches: Vec<ResolvedId>,
+    /// Whether there were more matches than listed.
+    pub truncated: bool,
+}
+
+/// A known ID matching a shortened one.
+#[derive(Debug, Serialize)]
+pub struct ResolvedId {
+    /// `account` or `transfer`.
+    pub kind: &'static str,
+    pub id: String,
+    /// The account's alias, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+}
+
+/// Recently viewed response.
+#[derive(Debug, Serialize)]
+pub struct RecentResponse {
+    /// Newest first.
+    pub viewed: Vec<ApiViewed>,
+}
+
+/// An account or transfer opened in the web UI.
+#[derive(Debug, Serialize)]
+pub struct ApiViewed {
+    /// `account` or `transfer`.
+    pub kind: &'static str,
+    pub id: String,
+    /// The account's alias, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// When it was last opened (RFC 3339).
+    pub viewed_at: String,
+}
+
+/// Whether a ledger's debits add up to its credits.
+#[derive(Debug, Serialize)]
+pub struct LedgerReconciliation {
+    pub ledger: u32,
+    /// Accounts checked.
+    pub accounts: u64,
+    /// Whether debits equal credits, both posted and pending.
+    pub balanced: bool,
+    pub debits_posted: String,
+    pub credits_posted: String,
+    pub debits_pending: String,
+    pub credits_pending: String,
+    /// Posted debits minus posted credits; "0" when they agree.
+    pub posted_discrepancy: String,
+    /// Pending debits minus pending credits; "0" when they agree.
+    pub pending_discrepancy: String,
+    /// How long the check took.
+    pub duration_ms: u64,
+    /// Amounts in the ledger's currency, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<LedgerReconciliationDisplay>,
+}
+
+/// A reconciliation's amounts in its ledger's currency.
+#[derive(Debug, Serialize)]
+pub struct LedgerReconciliationDisplay {
+    pub debits_posted: String,
+    pub credits_posted: String,
+    pub debits_pending: String,
+    pub credits_pending: String,
+    pub posted_discrepancy: String,
+    pub pending_discrepancy: String,
+}
+
+impl LedgerReconciliation {
+    /// Add the amounts formatted in the ledger's currency, if it has one.
+    pub fn with_display(mut self, currencies: &Currencies) -> Self {
+        if let Some(currency) = currencies.get(self.ledger) {
+            self.display = Some(LedgerReconciliationDisplay {
+                debits_posted: currency.format(&self.debits_posted),
+                credits_posted: currency.format(&self.credits_posted),
+                debits_pending: currency.format(&self.debits_pending),
+                credits_pending: currency.format(&self.credits_pending),
+                posted_discrepancy: currency.format(&self.posted_discrepancy),
+                pending_discrepancy: currency.format(&self.pending_discrepancy),
+            });
+        }
+        self
+    }
+}
+
+/// Account in the transfer graph.
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    /// Volume debited from / credited to the account by the transfers.
+    pub debits: String,
+    pub credits: String,
+}
+
+/// Transfers from one account to another in the transfer graph.
+#[derive(Debug, Serialize)]
+pub struct GraphEdge {
+    /// Debit account ID.
+    pub source: String,
+    /// Credit account ID.
+    pub target: String,
+    pub amount: String,
+    pub transfers: u64,
+}
+
+/// Transfer graph response.
+#[derive(Debug, Serialize)]
+pub struct GraphResponse {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Transfers aggregated.
+    pub transfers: u32,
+}
+
+/// Health and counters of a pooled TigerBeetle client.
+#[derive(Debug, Serialize)]
+pub struct ApiClientHealth {
+    pub ready: bool,
+    /// `ready`, `reconnecting` or `stopped`.
+    pub state: &'static str,
+    /// Sessions started again after failing.
+    pub restarts: u64,
+    pub requests: u64,
+    pub errors: u64,
+    /// Reads sent again after a transient error.
+    pub retries: u64,
+    /// Requests dropped unsent because their caller went away.
+    pub abandoned: u64,
+    pub in_flight: u32,
+    /// Milliseconds since a request last succeeded, if one has.
+    pub last_success_ms_ago: Option<u64>,
+}
+
+impl From<ClientHealth> for ApiClientHealth {
+    fn from(h: ClientHealth) -> Self {
+        Self {
+            ready: h.ready,
+            state: h.state.name(),
+            restarts: h.restarts,
+            requests: h.requests,
+            errors: h.errors,
+            retries: h.retries,
+            abandoned: h.abandoned,
+            in_flight: h.in_flight,
+            last_success_ms_ago: h.last_success.map(ms_ago),
+        }
+    }
+}
+
+/// Cluster details response.
+#[derive(Debug, Serialize)]
+pub struct ClusterResponse {
+    pub cluster_id: String,
+    /// Largest request in bytes, from registration.
+    pub batch_size_limit: Option<u32>,
+    /// When a request on any client last succeeded (RFC 3339).
+    pub last_success_at: Option<String>,
+    pub clients: Vec<ApiClientSession>,
+}
+
+/// A pooled client's session with the cluster.
+#[derive(Debug, Serialize)]
+pub struct ApiClientSession {
+    /// `ready`, `reconnecting` or `stopped`.
+    pub state: &'static str,
+    /// The rest is unset without a session, e.g. while reconnecting.
+    pub client_id: Option<String>,
+    pub view: Option<u32>,
+    /// The primary replica of the view.
+    pub primary: Option<usize>,
+    pub replicas: Vec<ApiReplica>,
+    /// When a request last succeeded (RFC 3339).
+    pub last_success_at: Option<String>,
+}
+
+/// A replica as one client sees it.
+#[derive(Debug, Serialize)]
+pub struct ApiReplica {
+    pub replica: usize,
+    /// The address the client is connected at, if connected.
+    pub address: Option<String>,
+}
+
+impl From<ClientHealth> for ApiClientSession {
+    fn from(h: ClientHealth) -> Self {
+        let session = h.session;
+        Self {
+            state: h.state.name(),
+            client_id: session.as_ref().map(|s| format!("{:032x}", s.client_id)),
+            view: session.as_ref().map(|s| s.view),
+            primary: session
+                .as_ref()
+                .filter(|s| !s.replicas.is_empty())
+                .map(|s| s.view as usize % s.replicas.len()),
+            replicas: session
+                .iter()
+                .flat_map(|s| s.replicas.iter().enumerate())
+                .map(|(replica, address)| ApiReplica {
+                    replica,
+                    address: address.map(|a| a.to_string()),
+                })
+                .collect(),
+            last_success_at: h.last_success.map(rfc3339),
+        }
+    }
+}
+
 /// Health check response.
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
     pub status: String,
+    /// Whether any client can reach the cluster.
     pub tb_connected: bool,
+    pub clients: Vec<ApiClientHealth>,
+    /// Each replica in `--tb-address` order.
+    pub replicas: Vec<ApiReplicaHealth>,
+}
+
+/// How many pooled clients a replica is connected to; no address, since
+/// the health endpoint is public.
+#[derive(Debug, Serialize)]
+pub struct ApiReplicaHealth {
+    pub replica: usize,
+    pub clients_connected: usize,
+}
+
+impl ApiReplicaHealth {
+    /// The connections to each of `count` replicas across `clients`.
+    pub fn tally(count: usize, clients: &[ClientHealth]) -> Vec<Self> {
+        (0..count)
+            .map(|replica| Self {
+                replica,
+                clients_connected: clients
+                    .iter()
+                    .filter_map(|h| h.session.as_ref())
+                    .filter(|s| s.replicas.get(replica).is_some_and(Option::is_some))
+                    .count(),
+            })
+            .collect()
+    }
+}
+
+/// Liveness probe response.
+#[derive(Debug, Serialize)]
+pub struct LivenessResponse {
+    pub status: &'static str,
+    pub uptime_secs: u64,
+}
+
+/// Readiness probe response.
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    /// Whether every check passed.
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+/// One readiness condition.
+#[derive(Debug, Serialize)]
+pub struct ReadinessCheck {
+    /// `registered`, `pool` or `last_op`.
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
 }
@@ -0,0 +1,521 @@
+//! Bearer token authentication against an OIDC issuer.
+//!
+//! With `--oidc-issuer`, every API request must carry an
+//! `Authorization: Bearer` JWT signed by one of the issuer's keys, issued by
+//! it for `--oidc-audience` and unexpired. The token's roles claim is mapped
+//! to a [`Role`], and each handler demands one by taking [`Viewer`],
+//! [`Operator`] or [`Admin`] as an argument:
+//!
+//! - viewer: read accounts, transfers and aliases, export them, run saved
+//!   queries, watch live updates, see cluster details;
+//! - operator: also create or import transfers, post or void pending ones,
+//!   set aliases and saved queries, and reconcile ledgers;
+//! - admin: also create or import accounts, generate demo data, and see
+//!   webhook deliveries.
+//!
+//! Without an issuer, every request is let through, as before. The static
+//! frontend, `/health` and the `/livez` and `/readyz` probes are always
+//! public; the pages load their data from the API, so an SSO proxy in front
+//! of tb-web must forward the token.
+//!
+//! The issuer's signing keys are found through OIDC discovery at startup and
+//! fetched again when a token names a key that is not known yet, so that key
+//! rotation needs no restart.
+
+use crate::error::AppError;
+use crate::state::AppState;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+/// Minimum time between fetches of the issuer's keys, so tokens naming
+/// unknown keys cannot make tb-web hammer the issuer.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Signature algorithms accepted. Only asymmetric ones: the keys are public.
+const ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// What a caller may do; each role includes those before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!(
+                "Unknown role '{}' (expected viewer, operator or admin)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// OIDC settings.
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL, as in the tokens' `iss` claim.
+    pub issuer: String,
+    /// Expected `aud` claim.
+    pub audience: String,
+    /// Claim holding the caller's roles or groups; dots descend into
+    /// nested objects (e.g. `realm_access.roles`).
+    pub roles_claim: String,
+    /// Claim values granting each role. When empty, the values `viewer`,
+    /// `operator` and `admin` grant the role of the same name.
+    pub role_map: Vec<(String, Role)>,
+}
+
+/// Validates bearer tokens for one issuer.
+pub struct Oidc {
+    config: OidcConfig,
+    http: reqwest::Client,
+    jwks_uri: String,
+    keys: RwLock<JwkSet>,
+    /// When the keys were last fetched.
+    refreshed: Mutex<Instant>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    jwks_uri: String,
+}
+
+impl Oidc {
+    /// Discover the issuer's keys.
+    pub async fn discover(config: OidcConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::new();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            config.issuer.trim_end_matches('/')
+        );
+        let discovery: Discovery = http
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if discovery.issuer != config.issuer {
+            return Err(format!(
+                "OIDC issuer mismatch: configured '{}', but {} names '{}'",
+                config.issuer, url, discovery.issuer
+            )
+            .into());
+        }
+
+        let keys = fetch_keys(&http, &discovery.jwks_uri).await?;
+        tracing::info!(
+            "Authenticating with OIDC issuer {} ({} signing keys)",
+            config.issuer,
+            keys.keys.len()
+        );
+        Ok(Self {
+            config,
+            http,
+            jwks_uri: discovery.jwks_uri,
+            keys: RwLock::new(keys),
+            refreshed: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Validate `token` and return the highest role it grants.
+    async fn authenticate(&self, token: &str) -> Result<Role, AppError> {
+        let header = jsonwebtoken::decode_header(token)
+            .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?;
+        if !ALGORITHMS.contains(&header.alg) {
+            return Err(AppError::Unauthorized(format!(
+                "Token algorithm {:?} is not accepted",
+                header.alg
+            )));
+        }
+        let kid = header
+            .kid
+            .ok_or_else(|| AppError::Unauthorized("Token has no key ID".to_string()))?;
+        let key = self.key(&kid, header.alg).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)
+            .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?
+            .claims;
+
+        self.role(&claims)
+            .ok_or_else(|| AppError::Forbidden("Token grants no tb-web role".to_string()))
+    }
+
+    /// The key `kid` for `alg`, fetching the keys again if it is unknown.
+    async fn key(&self, kid: &str, alg: Algorithm) -> Result<DecodingKey, AppError> {
+        if self.keys.read().await.find(kid).is_none() {
+            self.refresh().await;
+        }
+        let keys = self.keys.read().await;
+        let jwk = keys
+            .find(kid)
+            .ok_or_else(|| AppError::Unauthorized(format!("Unknown signing key '{}'", kid)))?;
+        if let Some(key_alg) = jwk.common.key_algorithm {
+            if key_alg.to_string() != format!("{:?}", alg) {
+                return Err(AppError::Unauthorized(format!(
+                    "Signing key '{}' is for {}, not {:?}",
+                    kid, key_alg, alg
+                )));
+            }
+        }
+        DecodingKey::from_jwk(jwk)
+            .map_err(|e| AppError::Unauthorized(format!("Unusable signing key '{}': {}", kid, e)))
+    }
+
+    /// Fetch the keys again, unless they were fetched recently.
+    async fn refresh(&self) {
+        let mut refreshed = self.refreshed.lock().await;
+        if refreshed.elapsed() < MIN_REFRESH_INTERVAL {
+            return;
+        }
+        *refreshed = Instant::now();
+        match fetch_keys(&self.http, &self.jwks_uri).await {
+            Ok(keys) => *self.keys.write().await = keys,
+            Err(e) => tracing::warn!("Fetching OIDC signing keys failed: {}", e),
+        }
+    }
+
+    /// The highest role granted by `claims`.
+    fn role(&self, claims: &Value) -> Option<Role> {
+        let claim = self
+            .config
+            .roles_claim
+            .split('.')
+            .try_fold(claims, |value, name| value.get(name))?;
+        let values: Vec<&str> = match claim {
+            Value::String(value) => vec![value.as_str()],
+            Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+            _ => return None,
+        };
+        values
+            .into_iter()
+            .filter_map(|value| {
+                if self.config.role_map.is_empty() {
+                    value.parse().ok()
+                } else {
+                    self.config
+                        .role_map
+                        .iter()
+                        .find(|(granted_by, _)| granted_by == value)
+                        .map(|&(_, role)| role)
+                }
+            })
+            .max()
+    }
+}
+
+async fn fetch_keys(http: &reqwest::Client, jwks_uri: &str) -> Result<JwkSet, reqwest::Error> {
+    http.get(jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// Check that a request with `headers` carries a token granting at least
+/// `required`.
+pub async fn authorize(
+    headers: &HeaderMap,
+    state: &AppState,
+    required: Role,
+) -> Result<(), AppError> {
+    check(state.auth.as_ref(), headers, required).await
+}
+
+/// [`authorize`] against `oidc`; without one, every request is let through.
+async fn check(oidc: Option<&Oidc>, headers: &HeaderMap, required: Role) -> Result<(), AppError> {
+    let Some(oidc) = oidc else {
+        return Ok(());
+    };
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+
+    let role = oidc.authenticate(token.trim()).await?;
+    if role < required {
+        return Err(AppError::Forbidden(format!(
+            "Requires the {} role, token grants {}",
+            required, role
+        )));
+    }
+    Ok(())
+}
+
+macro_rules! role_extractor {
+    ($name:ident, $role:expr) => {
+        #[async_trait]
+        impl FromRequestParts<Arc<AppState>> for $name {
+            type Rejection = AppError;
+
+            async fn from_request_parts(
+                parts: &mut Parts,
+                state: &Arc<AppState>,
+            ) -> Result<Self, Self::Rejection> {
+                authorize(&parts.headers, state, $role).await?;
+                Ok($name)
+            }
+        }
+    };
+}
+
+/// Extractor requiring the viewer role.
+pub struct Viewer;
+
+/// Extractor requiring the operator role.
+pub struct Operator;
+
+/// Extractor requiring the admin role.
+pub struct Admin;
+
+role_extractor!(Viewer, Role::Viewer);
+role_extractor!(Operator, Role::Operator);
+role_extractor!(Admin, Role::Admin);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use jsonwebtoken::{EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const ISSUER: &str = "https://sso.example.com";
+    const AUDIENCE: &str = "tb-web";
+    const KID: &str = "k1";
+
+    /// An issuer whose only key is `KID`, and the private half of it.
+    fn oidc(roles_claim: &str, role_map: Vec<(String, Role)>) -> (Oidc, EncodingKey) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let keys: JwkSet = serde_json::from_value(json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "kid": KID,
+                "alg": "EdDSA",
+                "x": URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+            }]
+        }))
+        .unwrap();
+        let oidc = Oidc {
+            config: OidcConfig {
+                issuer: ISSUER.to_string(),
+                audience: AUDIENCE.to_string(),
+                roles_claim: roles_claim.to_string(),
+                role_map,
+            },
+            http: reqwest::Client::new(),
+            jwks_uri: String::new(),
+            keys: RwLock::new(keys),
+            // Just fetched, so an unknown key is not fetched again.
+            refreshed: Mutex::new(Instant::now()),
+        };
+        (oidc, EncodingKey::from_ed_der(pkcs8.as_ref()))
+    }
+
+    /// Unexpired claims from `ISSUER` for `AUDIENCE` with `extra` added.
+    fn claims(extra: Value) -> Value {
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let mut claims = json!({ "iss": ISSUER, "aud": AUDIENCE, "exp": exp });
+        claims
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        claims
+    }
+
+    fn sign(key: &EncodingKey, kid: &str, claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(kid.to_string());
+        jsonwebtoken::encode(&header, claims, key).unwrap()
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_role_nested_claim() {
+        let (oidc, _) = oidc("realm_access.roles", Vec::new());
+        let claims = json!({ "realm_access": { "roles": ["operator"] } });
+        assert_eq!(oidc.role(&claims), Some(Role::Operator));
+        assert_eq!(oidc.role(&json!({ "realm_access": {} })), None);
+        assert_eq!(oidc.role(&json!({ "roles": ["operator"] })), None);
+    }
+
+    #[test]
+    fn test_role_string_or_array_claim() {
+        let (oidc, _) = oidc("roles", Vec::new());
+        assert_eq!(oidc.role(&json!({ "roles": "admin" })), Some(Role::Admin));
+        assert_eq!(
+            oidc.role(&json!({ "roles": ["viewer"] })),
+            Some(Role::Viewer)
+        );
+        assert_eq!(oidc.role(&json!({ "roles": 3 })), None);
+        assert_eq!(
+            oidc.role(&json!({ "roles": [3, "viewer"] })),
+            Some(Role::Viewer)
+        );
+    }
+
+    #[test]
+    fn test_role_map_replaces_default_names() {
+        let role_map = vec![
+            ("ledger-readers".to_string(), Role::Viewer),
+            ("ledger-admins".to_string(), Role::Admin),
+        ];
+        let (oidc, _) = oidc("groups", role_map);
+        assert_eq!(
+            oidc.role(&json!({ "groups": ["ledger-readers", "admin"] })),
+            Some(Role::Viewer)
+        );
+        assert_eq!(oidc.role(&json!({ "groups": ["admin"] })), None);
+        assert_eq!(
+            oidc.role(&json!({ "groups": "ledger-admins" })),
+            Some(Role::Admin)
+        );
+    }
+
+    #[test]
+    fn test_role_highest_wins() {
+        let (oidc, _) = oidc("roles", Vec::new());
+        let claims = json!({ "roles": ["viewer", "admin", "operator"] });
+        assert_eq!(oidc.role(&claims), Some(Role::Admin));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let (oidc, key) = oidc("roles", Vec::new());
+        let token = sign(&key, KID, &claims(json!({ "roles": ["operator"] })));
+        assert_eq!(oidc.authenticate(&token).await.unwrap(), Role::Operator);
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_no_role() {
+        let (oidc, key) = oidc("roles", Vec::new());
+        let token = sign(&key, KID, &claims(json!({ "roles": ["auditor"] })));
+        let result = oidc.authenticate(&token).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_symmetric_algorithm() {
+        let (oidc, _) = oidc("roles", Vec::new());
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(KID.to_string());
+        let claims = claims(json!({ "roles": ["admin"] }));
+        let token =
+            jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(b"s")).unwrap();
+        let result = oidc.authenticate(&token).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(msg)) if msg.contains("HS256")));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_none_algorithm() {
+        let (oidc, _) = oidc("roles", Vec::new());
+        let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"none","kid":"k1"}"#);
+        let claims = claims(json!({ "roles": ["admin"] }));
+        let body = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let token = format!("{}.{}.", header, body);
+        let result = oidc.authenticate(&token).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_unknown_key() {
+        let (oidc, key) = oidc("roles", Vec::new());
+        let token = sign(&key, "k2", &claims(json!({ "roles": ["admin"] })));
+        let result = oidc.authenticate(&token).await;
+        assert!(
+            matches!(result, Err(AppError::Unauthorized(msg)) if msg.contains("Unknown signing key"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_wrong_issuer_or_audience() {
+        let (oidc, key) = oidc("roles", Vec::new());
+        for extra in [
+            json!({ "roles": ["admin"], "iss": "https://evil.example.com" }),
+            json!({ "roles": ["admin"], "aud": "other" }),
+        ] {
+            let token = sign(&key, KID, &claims(extra));
+            let result = oidc.authenticate(&token).await;
+            assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_without_issuer() {
+        assert!(check(None, &HeaderMap::new(), Role::Admin).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let (oidc, key) = oidc("roles", Vec::new());
+        let token = sign(&key, KID, &claims(json!({ "roles": ["operator"] })));
+
+        let result = check(Some(&oidc), &HeaderMap::new(), Role::Viewer).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        assert!(check(Some(&oidc), &bearer(&token), Role::Operator)
+            .await
+            .is_ok());
+        let result = check(Some(&oidc), &bearer(&token), Role::Admin).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+}
@@ -0,0 +1,257 @@
+//! Short-lived caching of hot reads.
+//!
+//! Dashboards refreshing together ask for the same accounts and the same
+//! page of recent transfers over and over. Account lookups and transfer
+//! listings are cached for a short TTL, and concurrent misses for the same
+//! key share one query, so a refresh storm costs the cluster one request
+//! per key and TTL.
+//!
+//! Writes made through tb-web invalidate what they may change: creating
+//! accounts drops those accounts, and creating, posting or voiding transfers
+//! drops every cached account and listing, since posting or voiding changes
+//! accounts the request does not name. Writes by other clients show up when
+//! the TTL expires.
+//!
+//! The first pages of some lists are also kept in memory all along (see
+//! [`crate::warm`]).
+
+use crate::error::AppError;
+use crate::transport::ClientPool;
+use moka::future::Cache as MokaCache;
+use std::sync::Arc;
+use std::time::Duration;
+use tb_rs::{Account, ClientError, QueryFilter, Transfer};
+
+/// Most entries per cache.
+const CAPACITY: u64 = 10_000;
+
+/// A transfer listing, by the query filter fields the handlers set.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct TransfersKey {
+    ledger: u32,
+    code: u16,
+    timestamp_min: u64,
+    timestamp_max: u64,
+    limit: u32,
+    flags: u32,
+}
+
+impl From<&QueryFilter> for TransfersKey {
+    fn from(f: &QueryFilter) -> Self {
+        Self {
+            ledger: f.ledger,
+            code: f.code,
+            timestamp_min: f.timestamp_min,
+            timestamp_max: f.timestamp_max,
+            limit: f.limit,
+            flags: f.flags.bits(),
+        }
+    }
+}
+
+/// Caches for account lookups and transfer listings; a TTL of zero
+/// disables one.
+pub struct Cache {
+    accounts: Option<MokaCache<u128, Option<Account>>>,
+    transfers: Option<MokaCache<TransfersKey, Arc<Vec<Transfer>>>>,
+}
+
+impl Cache {
+    pub fn new(accounts_ttl: Duration, transfers_ttl: Duration) -> Self {
+        Self {
+            accounts: (!accounts_ttl.is_zero()).then(|| build(accounts_ttl)),
+            transfers: (!transfers_ttl.is_zero()).then(|| build(transfers_ttl)),
+        }
+    }
+
+    /// Look up account `id`.
+    pub async fn account(&self, pool: &ClientPool, id: u128) -> Result<Option<Account>, AppError> {
+        let lookup = async {
+            let accounts = pool.get().lookup_accounts(&[id]).await?;
+            Ok::<_, ClientError>(accounts.first().copied())
+        };
+        match &self.accounts {
+            Some(cache) => Ok(cache.try_get_with(id, lookup).await?),
+            None => Ok(lookup.await?),
+        }
+    }
+
+    /// Query transfers matching `filter`. User data fields are not part of
+    /// the key, so they must be zero.
+    pub async fn transfers(
+        &self,
+        pool: &ClientPool,
+        filter: QueryFilter,
+    ) -> Result<Arc<Vec<Transfer>>, AppError> {
+        debug_assert!(filter.user_data_128 == 0 && filter.user_data_64 == 0);
+        debug_assert!(filter.user_data_32 == 0);
+        let query =
+            async { Ok::<_, ClientError>(Arc::new(pool.get().query_transfers(filter).await?)) };
+        match &self.transfers {
+            Some(cache) => Ok(cache
+                .try_get_with(TransfersKey::from(&filter), query)
+                .await?),
+            None => Ok(query.await?),
+        }
+    }
+
+    /// Call `f` with each account and transfer ID the caches hold, and
+    /// whether it is an account's. An ID may come more than once.
+    pub fn for_each_id(&self, mut f: impl FnMut(u128, bool)) {
+        if let Some(cache) = &self.accounts {
+            for (id, account) in cache.iter() {
+                if account.is_some() {
+                    f(*id, true);
+                }
+            }
+        }
+        if let Some(cache) = &self.transfers {
+            for (_, transfers) in cache.iter() {
+                for transfer in transfers.iter() {
+                    f(transfer.id, false);
+                    f(transfer.debit_account_id, true);
+                    f(transfer.credit_account_id, true);
+                }
+            }
+        }
+    }
+
+    /// Forget accounts that were just created.
+    pub async fn accounts_created(&self, ids: &[u128]) {
+        if let Some(cache) = &self.accounts {
+            for id in ids {
+                cache.invalidate(id).await;
+            }
+        }
+    }
+
+    /// Forget everything transfers may have changed.
+    pub fn transfers_created(&self) {
+        if let Some(cache) = &self.accounts {
+            cache.invalidate_all();
+        }
+        if let Some(cache) = &self.transfers {
+            cache.invalidate_all();
+        }
+    }
+}
+
+fn build<K, V>(ttl: Duration) -> MokaCache<K, V>
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    MokaCache::builder()
+        .max_capacity(CAPACITY)
+        .time_to_live(ttl)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{account, transfer, FakeCluster};
+    use tb_rs::QueryFilterFlags;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn filter(ledger: u32) -> QueryFilter {
+        QueryFilter {
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            ledger,
+            code: 0,
+            timestamp_min: 0,
+            timestamp_max: 0,
+            limit: 10,
+            flags: QueryFilterFlags::REVERSED,
+            reserved: [0; 6],
+        }
+    }
+
+    fn ids(transfers: &[Transfer]) -> Vec<u128> {
+        transfers.iter().map(|t| t.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_accounts_expire() {
+        let cluster = FakeCluster::new();
+        let pool = ClientPool::fake(&cluster, 1);
+        let cache = Cache::new(Duration::from_millis(50), TTL);
+
+        // The miss is cached, so the account created behind the cache's
+        // back shows up only once it expires.
+        assert!(cache.account(&pool, 1).await.unwrap().is_none());
+        cluster.seed(&[account(1, 1)], &[]);
+        assert!(cache.account(&pool, 1).await.unwrap().is_none());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(cache.account(&pool, 1).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_transfers_expire() {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1), account(2, 1)], &[]);
+        let pool = ClientPool::fake(&cluster, 1);
+        let cache = Cache::new(TTL, Duration::from_millis(50));
+
+        assert!(cache.transfers(&pool, filter(1)).await.unwrap().is_empty());
+        cluster.seed(&[], &[transfer(10, 1, 2, 5, 1)]);
+        assert!(cache.transfers(&pool, filter(1)).await.unwrap().is_empty());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let transfers = cache.transfers(&pool, filter(1)).await.unwrap();
+        assert_eq!(ids(&transfers), [10]);
+    }
+
+    #[tokio::test]
+    async fn test_accounts_created() {
+        let cluster = FakeCluster::new();
+        let pool = ClientPool::fake(&cluster, 1);
+        let cache = Cache::new(TTL, TTL);
+
+        assert!(cache.account(&pool, 1).await.unwrap().is_none());
+        assert!(cache.account(&pool, 2).await.unwrap().is_none());
+        cluster.seed(&[account(1, 1), account(2, 1)], &[]);
+
+        // Only the accounts named are forgotten.
+        cache.accounts_created(&[1]).await;
+        assert!(cache.account(&pool, 1).await.unwrap().is_some());
+        assert!(cache.account(&pool, 2).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_transfers_created() {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1), account(2, 1), account(3, 2)], &[]);
+        let pool = ClientPool::fake(&cluster, 1);
+        let cache = Cache::new(TTL, TTL);
+
+        let before = cache.account(&pool, 1).await.unwrap().unwrap();
+        assert!(cache.transfers(&pool, filter(1)).await.unwrap().is_empty());
+        assert!(cache.transfers(&pool, filter(2)).await.unwrap().is_empty());
+        cluster.seed(&[], &[transfer(10, 1, 2, 5, 1)]);
+        assert_eq!(cache.account(&pool, 1).await.unwrap(), Some(before));
+
+        // Every account and listing goes, named by the transfers or not.
+        cache.transfers_created();
+        let after = cache.account(&pool, 1).await.unwrap().unwrap();
+        assert_eq!(after.debits_posted, 5);
+        let transfers = cache.transfers(&pool, filter(1)).await.unwrap();
+        assert_eq!(ids(&transfers), [10]);
+        assert!(cache.transfers(&pool, filter(2)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_disables() {
+        let cluster = FakeCluster::new();
+        let pool = ClientPool::fake(&cluster, 1);
+        let cache = Cache::new(Duration::ZERO, Duration::ZERO);
+
+        assert!(cache.account(&pool, 1).await.unwrap().is_none());
+        assert!(cache.transfers(&pool, filter(1)).await.unwrap().is_empty());
+        cluster.seed(&[account(1, 1), account(2, 1)], &[transfer(10, 1, 2, 5, 1)]);
+        assert!(cache.account(&pool, 1).await.unwrap().is_some());
+        assert_eq!(ids(&cache.transfers(&pool, filter(1)).await.unwrap()), [10]);
+    }
+}
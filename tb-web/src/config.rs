@@ -1,14 +1,366 @@
 //! Configuration for tb-web.
+//!
+//! Settings come from, in increasing precedence: defaults, a TOML file
+//! named by `--config` (or `TB_WEB_CONFIG`), `TB_WEB_*` environment
+//! variables, and command-line flags. The file uses the flag names as keys
+//! (`tb_address` or `tb-address` for `--tb-address`); tables only group
+//! keys and may be named anything:
+//!
+//! ```toml
+//! address = "0.0.0.0:8080"
+//! read_only = true
+//! tls_cert = "/etc/tb-web/cert.pem"
+//! tls_key = "/etc/tb-web/key.pem"
+//! aliases_db = "/var/lib/tb-web/aliases.db"
+//! queries_db = "/var/lib/tb-web/queries.db"
+//!
+//! [cluster]
+//! tb_address = "10.0.0.1:3000,10.0.0.2:3000,10.0.0.3:3000"
+//! cluster_id = 0
+//! clients = 8
+//!
+//! [auth]
+//! oidc_issuer = "https://sso.example.com/realms/finance"
+//! oidc_audience = "tb-web"
+//! oidc_roles_claim = "realm_access.roles"
+//! oidc_role = ["ledger-readers=viewer", "ledger-admins=admin"]
+//!
+//! [webhooks]
+//! webhook = ["https://hooks.example.com/tb;ledger=1"]
+//! webhook_secret = "change-me"
+//!
+//! [display]
+//! ledger_currency = ["1=USD:2", "2=EUR:2", "3=BTC:8"]
+//! display_timezone = "Europe/Amsterdam"
+//! relative_timestamps = true
+//!
+//! [cache]
+//! accounts_cache_ttl_ms = 500
+//! warm_ledger = [0, 1]
+//!
+//! [limits]
+//! max_body_bytes = 1048576
+//! import_max_bytes = 268435456
+//! max_batch_events = 1000
+//!
+//! [compression]
+//! compression_level = "best"
+//! compression_min_size = 1024
+//! ```
+//!
+//! The environment variable for a flag is its name in upper snake case
+//! after `TB_WEB_`, e.g. `TB_WEB_TB_ADDRESS`. `true` sets a switch, and
+//! arrays repeat a flag.
 
+use crate::auth::OidcConfig;
+use crate::currency::Currencies;
+use crate::timestamps::Timestamps;
+use crate::tls::TlsConfig;
+use crate::webhooks::WebhookConfig;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::{Table, Value};
 
 /// Application configuration.
 #[derive(Debug, Clone)]
 pub struct Config {
     /// Address to bind the web server.
     pub address: SocketAddr,
-    /// TigerBeetle cluster address.
-    pub tb_address: SocketAddr,
+    /// Certificate to serve HTTPS with; plain HTTP when `None`.
+    pub tls: Option<TlsConfig>,
+    /// TigerBeetle replica addresses, comma-separated.
+    pub tb_address: String,
+    /// Replicas in `tb_address`.
+    pub replica_count: usize,
     /// TigerBeetle cluster ID.
     pub cluster_id: u128,
+    /// TigerBeetle clients in the pool.
+    pub clients: u32,
+    /// Longest time since the last successful TigerBeetle operation for
+    /// `/readyz` to report ready.
+    pub ready_max_op_age: Duration,
+    /// Longest a request may take (zero disables).
+    pub request_timeout: Duration,
+    /// How long account lookups are cached (zero disables).
+    pub accounts_cache_ttl: Duration,
+    /// How long transfer listings are cached (zero disables).
+    pub transfers_cache_ttl: Duration,
+    /// How often dashboard statistics are refreshed (zero disables).
+    pub stats_refresh: Duration,
+    /// Ledgers whose lists are kept warm (0 for the unfiltered ones).
+    pub warm_ledgers: Vec<u32>,
+    /// Transfers kept warm per ledger.
+    pub warm_transfers: u32,
+    /// Accounts kept warm per ledger.
+    pub warm_accounts: u32,
+    /// Bearer token authentication, if enabled.
+    pub oidc: Option<OidcConfig>,
+    /// How amounts on each ledger are shown.
+    pub currencies: Currencies,
+    /// How timestamps are shown.
+    pub timestamps: Timestamps,
+    /// Endpoints to send new transfers to.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Key to sign webhook requests with.
+    pub webhook_secret: String,
+    /// Most transfers one graph request may aggregate.
+    pub graph_transfers_max: u32,
+    /// Most transfers one pending transfer listing may scan.
+    pub pending_scan_max: u32,
+    /// Most points one balance chart request may ask for.
+    pub chart_points_max: u32,
+    /// Most rows one import may hold.
+    pub import_rows_max: u64,
+    /// Most rows whose results an import report lists.
+    pub import_report_rows_max: u64,
+    /// Largest import upload accepted.
+    pub import_max_bytes: usize,
+    /// Largest request body accepted, imports aside.
+    pub max_body_bytes: usize,
+    /// Most events one create request may carry, if fewer than the
+    /// cluster's batch size limit allows.
+    pub max_batch_events: Option<u32>,
+    /// SQLite database of account aliases, if any.
+    pub aliases_db: Option<PathBuf>,
+    /// SQLite database of saved queries, if any.
+    pub queries_db: Option<PathBuf>,
+    /// Serve no endpoints that create accounts or transfers.
+    pub read_only: bool,
+    /// Serve no live update WebSocket or balance long-polls.
+    pub disable_live: bool,
+    /// Serve no NDJSON exports.
+    pub disable_export: bool,
+    /// Serve the endpoint that generates demo data.
+    pub enable_seeding: bool,
+}
+
+/// Prefix of the environment variables setting flags.
+const ENV_PREFIX: &str = "TB_WEB_";
+
+/// Expand the config file named in `argv` (or by `TB_WEB_CONFIG`) into the
+/// flags it sets, placed before the command-line flags so those take
+/// precedence. Keys with an environment variable set are left out, so the
+/// variable takes precedence over the file.
+pub fn expand(argv: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let Some(path) = config_path(&argv).or_else(|| env::var_os("TB_WEB_CONFIG")) else {
+        return Ok(argv);
+    };
+    let path = Path::new(&path);
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let flags = parse(&text, |name| env::var_os(env_var(name)).is_some())
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut expanded = Vec::with_capacity(argv.len() + flags.len());
+    let mut argv = argv.into_iter();
+    expanded.extend(argv.next());
+    expanded.extend(flags.into_iter().map(OsString::from));
+    expanded.extend(argv);
+    Ok(expanded)
+}
+
+/// The environment variable for flag `name` (dashed).
+fn env_var(name: &str) -> String {
+    format!("{}{}", ENV_PREFIX, name.replace('-', "_").to_uppercase())
+}
+
+/// The value of the last `--config` in `argv`, if any.
+fn config_path(argv: &[OsString]) -> Option<OsString> {
+    let mut path = None;
+    let mut args = argv.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            path = args.next().cloned();
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            path = Some(value.into());
+        }
+    }
+    path
+}
+
+/// Turn a config file into command-line flags, leaving out those for which
+/// `overridden` holds.
+fn parse(text: &str, overridden: impl Fn(&str) -> bool) -> Result<Vec<String>, String> {
+    let table: Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+    let mut flags = Vec::new();
+    let mut seen = Vec::new();
+    add_table(&table, &overridden, &mut flags, &mut seen)?;
+    Ok(flags)
+}
+
+fn add_table(
+    table: &Table,
+    overridden: &impl Fn(&str) -> bool,
+    flags: &mut Vec<String>,
+    seen: &mut Vec<String>,
+) -> Result<(), String> {
+    for (key, value) in table {
+        if let Value::Table(group) = value {
+            add_table(group, overridden, flags, seen)?;
+            continue;
+        }
+
+        let name = key.replace('_', "-");
+        if name == "config" {
+            return Err("a config file can't include another".to_string());
+        }
+        if seen.contains(&name) {
+            return Err(format!("'{}' is set twice", key));
+        }
+        seen.push(name.clone());
+        if overridden(&name) {
+            continue;
+        }
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Integer(n) => n.to_string(),
+                Value::Boolean(true) => {
+                    flags.push(format!("--{}", name));
+                    continue;
+                }
+                Value::Boolean(false) => continue,
+                _ => return Err(format!("'{}' has an unsupported value", key)),
+            };
+            flags.push(format!("--{}", name));
+            flags.push(value);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    fn flags(text: &str) -> Result<Vec<String>, String> {
+        parse(text, |_| false)
+    }
+
+    #[test]
+    fn test_env_var() {
+        assert_eq!(env_var("tb-address"), "TB_WEB_TB_ADDRESS");
+        assert_eq!(env_var("read-only"), "TB_WEB_READ_ONLY");
+    }
+
+    #[test]
+    fn test_config_path() {
+        assert_eq!(config_path(&argv(&["tb-web"])), None);
+        assert_eq!(
+            config_path(&argv(&["tb-web", "--config", "a.toml"])),
+            Some("a.toml".into())
+        );
+        assert_eq!(
+            config_path(&argv(&["tb-web", "--config=a.toml", "--config", "b.toml"])),
+            Some("b.toml".into())
+        );
+        // The program name and anything after `--` are not flags.
+        assert_eq!(config_path(&argv(&["--config=a.toml"])), None);
+        assert_eq!(
+            config_path(&argv(&["tb-web", "--", "--config=a.toml"])),
+            None
+        );
+        // A trailing `--config` has no value.
+        assert_eq!(config_path(&argv(&["tb-web", "--config"])), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+            address = "0.0.0.0:8080"
+            cluster_id = 0
+
+            [display]
+            ledger_currency = ["1=USD:2", "2=EUR:2"]
+
+            [cache.warm]
+            warm-ledger = [0, 1]
+        "#;
+        assert_eq!(
+            flags(text).unwrap(),
+            [
+                "--address",
+                "0.0.0.0:8080",
+                "--warm-ledger",
+                "0",
+                "--warm-ledger",
+                "1",
+                "--cluster-id",
+                "0",
+                "--ledger-currency",
+                "1=USD:2",
+                "--ledger-currency",
+                "2=EUR:2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_booleans() {
+        assert_eq!(flags("read_only = true").unwrap(), ["--read-only"]);
+        assert!(flags("read_only = false").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_overridden() {
+        let text = "tb_address = \"3000\"\n[cluster]\nclients = 8\n";
+        let flags = parse(text, |name| name == "tb-address").unwrap();
+        assert_eq!(flags, ["--clients", "8"]);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for (text, error) in [
+            ("config = \"other.toml\"", "can't include another"),
+            ("[nested]\nconfig = \"other.toml\"", "can't include another"),
+            ("read_only = true\n[a]\nread-only = true", "set twice"),
+            ("ratio = 0.5", "unsupported value"),
+            ("ledger = [[1]]", "unsupported value"),
+            ("address = ", ""),
+        ] {
+            let result = flags(text);
+            assert!(
+                matches!(&result, Err(e) if e.contains(error)),
+                "{:?}: {:?}",
+                text,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let path = env::temp_dir().join(format!("tb-web-config-{}.toml", std::process::id()));
+        fs::write(&path, "[cluster]\nclients = 8\n").unwrap();
+        let config = format!("--config={}", path.display());
+        let expanded = expand(argv(&["tb-web", &config, "--clients", "4"]));
+        fs::remove_file(&path).unwrap();
+        // File flags come before the command line's, which take precedence.
+        assert_eq!(
+            expanded.unwrap(),
+            argv(&["tb-web", "--clients", "8", &config, "--clients", "4"])
+        );
+
+        let missing = argv(&["tb-web", "--config", "/nonexistent/tb-web.toml"]);
+        assert!(expand(missing).is_err());
+    }
 }
@@ -0,0 +1,270 @@
+//! Per-ledger currency display.
+//!
+//! TigerBeetle amounts are integers in a ledger's smallest unit. With
+//! `--ledger-currency 1=USD:2`, amounts on ledger 1 are also shown as
+//! dollars with two decimal places: 1234 as `$12.34`. API responses keep
+//! the raw integers and add the formatted amounts in an optional `display`
+//! field; the UI shows the formatted ones.
+
+use std::collections::HashMap;
+
+/// How amounts on a ledger are shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Currency {
+    /// Currency code, e.g. `USD`.
+    pub code: String,
+    /// Decimal places in an amount: 2 for cents.
+    pub scale: u8,
+}
+
+/// Currencies by ledger.
+#[derive(Debug, Clone, Default)]
+pub struct Currencies(HashMap<u32, Currency>);
+
+/// Most decimal places a currency may have; u128 has 39 digits.
+const SCALE_MAX: u8 = 38;
+
+/// Parse a `LEDGER=CODE[:SCALE]` mapping; the scale defaults to 2.
+pub fn parse_mapping(s: &str) -> Result<(u32, Currency), String> {
+    let (ledger, currency) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected LEDGER=CODE[:SCALE], got '{}'", s))?;
+    let ledger = ledger
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid ledger '{}'", ledger))?;
+    let (code, scale) = match currency.split_once(':') {
+        Some((code, scale)) => {
+            let scale = scale
+                .trim()
+                .parse()
+                .ok()
+                .filter(|&scale| scale <= SCALE_MAX)
+                .ok_or_else(|| format!("Invalid scale '{}' (0 to {})", scale, SCALE_MAX))?;
+            (code.trim(), scale)
+        }
+        None => (currency.trim(), 2),
+    };
+    if code.is_empty() {
+        return Err(format!("Missing currency code in '{}'", s));
+    }
+    Ok((
+        ledger,
+        Currency {
+            code: code.to_string(),
+            scale,
+        },
+    ))
+}
+
+impl Currencies {
+    pub fn new(mappings: impl IntoIterator<Item = (u32, Currency)>) -> Self {
+        Self(mappings.into_iter().collect())
+    }
+
+    /// The currency of `ledger`, if it has one.
+    pub fn get(&self, ledger: u32) -> Option<&Currency> {
+        self.0.get(&ledger)
+    }
+
+    /// Every ledger with a currency, in order.
+    pub fn ledgers(&self) -> Vec<(u32, &Currency)> {
+        let mut ledgers: Vec<_> = self.0.iter().map(|(&ledger, c)| (ledger, c)).collect();
+        ledgers.sort_unstable_by_key(|&(ledger, _)| ledger);
+        ledgers
+    }
+}
+
+impl Currency {
+    /// Format decimal `amount` (optionally negative), e.g. `-1234` as
+    /// `-$12.34`.
+    pub fn format(&self, amount: &str) -> String {
+        let (sign, digits) = match amount.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", amount),
+        };
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (units, fraction) = digits.split_at(digits.len() - scale);
+
+        let mut number = group_thousands(units);
+        if scale > 0 {
+            number.push('.');
+            number.push_str(fraction);
+        }
+        match symbol(&self.code) {
+            Some(symbol) => format!("{}{}{}", sign, symbol, number),
+            None => format!("{}{} {}", sign, number, self.code),
+        }
+    }
+
+    /// Parse an amount written in this currency, e.g. `12.34` (or `1,234`,
+    /// or as [`Currency::format`] writes it) as 1234 cents, into the
+    /// ledger's smallest unit.
+    pub fn parse(&self, amount: &str) -> Result<u128, String> {
+        let invalid = || format!("invalid {} amount {:?}", self.code, amount);
+        let number = amount.trim();
+        let number = symbol(&self.code)
+            .and_then(|symbol| number.strip_prefix(symbol))
+            .or_else(|| number.strip_suffix(self.code.as_str()))
+            .unwrap_or(number)
+            .trim();
+        let digits = number.replace(',', "");
+        let (units, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+        if units.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if !is_digits(units) || !is_digits(fraction) {
+            return Err(invalid());
+        }
+        let scale = self.scale as usize;
+        if fraction.len() > scale {
+            return Err(format!(
+                "{} has {} decimal places, {:?} has more",
+                self.code, scale, amount
+            ));
+        }
+        format!("{}{:0<scale$}", units, fraction, scale = scale)
+            .parse()
+            .map_err(|_| invalid())
+    }
+}
+
+/// The symbol written before amounts in `code`, for common currencies.
+fn symbol(code: &str) -> Option<&'static str> {
+    match code {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "JPY" | "CNY" => Some("¥"),
+        "INR" => Some("₹"),
+        _ => None,
+    }
+}
+
+/// `digits` with a comma between each group of three.
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currency(code: &str, scale: u8) -> Currency {
+        Currency {
+            code: code.to_string(),
+            scale,
+        }
+    }
+
+    #[test]
+    fn test_parse_mapping() {
+        assert_eq!(parse_mapping("1=USD"), Ok((1, currency("USD", 2))));
+        assert_eq!(parse_mapping(" 2 = JPY:0"), Ok((2, currency("JPY", 0))));
+        assert_eq!(parse_mapping("3=XBT:38"), Ok((3, currency("XBT", 38))));
+        assert!(parse_mapping("USD").is_err());
+        assert!(parse_mapping("x=USD").is_err());
+        assert!(parse_mapping("1=USD:39").is_err());
+        assert!(parse_mapping("1=USD:-1").is_err());
+        assert!(parse_mapping("1=:2").is_err());
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        for (digits, grouped) in [
+            ("", ""),
+            ("0", "0"),
+            ("12", "12"),
+            ("123", "123"),
+            ("1234", "1,234"),
+            ("123456", "123,456"),
+            ("1234567", "1,234,567"),
+        ] {
+            assert_eq!(group_thousands(digits), grouped);
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let usd = currency("USD", 2);
+        assert_eq!(usd.format("1234"), "$12.34");
+        assert_eq!(usd.format("-1234"), "-$12.34");
+        assert_eq!(usd.format("123456789"), "$1,234,567.89");
+        // Below one unit, padded with zeros.
+        assert_eq!(usd.format("0"), "$0.00");
+        assert_eq!(usd.format("5"), "$0.05");
+        assert_eq!(usd.format("-50"), "-$0.50");
+
+        let units = currency("XYZ", 0);
+        assert_eq!(units.format("0"), "0 XYZ");
+        assert_eq!(units.format("1234567"), "1,234,567 XYZ");
+
+        let max = currency("XBT", 38);
+        assert_eq!(
+            max.format(&u128::MAX.to_string()),
+            "3.40282366920938463463374607431768211455 XBT"
+        );
+        assert_eq!(
+            max.format("1"),
+            "0.00000000000000000000000000000000000001 XBT"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let usd = currency("USD", 2);
+        assert_eq!(usd.parse("12.34"), Ok(1234));
+        assert_eq!(usd.parse(" 1,234 "), Ok(123400));
+        assert_eq!(usd.parse("12.3"), Ok(1230));
+        assert_eq!(usd.parse(".05"), Ok(5));
+        assert_eq!(usd.parse("7."), Ok(700));
+        assert_eq!(usd.parse("$12.34"), Ok(1234));
+        assert_eq!(usd.parse("12.34 USD"), Ok(1234));
+        assert_eq!(currency("XYZ", 0).parse("1,234"), Ok(1234));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let usd = currency("USD", 2);
+        for amount in ["", ".", "-1", "1e3", "12.3.4", "12,34x", "€12", "0x10"] {
+            assert!(usd.parse(amount).is_err(), "{:?}", amount);
+        }
+        // Too many decimals.
+        let error = usd.parse("12.345").unwrap_err();
+        assert!(error.contains("2 decimal places"), "{}", error);
+        assert!(currency("XYZ", 0).parse("1.0").is_err());
+
+        // Overflow.
+        let max = currency("XBT", 38);
+        assert_eq!(
+            max.parse("3.40282366920938463463374607431768211455"),
+            Ok(u128::MAX)
+        );
+        assert!(max
+            .parse("3.40282366920938463463374607431768211456")
+            .is_err());
+        assert!(currency("XYZ", 0)
+            .parse("340282366920938463463374607431768211456")
+            .is_err());
+    }
+
+    #[test]
+    fn test_format_parse_round_trip() {
+        for (code, scale) in [("USD", 2), ("XYZ", 0), ("JPY", 0), ("XBT", 38)] {
+            let currency = currency(code, scale);
+            for amount in [0, 1, 99, 100, 1234, 1_000_000, u64::MAX as u128, u128::MAX] {
+                let formatted = currency.format(&amount.to_string());
+                assert_eq!(currency.parse(&formatted), Ok(amount), "{}", formatted);
+            }
+        }
+    }
+}
@@ -1,14 +1,27 @@
 //! Error types for the web API.
+//!
+//! Every error response carries a JSON body with a human-readable `error`
+//! and a stable `code` for clients to match on. TigerBeetle being briefly
+//! out of reach (evicted, not registered, reconnecting) is a 503 with
+//! `Retry-After`, since the same request will likely succeed shortly.
 
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Serialize;
+use std::sync::Arc;
+use tb_rs::{ClientError, ProtocolError};
+
+/// Seconds a client should wait before retrying while TigerBeetle is out of
+/// reach.
+const RETRY_AFTER_SECS: u64 = 1;
 
 /// API error response.
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
+    /// Stable, machine-readable error code, e.g. `not_found`.
+    pub code: &'static str,
 }
 
 /// Application error type.
@@ -18,36 +31,385 @@ pub enum AppError {
     NotFound(String),
     /// Bad request (invalid parameters).
     BadRequest(String),
+    /// Well-formed request whose content fails validation.
+    Unprocessable(String),
+    /// Missing or invalid bearer token.
+    Unauthorized(String),
+    /// Valid token without the role the route requires.
+    Forbidden(String),
+    /// No format the client accepts.
+    NotAcceptable(String),
+    /// Request body or batch larger than allowed.
+    PayloadTooLarge(String),
+    /// The request took longer than `--request-timeout-secs`.
+    Timeout,
     /// TigerBeetle client error.
-    Client(tb_rs::ClientError),
+    Client(ClientError),
     /// Internal server error.
     Internal(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+impl AppError {
+    /// The HTTP status and error code of this error.
+    pub fn status(&self) -> (StatusCode, &'static str) {
+        match self {
+            AppError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            AppError::Unprocessable(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_input"),
+            AppError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            AppError::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden"),
+            AppError::NotAcceptable(_) => (StatusCode::NOT_ACCEPTABLE, "not_acceptable"),
+            AppError::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, "request_too_large"),
+            AppError::Timeout => (StatusCode::GATEWAY_TIMEOUT, "request_timeout"),
+            AppError::Client(err) => match err {
+                ClientError::Timeout => (StatusCode::GATEWAY_TIMEOUT, "tb_timeout"),
+                ClientError::Evicted(_) => (StatusCode::SERVICE_UNAVAILABLE, "tb_evicted"),
+                ClientError::NotRegistered => {
+                    (StatusCode::SERVICE_UNAVAILABLE, "tb_not_registered")
+                }
+                ClientError::Connection(_)
+                | ClientError::ConnectTimeout(_)
+                | ClientError::Io(_)
+                | ClientError::Transport(_)
+                | ClientError::Shutdown => (StatusCode::SERVICE_UNAVAILABLE, "tb_unavailable"),
+                ClientError::RequestTooLarge { .. } => {
+                    (StatusCode::PAYLOAD_TOO_LARGE, "request_too_large")
+                }
+                ClientError::Protocol(ProtocolError::ReservedField) => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "tb_reserved_field")
+                }
+                ClientError::Protocol(_) => (StatusCode::BAD_GATEWAY, "tb_protocol_error"),
+                ClientError::InvalidOperation => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "tb_client_error")
+                }
+            },
+            AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        }
+    }
+
+    /// The message to show for this error, logging those that are ours.
+    fn message(self) -> String {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::BadRequest(msg)
+            | AppError::Unprocessable(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotAcceptable(msg)
+            | AppError::PayloadTooLarge(msg) => msg,
+            AppError::Timeout => "Request timed out".to_string(),
+            AppError::Client(ClientError::RequestTooLarge { size, limit }) => format!(
+                "Request too large: {} bytes of events, the cluster accepts {}",
+                size, limit
+            ),
             AppError::Client(err) => {
                 tracing::error!("TigerBeetle client error: {:?}", err);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "TigerBeetle client error".to_string(),
-                )
+                match err {
+                    ClientError::Timeout => "TigerBeetle did not reply in time".to_string(),
+                    ClientError::Evicted(reason) => {
+                        format!("Evicted by TigerBeetle ({:?}), reconnecting", reason)
+                    }
+                    ClientError::NotRegistered => "Not yet registered with TigerBeetle".to_string(),
+                    ClientError::Connection(_)
+                    | ClientError::ConnectTimeout(_)
+                    | ClientError::Io(_)
+                    | ClientError::Transport(_)
+                    | ClientError::Shutdown => "TigerBeetle is unreachable".to_string(),
+                    ClientError::Protocol(ProtocolError::ReservedField) => {
+                        "TigerBeetle rejected a non-zero reserved field".to_string()
+                    }
+                    _ => "TigerBeetle client error".to_string(),
+                }
             }
             AppError::Internal(msg) => {
                 tracing::error!("Internal error: {}", msg);
-                (StatusCode::INTERNAL_SERVER_ERROR, msg)
+                msg
             }
-        };
+        }
+    }
+}
 
-        (status, Json(ErrorResponse { error: message })).into_response()
+impl From<AppError> for ErrorResponse {
+    fn from(error: AppError) -> Self {
+        let (_, code) = error.status();
+        Self {
+            error: error.message(),
+            code,
+        }
     }
 }
 
-impl From<tb_rs::ClientError> for AppError {
-    fn from(err: tb_rs::ClientError) -> Self {
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, _) = self.status();
+        let body = Json(ErrorResponse::from(self));
+        match status {
+            StatusCode::UNAUTHORIZED => (
+                status,
+                [(header::WWW_AUTHENTICATE, "Bearer".to_string())],
+                body,
+            )
+                .into_response(),
+            StatusCode::SERVICE_UNAVAILABLE => (
+                status,
+                [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+                body,
+            )
+                .into_response(),
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+impl From<Arc<ClientError>> for AppError {
+    fn from(err: Arc<ClientError>) -> Self {
+        // Requests that shared a cached query share its error.
+        match Arc::try_unwrap(err) {
+            Ok(err) => AppError::Client(err),
+            Err(err) => AppError::Client(match *err {
+                // Keep the kinds that decide the response status.
+                ClientError::Timeout => ClientError::Timeout,
+                ClientError::Evicted(reason) => ClientError::Evicted(reason),
+                ClientError::NotRegistered => ClientError::NotRegistered,
+                ClientError::Shutdown => ClientError::Shutdown,
+                ClientError::RequestTooLarge { size, limit } => {
+                    ClientError::RequestTooLarge { size, limit }
+                }
+                _ => ClientError::Connection(err.to_string()),
+            }),
+        }
+    }
+}
+
+impl From<ClientError> for AppError {
+    fn from(err: ClientError) -> Self {
         AppError::Client(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tb_rs::protocol::EvictionReason;
+    use tb_rs::{TransportError, TransportErrorKind};
+
+    /// The status, code and message of `error` as a response, and its
+    /// `Retry-After` or `WWW-Authenticate` header.
+    async fn respond(error: AppError) -> (StatusCode, String, String, Option<String>) {
+        let response = error.into_response();
+        let status = response.status();
+        let header = [header::RETRY_AFTER, header::WWW_AUTHENTICATE]
+            .into_iter()
+            .find_map(|name| response.headers().get(name))
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let code = body["code"].as_str().unwrap().to_string();
+        let message = body["error"].as_str().unwrap().to_string();
+        (status, code, message, header)
+    }
+
+    #[tokio::test]
+    async fn test_client_errors() {
+        use StatusCode as S;
+        let unreachable = "TigerBeetle is unreachable";
+        let retry = Some("1");
+        let cases = [
+            (
+                ClientError::Timeout,
+                S::GATEWAY_TIMEOUT,
+                "tb_timeout",
+                "TigerBeetle did not reply in time",
+                None,
+            ),
+            (
+                ClientError::Evicted(EvictionReason::SessionTooLow),
+                S::SERVICE_UNAVAILABLE,
+                "tb_evicted",
+                "Evicted by TigerBeetle (SessionTooLow), reconnecting",
+                retry,
+            ),
+            (
+                ClientError::NotRegistered,
+                S::SERVICE_UNAVAILABLE,
+                "tb_not_registered",
+                "Not yet registered with TigerBeetle",
+                retry,
+            ),
+            (
+                ClientError::Connection("reconnecting to TigerBeetle".into()),
+                S::SERVICE_UNAVAILABLE,
+                "tb_unavailable",
+                unreachable,
+                retry,
+            ),
+            (
+                ClientError::ConnectTimeout("127.0.0.1:3000".parse().unwrap()),
+                S::SERVICE_UNAVAILABLE,
+                "tb_unavailable",
+                unreachable,
+                retry,
+            ),
+            (
+                ClientError::Io(TransportError::new(0, TransportErrorKind::Reset)),
+                S::SERVICE_UNAVAILABLE,
+                "tb_unavailable",
+                unreachable,
+                retry,
+            ),
+            (
+                ClientError::Transport("gone".into()),
+                S::SERVICE_UNAVAILABLE,
+                "tb_unavailable",
+                unreachable,
+                retry,
+            ),
+            (
+                ClientError::Shutdown,
+                S::SERVICE_UNAVAILABLE,
+                "tb_unavailable",
+                unreachable,
+                retry,
+            ),
+            (
+                ClientError::RequestTooLarge {
+                    size: 2048,
+                    limit: 1024,
+                },
+                S::PAYLOAD_TOO_LARGE,
+                "request_too_large",
+                "Request too large: 2048 bytes of events, the cluster accepts 1024",
+                None,
+            ),
+            (
+                ClientError::Protocol(ProtocolError::ReservedField),
+                S::UNPROCESSABLE_ENTITY,
+                "tb_reserved_field",
+                "TigerBeetle rejected a non-zero reserved field",
+                None,
+            ),
+            (
+                ClientError::Protocol(ProtocolError::InvalidBodyChecksum),
+                S::BAD_GATEWAY,
+                "tb_protocol_error",
+                "TigerBeetle client error",
+                None,
+            ),
+            (
+                ClientError::InvalidOperation,
+                S::INTERNAL_SERVER_ERROR,
+                "tb_client_error",
+                "TigerBeetle client error",
+                None,
+            ),
+        ];
+        for (error, status, code, message, header) in cases {
+            let name = format!("{:?}", error);
+            let expected = (
+                status,
+                code.to_string(),
+                message.to_string(),
+                header.map(str::to_string),
+            );
+            assert_eq!(respond(AppError::Client(error)).await, expected, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_app_errors() {
+        use StatusCode as S;
+        let message = || "message".to_string();
+        let cases = [
+            (
+                AppError::NotFound(message()),
+                S::NOT_FOUND,
+                "not_found",
+                None,
+            ),
+            (
+                AppError::BadRequest(message()),
+                S::BAD_REQUEST,
+                "bad_request",
+                None,
+            ),
+            (
+                AppError::Unprocessable(message()),
+                S::UNPROCESSABLE_ENTITY,
+                "invalid_input",
+                None,
+            ),
+            (
+                AppError::Unauthorized(message()),
+                S::UNAUTHORIZED,
+                "unauthorized",
+                Some("Bearer"),
+            ),
+            (
+                AppError::Forbidden(message()),
+                S::FORBIDDEN,
+                "forbidden",
+                None,
+            ),
+            (
+                AppError::NotAcceptable(message()),
+                S::NOT_ACCEPTABLE,
+                "not_acceptable",
+                None,
+            ),
+            (
+                AppError::PayloadTooLarge(message()),
+                S::PAYLOAD_TOO_LARGE,
+                "request_too_large",
+                None,
+            ),
+            (
+                AppError::Internal(message()),
+                S::INTERNAL_SERVER_ERROR,
+                "internal",
+                None,
+            ),
+        ];
+        for (error, status, code, header) in cases {
+            let name = format!("{:?}", error);
+            let expected = (
+                status,
+                code.to_string(),
+                message(),
+                header.map(str::to_string),
+            );
+            assert_eq!(respond(error).await, expected, "{}", name);
+        }
+        let expected = (
+            S::GATEWAY_TIMEOUT,
+            "request_timeout".to_string(),
+            "Request timed out".to_string(),
+            None,
+        );
+        assert_eq!(respond(AppError::Timeout).await, expected);
+    }
+
+    #[test]
+    fn test_shared_client_errors() {
+        // A shared error keeps the kinds that decide the status.
+        let shared = |error: ClientError| {
+            let error = Arc::new(error);
+            let _other = error.clone();
+            AppError::from(error).status()
+        };
+        assert_eq!(shared(ClientError::Timeout).1, "tb_timeout");
+        assert_eq!(shared(ClientError::NotRegistered).1, "tb_not_registered");
+        assert_eq!(
+            shared(ClientError::RequestTooLarge { size: 2, limit: 1 }).1,
+            "request_too_large"
+        );
+        assert_eq!(
+            shared(ClientError::Protocol(ProtocolError::InvalidHeader)).1,
+            "tb_unavailable"
+        );
+        // Unshared, it is kept whole.
+        let error = Arc::new(ClientError::Protocol(ProtocolError::InvalidHeader));
+        assert_eq!(AppError::from(error).status().1, "tb_protocol_error");
+    }
+}
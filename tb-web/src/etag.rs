@@ -0,0 +1,223 @@
+//! Entity tags for conditional lookups.
+//!
+//! An account changes only when a transfer moves its balances or closes
+//! it, so its tag comes from its timestamp, balances and flags rather than
+//! from the rendered body; a transfer never changes, so its ID and
+//! timestamp are enough. A dashboard polling a detail page sends the tag
+//! back in `If-None-Match` and gets an empty 304 until the record changes.
+//!
+//! HTML fragments also show aliases, so their tags cover those too. With
+//! `--relative-timestamps` a fragment ages by itself, so it gets no tag.
+//! Tags are weak, since the compression layer may re-encode the body.
+
+use crate::aliases::Alias;
+use crate::timestamps::Timestamps;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use tb_rs::{Account, Transfer};
+
+/// The tag of one representation of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ETag(u64);
+
+impl ETag {
+    /// The tag of `account` as JSON.
+    pub fn account(account: &Account) -> Self {
+        Self::of((
+            "account",
+            account.id,
+            account.timestamp,
+            account.debits_pending,
+            account.debits_posted,
+            account.credits_pending,
+            account.credits_posted,
+            account.flags.bits(),
+        ))
+    }
+
+    /// The tag of `transfer` as JSON.
+    pub fn transfer(transfer: &Transfer) -> Self {
+        Self::of(("transfer", transfer.id, transfer.timestamp))
+    }
+
+    /// The tag of the HTML fragment of the same record, which shows
+    /// `aliases`; `None` if its timestamps are relative.
+    pub fn fragment(self, aliases: &[Option<Alias>], timestamps: &Timestamps) -> Option<Self> {
+        if timestamps.is_relative() {
+            return None;
+        }
+        let aliases: Vec<_> = aliases
+            .iter()
+            .map(|alias| alias.as_ref().map(|a| (&a.name, &a.labels)))
+            .collect();
+        Some(Self::of(("html", self.0, aliases)))
+    }
+
+    fn of(value: impl Hash) -> Self {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+
+    /// Whether `If-None-Match` in `headers` lists this tag.
+    fn matches(self, headers: &HeaderMap) -> bool {
+        let opaque = format!("\"{:016x}\"", self.0);
+        headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == opaque)
+    }
+
+    fn header(self) -> HeaderValue {
+        HeaderValue::from_str(&format!("W/\"{:016x}\"", self.0)).expect("a tag is ASCII")
+    }
+}
+
+/// Respond to a lookup with the response from `render` tagged `etag`, or
+/// with 304 if the client has that already. Without a tag, always render.
+pub fn respond(
+    headers: &HeaderMap,
+    etag: Option<ETag>,
+    render: impl FnOnce() -> Response,
+) -> Response {
+    let mut response = match etag {
+        Some(etag) if etag.matches(headers) => StatusCode::NOT_MODIFIED.into_response(),
+        _ => render(),
+    };
+    let response_headers = response.headers_mut();
+    // The same URL serves HTML to HTMX and JSON to everyone else.
+    response_headers.insert(header::VARY, HeaderValue::from_static("HX-Request"));
+    if let Some(etag) = etag {
+        response_headers.insert(header::ETAG, etag.header());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{account, transfer};
+    use chrono_tz::Tz;
+    use tb_rs::AccountFlags;
+
+    fn alias(name: &str, labels: &[&str]) -> Option<Alias> {
+        Some(Alias {
+            account_id: 1,
+            name: name.to_string(),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+        })
+    }
+
+    fn if_none_match(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_account() {
+        let tag = ETag::account(&account(1, 1));
+        assert_eq!(tag, ETag::account(&account(1, 1)));
+        assert_ne!(tag, ETag::account(&account(2, 1)));
+
+        // What a transfer or closing changes gives a new tag.
+        let changes: [fn(&mut Account); 6] = [
+            |a| a.timestamp += 1,
+            |a| a.debits_pending += 1,
+            |a| a.debits_posted += 1,
+            |a| a.credits_pending += 1,
+            |a| a.credits_posted += 1,
+            |a| a.flags |= AccountFlags::CLOSED,
+        ];
+        for change in changes {
+            let mut changed = account(1, 1);
+            change(&mut changed);
+            assert_ne!(ETag::account(&changed), tag);
+        }
+    }
+
+    #[test]
+    fn test_transfer() {
+        let tag = ETag::transfer(&transfer(10, 1, 2, 5, 1));
+        assert_eq!(tag, ETag::transfer(&transfer(10, 1, 2, 5, 1)));
+        assert_ne!(tag, ETag::transfer(&transfer(11, 1, 2, 5, 1)));
+        let mut later = transfer(10, 1, 2, 5, 1);
+        later.timestamp += 1;
+        assert_ne!(ETag::transfer(&later), tag);
+        // An account and a transfer with the same ID differ.
+        assert_ne!(ETag::account(&account(10, 1)), tag);
+    }
+
+    #[test]
+    fn test_fragment() {
+        let absolute = Timestamps::new(Tz::UTC, false);
+        let tag = ETag::account(&account(1, 1));
+        let fragment = tag.fragment(&[alias("alice", &["eu"])], &absolute).unwrap();
+        assert_ne!(fragment, tag);
+        assert_eq!(
+            tag.fragment(&[alias("alice", &["eu"])], &absolute),
+            Some(fragment)
+        );
+
+        // A new alias or label shows in the HTML, so changes the tag.
+        for aliases in [
+            vec![None],
+            vec![alias("bob", &["eu"])],
+            vec![alias("alice", &["eu", "vip"])],
+            vec![alias("alice", &["eu"]), None],
+        ] {
+            assert_ne!(tag.fragment(&aliases, &absolute), Some(fragment));
+        }
+
+        let relative = Timestamps::new(Tz::UTC, true);
+        assert_eq!(tag.fragment(&[None], &relative), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        let tag = ETag(0xabc);
+        let opaque = "\"0000000000000abc\"";
+        let weak = "W/\"0000000000000abc\"";
+        assert!(tag.matches(&if_none_match(&[opaque])));
+        assert!(tag.matches(&if_none_match(&[weak])));
+        assert!(tag.matches(&if_none_match(&["*"])));
+        assert!(tag.matches(&if_none_match(&[&format!("\"1\", {}", weak)])));
+        assert!(tag.matches(&if_none_match(&["\"1\"", opaque])));
+
+        assert!(!tag.matches(&HeaderMap::new()));
+        assert!(!tag.matches(&if_none_match(&["\"0000000000000abd\""])));
+        assert!(!tag.matches(&if_none_match(&["0000000000000abc"])));
+        assert_eq!(tag.header(), weak);
+    }
+
+    #[test]
+    fn test_respond() {
+        let tag = ETag(0xabc);
+        let render = || StatusCode::OK.into_response();
+
+        let response = respond(&HeaderMap::new(), Some(tag), render);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "W/\"0000000000000abc\"");
+        assert_eq!(response.headers()[header::VARY], "HX-Request");
+
+        // The client has it: nothing is rendered.
+        let headers = if_none_match(&[tag.header().to_str().unwrap()]);
+        let response = respond(&headers, Some(tag), || unreachable!("rendered"));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], tag.header());
+        assert_eq!(response.headers()[header::VARY], "HX-Request");
+
+        // Without a tag, it always renders, and says nothing of tags.
+        let response = respond(&headers, None, render);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::ETAG).is_none());
+        assert_eq!(response.headers()[header::VARY], "HX-Request");
+    }
+}
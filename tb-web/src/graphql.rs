@@ -0,0 +1,704 @@
+//! GraphQL schema over accounts and transfers.
+//!
+//! A read-only view of the REST API that follows relations, so a page can
+//! fetch an account, its recent transfers and each transfer's counterpart
+//! account in one request:
+//!
+//! ```graphql
+//! {
+//!   account(id: "1f") {
+//!     netBalance
+//!     transfers(first: 20, reversed: true) {
+//!       nodes { id amount debitAccount { id ledger } creditAccount { id ledger } }
+//!       nextCursor
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! IDs are hex like in the REST API. Amounts, balances and timestamps are
+//! decimal strings, since they overflow GraphQL's 32-bit `Int`. Lists are
+//! paginated by passing a connection's `nextCursor` as `after`.
+//!
+//! Counterpart accounts are looked up through [`crate::cache`], which
+//! coalesces concurrent lookups of the same account.
+
+use crate::error::AppError;
+use crate::state::AppState;
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object, SimpleObject, ID,
+};
+use std::sync::Arc;
+use tb_rs::{
+    Account, AccountBalance, AccountFilter, AccountFilterFlags, QueryFilter, QueryFilterFlags,
+    Transfer,
+};
+
+/// Deepest query accepted; nested relations are otherwise unbounded.
+const DEPTH_MAX: usize = 10;
+
+/// Most fields one query may resolve.
+const COMPLEXITY_MAX: usize = 2000;
+
+/// The tb-web GraphQL schema.
+pub type Schema = async_graphql::Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Build the schema. Requests carry the [`AppState`] as data.
+pub fn schema() -> Schema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(DEPTH_MAX)
+        .limit_complexity(COMPLEXITY_MAX)
+        .finish()
+}
+
+impl From<AppError> for async_graphql::Error {
+    fn from(error: AppError) -> Self {
+        let (_, code) = error.status();
+        let message = match error {
+            AppError::NotFound(msg)
+            | AppError::BadRequest(msg)
+            | AppError::Unprocessable(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotAcceptable(msg)
+            | AppError::PayloadTooLarge(msg)
+            | AppError::Internal(msg) => msg,
+            AppError::Timeout => "Request timed out".to_string(),
+            AppError::Client(err) => {
+                tracing::error!("TigerBeetle client error: {:?}", err);
+                "TigerBeetle client error".to_string()
+            }
+        };
+        async_graphql::Error::new(message).extend_with(|_, e| e.set("code", code))
+    }
+}
+
+fn state<'a>(ctx: &Context<'a>) -> &'a Arc<AppState> {
+    ctx.data_unchecked::<Arc<AppState>>()
+}
+
+fn parse_id(id: &ID) -> async_graphql::Result<u128> {
+    u128::from_str_radix(id, 16).map_err(|_| format!("Invalid ID: {}", id.as_str()).into())
+}
+
+/// The timestamp after which a page starts, from an `after` cursor.
+fn parse_cursor(after: Option<String>) -> async_graphql::Result<u64> {
+    match after {
+        Some(cursor) => cursor
+            .parse::<u64>()
+            .map(|t| t + 1)
+            .map_err(|_| format!("Invalid cursor: {}", cursor).into()),
+        None => Ok(0),
+    }
+}
+
+/// Entry points of every query.
+pub struct QueryRoot;
+
+#[Object(name = "Query")]
+impl QueryRoot {
+    /// Look up an account by ID.
+    async fn account(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<Option<GqlAccount>> {
+        let state = state(ctx);
+        let account = state.cache.account(&state.pool, parse_id(&id)?).await?;
+        Ok(account.map(GqlAccount))
+    }
+
+    /// Accounts in creation order, optionally on one ledger or with one code.
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        ledger: Option<u32>,
+        code: Option<u16>,
+        #[graphql(default = 100)] first: u32,
+        after: Option<String>,
+    ) -> async_graphql::Result<AccountConnection> {
+        let filter = QueryFilter {
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            ledger: ledger.unwrap_or(0),
+            code: code.unwrap_or(0),
+            timestamp_min: parse_cursor(after)?,
+            timestamp_max: 0,
+            limit: first,
+            flags: QueryFilterFlags::empty(),
+            reserved: [0; 6],
+        };
+        let accounts = {
+            let client = state(ctx).pool.get();
+            client
+                .query_accounts(filter)
+                .await
+                .map_err(AppError::from)?
+        };
+        Ok(AccountConnection {
+            next_cursor: accounts.last().map(|a| a.timestamp.to_string()),
+            nodes: accounts.into_iter().map(GqlAccount).collect(),
+        })
+    }
+
+    /// Look up a transfer by ID.
+    async fn transfer(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<Option<GqlTransfer>> {
+        let transfers = {
+            let client = state(ctx).pool.get();
+            client
+                .lookup_transfers(&[parse_id(&id)?])
+                .await
+                .map_err(AppError::from)?
+        };
+        Ok(transfers.first().copied().map(GqlTransfer))
+    }
+
+    /// Transfers in creation order, optionally on one ledger or with one
+    /// code.
+    async fn transfers(
+        &self,
+        ctx: &Context<'_>,
+        ledger: Option<u32>,
+        code: Option<u16>,
+        #[graphql(default = 100)] first: u32,
+        after: Option<String>,
+        #[graphql(default)] reversed: bool,
+    ) -> async_graphql::Result<TransferConnection> {
+        let mut flags = QueryFilterFlags::empty();
+        if reversed {
+            flags |= QueryFilterFlags::REVERSED;
+        }
+        let filter = QueryFilter {
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            ledger: ledger.unwrap_or(0),
+            code: code.unwrap_or(0),
+            timestamp_min: parse_cursor(after)?,
+            timestamp_max: 0,
+            limit: first,
+            flags,
+            reserved: [0; 6],
+        };
+        let state = state(ctx);
+        let transfers = state.cache.transfers(&state.pool, filter).await?;
+        Ok(TransferConnection::from(transfers.as_slice()))
+    }
+}
+
+/// A page of accounts.
+#[derive(SimpleObject)]
+pub struct AccountConnection {
+    nodes: Vec<GqlAccount>,
+    /// Pass as `after` for the next page; absent when this one is empty.
+    next_cursor: Option<String>,
+}
+
+/// A page of transfers.
+#[derive(SimpleObject)]
+pub struct TransferConnection {
+    nodes: Vec<GqlTransfer>,
+    /// Pass as `after` for the next page; absent when this one is empty.
+    next_cursor: Option<String>,
+}
+
+impl From<&[Transfer]> for TransferConnection {
+    fn from(transfers: &[Transfer]) -> Self {
+        Self {
+            next_cursor: transfers.last().map(|t| t.timestamp.to_string()),
+            nodes: transfers.iter().copied().map(GqlTransfer).collect(),
+        }
+    }
+}
+
+/// A TigerBeetle account.
+#[derive(Clone, Copy)]
+pub struct GqlAccount(Account);
+
+#[Object(name = "Account")]
+impl GqlAccount {
+    async fn id(&self) -> ID {
+        ID(format!("{:032x}", self.0.id))
+    }
+
+    async fn ledger(&self) -> u32 {
+        self.0.ledger
+    }
+
+    async fn code(&self) -> u16 {
+        self.0.code
+    }
+
+    async fn flags(&self) -> u16 {
+        self.0.flags.bits()
+    }
+
+    async fn user_data_128(&self) -> String {
+        format!("{:032x}", self.0.user_data_128)
+    }
+
+    async fn user_data_64(&self) -> String {
+        self.0.user_data_64.to_string()
+    }
+
+    async fn user_data_32(&self) -> u32 {
+        self.0.user_data_32
+    }
+
+    async fn debits_pending(&self) -> String {
+        self.0.debits_pending.to_string()
+    }
+
+    async fn debits_posted(&self) -> String {
+        self.0.debits_posted.to_string()
+    }
+
+    async fn credits_pending(&self) -> String {
+        self.0.credits_pending.to_string()
+    }
+
+    async fn credits_posted(&self) -> String {
+        self.0.credits_posted.to_string()
+    }
+
+    /// Posted credits minus posted debits.
+    async fn net_balance(&self) -> String {
+        (self.0.credits_posted as i128)
+            .saturating_sub(self.0.debits_posted as i128)
+            .to_string()
+    }
+
+    async fn timestamp(&self) -> String {
+        self.0.timestamp.to_string()
+    }
+
+    /// Transfers debiting or crediting this account.
+    async fn transfers(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100)] first: u32,
+        after: Option<String>,
+        #[graphql(default)] reversed: bool,
+        #[graphql(default = true)] debits: bool,
+        #[graphql(default = true)] credits: bool,
+    ) -> async_graphql::Result<TransferConnection> {
+        let mut flags = AccountFilterFlags::empty();
+        if debits {
+            flags |= AccountFilterFlags::DEBITS;
+        }
+        if credits {
+            flags |= AccountFilterFlags::CREDITS;
+        }
+        if reversed {
+            flags |= AccountFilterFlags::REVERSED;
+        }
+        let filter = AccountFilter {
+            account_id: self.0.id,
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            code: 0,
+            timestamp_min: parse_cursor(after)?,
+            timestamp_max: 0,
+            limit: first,
+            flags,
+            reserved: [0; 58],
+        };
+        let transfers = {
+            let client = state(ctx).pool.get();
+            client
+                .get_account_transfers(filter)
+                .await
+                .map_err(AppError::from)?
+        };
+        Ok(TransferConnection::from(transfers.as_slice()))
+    }
+
+    /// Balance history, for accounts with the history flag.
+    async fn balances(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100)] first: u32,
+        after: Option<String>,
+        #[graphql(default)] reversed: bool,
+    ) -> async_graphql::Result<Vec<GqlBalance>> {
+        let mut flags = AccountFilterFlags::DEBITS | AccountFilterFlags::CREDITS;
+        if reversed {
+            flags |= AccountFilterFlags::REVERSED;
+        }
+        let filter = AccountFilter {
+            account_id: self.0.id,
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            code: 0,
+            timestamp_min: parse_cursor(after)?,
+            timestamp_max: 0,
+            limit: first,
+            flags,
+            reserved: [0; 58],
+        };
+        let balances = {
+            let client = state(ctx).pool.get();
+            client
+                .get_account_balances(filter)
+                .await
+                .map_err(AppError::from)?
+        };
+        Ok(balances.into_iter().map(GqlBalance).collect())
+    }
+}
+
+/// An account's balances after one of its transfers.
+pub struct GqlBalance(AccountBalance);
+
+#[Object(name = "Balance")]
+impl GqlBalance {
+    async fn debits_pending(&self) -> String {
+        self.0.debits_pending.to_string()
+    }
+
+    async fn debits_posted(&self) -> String {
+        self.0.debits_posted.to_string()
+    }
+
+    async fn credits_pending(&self) -> String {
+        self.0.credits_pending.to_string()
+    }
+
+    async fn credits_posted(&self) -> String {
+        self.0.credits_posted.to_string()
+    }
+
+    /// Timestamp of the transfer; pass as `after` for the next page.
+    async fn timestamp(&self) -> String {
+        self.0.timestamp.to_string()
+    }
+}
+
+/// A TigerBeetle transfer.
+#[derive(Clone, Copy)]
+pub struct GqlTransfer(Transfer);
+
+#[Object(name = "Transfer")]
+impl GqlTransfer {
+    async fn id(&self) -> ID {
+        ID(format!("{:032x}", self.0.id))
+    }
+
+    async fn debit_account_id(&self) -> ID {
+        ID(format!("{:032x}", self.0.debit_account_id))
+    }
+
+    async fn credit_account_id(&self) -> ID {
+        ID(format!("{:032x}", self.0.credit_account_id))
+    }
+
+    /// The account debited.
+    async fn debit_account(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<GqlAccount>> {
+        let state = state(ctx);
+        let account = state
+            .cache
+            .account(&state.pool, self.0.debit_account_id)
+            .await?;
+        Ok(account.map(GqlAccount))
+    }
+
+    /// The account credited.
+    async fn credit_account(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<GqlAccount>> {
+        let state = state(ctx);
+        let account = state
+            .cache
+            .account(&state.pool, self.0.credit_account_id)
+            .await?;
+        Ok(account.map(GqlAccount))
+    }
+
+    async fn amount(&self) -> String {
+        self.0.amount.to_string()
+    }
+
+    /// The pending transfer this posts or voids, if any.
+    async fn pending_id(&self) -> Option<ID> {
+        (self.0.pending_id != 0).then(|| ID(format!("{:032x}", self.0.pending_id)))
+    }
+
+    async fn user_data_128(&self) -> String {
+        format!("{:032x}", self.0.user_data_128)
+    }
+
+    async fn user_data_64(&self) -> String {
+        self.0.user_data_64.to_string()
+    }
+
+    async fn user_data_32(&self) -> u32 {
+        self.0.user_data_32
+    }
+
+    async fn timeout(&self) -> u32 {
+        self.0.timeout
+    }
+
+    async fn ledger(&self) -> u32 {
+        self.0.ledger
+    }
+
+    async fn code(&self) -> u16 {
+        self.0.code
+    }
+
+    async fn flags(&self) -> u16 {
+        self.0.flags.bits()
+    }
+
+    async fn timestamp(&self) -> String {
+        self.0.timestamp.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeCluster};
+    use serde_json::{json, Value};
+    use tb_rs::ClientError;
+
+    /// Accounts 1 and 2 on ledger 700, with transfers 10, 11 and 12 of 5,
+    /// 7 and 11 from 1 to 2.
+    fn cluster() -> Arc<FakeCluster> {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 700), testing::account(2, 700)],
+            &[
+                testing::transfer(10, 1, 2, 5, 700),
+                testing::transfer(11, 1, 2, 7, 700),
+                testing::transfer(12, 1, 2, 11, 700),
+            ],
+        );
+        cluster
+    }
+
+    async fn execute(cluster: &Arc<FakeCluster>, query: &str) -> async_graphql::Response {
+        let state = testing::state(testing::config(), cluster);
+        state
+            .graphql
+            .execute(async_graphql::Request::new(query).data(state.clone()))
+            .await
+    }
+
+    /// The data of a query that must succeed.
+    async fn resolve(cluster: &Arc<FakeCluster>, query: &str) -> Value {
+        let response = execute(cluster, query).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    fn id(id: u128) -> String {
+        format!("{:032x}", id)
+    }
+
+    #[tokio::test]
+    async fn test_account_with_transfers_and_counterparts() {
+        let cluster = cluster();
+        let data = resolve(
+            &cluster,
+            r#"{
+                account(id: "2") {
+                    id
+                    netBalance
+                    creditsPosted
+                    transfers(first: 2, reversed: true) {
+                        nodes { id amount pendingId debitAccount { id netBalance } }
+                    }
+                    balances(first: 1) { creditsPosted }
+                }
+            }"#,
+        )
+        .await;
+        let account = &data["account"];
+        assert_eq!(account["id"], id(2));
+        assert_eq!(account["netBalance"], "23");
+        assert_eq!(account["creditsPosted"], "23");
+        assert_eq!(
+            account["transfers"]["nodes"],
+            json!([
+                {
+                    "id": id(12),
+                    "amount": "11",
+                    "pendingId": null,
+                    "debitAccount": { "id": id(1), "netBalance": "-23" },
+                },
+                {
+                    "id": id(11),
+                    "amount": "7",
+                    "pendingId": null,
+                    "debitAccount": { "id": id(1), "netBalance": "-23" },
+                },
+            ])
+        );
+        assert_eq!(account["balances"], json!([{ "creditsPosted": "5" }]));
+    }
+
+    #[tokio::test]
+    async fn test_missing_account_and_transfer_are_null() {
+        let cluster = cluster();
+        let data = resolve(
+            &cluster,
+            r#"{ account(id: "ff") { id } transfer(id: "ff") { id } }"#,
+        )
+        .await;
+        assert_eq!(data, json!({ "account": null, "transfer": null }));
+    }
+
+    #[tokio::test]
+    async fn test_transfers_paginate_with_cursor() {
+        let cluster = cluster();
+        let mut after = String::new();
+        let mut ids = Vec::new();
+        loop {
+            let query = format!(
+                "{{ transfers(ledger: 700, first: 2{}) {{ nodes {{ id }} nextCursor }} }}",
+                after
+            );
+            let data = resolve(&cluster, &query).await;
+            let page = &data["transfers"];
+            let nodes = page["nodes"].as_array().unwrap();
+            if nodes.is_empty() {
+                assert_eq!(page["nextCursor"], Value::Null);
+                break;
+            }
+            assert!(nodes.len() <= 2);
+            ids.extend(nodes.iter().map(|t| t["id"].as_str().unwrap().to_string()));
+            after = format!(r#", after: "{}""#, page["nextCursor"].as_str().unwrap());
+        }
+        assert_eq!(ids, [id(10), id(11), id(12)]);
+
+        let data = resolve(
+            &cluster,
+            "{ accounts(ledger: 700, first: 1) { nodes { id } } }",
+        )
+        .await;
+        assert_eq!(data["accounts"]["nodes"], json!([{ "id": id(1) }]));
+        let data = resolve(
+            &cluster,
+            "{ accounts(ledger: 701) { nodes { id } nextCursor } }",
+        )
+        .await;
+        assert_eq!(data["accounts"], json!({ "nodes": [], "nextCursor": null }));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_id_and_cursor() {
+        let cluster = cluster();
+        let response = execute(&cluster, r#"{ account(id: "xyz") { id } }"#).await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].message, "Invalid ID: xyz");
+
+        let response = execute(&cluster, r#"{ transfers(after: "-1") { nextCursor } }"#).await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].message, "Invalid cursor: -1");
+    }
+
+    /// An account query `hops` counterpart accounts deep.
+    fn nested(hops: usize) -> String {
+        let mut selection = "id".to_string();
+        for _ in 0..hops {
+            selection = format!(
+                "transfers {{ nodes {{ debitAccount {{ {} }} }} }}",
+                selection
+            );
+        }
+        format!(r#"{{ account(id: "2") {{ {} }} }}"#, selection)
+    }
+
+    #[tokio::test]
+    async fn test_depth_limit() {
+        let cluster = cluster();
+        // Each hop nests three fields, between `account` and `id`.
+        let response = execute(&cluster, &nested(3)).await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(
+            response.errors[0].message.contains("nested too deep"),
+            "{}",
+            response.errors[0].message
+        );
+        let data = resolve(&cluster, &nested(2)).await;
+        let hop = &data["account"]["transfers"]["nodes"][0]["debitAccount"];
+        let hop = &hop["transfers"]["nodes"][0]["debitAccount"];
+        assert_eq!(hop["id"], id(1));
+    }
+
+    #[tokio::test]
+    async fn test_complexity_limit() {
+        let cluster = cluster();
+        let fields = |n: usize| -> String {
+            (0..n)
+                .map(|i| format!(r#"a{}: account(id: "1") {{ id }}"#, i))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        // Each aliased lookup costs two: the account and its `id`.
+        let response = execute(
+            &cluster,
+            &format!("{{ {} }}", fields(COMPLEXITY_MAX / 2 + 1)),
+        )
+        .await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(
+            response.errors[0].message.contains("too complex"),
+            "{}",
+            response.errors[0].message
+        );
+        resolve(&cluster, &format!("{{ {} }}", fields(COMPLEXITY_MAX / 2))).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_error_carries_code() {
+        let cluster = cluster();
+        cluster.fail_next(ClientError::Shutdown);
+        let response = execute(&cluster, r#"{ transfer(id: "10") { id } }"#).await;
+        assert_eq!(response.errors.len(), 1);
+        let error = &response.errors[0];
+        assert_eq!(error.message, "TigerBeetle client error");
+        let code = error.extensions.as_ref().unwrap().get("code").unwrap();
+        assert_eq!(code, &async_graphql::Value::from("tb_unavailable"));
+    }
+
+    #[test]
+    fn test_app_error_codes() {
+        let cases = [
+            (AppError::NotFound("gone".into()), "gone", "not_found"),
+            (AppError::BadRequest("bad".into()), "bad", "bad_request"),
+            (AppError::Unprocessable("no".into()), "no", "invalid_input"),
+            (AppError::Forbidden("role".into()), "role", "forbidden"),
+            (AppError::Timeout, "Request timed out", "request_timeout"),
+            (AppError::Internal("oops".into()), "oops", "internal"),
+            (
+                AppError::Client(ClientError::NotRegistered),
+                "TigerBeetle client error",
+                "tb_not_registered",
+            ),
+            (
+                AppError::Client(ClientError::Shutdown),
+                "TigerBeetle client error",
+                "tb_unavailable",
+            ),
+        ];
+        for (error, message, code) in cases {
+            let error = async_graphql::Error::from(error);
+            assert_eq!(error.message, message);
+            let extensions = error.extensions.unwrap();
+            assert_eq!(
+                extensions.get("code"),
+                Some(&async_graphql::Value::from(code)),
+                "{}",
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,814 @@
+//! gRPC service mirroring the REST API.
+//!
+//! Defined in `proto/tb_web.proto` and served on the web server's own port,
+//! under `/tb_web.v1.TigerBeetle/`. Values are represented as in the REST
+//! API (hex IDs, decimal amounts, flag names), and requests are validated,
+//! authorized and cached the same way.
+
+use crate::api::{codes, ApiAccount, ApiAccountBalance, ApiTransfer, NewAccount, NewTransfer};
+use crate::auth::{self, Role};
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::validate;
+use axum::http::StatusCode;
+use std::sync::Arc;
+use tb_rs::{AccountFilter, AccountFilterFlags, QueryFilter, QueryFilterFlags};
+use tonic::{Request, Response, Status};
+
+/// Code generated from `proto/tb_web.proto`.
+pub mod proto {
+    tonic::include_proto!("tb_web.v1");
+}
+
+use proto::tiger_beetle_server::{TigerBeetle, TigerBeetleServer};
+
+/// Results per query when the request sets no limit.
+const DEFAULT_LIMIT: u32 = 100;
+
+/// The `TigerBeetle` gRPC service.
+pub struct TigerBeetleService {
+    state: Arc<AppState>,
+}
+
+impl TigerBeetleService {
+    /// The service, ready to be routed to.
+    pub fn server(state: Arc<AppState>) -> TigerBeetleServer<Self> {
+        let max_body_bytes = state.config.max_body_bytes;
+        TigerBeetleServer::new(Self { state }).max_decoding_message_size(max_body_bytes)
+    }
+
+    /// Check the request's bearer token as the REST extractors do.
+    async fn authorize<T>(&self, request: &Request<T>, required: Role) -> Result<(), Status> {
+        let headers = request.metadata().clone().into_headers();
+        auth::authorize(&headers, &self.state, required).await?;
+        Ok(())
+    }
+}
+
+impl From<AppError> for Status {
+    fn from(error: AppError) -> Self {
+        let (status, _) = error.status();
+        match error {
+            AppError::NotFound(msg) => Status::not_found(msg),
+            AppError::BadRequest(msg)
+            | AppError::Unprocessable(msg)
+            | AppError::NotAcceptable(msg)
+            | AppError::PayloadTooLarge(msg) => Status::invalid_argument(msg),
+            AppError::Unauthorized(msg) => Status::unauthenticated(msg),
+            AppError::Forbidden(msg) => Status::permission_denied(msg),
+            AppError::Timeout => Status::deadline_exceeded("Request timed out"),
+            AppError::Client(tb_rs::ClientError::RequestTooLarge { size, limit }) => {
+                Status::invalid_argument(format!(
+                    "Request too large: {} bytes of events, the cluster accepts {}",
+                    size, limit
+                ))
+            }
+            AppError::Client(err) => {
+                tracing::error!("TigerBeetle client error: {:?}", err);
+                match status {
+                    StatusCode::GATEWAY_TIMEOUT => {
+                        Status::deadline_exceeded("TigerBeetle did not reply in time")
+                    }
+                    StatusCode::SERVICE_UNAVAILABLE => {
+                        Status::unavailable("TigerBeetle is unreachable")
+                    }
+                    _ => Status::internal("TigerBeetle client error"),
+                }
+            }
+            AppError::Internal(msg) => {
+                tracing::error!("Internal error: {}", msg);
+                Status::internal("Internal server error")
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl TigerBeetle for TigerBeetleService {
+    async fn lookup_accounts(
+        &self,
+        request: Request<proto::LookupRequest>,
+    ) -> Result<Response<proto::AccountsReply>, Status> {
+        self.authorize(&request, Role::Viewer).await?;
+        let ids = parse_ids(&request.get_ref().ids)?;
+
+        let accounts = {
+            let client = self.state.pool.get();
+            client.lookup_accounts(&ids).await.map_err(AppError::from)?
+        };
+
+        Ok(Response::new(proto::AccountsReply {
+            next_timestamp: None,
+            accounts: accounts.iter().map(proto::Account::from).collect(),
+        }))
+    }
+
+    async fn lookup_transfers(
+        &self,
+        request: Request<proto::LookupRequest>,
+    ) -> Result<Response<proto::TransfersReply>, Status> {
+        self.authorize(&request, Role::Viewer).await?;
+        let ids = parse_ids(&request.get_ref().ids)?;
+
+        let transfers = {
+            let client = self.state.pool.get();
+            client
+                .lookup_transfers(&ids)
+                .await
+                .map_err(AppError::from)?
+        };
+
+        Ok(Response::new(proto::TransfersReply {
+            next_timestamp: None,
+            transfers: transfers.iter().map(proto::Transfer::from).collect(),
+        }))
+    }
+
+    async fn query_accounts(
+        &self,
+        request: Request<proto::QueryRequest>,
+    ) -> Result<Response<proto::AccountsReply>, Status> {
+        self.authorize(&request, Role::Viewer).await?;
+        let filter = query_filter(request.get_ref())?;
+
+        let accounts = {
+            let client = self.state.pool.get();
+            client
+                .query_accounts(filter)
+                .await
+                .map_err(AppError::from)?
+        };
+
+        Ok(Response::new(proto::AccountsReply {
+            next_timestamp: accounts.last().map(|a| a.timestamp),
+            accounts: accounts.iter().map(proto::Account::from).collect(),
+        }))
+    }
+
+    async fn query_transfers(
+        &self,
+        request: Request<proto::QueryRequest>,
+    ) -> Result<Response<proto::TransfersReply>, Status> {
+        self.authorize(&request, Role::Viewer).await?;
+        let filter = query_filter(request.get_ref())?;
+
+        let transfers = self.state.cache.transfers(&self.state.pool, filter).await?;
+
+        Ok(Response::new(proto::TransfersReply {
+            next_timestamp: transfers.last().map(|t| t.timestamp),
+            transfers: transfers.iter().map(proto::Transfer::from).collect(),
+        }))
+    }
+
+    async fn get_account_transfers(
+        &self,
+        request: Request<proto::AccountFilterRequest>,
+    ) -> Result<Response<proto::TransfersReply>, Status> {
+        self.authorize(&request, Role::Viewer).await?;
+        let filter = account_filter(request.get_ref())?;
+
+        let transfers = {
+            let client = self.state.pool.get();
+            client
+                .get_account_transfers(filter)
+                .await
+                .map_err(AppError::from)?
+        };
+
+        Ok(Response::new(proto::TransfersReply {
+            next_timestamp: transfers.last().map(|t| t.timestamp),
+            transfers: transfers.iter().map(proto::Transfer::from).collect(),
+        }))
+    }
+
+    async fn get_account_balances(
+        &self,
+        request: Request<proto::AccountFilterRequest>,
+    ) -> Result<Response<proto::BalancesReply>, Status> {
+        self.authorize(&request, Role::Viewer).await?;
+        let filter = account_filter(request.get_ref())?;
+
+        let balances = {
+            let client = self.state.pool.get();
+            client
+                .get_account_balances(filter)
+                .await
+                .map_err(AppError::from)?
+        };
+
+        Ok(Response::new(proto::BalancesReply {
+            balances: balances
+                .iter()
+                .map(|b| {
+                    let b = ApiAccountBalance::from(b);
+                    proto::AccountBalance {
+                        debits_pending: b.debits_pending,
+                        debits_posted: b.debits_posted,
+                        credits_pending: b.credits_pending,
+                        credits_posted: b.credits_posted,
+                        timestamp: b.timestamp,
+                    }
+                })
+                .collect(),
+        }))
+    }
+
+    async fn create_accounts(
+        &self,
+        request: Request<proto::CreateAccountsRequest>,
+    ) -> Result<Response<proto::CreateReply>, Status> {
+        if self.state.config.read_only {
+            return Err(Status::unimplemented("tb-web is read-only"));
+        }
+        self.authorize(&request, Role::Admin).await?;
+        let max = validate::max_events::<tb_rs::Account>(&self.state)?;
+        let accounts = parse_items(&request.get_ref().accounts, "accounts", max, |a| {
+            tb_rs::Account::try_from(&NewAccount {
+                id: a.id.clone(),
+                user_data_128: a.user_data_128.clone(),
+                user_data_64: a.user_data_64,
+                user_data_32: a.user_data_32,
+                ledger: a.ledger,
+                code: parse_code(a.code)?,
+                flags: a.flags.clone(),
+            })
+        })?;
+
+        let failures = {
+            let client = self.state.pool.get();
+            client
+                .create_accounts(&accounts)
+                .await
+                .map_err(AppError::from)?
+        };
+
+        let ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
+        self.state.cache.accounts_created(&ids).await;
+        let failures: Vec<(u32, &'static str)> = failures
+            .iter()
+            .map(|f| (f.index, codes::account_result(f.result)))
+            .collect();
+        Ok(Response::new(create_reply(&ids, &failures)))
+    }
+
+    async fn create_transfers(
+        &self,
+        request: Request<proto::CreateTransfersRequest>,
+    ) -> Result<Response<proto::CreateReply>, Status> {
+        if self.state.config.read_only {
+            return Err(Status::unimplemented("tb-web is read-only"));
+        }
+        self.authorize(&request, Role::Operator).await?;
+        let max = validate::max_events::<tb_rs::Transfer>(&self.state)?;
+        let transfers = parse_items(&request.get_ref().transfers, "transfers", max, |t| {
+            tb_rs::Transfer::try_from(&NewTransfer {
+                id: t.id.clone(),
+                debit_account_id: t.debit_account_id.clone(),
+                credit_account_id: t.credit_account_id.clone(),
+                amount: t.amount.clone(),
+                pending_id: t.pending_id.clone(),
+                user_data_128: t.user_data_128.clone(),
+                user_data_64: t.user_data_64,
+                user_data_32: t.user_data_32,
+                timeout: t.timeout,
+                ledger: t.ledger,
+                code: parse_code(t.code)?,
+                flags: t.flags.clone(),
+            })
+        })?;
+
+        let failures = {
+            let client = self.state.pool.get();
+            client
+                .create_transfers(&transfers)
+                .await
+                .map_err(AppError::from)?
+        };
+        self.state.cache.transfers_created();
+
+        let ids: Vec<u128> = transfers.iter().map(|t| t.id).collect();
+        let failures: Vec<(u32, &'static str)> = failures
+            .iter()
+            .map(|f| (f.index, codes::transfer_result(f.result)))
+            .collect();
+        Ok(Response::new(create_reply(&ids, &failures)))
+    }
+}
+
+impl From<&tb_rs::Account> for proto::Account {
+    fn from(a: &tb_rs::Account) -> Self {
+        let a = ApiAccount::from(a);
+        Self {
+            id: a.id,
+            debits_pending: a.debits_pending,
+            debits_posted: a.debits_posted,
+            credits_pending: a.credits_pending,
+            credits_posted: a.credits_posted,
+            user_data_128: a.user_data_128,
+            user_data_64: a.user_data_64,
+            user_data_32: a.user_data_32,
+            ledger: a.ledger,
+            code: a.code.into(),
+            flags: a.flags.into(),
+            timestamp: a.timestamp,
+        }
+    }
+}
+
+impl From<&tb_rs::Transfer> for proto::Transfer {
+    fn from(t: &tb_rs::Transfer) -> Self {
+        let t = ApiTransfer::from(t);
+        Self {
+            id: t.id,
+            debit_account_id: t.debit_account_id,
+            credit_account_id: t.credit_account_id,
+            amount: t.amount,
+            pending_id: t.pending_id,
+            user_data_128: t.user_data_128,
+            user_data_64: t.user_data_64,
+            user_data_32: t.user_data_32,
+            timeout: t.timeout,
+            ledger: t.ledger,
+            code: t.code.into(),
+            flags: t.flags.into(),
+            timestamp: t.timestamp,
+        }
+    }
+}
+
+fn parse_ids(ids: &[String]) -> Result<Vec<u128>, AppError> {
+    ids.iter()
+        .map(|id| {
+            u128::from_str_radix(id, 16)
+                .map_err(|_| AppError::BadRequest(format!("Invalid ID: {}", id)))
+        })
+        .collect()
+}
+
+fn parse_code(code: u32) -> Result<u16, String> {
+    u16::try_from(code).map_err(|_| format!("code: {} is out of range", code))
+}
+
+/// Convert the items of a create request, rejecting more than `max` and
+/// malformed input with the index of the first bad item, as
+/// [`validate::Events`] does.
+fn parse_items<T, E>(
+    items: &[T],
+    what: &str,
+    max: u32,
+    convert: impl Fn(&T) -> Result<E, String>,
+) -> Result<Vec<E>, AppError> {
+    if items.is_empty() {
+        return Err(AppError::BadRequest(format!("No {} given", what)));
+    }
+    if items.len() > max as usize {
+        return Err(AppError::PayloadTooLarge(format!(
+            "{} {} given, at most {} per request",
+            items.len(),
+            what,
+            max
+        )));
+    }
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            convert(item).map_err(|e| AppError::Unprocessable(format!("[{}].{}", i, e)))
+        })
+        .collect()
+}
+
+fn limit(limit: u32) -> u32 {
+    if limit == 0 {
+        DEFAULT_LIMIT
+    } else {
+        limit
+    }
+}
+
+fn query_filter(request: &proto::QueryRequest) -> Result<QueryFilter, AppError> {
+    let mut flags = QueryFilterFlags::empty();
+    if request.reversed {
+        flags |= QueryFilterFlags::REVERSED;
+    }
+    Ok(QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger: request.ledger.unwrap_or(0),
+        code: parse_code(request.code.unwrap_or(0)).map_err(AppError::BadRequest)?,
+        timestamp_min: request.after_timestamp.map(|t| t + 1).unwrap_or(0),
+        timestamp_max: 0,
+        limit: limit(request.limit),
+        flags,
+        reserved: [0; 6],
+    })
+}
+
+fn account_filter(request: &proto::AccountFilterRequest) -> Result<AccountFilter, AppError> {
+    let mut flags = AccountFilterFlags::empty();
+    if request.debits || !request.credits {
+        flags |= AccountFilterFlags::DEBITS;
+    }
+    if request.credits || !request.debits {
+        flags |= AccountFilterFlags::CREDITS;
+    }
+    if request.reversed {
+        flags |= AccountFilterFlags::REVERSED;
+    }
+    Ok(AccountFilter {
+        account_id: parse_ids(std::slice::from_ref(&request.account_id))?[0],
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        code: 0,
+        timestamp_min: request.after_timestamp.map(|t| t + 1).unwrap_or(0),
+        timestamp_max: 0,
+        limit: limit(request.limit),
+        flags,
+        reserved: [0; 58],
+    })
+}
+
+/// The reply to a create request for `ids` whose `failures` are (index,
+/// result name), like the REST API's.
+fn create_reply(ids: &[u128], failures: &[(u32, &'static str)]) -> proto::CreateReply {
+    let mut results: Vec<proto::CreateResult> = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| proto::CreateResult {
+            index: i as u32,
+            id: format!("{:032x}", id),
+            result: "ok".to_string(),
+        })
+        .collect();
+    for &(index, result) in failures {
+        results[index as usize].result = result.to_string();
+    }
+    proto::CreateReply {
+        created: (ids.len() - failures.len()) as u32,
+        failed: failures.len() as u32,
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeCluster};
+    use tb_rs::{AccountFlags, ClientError, TransferFlags};
+    use tonic::Code;
+
+    fn service(cluster: &Arc<FakeCluster>) -> TigerBeetleService {
+        TigerBeetleService {
+            state: testing::state(testing::config(), cluster),
+        }
+    }
+
+    fn new_transfer(id: &str, amount: &str) -> proto::NewTransfer {
+        proto::NewTransfer {
+            id: id.to_string(),
+            debit_account_id: "1".to_string(),
+            credit_account_id: "2".to_string(),
+            amount: amount.to_string(),
+            ledger: 700,
+            code: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_account_to_proto() {
+        let account = tb_rs::Account {
+            id: 0xab,
+            debits_pending: 1,
+            debits_posted: u128::MAX,
+            credits_pending: 3,
+            credits_posted: 4,
+            user_data_128: 0xcd,
+            user_data_64: 5,
+            user_data_32: 6,
+            ledger: 700,
+            code: u16::MAX,
+            flags: AccountFlags::HISTORY,
+            timestamp: 7,
+            ..Default::default()
+        };
+        assert_eq!(
+            proto::Account::from(&account),
+            proto::Account {
+                id: format!("{:032x}", 0xab),
+                debits_pending: "1".to_string(),
+                debits_posted: u128::MAX.to_string(),
+                credits_pending: "3".to_string(),
+                credits_posted: "4".to_string(),
+                user_data_128: format!("{:032x}", 0xcd),
+                user_data_64: 5,
+                user_data_32: 6,
+                ledger: 700,
+                code: 65535,
+                flags: AccountFlags::HISTORY.bits().into(),
+                timestamp: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn test_transfer_to_proto() {
+        let transfer = tb_rs::Transfer {
+            id: 1,
+            debit_account_id: 2,
+            credit_account_id: 3,
+            amount: u128::MAX,
+            pending_id: 4,
+            timeout: 60,
+            ledger: 700,
+            code: 9,
+            flags: TransferFlags::POST_PENDING_TRANSFER,
+            timestamp: 8,
+            ..Default::default()
+        };
+        let proto = proto::Transfer::from(&transfer);
+        assert_eq!(proto.id, format!("{:032x}", 1));
+        assert_eq!(proto.debit_account_id, format!("{:032x}", 2));
+        assert_eq!(proto.credit_account_id, format!("{:032x}", 3));
+        assert_eq!(proto.amount, "340282366920938463463374607431768211455");
+        assert_eq!(proto.pending_id, format!("{:032x}", 4));
+        assert_eq!(proto.user_data_128, format!("{:032x}", 0));
+        assert_eq!(proto.timeout, 60);
+        assert_eq!(proto.code, 9);
+        assert_eq!(
+            proto.flags,
+            u32::from(TransferFlags::POST_PENDING_TRANSFER.bits())
+        );
+        assert_eq!(proto.timestamp, 8);
+    }
+
+    #[test]
+    fn test_status_codes() {
+        let cases = [
+            (AppError::NotFound("gone".into()), Code::NotFound, "gone"),
+            (
+                AppError::BadRequest("bad".into()),
+                Code::InvalidArgument,
+                "bad",
+            ),
+            (
+                AppError::Unprocessable("no".into()),
+                Code::InvalidArgument,
+                "no",
+            ),
+            (
+                AppError::PayloadTooLarge("big".into()),
+                Code::InvalidArgument,
+                "big",
+            ),
+            (
+                AppError::Unauthorized("who".into()),
+                Code::Unauthenticated,
+                "who",
+            ),
+            (
+                AppError::Forbidden("role".into()),
+                Code::PermissionDenied,
+                "role",
+            ),
+            (
+                AppError::Timeout,
+                Code::DeadlineExceeded,
+                "Request timed out",
+            ),
+            (
+                AppError::Internal("secret".into()),
+                Code::Internal,
+                "Internal server error",
+            ),
+            (
+                AppError::Client(ClientError::Timeout),
+                Code::DeadlineExceeded,
+                "TigerBeetle did not reply in time",
+            ),
+            (
+                AppError::Client(ClientError::NotRegistered),
+                Code::Unavailable,
+                "TigerBeetle is unreachable",
+            ),
+            (
+                AppError::Client(ClientError::Shutdown),
+                Code::Unavailable,
+                "TigerBeetle is unreachable",
+            ),
+            (
+                AppError::Client(ClientError::RequestTooLarge {
+                    size: 2048,
+                    limit: 1024,
+                }),
+                Code::InvalidArgument,
+                "Request too large: 2048 bytes of events, the cluster accepts 1024",
+            ),
+            (
+                AppError::Client(ClientError::InvalidOperation),
+                Code::Internal,
+                "TigerBeetle client error",
+            ),
+        ];
+        for (error, code, message) in cases {
+            let status = Status::from(error);
+            assert_eq!(status.code(), code, "{}", message);
+            assert_eq!(status.message(), message);
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let filter = query_filter(&proto::QueryRequest {
+            ledger: Some(700),
+            code: Some(3),
+            limit: 0,
+            after_timestamp: Some(41),
+            reversed: true,
+        })
+        .unwrap();
+        assert_eq!((filter.ledger, filter.code), (700, 3));
+        assert_eq!(filter.timestamp_min, 42);
+        assert_eq!(filter.limit, DEFAULT_LIMIT);
+        assert_eq!(filter.flags, QueryFilterFlags::REVERSED);
+
+        let error = query_filter(&proto::QueryRequest {
+            code: Some(70000),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(Status::from(error).code(), Code::InvalidArgument);
+
+        let both = AccountFilterFlags::DEBITS | AccountFilterFlags::CREDITS;
+        for (debits, credits, flags) in [
+            (false, false, both),
+            (true, true, both),
+            (true, false, AccountFilterFlags::DEBITS),
+            (false, true, AccountFilterFlags::CREDITS),
+        ] {
+            let filter = account_filter(&proto::AccountFilterRequest {
+                account_id: "ab".to_string(),
+                debits,
+                credits,
+                limit: 5,
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(filter.account_id, 0xab);
+            assert_eq!(filter.flags, flags, "debits {} credits {}", debits, credits);
+            assert_eq!((filter.limit, filter.timestamp_min), (5, 0));
+        }
+
+        let error = account_filter(&proto::AccountFilterRequest {
+            account_id: "xyz".to_string(),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(Status::from(error).message(), "Invalid ID: xyz");
+    }
+
+    #[tokio::test]
+    async fn test_create_and_read_back() {
+        let cluster = FakeCluster::new();
+        let service = service(&cluster);
+        let reply = service
+            .create_accounts(Request::new(proto::CreateAccountsRequest {
+                accounts: [1, 2, 2]
+                    .iter()
+                    .map(|id| proto::NewAccount {
+                        id: format!("{:x}", id),
+                        ledger: 700,
+                        code: 10,
+                        flags: vec!["history".to_string()],
+                        ..Default::default()
+                    })
+                    .collect(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((reply.created, reply.failed), (2, 1));
+        let results: Vec<&str> = reply.results.iter().map(|r| r.result.as_str()).collect();
+        assert_eq!(results, ["ok", "ok", "exists"]);
+        assert_eq!(reply.results[2].id, format!("{:032x}", 2));
+
+        let reply = service
+            .create_transfers(Request::new(proto::CreateTransfersRequest {
+                transfers: vec![new_transfer("10", "5"), new_transfer("11", "7")],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((reply.created, reply.failed), (2, 0));
+
+        let reply = service
+            .query_transfers(Request::new(proto::QueryRequest {
+                ledger: Some(700),
+                limit: 1,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(reply.transfers.len(), 1);
+        assert_eq!(reply.transfers[0].id, format!("{:032x}", 0x10));
+        let reply = service
+            .query_transfers(Request::new(proto::QueryRequest {
+                ledger: Some(700),
+                after_timestamp: reply.next_timestamp,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let ids: Vec<&str> = reply.transfers.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, [format!("{:032x}", 0x11)]);
+
+        let reply = service
+            .lookup_accounts(Request::new(proto::LookupRequest {
+                ids: vec!["2".to_string(), "ff".to_string()],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(reply.accounts.len(), 1);
+        assert_eq!(reply.accounts[0].credits_posted, "12");
+
+        let reply = service
+            .get_account_balances(Request::new(proto::AccountFilterRequest {
+                account_id: "2".to_string(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let credits: Vec<&str> = reply
+            .balances
+            .iter()
+            .map(|b| b.credits_posted.as_str())
+            .collect();
+        assert_eq!(credits, ["5", "12"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_bad_input() {
+        let cluster = FakeCluster::new();
+        let service = service(&cluster);
+        let create = |transfers: Vec<proto::NewTransfer>| {
+            service.create_transfers(Request::new(proto::CreateTransfersRequest { transfers }))
+        };
+
+        let status = create(vec![]).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "No transfers given");
+
+        let status = create(vec![new_transfer("10", "5"), new_transfer("11", "-5")])
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(
+            status.message().starts_with("[1].amount:"),
+            "{}",
+            status.message()
+        );
+
+        let mut transfer = new_transfer("10", "5");
+        transfer.code = 70000;
+        let status = create(vec![transfer]).await.unwrap_err();
+        assert_eq!(status.message(), "[0].code: 70000 is out of range");
+
+        let max = validate::max_events::<tb_rs::Transfer>(&service.state).unwrap();
+        let status = create(vec![new_transfer("10", "5"); max as usize + 1])
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(
+            status.message().contains("per request"),
+            "{}",
+            status.message()
+        );
+
+        cluster.fail_next(ClientError::Shutdown);
+        let status = create(vec![new_transfer("10", "5")]).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let cluster = FakeCluster::new();
+        let config = crate::config::Config {
+            read_only: true,
+            ..testing::config()
+        };
+        let service = TigerBeetleService {
+            state: testing::state(config, &cluster),
+        };
+        let status = service
+            .create_accounts(Request::new(proto::CreateAccountsRequest::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+    }
+}
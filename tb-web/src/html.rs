@@ -1,6 +1,26 @@
 //! HTML template rendering for HTMX responses.
+//!
+//! Fragments are built with [`maud`], which escapes every interpolated
+//! value, so IDs and other API data can't inject markup. Pieces shared
+//! between fragments (tables, info rows, ID links) are functions returning
+//! [`Markup`].
 
-use crate::api::{ApiAccount, ApiTransfer};
+use crate::aliases::{Alias, Aliases};
+use crate::api::{
+    ApiAccount, ApiPendingTransfer, ApiTransfer, Direction, LedgerReconciliation, LedgerStats,
+    LedgerSummary, QueryKind, ResolveResponse, SavedQuery, StatsResponse,
+};
+use crate::currency::Currency;
+use crate::paging::Cursors;
+use crate::recent::{Kind, Viewed};
+use crate::routes::explore::ExploreParams;
+use crate::routes::ledgers::BrowseFilters;
+use crate::routes::new_transfer::{FormErrors, TransferForm};
+use crate::timestamps::Timestamps;
+use crate::validate::LIMIT_MAX;
+use maud::{html, Markup, PreEscaped, Render};
+use std::fmt::Write;
+use std::time::UNIX_EPOCH;
 
 /// Format a u128 hex ID for display (shortened).
 fn format_id(id: &str) -> String {
@@ -30,37 +50,32 @@ fn format_amount(amount: &str) -> String {
     result
 }
 
-/// Format a TigerBeetle timestamp (nanoseconds) to a readable date.
-fn format_timestamp(timestamp: u64) -> String {
-    if timestamp == 0 {
-        return "-".to_string();
+/// An amount in its ledger's currency if `display` has it formatted, else
+/// `raw` with thousands separators.
+fn amount(display: Option<&str>, raw: &str) -> String {
+    display.map_or_else(|| format_amount(raw), str::to_string)
+}
+
+/// Like [`amount`], for a `raw` amount that may be negative.
+fn signed_amount(display: Option<&str>, raw: &str) -> String {
+    match raw.strip_prefix('-') {
+        Some(digits) => {
+            display.map_or_else(|| format!("-{}", format_amount(digits)), str::to_string)
+        }
+        None => amount(display, raw),
     }
-    // Convert nanoseconds to milliseconds
-    let ms = timestamp / 1_000_000;
-    let secs = (ms / 1000) as i64;
-    let nanos = ((ms % 1000) * 1_000_000) as u32;
-
-    // Use chrono-free formatting (simple approach)
-    let datetime = std::time::UNIX_EPOCH + std::time::Duration::new(secs as u64, nanos);
-    let duration = datetime
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default();
-    let total_secs = duration.as_secs();
-
-    // Simple date formatting without chrono
-    let days = total_secs / 86400;
-    let years = 1970 + days / 365; // Approximate
-    let remaining_days = days % 365;
-    let months = remaining_days / 30 + 1;
-    let day = remaining_days % 30 + 1;
-    let hours = (total_secs % 86400) / 3600;
-    let minutes = (total_secs % 3600) / 60;
-    let seconds = total_secs % 60;
+}
 
-    format!(
-        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-        years, months, day, hours, minutes, seconds
-    )
+/// A timestamp as configured: absolute, or relative with the absolute
+/// time on hover.
+fn timestamp(timestamp: u64, timestamps: &Timestamps) -> Markup {
+    if timestamps.is_relative() && timestamp != 0 {
+        html! {
+            span title=(timestamps.absolute(timestamp)) { (timestamps.relative(timestamp)) }
+        }
+    } else {
+        html! { (timestamps.absolute(timestamp)) }
+    }
 }
 
 /// Calculate net balance from credits and debits.
@@ -77,318 +92,1080 @@ fn calculate_net_balance(credits_posted: &str, debits_posted: &str) -> (String,
     }
 }
 
+/// A link to an account or transfer page, showing the shortened ID.
+fn id_link(kind: &str, id: &str) -> Markup {
+    html! {
+        a href={ "/" (kind) "/" (id) } class="id" title=(id) { (format_id(id)) }
+    }
+}
+
+/// A link to a ledger's page.
+fn ledger_link(ledger: u32) -> Markup {
+    html! {
+        a href={ "/ledger/" (ledger) } class="ledger" { (ledger) }
+    }
+}
+
+/// A link to an account page, showing the account's alias if it has one.
+fn account_link(id: &str, aliases: &Aliases) -> Markup {
+    match aliases.get_hex(id) {
+        Some(alias) => html! {
+            a href={ "/account/" (id) } class="id" title=(id) { (alias.name) }
+        },
+        None => id_link("account", id),
+    }
+}
+
+/// A labelled value in a detail card.
+fn info_row(label: &str, value: impl Render) -> Markup {
+    html! {
+        div class="info-row" {
+            span class="info-label" { (label) }
+            span class="info-value" { (value) }
+        }
+    }
+}
+
+/// A table with a header row; `amount` columns are right-aligned.
+fn table(columns: &[(&str, bool)], rows: Markup) -> Markup {
+    html! {
+        table {
+            thead {
+                tr {
+                    @for &(name, amount) in columns {
+                        th class=[amount.then_some("amount")] { (name) }
+                    }
+                }
+            }
+            tbody { (rows) }
+        }
+    }
+}
+
+/// The "Load More" button after a page of a recent list.
+fn load_more(url: &str) -> Markup {
+    html! {
+        div class="pagination" {
+            button class="btn btn-secondary"
+                hx-get=(url)
+                hx-target="closest .recent-section div"
+                hx-swap="innerHTML" {
+                "Load More"
+            }
+        }
+    }
+}
+
+/// The Previous and Next buttons under a page of the list at `url`, whose
+/// query string carries the list's filters.
+pub struct Pager<'a> {
+    pub url: &'a str,
+    pub cursors: Cursors,
+}
+
+/// Buttons to the pages before and after, each taking this one's place.
+fn pager(pager: &Pager) -> Markup {
+    let Cursors { prev, next } = pager.cursors;
+    html! {
+        @if prev.is_some() || next.is_some() {
+            div class="pagination" {
+                @if let Some(ts) = prev {
+                    button class="btn btn-secondary"
+                        hx-get={ (pager.url) "&before_timestamp=" (ts) }
+                        hx-target="closest .recent-section > div"
+                        hx-swap="innerHTML" {
+                        "Previous"
+                    }
+                }
+                @if let Some(ts) = next {
+                    button class="btn btn-secondary"
+                        hx-get={ (pager.url) "&after_timestamp=" (ts) }
+                        hx-target="closest .recent-section > div"
+                        hx-swap="innerHTML" {
+                        "Next"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A "nothing here" placeholder.
+fn empty(message: &str) -> Markup {
+    html! {
+        p class="loading" { (message) }
+    }
+}
+
 /// Render accounts as an HTML table.
-pub fn render_accounts_table(accounts: &[ApiAccount], next_timestamp: Option<u64>) -> String {
-    if accounts.is_empty() {
-        return r#"<p class="loading">No accounts found</p>"#.to_string();
-    }
-
-    let mut html = String::from(
-        r#"<table>
-            <thead>
-                <tr>
-                    <th>ID</th>
-                    <th>Ledger</th>
-                    <th>Code</th>
-                    <th class="amount">Net Balance</th>
-                    <th class="amount">Credits</th>
-                    <th class="amount">Debits</th>
-                    <th>Created</th>
-                </tr>
-            </thead>
-            <tbody>"#,
-    );
-
-    for account in accounts {
-        let (net_balance, is_positive) = calculate_net_balance(&account.credits_posted, &account.debits_posted);
-        let balance_class = if is_positive { "positive" } else { "negative" };
-
-        html.push_str(&format!(
-            r#"<tr>
-                <td><a href="/account/{}" class="id" title="{}">{}</a></td>
-                <td>{}</td>
-                <td>{}</td>
-                <td class="amount {}">{}</td>
-                <td class="amount">{}</td>
-                <td class="amount">{}</td>
-                <td>{}</td>
-            </tr>"#,
-            account.id,
-            account.id,
-            format_id(&account.id),
-            account.ledger,
-            account.code,
-            balance_class,
-            net_balance,
-            format_amount(&account.credits_posted),
-            format_amount(&account.debits_posted),
-            format_timestamp(account.timestamp),
-        ));
-    }
-
-    html.push_str("</tbody></table>");
-
-    if let Some(ts) = next_timestamp {
-        html.push_str(&format!(
-            r#"<div class="pagination">
-                <button class="btn btn-secondary"
-                        hx-get="/api/v1/accounts?limit=100&after_timestamp={}"
-                        hx-target="closest .recent-section div"
-                        hx-swap="innerHTML">
-                    Load More
-                </button>
-            </div>"#,
-            ts
-        ));
-    }
-
-    html
+pub fn render_accounts_table(
+    accounts: &[ApiAccount],
+    page: Option<Pager>,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    let columns = [
+        ("ID", false),
+        ("Ledger", false),
+        ("Code", false),
+        ("Net Balance", true),
+        ("Credits", true),
+        ("Debits", true),
+        ("Created", false),
+    ];
+    let rows = html! {
+        @for account in accounts {
+            @let (net_balance, is_positive) =
+                calculate_net_balance(&account.credits_posted, &account.debits_posted);
+            @let display = account.display.as_ref();
+            tr data-account-id=(account.id) {
+                td { (account_link(&account.id, aliases)) }
+                td { (ledger_link(account.ledger)) }
+                td { (account.code) }
+                td class={ "amount " (balance_class(is_positive)) } {
+                    (display.map_or(net_balance, |d| d.net_balance.clone()))
+                }
+                td class="amount" {
+                    (amount(display.map(|d| d.credits_posted.as_str()), &account.credits_posted))
+                }
+                td class="amount" {
+                    (amount(display.map(|d| d.debits_posted.as_str()), &account.debits_posted))
+                }
+                td { (timestamp(account.timestamp, timestamps)) }
+            }
+        }
+    };
+
+    html! {
+        @if accounts.is_empty() {
+            (empty("No accounts found"))
+        } @else {
+            (table(&columns, rows))
+        }
+        @if let Some(page) = &page {
+            (pager(page))
+        }
+    }
+    .into_string()
 }
 
 /// Render transfers as an HTML table.
-pub fn render_transfers_table(transfers: &[ApiTransfer], next_timestamp: Option<u64>) -> String {
-    if transfers.is_empty() {
-        return r#"<p class="loading">No transfers found</p>"#.to_string();
-    }
-
-    let mut html = String::from(
-        r#"<table>
-            <thead>
-                <tr>
-                    <th>ID</th>
-                    <th>From</th>
-                    <th>To</th>
-                    <th class="amount">Amount</th>
-                    <th>Ledger</th>
-                    <th>Code</th>
-                    <th>Created</th>
-                </tr>
-            </thead>
-            <tbody>"#,
-    );
-
-    for transfer in transfers {
-        html.push_str(&format!(
-            r#"<tr>
-                <td><a href="/transfer/{}" class="id" title="{}">{}</a></td>
-                <td><a href="/account/{}" class="id" title="{}">{}</a></td>
-                <td><a href="/account/{}" class="id" title="{}">{}</a></td>
-                <td class="amount">{}</td>
-                <td>{}</td>
-                <td>{}</td>
-                <td>{}</td>
-            </tr>"#,
-            transfer.id,
-            transfer.id,
-            format_id(&transfer.id),
-            transfer.debit_account_id,
-            transfer.debit_account_id,
-            format_id(&transfer.debit_account_id),
-            transfer.credit_account_id,
-            transfer.credit_account_id,
-            format_id(&transfer.credit_account_id),
-            format_amount(&transfer.amount),
-            transfer.ledger,
-            transfer.code,
-            format_timestamp(transfer.timestamp),
-        ));
-    }
-
-    html.push_str("</tbody></table>");
-
-    if let Some(ts) = next_timestamp {
-        html.push_str(&format!(
-            r#"<div class="pagination">
-                <button class="btn btn-secondary"
-                        hx-get="/api/v1/transfers?limit=100&reversed=true&after_timestamp={}"
-                        hx-target="closest .recent-section div"
-                        hx-swap="innerHTML">
-                    Load More
-                </button>
-            </div>"#,
-            ts
-        ));
-    }
-
-    html
-}
-
-/// Render a stat card for accounts count.
-pub fn render_accounts_stat(accounts: &[ApiAccount]) -> String {
-    format!(
-        r#"<div class="label">Accounts</div>
-        <div class="value">{}</div>"#,
-        accounts.len()
-    )
+pub fn render_transfers_table(
+    transfers: &[ApiTransfer],
+    page: Option<Pager>,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    let columns = [
+        ("ID", false),
+        ("From", false),
+        ("To", false),
+        ("Amount", true),
+        ("Ledger", false),
+        ("Code", false),
+        ("Created", false),
+    ];
+    let rows = html! {
+        @for transfer in transfers {
+            tr {
+                td { (id_link("transfer", &transfer.id)) }
+                td { (account_link(&transfer.debit_account_id, aliases)) }
+                td { (account_link(&transfer.credit_account_id, aliases)) }
+                td class="amount" {
+                    (amount(transfer.display.as_ref().map(|d| d.amount.as_str()), &transfer.amount))
+                }
+                td { (ledger_link(transfer.ledger)) }
+                td { (transfer.code) }
+                td { (timestamp(transfer.timestamp, timestamps)) }
+            }
+        }
+    };
+
+    html! {
+        @if transfers.is_empty() {
+            (empty("No transfers found"))
+        } @else {
+            (table(&columns, rows))
+        }
+        @if let Some(page) = &page {
+            (pager(page))
+        }
+    }
+    .into_string()
+}
+
+/// Seconds left as "1h 02m 03s", "4m 05s" or "6s".
+fn format_countdown(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Render pending transfers as an HTML table, with the time each has left
+/// (counted down by the page) and, if `actions`, buttons to post or void.
+pub fn render_pending_transfers(
+    transfers: &[ApiPendingTransfer],
+    truncated: bool,
+    actions: bool,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    if transfers.is_empty() && !truncated {
+        return empty("No pending transfers").into_string();
+    }
+
+    let mut columns = vec![
+        ("ID", false),
+        ("From", false),
+        ("To", false),
+        ("Amount", true),
+        ("Ledger", false),
+        ("Code", false),
+        ("Created", false),
+        ("Expires In", false),
+    ];
+    if actions {
+        columns.push(("", false));
+    }
+    let rows = html! {
+        @for pending in transfers {
+            @let transfer = &pending.transfer;
+            tr {
+                td { (id_link("transfer", &transfer.id)) }
+                td { (account_link(&transfer.debit_account_id, aliases)) }
+                td { (account_link(&transfer.credit_account_id, aliases)) }
+                td class="amount" {
+                    (amount(transfer.display.as_ref().map(|d| d.amount.as_str()), &transfer.amount))
+                }
+                td { (ledger_link(transfer.ledger)) }
+                td { (transfer.code) }
+                td { (timestamp(transfer.timestamp, timestamps)) }
+                @match (pending.expires_at, pending.expires_in_secs) {
+                    (Some(at), Some(secs)) => {
+                        td data-expires-at=(at / 1_000_000) { (format_countdown(secs)) }
+                    }
+                    _ => td { "never" },
+                }
+                @if actions {
+                    td {
+                        button class="btn"
+                            hx-post={ "/api/v1/transfers/" (transfer.id) "/post" }
+                            hx-confirm="Post this pending transfer?"
+                            hx-swap="none"
+                            data-pending-action {
+                            "Post"
+                        }
+                        " "
+                        button class="btn btn-secondary"
+                            hx-post={ "/api/v1/transfers/" (transfer.id) "/void" }
+                            hx-confirm="Void this pending transfer?"
+                            hx-swap="none"
+                            data-pending-action {
+                            "Void"
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    html! {
+        (table(&columns, rows))
+        @if truncated {
+            p class="loading" {
+                "Showing the newest pending transfers only; older ones may be missing."
+            }
+        }
+    }
+    .into_string()
+}
+
+/// A stat card: a label over a value.
+fn stat(label: &str, value: impl Render) -> Markup {
+    html! {
+        div class="label" { (label) }
+        div class="value" { (value) }
+    }
 }
 
-/// Render a stat card for transfers count.
-pub fn render_transfers_stat(transfers: &[ApiTransfer]) -> String {
+/// Render the dashboard statistics: cards with the totals, then a table by
+/// ledger. `updated` is when they were refreshed, if they were yet.
+pub fn render_stats(
+    stats: &StatsResponse,
+    updated: Option<u64>,
+    enabled: bool,
+    timestamps: &Timestamps,
+) -> String {
+    let Some(updated) = updated else {
+        let message = if enabled {
+            "Counting accounts and transfers..."
+        } else {
+            "Statistics are off"
+        };
+        return empty(message).into_string();
+    };
+
+    let count = |n: u64| format_amount(&n.to_string());
+    let columns = [
+        ("Ledger", false),
+        ("Accounts", true),
+        ("Transfers", true),
+        ("Last Hour", true),
+        ("Last Day", true),
+        ("Volume", true),
+    ];
+    let rows = html! {
+        @for ledger in &stats.ledgers {
+            tr {
+                td { (ledger_link(ledger.ledger)) }
+                td class="amount" { (count(ledger.accounts)) }
+                td class="amount" { (count(ledger.transfers)) }
+                td class="amount" { (count(ledger.transfers_last_hour)) }
+                td class="amount" { (count(ledger.transfers_last_day)) }
+                td class="amount" {
+                    (amount(ledger.display.as_ref().map(|d| d.volume.as_str()), &ledger.volume))
+                }
+            }
+        }
+    };
+    html! {
+        div class="stats" {
+            div class="stat-card" { (stat("Accounts", count(stats.accounts))) }
+            div class="stat-card" { (stat("Transfers", count(stats.transfers))) }
+            div class="stat-card" {
+                (stat("Transfers, Last Hour", count(stats.transfers_last_hour)))
+            }
+            div class="stat-card" {
+                (stat("Transfers, Last Day", count(stats.transfers_last_day)))
+            }
+        }
+        @if stats.ledgers.is_empty() {
+            (empty("No ledgers yet"))
+        } @else {
+            (table(&columns, rows))
+        }
+        p class="stats-updated" { "Updated " (timestamp(updated, timestamps)) }
+    }
+    .into_string()
+}
+
+/// Render the IDs a shortened one may stand for, in full, as links.
+pub fn render_resolved(resolved: &ResolveResponse) -> String {
+    html! {
+        @if resolved.matches.is_empty() {
+            (empty("No known ID matches"))
+        } @else {
+            ul class="resolved" {
+                @for found in &resolved.matches {
+                    li {
+                        span class="kind" { (found.kind) }
+                        a href={ "/" (found.kind) "/" (found.id) } class="id" { (found.id) }
+                        @if let Some(alias) = &found.alias {
+                            span class="alias" { (alias) }
+                        }
+                    }
+                }
+            }
+            @if resolved.truncated {
+                (empty("More IDs match; give more digits"))
+            }
+        }
+    }
+    .into_string()
+}
+
+/// Render why an ID can't be resolved.
+pub fn render_resolve_error(message: &str) -> String {
+    html! {
+        p class="query-error" { (message) }
+    }
+    .into_string()
+}
+
+/// Render the accounts and transfers a session viewed, newest first.
+pub fn render_recent(viewed: &[Viewed], aliases: &Aliases, timestamps: &Timestamps) -> String {
+    html! {
+        @if viewed.is_empty() {
+            (empty("Accounts and transfers you open show up here"))
+        } @else {
+            ul class="resolved" {
+                @for viewed in viewed {
+                    @let id = format!("{:032x}", viewed.id);
+                    @let at = viewed.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    li {
+                        span class="kind" { (viewed.kind.name()) }
+                        @match viewed.kind {
+                            Kind::Account => (account_link(&id, aliases)),
+                            Kind::Transfer => (id_link("transfer", &id)),
+                        }
+                        span class="alias" { (timestamp(at.as_nanos() as u64, timestamps)) }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+/// The URL of the page of saved query `name` after `timestamp`.
+fn query_page_url(name: &str, timestamp: u64) -> String {
     format!(
-        r#"<div class="label">Recent Transfers</div>
-        <div class="value">{}</div>"#,
-        transfers.len()
+        "/api/v1/queries/{}/results?after_timestamp={}",
+        name, timestamp
     )
 }
 
+/// Render a page of a saved query's accounts.
+pub fn render_query_accounts(
+    name: &str,
+    accounts: &[ApiAccount],
+    next_timestamp: Option<u64>,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    html! {
+        (PreEscaped(render_accounts_table(accounts, None, aliases, timestamps)))
+        @if let Some(ts) = next_timestamp {
+            (load_more(&query_page_url(name, ts)))
+        }
+    }
+    .into_string()
+}
+
+/// Render a page of a saved query's transfers.
+pub fn render_query_transfers(
+    name: &str,
+    transfers: &[ApiTransfer],
+    next_timestamp: Option<u64>,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    html! {
+        (PreEscaped(render_transfers_table(transfers, None, aliases, timestamps)))
+        @if let Some(ts) = next_timestamp {
+            (load_more(&query_page_url(name, ts)))
+        }
+    }
+    .into_string()
+}
+
+/// Render the query builder form with the fields of `params` filled in,
+/// suggesting the ledgers that have a currency.
+pub fn render_query_builder(
+    params: &ExploreParams,
+    ledgers: &[(u32, &Currency)],
+    default_limit: u32,
+) -> String {
+    let accounts = params.is_accounts();
+    let limit = match params.limit.as_str() {
+        "" => default_limit.to_string(),
+        limit => limit.to_string(),
+    };
+    let directions = [
+        ("both", "Debits and credits"),
+        ("debits", "Debits"),
+        ("credits", "Credits"),
+    ];
+    html! {
+        form id="explore-form" class="query-form"
+            hx-get="/api/v1/explore/results"
+            hx-target="#explore-results" {
+            // Accounts have no account or direction, so switching kind
+            // re-renders the form.
+            select name="kind"
+                hx-get="/api/v1/explore"
+                hx-trigger="change"
+                hx-target="#explore-builder"
+                hx-include="closest form" {
+                option value="transfers" selected[!accounts] { "Transfers" }
+                option value="accounts" selected[accounts] { "Accounts" }
+            }
+            input type="number" name="ledger" min="1" max="4294967295" placeholder="Ledger"
+                list="explore-ledgers" value=(params.ledger);
+            datalist id="explore-ledgers" {
+                @for (ledger, currency) in ledgers {
+                    option value=(ledger) { (currency.code) }
+                }
+            }
+            input type="text" name="codes" placeholder="Codes, e.g. 1, 2" value=(params.codes);
+            input type="datetime-local" name="from" title="Created from" value=(params.from);
+            input type="datetime-local" name="until" title="Created until" value=(params.until);
+            input type="text" name="user_data_128" placeholder="User data 128 (hex)"
+                value=(params.user_data_128);
+            input type="number" name="user_data_64" min="0" placeholder="User data 64"
+                value=(params.user_data_64);
+            input type="number" name="user_data_32" min="0" max="4294967295"
+                placeholder="User data 32" value=(params.user_data_32);
+            @if !accounts {
+                input type="text" name="account" placeholder="Account ID (hex)"
+                    value=(params.account);
+                select name="direction" {
+                    @for (value, label) in directions {
+                        option value=(value) selected[params.direction == value] { (label) }
+                    }
+                }
+            }
+            input type="number" name="limit" min="1" max=(LIMIT_MAX) title="Rows per page"
+                value=(limit);
+            label {
+                input type="checkbox" name="reversed" value="true"
+                    checked[params.reversed == "true"];
+                " Newest first"
+            }
+            button type="submit" class="btn" { "Run" }
+        }
+    }
+    .into_string()
+}
+
+/// Render why the query builder form can't be run.
+pub fn render_explore_error(message: &str) -> String {
+    html! {
+        p class="query-error" { (message) }
+    }
+    .into_string()
+}
+
+/// Render a page of the query builder's accounts, with a link to the next
+/// page at `next_url`.
+pub fn render_explore_accounts(
+    accounts: &[ApiAccount],
+    next_url: Option<&str>,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    html! {
+        (PreEscaped(render_accounts_table(accounts, None, aliases, timestamps)))
+        @if let Some(url) = next_url {
+            (load_more(url))
+        }
+    }
+    .into_string()
+}
+
+/// Render a page of the query builder's transfers, with a link to the next
+/// page at `next_url`.
+pub fn render_explore_transfers(
+    transfers: &[ApiTransfer],
+    next_url: Option<&str>,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    html! {
+        (PreEscaped(render_transfers_table(transfers, None, aliases, timestamps)))
+        @if let Some(url) = next_url {
+            (load_more(url))
+        }
+    }
+    .into_string()
+}
+
+/// A saved query's filters in a few words.
+fn describe_query(query: &SavedQuery, timestamps: &Timestamps) -> String {
+    let mut parts = Vec::new();
+    if let Some(ledger) = query.ledger {
+        parts.push(format!("ledger {}", ledger));
+    }
+    if !query.codes.is_empty() {
+        let codes: Vec<String> = query.codes.iter().map(u16::to_string).collect();
+        parts.push(format!("code {}", codes.join(", ")));
+    }
+    if let Some(account) = &query.account {
+        let direction = match query.direction {
+            Direction::Debits => "debits of",
+            Direction::Credits => "credits of",
+            Direction::Both => "account",
+        };
+        parts.push(format!("{} {}", direction, format_id(account)));
+    }
+    match (query.timestamp_min, query.timestamp_max) {
+        (Some(min), Some(max)) => parts.push(format!(
+            "{} to {}",
+            timestamps.absolute(min),
+            timestamps.absolute(max)
+        )),
+        (Some(min), None) => parts.push(format!("from {}", timestamps.absolute(min))),
+        (None, Some(max)) => parts.push(format!("until {}", timestamps.absolute(max))),
+        (None, None) => {}
+    }
+    if query.user_data_128.is_some() || query.user_data_64.is_some() || query.user_data_32.is_some()
+    {
+        parts.push("user data".to_string());
+    }
+    if query.reversed {
+        parts.push("newest first".to_string());
+    }
+    parts.push(format!("up to {}", query.limit));
+    parts.join(" · ")
+}
+
+/// Render the saved queries, each with buttons to run, edit and delete it.
+pub fn render_saved_queries(queries: &[SavedQuery], timestamps: &Timestamps) -> String {
+    if queries.is_empty() {
+        return empty("No saved queries").into_string();
+    }
+
+    let columns = [
+        ("Name", false),
+        ("Lists", false),
+        ("Filter", false),
+        ("", false),
+    ];
+    let rows = html! {
+        @for query in queries {
+            tr {
+                td { (query.name) }
+                td {
+                    @match query.kind {
+                        QueryKind::Accounts => "Accounts",
+                        QueryKind::Transfers => "Transfers",
+                    }
+                }
+                td { (describe_query(query, timestamps)) }
+                td {
+                    button class="btn"
+                        hx-get={ "/api/v1/queries/" (query.name) "/results" }
+                        hx-target="#query-results" {
+                        "Run"
+                    }
+                    " "
+                    button class="btn btn-secondary" data-query-edit=(query.name) { "Edit" }
+                    " "
+                    button class="btn btn-secondary" data-query-delete=(query.name) { "Delete" }
+                }
+            }
+        }
+    };
+    table(&columns, rows).into_string()
+}
+
+/// Render a dashboard card for a ledger summary.
+pub fn render_ledger_summary(summary: &LedgerSummary) -> String {
+    let window = format_window(summary.window_secs);
+    let display = summary.display.as_ref();
+    html! {
+        (stat(
+            &format!("Ledger {}", summary.ledger),
+            format!("{} accounts", format_amount(&summary.accounts.to_string())),
+        ))
+        (info_row(
+            "Debits Posted",
+            amount(display.map(|d| d.debits_posted.as_str()), &summary.debits_posted),
+        ))
+        (info_row(
+            "Credits Posted",
+            amount(display.map(|d| d.credits_posted.as_str()), &summary.credits_posted),
+        ))
+        (info_row(
+            "Debits Pending",
+            amount(display.map(|d| d.debits_pending.as_str()), &summary.debits_pending),
+        ))
+        (info_row(
+            "Credits Pending",
+            amount(display.map(|d| d.credits_pending.as_str()), &summary.credits_pending),
+        ))
+        (info_row(
+            &format!("Transfers ({})", window),
+            format_amount(&summary.recent_transfers.to_string()),
+        ))
+        (info_row(
+            &format!("Volume ({})", window),
+            amount(display.map(|d| d.recent_volume.as_str()), &summary.recent_volume),
+        ))
+        div class="reconcile" {
+            button class="btn btn-secondary"
+                hx-get={ "/api/v1/ledgers/" (summary.ledger) "/reconcile" }
+                hx-target="closest .reconcile" {
+                "Reconcile"
+            }
+        }
+    }
+    .into_string()
+}
+
+/// Render the outcome of reconciling a ledger, for its dashboard card.
+pub fn render_ledger_reconciliation(reconciliation: &LedgerReconciliation) -> String {
+    let display = reconciliation.display.as_ref();
+    let outcome = if reconciliation.balanced {
+        "Balanced"
+    } else {
+        "Unbalanced"
+    };
+    html! {
+        (info_row(
+            "Reconciled",
+            html! {
+                span class=(balance_class(reconciliation.balanced)) { (outcome) }
+            },
+        ))
+        (info_row("Accounts Checked", format_amount(&reconciliation.accounts.to_string())))
+        @if reconciliation.posted_discrepancy != "0" {
+            (info_row(
+                "Posted Debits - Credits",
+                signed_amount(
+                    display.map(|d| d.posted_discrepancy.as_str()),
+                    &reconciliation.posted_discrepancy,
+                ),
+            ))
+        }
+        @if reconciliation.pending_discrepancy != "0" {
+            (info_row(
+                "Pending Debits - Credits",
+                signed_amount(
+                    display.map(|d| d.pending_discrepancy.as_str()),
+                    &reconciliation.pending_discrepancy,
+                ),
+            ))
+        }
+        (info_row("Took", format!("{} ms", reconciliation.duration_ms)))
+    }
+    .into_string()
+}
+
+/// Render a ledger's browse page: its statistics as of the last refresh
+/// (`None` while there are none), a filter form, and its accounts and
+/// transfers filtered by `filters`, each loaded on its own.
+pub fn render_ledger_browse(
+    ledger: u32,
+    currency: Option<&Currency>,
+    stats: Option<&LedgerStats>,
+    filters: &BrowseFilters,
+) -> String {
+    let count = |n: u64| format_amount(&n.to_string());
+    let mut list_query = format!("ledger={}&limit={}", ledger, filters.limit);
+    if let Some(code) = filters.code {
+        let _ = write!(list_query, "&code={}", code);
+    }
+    let orders = [("newest", "Newest first"), ("oldest", "Oldest first")];
+    html! {
+        section class="ledger-page" {
+            h2 {
+                "Ledger " (ledger)
+                @if let Some(currency) = currency { " (" (currency.code) ")" }
+            }
+            @if let Some(stats) = stats {
+                div class="stats" {
+                    div class="stat-card" { (stat("Accounts", count(stats.accounts))) }
+                    div class="stat-card" { (stat("Transfers", count(stats.transfers))) }
+                    div class="stat-card" {
+                        (stat("Transfers, Last Day", count(stats.transfers_last_day)))
+                    }
+                    div class="stat-card" {
+                        @let display = stats.display.as_ref();
+                        (stat("Volume", amount(display.map(|d| d.volume.as_str()), &stats.volume)))
+                    }
+                }
+            }
+            div class="recent-section" {
+                h3 { "Balances" }
+                div class="reconcile" {
+                    button class="btn btn-secondary"
+                        hx-get={ "/api/v1/ledgers/" (ledger) "/summary" }
+                        hx-target="closest .reconcile"
+                        hx-swap="outerHTML" {
+                        "Summarize"
+                    }
+                }
+            }
+            form class="query-form ledger-filters"
+                hx-get={ "/api/v1/ledgers/" (ledger) "/browse" }
+                hx-target="closest .ledger-page"
+                hx-swap="outerHTML" {
+                input type="number" name="code" min="1" max="65535" placeholder="Code"
+                    value=[filters.code];
+                input type="number" name="limit" min="1" max=(LIMIT_MAX) title="Rows per page"
+                    value=(filters.limit);
+                select name="order" title="Transfers" {
+                    @for (value, label) in orders {
+                        option value=(value) selected[filters.oldest_first == (value == "oldest")] {
+                            (label)
+                        }
+                    }
+                }
+                button type="submit" class="btn" { "Filter" }
+            }
+            div class="recent-section" {
+                h3 { "Accounts" }
+                div hx-get={ "/api/v1/accounts?" (list_query) } hx-trigger="load" {
+                    (empty("Loading accounts..."))
+                }
+            }
+            div class="recent-section" {
+                h3 { "Transfers" }
+                div hx-get={
+                        "/api/v1/transfers?" (list_query) "&reversed=" (!filters.oldest_first)
+                    }
+                    hx-trigger="load" {
+                    (empty("Loading transfers..."))
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+/// Format a window length as "last hour", "last 15m" and so on.
+fn format_window(secs: u64) -> String {
+    match secs {
+        3600 => "last hour".to_string(),
+        86400 => "last day".to_string(),
+        s if s % 3600 == 0 => format!("last {}h", s / 3600),
+        s if s % 60 == 0 => format!("last {}m", s / 60),
+        s => format!("last {}s", s),
+    }
+}
+
+/// The layout shared by detail pages: a titled section of info cards,
+/// followed by `extra`.
+fn detail_page(class: &str, title: &str, cards: &[(&str, Markup)], extra: Markup) -> Markup {
+    html! {
+        section class=(class) {
+            h2 { (title) }
+            div class="account-detail" {
+                @for (heading, rows) in cards {
+                    div class="account-info" {
+                        h3 { (heading) }
+                        (rows)
+                    }
+                }
+            }
+            (extra)
+        }
+    }
+}
+
 /// Render account detail page.
-pub fn render_account_detail(account: &ApiAccount) -> String {
-    let (net_balance, is_positive) = calculate_net_balance(&account.credits_posted, &account.debits_posted);
-    let balance_class = if is_positive { "positive" } else { "negative" };
+pub fn render_account_detail(
+    account: &ApiAccount,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    let (net_balance, is_positive) =
+        calculate_net_balance(&account.credits_posted, &account.debits_posted);
+    let display = account.display.as_ref();
+    let net_balance = display.map_or(net_balance, |d| d.net_balance.clone());
 
-    format!(
-        r#"<section class="account-detail-page">
-            <h2>Account Details</h2>
-
-            <div class="account-detail">
-                <div class="account-info">
-                    <h3>Information</h3>
-                    <div class="info-row">
-                        <span class="info-label">ID</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Ledger</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Code</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Flags</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Created</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                </div>
-
-                <div class="account-info">
-                    <h3>Balances</h3>
-                    <div class="info-row">
-                        <span class="info-label">Net Balance</span>
-                        <span class="info-value {}">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Credits Posted</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Debits Posted</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Credits Pending</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Debits Pending</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                </div>
-            </div>
-
-            <div class="chart-container">
-                <h3>Balance History</h3>
-                <canvas id="balanceChart" height="300"></canvas>
-            </div>
-            <script>
-                if (window.tbWeb && window.tbWeb.renderBalanceChart) {{
-                    window.tbWeb.renderBalanceChart('{}');
-                }}
-            </script>
-
-            <div class="recent-section">
-                <h3>Recent Transfers</h3>
-                <div id="account-transfers"
-                     hx-get="/api/v1/accounts/{}/transfers?limit=20&reversed=true"
-                     hx-trigger="load">
-                    Loading transfers...
-                </div>
-            </div>
-        </section>"#,
-        account.id,
-        account.ledger,
-        account.code,
-        format_account_flags(account.flags),
-        format_timestamp(account.timestamp),
-        balance_class,
-        net_balance,
-        format_amount(&account.credits_posted),
-        format_amount(&account.debits_posted),
-        format_amount(&account.credits_pending),
-        format_amount(&account.debits_pending),
-        account.id,
-        account.id,
+    let alias = aliases.get_hex(&account.id);
+    let information = html! {
+        (info_row("ID", &account.id))
+        @if let Some(alias) = &alias {
+            (info_row("Alias", &alias.name))
+            @if !alias.labels.is_empty() {
+                (info_row("Labels", alias.labels.join(", ")))
+            }
+        }
+        (info_row("Ledger", ledger_link(account.ledger)))
+        (info_row("Code", account.code))
+        (info_row("Flags", format_account_flags(account.flags)))
+        (info_row("Created", timestamp(account.timestamp, timestamps)))
+    };
+    let balances = html! {
+        div class="info-row" {
+            span class="info-label" { "Net Balance" }
+            span class={ "info-value " (balance_class(is_positive)) } { (net_balance) }
+        }
+        (info_row(
+            "Credits Posted",
+            amount(display.map(|d| d.credits_posted.as_str()), &account.credits_posted),
+        ))
+        (info_row(
+            "Debits Posted",
+            amount(display.map(|d| d.debits_posted.as_str()), &account.debits_posted),
+        ))
+        (info_row(
+            "Credits Pending",
+            amount(display.map(|d| d.credits_pending.as_str()), &account.credits_pending),
+        ))
+        (info_row(
+            "Debits Pending",
+            amount(display.map(|d| d.debits_pending.as_str()), &account.debits_pending),
+        ))
+    };
+    let extra = html! {
+        div class="chart-container" {
+            h3 { "Balance History" }
+            canvas id="balanceChart" height="300" {}
+        }
+        script {
+            (PreEscaped(format!(
+                "if (window.tbWeb && window.tbWeb.renderBalanceChart) {{ \
+                 window.tbWeb.renderBalanceChart({}); }}",
+                js_string(&account.id)
+            )))
+        }
+        div class="recent-section" {
+            h3 { "Recent Transfers" }
+            div id="account-transfers"
+                hx-get={ "/api/v1/accounts/" (account.id) "/transfers?limit=20&reversed=true" }
+                hx-trigger="load" {
+                "Loading transfers..."
+            }
+        }
+    };
+
+    detail_page(
+        "account-detail-page",
+        "Account Details",
+        &[("Information", information), ("Balances", balances)],
+        extra,
     )
+    .into_string()
 }
 
 /// Render transfer detail page.
-pub fn render_transfer_detail(transfer: &ApiTransfer) -> String {
-    format!(
-        r#"<section class="transfer-detail-page">
-            <h2>Transfer Details</h2>
-
-            <div class="account-detail">
-                <div class="account-info">
-                    <h3>Transfer</h3>
-                    <div class="info-row">
-                        <span class="info-label">ID</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Amount</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Ledger</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Code</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Flags</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Created</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                </div>
-
-                <div class="account-info">
-                    <h3>Accounts</h3>
-                    <div class="info-row">
-                        <span class="info-label">From (Debit)</span>
-                        <span class="info-value"><a href="/account/{}" class="id">{}</a></span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">To (Credit)</span>
-                        <span class="info-value"><a href="/account/{}" class="id">{}</a></span>
-                    </div>
-                    <div class="info-row">
-                        <span class="info-label">Pending ID</span>
-                        <span class="info-value">{}</span>
-                    </div>
-                </div>
-            </div>
-        </section>"#,
-        transfer.id,
-        format_amount(&transfer.amount),
-        transfer.ledger,
-        transfer.code,
-        format_transfer_flags(transfer.flags),
-        format_timestamp(transfer.timestamp),
-        transfer.debit_account_id,
-        format_id(&transfer.debit_account_id),
-        transfer.credit_account_id,
-        format_id(&transfer.credit_account_id),
-        format_id(&transfer.pending_id),
+pub fn render_transfer_detail(
+    transfer: &ApiTransfer,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    let details = html! {
+        (info_row("ID", &transfer.id))
+        (info_row(
+            "Amount",
+            amount(transfer.display.as_ref().map(|d| d.amount.as_str()), &transfer.amount),
+        ))
+        (info_row("Ledger", ledger_link(transfer.ledger)))
+        (info_row("Code", transfer.code))
+        (info_row("Flags", format_transfer_flags(transfer.flags)))
+        (info_row("Created", timestamp(transfer.timestamp, timestamps)))
+    };
+    let accounts = html! {
+        (info_row("From (Debit)", account_link(&transfer.debit_account_id, aliases)))
+        (info_row("To (Credit)", account_link(&transfer.credit_account_id, aliases)))
+        (info_row("Pending ID", format_id(&transfer.pending_id)))
+    };
+
+    detail_page(
+        "transfer-detail-page",
+        "Transfer Details",
+        &[("Transfer", details), ("Accounts", accounts)],
+        html! {},
     )
+    .into_string()
+}
+
+/// A labelled field of a form, with its error under it.
+fn form_field(label: &str, id: &str, error: Option<&str>, input: Markup) -> Markup {
+    html! {
+        div class={ "form-field" @if error.is_some() { " invalid" } } {
+            label for=(id) { (label) }
+            (input)
+            @if let Some(error) = error {
+                p class="field-error" { (error) }
+            }
+        }
+    }
+}
+
+/// Render the transfer form with the fields of `form` filled in and
+/// `errors` under them, offering `aliases` for the accounts. `ledger` is
+/// the debit account's, with its currency, if it is known.
+pub fn render_transfer_form(
+    form: &TransferForm,
+    ledger: Option<(u32, Option<&Currency>)>,
+    aliases: &[Alias],
+    errors: &FormErrors,
+) -> String {
+    let hint = match ledger {
+        Some((ledger, Some(currency))) => format!(
+            "Ledger {}: {}, {} decimal places",
+            ledger, currency.code, currency.scale
+        ),
+        Some((ledger, None)) => format!("Ledger {}: whole units", ledger),
+        None => "In the debit account's ledger".to_string(),
+    };
+    let account = |name: &str, label: &str, value: &str| {
+        let id = format!("transfer-{}", name);
+        form_field(label, &id, errors.get(name), html! {
+            input type="text" id=(id) name=(name) value=(value)
+                list="transfer-aliases" placeholder="Alias or hex ID" autocomplete="off";
+        })
+    };
+    html! {
+        form id="transfer-form" class="transfer-form"
+            hx-post="/api/v1/transfers/new"
+            hx-target="#new-transfer" {
+            input type="hidden" name="id" value=(form.id);
+            @if let Some(error) = errors.get("") {
+                p class="query-error" { (error) }
+            }
+            // Picking the debit account re-renders the form, so the
+            // amount's currency shows.
+            div hx-get="/api/v1/transfers/new"
+                hx-trigger="change"
+                hx-target="#new-transfer"
+                hx-include="closest form" {
+                (account("debit", "Debit account", &form.debit))
+            }
+            (account("credit", "Credit account", &form.credit))
+            datalist id="transfer-aliases" {
+                @for alias in aliases {
+                    option value=(alias.name) { (format_id(&format!("{:032x}", alias.account_id))) }
+                }
+            }
+            (form_field("Amount", "transfer-amount", errors.get("amount"), html! {
+                input type="text" id="transfer-amount" name="amount" inputmode="decimal"
+                    placeholder="e.g. 12.50" value=(form.amount);
+                p class="field-hint" { (hint) }
+            }))
+            (form_field("Code", "transfer-code", errors.get("code"), html! {
+                input type="number" id="transfer-code" name="code" min="1" max="65535"
+                    value=(form.code);
+            }))
+            (form_field("Timeout (seconds)", "transfer-timeout", errors.get("timeout"), html! {
+                input type="number" id="transfer-timeout" name="timeout" min="0"
+                    max="4294967295" placeholder="Pending only; none if blank"
+                    value=(form.timeout);
+            }))
+            div class="form-actions" {
+                label {
+                    input type="checkbox" name="pending" value="true"
+                        checked[form.pending == "true"];
+                    " Pending"
+                }
+                button type="submit" class="btn" { "Create Transfer" }
+            }
+        }
+    }
+    .into_string()
+}
+
+/// Render a transfer the form just created, with a button for another.
+pub fn render_transfer_created(
+    transfer: &ApiTransfer,
+    aliases: &Aliases,
+    timestamps: &Timestamps,
+) -> String {
+    html! {
+        p class="form-success" { "Transfer created." }
+        (PreEscaped(render_transfer_detail(transfer, aliases, timestamps)))
+        button class="btn" hx-get="/api/v1/transfers/new" hx-target="#new-transfer" {
+            "New Transfer"
+        }
+    }
+    .into_string()
+}
+
+/// CSS class for a net balance.
+fn balance_class(is_positive: bool) -> &'static str {
+    if is_positive {
+        "positive"
+    } else {
+        "negative"
+    }
+}
+
+/// Quote `s` as a JavaScript string literal that is safe inside a
+/// `<script>` element.
+fn js_string(s: &str) -> String {
+    serde_json::to_string(s)
+        .unwrap_or_default()
+        .replace('<', "\\u003c")
 }
 
 /// Format account flags.
@@ -417,3 +1194,105 @@ fn format_transfer_flags(flags: u16) -> String {
     if flags & (1 << 8) != 0 { names.push("IMPORTED"); }
     if names.is_empty() { "none".to_string() } else { names.join(", ") }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ResolvedId;
+    use crate::testing::{account, transfer};
+    use chrono_tz::Tz;
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    const NAME: &str = r#"<script>alert("name")</script>"#;
+    const LABEL: &str = "<b>vip</b> & co";
+
+    /// Aliases naming account 1 with markup.
+    async fn aliases() -> Aliases {
+        let aliases = Aliases::open(Path::new(":memory:")).unwrap();
+        let alias = Alias {
+            account_id: 1,
+            name: NAME.to_string(),
+            labels: vec![LABEL.to_string()],
+        };
+        aliases.set(alias).await.unwrap();
+        aliases
+    }
+
+    /// Check that `html` shows the alias escaped, and never as markup.
+    fn assert_escaped(html: &str) {
+        assert!(!html.contains("<script>alert"), "{}", html);
+        assert!(
+            html.contains("&lt;script&gt;alert(&quot;name&quot;)&lt;/script&gt;"),
+            "{}",
+            html
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aliases_are_escaped() {
+        let aliases = aliases().await;
+        let timestamps = Timestamps::new(Tz::UTC, false);
+        let accounts = [ApiAccount::from(&account(1, 1))];
+        let transfers = [ApiTransfer::from(&transfer(10, 1, 2, 5, 1))];
+
+        assert_escaped(&render_accounts_table(&accounts, None, &aliases, &timestamps));
+        assert_escaped(&render_transfers_table(&transfers, None, &aliases, &timestamps));
+
+        let detail = render_account_detail(&accounts[0], &aliases, &timestamps);
+        assert_escaped(&detail);
+        assert!(!detail.contains("<b>vip"), "{}", detail);
+        assert!(detail.contains("&lt;b&gt;vip&lt;/b&gt; &amp; co"), "{}", detail);
+
+        let viewed = [Viewed {
+            kind: Kind::Account,
+            id: 1,
+            at: SystemTime::now(),
+        }];
+        assert_escaped(&render_recent(&viewed, &aliases, &timestamps));
+
+        let resolved = ResolveResponse {
+            matches: vec![ResolvedId {
+                kind: "account",
+                id: format!("{:032x}", 1),
+                alias: Some(NAME.to_string()),
+            }],
+            truncated: false,
+        };
+        assert_escaped(&render_resolved(&resolved));
+
+        let form = render_transfer_form(
+            &TransferForm::default(),
+            None,
+            &aliases.list(),
+            &FormErrors::default(),
+        );
+        assert_escaped(&form);
+    }
+
+    #[test]
+    fn test_input_is_escaped() {
+        // Messages quoting what was typed.
+        let message = format!("Invalid ID prefix: {}", NAME);
+        assert_escaped(&render_resolve_error(&message));
+        assert_escaped(&render_explore_error(&message));
+
+        // Form values go back into attributes.
+        let form = TransferForm {
+            debit: NAME.to_string(),
+            amount: r#""><script>alert("name")</script>"#.to_string(),
+            ..TransferForm::default()
+        };
+        let html = render_transfer_form(&form, None, &[], &FormErrors::default());
+        assert_escaped(&html);
+        assert!(!html.contains(r#""><script>"#), "{}", html);
+    }
+
+    #[test]
+    fn test_js_string() {
+        assert_eq!(js_string("abc"), r#""abc""#);
+        assert_eq!(js_string(r#"a"b\c"#), r#""a\"b\\c""#);
+        // A closing tag can't end the surrounding script element.
+        assert_eq!(js_string("</script>"), r#""\u003c/script>""#);
+    }
+}
@@ -0,0 +1,268 @@
+//! Live updates for connected browsers.
+//!
+//! TigerBeetle has no change feed, so a background task polls
+//! query_transfers for transfers newer than the last one it saw. Each new
+//! transfer is published as a `transfer` event, followed by an `account`
+//! event with the new balances of every account the page of transfers
+//! touched. The same balances go to balance long-polls as
+//! [`BalanceChange`]s, and new transfers to the webhooks (see
+//! [`crate::webhooks`]). Polling only runs while someone is subscribed or
+//! waiting, or webhooks are configured; it resumes from the newest
+//! transfer, so a quiet period is not replayed.
+
+use crate::api::{ApiAccount, ApiTransfer, LiveEvent};
+use crate::state::AppState;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tb_rs::{Account, ClientError, QueryFilter, QueryFilterFlags};
+use tokio::sync::broadcast;
+
+/// How often to poll for new transfers.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transfers fetched per poll.
+const PAGE_SIZE: u32 = 1000;
+
+/// Events buffered per subscriber before it starts missing them.
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+/// An account a poll saw transfers touch, with its balances after them.
+#[derive(Debug, Clone)]
+pub struct BalanceChange {
+    pub account: Account,
+    /// Timestamp of the newest of those transfers.
+    pub timestamp: u64,
+}
+
+/// Start polling for `state.live` subscribers, `state.balances` waiters and
+/// webhooks.
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut cursor = None;
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            if !has_listeners(&state) && state.webhooks.is_empty() {
+                cursor = None;
+                continue;
+            }
+            match poll(&state, cursor).await {
+                Ok(next) => cursor = Some(next),
+                Err(e) => tracing::warn!("Live update poll failed: {:?}", e),
+            }
+        }
+    });
+}
+
+/// Publish the transfers after `cursor` (or note the newest one if there
+/// is no cursor yet), returning the next cursor.
+async fn poll(state: &AppState, cursor: Option<u64>) -> Result<u64, ClientError> {
+    let client = state.pool.get();
+    let Some(cursor) = cursor else {
+        let newest = client
+            .query_transfers(filter(0, 1, QueryFilterFlags::REVERSED))
+            .await?;
+        return Ok(newest.first().map_or(0, |t| t.timestamp + 1));
+    };
+
+    let transfers = client
+        .query_transfers(filter(cursor, PAGE_SIZE, QueryFilterFlags::empty()))
+        .await?;
+    let Some(last) = transfers.last() else {
+        return Ok(cursor);
+    };
+    let next = last.timestamp + 1;
+
+    state.webhooks.publish(&transfers);
+    if !has_listeners(state) {
+        return Ok(next);
+    }
+
+    // The newest transfer touching each account; transfers come oldest first.
+    let mut touched: BTreeMap<u128, u64> = BTreeMap::new();
+    for transfer in &transfers {
+        touched.insert(transfer.debit_account_id, transfer.timestamp);
+        touched.insert(transfer.credit_account_id, transfer.timestamp);
+    }
+    let ids: Vec<u128> = touched.keys().copied().collect();
+    let accounts = client.lookup_accounts(&ids).await?;
+
+    if state.balances.receiver_count() > 0 {
+        let changes: Arc<[BalanceChange]> = accounts
+            .iter()
+            .map(|account| BalanceChange {
+                account: *account,
+                timestamp: touched[&account.id],
+            })
+            .collect();
+        let _ = state.balances.send(changes);
+    }
+    if state.live.receiver_count() == 0 {
+        return Ok(next);
+    }
+
+    // Sending fails only when everyone has gone, which the next poll sees.
+    for transfer in &transfers {
+        let _ = state.live.send(event(&LiveEvent::Transfer {
+            transfer: ApiTransfer::from(transfer).with_display(&state.config.currencies),
+        }));
+    }
+    for account in &accounts {
+        let _ = state.live.send(event(&LiveEvent::Account {
+            account: ApiAccount::from(account).with_display(&state.config.currencies),
+        }));
+    }
+    Ok(next)
+}
+
+/// Whether anyone wants the balances of the accounts new transfers touch.
+fn has_listeners(state: &AppState) -> bool {
+    state.live.receiver_count() > 0 || state.balances.receiver_count() > 0
+}
+
+fn filter(timestamp_min: u64, limit: u32, flags: QueryFilterFlags) -> QueryFilter {
+    QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger: 0,
+        code: 0,
+        timestamp_min,
+        timestamp_max: 0,
+        limit,
+        flags,
+        reserved: [0; 6],
+    }
+}
+
+fn event(event: &LiveEvent) -> Arc<str> {
+    serde_json::to_string(event)
+        .expect("API types serialize")
+        .into()
+}
+
+/// Subscribe to live events, as JSON text.
+pub fn subscribe(state: &AppState) -> broadcast::Receiver<Arc<str>> {
+    state.live.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, account, transfer, FakeCluster};
+    use broadcast::error::TryRecvError;
+
+    /// Each event as its type and the ID of its transfer or account.
+    fn events(rx: &mut broadcast::Receiver<Arc<str>>) -> Vec<(String, u128)> {
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            let event: serde_json::Value = serde_json::from_str(&event).unwrap();
+            let kind = event["type"].as_str().unwrap().to_string();
+            let id = event[&kind]["id"].as_str().unwrap();
+            events.push((kind, u128::from_str_radix(id, 16).unwrap()));
+        }
+        events
+    }
+
+    fn timestamp(cluster: &FakeCluster, id: u128) -> u64 {
+        cluster.lookup_transfers(&[id]).unwrap()[0].timestamp
+    }
+
+    #[tokio::test]
+    async fn test_poll_starts_after_newest() {
+        let cluster = FakeCluster::new();
+        let state = testing::state(testing::config(), &cluster);
+        let mut rx = subscribe(&state);
+        assert_eq!(poll(&state, None).await.unwrap(), 0);
+
+        cluster.seed(
+            &[account(1, 1), account(2, 1)],
+            &[transfer(10, 1, 2, 5, 1), transfer(11, 1, 2, 5, 1)],
+        );
+        // What came before the first poll is not replayed.
+        let cursor = poll(&state, None).await.unwrap();
+        assert_eq!(cursor, timestamp(&cluster, 11) + 1);
+        assert!(events(&mut rx).is_empty());
+        assert_eq!(poll(&state, Some(cursor)).await.unwrap(), cursor);
+        assert!(events(&mut rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_poll_publishes() {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1), account(2, 1), account(3, 1)], &[]);
+        let state = testing::state(testing::config(), &cluster);
+        let mut rx = subscribe(&state);
+        let mut balances = state.balances.subscribe();
+        let cursor = poll(&state, None).await.unwrap();
+
+        cluster.seed(&[], &[transfer(10, 1, 2, 5, 1), transfer(11, 3, 1, 2, 1)]);
+        let cursor = poll(&state, Some(cursor)).await.unwrap();
+        assert_eq!(cursor, timestamp(&cluster, 11) + 1);
+        // Each transfer, oldest first, then each account they touched.
+        let expected = [
+            ("transfer", 10),
+            ("transfer", 11),
+            ("account", 1),
+            ("account", 2),
+            ("account", 3),
+        ];
+        let expected: Vec<(String, u128)> = expected
+            .into_iter()
+            .map(|(kind, id)| (kind.to_string(), id))
+            .collect();
+        assert_eq!(events(&mut rx), expected);
+
+        // Balances after the page, with the newest transfer touching each.
+        let changes = balances.try_recv().unwrap();
+        let changes: Vec<(u128, u64, u128)> = changes
+            .iter()
+            .map(|c| (c.account.id, c.timestamp, c.account.debits_posted))
+            .collect();
+        let (first, second) = (timestamp(&cluster, 10), timestamp(&cluster, 11));
+        assert_eq!(changes, [(1, second, 5), (2, first, 0), (3, second, 2)]);
+
+        // Nothing new, nothing sent.
+        assert_eq!(poll(&state, Some(cursor)).await.unwrap(), cursor);
+        assert!(events(&mut rx).is_empty());
+        assert!(matches!(balances.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_poll_without_listeners() {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1), account(2, 1)], &[]);
+        let state = testing::state(testing::config(), &cluster);
+        let cursor = poll(&state, None).await.unwrap();
+        cluster.seed(&[], &[transfer(10, 1, 2, 5, 1)]);
+
+        // The cursor moves on, so a later subscriber gets only what is
+        // newer still.
+        let cursor = poll(&state, Some(cursor)).await.unwrap();
+        assert_eq!(cursor, timestamp(&cluster, 10) + 1);
+        let mut rx = subscribe(&state);
+        cluster.seed(&[], &[transfer(11, 1, 2, 5, 1)]);
+        poll(&state, Some(cursor)).await.unwrap();
+        assert_eq!(
+            events(&mut rx),
+            [
+                ("transfer".to_string(), 11),
+                ("account".to_string(), 1),
+                ("account".to_string(), 2),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poll_error() {
+        let cluster = FakeCluster::new();
+        let state = testing::state(testing::config(), &cluster);
+        let mut rx = subscribe(&state);
+        cluster.fail_next(ClientError::Shutdown);
+        assert!(matches!(
+            poll(&state, Some(1)).await,
+            Err(ClientError::Shutdown)
+        ));
+        assert!(events(&mut rx).is_empty());
+    }
+}
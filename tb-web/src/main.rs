@@ -1,107 +1,519 @@
 //! tb-web: Web interface for TigerBeetle.
 
-use axum::routing::get;
-use axum::Router;
+use axum::middleware;
+use chrono_tz::Tz;
 use clap::Parser;
 use std::net::SocketAddr;
-use tower_http::compression::CompressionLayer;
+use std::time::Duration;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::{CompressionLayer, CompressionLevel};
 use tower_http::cors::CorsLayer;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
+mod access_log;
+mod aliases;
 mod api;
+mod auth;
+mod cache;
 mod config;
+mod currency;
 mod error;
+mod etag;
+mod graphql;
+mod grpc;
 mod html;
+mod live;
+mod otel;
+mod paging;
+mod queries;
+mod recent;
 mod routes;
 mod state;
+mod stats;
+#[cfg(test)]
+mod testing;
+mod timestamps;
+mod tls;
 mod transport;
+mod validate;
+mod warm;
+mod webhooks;
+mod zip;
 
+use access_log::AccessLog;
+use auth::{OidcConfig, Role};
 use config::Config;
+use currency::{Currencies, Currency};
+use otel::OtlpLayer;
 use state::AppState;
+use std::path::PathBuf;
+use std::sync::Arc;
+use timestamps::Timestamps;
+use tls::{Tls, TlsConfig};
+use webhooks::WebhookConfig;
 
 /// Web interface for TigerBeetle.
+///
+/// Every flag can also be set by a `TB_WEB_*` environment variable or in
+/// the `--config` file (see [`config`]).
 #[derive(Parser, Debug)]
 #[command(name = "tb-web")]
 #[command(about = "Web interface for TigerBeetle", long_about = None)]
+#[command(args_override_self = true)]
 struct Args {
+    /// TOML file setting any of these flags; flags and environment
+    /// variables override it.
+    #[arg(long, value_name = "PATH", env = "TB_WEB_CONFIG")]
+    config: Option<String>,
+
     /// Address to bind the web server.
-    #[arg(long, default_value = "127.0.0.1:8080")]
+    #[arg(long, env = "TB_WEB_ADDRESS", default_value = "127.0.0.1:8080")]
     address: String,
 
-    /// TigerBeetle cluster address.
-    #[arg(long, default_value = "127.0.0.1:3000")]
+    /// PEM certificate chain to serve HTTPS with, leaf first; reloaded on
+    /// SIGHUP.
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "TB_WEB_TLS_CERT",
+        requires = "tls_key"
+    )]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert.
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "TB_WEB_TLS_KEY",
+        requires = "tls_cert"
+    )]
+    tls_key: Option<PathBuf>,
+
+    /// TigerBeetle replica addresses, comma-separated, one for every
+    /// replica in the cluster: IPs, with port 3001 if left out, or
+    /// hostnames with a port.
+    #[arg(long, env = "TB_WEB_TB_ADDRESS", default_value = "127.0.0.1:3000")]
     tb_address: String,
 
     /// TigerBeetle cluster ID.
-    #[arg(long, default_value = "0")]
+    #[arg(long, env = "TB_WEB_CLUSTER_ID", default_value = "0")]
     cluster_id: u128,
 
+    /// TigerBeetle clients to share requests between, each with its own
+    /// session, so one slow query does not hold up the rest.
+    #[arg(
+        long,
+        env = "TB_WEB_CLIENTS",
+        default_value = "4",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    clients: u32,
+
+    /// Seconds since the last successful TigerBeetle operation after which
+    /// /readyz reports not ready (it tries one itself first).
+    #[arg(
+        long,
+        env = "TB_WEB_READY_MAX_OP_AGE_SECS",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    ready_max_op_age_secs: u64,
+
+    /// Seconds a request may take before it fails with 504 and its queued
+    /// TigerBeetle work is dropped (0 disables). Imports and seeding are
+    /// exempt.
+    #[arg(long, env = "TB_WEB_REQUEST_TIMEOUT_SECS", default_value = "30")]
+    request_timeout_secs: u64,
+
+    /// How long to cache account lookups, in milliseconds (0 disables).
+    #[arg(long, env = "TB_WEB_ACCOUNTS_CACHE_TTL_MS", default_value = "1000")]
+    accounts_cache_ttl_ms: u64,
+
+    /// How long to cache transfer listings, in milliseconds (0 disables).
+    #[arg(long, env = "TB_WEB_TRANSFERS_CACHE_TTL_MS", default_value = "1000")]
+    transfers_cache_ttl_ms: u64,
+
+    /// How often to refresh the dashboard statistics, in seconds (0
+    /// disables). Each refresh reads the accounts and transfers created
+    /// since the one before; the first reads them all.
+    #[arg(long, env = "TB_WEB_STATS_REFRESH_SECS", default_value = "60")]
+    stats_refresh_secs: u64,
+
+    /// Keep the newest transfers and the first accounts on LEDGER in
+    /// memory, so the lists opening on them render without waiting on
+    /// TigerBeetle; 0 for the unfiltered lists (repeatable).
+    #[arg(
+        long = "warm-ledger",
+        value_name = "LEDGER",
+        env = "TB_WEB_WARM_LEDGER",
+        value_delimiter = ','
+    )]
+    warm_ledgers: Vec<u32>,
+
+    /// Transfers kept warm per --warm-ledger.
+    #[arg(
+        long,
+        env = "TB_WEB_WARM_TRANSFERS",
+        default_value = "1000",
+        value_parser = clap::value_parser!(u32).range(1..=validate::LIMIT_MAX as i64)
+    )]
+    warm_transfers: u32,
+
+    /// Accounts kept warm per --warm-ledger.
+    #[arg(
+        long,
+        env = "TB_WEB_WARM_ACCOUNTS",
+        default_value = "1000",
+        value_parser = clap::value_parser!(u32).range(1..=validate::LIMIT_MAX as i64)
+    )]
+    warm_accounts: u32,
+
+    /// OIDC issuer URL; when set, API requests need a bearer token from it.
+    #[arg(long, env = "TB_WEB_OIDC_ISSUER", requires = "oidc_audience")]
+    oidc_issuer: Option<String>,
+
+    /// Audience the bearer tokens must be issued for.
+    #[arg(long, env = "TB_WEB_OIDC_AUDIENCE", requires = "oidc_issuer")]
+    oidc_audience: Option<String>,
+
+    /// Token claim listing the caller's roles or groups (dots for nested claims).
+    #[arg(long, env = "TB_WEB_OIDC_ROLES_CLAIM", default_value = "roles")]
+    oidc_roles_claim: String,
+
+    /// Grant ROLE (viewer, operator or admin) to tokens whose roles claim
+    /// contains VALUE, as VALUE=ROLE (repeatable). By default, the claim
+    /// values viewer, operator and admin grant those roles.
+    #[arg(
+        long = "oidc-role",
+        value_name = "VALUE=ROLE",
+        env = "TB_WEB_OIDC_ROLE",
+        value_delimiter = ',',
+        value_parser = parse_role_mapping
+    )]
+    oidc_roles: Vec<(String, Role)>,
+
+    /// Show amounts on LEDGER in currency CODE with SCALE decimal places
+    /// (default 2), as LEDGER=CODE[:SCALE] (repeatable), e.g. 1=USD:2.
+    #[arg(
+        long = "ledger-currency",
+        value_name = "LEDGER=CODE[:SCALE]",
+        env = "TB_WEB_LEDGER_CURRENCY",
+        value_delimiter = ',',
+        value_parser = currency::parse_mapping
+    )]
+    ledger_currencies: Vec<(u32, Currency)>,
+
+    /// Time zone to show timestamps in, as an IANA name, e.g.
+    /// Europe/Amsterdam.
+    #[arg(
+        long,
+        value_name = "ZONE",
+        env = "TB_WEB_DISPLAY_TIMEZONE",
+        default_value = "UTC",
+        value_parser = timestamps::parse_zone
+    )]
+    display_timezone: Tz,
+
+    /// Show timestamps as the time since, e.g. "3m ago", with the full time
+    /// on hover.
+    #[arg(long, env = "TB_WEB_RELATIVE_TIMESTAMPS")]
+    relative_timestamps: bool,
+
+    /// POST new transfers to URL, optionally only those on a ledger, with a
+    /// code or touching an account, as URL[;ledger=N][;code=N][;account=HEX]
+    /// (repeatable).
+    #[arg(
+        long = "webhook",
+        value_name = "URL[;FILTER]",
+        env = "TB_WEB_WEBHOOK",
+        value_delimiter = ',',
+        value_parser = webhooks::parse_webhook,
+        requires = "webhook_secret"
+    )]
+    webhooks: Vec<WebhookConfig>,
+
+    /// Key to sign webhook requests with (HMAC-SHA256).
+    #[arg(long, value_name = "SECRET", env = "TB_WEB_WEBHOOK_SECRET")]
+    webhook_secret: Option<String>,
+
+    /// Most transfers one /api/v1/graph request may aggregate.
+    #[arg(
+        long,
+        env = "TB_WEB_GRAPH_TRANSFERS_MAX",
+        default_value = "100000",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    graph_transfers_max: u32,
+
+    /// Most transfers one pending transfer listing may scan, newest first.
+    #[arg(
+        long,
+        env = "TB_WEB_PENDING_SCAN_MAX",
+        default_value = "100000",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pending_scan_max: u32,
+
+    /// Most points one balance chart request may ask for.
+    #[arg(
+        long,
+        env = "TB_WEB_CHART_POINTS_MAX",
+        default_value = "2000",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    chart_points_max: u32,
+
+    /// Most rows one /api/v1/import upload may hold.
+    #[arg(
+        long,
+        env = "TB_WEB_IMPORT_ROWS_MAX",
+        default_value = "1000000",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    import_rows_max: u64,
+
+    /// Most rows whose results an import report lists; the rest are only
+    /// counted.
+    #[arg(long, env = "TB_WEB_IMPORT_REPORT_ROWS_MAX", default_value = "100000")]
+    import_report_rows_max: u64,
+
+    /// Largest /api/v1/import upload to accept, in bytes (413 beyond).
+    #[arg(long, env = "TB_WEB_IMPORT_MAX_BYTES", default_value = "1073741824")]
+    import_max_bytes: usize,
+
+    /// Largest request body to accept, in bytes (413 beyond). Imports have
+    /// their own limit, --import-max-bytes.
+    #[arg(long, env = "TB_WEB_MAX_BODY_BYTES", default_value = "4194304")]
+    max_body_bytes: usize,
+
+    /// Most accounts or transfers one create request may carry (413
+    /// beyond); at most as many as fit in the cluster's batch size limit,
+    /// the default.
+    #[arg(
+        long,
+        env = "TB_WEB_MAX_BATCH_EVENTS",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    max_batch_events: Option<u32>,
+
+    /// SQLite database of account aliases, created if missing (default: no
+    /// aliases).
+    #[arg(long, value_name = "PATH", env = "TB_WEB_ALIASES_DB")]
+    aliases_db: Option<PathBuf>,
+
+    /// SQLite database of saved queries, created if missing (default: no
+    /// saved queries).
+    #[arg(long, value_name = "PATH", env = "TB_WEB_QUERIES_DB")]
+    queries_db: Option<PathBuf>,
+
+    /// Serve no endpoints that create accounts or transfers.
+    #[arg(long, env = "TB_WEB_READ_ONLY")]
+    read_only: bool,
+
+    /// Serve no live update WebSocket or balance long-polls.
+    #[arg(long, env = "TB_WEB_DISABLE_LIVE")]
+    disable_live: bool,
+
+    /// Serve no NDJSON exports.
+    #[arg(long, env = "TB_WEB_DISABLE_EXPORT")]
+    disable_export: bool,
+
+    /// Serve POST /api/v1/seed, which fills a ledger with demo accounts and
+    /// transfers (for dev clusters; ignored with --read-only).
+    #[arg(long, env = "TB_WEB_ENABLE_SEEDING")]
+    enable_seeding: bool,
+
+    /// How hard to compress responses (brotli or gzip, as the client
+    /// prefers): fastest, default, best, or a level for the encoding
+    /// (brotli 0-11, gzip 0-9).
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        env = "TB_WEB_COMPRESSION_LEVEL",
+        default_value = "default",
+        value_parser = parse_compression_level
+    )]
+    compression_level: CompressionLevel,
+
+    /// Smallest response to compress, in bytes. Streamed responses, whose
+    /// size is unknown, are always compressed.
+    #[arg(
+        long,
+        value_name = "BYTES",
+        env = "TB_WEB_COMPRESSION_MIN_SIZE",
+        default_value = "32"
+    )]
+    compression_min_size: u16,
+
+    /// Log one in every N successful requests (0: only errors).
+    #[arg(
+        long,
+        value_name = "N",
+        env = "TB_WEB_ACCESS_LOG_SAMPLE",
+        default_value = "1"
+    )]
+    access_log_sample: u64,
+
     /// Log level (trace, debug, info, warn, error).
-    #[arg(long, default_value = "info")]
+    #[arg(long, env = "TB_WEB_LOG_LEVEL", default_value = "info")]
     log_level: String,
+
+    /// OTLP/HTTP collector to send request and TigerBeetle operation
+    /// traces to (e.g. http://localhost:4318).
+    #[arg(long, env = "TB_WEB_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Service name to report traces under.
+    #[arg(long, env = "TB_WEB_OTLP_SERVICE_NAME", default_value = "tb-web")]
+    otlp_service_name: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    let args = Args::parse_from(config::expand(std::env::args_os().collect())?);
+
+    // Validate before anything starts, naming the setting at fault.
+    let address: SocketAddr = args
+        .address
+        .parse()
+        .map_err(|e| format!("Invalid --address '{}': {}", args.address, e))?;
+    let replica_count = tb_rs::replica_count(&args.tb_address)
+        .map_err(|e| format!("Invalid --tb-address '{}': {}", args.tb_address, e))?;
+    let log_filter = tracing_subscriber::EnvFilter::try_new(&args.log_level)
+        .map_err(|e| format!("Invalid --log-level '{}': {}", args.log_level, e))?;
 
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&args.log_level)),
+    let otlp = args
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| OtlpLayer::spawn(endpoint, args.otlp_service_name.clone()))
+        .transpose()?;
+
+    // Initialize logging, and tracing if enabled. Traced spans stay out of
+    // the log.
+    let log_filter = tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or(log_filter);
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_filter(log_filter)
+                .with_filter(filter_fn(|metadata| !otel::is_traced(metadata))),
         )
+        .with(otlp.map(OtlpLayer::filtered))
         .init();
 
-    // Parse addresses
-    let address: SocketAddr = args.address.parse()?;
-    let tb_address: SocketAddr = args.tb_address.parse()?;
-
     let config = Config {
         address,
-        tb_address,
+        tls: args
+            .tls_cert
+            .zip(args.tls_key)
+            .map(|(cert, key)| TlsConfig { cert, key }),
+        tb_address: args.tb_address,
+        replica_count,
         cluster_id: args.cluster_id,
+        clients: args.clients,
+        ready_max_op_age: Duration::from_secs(args.ready_max_op_age_secs),
+        request_timeout: Duration::from_secs(args.request_timeout_secs),
+        accounts_cache_ttl: Duration::from_millis(args.accounts_cache_ttl_ms),
+        transfers_cache_ttl: Duration::from_millis(args.transfers_cache_ttl_ms),
+        stats_refresh: Duration::from_secs(args.stats_refresh_secs),
+        warm_ledgers: args.warm_ledgers,
+        warm_transfers: args.warm_transfers,
+        warm_accounts: args.warm_accounts,
+        oidc: args.oidc_issuer.map(|issuer| OidcConfig {
+            issuer,
+            audience: args.oidc_audience.unwrap_or_default(),
+            roles_claim: args.oidc_roles_claim,
+            role_map: args.oidc_roles,
+        }),
+        currencies: Currencies::new(args.ledger_currencies),
+        timestamps: Timestamps::new(args.display_timezone, args.relative_timestamps),
+        webhooks: args.webhooks,
+        webhook_secret: args.webhook_secret.unwrap_or_default(),
+        graph_transfers_max: args.graph_transfers_max,
+        pending_scan_max: args.pending_scan_max,
+        chart_points_max: args.chart_points_max,
+        import_rows_max: args.import_rows_max,
+        import_report_rows_max: args.import_report_rows_max,
+        import_max_bytes: args.import_max_bytes,
+        max_body_bytes: args.max_body_bytes,
+        max_batch_events: args.max_batch_events,
+        aliases_db: args.aliases_db,
+        queries_db: args.queries_db,
+        read_only: args.read_only,
+        disable_live: args.disable_live,
+        disable_export: args.disable_export,
+        enable_seeding: args.enable_seeding,
     };
 
-    tracing::info!("Connecting to TigerBeetle at {}...", config.tb_address);
+    // Load the certificate before connecting, so a bad one fails fast.
+    let tls = match &config.tls {
+        Some(tls) => Some(Arc::new(Tls::load(tls.clone())?)),
+        None => None,
+    };
 
     // Create application state
     let state = AppState::new(config.clone()).await?;
 
-    // Build router
-    let app = Router::new()
-        // API routes
-        .route("/api/v1/accounts", get(routes::accounts::list_accounts))
-        .route("/api/v1/accounts/{id}", get(routes::accounts::get_account))
-        .route(
-            "/api/v1/accounts/{id}/transfers",
-            get(routes::accounts::get_account_transfers),
-        )
-        .route(
-            "/api/v1/accounts/{id}/balances",
-            get(routes::accounts::get_account_balances),
-        )
-        .route("/api/v1/transfers", get(routes::transfers::list_transfers))
-        .route(
-            "/api/v1/transfers/{id}",
-            get(routes::transfers::get_transfer),
-        )
-        .route("/health", get(routes::health))
-        // Frontend page routes (serve same content, HTMX handles detail loading)
-        .route("/account/{id}", get(routes::frontend::serve_account_page))
-        .route("/transfer/{id}", get(routes::frontend::serve_transfer_page))
-        // Frontend fallback
-        .fallback(routes::frontend::serve_frontend)
-        // State
-        .with_state(state)
+    let app = routes::router(state)
         // Middleware
+        .layer(middleware::from_fn_with_state(
+            AccessLog::new(args.access_log_sample),
+            access_log::log,
+        ))
+        .layer(middleware::from_fn(otel::trace))
         .layer(CorsLayer::permissive())
-        .layer(CompressionLayer::new());
+        // Snapshots are deflated already.
+        .layer(
+            CompressionLayer::new()
+                .quality(args.compression_level)
+                .compress_when(
+                    SizeAbove::new(args.compression_min_size)
+                        .and(NotForContentType::GRPC)
+                        .and(NotForContentType::IMAGES)
+                        .and(NotForContentType::SSE)
+                        .and(NotForContentType::const_new("application/zip")),
+                ),
+        );
 
     // Start server
     let listener = tokio::net::TcpListener::bind(address).await?;
-    tracing::info!("tb-web listening on http://{}", address);
-
-    axum::serve(listener, app).await?;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(tls) => {
+            tls.reload_on_sighup()?;
+            tracing::info!("tb-web listening on https://{}", address);
+            tls::serve(listener, app, tls).await?;
+        }
+        None => {
+            tracing::info!("tb-web listening on http://{}", address);
+            axum::serve(listener, app).await?;
+        }
+    }
 
     Ok(())
 }
+
+/// Parse a `VALUE=ROLE` claim mapping.
+fn parse_role_mapping(s: &str) -> Result<(String, Role), String> {
+    let (value, role) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Expected VALUE=ROLE, got '{}'", s))?;
+    Ok((value.to_string(), role.parse()?))
+}
+
+/// Parse a compression level: `fastest`, `default`, `best` or a number.
+fn parse_compression_level(s: &str) -> Result<CompressionLevel, String> {
+    match s {
+        "fastest" => Ok(CompressionLevel::Fastest),
+        "default" => Ok(CompressionLevel::Default),
+        "best" => Ok(CompressionLevel::Best),
+        _ => s
+            .parse()
+            .ok()
+            .filter(|level| (0..=11).contains(level))
+            .map(CompressionLevel::Precise)
+            .ok_or_else(|| format!("Expected fastest, default, best or 0 to 11, got '{}'", s)),
+    }
+}
@@ -0,0 +1,571 @@
+//! OpenTelemetry trace export.
+//!
+//! With `--otlp-endpoint`, each HTTP request gets a span, and each
+//! TigerBeetle operation it performs gets a child span on the client thread
+//! that ran it, its parent carried there with the request through the
+//! transport channel. Operation spans also record how long the request
+//! waited in the channel, so a slow page can be traced to the call, or the
+//! queue, that made it slow.
+//!
+//! A request with a W3C `traceparent` header continues that trace, and is
+//! not exported if the caller did not sample it. Finished spans are batched
+//! and sent to `<endpoint>/v1/traces` as OTLP JSON; spans that arrive while
+//! the queue is full, and batches that fail to send, are dropped.
+//!
+//! Only spans with the [`TARGET`] target are exported, and those are left
+//! out of the log output. Their `otel.name`, `otel.kind`, `otel.status_code`
+//! and `otel.status_message` fields set the span's name, kind and status
+//! rather than becoming attributes, as with `tracing-opentelemetry`.
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Instrument, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target of the spans to export.
+pub const TARGET: &str = "tb_web::trace";
+
+/// Finished spans waiting to be sent; more are dropped.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Most spans sent in one request.
+const BATCH_MAX: usize = 512;
+
+/// Longest a finished span waits for others to batch with.
+const BATCH_DELAY: Duration = Duration::from_secs(5);
+
+/// Timeout of a request to the collector.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const KIND_INTERNAL: u8 = 1;
+const KIND_SERVER: u8 = 2;
+const KIND_CLIENT: u8 = 3;
+const STATUS_ERROR: u8 = 2;
+
+/// Whether `metadata` is of a span to export.
+pub fn is_traced(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span() && metadata.target() == TARGET
+}
+
+/// Layer recording traced spans and sending them to an OTLP collector.
+pub struct OtlpLayer {
+    tx: mpsc::Sender<ExportSpan>,
+    random: SystemRandom,
+}
+
+impl OtlpLayer {
+    /// Send spans to the collector at `endpoint` (e.g.
+    /// `http://localhost:4318`) as service `service_name`, from a task on
+    /// the current runtime.
+    pub fn spawn(endpoint: &str, service_name: String) -> Result<Self, String> {
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        let url = reqwest::Url::parse(&url)
+            .map_err(|e| format!("Invalid --otlp-endpoint '{}': {}", endpoint, e))?;
+        let http = reqwest::Client::builder()
+            .timeout(EXPORT_TIMEOUT)
+            .build()
+            .expect("HTTP client builds");
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let resource = Resource {
+            attributes: vec![KeyValue::new(
+                "service.name",
+                AnyValue::String(service_name),
+            )],
+        };
+        tokio::spawn(export(http, url, resource, rx));
+        Ok(Self {
+            tx,
+            random: SystemRandom::new(),
+        })
+    }
+
+    /// A layer that sees only the spans to export.
+    pub fn filtered<S>(self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        self.with_filter(tracing_subscriber::filter::filter_fn(is_traced))
+    }
+
+    fn random<const N: usize>(&self) -> [u8; N] {
+        let mut bytes = [0; N];
+        self.random
+            .fill(&mut bytes)
+            .expect("the system has randomness");
+        bytes
+    }
+}
+
+/// What is known of a span while it is open.
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    sampled: bool,
+    name: String,
+    kind: u8,
+    start: SystemTime,
+    status_code: u8,
+    status_message: String,
+    attributes: Vec<KeyValue>,
+    /// The `traceparent` field, until the span is placed in a trace.
+    traceparent: Option<String>,
+}
+
+impl SpanData {
+    fn set(&mut self, key: &str, value: AnyValue) {
+        let text = || match &value {
+            AnyValue::String(text) => text.clone(),
+            _ => String::new(),
+        };
+        match key {
+            "otel.name" => self.name = text(),
+            "otel.kind" => {
+                self.kind = match text().as_str() {
+                    "server" => KIND_SERVER,
+                    "client" => KIND_CLIENT,
+                    _ => KIND_INTERNAL,
+                }
+            }
+            "otel.status_code" if text() == "error" => self.status_code = STATUS_ERROR,
+            "otel.status_message" => self.status_message = text(),
+            "traceparent" => self.traceparent = Some(text()),
+            _ => self.attributes.push(KeyValue::new(key, value)),
+        }
+    }
+}
+
+impl Visit for SpanData {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field.name(), AnyValue::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field.name(), AnyValue::Int(value.to_string()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field.name(), AnyValue::Int(value.to_string()));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field.name(), AnyValue::Double(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field.name(), AnyValue::Bool(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field.name(), AnyValue::String(format!("{:?}", value)));
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut data = SpanData {
+            trace_id: 0,
+            span_id: u64::from_be_bytes(self.random()),
+            parent_span_id: None,
+            sampled: true,
+            name: attrs.metadata().name().to_string(),
+            kind: KIND_INTERNAL,
+            start: SystemTime::now(),
+            status_code: 0,
+            status_message: String::new(),
+            attributes: Vec::new(),
+            traceparent: None,
+        };
+        attrs.record(&mut data);
+
+        let local = span.parent().and_then(|parent| {
+            let extensions = parent.extensions();
+            let parent = extensions.get::<SpanData>()?;
+            Some((parent.trace_id, parent.span_id, parent.sampled))
+        });
+        let remote = || data.traceparent.as_deref().and_then(parse_traceparent);
+        match local.or_else(remote) {
+            Some((trace_id, parent_span_id, sampled)) => {
+                data.trace_id = trace_id;
+                data.parent_span_id = Some(parent_span_id);
+                data.sampled = sampled;
+            }
+            None => data.trace_id = u128::from_be_bytes(self.random()),
+        }
+        data.traceparent = None;
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(data);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        if !data.sampled {
+            return;
+        }
+        let _ = self.tx.try_send(ExportSpan {
+            trace_id: format!("{:032x}", data.trace_id),
+            span_id: format!("{:016x}", data.span_id),
+            parent_span_id: data.parent_span_id.map(|id| format!("{:016x}", id)),
+            name: data.name,
+            kind: data.kind,
+            start_time_unix_nano: unix_nanos(data.start),
+            end_time_unix_nano: unix_nanos(SystemTime::now()),
+            attributes: data.attributes,
+            status: ExportStatus {
+                code: data.status_code,
+                message: data.status_message,
+            },
+        });
+    }
+}
+
+/// The trace ID, parent span ID and sampled flag in a `traceparent` header
+/// value, e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+fn parse_traceparent(value: &str) -> Option<(u128, u64, bool)> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+    if version.len() != 2 || version == "ff" || trace_id.len() != 32 || parent_id.len() != 16 {
+        return None;
+    }
+    // Lowercase hex only, as `from_str_radix` would also take a sign.
+    let is_hex = |part: &str| part.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if flags.len() != 2
+        || ![version, trace_id, parent_id, flags]
+            .into_iter()
+            .all(is_hex)
+    {
+        return None;
+    }
+    // Version 00 has exactly four parts; later ones may add more.
+    if version == "00" && parts.next().is_some() {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+    let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    if trace_id == 0 || parent_id == 0 {
+        return None;
+    }
+    Some((trace_id, parent_id, flags & 1 == 1))
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// Middleware giving each request a span, in the caller's trace if it sent
+/// a `traceparent` header.
+pub async fn trace(request: Request, next: Next) -> Response {
+    let traceparent = request
+        .headers()
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        target: TARGET,
+        "request",
+        otel.name = %request.method(),
+        otel.kind = "server",
+        otel.status_code = tracing::field::Empty,
+        http.request.method = %request.method(),
+        url.path = request.uri().path(),
+        http.response.status_code = tracing::field::Empty,
+        traceparent,
+    );
+    let response = next.run(request).instrument(span.clone()).await;
+    let status = response.status();
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "error");
+    }
+    response
+}
+
+/// Send finished spans to `url` in batches.
+async fn export(
+    http: reqwest::Client,
+    url: reqwest::Url,
+    resource: Resource,
+    mut rx: mpsc::Receiver<ExportSpan>,
+) {
+    while let Some(first) = rx.recv().await {
+        let mut spans = vec![first];
+        let deadline = tokio::time::Instant::now() + BATCH_DELAY;
+        while spans.len() < BATCH_MAX {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(span)) => spans.push(span),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let body = ExportRequest {
+            resource_spans: [ResourceSpans {
+                resource: &resource,
+                scope_spans: [ScopeSpans {
+                    scope: Scope {
+                        name: "tb-web",
+                        version: env!("CARGO_PKG_VERSION"),
+                    },
+                    spans: &spans,
+                }],
+            }],
+        };
+        let result = http
+            .post(url.clone())
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            tracing::warn!("Sending {} spans to {} failed: {}", spans.len(), url, e);
+        }
+    }
+}
+
+/// An OTLP `ExportTraceServiceRequest`, in its JSON encoding.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRequest<'a> {
+    resource_spans: [ResourceSpans<'a>; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans<'a> {
+    resource: &'a Resource,
+    scope_spans: [ScopeSpans<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize)]
+struct ScopeSpans<'a> {
+    scope: Scope,
+    spans: &'a [ExportSpan],
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportSpan {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<String>,
+    name: String,
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    status: ExportStatus,
+}
+
+#[derive(Serialize)]
+struct ExportStatus {
+    code: u8,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    message: String,
+}
+
+#[derive(Serialize)]
+struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+impl KeyValue {
+    fn new(key: &str, value: AnyValue) -> Self {
+        Self {
+            key: key.to_string(),
+            value,
+        }
+    }
+}
+
+/// An attribute value; 64-bit integers are strings in OTLP JSON.
+#[derive(Serialize)]
+enum AnyValue {
+    #[serde(rename = "stringValue")]
+    String(String),
+    #[serde(rename = "intValue")]
+    Int(String),
+    #[serde(rename = "doubleValue")]
+    Double(f64),
+    #[serde(rename = "boolValue")]
+    Bool(bool),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_ID: &str = "00f067aa0ba902b7";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let traceparent = format!("00-{}-{}-01", TRACE_ID, PARENT_ID);
+        assert_eq!(
+            parse_traceparent(&traceparent),
+            Some((0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7, true))
+        );
+        let unsampled = format!("00-{}-{}-00", TRACE_ID, PARENT_ID);
+        assert_eq!(
+            parse_traceparent(&unsampled).map(|(_, _, sampled)| sampled),
+            Some(false)
+        );
+        // Only the sampled bit of the flags counts.
+        let flags = format!("00-{}-{}-fe", TRACE_ID, PARENT_ID);
+        assert_eq!(
+            parse_traceparent(&flags).map(|(_, _, sampled)| sampled),
+            Some(false)
+        );
+        // Later versions may add parts.
+        let later = format!("01-{}-{}-01-extra", TRACE_ID, PARENT_ID);
+        assert!(parse_traceparent(&later).is_some());
+    }
+
+    #[test]
+    fn test_parse_traceparent_invalid() {
+        let zero_trace = "0".repeat(32);
+        let zero_parent = "0".repeat(16);
+        for (version, trace_id, parent_id, flags) in [
+            ("ff", TRACE_ID, PARENT_ID, "01"),
+            ("00", TRACE_ID, PARENT_ID, "01-extra"),
+            ("00", &zero_trace, PARENT_ID, "01"),
+            ("00", TRACE_ID, &zero_parent, "01"),
+            ("0", TRACE_ID, PARENT_ID, "01"),
+            ("00", &TRACE_ID[1..], PARENT_ID, "01"),
+            ("00", TRACE_ID, &PARENT_ID[1..], "01"),
+            ("00", TRACE_ID, PARENT_ID, "1"),
+            ("00", TRACE_ID, PARENT_ID, "011"),
+            ("0g", TRACE_ID, PARENT_ID, "01"),
+            ("00", "+bf92f3577b34da6a3ce929d0e0e4736", PARENT_ID, "01"),
+            ("00", TRACE_ID, "+0f067aa0ba902b7", "01"),
+            ("00", TRACE_ID, PARENT_ID, "+1"),
+            ("00", "4BF92F3577B34DA6A3CE929D0E0E4736", PARENT_ID, "01"),
+            ("00", TRACE_ID, "00f067aa0ba902bz", "01"),
+        ] {
+            let traceparent = format!("{}-{}-{}-{}", version, trace_id, parent_id, flags);
+            assert_eq!(parse_traceparent(&traceparent), None, "{}", traceparent);
+        }
+        assert_eq!(parse_traceparent(""), None);
+        assert_eq!(
+            parse_traceparent(&format!("00-{}-{}", TRACE_ID, PARENT_ID)),
+            None
+        );
+    }
+
+    /// Run `f` with spans going to a layer, returning those exported.
+    fn export(f: impl FnOnce()) -> Vec<ExportSpan> {
+        let (tx, mut rx) = mpsc::channel(QUEUE_CAPACITY);
+        let layer = OtlpLayer {
+            tx,
+            random: SystemRandom::new(),
+        };
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, f);
+        let mut spans = Vec::new();
+        while let Ok(span) = rx.try_recv() {
+            spans.push(span);
+        }
+        spans
+    }
+
+    #[test]
+    fn test_local_parent() {
+        let spans = export(|| {
+            let parent = tracing::info_span!(target: TARGET, "request", otel.kind = "server");
+            let _entered = parent.enter();
+            tracing::info_span!(target: TARGET, "operation", otel.kind = "client").in_scope(|| {});
+        });
+        let [child, parent] = &spans[..] else {
+            panic!("expected two spans");
+        };
+        assert_eq!(
+            (child.name.as_str(), child.kind),
+            ("operation", KIND_CLIENT)
+        );
+        assert_eq!(
+            (parent.name.as_str(), parent.kind),
+            ("request", KIND_SERVER)
+        );
+        assert_eq!(child.trace_id, parent.trace_id);
+        assert_eq!(child.parent_span_id.as_ref(), Some(&parent.span_id));
+        assert_eq!(parent.parent_span_id, None);
+        assert_ne!(child.span_id, parent.span_id);
+        assert_ne!(parent.trace_id, "0".repeat(32));
+    }
+
+    #[test]
+    fn test_remote_parent() {
+        let traceparent = format!("00-{}-{}-01", TRACE_ID, PARENT_ID);
+        let spans = export(|| {
+            let request = tracing::info_span!(target: TARGET, "request", traceparent);
+            let _entered = request.enter();
+            tracing::info_span!(target: TARGET, "operation").in_scope(|| {});
+        });
+        let [child, request] = &spans[..] else {
+            panic!("expected two spans");
+        };
+        assert_eq!(request.trace_id, TRACE_ID);
+        assert_eq!(request.parent_span_id.as_deref(), Some(PARENT_ID));
+        assert_eq!(child.trace_id, TRACE_ID);
+        assert_eq!(child.parent_span_id.as_ref(), Some(&request.span_id));
+        // The header is not an attribute.
+        assert!(request.attributes.is_empty());
+    }
+
+    #[test]
+    fn test_remote_parent_unsampled() {
+        let traceparent = format!("00-{}-{}-00", TRACE_ID, PARENT_ID);
+        let spans = export(|| {
+            let request = tracing::info_span!(target: TARGET, "request", traceparent);
+            let _entered = request.enter();
+            tracing::info_span!(target: TARGET, "operation").in_scope(|| {});
+        });
+        assert!(spans.is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+//! Paging through lists in both directions.
+//!
+//! A list is in timestamp order, newest first if reversed, and a page of it
+//! is asked for by the timestamp of a record next to it: `after_timestamp`
+//! for the page after a record, `before_timestamp` for the page before
+//! one. TigerBeetle only pages forward through a query, so the page before
+//! a record is the page after it in the other order, turned around.
+//!
+//! Each response carries a `prev_cursor` and `next_cursor` to pass back as
+//! `before_timestamp` and `after_timestamp`, when there may be records
+//! there.
+
+/// Which page of a list to get.
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    /// Whether the list is newest first.
+    reversed: bool,
+    after: Option<u64>,
+    before: Option<u64>,
+}
+
+/// Where the pages next to one are, as timestamps to page from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cursors {
+    /// `before_timestamp` of the page before.
+    pub prev: Option<u64>,
+    /// `after_timestamp` of the page after.
+    pub next: Option<u64>,
+}
+
+impl Page {
+    /// The page of a list (newest first if `reversed`) after the record at
+    /// `after` or before the one at `before`, or else the first; at most
+    /// one is given (see [`crate::validate::cursors`]).
+    pub fn new(reversed: bool, after: Option<u64>, before: Option<u64>) -> Self {
+        Self {
+            reversed,
+            after,
+            before,
+        }
+    }
+
+    /// Whether this is the first page.
+    pub fn is_first(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+
+    /// Whether this is the first page or one after it, which is all an
+    /// export can stream.
+    pub fn is_forward(&self) -> bool {
+        self.before.is_none()
+    }
+
+    /// The `timestamp_min` and `timestamp_max` to query, and whether to
+    /// query newest first. A page past either end of time gets a range
+    /// no record is in.
+    pub fn range(&self) -> (u64, u64, bool) {
+        let (bound, reversed) = match (self.after, self.before) {
+            (Some(after), _) => (Some(after), self.reversed),
+            (None, Some(before)) => (Some(before), !self.reversed),
+            (None, None) => (None, self.reversed),
+        };
+        match bound {
+            None => (0, 0, reversed),
+            // Zero would lift the bound instead.
+            Some(t) if reversed && t <= 1 => (u64::MAX, u64::MAX, true),
+            Some(t) if reversed => (0, t - 1, true),
+            Some(t) => (t.saturating_add(1), 0, false),
+        }
+    }
+
+    /// Put `records`, queried over [`Page::range`], in list order.
+    pub fn order<T>(&self, records: &mut [T]) {
+        if self.before.is_some() {
+            records.reverse();
+        }
+    }
+
+    /// The cursors around a page whose first and last records are at
+    /// `first` and `last`; `full` if it holds as many as asked for, so more
+    /// may follow in the direction it was paged.
+    pub fn cursors(&self, first: Option<u64>, last: Option<u64>, full: bool) -> Cursors {
+        // A step from `t` on or back in list order, so that paging from
+        // there includes `t`.
+        let step = |t: u64, on: bool| {
+            if on != self.reversed {
+                t.saturating_add(1)
+            } else {
+                t.saturating_sub(1)
+            }
+        };
+        match self.before {
+            // Paged forward: the records before exist if it didn't start
+            // at the beginning. On an empty page, go back to the last one.
+            None => Cursors {
+                prev: self.after.and(first).or(self.after.map(|t| step(t, true))),
+                next: last.filter(|_| full),
+            },
+            // Paged backward: the records after it exist.
+            Some(t) => Cursors {
+                prev: first.filter(|_| full),
+                next: last.or(Some(step(t, false))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: u64 = u64::MAX;
+
+    #[test]
+    fn test_is_first_and_forward() {
+        assert!(Page::new(false, None, None).is_first());
+        assert!(Page::new(true, None, None).is_forward());
+        assert!(!Page::new(false, Some(1), None).is_first());
+        assert!(Page::new(false, Some(1), None).is_forward());
+        assert!(!Page::new(false, None, Some(1)).is_first());
+        assert!(!Page::new(false, None, Some(1)).is_forward());
+    }
+
+    #[test]
+    fn test_range() {
+        for (reversed, after, before, range) in [
+            // First pages.
+            (false, None, None, (0, 0, false)),
+            (true, None, None, (0, 0, true)),
+            // After a record, in list order.
+            (false, Some(10), None, (11, 0, false)),
+            (true, Some(10), None, (0, 9, true)),
+            (true, Some(2), None, (0, 1, true)),
+            // Before a record, in the other order.
+            (false, None, Some(10), (0, 9, true)),
+            (true, None, Some(10), (11, 0, false)),
+            // Past the start of time.
+            (true, Some(1), None, (MAX, MAX, true)),
+            (true, Some(0), None, (MAX, MAX, true)),
+            (false, None, Some(1), (MAX, MAX, true)),
+            (false, None, Some(0), (MAX, MAX, true)),
+            // Past the end of time.
+            (false, Some(MAX), None, (MAX, 0, false)),
+            (true, None, Some(MAX), (MAX, 0, false)),
+        ] {
+            let page = Page::new(reversed, after, before);
+            assert_eq!(page.range(), range, "{:?}", page);
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let mut records = [1, 2, 3];
+        Page::new(true, Some(5), None).order(&mut records);
+        assert_eq!(records, [1, 2, 3]);
+        Page::new(true, None, Some(5)).order(&mut records);
+        assert_eq!(records, [3, 2, 1]);
+        let mut empty: [u64; 0] = [];
+        Page::new(false, None, Some(5)).order(&mut empty);
+    }
+
+    #[test]
+    fn test_cursors() {
+        // (reversed, after, before), (first, last, full), (prev, next)
+        for ((reversed, after, before), (first, last, full), (prev, next)) in [
+            // First page: nothing before it.
+            (
+                (false, None, None),
+                (Some(1), Some(5), true),
+                (None, Some(5)),
+            ),
+            ((false, None, None), (Some(1), Some(3), false), (None, None)),
+            ((false, None, None), (None, None, false), (None, None)),
+            (
+                (true, None, None),
+                (Some(9), Some(5), true),
+                (None, Some(5)),
+            ),
+            // Paged forward.
+            (
+                (false, Some(10), None),
+                (Some(11), Some(15), true),
+                (Some(11), Some(15)),
+            ),
+            (
+                (false, Some(10), None),
+                (Some(11), Some(12), false),
+                (Some(11), None),
+            ),
+            (
+                (true, Some(10), None),
+                (Some(9), Some(5), true),
+                (Some(9), Some(5)),
+            ),
+            (
+                (true, Some(10), None),
+                (Some(9), Some(8), false),
+                (Some(9), None),
+            ),
+            // Paged forward onto an empty page: back to include the record.
+            (
+                (false, Some(10), None),
+                (None, None, false),
+                (Some(11), None),
+            ),
+            ((true, Some(10), None), (None, None, false), (Some(9), None)),
+            (
+                (false, Some(MAX), None),
+                (None, None, false),
+                (Some(MAX), None),
+            ),
+            ((true, Some(0), None), (None, None, false), (Some(0), None)),
+            // Paged backward.
+            (
+                (false, None, Some(10)),
+                (Some(5), Some(9), true),
+                (Some(5), Some(9)),
+            ),
+            (
+                (false, None, Some(10)),
+                (Some(8), Some(9), false),
+                (None, Some(9)),
+            ),
+            (
+                (true, None, Some(10)),
+                (Some(15), Some(11), true),
+                (Some(15), Some(11)),
+            ),
+            (
+                (true, None, Some(10)),
+                (Some(12), Some(11), false),
+                (None, Some(11)),
+            ),
+            // Paged backward onto an empty page: on to include the record.
+            (
+                (false, None, Some(10)),
+                (None, None, false),
+                (None, Some(9)),
+            ),
+            (
+                (true, None, Some(10)),
+                (None, None, false),
+                (None, Some(11)),
+            ),
+            ((false, None, Some(0)), (None, None, false), (None, Some(0))),
+            (
+                (true, None, Some(MAX)),
+                (None, None, false),
+                (None, Some(MAX)),
+            ),
+        ] {
+            let page = Page::new(reversed, after, before);
+            let cursors = page.cursors(first, last, full);
+            assert_eq!(
+                (cursors.prev, cursors.next),
+                (prev, next),
+                "{:?} {:?}",
+                page,
+                (first, last, full)
+            );
+        }
+    }
+}
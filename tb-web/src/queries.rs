@@ -0,0 +1,589 @@
+//! Saved queries.
+//!
+//! With `--queries-db`, tb-web keeps named filters in a SQLite database.
+//! A saved query is richer than the list endpoints' parameters: a list of
+//! codes, a time range, `user_data` values, and for transfers an account
+//! and direction. Running one turns it into the TigerBeetle queries that
+//! answer it, one per code since a filter takes a single code, and merges
+//! their results by timestamp.
+//!
+//! Like [`crate::aliases`], every query is held in memory and the database
+//! is written through on each change.
+
+use crate::api::{Direction, QueryKind, SavedQuery};
+use crate::error::AppError;
+use crate::transport::ClientPool;
+use crate::validate::{self, LIMIT_MAX};
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use tb_rs::{Account, AccountFilter, AccountFilterFlags, QueryFilter, QueryFilterFlags, Transfer};
+
+/// Longest query name, in characters.
+pub const NAME_LEN_MAX: usize = 64;
+
+/// Most codes one query may list, each costing a TigerBeetle query.
+pub const CODES_MAX: usize = 16;
+
+/// The registry, or a stand-in without a database that holds nothing.
+pub struct Queries {
+    registry: Option<Arc<Registry>>,
+}
+
+struct Registry {
+    db: Mutex<Connection>,
+    by_name: RwLock<BTreeMap<String, SavedQuery>>,
+}
+
+impl Queries {
+    /// Open (creating if need be) the database at `path`.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let error = |e: rusqlite::Error| format!("{}: {}", path.display(), e);
+        let db = Connection::open(path).map_err(error)?;
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS queries (
+                name TEXT PRIMARY KEY,
+                query TEXT NOT NULL
+            )",
+        )
+        .map_err(error)?;
+
+        let mut by_name = BTreeMap::new();
+        {
+            let mut select = db
+                .prepare("SELECT name, query FROM queries")
+                .map_err(error)?;
+            let rows = select
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(error)?;
+            for row in rows {
+                let (name, query) = row.map_err(error)?;
+                let query = serde_json::from_str(&query)
+                    .map_err(|e| format!("{}: bad query '{}': {}", path.display(), name, e))?;
+                by_name.insert(name, query);
+            }
+        }
+
+        Ok(Self {
+            registry: Some(Arc::new(Registry {
+                db: Mutex::new(db),
+                by_name: RwLock::new(by_name),
+            })),
+        })
+    }
+
+    /// A registry that holds nothing, for when there is no database.
+    pub fn disabled() -> Self {
+        Self { registry: None }
+    }
+
+    /// The query saved as `name`.
+    pub fn get(&self, name: &str) -> Option<SavedQuery> {
+        let registry = self.registry.as_ref()?;
+        registry.by_name.read().unwrap().get(name).cloned()
+    }
+
+    /// Every query, by name.
+    pub fn list(&self) -> Vec<SavedQuery> {
+        let Some(registry) = &self.registry else {
+            return Vec::new();
+        };
+        registry.by_name.read().unwrap().values().cloned().collect()
+    }
+
+    /// Save `query` under its name, returning whether it replaced one.
+    pub async fn set(&self, query: SavedQuery) -> Result<bool, AppError> {
+        let registry = self.registry()?;
+        tokio::task::spawn_blocking(move || {
+            let db = registry.db.lock().unwrap();
+            let json =
+                serde_json::to_string(&query).map_err(|e| AppError::Internal(e.to_string()))?;
+            db.execute(
+                "INSERT INTO queries (name, query) VALUES (?1, ?2)
+                 ON CONFLICT (name) DO UPDATE SET query = ?2",
+                params![query.name, json],
+            )
+            .map_err(|e| AppError::Internal(format!("Saving query failed: {}", e)))?;
+            let mut by_name = registry.by_name.write().unwrap();
+            Ok(by_name.insert(query.name.clone(), query).is_some())
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+    }
+
+    /// Remove the query saved as `name`, returning whether there was one.
+    pub async fn remove(&self, name: String) -> Result<bool, AppError> {
+        let registry = self.registry()?;
+        tokio::task::spawn_blocking(move || {
+            let db = registry.db.lock().unwrap();
+            db.execute("DELETE FROM queries WHERE name = ?1", params![name])
+                .map_err(|e| AppError::Internal(format!("Removing query failed: {}", e)))?;
+            Ok(registry.by_name.write().unwrap().remove(&name).is_some())
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+    }
+
+    fn registry(&self) -> Result<Arc<Registry>, AppError> {
+        self.registry
+            .clone()
+            .ok_or_else(|| AppError::Internal("No query database configured".to_string()))
+    }
+}
+
+/// The TigerBeetle queries that answer a saved query.
+pub enum Plan {
+    Accounts(Vec<QueryFilter>),
+    Transfers(Vec<QueryFilter>),
+    /// Transfers of one account, which carry its ledger; any other ledger
+    /// matches none of them.
+    AccountTransfers {
+        filters: Vec<AccountFilter>,
+        ledger: Option<u32>,
+    },
+}
+
+/// Records found by running a query.
+pub enum Results {
+    Accounts(Vec<Account>),
+    Transfers(Vec<Transfer>),
+}
+
+/// Check `query` and turn it into filters, resuming after the record with
+/// timestamp `after` (in the query's order) if given; `None` if no record
+/// can come after it.
+pub fn plan(query: &SavedQuery, after: Option<u64>) -> Result<Option<Plan>, String> {
+    if query.name.is_empty()
+        || query.name.chars().count() > NAME_LEN_MAX
+        || !query
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "name: must be 1 to {} letters, digits, '-', '_' or '.'",
+            NAME_LEN_MAX
+        ));
+    }
+    if query.limit == 0 || query.limit > LIMIT_MAX {
+        return Err(format!("limit: must be 1 to {}", LIMIT_MAX));
+    }
+    if query.codes.len() > CODES_MAX {
+        return Err(format!("codes: at most {}", CODES_MAX));
+    }
+    if query.account.is_some() && query.kind != QueryKind::Transfers {
+        return Err("account: only transfers can be queried by account".to_string());
+    }
+    if query.account.is_none() && query.direction != Direction::Both {
+        return Err("direction: needs an account".to_string());
+    }
+
+    let mut timestamp_min = query.timestamp_min.unwrap_or(0);
+    let mut timestamp_max = query.timestamp_max.unwrap_or(0);
+    if timestamp_max != 0 && timestamp_min > timestamp_max {
+        return Err("timestamp_min: after timestamp_max".to_string());
+    }
+    if let Some(after) = after {
+        if query.reversed {
+            // Zero would lift the bound instead.
+            if after <= 1 {
+                return Ok(None);
+            }
+            timestamp_max = match timestamp_max {
+                0 => after - 1,
+                max => max.min(after - 1),
+            };
+        } else {
+            timestamp_min = timestamp_min.max(after.saturating_add(1));
+        }
+        if timestamp_max != 0 && timestamp_min > timestamp_max {
+            return Ok(None);
+        }
+    }
+
+    let user_data_128 = match &query.user_data_128 {
+        Some(value) => validate::hex_id("user_data_128", value)?,
+        None => 0,
+    };
+
+    // Codes once each, or zero for any code.
+    let mut codes = query.codes.clone();
+    codes.sort_unstable();
+    codes.dedup();
+    if codes.is_empty() {
+        codes.push(0);
+    }
+
+    if let Some(account) = &query.account {
+        let account_id = validate::hex_id("account", account)?;
+        let mut flags = match query.direction {
+            Direction::Debits => AccountFilterFlags::DEBITS,
+            Direction::Credits => AccountFilterFlags::CREDITS,
+            Direction::Both => AccountFilterFlags::DEBITS | AccountFilterFlags::CREDITS,
+        };
+        if query.reversed {
+            flags |= AccountFilterFlags::REVERSED;
+        }
+        let filters = codes
+            .iter()
+            .map(|&code| AccountFilter {
+                account_id,
+                user_data_128,
+                user_data_64: query.user_data_64.unwrap_or(0),
+                user_data_32: query.user_data_32.unwrap_or(0),
+                code,
+                timestamp_min,
+                timestamp_max,
+                limit: query.limit,
+                flags,
+                reserved: [0; 58],
+            })
+            .collect();
+        return Ok(Some(Plan::AccountTransfers {
+            filters,
+            ledger: query.ledger,
+        }));
+    }
+
+    let flags = if query.reversed {
+        QueryFilterFlags::REVERSED
+    } else {
+        QueryFilterFlags::empty()
+    };
+    let filters = codes
+        .iter()
+        .map(|&code| QueryFilter {
+            user_data_128,
+            user_data_64: query.user_data_64.unwrap_or(0),
+            user_data_32: query.user_data_32.unwrap_or(0),
+            ledger: query.ledger.unwrap_or(0),
+            code,
+            timestamp_min,
+            timestamp_max,
+            limit: query.limit,
+            flags,
+            reserved: [0; 6],
+        })
+        .collect();
+    Ok(Some(match query.kind {
+        QueryKind::Accounts => Plan::Accounts(filters),
+        QueryKind::Transfers => Plan::Transfers(filters),
+    }))
+}
+
+/// Run the queries of `plan`, merging their results into one list of at
+/// most `limit` in timestamp order (newest first if `reversed`).
+pub async fn run(
+    pool: &ClientPool,
+    plan: Plan,
+    limit: u32,
+    reversed: bool,
+) -> Result<Results, AppError> {
+    let client = pool.get();
+    Ok(match plan {
+        Plan::Accounts(filters) => {
+            let mut accounts = Vec::new();
+            for filter in filters {
+                accounts.extend(client.query_accounts(filter).await?);
+            }
+            Results::Accounts(merge(accounts, |a| a.timestamp, limit, reversed))
+        }
+        Plan::Transfers(filters) => {
+            let mut transfers = Vec::new();
+            for filter in filters {
+                transfers.extend(client.query_transfers(filter).await?);
+            }
+            Results::Transfers(merge(transfers, |t| t.timestamp, limit, reversed))
+        }
+        Plan::AccountTransfers { filters, ledger } => {
+            let mut transfers = Vec::new();
+            for filter in filters {
+                transfers.extend(client.get_account_transfers(filter).await?);
+            }
+            transfers.retain(|t| ledger.is_none_or(|ledger| t.ledger == ledger));
+            Results::Transfers(merge(transfers, |t| t.timestamp, limit, reversed))
+        }
+    })
+}
+
+/// Sort `records` from the queries of each code by timestamp and keep the
+/// first `limit`.
+fn merge<T>(mut records: Vec<T>, timestamp: fn(&T) -> u64, limit: u32, reversed: bool) -> Vec<T> {
+    if reversed {
+        records.sort_unstable_by_key(|r| std::cmp::Reverse(timestamp(r)));
+    } else {
+        records.sort_unstable_by_key(timestamp);
+    }
+    records.truncate(limit as usize);
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{account, transfer, FakeCluster};
+    use serde_json::json;
+    use std::{env, fs};
+
+    /// A query from its JSON fields, named `q` unless they say otherwise.
+    fn query(fields: serde_json::Value) -> SavedQuery {
+        let mut query = json!({"name": "q", "kind": "transfers"});
+        query
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(query).unwrap()
+    }
+
+    fn names(queries: &Queries) -> Vec<String> {
+        queries.list().into_iter().map(|q| q.name).collect()
+    }
+
+    fn in_memory() -> Queries {
+        Queries::open(Path::new(":memory:")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_set_get_remove() {
+        let queries = in_memory();
+        assert!(queries.get("q").is_none());
+        assert!(!queries.set(query(json!({"ledger": 1}))).await.unwrap());
+        assert!(!queries.set(query(json!({"name": "a"}))).await.unwrap());
+        assert_eq!(names(&queries), ["a", "q"]);
+        assert_eq!(queries.get("q").unwrap().ledger, Some(1));
+
+        // Saving under a name in use replaces that query.
+        assert!(queries.set(query(json!({"ledger": 2}))).await.unwrap());
+        assert_eq!(queries.get("q").unwrap().ledger, Some(2));
+        assert_eq!(names(&queries), ["a", "q"]);
+
+        assert!(queries.remove("q".to_string()).await.unwrap());
+        assert!(!queries.remove("q".to_string()).await.unwrap());
+        assert!(queries.get("q").is_none());
+        assert_eq!(names(&queries), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let queries = Queries::disabled();
+        assert!(queries.list().is_empty());
+        assert!(queries.get("q").is_none());
+        assert!(matches!(
+            queries.set(query(json!({}))).await,
+            Err(AppError::Internal(_))
+        ));
+        assert!(queries.remove("q".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reopen() {
+        let path = env::temp_dir().join(format!("tb-web-queries-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let queries = Queries::open(&path).unwrap();
+        let saved = query(json!({"codes": [1, 2], "reversed": true, "limit": 10}));
+        queries.set(saved.clone()).await.unwrap();
+        queries.set(query(json!({"name": "gone"}))).await.unwrap();
+        queries.remove("gone".to_string()).await.unwrap();
+        drop(queries);
+
+        let queries = Queries::open(&path).unwrap();
+        assert_eq!(names(&queries), ["q"]);
+        let loaded = queries.get("q").unwrap();
+        assert_eq!(
+            serde_json::to_value(loaded).unwrap(),
+            serde_json::to_value(saved).unwrap()
+        );
+
+        // A row that is not a query is an error, naming it.
+        queries
+            .registry
+            .as_ref()
+            .unwrap()
+            .db
+            .lock()
+            .unwrap()
+            .execute("INSERT INTO queries VALUES ('bad', '{}')", [])
+            .unwrap();
+        drop(queries);
+        let error = Queries::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(error.contains("bad query 'bad'"), "{}", error);
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let long = "q".repeat(NAME_LEN_MAX + 1);
+        let codes: Vec<u16> = (1..=CODES_MAX as u16 + 1).collect();
+        let cases = [
+            (json!({"name": ""}), "name: must be 1 to"),
+            (json!({"name": "a b"}), "name: must be 1 to"),
+            (json!({"name": long}), "name: must be 1 to"),
+            (json!({"limit": 0}), "limit: must be 1 to 1000"),
+            (json!({"limit": LIMIT_MAX + 1}), "limit: must be 1 to 1000"),
+            (json!({"codes": codes}), "codes: at most 16"),
+            (
+                json!({"kind": "accounts", "account": "1"}),
+                "account: only transfers",
+            ),
+            (
+                json!({"direction": "debits"}),
+                "direction: needs an account",
+            ),
+            (
+                json!({"timestamp_min": 2, "timestamp_max": 1}),
+                "timestamp_min: after timestamp_max",
+            ),
+            (
+                json!({"user_data_128": "xyz"}),
+                "user_data_128: invalid hex ID",
+            ),
+            (json!({"account": "-1"}), "account: invalid hex ID"),
+        ];
+        for (fields, message) in cases {
+            let result = plan(&query(fields.clone()), None);
+            let error = result.err().unwrap();
+            assert!(error.starts_with(message), "{}: {}", fields, error);
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let fields = json!({
+            "ledger": 3,
+            "codes": [5, 1, 5],
+            "timestamp_min": 10,
+            "user_data_128": "ff",
+            "user_data_64": 64,
+            "limit": 7,
+        });
+        let Some(Plan::Transfers(filters)) = plan(&query(fields), None).unwrap() else {
+            panic!("not a transfer plan");
+        };
+        // A filter per code, once each.
+        let codes: Vec<u16> = filters.iter().map(|f| f.code).collect();
+        assert_eq!(codes, [1, 5]);
+        let filter = filters[0];
+        assert_eq!(
+            (filter.ledger, filter.user_data_128, filter.user_data_64),
+            (3, 0xff, 64)
+        );
+        assert_eq!((filter.timestamp_min, filter.timestamp_max), (10, 0));
+        assert_eq!((filter.limit, filter.flags), (7, QueryFilterFlags::empty()));
+
+        // No codes is any code.
+        let fields = json!({"kind": "accounts", "reversed": true});
+        let Some(Plan::Accounts(filters)) = plan(&query(fields), None).unwrap() else {
+            panic!("not an account plan");
+        };
+        assert_eq!(filters.len(), 1);
+        assert_eq!(
+            (filters[0].code, filters[0].flags),
+            (0, QueryFilterFlags::REVERSED)
+        );
+
+        let fields = json!({"account": "0x2a", "direction": "credits", "ledger": 1});
+        let Some(Plan::AccountTransfers { filters, ledger }) = plan(&query(fields), None).unwrap()
+        else {
+            panic!("not an account transfers plan");
+        };
+        assert_eq!(ledger, Some(1));
+        assert_eq!(filters[0].account_id, 42);
+        assert_eq!(filters[0].flags, AccountFilterFlags::CREDITS);
+    }
+
+    #[test]
+    fn test_plan_after() {
+        let bounds = |fields: serde_json::Value, after| {
+            plan(&query(fields), Some(after)).unwrap().map(|plan| {
+                let Plan::Transfers(filters) = plan else {
+                    panic!("not a transfer plan");
+                };
+                (filters[0].timestamp_min, filters[0].timestamp_max)
+            })
+        };
+        // Oldest first, the next page starts after the last timestamp.
+        assert_eq!(bounds(json!({}), 5), Some((6, 0)));
+        assert_eq!(bounds(json!({"timestamp_min": 9}), 5), Some((9, 0)));
+        assert_eq!(bounds(json!({"timestamp_max": 6}), 5), Some((6, 6)));
+        assert_eq!(bounds(json!({"timestamp_max": 5}), 5), None);
+
+        // Newest first, it ends before it.
+        let reversed = |fields: serde_json::Value| {
+            let mut fields = fields;
+            fields["reversed"] = json!(true);
+            fields
+        };
+        assert_eq!(bounds(reversed(json!({})), 5), Some((0, 4)));
+        assert_eq!(
+            bounds(reversed(json!({"timestamp_max": 3})), 5),
+            Some((0, 3))
+        );
+        assert_eq!(
+            bounds(reversed(json!({"timestamp_min": 4})), 5),
+            Some((4, 4))
+        );
+        assert_eq!(bounds(reversed(json!({"timestamp_min": 5})), 5), None);
+        // Zero would mean no bound.
+        assert_eq!(bounds(reversed(json!({})), 1), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let records = vec![3, 1, 4, 1, 5, 9, 2, 6];
+        assert_eq!(merge(records.clone(), |&r| r, 4, false), [1, 1, 2, 3]);
+        assert_eq!(merge(records.clone(), |&r| r, 3, true), [9, 6, 5]);
+        assert_eq!(merge(records, |&r| r, 100, false).len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let cluster = FakeCluster::new();
+        let accounts = [account(1, 1), account(2, 1), account(3, 2), account(4, 2)];
+        let mut transfers = Vec::new();
+        for (id, code) in [(10, 1), (11, 2), (12, 1), (13, 3), (14, 2)] {
+            let mut transfer = transfer(id, 1, 2, 1, 1);
+            transfer.code = code;
+            transfers.push(transfer);
+        }
+        let mut other_ledger = transfer(15, 3, 4, 1, 2);
+        other_ledger.code = 9;
+        transfers.push(other_ledger);
+        cluster.seed(&accounts, &transfers);
+        let pool = ClientPool::fake(&cluster, 1);
+        let ids = |query: SavedQuery| {
+            let pool = &pool;
+            async move {
+                let planned = plan(&query, None).unwrap().unwrap();
+                match run(pool, planned, query.limit, query.reversed)
+                    .await
+                    .unwrap()
+                {
+                    Results::Transfers(transfers) => transfers.iter().map(|t| t.id).collect(),
+                    Results::Accounts(accounts) => {
+                        accounts.iter().map(|a| a.id).collect::<Vec<_>>()
+                    }
+                }
+            }
+        };
+
+        // Each code's results, merged in order and cut to the limit.
+        let found = ids(query(json!({"codes": [2, 1], "limit": 3}))).await;
+        assert_eq!(found, [10, 11, 12]);
+        let found = ids(query(json!({"codes": [2, 1], "reversed": true}))).await;
+        assert_eq!(found, [14, 12, 11, 10]);
+
+        // An account's transfers on another ledger are none.
+        let found = ids(query(json!({"account": "3"}))).await;
+        assert_eq!(found, [15]);
+        let found = ids(query(json!({"account": "3", "ledger": 1}))).await;
+        assert!(found.is_empty());
+        let found = ids(query(json!({"account": "2", "direction": "debits"}))).await;
+        assert!(found.is_empty());
+
+        let found = ids(query(json!({"kind": "accounts", "ledger": 2}))).await;
+        assert_eq!(found, [3, 4]);
+    }
+}
@@ -0,0 +1,280 @@
+//! Recently viewed accounts and transfers.
+//!
+//! The first account or transfer a browser opens in the web UI gets it a
+//! `tb_web_session` cookie, an opaque random ID that lasts until the
+//! browser closes. Under that ID the server keeps the last [`RECENT_MAX`]
+//! accounts and transfers opened, newest first, for the dashboard's
+//! "Recently Viewed" panel.
+//!
+//! Histories are kept in memory, so they are lost on restart; one idle for
+//! [`IDLE`] is dropped, and past [`SESSIONS_MAX`] the least used go first.
+//! API requests are not recorded.
+
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderValue};
+use moka::future::Cache as MokaCache;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Name of the session cookie.
+const COOKIE: &str = "tb_web_session";
+
+/// Most entries kept per session.
+const RECENT_MAX: usize = 20;
+
+/// Most sessions kept.
+const SESSIONS_MAX: u64 = 10_000;
+
+/// How long an unused session is kept.
+const IDLE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// What was viewed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Account,
+    Transfer,
+}
+
+impl Kind {
+    /// `account` or `transfer`, as in page URLs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Account => "account",
+            Kind::Transfer => "transfer",
+        }
+    }
+}
+
+/// One account or transfer viewed.
+#[derive(Debug, Clone, Copy)]
+pub struct Viewed {
+    pub kind: Kind,
+    pub id: u128,
+    pub at: SystemTime,
+}
+
+type History = Arc<Mutex<VecDeque<Viewed>>>;
+
+/// The history of each session.
+pub struct Recent {
+    sessions: MokaCache<u128, History>,
+    random: SystemRandom,
+}
+
+impl Default for Recent {
+    fn default() -> Self {
+        Self {
+            sessions: MokaCache::builder()
+                .max_capacity(SESSIONS_MAX)
+                .time_to_idle(IDLE)
+                .build(),
+            random: SystemRandom::new(),
+        }
+    }
+}
+
+impl Recent {
+    /// Record that `session` viewed `kind` `id`, starting a session if
+    /// there is none. Returns the `Set-Cookie` value for a new one, marked
+    /// `Secure` if `secure`.
+    pub async fn record(
+        &self,
+        session: Session,
+        kind: Kind,
+        id: u128,
+        secure: bool,
+    ) -> Option<HeaderValue> {
+        let (session, cookie) = match session.0 {
+            Some(session) => (session, None),
+            None => {
+                let session = self.new_session()?;
+                (session, Some(cookie(session, secure)))
+            }
+        };
+        let history = self
+            .sessions
+            .get_with(session, async { History::default() })
+            .await;
+        let mut history = history.lock().unwrap();
+        history.retain(|viewed| viewed.kind != kind || viewed.id != id);
+        history.push_front(Viewed {
+            kind,
+            id,
+            at: SystemTime::now(),
+        });
+        history.truncate(RECENT_MAX);
+        cookie
+    }
+
+    /// What `session` viewed, newest first.
+    pub async fn list(&self, session: Session) -> Vec<Viewed> {
+        let Some(session) = session.0 else {
+            return Vec::new();
+        };
+        match self.sessions.get(&session).await {
+            Some(history) => history.lock().unwrap().iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// A new random session ID; `None` if the system has no randomness to
+    /// give, and nothing is recorded.
+    fn new_session(&self) -> Option<u128> {
+        let mut bytes = [0u8; 16];
+        self.random.fill(&mut bytes).ok()?;
+        Some(u128::from_le_bytes(bytes))
+    }
+}
+
+/// The `Set-Cookie` value for `session`.
+fn cookie(session: u128, secure: bool) -> HeaderValue {
+    let secure = if secure { "; Secure" } else { "" };
+    let cookie = format!(
+        "{}={:032x}; Path=/; HttpOnly; SameSite=Lax{}",
+        COOKIE, session, secure
+    );
+    HeaderValue::from_str(&cookie).expect("cookie is ASCII")
+}
+
+/// Extractor for the session in the request's cookie, if any.
+pub struct Session(Option<u128>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Session {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Infallible> {
+        let session = parts
+            .headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().strip_prefix(COOKIE)?.strip_prefix('='))
+            .filter(|id| id.len() == 32)
+            .find_map(|id| u128::from_str_radix(id, 16).ok());
+        Ok(Self(session))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn viewed(recent: &[Viewed]) -> Vec<(Kind, u128)> {
+        recent.iter().map(|v| (v.kind, v.id)).collect()
+    }
+
+    async fn session(cookies: &[&str]) -> Option<u128> {
+        let mut request = Request::get("/");
+        for cookie in cookies {
+            request = request.header(header::COOKIE, *cookie);
+        }
+        let (mut parts, ()) = request.body(()).unwrap().into_parts();
+        let Ok(Session(session)) = Session::from_request_parts(&mut parts, &()).await;
+        session
+    }
+
+    /// The session a `Set-Cookie` value starts.
+    fn started(cookie: &HeaderValue) -> u128 {
+        let value = cookie.to_str().unwrap();
+        let id = &value[COOKIE.len() + 1..COOKIE.len() + 33];
+        u128::from_str_radix(id, 16).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_record_orders_and_caps() {
+        let recent = Recent::default();
+        let cookie = recent
+            .record(Session(None), Kind::Account, 1, false)
+            .await
+            .unwrap();
+        let id = started(&cookie);
+        let session = || Session(Some(id));
+        assert!(recent
+            .record(session(), Kind::Transfer, 1, false)
+            .await
+            .is_none());
+        recent.record(session(), Kind::Account, 2, false).await;
+        // Newest first; an account and a transfer may share an ID.
+        assert_eq!(
+            viewed(&recent.list(session()).await),
+            [(Kind::Account, 2), (Kind::Transfer, 1), (Kind::Account, 1)]
+        );
+
+        // Viewing one again moves it to the front.
+        recent.record(session(), Kind::Account, 1, false).await;
+        assert_eq!(
+            viewed(&recent.list(session()).await),
+            [(Kind::Account, 1), (Kind::Account, 2), (Kind::Transfer, 1)]
+        );
+
+        // The oldest go past the most kept.
+        for id in 100..100 + RECENT_MAX as u128 {
+            recent.record(session(), Kind::Transfer, id, false).await;
+        }
+        let list = viewed(&recent.list(session()).await);
+        assert_eq!(list.len(), RECENT_MAX);
+        assert_eq!(list[0], (Kind::Transfer, 99 + RECENT_MAX as u128));
+        assert_eq!(list[RECENT_MAX - 1], (Kind::Transfer, 100));
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_separate() {
+        let recent = Recent::default();
+        let first = recent.record(Session(None), Kind::Account, 1, false).await;
+        let second = recent.record(Session(None), Kind::Account, 2, false).await;
+        let (first, second) = (started(&first.unwrap()), started(&second.unwrap()));
+        assert_ne!(first, second);
+        let list = |id| recent.list(Session(Some(id)));
+        assert_eq!(viewed(&list(first).await), [(Kind::Account, 1)]);
+        assert_eq!(viewed(&list(second).await), [(Kind::Account, 2)]);
+
+        // None for no session, or one the server does not know.
+        assert!(recent.list(Session(None)).await.is_empty());
+        assert!(recent.list(Session(Some(first ^ 1))).await.is_empty());
+    }
+
+    #[test]
+    fn test_cookie() {
+        assert_eq!(
+            cookie(0xab, false),
+            "tb_web_session=000000000000000000000000000000ab; Path=/; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            cookie(0xab, true),
+            "tb_web_session=000000000000000000000000000000ab; Path=/; HttpOnly; SameSite=Lax; \
+             Secure"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let id = "000000000000000000000000000000ab";
+        assert_eq!(
+            session(&[&format!("tb_web_session={}", id)]).await,
+            Some(0xab)
+        );
+        let among = format!("theme=dark; tb_web_session={}; lang=en", id);
+        assert_eq!(session(&[&among]).await, Some(0xab));
+        assert_eq!(
+            session(&["theme=dark", &format!("tb_web_session={}", id)]).await,
+            Some(0xab)
+        );
+
+        assert_eq!(session(&[]).await, None);
+        assert_eq!(session(&["tb_web_session=ab"]).await, None);
+        assert_eq!(session(&[&format!("tb_web_session={}0", id)]).await, None);
+        assert_eq!(session(&[&format!("tb_web_sessions={}", id)]).await, None);
+        assert_eq!(
+            session(&[&format!("tb_web_session={}", "g".repeat(32))]).await,
+            None
+        );
+    }
+}
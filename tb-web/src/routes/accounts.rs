@@ -1,19 +1,32 @@
 //! Account route handlers.
 
 use crate::api::{
-    AccountsResponse, ApiAccount, ApiAccountBalance, ApiTransfer, BalancesResponse,
-    TransfersResponse,
+    codes, AccountsResponse, ApiAccount, ApiAccountBalance, ApiBalancePoint, ApiTransfer,
+    BalanceChartResponse, BalanceWaitResponse, BalancesResponse, TransfersResponse,
 };
+use crate::auth::{Admin, Viewer};
 use crate::error::AppError;
-use crate::html;
+use crate::etag::{self, ETag};
+use crate::html::{self, Pager};
+use crate::paging::Page;
+use crate::recent::{Kind, Session};
+use crate::routes::create_response;
+use crate::routes::export;
 use crate::state::AppState;
-use axum::extract::{Path, Query, State};
-use axum::http::HeaderMap;
+use crate::validate::{self, Events, IdPath, Valid, Validate};
+use axum::extract::State;
+use axum::http::{header, HeaderMap};
 use axum::response::{Html, IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
+use std::fmt::Write;
 use std::sync::Arc;
-use tb_rs::{AccountFilter, AccountFilterFlags, QueryFilter, QueryFilterFlags};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tb_rs::{
+    Account, AccountBalance, AccountFilter, AccountFilterFlags, QueryFilter, QueryFilterFlags,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
 
 /// Check if request is from HTMX.
 fn is_htmx_request(headers: &HeaderMap) -> bool {
@@ -32,74 +45,187 @@ pub struct ListAccountsParams {
     pub limit: u32,
     /// Pagination: start after this timestamp.
     pub after_timestamp: Option<u64>,
+    /// Pagination: end before this timestamp.
+    pub before_timestamp: Option<u64>,
 }
 
 fn default_limit() -> u32 {
     100
 }
 
+impl Validate for ListAccountsParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::ledger("ledger", self.ledger)?;
+        validate::code("code", self.code)?;
+        // A limit over a page is streamed, so it is not capped.
+        validate::limit(&mut self.limit, u32::MAX)?;
+        validate::cursors(self.after_timestamp, self.before_timestamp)
+    }
+}
+
 /// List accounts with optional filters.
 pub async fn list_accounts(
+    _: Viewer,
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Query(params): Query<ListAccountsParams>,
+    Valid(params): Valid<ListAccountsParams>,
 ) -> Result<Response, AppError> {
+    let page = Page::new(false, params.after_timestamp, params.before_timestamp);
+    let (timestamp_min, timestamp_max, reversed) = page.range();
     let filter = QueryFilter {
         user_data_128: 0,
         user_data_64: 0,
         user_data_32: 0,
         ledger: params.ledger.unwrap_or(0),
         code: params.code.unwrap_or(0),
-        timestamp_min: params.after_timestamp.map(|t| t + 1).unwrap_or(0),
-        timestamp_max: 0,
+        timestamp_min,
+        timestamp_max,
         limit: params.limit,
-        flags: QueryFilterFlags::empty(),
+        flags: if reversed {
+            QueryFilterFlags::REVERSED
+        } else {
+            QueryFilterFlags::empty()
+        },
         reserved: [0; 6],
     };
 
-    let accounts = {
-        let client = state.client.lock().await;
-        client.query_accounts(filter).await?
+    if !is_htmx_request(&headers) {
+        if let Some(framing) =
+            export::list_framing(&headers, params.limit, export::Records::Accounts)?
+        {
+            export::check_page(&page)?;
+            return Ok(export::stream(
+                state,
+                export::Paged::Accounts(filter),
+                params.limit as u64,
+                framing,
+            ));
+        }
+    }
+
+    let warm = if page.is_first() && params.code.is_none() {
+        state.warm.accounts(filter.ledger, params.limit)
+    } else {
+        None
     };
+    let mut accounts = match warm {
+        Some(accounts) => accounts,
+        None => {
+            let client = state.pool.get();
+            client.query_accounts(filter).await?
+        }
+    };
+    page.order(&mut accounts);
 
     let next_timestamp = accounts.last().map(|a| a.timestamp);
-    let api_accounts: Vec<ApiAccount> = accounts.iter().map(ApiAccount::from).collect();
+    let cursors = page.cursors(
+        accounts.first().map(|a| a.timestamp),
+        next_timestamp,
+        accounts.len() == params.limit as usize,
+    );
+    let api_accounts: Vec<ApiAccount> = accounts
+        .iter()
+        .map(|a| ApiAccount::from(a).with_display(&state.config.currencies))
+        .collect();
 
     if is_htmx_request(&headers) {
-        Ok(Html(html::render_accounts_table(&api_accounts, next_timestamp)).into_response())
+        let mut url = format!("/api/v1/accounts?limit={}", params.limit);
+        if let Some(ledger) = params.ledger {
+            let _ = write!(url, "&ledger={}", ledger);
+        }
+        if let Some(code) = params.code {
+            let _ = write!(url, "&code={}", code);
+        }
+        Ok(Html(html::render_accounts_table(
+            &api_accounts,
+            Some(Pager { url: &url, cursors }),
+            &state.aliases,
+            &state.config.timestamps,
+        ))
+        .into_response())
     } else {
         Ok(Json(AccountsResponse {
             accounts: api_accounts,
             next_timestamp,
+            prev_cursor: cursors.prev,
+            next_cursor: cursors.next,
         })
         .into_response())
     }
 }
 
-/// Get a single account by ID.
-pub async fn get_account(
+/// Create accounts from a JSON array.
+///
+/// Returns 201 when every account was created and 207 with each account's
+/// result otherwise. The body is checked as [`Events`] before anything is
+/// sent.
+pub async fn create_accounts(
+    _: Admin,
     State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
-    Path(id): Path<String>,
+    Events(accounts): Events<Account>,
 ) -> Result<Response, AppError> {
-    let account_id = parse_id(&id)?;
-
-    let accounts = {
-        let client = state.client.lock().await;
-        client.lookup_accounts(&[account_id]).await?
+    let failures = {
+        let client = state.pool.get();
+        client.create_accounts(&accounts).await?
     };
 
-    let account = accounts
-        .first()
-        .ok_or_else(|| AppError::NotFound(format!("Account {} not found", id)))?;
+    let ids: Vec<u128> = accounts.iter().map(|a| a.id).collect();
+    state.cache.accounts_created(&ids).await;
+    let failures: Vec<(u32, &'static str)> = failures
+        .iter()
+        .map(|f| (f.index, codes::account_result(f.result)))
+        .collect();
+    Ok(create_response(&ids, &failures))
+}
 
-    let api_account = ApiAccount::from(account);
+/// Get a single account by ID, with an ETag that changes with its balances
+/// and flags (304 if `If-None-Match` has it). Views in the web UI are
+/// recorded for the session (see [`crate::recent`]).
+pub async fn get_account(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    session: Session,
+    IdPath(account_id): IdPath,
+) -> Result<Response, AppError> {
+    let account = state
+        .cache
+        .account(&state.pool, account_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Account {:032x} not found", account_id)))?;
 
-    if is_htmx_request(&headers) {
-        Ok(Html(html::render_account_detail(&api_account)).into_response())
+    let htmx = is_htmx_request(&headers);
+    let etag = ETag::account(&account);
+    let etag = if htmx {
+        etag.fragment(&[state.aliases.get(account.id)], &state.config.timestamps)
     } else {
-        Ok(Json(api_account).into_response())
+        Some(etag)
+    };
+
+    let mut response = etag::respond(&headers, etag, || {
+        let api_account = ApiAccount::from(&account).with_display(&state.config.currencies);
+        if htmx {
+            Html(html::render_account_detail(
+                &api_account,
+                &state.aliases,
+                &state.config.timestamps,
+            ))
+            .into_response()
+        } else {
+            Json(api_account).into_response()
+        }
+    });
+    if htmx {
+        let secure = state.config.tls.is_some();
+        if let Some(cookie) = state
+            .recent
+            .record(session, Kind::Account, account.id, secure)
+            .await
+        {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
     }
+    Ok(response)
 }
 
 /// Query parameters for account transfers.
@@ -119,21 +245,29 @@ pub struct AccountTransfersParams {
     pub limit: u32,
     /// Pagination: start after this timestamp.
     pub after_timestamp: Option<u64>,
+    /// Pagination: end before this timestamp.
+    pub before_timestamp: Option<u64>,
 }
 
 fn default_true() -> bool {
     true
 }
 
+impl Validate for AccountTransfersParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::limit(&mut self.limit, u32::MAX)?;
+        validate::cursors(self.after_timestamp, self.before_timestamp)
+    }
+}
+
 /// Get transfers for an account.
 pub async fn get_account_transfers(
+    _: Viewer,
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Path(id): Path<String>,
-    Query(params): Query<AccountTransfersParams>,
+    IdPath(account_id): IdPath,
+    Valid(params): Valid<AccountTransfersParams>,
 ) -> Result<Response, AppError> {
-    let account_id = parse_id(&id)?;
-
     let mut flags = AccountFilterFlags::empty();
     if params.debits {
         flags |= AccountFilterFlags::DEBITS;
@@ -141,7 +275,13 @@ pub async fn get_account_transfers(
     if params.credits {
         flags |= AccountFilterFlags::CREDITS;
     }
-    if params.reversed {
+    let page = Page::new(
+        params.reversed,
+        params.after_timestamp,
+        params.before_timestamp,
+    );
+    let (timestamp_min, timestamp_max, reversed) = page.range();
+    if reversed {
         flags |= AccountFilterFlags::REVERSED;
     }
 
@@ -151,27 +291,62 @@ pub async fn get_account_transfers(
         user_data_64: 0,
         user_data_32: 0,
         code: 0,
-        timestamp_min: params.after_timestamp.map(|t| t + 1).unwrap_or(0),
-        timestamp_max: 0,
+        timestamp_min,
+        timestamp_max,
         limit: params.limit,
         flags,
         reserved: [0; 58],
     };
 
-    let transfers = {
-        let client = state.client.lock().await;
+    if !is_htmx_request(&headers) {
+        if let Some(framing) =
+            export::list_framing(&headers, params.limit, export::Records::Transfers)?
+        {
+            export::check_page(&page)?;
+            return Ok(export::stream(
+                state,
+                export::Paged::AccountTransfers(filter),
+                params.limit as u64,
+                framing,
+            ));
+        }
+    }
+
+    let mut transfers = {
+        let client = state.pool.get();
         client.get_account_transfers(filter).await?
     };
+    page.order(&mut transfers);
 
     let next_timestamp = transfers.last().map(|t| t.timestamp);
-    let api_transfers: Vec<ApiTransfer> = transfers.iter().map(ApiTransfer::from).collect();
+    let cursors = page.cursors(
+        transfers.first().map(|t| t.timestamp),
+        next_timestamp,
+        transfers.len() == params.limit as usize,
+    );
+    let api_transfers: Vec<ApiTransfer> = transfers
+        .iter()
+        .map(|t| ApiTransfer::from(t).with_display(&state.config.currencies))
+        .collect();
 
     if is_htmx_request(&headers) {
-        Ok(Html(html::render_transfers_table(&api_transfers, next_timestamp)).into_response())
+        let url = format!(
+            "/api/v1/accounts/{:032x}/transfers?limit={}&debits={}&credits={}&reversed={}",
+            account_id, params.limit, params.debits, params.credits, params.reversed
+        );
+        Ok(Html(html::render_transfers_table(
+            &api_transfers,
+            Some(Pager { url: &url, cursors }),
+            &state.aliases,
+            &state.config.timestamps,
+        ))
+        .into_response())
     } else {
         Ok(Json(TransfersResponse {
             transfers: api_transfers,
             next_timestamp,
+            prev_cursor: cursors.prev,
+            next_cursor: cursors.next,
         })
         .into_response())
     }
@@ -188,14 +363,19 @@ pub struct AccountBalancesParams {
     pub reversed: bool,
 }
 
+impl Validate for AccountBalancesParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::limit(&mut self.limit, validate::LIMIT_MAX)
+    }
+}
+
 /// Get balance history for an account.
 pub async fn get_account_balances(
+    _: Viewer,
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
-    Query(params): Query<AccountBalancesParams>,
+    IdPath(account_id): IdPath,
+    Valid(params): Valid<AccountBalancesParams>,
 ) -> Result<Json<BalancesResponse>, AppError> {
-    let account_id = parse_id(&id)?;
-
     let mut flags = AccountFilterFlags::DEBITS | AccountFilterFlags::CREDITS;
     if params.reversed {
         flags |= AccountFilterFlags::REVERSED;
@@ -215,7 +395,7 @@ pub async fn get_account_balances(
     };
 
     let balances = {
-        let client = state.client.lock().await;
+        let client = state.pool.get();
         client.get_account_balances(filter).await?
     };
 
@@ -227,7 +407,483 @@ pub async fn get_account_balances(
     }))
 }
 
-/// Parse a hex ID string to u128.
-fn parse_id(id: &str) -> Result<u128, AppError> {
-    u128::from_str_radix(id, 16).map_err(|_| AppError::BadRequest(format!("Invalid ID: {}", id)))
+/// How long a balance long-poll waits by default.
+const BALANCE_WAIT_DEFAULT: Duration = Duration::from_secs(30);
+
+/// Longest a balance long-poll may wait.
+const BALANCE_WAIT_MAX: Duration = Duration::from_secs(60);
+
+/// Query parameters for waiting on a balance.
+#[derive(Debug, Deserialize)]
+pub struct BalanceWaitParams {
+    /// How long to wait for a change, e.g. `30s`.
+    pub wait: Option<String>,
+    /// Respond at once if a transfer newer than this touched the account.
+    pub since_timestamp: Option<u64>,
+}
+
+impl BalanceWaitParams {
+    /// How long to wait, once validated.
+    fn wait(&self) -> Duration {
+        match &self.wait {
+            Some(wait) => parse_duration(wait, "wait", "").unwrap_or(BALANCE_WAIT_DEFAULT),
+            None => BALANCE_WAIT_DEFAULT,
+        }
+    }
+}
+
+impl Validate for BalanceWaitParams {
+    fn validate(&mut self) -> Result<(), String> {
+        let Some(wait) = &self.wait else {
+            return Ok(());
+        };
+        if parse_duration(wait, "wait", "30s, 2m")? > BALANCE_WAIT_MAX {
+            return Err(format!("wait: at most {}s", BALANCE_WAIT_MAX.as_secs()));
+        }
+        Ok(())
+    }
+}
+
+/// Get an account's balance once it changes.
+///
+/// With `since_timestamp`, responds at once if a newer transfer touched the
+/// account. Otherwise holds the request until the live update poller (see
+/// [`crate::live`]) sees a transfer touch it, or until `wait` runs out and
+/// the balance is returned unchanged.
+pub async fn wait_for_balance(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    IdPath(account_id): IdPath,
+    Valid(params): Valid<BalanceWaitParams>,
+) -> Result<Json<BalanceWaitResponse>, AppError> {
+    let deadline = Instant::now() + params.wait();
+    let currencies = &state.config.currencies;
+    let respond = |account: &Account, changed, timestamp| {
+        Json(BalanceWaitResponse {
+            account: ApiAccount::from(account).with_display(currencies),
+            changed,
+            timestamp,
+        })
+    };
+
+    // Subscribe before looking, so a change in between is not missed.
+    let mut changes = state.balances.subscribe();
+    let newest = newest_transfer(&state, account_id, params.since_timestamp.unwrap_or(0)).await?;
+    let account = lookup_account(&state, account_id).await?;
+    let since = match (params.since_timestamp, newest) {
+        (Some(_), Some(newest)) => return Ok(respond(&account, true, newest)),
+        (Some(since), None) => since,
+        (None, newest) => newest.unwrap_or(0),
+    };
+
+    loop {
+        match tokio::time::timeout_at(deadline, changes.recv()).await {
+            Ok(Ok(changes)) => {
+                if let Some(change) = changes.iter().find(|c| c.account.id == account_id) {
+                    return Ok(respond(&change.account, true, change.timestamp));
+                }
+            }
+            // Changes were missed; look for ours below.
+            Ok(Err(RecvError::Lagged(_))) => break,
+            Ok(Err(RecvError::Closed)) => {
+                return Err(AppError::Internal("Live updates stopped".into()));
+            }
+            Err(_) => break,
+        }
+    }
+    // A change may also have come before the poller picked up again.
+    match newest_transfer(&state, account_id, since).await? {
+        Some(newest) => {
+            let account = lookup_account(&state, account_id).await?;
+            Ok(respond(&account, true, newest))
+        }
+        None => Ok(respond(&account, false, since)),
+    }
+}
+
+/// The timestamp of the newest transfer touching `account_id` after
+/// `since`, if any.
+async fn newest_transfer(
+    state: &AppState,
+    account_id: u128,
+    since: u64,
+) -> Result<Option<u64>, AppError> {
+    let filter = AccountFilter {
+        account_id,
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        code: 0,
+        timestamp_min: since.saturating_add(1),
+        timestamp_max: 0,
+        limit: 1,
+        flags: AccountFilterFlags::DEBITS
+            | AccountFilterFlags::CREDITS
+            | AccountFilterFlags::REVERSED,
+        reserved: [0; 58],
+    };
+    let transfers = {
+        let client = state.pool.get();
+        client.get_account_transfers(filter).await?
+    };
+    Ok(transfers.first().map(|t| t.timestamp))
+}
+
+/// Look up `account_id` past the cache, as its balance is what is asked.
+async fn lookup_account(state: &AppState, account_id: u128) -> Result<Account, AppError> {
+    let accounts = {
+        let client = state.pool.get();
+        client.lookup_accounts(&[account_id]).await?
+    };
+    accounts
+        .first()
+        .copied()
+        .ok_or_else(|| AppError::NotFound(format!("Account {:032x} not found", account_id)))
+}
+
+/// Balance history rows fetched per query.
+const BALANCES_PAGE_SIZE: u32 = 1000;
+
+/// Query parameters for a balance chart.
+#[derive(Debug, Deserialize)]
+pub struct BalanceChartParams {
+    /// Number of buckets to downsample into.
+    #[serde(default = "default_points")]
+    pub points: u32,
+    /// How far back to chart, e.g. `90m`, `12h`, `30d` (default: all history).
+    pub range: Option<String>,
+}
+
+fn default_points() -> u32 {
+    200
+}
+
+impl BalanceChartParams {
+    /// How far back to chart, once validated.
+    fn range(&self) -> Option<Duration> {
+        self.range
+            .as_deref()
+            .and_then(|range| parse_range(range).ok())
+    }
+}
+
+impl Validate for BalanceChartParams {
+    fn validate(&mut self) -> Result<(), String> {
+        // The most points is configured, so the handler checks that.
+        if self.points == 0 {
+            return Err("points: must be at least 1".to_string());
+        }
+        match &self.range {
+            Some(range) => parse_range(range).map(drop),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Get balance history for an account, downsampled for charting.
+///
+/// Pages through the whole history in range, keeping the lowest, highest
+/// and last net balance per bucket, so the response stays `points` long
+/// however many rows the account has.
+pub async fn get_account_balance_chart(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    IdPath(account_id): IdPath,
+    Valid(params): Valid<BalanceChartParams>,
+) -> Result<Json<BalanceChartResponse>, AppError> {
+    let points_max = state.config.chart_points_max;
+    if params.points > points_max {
+        return Err(AppError::Unprocessable(format!(
+            "points: must be 1 to {}",
+            points_max
+        )));
+    }
+
+    let mut filter = AccountFilter {
+        account_id,
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        code: 0,
+        timestamp_min: 0,
+        timestamp_max: 0,
+        limit: BALANCES_PAGE_SIZE,
+        flags: AccountFilterFlags::DEBITS | AccountFilterFlags::CREDITS,
+        reserved: [0; 58],
+    };
+
+    // Cluster timestamps are nanoseconds since the epoch, close to wall time.
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let start = match params.range() {
+        Some(range) => now.saturating_sub(range.as_nanos() as u64),
+        None => {
+            // Without a range, the chart starts at the oldest balance.
+            let oldest = {
+                let client = state.pool.get();
+                client
+                    .get_account_balances(AccountFilter { limit: 1, ..filter })
+                    .await?
+            };
+            match oldest.first() {
+                Some(balance) => balance.timestamp,
+                None => now,
+            }
+        }
+    };
+    filter.timestamp_min = start;
+    let width = (now.saturating_sub(start) / params.points as u64).max(1);
+
+    let mut points: Vec<ApiBalancePoint> = Vec::new();
+    let mut bucket: Option<Bucket> = None;
+    let mut rows = 0u64;
+    loop {
+        let balances = {
+            let client = state.pool.get();
+            client.get_account_balances(filter).await?
+        };
+        for balance in &balances {
+            rows += 1;
+            let index = Bucket::index(balance.timestamp, start, width, params.points);
+            let net = balance.credits_posted.wrapping_sub(balance.debits_posted) as i128;
+            match &mut bucket {
+                Some(current) if current.index == index => current.add(net, balance),
+                _ => {
+                    points.extend(bucket.take().map(|b| b.point(start, width)));
+                    bucket = Some(Bucket::new(index, net, balance));
+                }
+            }
+        }
+        match balances.last() {
+            Some(last) if balances.len() == BALANCES_PAGE_SIZE as usize => {
+                filter.timestamp_min = last.timestamp + 1;
+            }
+            _ => break,
+        }
+    }
+    points.extend(bucket.map(|b| b.point(start, width)));
+
+    Ok(Json(BalanceChartResponse { points, rows }))
+}
+
+/// Net balances seen in one chart bucket.
+struct Bucket {
+    index: u64,
+    min: i128,
+    max: i128,
+    last: i128,
+    credits_posted: u128,
+    debits_posted: u128,
+}
+
+impl Bucket {
+    /// The bucket of a balance at `timestamp`, of `points` buckets `width`
+    /// apart from `start`. The last bucket also catches balances written
+    /// since the chart's end.
+    fn index(timestamp: u64, start: u64, width: u64, points: u32) -> u64 {
+        ((timestamp - start) / width).min(points as u64 - 1)
+    }
+
+    fn new(index: u64, net: i128, balance: &AccountBalance) -> Self {
+        Self {
+            index,
+            min: net,
+            max: net,
+            last: net,
+            credits_posted: balance.credits_posted,
+            debits_posted: balance.debits_posted,
+        }
+    }
+
+    fn add(&mut self, net: i128, balance: &AccountBalance) {
+        self.min = self.min.min(net);
+        self.max = self.max.max(net);
+        self.last = net;
+        self.credits_posted = balance.credits_posted;
+        self.debits_posted = balance.debits_posted;
+    }
+
+    fn point(self, start: u64, width: u64) -> ApiBalancePoint {
+        ApiBalancePoint {
+            timestamp: start + self.index * width,
+            min: self.min.to_string(),
+            max: self.max.to_string(),
+            last: self.last.to_string(),
+            credits_posted: self.credits_posted.to_string(),
+            debits_posted: self.debits_posted.to_string(),
+        }
+    }
+}
+
+/// Parse a chart range such as `90m`, `12h` or `30d`.
+fn parse_range(range: &str) -> Result<Duration, String> {
+    parse_duration(range, "range", "12h, 30d")
+}
+
+/// Parse a duration such as `30s` or `12h` given as `name`.
+fn parse_duration(value: &str, name: &str, examples: &str) -> Result<Duration, String> {
+    let invalid = || format!("{}: invalid duration {:?} (e.g. {})", name, value, examples);
+    let unit = match value.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3600,
+        Some('d') => 86400,
+        Some('w') => 7 * 86400,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = value[..value.len() - 1].parse().map_err(|_| invalid())?;
+    match count.checked_mul(unit) {
+        Some(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeCluster};
+
+    fn balance(debits_posted: u128, credits_posted: u128) -> AccountBalance {
+        AccountBalance {
+            debits_posted,
+            credits_posted,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        for (value, secs) in [
+            ("1s", 1),
+            ("90m", 90 * 60),
+            ("12h", 12 * 3600),
+            ("30d", 30 * 86400),
+            ("2w", 14 * 86400),
+        ] {
+            assert_eq!(
+                parse_range(value),
+                Ok(Duration::from_secs(secs)),
+                "{}",
+                value
+            );
+        }
+        for value in [
+            "",
+            "s",
+            "0s",
+            "12",
+            "12y",
+            "-1h",
+            "1.5h",
+            " 1h",
+            "1 h",
+            // Overflowing the count, then the seconds.
+            "18446744073709551616s",
+            "18446744073709551615w",
+            // Multibyte text is rejected rather than split mid-character.
+            "5秒",
+            "秒s",
+            "１s",
+        ] {
+            assert_eq!(
+                parse_range(value),
+                Err(format!(
+                    "range: invalid duration {:?} (e.g. 12h, 30d)",
+                    value
+                )),
+            );
+        }
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        // Ten buckets of width 100 from 1000.
+        for (timestamp, index) in [
+            (1000, 0),
+            (1099, 0),
+            (1100, 1),
+            (1950, 9),
+            // Past the end: clamped into the last bucket.
+            (2000, 9),
+            (u64::MAX, 9),
+        ] {
+            assert_eq!(
+                Bucket::index(timestamp, 1000, 100, 10),
+                index,
+                "{}",
+                timestamp
+            );
+        }
+        assert_eq!(Bucket::index(5, 5, 1, 1), 0);
+        assert_eq!(Bucket::index(u64::MAX, 0, 1, 1), 0);
+    }
+
+    #[test]
+    fn test_bucket_downsamples() {
+        let mut bucket = Bucket::new(3, 10, &balance(0, 10));
+        bucket.add(-4, &balance(14, 10));
+        bucket.add(25, &balance(14, 39));
+        bucket.add(7, &balance(32, 39));
+        let point = bucket.point(1000, 50);
+        assert_eq!(point.timestamp, 1150);
+        assert_eq!(
+            (point.min, point.max, point.last),
+            ("-4".to_string(), "25".to_string(), "7".to_string())
+        );
+        assert_eq!(point.credits_posted, "39");
+        assert_eq!(point.debits_posted, "32");
+    }
+
+    #[tokio::test]
+    async fn test_balance_chart() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 700), testing::account(2, 700)],
+            &[
+                testing::transfer(10, 1, 2, 5, 700),
+                testing::transfer(11, 1, 2, 7, 700),
+                testing::transfer(12, 2, 1, 20, 700),
+            ],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        // One point summarizes the whole history.
+        let (status, body) =
+            testing::get(&router, "/api/v1/accounts/2/balances/chart?points=1").await;
+        assert_eq!(status, 200, "{}", body);
+        let chart: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(chart["rows"], 3);
+        let points = chart["points"].as_array().unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(
+            (&points[0]["min"], &points[0]["max"], &points[0]["last"]),
+            (&"-8".into(), &"12".into(), &"-8".into())
+        );
+
+        // More points than rows: no bucket holds more than one.
+        let (status, body) =
+            testing::get(&router, "/api/v1/accounts/1/balances/chart?range=1h").await;
+        assert_eq!(status, 200, "{}", body);
+        let chart: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let lasts: Vec<&str> = chart["points"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["last"].as_str().unwrap())
+            .collect();
+        assert_eq!(chart["rows"], 3);
+        assert!(
+            lasts.len() <= 3 && lasts.last() == Some(&"8"),
+            "{:?}",
+            lasts
+        );
+
+        for query in ["points=0", "points=2001", "range=0s", "range=1y"] {
+            let uri = format!("/api/v1/accounts/1/balances/chart?{}", query);
+            let (status, body) = testing::get(&router, &uri).await;
+            assert_eq!(status, 422, "{}: {}", query, body);
+        }
+    }
 }
@@ -0,0 +1,117 @@
+//! Account alias route handlers (see [`crate::aliases`]).
+
+use crate::aliases::{Alias, LABEL_LEN_MAX, NAME_LEN_MAX};
+use crate::api::{AliasesResponse, ApiAlias, SetAlias};
+use crate::auth::{Operator, Viewer};
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::validate::{IdPath, Valid, Validate};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Query parameters for listing aliases.
+#[derive(Debug, Deserialize)]
+pub struct ListAliasesParams {
+    /// Only aliases with this label.
+    pub label: Option<String>,
+}
+
+impl Validate for ListAliasesParams {}
+
+/// List aliases by name.
+pub async fn list_aliases(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Valid(params): Valid<ListAliasesParams>,
+) -> Json<AliasesResponse> {
+    let aliases = state
+        .aliases
+        .list()
+        .iter()
+        .filter(|alias| match &params.label {
+            Some(label) => alias.labels.contains(label),
+            None => true,
+        })
+        .map(ApiAlias::from)
+        .collect();
+    Json(AliasesResponse { aliases })
+}
+
+/// Get the alias of account `id`.
+pub async fn get_alias(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    IdPath(account_id): IdPath,
+) -> Result<Json<ApiAlias>, AppError> {
+    let alias = state
+        .aliases
+        .get(account_id)
+        .ok_or_else(|| AppError::NotFound(format!("Account {:032x} has no alias", account_id)))?;
+    Ok(Json(ApiAlias::from(&alias)))
+}
+
+/// Set the alias of account `id`, which must exist.
+pub async fn set_alias(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    IdPath(account_id): IdPath,
+    body: Result<Json<SetAlias>, JsonRejection>,
+) -> Result<Json<ApiAlias>, AppError> {
+    let Json(body) = body.map_err(|e| AppError::BadRequest(e.body_text()))?;
+
+    let name = body.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > NAME_LEN_MAX {
+        return Err(AppError::Unprocessable(format!(
+            "Alias must be 1 to {} characters",
+            NAME_LEN_MAX
+        )));
+    }
+    let mut labels: Vec<String> = Vec::new();
+    for label in &body.labels {
+        let label = label.trim();
+        if label.is_empty() || label.chars().count() > LABEL_LEN_MAX {
+            return Err(AppError::Unprocessable(format!(
+                "Labels must be 1 to {} characters",
+                LABEL_LEN_MAX
+            )));
+        }
+        if !labels.iter().any(|l| l == label) {
+            labels.push(label.to_string());
+        }
+    }
+
+    state
+        .cache
+        .account(&state.pool, account_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Account {:032x} not found", account_id)))?;
+
+    let alias = Alias {
+        account_id,
+        name,
+        labels,
+    };
+    let response = ApiAlias::from(&alias);
+    state.aliases.set(alias).await?;
+    Ok(Json(response))
+}
+
+/// Remove the alias of account `id`.
+pub async fn delete_alias(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    IdPath(account_id): IdPath,
+) -> Result<Response, AppError> {
+    if !state.aliases.remove(account_id).await? {
+        return Err(AppError::NotFound(format!(
+            "Account {:032x} has no alias",
+            account_id
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
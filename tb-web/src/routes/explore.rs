@@ -0,0 +1,445 @@
+//! Query builder fragments.
+//!
+//! The Explore page lets someone without the API at hand filter accounts
+//! and transfers by ledger, code, time range and `user_data`. Both
+//! endpoints serve HTML only: `GET /api/v1/explore` renders the builder
+//! form, re-rendered with what was entered whenever its kind changes, and
+//! `GET /api/v1/explore/results` runs the form as an unsaved query (see
+//! [`crate::queries`]) and renders a page of its results.
+//!
+//! Inputs arrive as a browser submits them, with empty strings for empty
+//! fields and times in the display time zone, so every field is a string
+//! until it is checked here.
+
+use crate::api::{ApiAccount, ApiTransfer, Direction, QueryKind, SavedQuery};
+use crate::auth::Viewer;
+use crate::error::AppError;
+use crate::html;
+use crate::queries::{self, Results};
+use crate::state::AppState;
+use crate::timestamps::Timestamps;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The builder form's fields, as submitted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ExploreParams {
+    /// `accounts` or `transfers` (the default).
+    pub kind: String,
+    pub ledger: String,
+    /// Codes separated by commas.
+    pub codes: String,
+    /// Created at or after, as `YYYY-MM-DDTHH:MM`.
+    pub from: String,
+    /// Created at or before, as `YYYY-MM-DDTHH:MM`.
+    pub until: String,
+    pub user_data_128: String,
+    pub user_data_64: String,
+    pub user_data_32: String,
+    /// For transfers, an account ID (hex).
+    pub account: String,
+    /// With `account`: `both`, `debits` or `credits`.
+    pub direction: String,
+    pub limit: String,
+    /// `true` for newest first.
+    pub reversed: String,
+    /// Pagination: start after this timestamp. Set by "Load More" only.
+    pub after_timestamp: Option<u64>,
+}
+
+impl ExploreParams {
+    /// Whether the form lists accounts rather than transfers.
+    pub fn is_accounts(&self) -> bool {
+        self.kind == "accounts"
+    }
+
+    /// The form as an unsaved query, or which field is wrong and why.
+    fn query(&self, timestamps: &Timestamps) -> Result<SavedQuery, String> {
+        let kind = if self.is_accounts() {
+            QueryKind::Accounts
+        } else {
+            QueryKind::Transfers
+        };
+        let codes = self
+            .codes
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(|code| {
+                code.parse()
+                    .map_err(|_| format!("codes: invalid code {:?}", code))
+            })
+            .collect::<Result<_, _>>()?;
+        let time = |name: &str, value: &str| match value.trim() {
+            "" => Ok(None),
+            value => timestamps
+                .parse_local(value)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", name, e)),
+        };
+        let timestamp_min = time("from", &self.from)?;
+        let timestamp_max = time("until", &self.until)?;
+        if let (Some(min), Some(max)) = (timestamp_min, timestamp_max) {
+            if min > max {
+                return Err("from: after until".to_string());
+            }
+        }
+        let direction = match self.direction.trim() {
+            "" | "both" => Direction::Both,
+            "debits" => Direction::Debits,
+            "credits" => Direction::Credits,
+            other => {
+                return Err(format!(
+                    "direction: expected both, debits or credits, got {:?}",
+                    other
+                ))
+            }
+        };
+        let hex = |value: &str| {
+            let value = value.trim();
+            let value = value.strip_prefix("0x").unwrap_or(value);
+            (!value.is_empty()).then(|| value.to_string())
+        };
+        Ok(SavedQuery {
+            // Only checked, never stored.
+            name: "explore".to_string(),
+            kind,
+            ledger: number("ledger", &self.ledger)?,
+            codes,
+            timestamp_min,
+            timestamp_max,
+            user_data_128: hex(&self.user_data_128),
+            user_data_64: number("user_data_64", &self.user_data_64)?,
+            user_data_32: number("user_data_32", &self.user_data_32)?,
+            account: hex(&self.account),
+            direction,
+            reversed: self.reversed == "true",
+            limit: number("limit", &self.limit)?.unwrap_or(DEFAULT_LIMIT),
+        })
+    }
+
+    /// The URL of the page of these results after `timestamp`.
+    fn page_url(&self, timestamp: u64) -> String {
+        let fields = [
+            ("kind", &self.kind),
+            ("ledger", &self.ledger),
+            ("codes", &self.codes),
+            ("from", &self.from),
+            ("until", &self.until),
+            ("user_data_128", &self.user_data_128),
+            ("user_data_64", &self.user_data_64),
+            ("user_data_32", &self.user_data_32),
+            ("account", &self.account),
+            ("direction", &self.direction),
+            ("limit", &self.limit),
+            ("reversed", &self.reversed),
+        ];
+        let mut url = format!("/api/v1/explore/results?after_timestamp={}", timestamp);
+        for (name, value) in fields {
+            if !value.is_empty() {
+                let _ = write!(url, "&{}={}", name, encode(value));
+            }
+        }
+        url
+    }
+}
+
+/// Rows per page unless the form says otherwise.
+const DEFAULT_LIMIT: u32 = 25;
+
+/// Parse optional number field `name`.
+fn number<T: FromStr>(name: &str, value: &str) -> Result<Option<T>, String> {
+    match value.trim() {
+        "" => Ok(None),
+        value => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{}: invalid number {:?}", name, value)),
+    }
+}
+
+/// Percent-encode `value` for a query string.
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+/// Render the query builder form, keeping any values already entered.
+pub async fn builder(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExploreParams>,
+) -> Html<String> {
+    Html(html::render_query_builder(
+        &params,
+        &state.config.currencies.ledgers(),
+        DEFAULT_LIMIT,
+    ))
+}
+
+/// Run the builder form and render a page of its results.
+///
+/// An invalid field is rendered as a message with status 422.
+pub async fn results(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExploreParams>,
+) -> Result<Response, AppError> {
+    let timestamps = &state.config.timestamps;
+    let planned = params.query(timestamps).and_then(|query| {
+        let plan = queries::plan(&query, params.after_timestamp)?;
+        Ok((query, plan))
+    });
+    let (query, plan) = match planned {
+        Ok(planned) => planned,
+        Err(message) => {
+            let body = html::render_explore_error(&message);
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, Html(body)).into_response());
+        }
+    };
+
+    let results = match plan {
+        Some(plan) => queries::run(&state.pool, plan, query.limit, query.reversed).await?,
+        None => match query.kind {
+            QueryKind::Accounts => Results::Accounts(Vec::new()),
+            QueryKind::Transfers => Results::Transfers(Vec::new()),
+        },
+    };
+
+    let currencies = &state.config.currencies;
+    let next_url = |timestamp: Option<u64>, len: usize| {
+        timestamp
+            .filter(|_| len == query.limit as usize)
+            .map(|timestamp| params.page_url(timestamp))
+    };
+    let body = match results {
+        Results::Accounts(accounts) => {
+            let next_url = next_url(accounts.last().map(|a| a.timestamp), accounts.len());
+            let accounts: Vec<ApiAccount> = accounts
+                .iter()
+                .map(|a| ApiAccount::from(a).with_display(currencies))
+                .collect();
+            html::render_explore_accounts(
+                &accounts,
+                next_url.as_deref(),
+                &state.aliases,
+                timestamps,
+            )
+        }
+        Results::Transfers(transfers) => {
+            let next_url = next_url(transfers.last().map(|t| t.timestamp), transfers.len());
+            let transfers: Vec<ApiTransfer> = transfers
+                .iter()
+                .map(|t| ApiTransfer::from(t).with_display(currencies))
+                .collect();
+            html::render_explore_transfers(
+                &transfers,
+                next_url.as_deref(),
+                &state.aliases,
+                timestamps,
+            )
+        }
+    };
+    Ok(Html(body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, account, transfer, FakeCluster};
+    use chrono_tz::Tz;
+
+    fn timestamps() -> Timestamps {
+        Timestamps::new(Tz::UTC, false)
+    }
+
+    fn params(fields: &[(&str, &str)]) -> ExploreParams {
+        let query: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, encode(value)))
+            .collect();
+        Query::try_from_uri(&format!("/?{}", query.join("&")).parse().unwrap())
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_query_defaults() {
+        let query = params(&[]).query(&timestamps()).unwrap();
+        assert_eq!(query.kind, QueryKind::Transfers);
+        assert_eq!(query.limit, DEFAULT_LIMIT);
+        assert_eq!(query.direction, Direction::Both);
+        assert!(!query.reversed);
+        assert!(query.ledger.is_none() && query.codes.is_empty());
+        assert!(query.timestamp_min.is_none() && query.timestamp_max.is_none());
+        assert!(query.user_data_128.is_none() && query.account.is_none());
+        // Blank fields are unset.
+        let query = params(&[("ledger", " "), ("codes", " , "), ("account", "0x")])
+            .query(&timestamps())
+            .unwrap();
+        assert!(query.ledger.is_none() && query.codes.is_empty() && query.account.is_none());
+    }
+
+    #[test]
+    fn test_query() {
+        let query = params(&[
+            ("kind", "transfers"),
+            ("ledger", " 7 "),
+            ("codes", "1, 20,"),
+            ("from", "2024-02-29T13:45"),
+            ("until", "2024-02-29T13:45:30"),
+            ("user_data_128", "0xABC"),
+            ("user_data_64", "64"),
+            ("user_data_32", "32"),
+            ("account", " 0xff "),
+            ("direction", "credits"),
+            ("limit", "10"),
+            ("reversed", "true"),
+        ])
+        .query(&timestamps())
+        .unwrap();
+        assert_eq!(query.ledger, Some(7));
+        assert_eq!(query.codes, [1, 20]);
+        assert_eq!(query.timestamp_min, Some(1_709_214_300_000_000_000));
+        assert_eq!(query.timestamp_max, Some(1_709_214_330_000_000_000));
+        assert_eq!(query.user_data_128.as_deref(), Some("ABC"));
+        assert_eq!(
+            (query.user_data_64, query.user_data_32),
+            (Some(64), Some(32))
+        );
+        assert_eq!(query.account.as_deref(), Some("ff"));
+        assert_eq!(query.direction, Direction::Credits);
+        assert_eq!(query.limit, 10);
+        assert!(query.reversed);
+
+        let query = params(&[("kind", "accounts")])
+            .query(&timestamps())
+            .unwrap();
+        assert_eq!(query.kind, QueryKind::Accounts);
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let cases = [
+            ("codes", "1,x", r#"codes: invalid code "x""#),
+            ("codes", "70000", r#"codes: invalid code "70000""#),
+            ("ledger", "-1", r#"ledger: invalid number "-1""#),
+            (
+                "user_data_64",
+                "1e3",
+                r#"user_data_64: invalid number "1e3""#,
+            ),
+            (
+                "user_data_32",
+                "4294967296",
+                r#"user_data_32: invalid number "4294967296""#,
+            ),
+            ("limit", "ten", r#"limit: invalid number "ten""#),
+            (
+                "direction",
+                "up",
+                r#"direction: expected both, debits or credits, got "up""#,
+            ),
+            (
+                "from",
+                "29/02/2024",
+                r#"from: invalid time "29/02/2024", e.g. 2024-02-29T13:45"#,
+            ),
+            (
+                "until",
+                "2024-02-30T00:00",
+                r#"until: invalid time "2024-02-30T00:00", e.g. 2024-02-29T13:45"#,
+            ),
+        ];
+        for (name, value, message) in cases {
+            let result = params(&[(name, value)]).query(&timestamps());
+            assert_eq!(result.unwrap_err(), message, "{}={}", name, value);
+        }
+        let result = params(&[("from", "2024-03-01T00:00"), ("until", "2024-02-29T23:59")])
+            .query(&timestamps());
+        assert_eq!(result.unwrap_err(), "from: after until");
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("AZaz09-_.~"), "AZaz09-_.~");
+        assert_eq!(encode("1, 2&x=é"), "1%2C%202%26x%3D%C3%A9");
+        assert_eq!(encode(""), "");
+    }
+
+    #[test]
+    fn test_page_url() {
+        let fields = [
+            ("kind", "accounts"),
+            ("codes", "1, 2"),
+            ("from", "2024-02-29T13:45"),
+            ("reversed", "true"),
+        ];
+        let url = params(&fields).page_url(42);
+        assert_eq!(
+            url,
+            "/api/v1/explore/results?after_timestamp=42&kind=accounts&codes=1%2C%202\
+             &from=2024-02-29T13%3A45&reversed=true"
+        );
+        // It reads back as the same form.
+        let page: ExploreParams = Query::try_from_uri(&url.parse().unwrap()).unwrap().0;
+        assert_eq!(page.after_timestamp, Some(42));
+        assert_eq!(page.page_url(42), url);
+    }
+
+    #[tokio::test]
+    async fn test_results() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[account(1, 1), account(2, 1)],
+            &[
+                transfer(10, 1, 2, 5, 1),
+                transfer(11, 1, 2, 5, 1),
+                transfer(12, 1, 2, 5, 1),
+            ],
+        );
+        let first = cluster.lookup_transfers(&[10]).unwrap()[0].timestamp;
+        let router = testing::router(testing::config(), &cluster);
+
+        // A full page links to the next.
+        let uri = "/api/v1/explore/results?ledger=1&limit=1";
+        let (status, body) = testing::get(&router, uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let next = format!(
+            "/api/v1/explore/results?after_timestamp={}&amp;ledger=1&amp;limit=1",
+            first
+        );
+        assert!(body.contains(&next), "{}", body);
+
+        // The last page does not.
+        let uri = "/api/v1/explore/results?ledger=1&limit=5";
+        let (_, body) = testing::get(&router, uri).await;
+        assert!(!body.contains("Load More"), "{}", body);
+
+        // Field and plan errors come back as the message, with a 422.
+        for (uri, message) in [
+            ("limit=0", "limit: must be 1 to"),
+            ("kind=accounts&account=ff", "account: only transfers"),
+            ("direction=debits", "direction: needs an account"),
+            ("codes=x", "codes: invalid code"),
+        ] {
+            let uri = format!("/api/v1/explore/results?{}", uri);
+            let (status, body) = testing::get(&router, &uri).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", uri);
+            assert!(body.contains(message), "{}: {}", uri, body);
+        }
+    }
+}
@@ -0,0 +1,863 @@
+//! Streaming NDJSON export handlers, and the paging behind streamed lists.
+//!
+//! The list endpoints return one page per request. These page through every
+//! matching record instead and stream one JSON object per line, writing each
+//! page as it arrives. A small channel sits between the paging task and the
+//! response body, so a slow reader holds back the queries rather than
+//! buffering the result set in memory.
+//!
+//! The list endpoints stream the same way (see [`list_framing`]) when
+//! `Accept` asks for `application/x-ndjson` or `text/csv`, or when their
+//! `limit` is more than one page; then the usual JSON object is written a
+//! page at a time. CSV has a header row and leaves out the currency display
+//! fields, as in snapshots.
+
+use crate::api::{ApiAccount, ApiTransfer};
+use crate::auth::Viewer;
+use crate::currency::Currencies;
+use crate::error::AppError;
+use crate::paging::Page;
+use crate::state::AppState;
+use crate::validate::{self, Valid, Validate};
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use futures_core::Stream;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tb_rs::{
+    AccountFilter, AccountFilterFlags, ClientError, QueryFilter, QueryFilterFlags, Transfer,
+};
+use tokio::sync::mpsc;
+
+/// Records fetched per query.
+pub const PAGE_SIZE: u32 = 1000;
+
+/// Pages buffered ahead of the response body.
+pub const PAGES_AHEAD: usize = 2;
+
+/// CSV columns of accounts, in [`ApiAccount`] order.
+pub const ACCOUNT_COLUMNS: [&str; 12] = [
+    "id",
+    "debits_pending",
+    "debits_posted",
+    "credits_pending",
+    "credits_posted",
+    "user_data_128",
+    "user_data_64",
+    "user_data_32",
+    "ledger",
+    "code",
+    "flags",
+    "timestamp",
+];
+
+/// CSV columns of transfers, in [`ApiTransfer`] order.
+pub const TRANSFER_COLUMNS: [&str; 13] = [
+    "id",
+    "debit_account_id",
+    "credit_account_id",
+    "amount",
+    "pending_id",
+    "user_data_128",
+    "user_data_64",
+    "user_data_32",
+    "timeout",
+    "ledger",
+    "code",
+    "flags",
+    "timestamp",
+];
+
+/// Query parameters for exports.
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// Filter by ledger.
+    pub ledger: Option<u32>,
+    /// Filter by code.
+    pub code: Option<u16>,
+    /// Return in reverse chronological order.
+    #[serde(default)]
+    pub reversed: bool,
+    /// Stop after this many records (default: all).
+    pub limit: Option<u64>,
+}
+
+impl Validate for ExportParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::ledger("ledger", self.ledger)?;
+        validate::code("code", self.code)?;
+        if self.limit == Some(0) {
+            return Err("limit: must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What to export or list.
+#[derive(Clone, Copy)]
+pub enum Records {
+    Accounts,
+    Transfers,
+}
+
+impl Records {
+    /// The key of the records in a list response.
+    fn key(self) -> &'static str {
+        match self {
+            Records::Accounts => "accounts",
+            Records::Transfers => "transfers",
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            Records::Accounts => &ACCOUNT_COLUMNS,
+            Records::Transfers => &TRANSFER_COLUMNS,
+        }
+    }
+}
+
+/// Export accounts as NDJSON.
+pub async fn export_accounts(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Valid(params): Valid<ExportParams>,
+) -> Response {
+    export(state, params, Records::Accounts)
+}
+
+/// Export transfers as NDJSON.
+pub async fn export_transfers(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Valid(params): Valid<ExportParams>,
+) -> Response {
+    export(state, params, Records::Transfers)
+}
+
+fn export(state: Arc<AppState>, params: ExportParams, records: Records) -> Response {
+    let filter = QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger: params.ledger.unwrap_or(0),
+        code: params.code.unwrap_or(0),
+        timestamp_min: 0,
+        timestamp_max: 0,
+        limit: PAGE_SIZE,
+        flags: if params.reversed {
+            QueryFilterFlags::REVERSED
+        } else {
+            QueryFilterFlags::empty()
+        },
+        reserved: [0; 6],
+    };
+    let query = match records {
+        Records::Accounts => Paged::Accounts(filter),
+        Records::Transfers => Paged::Transfers(filter),
+    };
+    stream(
+        state,
+        query,
+        params.limit.unwrap_or(u64::MAX),
+        Framing::Ndjson,
+    )
+}
+
+/// How a list endpoint should stream its response, if it should: as NDJSON
+/// or CSV when `Accept` prefers them, or as the usual JSON object when
+/// `limit` is more than one page. Fails with 406 if `Accept` takes none of
+/// the three.
+pub fn list_framing(
+    headers: &HeaderMap,
+    limit: u32,
+    records: Records,
+) -> Result<Option<Framing>, AppError> {
+    Ok(match negotiate(headers)? {
+        Format::Ndjson => Some(Framing::Ndjson),
+        Format::Csv => Some(Framing::Csv {
+            columns: records.columns(),
+        }),
+        Format::Json if limit > PAGE_SIZE => Some(Framing::Json { key: records.key() }),
+        Format::Json => None,
+    })
+}
+
+/// Formats a list can be written in.
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    Ndjson,
+    Csv,
+}
+
+/// Each format's media type, in order of preference.
+const FORMATS: [(Format, &str); 3] = [
+    (Format::Json, "application/json"),
+    (Format::Ndjson, "application/x-ndjson"),
+    (Format::Csv, "text/csv"),
+];
+
+/// The format `Accept` prefers, JSON if there is none.
+///
+/// A format's q-value is that of the most specific media range matching
+/// it. The highest wins; on a tie, the format named outright rather than
+/// by a wildcard, then the one whose range comes first.
+fn negotiate(headers: &HeaderMap) -> Result<Format, AppError> {
+    let ranges: Vec<&str> = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .collect();
+    if ranges.is_empty() {
+        return Ok(Format::Json);
+    }
+
+    let mut best = None;
+    for (format, media_type) in FORMATS {
+        let matched = ranges
+            .iter()
+            .enumerate()
+            .filter_map(|(i, range)| {
+                let (range, q) = media_range(range)?;
+                Some((specificity(range, media_type)?, q, i))
+            })
+            .max_by_key(|&(specificity, _, _)| specificity);
+        let Some((specificity, q, i)) = matched.filter(|&(_, q, _)| q > 0) else {
+            continue;
+        };
+        let rank = (q, specificity, Reverse(i));
+        if best.as_ref().is_none_or(|(_, best)| rank > *best) {
+            best = Some((format, rank));
+        }
+    }
+    best.map(|(format, _)| format).ok_or_else(|| {
+        AppError::NotAcceptable(
+            "Lists are application/json, application/x-ndjson or text/csv".to_string(),
+        )
+    })
+}
+
+/// Split a media range from `Accept` into its type and its q-value in
+/// thousandths; `None` if the q-value is invalid.
+fn media_range(range: &str) -> Option<(&str, u16)> {
+    let mut parts = range.split(';').map(str::trim);
+    let media_type = parts.next()?;
+    let mut q = 1000;
+    for param in parts {
+        if let Some((name, value)) = param.split_once('=') {
+            if name.trim().eq_ignore_ascii_case("q") {
+                let value: f32 = value.trim().parse().ok()?;
+                if !(0.0..=1.0).contains(&value) {
+                    return None;
+                }
+                q = (value * 1000.0).round() as u16;
+            }
+        }
+    }
+    Some((media_type, q))
+}
+
+/// How specifically media range `range` matches `media_type`: 2 if it
+/// names it, 1 for `type/*`, 0 for `*/*`; `None` if it doesn't.
+fn specificity(range: &str, media_type: &str) -> Option<u8> {
+    if range.eq_ignore_ascii_case(media_type) {
+        return Some(2);
+    }
+    let (range_type, range_subtype) = range.split_once('/')?;
+    let (media_type, _) = media_type.split_once('/')?;
+    match (range_type, range_subtype) {
+        ("*", "*") => Some(0),
+        (range_type, "*") if range_type.eq_ignore_ascii_case(media_type) => Some(1),
+        _ => None,
+    }
+}
+
+/// Check that a streamed list starts at `page`: a stream pages forward
+/// only, so it can't end before a timestamp.
+pub fn check_page(page: &Page) -> Result<(), AppError> {
+    if page.is_forward() {
+        Ok(())
+    } else {
+        Err(AppError::Unprocessable(format!(
+            "before_timestamp: not supported when streaming (NDJSON, CSV, or limit over {})",
+            PAGE_SIZE
+        )))
+    }
+}
+
+/// A query paged through by timestamp.
+#[derive(Clone, Copy)]
+pub enum Paged {
+    Accounts(QueryFilter),
+    Transfers(QueryFilter),
+    AccountTransfers(AccountFilter),
+}
+
+/// How streamed records are framed.
+#[derive(Clone, Copy)]
+pub enum Framing {
+    /// One JSON object per line.
+    Ndjson,
+    /// A header of `columns`, then one CSV row per record.
+    Csv { columns: &'static [&'static str] },
+    /// A list response: an object with the records in an array under
+    /// `key`, then `next_timestamp`.
+    Json { key: &'static str },
+}
+
+impl Paged {
+    fn set_limit(&mut self, limit: u32) {
+        match self {
+            Paged::Accounts(filter) | Paged::Transfers(filter) => filter.limit = limit,
+            Paged::AccountTransfers(filter) => filter.limit = limit,
+        }
+    }
+
+    /// Move past the record with timestamp `last`; false if nothing can
+    /// come after it.
+    fn advance(&mut self, last: u64) -> bool {
+        let (reversed, timestamp_min, timestamp_max) = match self {
+            Paged::Accounts(filter) | Paged::Transfers(filter) => (
+                filter.flags.contains(QueryFilterFlags::REVERSED),
+                &mut filter.timestamp_min,
+                &mut filter.timestamp_max,
+            ),
+            Paged::AccountTransfers(filter) => (
+                filter.flags.contains(AccountFilterFlags::REVERSED),
+                &mut filter.timestamp_min,
+                &mut filter.timestamp_max,
+            ),
+        };
+        if reversed {
+            // Zero would lift the bound instead.
+            if last <= 1 {
+                return false;
+            }
+            *timestamp_max = last - 1;
+        } else {
+            *timestamp_min = last + 1;
+        }
+        true
+    }
+
+    /// Run the query, returning each record encoded for `framing` and the
+    /// timestamp of the last.
+    async fn fetch(
+        &self,
+        state: &AppState,
+        framing: Framing,
+    ) -> Result<(Vec<Vec<u8>>, Option<u64>), ClientError> {
+        // CSV has no nesting, so no display fields.
+        let no_currencies = Currencies::default();
+        let currencies = match framing {
+            Framing::Csv { .. } => &no_currencies,
+            Framing::Ndjson | Framing::Json { .. } => &state.config.currencies,
+        };
+        let client = state.pool.get();
+        Ok(match *self {
+            Paged::Accounts(filter) => {
+                let page = client.query_accounts(filter).await?;
+                let records = page
+                    .iter()
+                    .map(|a| encode(&ApiAccount::from(a).with_display(currencies), framing))
+                    .collect();
+                (records, page.last().map(|a| a.timestamp))
+            }
+            Paged::Transfers(filter) => {
+                let page = client.query_transfers(filter).await?;
+                (
+                    transfers(&page, currencies, framing),
+                    page.last().map(|t| t.timestamp),
+                )
+            }
+            Paged::AccountTransfers(filter) => {
+                let page = client.get_account_transfers(filter).await?;
+                (
+                    transfers(&page, currencies, framing),
+                    page.last().map(|t| t.timestamp),
+                )
+            }
+        })
+    }
+}
+
+/// Stream up to `limit` records of `query` as they are fetched, a page at
+/// a time.
+pub fn stream(state: Arc<AppState>, query: Paged, limit: u64, framing: Framing) -> Response {
+    let (tx, rx) = mpsc::channel(PAGES_AHEAD);
+    tokio::spawn(async move {
+        if let Err(e) = send_pages(&state, query, limit, framing, &tx).await {
+            tracing::error!("Streaming records failed: {:?}", e);
+            // Ends the body early, so the client sees a broken transfer.
+            let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+        }
+    });
+    let content_type = match framing {
+        Framing::Ndjson => "application/x-ndjson",
+        Framing::Csv { .. } => "text/csv",
+        Framing::Json { .. } => "application/json",
+    };
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(Pages(rx)),
+    )
+        .into_response()
+}
+
+/// Query page after page, sending each to `tx` until the records or the
+/// reader run out.
+async fn send_pages(
+    state: &AppState,
+    mut query: Paged,
+    limit: u64,
+    framing: Framing,
+    tx: &mpsc::Sender<io::Result<Bytes>>,
+) -> Result<(), ClientError> {
+    let mut out = Vec::new();
+    match framing {
+        Framing::Ndjson => {}
+        Framing::Csv { columns } => out = csv_line(columns),
+        Framing::Json { key } => write!(out, "{{\"{}\":[", key).expect("writing to a Vec succeeds"),
+    }
+    let mut remaining = limit;
+    let mut first = true;
+    let mut next_timestamp = None;
+
+    while remaining > 0 {
+        let page_limit = remaining.min(PAGE_SIZE as u64) as u32;
+        query.set_limit(page_limit);
+        let (records, last) = query.fetch(state, framing).await?;
+        let count = records.len();
+        for record in records {
+            match framing {
+                Framing::Ndjson => {
+                    out.extend_from_slice(&record);
+                    out.push(b'\n');
+                }
+                // Rows end in a newline already.
+                Framing::Csv { .. } => out.extend_from_slice(&record),
+                Framing::Json { .. } => {
+                    if !first {
+                        out.push(b',');
+                    }
+                    out.extend_from_slice(&record);
+                }
+            }
+            first = false;
+        }
+        if !out.is_empty() {
+            let page = Bytes::from(std::mem::take(&mut out));
+            if tx.send(Ok(page)).await.is_err() {
+                // The reader went away.
+                return Ok(());
+            }
+        }
+        remaining -= count as u64;
+        next_timestamp = last.or(next_timestamp);
+
+        // A short page is the last one.
+        let Some(last) = last.filter(|_| count as u32 == page_limit) else {
+            break;
+        };
+        if !query.advance(last) {
+            break;
+        }
+    }
+
+    if let Framing::Json { .. } = framing {
+        out.push(b']');
+        if let Some(timestamp) = next_timestamp {
+            write!(out, ",\"next_timestamp\":{}", timestamp).expect("writing to a Vec succeeds");
+        }
+        out.push(b'}');
+        let _ = tx.send(Ok(Bytes::from(out))).await;
+    }
+    Ok(())
+}
+
+fn transfers(page: &[Transfer], currencies: &Currencies, framing: Framing) -> Vec<Vec<u8>> {
+    page.iter()
+        .map(|t| encode(&ApiTransfer::from(t).with_display(currencies), framing))
+        .collect()
+}
+
+/// `record` as JSON, or as a CSV row if `framing` is CSV.
+fn encode<T: serde::Serialize>(record: &T, framing: Framing) -> Vec<u8> {
+    match framing {
+        Framing::Csv { .. } => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            writer.serialize(record).expect("API types serialize");
+            writer.into_inner().expect("writing to a Vec succeeds")
+        }
+        Framing::Ndjson | Framing::Json { .. } => {
+            serde_json::to_vec(record).expect("API types serialize")
+        }
+    }
+}
+
+/// The CSV row of `cells`.
+pub fn csv_line(cells: &[&str]) -> Vec<u8> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(cells)
+        .expect("writing to a Vec succeeds");
+    writer.into_inner().expect("writing to a Vec succeeds")
+}
+
+/// Pages from the paging task, as a response body stream.
+pub struct Pages(pub mpsc::Receiver<io::Result<Bytes>>);
+
+impl Stream for Pages {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeCluster};
+    use axum::body::Body;
+    use axum::http::{HeaderValue, Request};
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn filter(ledger: u32, flags: QueryFilterFlags) -> QueryFilter {
+        QueryFilter {
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            ledger,
+            code: 0,
+            timestamp_min: 0,
+            timestamp_max: 0,
+            limit: 0,
+            flags,
+            reserved: [0; 6],
+        }
+    }
+
+    /// A cluster with `count` accounts on ledger 1, IDs counting from 1.
+    fn cluster(count: u128) -> Arc<FakeCluster> {
+        let cluster = FakeCluster::new();
+        let accounts: Vec<_> = (1..=count).map(|id| testing::account(id, 1)).collect();
+        cluster.seed(&accounts, &[]);
+        cluster
+    }
+
+    /// The body streamed for `query`, or the error that broke it off.
+    async fn streamed(
+        cluster: &Arc<FakeCluster>,
+        query: Paged,
+        limit: u64,
+        framing: Framing,
+    ) -> Result<String, axum::Error> {
+        let state = testing::state(testing::config(), cluster);
+        let response = stream(state, query, limit, framing);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        Ok(String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// The account IDs of an NDJSON body.
+    fn ids(body: &str) -> Vec<u128> {
+        body.lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                u128::from_str_radix(record["id"].as_str().unwrap(), 16).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_list_framing() {
+        let framing = |headers: &HeaderMap, limit| {
+            list_framing(headers, limit, Records::Transfers).map(|framing| match framing {
+                None => "buffered".to_string(),
+                Some(Framing::Ndjson) => "ndjson".to_string(),
+                Some(Framing::Csv { columns }) => format!("csv {}", columns.len()),
+                Some(Framing::Json { key }) => format!("json {}", key),
+            })
+        };
+        let none = HeaderMap::new();
+        for (headers, limit, expected) in [
+            (&none, 10, "buffered"),
+            (&none, PAGE_SIZE, "buffered"),
+            (&none, PAGE_SIZE + 1, "json transfers"),
+            (&accept("application/json"), PAGE_SIZE + 1, "json transfers"),
+            (&accept("application/x-ndjson"), 10, "ndjson"),
+            (&accept("text/csv"), 10, "csv 13"),
+            (&accept("text/*"), 10, "csv 13"),
+            (&accept("*/*"), 10, "buffered"),
+            // The highest q-value wins, then the most specific range.
+            (&accept("application/json;q=0.5, text/csv"), 10, "csv 13"),
+            (&accept("application/*, application/x-ndjson"), 10, "ndjson"),
+            (&accept("text/csv;q=0, */*;q=0.1"), 10, "buffered"),
+            // Ranges with an invalid q-value are ignored.
+            (&accept("text/csv;q=2, application/x-ndjson"), 10, "ndjson"),
+        ] {
+            let accept = headers.get(header::ACCEPT);
+            assert_eq!(
+                framing(headers, limit).unwrap(),
+                expected,
+                "{:?} {}",
+                accept,
+                limit
+            );
+        }
+
+        for value in ["text/html", "application/json;q=0, text/csv;q=0"] {
+            let error = framing(&accept(value), 10).unwrap_err();
+            assert_eq!(error.status().1, "not_acceptable", "{}", value);
+        }
+        let columns = match list_framing(&accept("text/csv"), 10, Records::Accounts) {
+            Ok(Some(Framing::Csv { columns })) => columns,
+            _ => panic!("expected CSV"),
+        };
+        assert_eq!(columns, ACCOUNT_COLUMNS);
+    }
+
+    #[test]
+    fn test_check_page() {
+        assert!(check_page(&Page::new(false, None, None)).is_ok());
+        assert!(check_page(&Page::new(true, Some(5), None)).is_ok());
+        let error = check_page(&Page::new(false, None, Some(5))).unwrap_err();
+        assert_eq!(error.status().1, "invalid_input");
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut query = Paged::Accounts(filter(1, QueryFilterFlags::empty()));
+        assert!(query.advance(10));
+        let Paged::Accounts(forward) = query else {
+            unreachable!()
+        };
+        assert_eq!((forward.timestamp_min, forward.timestamp_max), (11, 0));
+
+        let mut query = Paged::Transfers(filter(1, QueryFilterFlags::REVERSED));
+        assert!(query.advance(10));
+        let Paged::Transfers(reversed) = query else {
+            unreachable!()
+        };
+        assert_eq!((reversed.timestamp_min, reversed.timestamp_max), (0, 9));
+
+        // Nothing comes before the first timestamp.
+        assert!(!query.advance(1));
+        assert!(!query.advance(0));
+    }
+
+    #[tokio::test]
+    async fn test_stream_pages_through_everything() {
+        let count = 2 * PAGE_SIZE as u128 + 1;
+        let cluster = cluster(count);
+        let body = streamed(
+            &cluster,
+            Paged::Accounts(filter(1, QueryFilterFlags::empty())),
+            u64::MAX,
+            Framing::Ndjson,
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(&body), (1..=count).collect::<Vec<_>>());
+
+        let body = streamed(
+            &cluster,
+            Paged::Accounts(filter(1, QueryFilterFlags::REVERSED)),
+            u64::MAX,
+            Framing::Ndjson,
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(&body), (1..=count).rev().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_on_empty_page() {
+        // A full last page is followed by an empty one.
+        let cluster = cluster(PAGE_SIZE as u128);
+        let body = streamed(
+            &cluster,
+            Paged::Accounts(filter(1, QueryFilterFlags::empty())),
+            u64::MAX,
+            Framing::Json { key: "accounts" },
+        )
+        .await
+        .unwrap();
+        let list: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let accounts = list["accounts"].as_array().unwrap();
+        assert_eq!(accounts.len(), PAGE_SIZE as usize);
+        assert_eq!(
+            list["next_timestamp"],
+            accounts.last().unwrap()["timestamp"]
+        );
+
+        // Nothing at all.
+        let body = streamed(
+            &cluster,
+            Paged::Accounts(filter(2, QueryFilterFlags::empty())),
+            u64::MAX,
+            Framing::Json { key: "accounts" },
+        )
+        .await
+        .unwrap();
+        assert_eq!(body, r#"{"accounts":[]}"#);
+        let body = streamed(
+            &cluster,
+            Paged::Accounts(filter(2, QueryFilterFlags::empty())),
+            u64::MAX,
+            Framing::Csv {
+                columns: &ACCOUNT_COLUMNS,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(body, format!("{}\n", ACCOUNT_COLUMNS.join(",")));
+    }
+
+    #[tokio::test]
+    async fn test_stream_stops_at_limit() {
+        let cluster = cluster(PAGE_SIZE as u128 + 10);
+        for limit in [1, 5, PAGE_SIZE as u64, PAGE_SIZE as u64 + 3] {
+            let body = streamed(
+                &cluster,
+                Paged::Accounts(filter(1, QueryFilterFlags::empty())),
+                limit,
+                Framing::Ndjson,
+            )
+            .await
+            .unwrap();
+            assert_eq!(ids(&body), (1..=limit as u128).collect::<Vec<_>>());
+        }
+
+        let body = streamed(
+            &cluster,
+            Paged::Accounts(filter(1, QueryFilterFlags::empty())),
+            2,
+            Framing::Csv {
+                columns: &ACCOUNT_COLUMNS,
+            },
+        )
+        .await
+        .unwrap();
+        let rows: Vec<&str> = body.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[2].starts_with(&format!("{:032x},", 2)), "{}", rows[2]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_stops_when_reader_goes() {
+        let cluster = cluster(2 * PAGE_SIZE as u128);
+        let state = testing::state(testing::config(), &cluster);
+        let (tx, rx) = mpsc::channel(PAGES_AHEAD);
+        drop(rx);
+        let query = Paged::Accounts(filter(1, QueryFilterFlags::empty()));
+        send_pages(&state, query, u64::MAX, Framing::Ndjson, &tx)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_breaks_body() {
+        let cluster = cluster(3);
+        cluster.fail_next(ClientError::Shutdown);
+        let query = Paged::Accounts(filter(1, QueryFilterFlags::empty()));
+        assert!(streamed(&cluster, query, u64::MAX, Framing::Ndjson)
+            .await
+            .is_err());
+    }
+
+    /// Ask `router` for `uri` as JSON, returning the content type and body.
+    async fn get_json(router: &axum::Router, uri: &str) -> (String, serde_json::Value) {
+        let request = Request::get(uri)
+            .header(header::ACCEPT, "application/json")
+            .body(Body::empty())
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(router.clone(), request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{}", uri);
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (content_type, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_lists_stream_over_one_page() {
+        let count = PAGE_SIZE as u128 + 1;
+        let cluster = cluster(count);
+        let transfers: Vec<_> = (1..=count)
+            .map(|id| testing::transfer(id, 1, 2, 1, 1))
+            .collect();
+        cluster.seed(&[], &transfers);
+        let router = testing::router(testing::config(), &cluster);
+
+        for (uri, key) in [
+            ("/api/v1/accounts", "accounts"),
+            ("/api/v1/transfers", "transfers"),
+            ("/api/v1/accounts/1/transfers", "transfers"),
+        ] {
+            // One page is buffered, with cursors to the next.
+            let (_, page) = get_json(&router, &format!("{}?limit={}", uri, PAGE_SIZE)).await;
+            assert_eq!(page[key].as_array().unwrap().len(), PAGE_SIZE as usize);
+            assert!(page["next_cursor"].is_u64(), "{}: {}", uri, page);
+
+            // Any more is streamed in pages, with no cursors.
+            let (content_type, list) =
+                get_json(&router, &format!("{}?limit={}", uri, PAGE_SIZE + 1)).await;
+            assert_eq!(content_type, "application/json");
+            let records = list[key].as_array().unwrap();
+            assert_eq!(records.len(), PAGE_SIZE as usize + 1, "{}", uri);
+            assert_eq!(list["next_timestamp"], records.last().unwrap()["timestamp"]);
+            assert!(list.get("next_cursor").is_none(), "{}", uri);
+            let ids: Vec<u128> = records
+                .iter()
+                .map(|r| u128::from_str_radix(r["id"].as_str().unwrap(), 16).unwrap())
+                .collect();
+            assert!(ids.windows(2).all(|w| w[0] < w[1]), "{}", uri);
+        }
+
+        // The whole list, newest first, in fewer records than the limit.
+        let (_, list) = get_json(&router, "/api/v1/transfers?limit=5000&reversed=true").await;
+        let records = list["transfers"].as_array().unwrap();
+        assert_eq!(records.len(), count as usize);
+        assert_eq!(records[0]["id"], format!("{:032x}", count));
+
+        // A stream can't page backwards.
+        let before = format!(
+            "/api/v1/transfers?limit={}&before_timestamp={}",
+            PAGE_SIZE + 1,
+            u64::MAX
+        );
+        let (status, body) = testing::get(&router, &before).await;
+        assert_eq!(status, 422, "{}", body);
+        let before = format!(
+            "/api/v1/transfers?limit={}&before_timestamp={}",
+            PAGE_SIZE,
+            u64::MAX
+        );
+        let (status, body) = testing::get(&router, &before).await;
+        assert_eq!(status, 200, "{}", body);
+    }
+}
@@ -1,7 +1,8 @@
 //! Frontend asset serving.
 
+use crate::routes::ledgers::LedgerBrowseParams;
 use axum::body::Body;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::{header, StatusCode, Uri};
 use axum::response::{Html, IntoResponse, Response};
 use rust_embed::{Embed, EmbeddedFile};
@@ -34,6 +35,21 @@ pub async fn serve_transfer_page(Path(id): Path<String>) -> impl IntoResponse {
     Html(generate_detail_page(&format!("/api/v1/transfers/{}", id), "transfer"))
 }
 
+/// Serve ledger browse page with HTMX auto-loading, keeping the filters in
+/// its query string.
+pub async fn serve_ledger_page(
+    Path(id): Path<String>,
+    Query(params): Query<LedgerBrowseParams>,
+) -> Response {
+    match (id.parse::<u32>(), params.filters()) {
+        (Ok(ledger), Ok(filters)) => {
+            let api_url = format!("/api/v1/ledgers/{}/browse{}", ledger, filters.query());
+            Html(generate_detail_page(&api_url, "ledger")).into_response()
+        }
+        _ => (StatusCode::NOT_FOUND, "Not Found").into_response(),
+    }
+}
+
 /// Generate an HTML page that loads detail content via HTMX.
 fn generate_detail_page(api_url: &str, page_type: &str) -> String {
     format!(
@@ -55,7 +71,14 @@ fn generate_detail_page(api_url: &str, page_type: &str) -> String {
                 <a href="/" class="nav-link">Dashboard</a>
                 <a href="/accounts" class="nav-link">Accounts</a>
                 <a href="/transfers" class="nav-link">Transfers</a>
+                <a href="/pending" class="nav-link">Pending</a>
+                <a href="/queries" class="nav-link">Queries</a>
+                <a href="/explore" class="nav-link">Explore</a>
             </nav>
+            <form class="id-search" hx-get="/api/v1/resolve" hx-target="#id-search-results">
+                <input type="search" name="id_prefix" placeholder="Find ID, e.g. abcd1234..." aria-label="Find ID">
+                <div id="id-search-results"></div>
+            </form>
         </header>
 
         <main id="main">
@@ -0,0 +1,244 @@
+//! Transfer graph handler.
+
+use crate::api::{GraphEdge, GraphNode, GraphResponse};
+use crate::auth::Viewer;
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::validate::{self, Valid, Validate};
+use axum::extract::State;
+use axum::Json;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tb_rs::{QueryFilter, QueryFilterFlags};
+
+/// Transfers fetched per query.
+const PAGE_SIZE: u32 = 1000;
+
+/// Query parameters for the transfer graph.
+#[derive(Debug, Deserialize)]
+pub struct GraphParams {
+    /// Filter by ledger.
+    pub ledger: Option<u32>,
+    /// Number of most recent transfers to aggregate.
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_limit() -> u32 {
+    10_000
+}
+
+impl Validate for GraphParams {
+    fn validate(&mut self) -> Result<(), String> {
+        // The most transfers is configured, so the handler checks that.
+        validate::ledger("ledger", self.ledger)?;
+        validate::limit(&mut self.limit, u32::MAX)
+    }
+}
+
+/// Money flow between accounts: one node per account and one edge per
+/// debit/credit account pair, totalling the most recent `limit` transfers.
+pub async fn get_graph(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Valid(params): Valid<GraphParams>,
+) -> Result<Json<GraphResponse>, AppError> {
+    let limit_max = state.config.graph_transfers_max;
+    if params.limit > limit_max {
+        return Err(AppError::Unprocessable(format!(
+            "limit: must be 1 to {}",
+            limit_max
+        )));
+    }
+
+    let mut filter = QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger: params.ledger.unwrap_or(0),
+        code: 0,
+        timestamp_min: 0,
+        timestamp_max: 0,
+        limit: PAGE_SIZE,
+        flags: QueryFilterFlags::REVERSED,
+        reserved: [0; 6],
+    };
+
+    // Keyed by (debit, credit) account; sums saturate rather than wrap.
+    let mut edges: BTreeMap<(u128, u128), (u128, u64)> = BTreeMap::new();
+    let mut transfers = 0u32;
+    while transfers < params.limit {
+        filter.limit = PAGE_SIZE.min(params.limit - transfers);
+        let page = {
+            let client = state.pool.get();
+            client.query_transfers(filter).await?
+        };
+        for transfer in &page {
+            let edge = edges
+                .entry((transfer.debit_account_id, transfer.credit_account_id))
+                .or_default();
+            edge.0 = edge.0.saturating_add(transfer.amount);
+            edge.1 += 1;
+        }
+        transfers += page.len() as u32;
+
+        // A short page is the last one; zero would lift the bound.
+        match page.last() {
+            Some(last) if page.len() == filter.limit as usize && last.timestamp > 1 => {
+                filter.timestamp_max = last.timestamp - 1;
+            }
+            _ => break,
+        }
+    }
+
+    let mut nodes: BTreeMap<u128, (u128, u128)> = BTreeMap::new();
+    for (&(debit, credit), &(amount, _)) in &edges {
+        let node = nodes.entry(debit).or_default();
+        node.0 = node.0.saturating_add(amount);
+        let node = nodes.entry(credit).or_default();
+        node.1 = node.1.saturating_add(amount);
+    }
+
+    Ok(Json(GraphResponse {
+        nodes: nodes
+            .into_iter()
+            .map(|(id, (debits, credits))| GraphNode {
+                id: format!("{:032x}", id),
+                debits: debits.to_string(),
+                credits: credits.to_string(),
+            })
+            .collect(),
+        edges: edges
+            .into_iter()
+            .map(|((debit, credit), (amount, count))| GraphEdge {
+                source: format!("{:032x}", debit),
+                target: format!("{:032x}", credit),
+                amount: amount.to_string(),
+                transfers: count,
+            })
+            .collect(),
+        transfers,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, account, transfer, FakeCluster};
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+    use tb_rs::ClientError;
+
+    async fn get_json(router: &axum::Router, query: &str) -> (StatusCode, Value) {
+        let (status, body) = testing::get(router, &format!("/api/v1/graph?{}", query)).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn hex(id: u128) -> String {
+        format!("{:032x}", id)
+    }
+
+    #[tokio::test]
+    async fn test_graph() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[
+                account(1, 1),
+                account(2, 1),
+                account(3, 1),
+                account(4, 2),
+                account(5, 2),
+            ],
+            &[
+                transfer(10, 1, 2, 5, 1),
+                transfer(11, 4, 5, 100, 2),
+                transfer(12, 1, 2, 7, 1),
+                transfer(13, 2, 3, 3, 1),
+            ],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        // Transfers between the same pair of accounts make one edge.
+        let (status, response) = get_json(&router, "ledger=1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response,
+            json!({
+                "nodes": [
+                    {"id": hex(1), "debits": "12", "credits": "0"},
+                    {"id": hex(2), "debits": "3", "credits": "12"},
+                    {"id": hex(3), "debits": "0", "credits": "3"},
+                ],
+                "edges": [
+                    {"source": hex(1), "target": hex(2), "amount": "12", "transfers": 2},
+                    {"source": hex(2), "target": hex(3), "amount": "3", "transfers": 1},
+                ],
+                "transfers": 3,
+            })
+        );
+
+        // Without a ledger, every ledger.
+        let (_, response) = get_json(&router, "").await;
+        assert_eq!(response["nodes"].as_array().unwrap().len(), 5);
+        assert_eq!(response["transfers"], 4);
+
+        // The most recent transfers.
+        let (_, response) = get_json(&router, "ledger=1&limit=1").await;
+        assert_eq!(
+            response["edges"],
+            json!([{"source": hex(2), "target": hex(3), "amount": "3", "transfers": 1}])
+        );
+        assert_eq!(response["transfers"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_graph_pages() {
+        let cluster = FakeCluster::new();
+        let transfers: Vec<_> = (0..PAGE_SIZE as u128 * 2 + 10)
+            .map(|id| transfer(id + 1, 1, 2, 1, 1))
+            .collect();
+        cluster.seed(&[account(1, 1), account(2, 1)], &transfers);
+        let router = testing::router(testing::config(), &cluster);
+
+        for (limit, total) in [
+            (PAGE_SIZE, PAGE_SIZE),
+            (PAGE_SIZE + 1, PAGE_SIZE + 1),
+            (PAGE_SIZE * 3, PAGE_SIZE * 2 + 10),
+        ] {
+            let (_, response) = get_json(&router, &format!("limit={}", limit)).await;
+            assert_eq!(response["transfers"], total, "{}", limit);
+            assert_eq!(response["edges"][0]["transfers"], total, "{}", limit);
+            assert_eq!(
+                response["edges"][0]["amount"],
+                total.to_string(),
+                "{}",
+                limit
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_graph_errors() {
+        let cluster = FakeCluster::new();
+        let mut config = testing::config();
+        config.graph_transfers_max = 10;
+        let router = testing::router(config, &cluster);
+
+        for (query, error) in [
+            ("ledger=0", "ledger: must be 1 to 4294967295"),
+            ("limit=0", "limit: must be at least 1"),
+            ("limit=11", "limit: must be 1 to 10"),
+        ] {
+            let (status, response) = get_json(&router, query).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", query);
+            assert_eq!(response["error"], error, "{}", query);
+        }
+
+        let (status, _) = get_json(&router, "limit=10").await;
+        assert_eq!(status, StatusCode::OK);
+        cluster.fail_next(ClientError::Shutdown);
+        let (status, _) = get_json(&router, "limit=10").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
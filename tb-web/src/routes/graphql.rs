@@ -0,0 +1,20 @@
+//! GraphQL route handler.
+
+use crate::auth::Viewer;
+use crate::state::AppState;
+use axum::extract::State;
+use axum::Json;
+use std::sync::Arc;
+
+/// Execute a GraphQL query (see [`crate::graphql`]).
+///
+/// Errors are reported in the response's `errors`, with status 200, as
+/// GraphQL clients expect.
+pub async fn graphql(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = request.data(state.clone());
+    Json(state.graphql.execute(request).await)
+}
@@ -0,0 +1,660 @@
+//! Bulk import handler.
+//!
+//! `POST /api/v1/import?kind=accounts` (or `kind=transfers`) takes a
+//! multipart upload whose `file` field holds records in CSV, with a header
+//! row naming the fields, or NDJSON. Either way a record has the fields of
+//! a create request item; in CSV an empty cell leaves a field unset and
+//! `flags` separates names with `|`. The format comes from `format=csv` or
+//! `format=ndjson`, else from the file name or content type.
+//!
+//! Uploads are limited to `--import-max-bytes`, apart from the smaller
+//! limit on other requests. The upload is parsed as it arrives and created
+//! in batches as large as the cluster accepts, so memory holds one batch
+//! and the report rather than the file. A batch never splits a linked
+//! chain; a chain with an invalid record is not submitted, its other
+//! records failing with `linked_event_failed`. The report, as JSON or,
+//! with `report=csv`, as a CSV download, counts every row but lists the
+//! results of only the first `--import-report-rows-max`; the JSON report
+//! is then marked `truncated`. A client error stops the import part way
+//! through; uploading the same file again reports the rows already created
+//! as `exists`.
+
+use crate::api::{codes, ImportReport, ImportRow, NewAccount, NewTransfer};
+use crate::auth::{self, Role};
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::validate::{self, Valid, Validate};
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::{Multipart, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::future::Future;
+use std::sync::Arc;
+use tb_rs::{Account, Transfer};
+
+/// CSV columns holding numbers rather than strings.
+const NUMERIC_FIELDS: &[&str] = &["user_data_64", "user_data_32", "ledger", "code", "timeout"];
+
+/// Query parameters for imports.
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    /// What the upload holds.
+    pub kind: ImportKind,
+    /// Upload format (default: from the file name or content type).
+    pub format: Option<ImportFormat>,
+    /// Report format.
+    #[serde(default)]
+    pub report: ReportFormat,
+}
+
+impl Validate for ImportParams {}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportKind {
+    Accounts,
+    Transfers,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Import accounts (admin) or transfers (operator) from an upload.
+pub async fn import(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Valid(params): Valid<ImportParams>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    let role = match params.kind {
+        ImportKind::Accounts => Role::Admin,
+        ImportKind::Transfers => Role::Operator,
+    };
+    auth::authorize(&headers, &state, role).await?;
+
+    let field = loop {
+        match multipart
+            .next_field()
+            .await
+            .map_err(|e| bad_upload(&state, e))?
+        {
+            Some(field) if field.name() == Some("file") => break field,
+            Some(_) => continue,
+            None => return Err(AppError::BadRequest("No 'file' field in the upload".into())),
+        }
+    };
+    let format = match params.format {
+        Some(format) => format,
+        None => infer_format(&field)?,
+    };
+
+    let report = match params.kind {
+        ImportKind::Accounts => run::<Account>(&state, field, format).await?,
+        ImportKind::Transfers => run::<Transfer>(&state, field, format).await?,
+    };
+
+    let status = if report.failed == 0 {
+        StatusCode::CREATED
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    match params.report {
+        ReportFormat::Json => Ok((status, Json(report)).into_response()),
+        ReportFormat::Csv => Ok((
+            status,
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"import-report.csv\"",
+                ),
+            ],
+            report_csv(&report)?,
+        )
+            .into_response()),
+    }
+}
+
+fn bad_upload(state: &AppState, e: MultipartError) -> AppError {
+    match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(format!(
+            "Upload over {} bytes",
+            state.config.import_max_bytes
+        )),
+        _ => AppError::BadRequest(format!("Reading the upload failed: {}", e.body_text())),
+    }
+}
+
+fn infer_format(field: &Field<'_>) -> Result<ImportFormat, AppError> {
+    let name = field.file_name().unwrap_or_default().to_ascii_lowercase();
+    let content_type = field.content_type().unwrap_or_default();
+    if name.ends_with(".csv") || content_type == "text/csv" {
+        Ok(ImportFormat::Csv)
+    } else if name.ends_with(".ndjson")
+        || name.ends_with(".jsonl")
+        || content_type == "application/x-ndjson"
+    {
+        Ok(ImportFormat::Ndjson)
+    } else {
+        Err(AppError::BadRequest(
+            "Can't tell the upload's format, pass format=csv or format=ndjson".into(),
+        ))
+    }
+}
+
+/// A record that can be imported.
+trait Record: Sized + Send + Sync {
+    /// The create request item it's parsed from.
+    type New: DeserializeOwned + Send;
+
+    const NAME: &'static str;
+
+    fn id(&self) -> u128;
+
+    fn from_new(new: &Self::New) -> Result<Self, String>;
+
+    /// Create `batch`, returning (index, result name) of each failure.
+    fn create(
+        state: &AppState,
+        batch: &[Self],
+    ) -> impl Future<Output = Result<Vec<(u32, &'static str)>, AppError>> + Send;
+}
+
+impl Record for Account {
+    type New = NewAccount;
+
+    const NAME: &'static str = "accounts";
+
+    fn id(&self) -> u128 {
+        self.id
+    }
+
+    fn from_new(new: &NewAccount) -> Result<Self, String> {
+        Account::try_from(new)
+    }
+
+    async fn create(
+        state: &AppState,
+        batch: &[Self],
+    ) -> Result<Vec<(u32, &'static str)>, AppError> {
+        let failures = {
+            let client = state.pool.get();
+            client.create_accounts(batch).await?
+        };
+        let ids: Vec<u128> = batch.iter().map(|a| a.id).collect();
+        state.cache.accounts_created(&ids).await;
+        Ok(failures
+            .iter()
+            .map(|f| (f.index, codes::account_result(f.result)))
+            .collect())
+    }
+}
+
+impl Record for Transfer {
+    type New = NewTransfer;
+
+    const NAME: &'static str = "transfers";
+
+    fn id(&self) -> u128 {
+        self.id
+    }
+
+    fn from_new(new: &NewTransfer) -> Result<Self, String> {
+        Transfer::try_from(new)
+    }
+
+    async fn create(
+        state: &AppState,
+        batch: &[Self],
+    ) -> Result<Vec<(u32, &'static str)>, AppError> {
+        let failures = {
+            let client = state.pool.get();
+            client.create_transfers(batch).await?
+        };
+        state.cache.transfers_created();
+        Ok(failures
+            .iter()
+            .map(|f| (f.index, codes::transfer_result(f.result)))
+            .collect())
+    }
+}
+
+/// Read the upload in `field` line by line and import its records.
+async fn run<T: Record>(
+    state: &AppState,
+    mut field: Field<'_>,
+    format: ImportFormat,
+) -> Result<ImportReport, AppError> {
+    let batch_max = validate::max_events::<T>(state)?;
+    let mut import = Import::<T>::new(state, format, batch_max as usize);
+
+    let mut pending: Vec<u8> = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(|e| bad_upload(state, e))? {
+        pending.extend_from_slice(&chunk);
+        let mut start = 0;
+        while let Some(end) = pending[start..].iter().position(|&b| b == b'\n') {
+            import.line(&pending[start..start + end]).await?;
+            start += end + 1;
+        }
+        pending.drain(..start);
+    }
+    if !pending.is_empty() {
+        import.line(&pending).await?;
+    }
+    import.finish().await
+}
+
+/// An import in progress.
+struct Import<'a, T> {
+    state: &'a AppState,
+    format: ImportFormat,
+    batch_max: usize,
+    /// CSV column names, once the header is read.
+    header: Option<Vec<String>>,
+    /// Lines of a CSV record with a quoted newline, so far.
+    partial: Vec<u8>,
+    /// Records waiting to be created, and their rows.
+    batch: Vec<T>,
+    batch_rows: Vec<u64>,
+    /// Where the linked chain still open at the end of `batch` starts.
+    chain_start: Option<usize>,
+    /// Whether the open linked chain has failed, so its remaining records
+    /// are not created.
+    chain_failed: bool,
+    rows: u64,
+    report: Report,
+}
+
+impl<'a, T: Record> Import<'a, T> {
+    fn new(state: &'a AppState, format: ImportFormat, batch_max: usize) -> Self {
+        Self {
+            state,
+            format,
+            batch_max,
+            header: None,
+            partial: Vec::new(),
+            batch: Vec::with_capacity(batch_max),
+            batch_rows: Vec::with_capacity(batch_max),
+            chain_start: None,
+            chain_failed: false,
+            rows: 0,
+            report: Report::new(state.config.import_report_rows_max),
+        }
+    }
+
+    async fn line(&mut self, line: &[u8]) -> Result<(), AppError> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = if self.rows == 0 && self.header.is_none() && self.partial.is_empty() {
+            line.strip_prefix("\u{feff}".as_bytes()).unwrap_or(line)
+        } else {
+            line
+        };
+
+        match self.format {
+            ImportFormat::Ndjson => {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    return Ok(());
+                }
+                let fields =
+                    serde_json::from_slice::<Map<String, Value>>(line).map_err(|e| e.to_string());
+                self.record(fields).await
+            }
+            ImportFormat::Csv => {
+                if self.partial.is_empty() && line.iter().all(u8::is_ascii_whitespace) {
+                    return Ok(());
+                }
+                if !self.partial.is_empty() {
+                    self.partial.push(b'\n');
+                }
+                self.partial.extend_from_slice(line);
+                // An odd number of quotes leaves a quoted cell open.
+                if self.partial.iter().filter(|&&b| b == b'"').count() % 2 == 1 {
+                    return Ok(());
+                }
+                let text = std::mem::take(&mut self.partial);
+                self.csv_record(&text).await
+            }
+        }
+    }
+
+    async fn csv_record(&mut self, text: &[u8]) -> Result<(), AppError> {
+        let record = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .trim(csv::Trim::All)
+            .from_reader(text)
+            .records()
+            .next()
+            .unwrap_or_else(|| Ok(csv::StringRecord::new()))
+            .map_err(|e| e.to_string());
+
+        let Some(header) = &self.header else {
+            let header = record.map_err(|e| AppError::BadRequest(format!("CSV header: {}", e)))?;
+            self.header = Some(header.iter().map(str::to_string).collect());
+            return Ok(());
+        };
+        let fields = record.and_then(|record| {
+            if record.len() != header.len() {
+                return Err(format!(
+                    "{} cells, the header has {}",
+                    record.len(),
+                    header.len()
+                ));
+            }
+            Ok(header
+                .iter()
+                .zip(record.iter())
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|(name, cell)| (name.clone(), csv_value(name, cell)))
+                .collect())
+        });
+        self.record(fields).await
+    }
+
+    /// Import the next record, given as its fields.
+    async fn record(&mut self, fields: Result<Map<String, Value>, String>) -> Result<(), AppError> {
+        self.rows += 1;
+        let max = self.state.config.import_rows_max;
+        if self.rows > max {
+            return Err(AppError::BadRequest(format!(
+                "Stopped after {} rows, the most one import takes; the rows before were imported",
+                max
+            )));
+        }
+        let row = self.rows;
+
+        // A record whose fields parsed counts towards its chain even if
+        // it's invalid, so a bad record fails the chain rather than
+        // splitting it.
+        let linked = fields.as_ref().is_ok_and(|fields| {
+            fields
+                .get("flags")
+                .and_then(Value::as_array)
+                .is_some_and(|flags| flags.iter().any(|flag| flag == "linked"))
+        });
+        let record = fields.and_then(|fields| {
+            let new: T::New =
+                serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())?;
+            T::from_new(&new)
+        });
+
+        match record {
+            Ok(record) if self.chain_failed => {
+                self.report.push(ImportRow {
+                    row,
+                    id: Some(format!("{:032x}", record.id())),
+                    result: "linked_event_failed",
+                    error: None,
+                });
+                self.chain_failed = linked;
+            }
+            Ok(record) => {
+                self.report.push(ImportRow {
+                    row,
+                    id: Some(format!("{:032x}", record.id())),
+                    result: "ok",
+                    error: None,
+                });
+                self.batch.push(record);
+                self.batch_rows.push(row);
+                if !linked {
+                    self.chain_start = None;
+                } else if self.chain_start.is_none() {
+                    self.chain_start = Some(self.batch.len() - 1);
+                }
+                if self.batch.len() >= self.batch_max {
+                    self.flush_full().await?;
+                }
+            }
+            Err(error) => {
+                self.report.push(ImportRow {
+                    row,
+                    id: None,
+                    result: "invalid",
+                    error: Some(error),
+                });
+                if let Some(start) = self.chain_start.take() {
+                    self.fail_from(start, "linked_event_failed", None);
+                }
+                self.chain_failed = linked;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a full batch, holding back a linked chain still open at its
+    /// end for the next one.
+    async fn flush_full(&mut self) -> Result<(), AppError> {
+        match self.chain_start {
+            None => self.submit(self.batch.len()).await,
+            Some(0) => {
+                let error = format!(
+                    "Linked chain longer than the {} {} one request takes",
+                    self.batch_max,
+                    T::NAME
+                );
+                self.fail_from(0, "invalid", Some(error));
+                self.chain_start = None;
+                self.chain_failed = true;
+                Ok(())
+            }
+            Some(start) => {
+                self.submit(start).await?;
+                self.chain_start = Some(0);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the records from batch index `start` on, reporting `result`.
+    fn fail_from(&mut self, start: usize, result: &'static str, error: Option<String>) {
+        self.batch.truncate(start);
+        for row in self.batch_rows.drain(start..) {
+            self.report.fail(row, result, error.clone());
+        }
+    }
+
+    /// Create the first `count` records of the batch.
+    async fn submit(&mut self, count: usize) -> Result<(), AppError> {
+        if count == 0 {
+            return Ok(());
+        }
+        let failures = T::create(self.state, &self.batch[..count]).await?;
+        for (index, result) in failures {
+            let row = self.batch_rows[index as usize];
+            self.report.fail(row, result, None);
+        }
+        self.batch.drain(..count);
+        self.batch_rows.drain(..count);
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<ImportReport, AppError> {
+        if !self.partial.is_empty() {
+            self.record(Err("Unterminated quoted cell".into())).await?;
+        }
+        if self.rows == 0 {
+            return Err(AppError::BadRequest(format!(
+                "No {} in the upload",
+                T::NAME
+            )));
+        }
+        self.submit(self.batch.len()).await?;
+        Ok(self.report.finish(self.rows))
+    }
+}
+
+/// The results of an import's rows so far, listing up to `max` of them.
+struct Report {
+    max: u64,
+    failed: u64,
+    results: Vec<ImportRow>,
+}
+
+impl Report {
+    fn new(max: u64) -> Self {
+        Self {
+            max,
+            failed: 0,
+            results: Vec::new(),
+        }
+    }
+
+    /// Add the next row's result.
+    fn push(&mut self, result: ImportRow) {
+        if result.result != "ok" {
+            self.failed += 1;
+        }
+        if result.row <= self.max {
+            self.results.push(result);
+        }
+    }
+
+    /// Change the result of `row`, added as `ok`, to failed with `result`.
+    fn fail(&mut self, row: u64, result: &'static str, error: Option<String>) {
+        self.failed += 1;
+        if let Some(report) = self.results.get_mut(row as usize - 1) {
+            report.result = result;
+            report.error = error;
+        }
+    }
+
+    fn finish(self, rows: u64) -> ImportReport {
+        ImportReport {
+            rows,
+            created: rows - self.failed,
+            failed: self.failed,
+            truncated: rows > self.max,
+            results: self.results,
+        }
+    }
+}
+
+/// The JSON value of CSV `cell` in column `name`.
+fn csv_value(name: &str, cell: &str) -> Value {
+    if name == "flags" {
+        return cell
+            .split('|')
+            .map(str::trim)
+            .filter(|flag| !flag.is_empty())
+            .map(|flag| Value::String(flag.to_string()))
+            .collect();
+    }
+    if NUMERIC_FIELDS.contains(&name) {
+        if let Ok(n) = cell.parse::<u64>() {
+            return n.into();
+        }
+    }
+    Value::String(cell.to_string())
+}
+
+/// The report as CSV: one line per listed row.
+fn report_csv(report: &ImportReport) -> Result<Vec<u8>, AppError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let write = |writer: &mut csv::Writer<Vec<u8>>| -> csv::Result<()> {
+        writer.write_record(["row", "id", "result", "error"])?;
+        for row in &report.results {
+            writer.write_record([
+                row.row.to_string().as_str(),
+                row.id.as_deref().unwrap_or_default(),
+                row.result,
+                row.error.as_deref().unwrap_or_default(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    };
+    write(&mut writer).map_err(|e| AppError::Internal(e.to_string()))?;
+    writer
+        .into_inner()
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(row: u64, result: &'static str) -> ImportRow {
+        ImportRow {
+            row,
+            id: None,
+            result,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_report_lists_every_row_under_max() {
+        let mut report = Report::new(10);
+        report.push(row(1, "ok"));
+        report.push(row(2, "invalid"));
+        report.push(row(3, "ok"));
+        report.fail(3, "exists", None);
+
+        let report = report.finish(3);
+        assert_eq!((report.rows, report.created, report.failed), (3, 1, 2));
+        assert!(!report.truncated);
+        let results: Vec<_> = report.results.iter().map(|r| r.result).collect();
+        assert_eq!(results, ["ok", "invalid", "exists"]);
+    }
+
+    #[test]
+    fn test_report_counts_rows_past_max() {
+        let mut report = Report::new(2);
+        for n in 1..=5 {
+            report.push(row(n, if n == 4 { "invalid" } else { "ok" }));
+        }
+        report.fail(2, "linked_event_failed", Some("chain".into()));
+        report.fail(5, "exists", None);
+
+        let report = report.finish(5);
+        assert_eq!((report.rows, report.created, report.failed), (5, 2, 3));
+        assert!(report.truncated);
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(report.results[1].result, "linked_event_failed");
+        assert_eq!(report.results[1].error.as_deref(), Some("chain"));
+    }
+
+    #[test]
+    fn test_report_max_zero_lists_nothing() {
+        let mut report = Report::new(0);
+        report.push(row(1, "ok"));
+        report.fail(1, "exists", None);
+
+        let report = report.finish(1);
+        assert_eq!((report.created, report.failed), (0, 1));
+        assert!(report.truncated);
+        assert!(report.results.is_empty());
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let mut report = Report::new(10);
+        report.push(ImportRow {
+            row: 1,
+            id: Some("0a".into()),
+            result: "invalid",
+            error: Some("bad, \"ledger\"".into()),
+        });
+        let csv = report_csv(&report.finish(1)).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "row,id,result,error\n1,0a,invalid,\"bad, \"\"ledger\"\"\"\n"
+        );
+    }
+}
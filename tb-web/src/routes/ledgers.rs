@@ -0,0 +1,527 @@
+//! Ledger route handlers.
+
+use crate::api::{LedgerReconciliation, LedgerSummary};
+use crate::auth::{Operator, Viewer};
+use crate::error::AppError;
+use crate::html;
+use crate::routes::stats;
+use crate::state::AppState;
+use crate::validate::{self, LedgerPath, Valid, Validate, LIMIT_MAX};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tb_rs::{Account, QueryFilter, QueryFilterFlags};
+
+/// Records fetched per query.
+const PAGE_SIZE: u32 = 1000;
+
+/// Check if request is from HTMX.
+fn is_htmx_request(headers: &HeaderMap) -> bool {
+    headers.contains_key("hx-request")
+}
+
+/// Query parameters for a ledger summary.
+#[derive(Debug, Deserialize)]
+pub struct LedgerSummaryParams {
+    /// How far back recent transfer volume reaches, in seconds.
+    #[serde(default = "default_window")]
+    pub window_secs: u64,
+}
+
+fn default_window() -> u64 {
+    3600
+}
+
+impl Validate for LedgerSummaryParams {}
+
+/// Totals for one ledger: its accounts' balances and the transfers in the
+/// recent window.
+///
+/// TigerBeetle keeps no per-ledger totals, so this pages through every
+/// account on the ledger and every recent transfer. Expect it to take a
+/// while on large ledgers.
+pub async fn get_ledger_summary(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    LedgerPath(ledger): LedgerPath,
+    Valid(params): Valid<LedgerSummaryParams>,
+) -> Result<Response, AppError> {
+    let mut totals = Totals::default();
+    for_each_account(&state, ledger, |account| {
+        totals.accounts += 1;
+        totals.debits_posted = totals.debits_posted.saturating_add(account.debits_posted);
+        totals.credits_posted = totals.credits_posted.saturating_add(account.credits_posted);
+        totals.debits_pending = totals.debits_pending.saturating_add(account.debits_pending);
+        totals.credits_pending = totals
+            .credits_pending
+            .saturating_add(account.credits_pending);
+    })
+    .await?;
+
+    // Cluster timestamps are nanoseconds since the epoch, close to wall time.
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let since = now.saturating_sub(Duration::from_secs(params.window_secs));
+    let mut filter = ledger_filter(ledger, since.as_nanos() as u64);
+    loop {
+        let transfers = {
+            let client = state.pool.get();
+            client.query_transfers(filter).await?
+        };
+        for transfer in &transfers {
+            totals.recent_transfers += 1;
+            totals.recent_volume = totals.recent_volume.saturating_add(transfer.amount);
+        }
+        match transfers.last() {
+            Some(last) if transfers.len() == PAGE_SIZE as usize => {
+                filter.timestamp_min = last.timestamp + 1;
+            }
+            _ => break,
+        }
+    }
+
+    let summary = LedgerSummary {
+        ledger,
+        accounts: totals.accounts,
+        debits_posted: totals.debits_posted.to_string(),
+        credits_posted: totals.credits_posted.to_string(),
+        debits_pending: totals.debits_pending.to_string(),
+        credits_pending: totals.credits_pending.to_string(),
+        window_secs: params.window_secs,
+        recent_transfers: totals.recent_transfers,
+        recent_volume: totals.recent_volume.to_string(),
+        display: None,
+    }
+    .with_display(&state.config.currencies);
+
+    if is_htmx_request(&headers) {
+        Ok(Html(html::render_ledger_summary(&summary)).into_response())
+    } else {
+        Ok(Json(summary).into_response())
+    }
+}
+
+/// Check that the debits of every account on a ledger add up to its
+/// credits, posted and pending, as double-entry bookkeeping demands.
+///
+/// Like the summary, this pages through every account on the ledger. The
+/// pages are read one after another, not as one snapshot, so transfers
+/// made meanwhile can show up as a discrepancy that is gone when run again.
+pub async fn reconcile_ledger(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    LedgerPath(ledger): LedgerPath,
+) -> Result<Response, AppError> {
+    let started = Instant::now();
+    let mut accounts = 0;
+    let mut sums = [WideSum::default(); 4];
+    for_each_account(&state, ledger, |account| {
+        accounts += 1;
+        sums[0].add(account.debits_posted);
+        sums[1].add(account.credits_posted);
+        sums[2].add(account.debits_pending);
+        sums[3].add(account.credits_pending);
+    })
+    .await?;
+    let [debits_posted, credits_posted, debits_pending, credits_pending] = sums;
+
+    let balanced = debits_posted == credits_posted && debits_pending == credits_pending;
+    let reconciliation = LedgerReconciliation {
+        ledger,
+        accounts,
+        balanced,
+        debits_posted: debits_posted.to_string(),
+        credits_posted: credits_posted.to_string(),
+        debits_pending: debits_pending.to_string(),
+        credits_pending: credits_pending.to_string(),
+        posted_discrepancy: WideSum::difference(debits_posted, credits_posted),
+        pending_discrepancy: WideSum::difference(debits_pending, credits_pending),
+        duration_ms: started.elapsed().as_millis() as u64,
+        display: None,
+    }
+    .with_display(&state.config.currencies);
+    if !balanced {
+        tracing::warn!(
+            "Ledger {} does not balance: posted debits - credits = {}, pending = {}",
+            ledger,
+            reconciliation.posted_discrepancy,
+            reconciliation.pending_discrepancy
+        );
+    }
+
+    if is_htmx_request(&headers) {
+        Ok(Html(html::render_ledger_reconciliation(&reconciliation)).into_response())
+    } else {
+        Ok(Json(reconciliation).into_response())
+    }
+}
+
+/// Filters of a ledger's browse page, as submitted.
+///
+/// The form submits empty fields as empty strings, so numbers are strings
+/// until checked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LedgerBrowseParams {
+    /// Only accounts and transfers with this code.
+    pub code: String,
+    /// Rows per page of each table.
+    pub limit: String,
+    /// `oldest` to list transfers oldest first; newest first otherwise.
+    pub order: String,
+}
+
+/// The filters of a ledger's browse page, checked.
+#[derive(Debug)]
+pub struct BrowseFilters {
+    pub code: Option<u16>,
+    pub limit: u32,
+    pub oldest_first: bool,
+}
+
+/// Rows per page of each table unless the filters say otherwise.
+const BROWSE_LIMIT: u32 = 25;
+
+impl LedgerBrowseParams {
+    /// Check the filters.
+    pub fn filters(&self) -> Result<BrowseFilters, String> {
+        let code = match self.code.trim() {
+            "" => None,
+            code => {
+                let code = code
+                    .parse()
+                    .map_err(|_| format!("code: invalid number {:?}", code))?;
+                validate::code("code", Some(code))?;
+                Some(code)
+            }
+        };
+        let mut limit = match self.limit.trim() {
+            "" => BROWSE_LIMIT,
+            limit => limit
+                .parse()
+                .map_err(|_| format!("limit: invalid number {:?}", limit))?,
+        };
+        validate::limit(&mut limit, LIMIT_MAX)?;
+        Ok(BrowseFilters {
+            code,
+            limit,
+            oldest_first: self.order == "oldest",
+        })
+    }
+}
+
+impl Validate for LedgerBrowseParams {
+    fn validate(&mut self) -> Result<(), String> {
+        self.filters().map(drop)
+    }
+}
+
+impl BrowseFilters {
+    /// The filters as a query string, starting with `?`, or empty if they
+    /// are the defaults.
+    pub fn query(&self) -> String {
+        let mut query = Vec::new();
+        if let Some(code) = self.code {
+            query.push(format!("code={}", code));
+        }
+        if self.limit != BROWSE_LIMIT {
+            query.push(format!("limit={}", self.limit));
+        }
+        if self.oldest_first {
+            query.push("order=oldest".to_string());
+        }
+        if query.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", query.join("&"))
+        }
+    }
+}
+
+/// A ledger's browse page: its statistics, then its accounts and its
+/// transfers, each paged on its own. HTML only.
+///
+/// The filters are kept in the page's URL, `/ledger/{id}` with the same
+/// query string, so a filtered ledger can be reloaded or shared.
+pub async fn browse_ledger(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    LedgerPath(ledger): LedgerPath,
+    Valid(params): Valid<LedgerBrowseParams>,
+) -> Result<Response, AppError> {
+    let filters = params.filters().map_err(AppError::Unprocessable)?;
+
+    let currencies = &state.config.currencies;
+    let stats = state.stats.latest().map(|snapshot| {
+        let counts = snapshot.ledgers.get(&ledger).cloned().unwrap_or_default();
+        stats::ledger_stats(ledger, &counts, currencies)
+    });
+    let body = html::render_ledger_browse(ledger, currencies.get(ledger), stats.as_ref(), &filters);
+    let page_url = format!("/ledger/{}{}", ledger, filters.query());
+    Ok(([("hx-replace-url", page_url)], Html(body)).into_response())
+}
+
+/// Call `f` with every account on `ledger`, a page at a time.
+async fn for_each_account(
+    state: &AppState,
+    ledger: u32,
+    mut f: impl FnMut(&Account),
+) -> Result<(), AppError> {
+    let mut filter = ledger_filter(ledger, 0);
+    loop {
+        let accounts = {
+            let client = state.pool.get();
+            client.query_accounts(filter).await?
+        };
+        accounts.iter().for_each(&mut f);
+        match accounts.last() {
+            Some(last) if accounts.len() == PAGE_SIZE as usize => {
+                filter.timestamp_min = last.timestamp + 1;
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// A sum of amounts that cannot overflow: `carries` times 2^128 plus
+/// `low`. A ledger's accounts may hold more than a u128 between them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct WideSum {
+    carries: u64,
+    low: u128,
+}
+
+impl WideSum {
+    fn add(&mut self, amount: u128) {
+        let (low, carry) = self.low.overflowing_add(amount);
+        self.low = low;
+        self.carries += carry as u64;
+    }
+
+    /// `a - b` in decimal, negative if `b` is larger.
+    fn difference(a: Self, b: Self) -> String {
+        let (larger, smaller, sign) = if a >= b { (a, b, "") } else { (b, a, "-") };
+        let (low, borrow) = larger.low.overflowing_sub(smaller.low);
+        let difference = Self {
+            carries: larger.carries - smaller.carries - borrow as u64,
+            low,
+        };
+        if difference == Self::default() {
+            "0".to_string()
+        } else {
+            format!("{}{}", sign, difference)
+        }
+    }
+}
+
+impl fmt::Display for WideSum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.carries == 0 {
+            return write!(f, "{}", self.low);
+        }
+        // Long division by 10^19, the largest power of ten in a u64.
+        const CHUNK: u128 = 10_000_000_000_000_000_000;
+        let mut limbs = [self.carries, (self.low >> 64) as u64, self.low as u64];
+        let mut chunks = Vec::new();
+        while limbs != [0; 3] {
+            let mut remainder = 0u128;
+            for limb in &mut limbs {
+                let value = (remainder << 64) | *limb as u128;
+                *limb = (value / CHUNK) as u64;
+                remainder = value % CHUNK;
+            }
+            chunks.push(remainder as u64);
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().expect("carries is not zero"))?;
+        chunks.try_for_each(|chunk| write!(f, "{:019}", chunk))
+    }
+}
+
+/// Running totals; sums saturate rather than wrap.
+#[derive(Default)]
+struct Totals {
+    accounts: u64,
+    debits_posted: u128,
+    credits_posted: u128,
+    debits_pending: u128,
+    credits_pending: u128,
+    recent_transfers: u64,
+    recent_volume: u128,
+}
+
+fn ledger_filter(ledger: u32, timestamp_min: u64) -> QueryFilter {
+    QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger,
+        code: 0,
+        timestamp_min,
+        timestamp_max: 0,
+        limit: PAGE_SIZE,
+        flags: QueryFilterFlags::empty(),
+        reserved: [0; 6],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(carries: u64, low: u128) -> WideSum {
+        WideSum { carries, low }
+    }
+
+    fn params(code: &str, limit: &str, order: &str) -> LedgerBrowseParams {
+        LedgerBrowseParams {
+            code: code.to_string(),
+            limit: limit.to_string(),
+            order: order.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_wide_sum_add() {
+        let mut sum = WideSum::default();
+        sum.add(5);
+        assert_eq!(sum, wide(0, 5));
+        sum.add(u128::MAX - 4);
+        assert_eq!(sum, wide(1, 0));
+        sum.add(u128::MAX);
+        sum.add(u128::MAX);
+        assert_eq!(sum, wide(2, u128::MAX - 1));
+        sum.add(0);
+        assert_eq!(sum, wide(2, u128::MAX - 1));
+    }
+
+    #[test]
+    fn test_wide_sum_display() {
+        for (sum, expected) in [
+            (wide(0, 0), "0"),
+            (
+                wide(0, u128::MAX),
+                "340282366920938463463374607431768211455",
+            ),
+            (wide(1, 0), "340282366920938463463374607431768211456"),
+            (
+                wide(2, 340282366920938463463374607431768211453),
+                "1020847100762815390390123822295304634365",
+            ),
+            (
+                wide(u64::MAX, u128::MAX),
+                "6277101735386680763835789423207666416102355444464034512895",
+            ),
+            // Chunks of 10^19 that are exactly zero, or just under it.
+            (
+                wide(2938735877055718769, 313686354140541217734174016852339982336),
+                "1000000000000000000000000000000000000000000000000000000000",
+            ),
+            (
+                wide(2938735877055718769, 313686354140541217734174016852339982335),
+                "999999999999999999999999999999999999999999999999999999999",
+            ),
+            (
+                wide(2938735877055718769, 313686354140541217744174016852339982336),
+                "1000000000000000000000000000000000000010000000000000000000",
+            ),
+            (
+                wide(3, 19435266158123073073250785136463577088),
+                "1040282366920938463463374607431768211456",
+            ),
+        ] {
+            assert_eq!(sum.to_string(), expected, "{:?}", sum);
+        }
+    }
+
+    #[test]
+    fn test_wide_sum_difference() {
+        for (a, b, expected) in [
+            (wide(0, 7), wide(0, 7), "0"),
+            (wide(3, 9), wide(3, 9), "0"),
+            (wide(0, 7), wide(0, 5), "2"),
+            (wide(0, 5), wide(0, 7), "-2"),
+            // A borrow from the carries.
+            (
+                wide(1, 0),
+                wide(0, 1),
+                "340282366920938463463374607431768211455",
+            ),
+            (
+                wide(0, 1),
+                wide(1, 0),
+                "-340282366920938463463374607431768211455",
+            ),
+            (
+                wide(2, 1),
+                wide(0, 2),
+                "680564733841876926926749214863536422911",
+            ),
+            // Carries left over after subtracting.
+            (
+                wide(2, 5),
+                wide(1, 5),
+                "340282366920938463463374607431768211456",
+            ),
+            (
+                wide(1, 5),
+                wide(2, 5),
+                "-340282366920938463463374607431768211456",
+            ),
+            (
+                wide(u64::MAX, u128::MAX),
+                wide(0, 0),
+                "6277101735386680763835789423207666416102355444464034512895",
+            ),
+        ] {
+            assert_eq!(WideSum::difference(a, b), expected, "{:?} - {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_browse_filters() {
+        let filters = params("", "", "").filters().unwrap();
+        assert_eq!(filters.code, None);
+        assert_eq!(filters.limit, BROWSE_LIMIT);
+        assert!(!filters.oldest_first);
+        assert_eq!(filters.query(), "");
+
+        let filters = params(" 3 ", "50", "oldest").filters().unwrap();
+        assert_eq!(filters.code, Some(3));
+        assert_eq!(filters.limit, 50);
+        assert!(filters.oldest_first);
+        assert_eq!(filters.query(), "?code=3&limit=50&order=oldest");
+
+        // Defaults are left out of the query.
+        let filters = params("", &BROWSE_LIMIT.to_string(), "newest")
+            .filters()
+            .unwrap();
+        assert_eq!(filters.query(), "");
+        let filters = params("", "", "OLDEST").filters().unwrap();
+        assert!(!filters.oldest_first);
+        assert_eq!(params("7", "", "").filters().unwrap().query(), "?code=7");
+
+        // Limits over the maximum are capped.
+        let filters = params("", "5000", "").filters().unwrap();
+        assert_eq!(filters.limit, LIMIT_MAX);
+        assert_eq!(filters.query(), format!("?limit={}", LIMIT_MAX));
+
+        for (params, error) in [
+            (params("x", "", ""), "code: invalid number \"x\""),
+            (params("70000", "", ""), "code: invalid number \"70000\""),
+            (params("0", "", ""), "code: must be 1 to 65535"),
+            (params("", "-1", ""), "limit: invalid number \"-1\""),
+            (params("", "0", ""), "limit: must be at least 1"),
+        ] {
+            assert_eq!(params.filters().unwrap_err(), error, "{:?}", params);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! WebSocket handler for live updates.
+
+use crate::auth::Viewer;
+use crate::live;
+use crate::state::AppState;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Push live transfer and account events (see [`live`]) until the browser
+/// disconnects.
+pub async fn live(_: Viewer, State(state): State<Arc<AppState>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| forward(state, socket))
+}
+
+async fn forward(state: Arc<AppState>, mut socket: WebSocket) {
+    let mut events = live::subscribe(&state);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if socket.send(Message::Text(event.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+                // A slow browser misses events rather than holding up others.
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::debug!("Live update subscriber missed {} events", missed);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                // Browsers only send close and control frames.
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
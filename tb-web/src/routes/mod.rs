@@ -1,24 +1,567 @@
 //! HTTP route handlers.
 
 pub mod accounts;
+pub mod aliases;
+pub mod explore;
+pub mod export;
 pub mod frontend;
+pub mod graph;
+pub mod graphql;
+pub mod import;
+pub mod ledgers;
+pub mod live;
+pub mod new_transfer;
+pub mod queries;
+pub mod recent;
+pub mod resolve;
+pub mod seed;
+pub mod snapshot;
+pub mod stats;
 pub mod transfers;
+pub mod webhooks;
 
-use crate::api::HealthResponse;
+use crate::api::{
+    rfc3339, ApiClientHealth, ApiReplicaHealth, ClusterResponse, CreateResponse, CreateResult,
+    HealthResponse, LivenessResponse, ReadinessCheck, ReadinessResponse,
+};
+use crate::auth::Viewer;
+use crate::error::AppError;
+use crate::grpc::TigerBeetleService;
 use crate::state::AppState;
-use axum::extract::State;
-use axum::Json;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{BoxError, Json, Router};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tb_rs::{QueryFilter, QueryFilterFlags};
+use tower::timeout::error::Elapsed;
+use tower::ServiceBuilder;
 
-/// Health check endpoint.
+/// Longest `/readyz` waits for its own TigerBeetle operation.
+const READY_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The routes of every endpoint enabled in `state.config`, served from
+/// `state`.
+pub fn router(state: Arc<AppState>) -> Router {
+    // Routes that may rightly run long go in `untimed`.
+    let mut untimed = Router::new();
+    let mut app = Router::new()
+        // API routes
+        .route("/api/v1/accounts", get(accounts::list_accounts))
+        .route("/api/v1/accounts/:id", get(accounts::get_account))
+        .route(
+            "/api/v1/accounts/:id/transfers",
+            get(accounts::get_account_transfers),
+        )
+        .route(
+            "/api/v1/accounts/:id/balances",
+            get(accounts::get_account_balances),
+        )
+        .route(
+            "/api/v1/accounts/:id/balances/chart",
+            get(accounts::get_account_balance_chart),
+        )
+        .route("/api/v1/transfers", get(transfers::list_transfers))
+        .route(
+            "/api/v1/transfers/pending",
+            get(transfers::list_pending_transfers),
+        )
+        .route("/api/v1/transfers/:id", get(transfers::get_transfer))
+        .route(
+            "/api/v1/ledgers/:id/summary",
+            get(ledgers::get_ledger_summary),
+        )
+        .route(
+            "/api/v1/ledgers/:id/reconcile",
+            get(ledgers::reconcile_ledger),
+        )
+        .route("/api/v1/ledgers/:id/browse", get(ledgers::browse_ledger))
+        .route("/api/v1/stats", get(stats::get_stats))
+        .route("/api/v1/resolve", get(resolve::resolve))
+        .route("/api/v1/recent", get(recent::list_recent))
+        .route("/api/v1/explore", get(explore::builder))
+        .route("/api/v1/explore/results", get(explore::results))
+        .route("/api/v1/graph", get(graph::get_graph))
+        .route("/api/v1/cluster", get(cluster))
+        .route("/api/v1/graphql", post(graphql::graphql))
+        .route_service(
+            "/tb_web.v1.TigerBeetle/*rpc",
+            TigerBeetleService::server(state.clone()),
+        )
+        .route("/health", get(health))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        // Frontend page routes (serve same content, HTMX handles detail loading)
+        .route("/account/:id", get(frontend::serve_account_page))
+        .route("/transfer/:id", get(frontend::serve_transfer_page))
+        .route("/ledger/:id", get(frontend::serve_ledger_page));
+    if !state.config.read_only {
+        app = app
+            .route("/api/v1/accounts", post(accounts::create_accounts))
+            .route("/api/v1/transfers", post(transfers::create_transfers))
+            .route(
+                "/api/v1/transfers/new",
+                get(new_transfer::form).post(new_transfer::create),
+            )
+            .route(
+                "/api/v1/transfers/:id/post",
+                post(transfers::post_pending_transfer),
+            )
+            .route(
+                "/api/v1/transfers/:id/void",
+                post(transfers::void_pending_transfer),
+            );
+        untimed = untimed.route(
+            "/api/v1/import",
+            post(import::import).layer(DefaultBodyLimit::max(state.config.import_max_bytes)),
+        );
+        if state.config.enable_seeding {
+            untimed = untimed.route("/api/v1/seed", post(seed::seed));
+        }
+    }
+    if state.config.aliases_db.is_some() {
+        app = app
+            .route("/api/v1/aliases", get(aliases::list_aliases))
+            .route(
+                "/api/v1/aliases/:id",
+                get(aliases::get_alias)
+                    .put(aliases::set_alias)
+                    .delete(aliases::delete_alias),
+            );
+    }
+    if state.config.queries_db.is_some() {
+        app = app
+            .route(
+                "/api/v1/queries",
+                get(queries::list_queries).post(queries::save_query),
+            )
+            .route(
+                "/api/v1/queries/:name",
+                get(queries::get_query).delete(queries::delete_query),
+            )
+            .route("/api/v1/queries/:name/results", get(queries::run_query));
+    }
+    if !state.config.webhooks.is_empty() {
+        app = app.route("/api/v1/webhooks", get(webhooks::list_webhooks));
+    }
+    if !state.config.disable_export {
+        app = app
+            .route("/api/v1/export/accounts", get(export::export_accounts))
+            .route("/api/v1/export/transfers", get(export::export_transfers))
+            .route("/api/v1/export/snapshot", get(snapshot::export_snapshot));
+    }
+    if !state.config.disable_live {
+        app = app.route("/api/v1/live", get(live::live));
+        // Long-polls end by themselves, within their `wait`.
+        untimed = untimed.route(
+            "/api/v1/accounts/:id/balance",
+            get(accounts::wait_for_balance),
+        );
+    }
+    if !state.config.request_timeout.is_zero() {
+        // Dropping a timed-out handler drops its queued TigerBeetle work.
+        app = app.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(timed_out))
+                .timeout(state.config.request_timeout),
+        );
+    }
+    app.merge(untimed)
+        .layer(DefaultBodyLimit::max(state.config.max_body_bytes))
+        // Frontend fallback
+        .fallback(frontend::serve_frontend)
+        // State
+        .with_state(state)
+}
+
+/// Health check endpoint: the pooled clients, and how many of them are
+/// connected to each replica.
 pub async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
-    let tb_connected = {
-        let client = state.client.lock().await;
-        client.is_ready()
-    };
+    let health = state.pool.health();
+    let replicas = ApiReplicaHealth::tally(state.config.replica_count, &health);
+    let clients: Vec<ApiClientHealth> = health.into_iter().map(ApiClientHealth::from).collect();
 
     Json(HealthResponse {
         status: "ok".to_string(),
-        tb_connected,
+        tb_connected: clients.iter().any(|client| client.ready),
+        clients,
+        replicas,
+    })
+}
+
+/// Cluster details: the sessions of the pooled clients, and which replicas
+/// each is connected to.
+///
+/// Unlike [`health`], this is an API endpoint, since replica addresses are
+/// not for the public.
+pub async fn cluster(_: Viewer, State(state): State<Arc<AppState>>) -> Json<ClusterResponse> {
+    Json(ClusterResponse {
+        cluster_id: state.pool.cluster_id().to_string(),
+        batch_size_limit: state.pool.batch_size_limit(),
+        last_success_at: state.pool.last_success().map(rfc3339),
+        clients: state.pool.health().into_iter().map(Into::into).collect(),
     })
 }
+
+/// Fail a request that did not finish within `--request-timeout-secs`.
+pub async fn timed_out(error: BoxError) -> AppError {
+    if error.is::<Elapsed>() {
+        AppError::Timeout
+    } else {
+        AppError::Internal(format!("Unhandled middleware error: {}", error))
+    }
+}
+
+/// Liveness probe: the process is up and serving HTTP. Restarting it is
+/// the fix only when this fails; a lost TigerBeetle connection shows in
+/// [`readyz`] instead.
+pub async fn livez(State(state): State<Arc<AppState>>) -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: "ok",
+        uptime_secs: state.started.elapsed().as_secs(),
+    })
+}
+
+/// Readiness probe: 200 if this instance can serve TigerBeetle requests,
+/// 503 otherwise, with the outcome of each check.
+///
+/// Ready means the clients registered with the cluster, at least half of
+/// the pool is connected, and an operation succeeded within
+/// `--ready-max-op-age-secs`. An idle instance has no recent operation, so
+/// the probe runs a one-account query of its own before judging that.
+pub async fn readyz(State(state): State<Arc<AppState>>) -> Response {
+    let max_age = state.config.ready_max_op_age;
+    let age = |last: Option<SystemTime>| last.map(|t| t.elapsed().unwrap_or_default());
+    if age(state.pool.last_success()).is_none_or(|age| age > max_age) {
+        let filter = QueryFilter {
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            ledger: 0,
+            code: 0,
+            timestamp_min: 0,
+            timestamp_max: 0,
+            limit: 1,
+            flags: QueryFilterFlags::empty(),
+            reserved: [0; 6],
+        };
+        let client = state.pool.get();
+        // Success is recorded by the pool; failure shows in the checks.
+        let _ = tokio::time::timeout(READY_PROBE_TIMEOUT, client.query_accounts(filter)).await;
+    }
+
+    let clients = state.pool.health();
+    let ready_clients = clients.iter().filter(|client| client.ready).count();
+    let last_op = age(state.pool.last_success());
+    let checks = vec![
+        ReadinessCheck {
+            name: "registered",
+            ok: state.pool.batch_size_limit().is_some(),
+            detail: match state.pool.batch_size_limit() {
+                Some(limit) => format!("batch size limit {}", limit),
+                None => "not registered with the cluster".to_string(),
+            },
+        },
+        ReadinessCheck {
+            name: "pool",
+            ok: ready_clients > 0 && ready_clients * 2 >= clients.len(),
+            detail: format!("{} of {} clients connected", ready_clients, clients.len()),
+        },
+        ReadinessCheck {
+            name: "last_op",
+            ok: last_op.is_some_and(|age| age <= max_age),
+            detail: match last_op {
+                Some(age) => format!(
+                    "last successful operation {}s ago (limit {}s)",
+                    age.as_secs(),
+                    max_age.as_secs()
+                ),
+                None => "no successful operation yet".to_string(),
+            },
+        },
+    ];
+
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(ReadinessResponse { ready, checks })).into_response()
+}
+
+/// Respond to a create request for `ids` whose `failures` are (index,
+/// result name): 201 if every item was created, 207 otherwise, with each
+/// item's result either way.
+fn create_response(ids: &[u128], failures: &[(u32, &'static str)]) -> Response {
+    let mut results: Vec<CreateResult> = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| CreateResult {
+            index: i as u32,
+            id: format!("{:032x}", id),
+            result: "ok",
+        })
+        .collect();
+    for &(index, result) in failures {
+        results[index as usize].result = result;
+    }
+
+    let status = if failures.is_empty() {
+        StatusCode::CREATED
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    let response = CreateResponse {
+        created: ids.len() - failures.len(),
+        failed: failures.len(),
+        results,
+    };
+    (status, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::testing::{self, FakeCluster};
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::json;
+    use tb_rs::{Transfer, TransferFlags};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_router_account_and_transfer_routes() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 1), testing::account(2, 1)],
+            &[testing::transfer(3, 1, 2, 50, 1)],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        for uri in [
+            "/api/v1/accounts/1",
+            "/api/v1/accounts/1/transfers",
+            "/api/v1/accounts/1/balances",
+            "/api/v1/transfers/3",
+            "/account/1",
+            "/transfer/3",
+        ] {
+            let (status, _) = testing::get(&router, uri).await;
+            assert_eq!(status, 200, "{}", uri);
+        }
+        let (status, body) = testing::get(&router, "/api/v1/accounts/1").await;
+        assert_eq!(status, 200);
+        assert!(body.contains("\"debits_posted\":\"50\""), "{}", body);
+        let (status, _) = testing::get(&router, "/api/v1/accounts/9").await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_router_ledger_summary() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 7), testing::account(2, 7)],
+            &[testing::transfer(3, 1, 2, 50, 7)],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        let (status, body) = testing::get(&router, "/api/v1/ledgers/7/summary").await;
+        assert_eq!(status, 200, "{}", body);
+        let summary: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(summary["ledger"], 7);
+        assert_eq!(summary["accounts"], 2);
+        assert_eq!(summary["debits_posted"], "50");
+        assert_eq!(summary["recent_transfers"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_router_ledger_reconcile() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 7), testing::account(2, 7)],
+            &[testing::transfer(3, 1, 2, 50, 7)],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        let (status, body) = testing::get(&router, "/api/v1/ledgers/7/reconcile").await;
+        assert_eq!(status, 200, "{}", body);
+        let reconciliation: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(reconciliation["accounts"], 2);
+        assert_eq!(reconciliation["balanced"], true);
+        assert_eq!(reconciliation["posted_discrepancy"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_router_ledger_browse() {
+        let cluster = FakeCluster::new();
+        let router = testing::router(testing::config(), &cluster);
+
+        let request = Request::get("/api/v1/ledgers/7/browse?code=3&order=oldest")
+            .header("hx-request", "true")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let page_url = response.headers()["hx-replace-url"].to_str().unwrap();
+        assert_eq!(page_url, "/ledger/7?code=3&order=oldest");
+
+        // The URL the browser is left at loads the same page.
+        let (status, body) = testing::get(&router, page_url).await;
+        assert_eq!(status, 200);
+        assert!(body.contains("/api/v1/ledgers/7/browse?code=3&order=oldest"));
+    }
+
+    #[tokio::test]
+    async fn test_router_post_and_void_pending_transfers() {
+        let pending = |id, amount| Transfer {
+            flags: TransferFlags::PENDING,
+            ..testing::transfer(id, 1, 2, amount, 1)
+        };
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 1), testing::account(2, 1)],
+            &[pending(3, 50), pending(4, 20)],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        let post = json!({"id": "a", "amount": "30"});
+        let (status, body) =
+            testing::send_json(&router, "POST", "/api/v1/transfers/3/post", post).await;
+        assert_eq!(status, 201, "{}", body);
+        let (status, body) =
+            testing::send_json(&router, "POST", "/api/v1/transfers/4/void", json!({})).await;
+        assert_eq!(status, 201, "{}", body);
+
+        let account = &cluster.lookup_accounts(&[1]).unwrap()[0];
+        assert_eq!(account.debits_posted, 30);
+        assert_eq!(account.debits_pending, 0);
+        let posting = &cluster.lookup_transfers(&[0xa]).unwrap()[0];
+        assert_eq!(posting.pending_id, 3);
+
+        // Each can be resolved once.
+        let (status, body) =
+            testing::send_json(&router, "POST", "/api/v1/transfers/3/void", json!({})).await;
+        assert_eq!(status, 207);
+        assert!(body.contains("pending_transfer_already_posted"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_router_balance_chart() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 1), testing::account(2, 1)],
+            &[
+                testing::transfer(3, 1, 2, 50, 1),
+                testing::transfer(4, 2, 1, 20, 1),
+            ],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        let (status, body) = testing::get(
+            &router,
+            "/api/v1/accounts/1/balances/chart?points=10&range=1h",
+        )
+        .await;
+        assert_eq!(status, 200, "{}", body);
+        let chart: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(chart["rows"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_router_balance_long_poll() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 1), testing::account(2, 1)],
+            &[testing::transfer(3, 1, 2, 50, 1)],
+        );
+        let config = Config {
+            disable_live: false,
+            ..testing::config()
+        };
+        let router = testing::router(config, &cluster);
+
+        // A transfer newer than `since_timestamp` answers at once.
+        let (status, body) =
+            testing::get(&router, "/api/v1/accounts/1/balance?since_timestamp=0").await;
+        assert_eq!(status, 200, "{}", body);
+        let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(response["changed"], true);
+        assert_eq!(response["account"]["debits_posted"], "50");
+
+        // Nothing newer waits out `wait`.
+        let since = response["timestamp"].as_u64().unwrap();
+        let uri = format!(
+            "/api/v1/accounts/1/balance?since_timestamp={}&wait=1s",
+            since
+        );
+        let (status, body) = testing::get(&router, &uri).await;
+        assert_eq!(status, 200, "{}", body);
+        let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(response["changed"], false);
+    }
+
+    #[tokio::test]
+    async fn test_router_aliases() {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[testing::account(1, 1)], &[]);
+        let router = testing::router(testing::config(), &cluster);
+
+        let alias = json!({"name": "treasury", "labels": ["eu"]});
+        let (status, body) = testing::send_json(&router, "PUT", "/api/v1/aliases/1", alias).await;
+        assert_eq!(status, 200, "{}", body);
+        let (status, body) = testing::get(&router, "/api/v1/aliases/1").await;
+        assert_eq!(status, 200);
+        assert!(body.contains("\"name\":\"treasury\""), "{}", body);
+
+        let request = Request::delete("/api/v1/aliases/1")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _) = testing::send(&router, request).await;
+        assert_eq!(status, 204);
+        let (status, _) = testing::get(&router, "/api/v1/aliases/1").await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_router_saved_queries() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[testing::account(1, 1), testing::account(2, 1)],
+            &[
+                testing::transfer(3, 1, 2, 50, 1),
+                testing::transfer(4, 2, 1, 20, 1),
+            ],
+        );
+        let router = testing::router(testing::config(), &cluster);
+
+        let query = json!({"name": "big", "kind": "transfers", "ledger": 1, "reversed": true});
+        let (status, body) = testing::send_json(&router, "POST", "/api/v1/queries", query).await;
+        assert_eq!(status, 201, "{}", body);
+        let (status, body) = testing::get(&router, "/api/v1/queries/big").await;
+        assert_eq!(status, 200);
+        assert!(body.contains("\"name\":\"big\""), "{}", body);
+
+        let (status, body) = testing::get(&router, "/api/v1/queries/big/results").await;
+        assert_eq!(status, 200, "{}", body);
+        let results: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let ids: Vec<&str> = results["transfers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, [format!("{:032x}", 4), format!("{:032x}", 3)]);
+
+        let request = Request::delete("/api/v1/queries/big")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _) = testing::send(&router, request).await;
+        assert_eq!(status, 204);
+        let (status, _) = testing::get(&router, "/api/v1/queries/big/results").await;
+        assert_eq!(status, 404);
+    }
+}
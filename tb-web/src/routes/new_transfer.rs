@@ -0,0 +1,520 @@
+//! Transfer creation form.
+//!
+//! The New Transfer page creates one transfer from a form instead of JSON.
+//! Accounts are picked by alias or hex ID, the ledger is the debit
+//! account's, and the amount is written in the ledger's currency (see
+//! [`crate::currency`]). `GET /api/v1/transfers/new` renders the form,
+//! re-rendered with what was entered whenever the debit account changes so
+//! it can say which currency to write the amount in, and
+//! `POST /api/v1/transfers/new` checks and creates the transfer.
+//!
+//! Both endpoints serve HTML only. An invalid form comes back with status
+//! 422 and each field's error under it; a created transfer comes back with
+//! status 201 and its details.
+
+use crate::aliases::Aliases;
+use crate::api::{codes, ApiTransfer};
+use crate::auth::{Operator, Viewer};
+use crate::error::AppError;
+use crate::html;
+use crate::state::AppState;
+use crate::validate;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Form;
+use serde::Deserialize;
+use std::sync::Arc;
+use tb_rs::{Account, CreateTransferResult, Transfer, TransferFlags};
+
+/// The form's fields, as submitted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TransferForm {
+    /// ID of the transfer to create (hex), chosen when the form is first
+    /// shown so that submitting it twice creates one transfer.
+    pub id: String,
+    /// Debit account: an alias or a hex ID.
+    pub debit: String,
+    /// Credit account: an alias or a hex ID.
+    pub credit: String,
+    /// Amount in the ledger's currency, or in its smallest unit if it has
+    /// none.
+    pub amount: String,
+    pub code: String,
+    /// `true` for a pending transfer.
+    pub pending: String,
+    /// Seconds until a pending transfer expires.
+    pub timeout: String,
+}
+
+/// What is wrong with a form: messages by field name, with `""` for the
+/// form as a whole.
+#[derive(Debug, Default)]
+pub struct FormErrors(Vec<(&'static str, String)>);
+
+impl FormErrors {
+    /// The error of field `name`, if it has one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, message)| message.as_str())
+    }
+
+    fn add(&mut self, name: &'static str, message: impl Into<String>) {
+        self.0.push((name, message.into()));
+    }
+
+    /// The value of `result`, or `None` with its error added to `name`.
+    fn check<T>(&mut self, name: &'static str, result: Result<T, String>) -> Option<T> {
+        result.map_err(|message| self.add(name, message)).ok()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Render the form, keeping any values already entered.
+pub async fn form(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Query(mut form): Query<TransferForm>,
+) -> Result<Html<String>, AppError> {
+    if form.id.is_empty() {
+        form.id = new_id();
+    }
+    let ledger = match account_id(&state.aliases, &form.debit) {
+        Ok(id) => state
+            .cache
+            .account(&state.pool, id)
+            .await?
+            .map(|a| a.ledger),
+        Err(_) => None,
+    };
+    Ok(Html(render_form(
+        &state,
+        &form,
+        ledger,
+        &FormErrors::default(),
+    )))
+}
+
+/// Check the form and create its transfer.
+pub async fn create(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    Form(mut form): Form<TransferForm>,
+) -> Result<Response, AppError> {
+    let mut errors = FormErrors::default();
+    let id = u128::from_str_radix(&form.id, 16)
+        .ok()
+        .filter(|&id| id != 0);
+    if id.is_none() {
+        form.id = new_id();
+        errors.add("", "The form was out of date; check it and submit it again");
+    }
+    let debit = account(&state, &mut errors, "debit", &form.debit).await?;
+    let credit = account(&state, &mut errors, "credit", &form.credit).await?;
+    if let (Some(debit), Some(credit)) = (&debit, &credit) {
+        if debit.id == credit.id {
+            errors.add("credit", "must differ from the debit account");
+        } else if debit.ledger != credit.ledger {
+            errors.add(
+                "credit",
+                format!(
+                    "is on ledger {}, the debit account on ledger {}",
+                    credit.ledger, debit.ledger
+                ),
+            );
+        }
+    }
+    let ledger = debit.map(|account| account.ledger);
+    // Without a ledger, there is no telling which unit the amount is in.
+    let amount = ledger.and_then(|ledger| {
+        let amount = match state.config.currencies.get(ledger) {
+            Some(currency) => currency.parse(&form.amount),
+            None => form
+                .amount
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid amount {:?}", form.amount)),
+        };
+        errors.check("amount", amount.and_then(positive))
+    });
+    let code = errors.check("code", parse_code(&form.code));
+    let pending = form.pending == "true";
+    let timeout = errors.check("timeout", parse_timeout(&form.timeout, pending));
+
+    let transfer = match (id, debit, credit, amount, code, timeout) {
+        (Some(id), Some(debit), Some(credit), Some(amount), Some(code), Some(timeout))
+            if errors.is_empty() =>
+        {
+            Transfer {
+                id,
+                debit_account_id: debit.id,
+                credit_account_id: credit.id,
+                amount,
+                ledger: debit.ledger,
+                code,
+                timeout,
+                flags: if pending {
+                    TransferFlags::PENDING
+                } else {
+                    TransferFlags::empty()
+                },
+                ..Default::default()
+            }
+        }
+        _ => return Ok(invalid(&state, &form, ledger, &errors)),
+    };
+    let failures = {
+        let client = state.pool.get();
+        client.create_transfers(&[transfer]).await?
+    };
+    state.cache.transfers_created();
+
+    // `exists` is the same form submitted again, which created it.
+    match failures.first().map(|f| f.result) {
+        None | Some(CreateTransferResult::Exists) => {}
+        Some(result) => {
+            // TigerBeetle remembers some failed IDs, so the next try needs
+            // a new one.
+            form.id = new_id();
+            errors.add(
+                "",
+                format!(
+                    "TigerBeetle rejected the transfer: {}",
+                    codes::transfer_result(result)
+                ),
+            );
+            return Ok(invalid(&state, &form, ledger, &errors));
+        }
+    }
+
+    let created = {
+        let client = state.pool.get();
+        client.lookup_transfers(&[transfer.id]).await?
+    };
+    let transfer = created.first().unwrap_or(&transfer);
+    let api_transfer = ApiTransfer::from(transfer).with_display(&state.config.currencies);
+    let body =
+        html::render_transfer_created(&api_transfer, &state.aliases, &state.config.timestamps);
+    Ok((StatusCode::CREATED, Html(body)).into_response())
+}
+
+/// The form with its errors, as status 422.
+fn invalid(
+    state: &AppState,
+    form: &TransferForm,
+    ledger: Option<u32>,
+    errors: &FormErrors,
+) -> Response {
+    let body = render_form(state, form, ledger, errors);
+    (StatusCode::UNPROCESSABLE_ENTITY, Html(body)).into_response()
+}
+
+/// Render the form, saying which currency the amount is in if the debit
+/// account's `ledger` is known.
+fn render_form(
+    state: &AppState,
+    form: &TransferForm,
+    ledger: Option<u32>,
+    errors: &FormErrors,
+) -> String {
+    let ledger = ledger.map(|ledger| (ledger, state.config.currencies.get(ledger)));
+    html::render_transfer_form(form, ledger, &state.aliases.list(), errors)
+}
+
+/// A new transfer ID, in hex.
+fn new_id() -> String {
+    format!("{:032x}", tb_rs::id())
+}
+
+/// The account named by form field `name`, or `None` with the field's
+/// error added.
+async fn account(
+    state: &AppState,
+    errors: &mut FormErrors,
+    name: &'static str,
+    value: &str,
+) -> Result<Option<Account>, AppError> {
+    let Some(id) = errors.check(name, account_id(&state.aliases, value)) else {
+        return Ok(None);
+    };
+    let account = state.cache.account(&state.pool, id).await?;
+    if account.is_none() {
+        errors.add(name, format!("no account {:032x}", id));
+    }
+    Ok(account)
+}
+
+/// The ID of the account `value` names, by alias or hex ID.
+fn account_id(aliases: &Aliases, value: &str) -> Result<u128, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("required".to_string());
+    }
+    if let Some(alias) = aliases.find(value) {
+        return Ok(alias.account_id);
+    }
+    match validate::hex_id("", value) {
+        Ok(id) if id != 0 => Ok(id),
+        _ => Err(format!("no alias or hex ID {:?}", value)),
+    }
+}
+
+fn positive(amount: u128) -> Result<u128, String> {
+    if amount == 0 {
+        Err("must be more than zero".to_string())
+    } else {
+        Ok(amount)
+    }
+}
+
+fn parse_code(value: &str) -> Result<u16, String> {
+    match value.trim() {
+        "" => Err("required".to_string()),
+        value => value
+            .parse()
+            .ok()
+            .filter(|&code| code != 0)
+            .ok_or_else(|| format!("must be 1 to {}, not {:?}", u16::MAX, value)),
+    }
+}
+
+/// Parse the timeout of a transfer that is `pending` or not; blank is
+/// none.
+fn parse_timeout(value: &str, pending: bool) -> Result<u32, String> {
+    match value.trim() {
+        "" => Ok(0),
+        _ if !pending => Err("only pending transfers time out".to_string()),
+        value => value
+            .parse()
+            .map_err(|_| format!("invalid number of seconds {:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aliases::Alias;
+    use crate::currency::{parse_mapping, Currencies};
+    use crate::testing::{self, account, FakeCluster};
+    use axum::body::Body;
+    use axum::http::Request;
+
+    const ID: &str = "000000000000000000000000000000aa";
+
+    type Changes<'a> = &'a [(&'a str, &'a str)];
+
+    /// A valid form for 12.50 from account 1 to account 2, with `changes`.
+    fn fields<'a>(changes: &[(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+        let mut fields = vec![
+            ("id", ID),
+            ("debit", "1"),
+            ("credit", "2"),
+            ("amount", "12.50"),
+            ("code", "7"),
+        ];
+        for &(name, value) in changes {
+            fields.retain(|&(field, _)| field != name);
+            fields.push((name, value));
+        }
+        fields
+    }
+
+    async fn post(router: &axum::Router, fields: &[(&str, &str)]) -> (StatusCode, String) {
+        // The values used here need no escaping.
+        let body: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let request = Request::post("/api/v1/transfers/new")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(body.join("&")))
+            .unwrap();
+        testing::send(router, request).await
+    }
+
+    /// The errors a rendered form shows, by the ID of the field's input
+    /// (`""` for the form as a whole).
+    fn shown_errors(body: &str) -> Vec<(String, String)> {
+        let text = |s: &str| s[..s.find("</p>").unwrap()].replace("&quot;", "\"");
+        let mut errors = Vec::new();
+        if let Some((_, rest)) = body.split_once(r#"<p class="query-error">"#) {
+            errors.push((String::new(), text(rest)));
+        }
+        for field in body.split(r#"class="form-field invalid""#).skip(1) {
+            let (_, id) = field.split_once(r#"for=""#).unwrap();
+            let id = &id[..id.find('"').unwrap()];
+            let (_, error) = field.split_once(r#"<p class="field-error">"#).unwrap();
+            errors.push((id.to_string(), text(error)));
+        }
+        errors
+    }
+
+    /// Accounts 1 and 2 on ledger 1, in USD, and 3 on ledger 2 in whole
+    /// units; account 1 is aliased `alice`.
+    async fn router() -> (axum::Router, std::sync::Arc<FakeCluster>) {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1), account(2, 1), account(3, 2)], &[]);
+        let mut config = testing::config();
+        config.currencies = Currencies::new([parse_mapping("1=USD").unwrap()]);
+        let state = testing::state(config, &cluster);
+        let alias = Alias {
+            account_id: 1,
+            name: "alice".to_string(),
+            labels: Vec::new(),
+        };
+        state.aliases.set(alias).await.unwrap();
+        (crate::routes::router(state), cluster)
+    }
+
+    #[tokio::test]
+    async fn test_field_errors() {
+        let (router, _) = router().await;
+        // The changes to a valid form, the field in error and its error.
+        let cases: &[(Changes, &str, &str)] = &[
+            (&[("debit", " ")], "transfer-debit", "required"),
+            (
+                &[("debit", "bob")],
+                "transfer-debit",
+                r#"no alias or hex ID "bob""#,
+            ),
+            (
+                &[("debit", "0")],
+                "transfer-debit",
+                r#"no alias or hex ID "0""#,
+            ),
+            (
+                &[("debit", "ff")],
+                "transfer-debit",
+                "no account 000000000000000000000000000000ff",
+            ),
+            (&[("credit", "")], "transfer-credit", "required"),
+            (
+                &[("credit", "alice")],
+                "transfer-credit",
+                "must differ from the debit account",
+            ),
+            (
+                &[("credit", "3")],
+                "transfer-credit",
+                "is on ledger 2, the debit account on ledger 1",
+            ),
+            // The amount is in the debit account's currency.
+            (
+                &[("amount", "12.505")],
+                "transfer-amount",
+                r#"USD has 2 decimal places, "12.505" has more"#,
+            ),
+            (
+                &[("amount", "12,5x")],
+                "transfer-amount",
+                r#"invalid USD amount "12,5x""#,
+            ),
+            (
+                &[("amount", "0.00")],
+                "transfer-amount",
+                "must be more than zero",
+            ),
+            (
+                &[("debit", "3"), ("credit", "2"), ("amount", "5")],
+                "transfer-credit",
+                "is on ledger 1, the debit account on ledger 2",
+            ),
+            (&[("code", "")], "transfer-code", "required"),
+            (
+                &[("code", "0")],
+                "transfer-code",
+                r#"must be 1 to 65535, not "0""#,
+            ),
+            (
+                &[("code", "65536")],
+                "transfer-code",
+                r#"must be 1 to 65535, not "65536""#,
+            ),
+            (
+                &[("timeout", "60")],
+                "transfer-timeout",
+                "only pending transfers time out",
+            ),
+            (
+                &[("pending", "true"), ("timeout", "soon")],
+                "transfer-timeout",
+                r#"invalid number of seconds "soon""#,
+            ),
+            (
+                &[("id", "")],
+                "",
+                "The form was out of date; check it and submit it again",
+            ),
+        ];
+        for &(changes, field, message) in cases {
+            let (status, body) = post(&router, &fields(changes)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}", changes);
+            assert_eq!(
+                shown_errors(&body),
+                [(field.to_string(), message.to_string())],
+                "{:?}",
+                changes
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_amount_without_currency() {
+        let (router, cluster) = router().await;
+        cluster.seed(&[account(4, 2)], &[]);
+        // Ledger 2 has no currency, so the amount is in whole units.
+        let changes = [("debit", "3"), ("credit", "4"), ("amount", "1.5")];
+        let (_, body) = post(&router, &fields(&changes)).await;
+        assert_eq!(
+            shown_errors(&body),
+            [(
+                "transfer-amount".to_string(),
+                r#"invalid amount "1.5""#.to_string()
+            )]
+        );
+        let changes = [("debit", "3"), ("credit", "4"), ("amount", "15")];
+        let (status, _) = post(&router, &fields(&changes)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(cluster.lookup_transfers(&[0xaa]).unwrap()[0].amount, 15);
+
+        // Without a debit account, the amount can't be checked.
+        let changes = [("debit", ""), ("amount", "lots")];
+        let (_, body) = post(&router, &fields(&changes)).await;
+        assert_eq!(
+            shown_errors(&body),
+            [("transfer-debit".to_string(), "required".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create() {
+        let (router, cluster) = router().await;
+        let changes = [("debit", "alice"), ("pending", "true"), ("timeout", "60")];
+        let (status, body) = post(&router, &fields(&changes)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let created = cluster.lookup_transfers(&[0xaa]).unwrap();
+        let transfer = created[0];
+        assert_eq!(
+            (transfer.debit_account_id, transfer.credit_account_id),
+            (1, 2)
+        );
+        assert_eq!(
+            (transfer.amount, transfer.ledger, transfer.code),
+            (1250, 1, 7)
+        );
+        assert_eq!(transfer.timeout, 60);
+        assert!(transfer.flags.contains(TransferFlags::PENDING));
+
+        // Submitting the form again does not create another.
+        let (status, _) = post(&router, &fields(&changes)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let pending = cluster.lookup_accounts(&[1]).unwrap()[0].debits_pending;
+        assert_eq!(pending, 1250);
+    }
+}
@@ -0,0 +1,185 @@
+//! Saved query route handlers (see [`crate::queries`]).
+
+use crate::api::{
+    AccountsResponse, ApiAccount, ApiTransfer, QueriesResponse, QueryKind, SavedQuery,
+    TransfersResponse,
+};
+use crate::auth::{Operator, Viewer};
+use crate::error::AppError;
+use crate::html;
+use crate::queries::{self, Results};
+use crate::state::AppState;
+use crate::validate::{Valid, Validate};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Check if request is from HTMX.
+fn is_htmx_request(headers: &HeaderMap) -> bool {
+    headers.contains_key("hx-request")
+}
+
+/// List saved queries by name.
+pub async fn list_queries(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let queries = state.queries.list();
+    if is_htmx_request(&headers) {
+        Html(html::render_saved_queries(
+            &queries,
+            &state.config.timestamps,
+        ))
+        .into_response()
+    } else {
+        Json(QueriesResponse { queries }).into_response()
+    }
+}
+
+/// Save a query under its name, replacing any query of that name.
+///
+/// Returns 201 for a new name and 200 for a replaced query.
+pub async fn save_query(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    body: Result<Json<SavedQuery>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(mut query) = body.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    query.name = query.name.trim().to_string();
+    queries::plan(&query, None).map_err(AppError::Unprocessable)?;
+
+    let replaced = state.queries.set(query.clone()).await?;
+    let status = if replaced {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((status, Json(query)).into_response())
+}
+
+/// Get the query saved as `name`.
+pub async fn get_query(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<SavedQuery>, AppError> {
+    state
+        .queries
+        .get(&name)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Query {} not found", name)))
+}
+
+/// Remove the query saved as `name`.
+pub async fn delete_query(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    if !state.queries.remove(name.clone()).await? {
+        return Err(AppError::NotFound(format!("Query {} not found", name)));
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Query parameters for running a saved query.
+#[derive(Debug, Deserialize)]
+pub struct RunQueryParams {
+    /// Pagination: start after this timestamp.
+    pub after_timestamp: Option<u64>,
+}
+
+impl Validate for RunQueryParams {}
+
+/// Run the query saved as `name`.
+///
+/// Responds like the list endpoints, with `next_timestamp` set only when a
+/// full page came back. Runs page forward only, so there is no
+/// `prev_cursor`.
+pub async fn run_query(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Valid(params): Valid<RunQueryParams>,
+) -> Result<Response, AppError> {
+    let query = state
+        .queries
+        .get(&name)
+        .ok_or_else(|| AppError::NotFound(format!("Query {} not found", name)))?;
+    let plan = queries::plan(&query, params.after_timestamp).map_err(AppError::BadRequest)?;
+
+    let results = match plan {
+        Some(plan) => queries::run(&state.pool, plan, query.limit, query.reversed).await?,
+        None => match query.kind {
+            QueryKind::Accounts => Results::Accounts(Vec::new()),
+            QueryKind::Transfers => Results::Transfers(Vec::new()),
+        },
+    };
+
+    let currencies = &state.config.currencies;
+    let full = |len: usize| len == query.limit as usize;
+    match results {
+        Results::Accounts(accounts) => {
+            let next_timestamp = accounts
+                .last()
+                .filter(|_| full(accounts.len()))
+                .map(|a| a.timestamp);
+            let accounts: Vec<ApiAccount> = accounts
+                .iter()
+                .map(|a| ApiAccount::from(a).with_display(currencies))
+                .collect();
+            if is_htmx_request(&headers) {
+                Ok(Html(html::render_query_accounts(
+                    &name,
+                    &accounts,
+                    next_timestamp,
+                    &state.aliases,
+                    &state.config.timestamps,
+                ))
+                .into_response())
+            } else {
+                Ok(Json(AccountsResponse {
+                    accounts,
+                    next_timestamp,
+                    prev_cursor: None,
+                    next_cursor: next_timestamp,
+                })
+                .into_response())
+            }
+        }
+        Results::Transfers(transfers) => {
+            let next_timestamp = transfers
+                .last()
+                .filter(|_| full(transfers.len()))
+                .map(|t| t.timestamp);
+            let transfers: Vec<ApiTransfer> = transfers
+                .iter()
+                .map(|t| ApiTransfer::from(t).with_display(currencies))
+                .collect();
+            if is_htmx_request(&headers) {
+                Ok(Html(html::render_query_transfers(
+                    &name,
+                    &transfers,
+                    next_timestamp,
+                    &state.aliases,
+                    &state.config.timestamps,
+                ))
+                .into_response())
+            } else {
+                Ok(Json(TransfersResponse {
+                    transfers,
+                    next_timestamp,
+                    prev_cursor: None,
+                    next_cursor: next_timestamp,
+                })
+                .into_response())
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Recently viewed handler (see [`crate::recent`]).
+
+use crate::api::{rfc3339, ApiViewed, RecentResponse};
+use crate::auth::Viewer;
+use crate::html;
+use crate::recent::{Kind, Session};
+use crate::state::AppState;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use std::sync::Arc;
+
+/// Check if request is from HTMX.
+fn is_htmx_request(headers: &HeaderMap) -> bool {
+    headers.contains_key("hx-request")
+}
+
+/// The accounts and transfers this session opened in the web UI, newest
+/// first; none without a session cookie.
+pub async fn list_recent(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    session: Session,
+) -> Response {
+    let viewed = state.recent.list(session).await;
+
+    if is_htmx_request(&headers) {
+        return Html(html::render_recent(
+            &viewed,
+            &state.aliases,
+            &state.config.timestamps,
+        ))
+        .into_response();
+    }
+    let viewed = viewed
+        .iter()
+        .map(|viewed| ApiViewed {
+            kind: viewed.kind.name(),
+            id: format!("{:032x}", viewed.id),
+            alias: match viewed.kind {
+                Kind::Account => state.aliases.get(viewed.id).map(|alias| alias.name),
+                Kind::Transfer => None,
+            },
+            viewed_at: rfc3339(viewed.at),
+        })
+        .collect();
+    Json(RecentResponse { viewed }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, account, transfer, FakeCluster};
+    use crate::tls::TlsConfig;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    /// Open `uri` in the web UI with `cookie`, returning the `Set-Cookie`
+    /// value if there is one.
+    async fn open(router: &Router, uri: &str, cookie: Option<&str>) -> Option<String> {
+        let mut request = Request::get(uri).header("hx-request", "true");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let response = router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let set_cookie = response.headers().get(header::SET_COOKIE)?;
+        Some(set_cookie.to_str().unwrap().to_string())
+    }
+
+    fn cluster() -> std::sync::Arc<FakeCluster> {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[account(1, 1), account(2, 1)], &[transfer(10, 1, 2, 5, 1)]);
+        cluster
+    }
+
+    #[tokio::test]
+    async fn test_recent() {
+        let router = testing::router(testing::config(), &cluster());
+        let set_cookie = open(&router, "/api/v1/accounts/1", None).await.unwrap();
+        assert!(!set_cookie.contains("Secure"), "{}", set_cookie);
+        let cookie = set_cookie.split(';').next().unwrap();
+        assert_eq!(
+            open(&router, "/api/v1/transfers/a", Some(cookie)).await,
+            None
+        );
+
+        let request = Request::get("/api/v1/recent")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = testing::send(&router, request).await;
+        assert_eq!(status, StatusCode::OK);
+        let recent: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let viewed: Vec<(&str, &str)> = recent["viewed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| (v["kind"].as_str().unwrap(), v["id"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            viewed,
+            [
+                ("transfer", "0000000000000000000000000000000a"),
+                ("account", "00000000000000000000000000000001"),
+            ]
+        );
+
+        // Without the cookie, there is nothing; and JSON requests are not
+        // recorded.
+        let (_, body) = testing::get(&router, "/api/v1/recent").await;
+        assert_eq!(body, r#"{"viewed":[]}"#);
+        testing::get(&router, "/api/v1/accounts/2").await;
+        let request = Request::get("/api/v1/recent")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let (_, body) = testing::send(&router, request).await;
+        assert!(
+            !body.contains("00000000000000000000000000000002"),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cookie_secure_over_tls() {
+        let mut config = testing::config();
+        config.tls = Some(TlsConfig {
+            cert: "cert.pem".into(),
+            key: "key.pem".into(),
+        });
+        let router = testing::router(config, &cluster());
+        let set_cookie = open(&router, "/api/v1/accounts/1", None).await.unwrap();
+        assert!(set_cookie.ends_with("; Secure"), "{}", set_cookie);
+        let set_cookie = open(&router, "/api/v1/transfers/a", None).await.unwrap();
+        assert!(set_cookie.ends_with("; Secure"), "{}", set_cookie);
+    }
+}
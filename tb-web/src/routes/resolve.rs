@@ -0,0 +1,338 @@
+//! Resolving shortened IDs.
+//!
+//! Tables show a 128-bit ID as its first and last eight hex digits, like
+//! `0000abcd...00001234`, keeping the full ID in the link. Copied from the
+//! page, such an ID can be turned back into the full one with
+//! `GET /api/v1/resolve?id_prefix=0000abcd...00001234`: the IDs that start
+//! with the digits given and, after a `...`, end with the ones after it.
+//!
+//! TigerBeetle can't look up IDs by their digits, so only the IDs tb-web
+//! knows of are searched: aliased accounts, and the accounts and transfers
+//! in the caches. With `scan=true`, the newest accounts are paged through
+//! as well, up to [`SCAN_MAX`] of them.
+
+use crate::api::{ResolveResponse, ResolvedId};
+use crate::auth::Viewer;
+use crate::error::AppError;
+use crate::html;
+use crate::state::AppState;
+use crate::validate::{Valid, Validate};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tb_rs::{QueryFilter, QueryFilterFlags};
+
+/// Fewest hex digits to search by, so a search can't list everything.
+const MIN_DIGITS: usize = 4;
+
+/// Most matches listed.
+const MATCHES_MAX: usize = 20;
+
+/// Most accounts a scan pages through.
+const SCAN_MAX: usize = 100_000;
+
+/// Accounts fetched per query of a scan.
+const SCAN_PAGE_SIZE: u32 = 1000;
+
+/// Check if request is from HTMX.
+fn is_htmx_request(headers: &HeaderMap) -> bool {
+    headers.contains_key("hx-request")
+}
+
+/// Query parameters for resolving an ID.
+#[derive(Debug, Deserialize)]
+pub struct ResolveParams {
+    /// Leading hex digits of the ID, optionally followed by `...` and its
+    /// trailing digits.
+    pub id_prefix: String,
+    /// Also page through the newest accounts.
+    #[serde(default)]
+    pub scan: bool,
+}
+
+impl Validate for ResolveParams {}
+
+/// A shortened ID: the digits an ID starts and ends with.
+struct Pattern {
+    prefix: String,
+    suffix: String,
+}
+
+impl Pattern {
+    /// Parse `id`, or say why it is not a shortened ID.
+    fn parse(id: &str) -> Result<Self, String> {
+        let id = id.trim().to_ascii_lowercase();
+        let digits = id.strip_prefix("0x").unwrap_or(&id);
+        let (prefix, suffix) = digits.split_once("...").unwrap_or((digits, ""));
+        let hex = |part: &str| part.bytes().all(|b| b.is_ascii_hexdigit());
+        if !hex(prefix) || !hex(suffix) || prefix.len() + suffix.len() > 32 {
+            return Err(format!("Invalid ID prefix: {}", id));
+        }
+        if prefix.len() + suffix.len() < MIN_DIGITS {
+            return Err(format!(
+                "ID prefix needs at least {} hex digits",
+                MIN_DIGITS
+            ));
+        }
+        Ok(Self {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+        })
+    }
+
+    fn matches(&self, id: u128) -> bool {
+        let hex = format!("{:032x}", id);
+        hex.starts_with(&self.prefix) && hex.ends_with(&self.suffix)
+    }
+}
+
+/// The known IDs a shortened one may stand for, accounts first.
+///
+/// An invalid `id_prefix` is a 422, rendered as a message for HTMX.
+pub async fn resolve(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Valid(params): Valid<ResolveParams>,
+) -> Result<Response, AppError> {
+    let htmx = is_htmx_request(&headers);
+    let pattern = match Pattern::parse(&params.id_prefix) {
+        Ok(pattern) => pattern,
+        Err(message) if htmx => {
+            let body = html::render_resolve_error(&message);
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, Html(body)).into_response());
+        }
+        Err(message) => return Err(AppError::Unprocessable(message)),
+    };
+
+    // (whether it is a transfer's, ID), so accounts sort first.
+    let mut found = BTreeSet::new();
+    for alias in state.aliases.list() {
+        if pattern.matches(alias.account_id) {
+            found.insert((false, alias.account_id));
+        }
+    }
+    state.cache.for_each_id(|id, account| {
+        if pattern.matches(id) {
+            found.insert((!account, id));
+        }
+    });
+    if params.scan && found.len() <= MATCHES_MAX {
+        scan(&state, &pattern, &mut found).await?;
+    }
+
+    let response = ResolveResponse {
+        truncated: found.len() > MATCHES_MAX,
+        matches: found
+            .into_iter()
+            .take(MATCHES_MAX)
+            .map(|(transfer, id)| ResolvedId {
+                kind: if transfer { "transfer" } else { "account" },
+                id: format!("{:032x}", id),
+                alias: (!transfer)
+                    .then(|| state.aliases.get(id))
+                    .flatten()
+                    .map(|alias| alias.name),
+            })
+            .collect(),
+    };
+
+    if htmx {
+        Ok(Html(html::render_resolved(&response)).into_response())
+    } else {
+        Ok(Json(response).into_response())
+    }
+}
+
+/// Add the matching accounts among the newest [`SCAN_MAX`], stopping once
+/// there are more matches than can be listed.
+async fn scan(
+    state: &AppState,
+    pattern: &Pattern,
+    found: &mut BTreeSet<(bool, u128)>,
+) -> Result<(), AppError> {
+    let mut timestamp_max = 0;
+    let mut scanned = 0;
+    while scanned < SCAN_MAX && found.len() <= MATCHES_MAX {
+        let filter = QueryFilter {
+            user_data_128: 0,
+            user_data_64: 0,
+            user_data_32: 0,
+            ledger: 0,
+            code: 0,
+            timestamp_min: 0,
+            timestamp_max,
+            limit: SCAN_PAGE_SIZE,
+            flags: QueryFilterFlags::REVERSED,
+            reserved: [0; 6],
+        };
+        let accounts = state.pool.get().query_accounts(filter).await?;
+        for account in &accounts {
+            if pattern.matches(account.id) {
+                found.insert((false, account.id));
+            }
+        }
+        scanned += accounts.len();
+        match accounts.last() {
+            // Zero would lift the bound instead.
+            Some(last) if accounts.len() == SCAN_PAGE_SIZE as usize && last.timestamp > 1 => {
+                timestamp_max = last.timestamp - 1;
+            }
+            _ => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aliases::Alias;
+    use crate::testing::{self, account, FakeCluster};
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::{json, Value};
+
+    /// An ID whose hex starts with `high` and ends with `low`.
+    fn id(high: u16, low: u16) -> u128 {
+        u128::from(high) << 112 | u128::from(low)
+    }
+
+    async fn get_json(router: &axum::Router, query: &str) -> (StatusCode, Value) {
+        let (status, body) = testing::get(router, &format!("/api/v1/resolve?{}", query)).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn ids(response: &Value) -> Vec<&str> {
+        response["matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_pattern_parse() {
+        let parse = |id: &str| Pattern::parse(id).map(|p| (p.prefix, p.suffix));
+        let pattern = |prefix: &str, suffix: &str| Ok((prefix.to_string(), suffix.to_string()));
+        assert_eq!(parse("abcd"), pattern("abcd", ""));
+        assert_eq!(parse(" 0xABCD...0012 "), pattern("abcd", "0012"));
+        assert_eq!(parse("0X12...34"), pattern("12", "34"));
+        assert_eq!(parse("...1234"), pattern("", "1234"));
+        assert_eq!(parse(&"f".repeat(32)), pattern(&"f".repeat(32), ""));
+
+        assert_eq!(
+            parse("abc").unwrap_err(),
+            "ID prefix needs at least 4 hex digits"
+        );
+        assert_eq!(
+            parse("0x").unwrap_err(),
+            "ID prefix needs at least 4 hex digits"
+        );
+        for id in ["abcg", "ab..cd", "0x0xabcd", &"f".repeat(33)] {
+            assert!(
+                parse(id).unwrap_err().starts_with("Invalid ID prefix"),
+                "{}",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let pattern = Pattern::parse("abcd...0002").unwrap();
+        assert!(pattern.matches(id(0xabcd, 2)));
+        assert!(!pattern.matches(id(0xabcd, 3)));
+        assert!(!pattern.matches(id(0xabce, 2)));
+        // Leading zeros count as digits.
+        assert!(Pattern::parse("0000").unwrap().matches(1));
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let cluster = FakeCluster::new();
+        let accounts: Vec<_> = [id(0xabcd, 1), id(0xabcd, 2), id(0xabce, 1)]
+            .into_iter()
+            .map(|id| account(id, 1))
+            .collect();
+        cluster.seed(&accounts, &[]);
+        let state = testing::state(testing::config(), &cluster);
+        let alias = Alias {
+            account_id: id(0xabcd, 2),
+            name: "savings".to_string(),
+            labels: Vec::new(),
+        };
+        state.aliases.set(alias).await.unwrap();
+        let router = crate::routes::router(state);
+
+        // Without a scan, only the aliased account is known.
+        let (status, response) = get_json(&router, "id_prefix=abcd").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response,
+            json!({
+                "matches": [{
+                    "kind": "account",
+                    "id": "abcd0000000000000000000000000002",
+                    "alias": "savings",
+                }],
+                "truncated": false,
+            })
+        );
+
+        // Ambiguous: every match is listed, in ID order.
+        let (_, response) = get_json(&router, "id_prefix=abcd&scan=true").await;
+        assert_eq!(
+            ids(&response),
+            [
+                "abcd0000000000000000000000000001",
+                "abcd0000000000000000000000000002",
+            ]
+        );
+
+        // The full shortened form, with `0x`.
+        let (_, response) = get_json(&router, "id_prefix=0xABCE...00000001&scan=true").await;
+        assert_eq!(ids(&response), ["abce0000000000000000000000000001"]);
+
+        // Not found.
+        let (status, response) = get_json(&router, "id_prefix=ffff&scan=true").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!({"matches": [], "truncated": false}));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_truncates() {
+        let cluster = FakeCluster::new();
+        let accounts: Vec<_> = (0..MATCHES_MAX as u16 + 5)
+            .map(|low| account(id(0xabcd, low + 1), 1))
+            .collect();
+        cluster.seed(&accounts, &[]);
+        let router = testing::router(testing::config(), &cluster);
+        let (_, response) = get_json(&router, "id_prefix=abcd&scan=true").await;
+        assert_eq!(ids(&response).len(), MATCHES_MAX);
+        assert_eq!(ids(&response)[0], "abcd0000000000000000000000000001");
+        assert_eq!(response["truncated"], true);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_invalid() {
+        let router = testing::router(testing::config(), &FakeCluster::new());
+        let (status, response) = get_json(&router, "id_prefix=0xab").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response["error"], "ID prefix needs at least 4 hex digits");
+
+        // HTMX gets the message to show in place of the matches.
+        let request = Request::get("/api/v1/resolve?id_prefix=xyz!")
+            .header("hx-request", "true")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = testing::send(&router, request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.contains("Invalid ID prefix"), "{}", body);
+    }
+}
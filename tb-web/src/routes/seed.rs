@@ -0,0 +1,303 @@
+//! Demo data handler.
+//!
+//! With `--enable-seeding`, `POST /api/v1/seed` fills a ledger with random
+//! accounts and transfers between them, so a new user can explore the UI
+//! against an empty dev cluster. The data is generated as `tb-gen` would,
+//! with its generation logic as a library: the body can ask for a number of
+//! accounts and transfers, a ledger and code, the largest amount, how
+//! transfers pick accounts, and a seed to generate the same data each time.
+//!
+//! Everything generated carries the request's run ID in `user_data_64`, so
+//! it can be told apart from real data and queried later.
+
+use crate::api::{SeedReport, SeedRequest};
+use crate::auth::Admin;
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::validate;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use clap::ValueEnum;
+use std::sync::Arc;
+use tb_gen::distribution::Distribution;
+use tb_gen::generate::{generate_accounts, generate_transfers, tag_accounts};
+use tb_gen::ledger::Ledger;
+use tb_gen::seed::{self, Source};
+use tb_rs::{Account, Transfer};
+
+/// Most accounts one request may generate.
+pub const ACCOUNTS_MAX: u32 = 10_000;
+
+/// Most transfers one request may generate.
+pub const TRANSFERS_MAX: u32 = 1_000_000;
+
+/// Skew of the zipfian distribution, tb-gen's default.
+const ZIPFIAN_SKEW: f64 = 1.1;
+
+/// Generate demo accounts and transfers and create them.
+///
+/// Returns 201 when everything was created and 207 otherwise, with how many
+/// of each were created and failed either way. A client error part way
+/// through stops seeding and is in the report, whose counts are of the
+/// batches created before it.
+pub async fn seed(
+    _: Admin,
+    State(state): State<Arc<AppState>>,
+    body: Result<Json<SeedRequest>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(request) = body.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let distribution = check(&request).map_err(AppError::Unprocessable)?;
+    let batch_max = (
+        validate::max_events::<Account>(&state)?,
+        validate::max_events::<Transfer>(&state)?,
+    );
+
+    let mut report = SeedReport {
+        ledger: request.ledger,
+        run_id: seed::run_id(request.seed),
+        accounts_created: 0,
+        accounts_failed: 0,
+        transfers_created: 0,
+        transfers_failed: 0,
+        error: None,
+    };
+    if let Err(error) = create(&state, &request, distribution, batch_max, &mut report).await {
+        report.error = Some(error.into());
+    }
+
+    tracing::info!(
+        "Seeded ledger {} with {} accounts and {} transfers (run ID {})",
+        report.ledger,
+        report.accounts_created,
+        report.transfers_created,
+        report.run_id
+    );
+    let status =
+        if report.accounts_failed == 0 && report.transfers_failed == 0 && report.error.is_none() {
+            StatusCode::CREATED
+        } else {
+            StatusCode::MULTI_STATUS
+        };
+    Ok((status, Json(report)).into_response())
+}
+
+/// Generate and create what `request` asks for, in batches of at most
+/// `batch_max` accounts and transfers, counting each batch in `report` as
+/// it is created.
+async fn create(
+    state: &AppState,
+    request: &SeedRequest,
+    distribution: Distribution,
+    (accounts_max, transfers_max): (u32, u32),
+    report: &mut SeedReport,
+) -> Result<(), AppError> {
+    let mut source = Source::new(request.seed);
+    let mut accounts =
+        generate_accounts(request.accounts, request.ledger, request.code, &mut source);
+    tag_accounts(&mut accounts, report.run_id);
+    for batch in accounts.chunks(accounts_max as usize) {
+        let failures = {
+            let client = state.pool.get();
+            client.create_accounts(batch).await?
+        };
+        let ids: Vec<u128> = batch.iter().map(|a| a.id).collect();
+        state.cache.accounts_created(&ids).await;
+        report.accounts_created += (batch.len() - failures.len()) as u64;
+        report.accounts_failed += failures.len() as u64;
+    }
+
+    if request.transfers > 0 {
+        let ids = accounts.iter().map(|a| a.id).collect();
+        let ledgers = [Ledger::new(request.ledger, ids, distribution, ZIPFIAN_SKEW)];
+        let mut remaining = request.transfers;
+        while remaining > 0 {
+            let count = remaining.min(transfers_max);
+            let mut transfers = generate_transfers(
+                count,
+                &ledgers,
+                request.code,
+                request.max_amount as u128,
+                &mut source,
+            );
+            for transfer in &mut transfers {
+                transfer.user_data_64 = report.run_id;
+            }
+            let failures = {
+                let client = state.pool.get();
+                client.create_transfers(&transfers).await?
+            };
+            state.cache.transfers_created();
+            report.transfers_created += (transfers.len() - failures.len()) as u64;
+            report.transfers_failed += failures.len() as u64;
+            remaining -= count;
+        }
+    }
+    Ok(())
+}
+
+/// Check `request`, returning how its transfers pick accounts.
+fn check(request: &SeedRequest) -> Result<Distribution, String> {
+    if request.accounts == 0 || request.accounts > ACCOUNTS_MAX {
+        return Err(format!("accounts: must be 1 to {}", ACCOUNTS_MAX));
+    }
+    if request.transfers > TRANSFERS_MAX {
+        return Err(format!("transfers: at most {}", TRANSFERS_MAX));
+    }
+    if request.transfers > 0 && request.accounts < 2 {
+        return Err("accounts: transfers need at least 2".to_string());
+    }
+    if request.ledger == 0 {
+        return Err("ledger: must not be 0".to_string());
+    }
+    if request.code == 0 {
+        return Err("code: must not be 0".to_string());
+    }
+    if request.max_amount == 0 {
+        return Err("max_amount: must not be 0".to_string());
+    }
+    match &request.distribution {
+        Some(name) => Distribution::from_str(name, true)
+            .map_err(|_| format!("distribution: expected uniform or zipfian, got {:?}", name)),
+        None => Ok(Distribution::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::testing::{self, FakeCluster};
+    use serde_json::json;
+    use tb_rs::ClientError;
+
+    fn request(accounts: u32, transfers: u32) -> SeedRequest {
+        SeedRequest {
+            accounts,
+            transfers,
+            ledger: 1,
+            code: 1,
+            max_amount: 100,
+            distribution: None,
+            seed: Some(7),
+        }
+    }
+
+    async fn seed(
+        config: Config,
+        cluster: &Arc<FakeCluster>,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let router = testing::router(config, cluster);
+        let (status, body) = testing::send_json(&router, "POST", "/api/v1/seed", body).await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&request(1, 0)).is_ok());
+        assert!(check(&request(2, 1)).is_ok());
+        assert!(check(&request(ACCOUNTS_MAX, TRANSFERS_MAX)).is_ok());
+
+        for (request, error) in [
+            (request(0, 0), "accounts: must be 1 to 10000"),
+            (request(ACCOUNTS_MAX + 1, 0), "accounts: must be 1 to 10000"),
+            (request(2, TRANSFERS_MAX + 1), "transfers: at most 1000000"),
+            (request(1, 1), "accounts: transfers need at least 2"),
+            (
+                SeedRequest {
+                    ledger: 0,
+                    ..request(2, 1)
+                },
+                "ledger: must not be 0",
+            ),
+            (
+                SeedRequest {
+                    code: 0,
+                    ..request(2, 1)
+                },
+                "code: must not be 0",
+            ),
+            (
+                SeedRequest {
+                    max_amount: 0,
+                    ..request(2, 1)
+                },
+                "max_amount: must not be 0",
+            ),
+            (
+                SeedRequest {
+                    distribution: Some("normal".to_string()),
+                    ..request(2, 1)
+                },
+                "distribution: expected uniform or zipfian, got \"normal\"",
+            ),
+        ] {
+            assert_eq!(check(&request).unwrap_err(), error, "{:?}", request);
+        }
+
+        for (name, distribution) in [
+            ("uniform", Distribution::Uniform),
+            ("Zipfian", Distribution::Zipfian),
+        ] {
+            let request = SeedRequest {
+                distribution: Some(name.to_string()),
+                ..request(2, 1)
+            };
+            assert_eq!(check(&request).unwrap(), distribution);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seed() {
+        let cluster = FakeCluster::new();
+        let body = json!({ "accounts": 10, "transfers": 25, "ledger": 3, "seed": 7 });
+        let (status, report) = seed(testing::config(), &cluster, body.clone()).await;
+        assert_eq!(status, 201, "{}", report);
+        assert_eq!(report["ledger"], 3);
+        assert_eq!(report["run_id"], seed::run_id(Some(7)));
+        assert_eq!(report["accounts_created"], 10);
+        assert_eq!(report["transfers_created"], 25);
+        assert!(report.get("error").is_none());
+
+        // The same seed generates the same IDs again.
+        let (status, report) = seed(testing::config(), &cluster, body).await;
+        assert_eq!(status, 207);
+        assert_eq!(report["accounts_created"], 0);
+        assert_eq!(report["accounts_failed"], 10);
+    }
+
+    #[tokio::test]
+    async fn test_seed_keeps_report_on_error() {
+        let config = Config {
+            max_batch_events: Some(10),
+            ..testing::config()
+        };
+        let body = json!({ "accounts": 25, "transfers": 25, "seed": 7 });
+
+        // The third batch of accounts fails.
+        let cluster = FakeCluster::new();
+        cluster.fail_after(2, ClientError::Timeout);
+        let (status, report) = seed(config.clone(), &cluster, body.clone()).await;
+        assert_eq!(status, 207, "{}", report);
+        assert_eq!(report["accounts_created"], 20);
+        assert_eq!(report["accounts_failed"], 0);
+        assert_eq!(report["transfers_created"], 0);
+        assert_eq!(report["error"]["code"], "tb_timeout");
+        assert_eq!(
+            report["error"]["error"],
+            "TigerBeetle did not reply in time"
+        );
+
+        // The second batch of transfers fails.
+        let cluster = FakeCluster::new();
+        cluster.fail_after(4, ClientError::Shutdown);
+        let (status, report) = seed(config, &cluster, body).await;
+        assert_eq!(status, 207, "{}", report);
+        assert_eq!(report["accounts_created"], 25);
+        assert_eq!(report["transfers_created"], 10);
+        assert_eq!(report["error"]["code"], "tb_unavailable");
+    }
+}
@@ -0,0 +1,583 @@
+//! Snapshot export handler.
+//!
+//! `GET /api/v1/export/snapshot` downloads every account and transfer
+//! matching optional filters as one ZIP archive, for backups and analysis:
+//! `accounts` and `transfers` files in NDJSON or, with `format=csv`, CSV
+//! with a header row, then a `manifest.json` saying what was exported and
+//! how many records each file holds. CSV leaves out the currency display
+//! fields.
+//!
+//! Before streaming, the first and last matching timestamp of each file is
+//! looked up and sent in the `X-Snapshot-Accounts-Range` and
+//! `X-Snapshot-Transfers-Range` headers, as `first-last`. Records created
+//! while the archive is written fall outside them and are left out, and
+//! since each file is in timestamp order, a client can tell its progress
+//! from the timestamp of the last record it unpacked. A file with nothing
+//! to export has no header.
+//!
+//! The archive is written a page at a time like the NDJSON exports (see
+//! [`super::export`]), so neither side holds it in memory.
+
+use super::export::{csv_line, Pages, ACCOUNT_COLUMNS, PAGES_AHEAD, PAGE_SIZE, TRANSFER_COLUMNS};
+use crate::api::{ApiAccount, ApiTransfer, SnapshotManifest};
+use crate::auth::Viewer;
+use crate::currency::Currencies;
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::validate::{self, Valid, Validate};
+use crate::zip::ZipWriter;
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use tb_rs::{ClientError, QueryFilter, QueryFilterFlags};
+use tokio::sync::mpsc;
+
+/// Query parameters for snapshots.
+#[derive(Debug, Deserialize)]
+pub struct SnapshotParams {
+    /// Format of the record files.
+    #[serde(default)]
+    pub format: SnapshotFormat,
+    /// Filter by ledger.
+    pub ledger: Option<u32>,
+    /// Filter by code.
+    pub code: Option<u16>,
+    /// Filter by user_data_128 (hex).
+    pub user_data_128: Option<String>,
+    /// Filter by user_data_64.
+    pub user_data_64: Option<u64>,
+    /// Filter by user_data_32.
+    pub user_data_32: Option<u32>,
+    /// Created at or after this timestamp (nanoseconds).
+    pub timestamp_min: Option<u64>,
+    /// Created at or before this timestamp (nanoseconds).
+    pub timestamp_max: Option<u64>,
+}
+
+impl SnapshotParams {
+    /// The `user_data_128` filter, once validated.
+    fn user_data_128(&self) -> u128 {
+        match &self.user_data_128 {
+            Some(value) => validate::hex_id("user_data_128", value).unwrap_or(0),
+            None => 0,
+        }
+    }
+}
+
+impl Validate for SnapshotParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::ledger("ledger", self.ledger)?;
+        validate::code("code", self.code)?;
+        if let Some(value) = &self.user_data_128 {
+            validate::hex_id("user_data_128", value)?;
+        }
+        validate::timestamp_range(self.timestamp_min, self.timestamp_max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+impl SnapshotFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Ndjson => "ndjson",
+            SnapshotFormat::Csv => "csv",
+        }
+    }
+}
+
+/// What a file of the archive holds.
+#[derive(Clone, Copy)]
+enum Records {
+    Accounts,
+    Transfers,
+}
+
+impl Records {
+    fn name(self) -> &'static str {
+        match self {
+            Records::Accounts => "accounts",
+            Records::Transfers => "transfers",
+        }
+    }
+
+    fn range_header(self) -> HeaderName {
+        HeaderName::from_static(match self {
+            Records::Accounts => "x-snapshot-accounts-range",
+            Records::Transfers => "x-snapshot-transfers-range",
+        })
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            Records::Accounts => &ACCOUNT_COLUMNS,
+            Records::Transfers => &TRANSFER_COLUMNS,
+        }
+    }
+}
+
+/// The records to export, and where they begin and end.
+struct File {
+    records: Records,
+    range: Option<(u64, u64)>,
+}
+
+/// Download matching accounts and transfers as a ZIP archive.
+pub async fn export_snapshot(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    Valid(params): Valid<SnapshotParams>,
+) -> Result<Response, AppError> {
+    let filter = QueryFilter {
+        user_data_128: params.user_data_128(),
+        user_data_64: params.user_data_64.unwrap_or(0),
+        user_data_32: params.user_data_32.unwrap_or(0),
+        ledger: params.ledger.unwrap_or(0),
+        code: params.code.unwrap_or(0),
+        timestamp_min: params.timestamp_min.unwrap_or(0),
+        timestamp_max: params.timestamp_max.unwrap_or(0),
+        limit: 1,
+        flags: QueryFilterFlags::empty(),
+        reserved: [0; 6],
+    };
+
+    let files = [
+        File {
+            records: Records::Accounts,
+            range: range(&state, Records::Accounts, filter).await?,
+        },
+        File {
+            records: Records::Transfers,
+            range: range(&state, Records::Transfers, filter).await?,
+        },
+    ];
+    let created_at = Utc::now();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/zip"),
+    );
+    let disposition = format!(
+        "attachment; filename=\"tb-snapshot-{}.zip\"",
+        created_at.format("%Y%m%dT%H%M%SZ")
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&disposition).expect("the file name is ASCII"),
+    );
+    for file in &files {
+        if let Some((first, last)) = file.range {
+            let range = format!("{}-{}", first, last);
+            headers.insert(
+                file.records.range_header(),
+                HeaderValue::from_str(&range).expect("digits are a valid header value"),
+            );
+        }
+    }
+
+    let snapshot = Snapshot {
+        format: params.format,
+        filter,
+        files,
+        created_at,
+        manifest: SnapshotManifest {
+            created_at: created_at.to_rfc3339(),
+            format: params.format.extension(),
+            ledger: params.ledger,
+            code: params.code,
+            user_data_128: params.user_data_128,
+            user_data_64: params.user_data_64,
+            user_data_32: params.user_data_32,
+            timestamp_min: params.timestamp_min,
+            timestamp_max: params.timestamp_max,
+            accounts: 0,
+            transfers: 0,
+        },
+    };
+    let (tx, rx) = mpsc::channel(PAGES_AHEAD);
+    tokio::spawn(async move {
+        if let Err(e) = send_snapshot(&state, snapshot, &tx).await {
+            tracing::error!("Streaming snapshot failed: {:?}", e);
+            // Ends the body early, so the client sees a broken transfer.
+            let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+        }
+    });
+    Ok((headers, Body::from_stream(Pages(rx))).into_response())
+}
+
+/// A snapshot being written.
+struct Snapshot {
+    format: SnapshotFormat,
+    filter: QueryFilter,
+    files: [File; 2],
+    created_at: DateTime<Utc>,
+    /// Counts are filled in as the files are written.
+    manifest: SnapshotManifest,
+}
+
+/// The timestamps of the first and last of `records` matching `filter`.
+async fn range(
+    state: &AppState,
+    records: Records,
+    mut filter: QueryFilter,
+) -> Result<Option<(u64, u64)>, AppError> {
+    let client = state.pool.get();
+    let mut timestamps = [0; 2];
+    for (timestamp, flags) in timestamps
+        .iter_mut()
+        .zip([QueryFilterFlags::empty(), QueryFilterFlags::REVERSED])
+    {
+        filter.flags = flags;
+        let found = match records {
+            Records::Accounts => client
+                .query_accounts(filter)
+                .await?
+                .first()
+                .map(|a| a.timestamp),
+            Records::Transfers => client
+                .query_transfers(filter)
+                .await?
+                .first()
+                .map(|t| t.timestamp),
+        };
+        let Some(found) = found else {
+            return Ok(None);
+        };
+        *timestamp = found;
+    }
+    Ok(Some((timestamps[0], timestamps[1])))
+}
+
+/// Write the archive, sending it to `tx` a page at a time until it ends or
+/// the reader goes away.
+async fn send_snapshot(
+    state: &AppState,
+    mut snapshot: Snapshot,
+    tx: &mpsc::Sender<io::Result<Bytes>>,
+) -> Result<(), ClientError> {
+    // CSV has no nesting, so no display fields.
+    let no_currencies = Currencies::default();
+    let currencies = match snapshot.format {
+        SnapshotFormat::Ndjson => &state.config.currencies,
+        SnapshotFormat::Csv => &no_currencies,
+    };
+    let mut zip = ZipWriter::new(snapshot.created_at);
+    for file in &snapshot.files {
+        zip.start(&format!(
+            "{}.{}",
+            file.records.name(),
+            snapshot.format.extension()
+        ));
+        if let SnapshotFormat::Csv = snapshot.format {
+            zip.write(&csv_line(file.records.columns()));
+        }
+        let Some((first, last)) = file.range else {
+            continue;
+        };
+
+        let mut filter = snapshot.filter;
+        filter.timestamp_min = first;
+        filter.timestamp_max = last;
+        filter.limit = PAGE_SIZE;
+        let mut count = 0;
+        loop {
+            let (page, last) =
+                fetch(state, file.records, filter, snapshot.format, currencies).await?;
+            zip.write(&page.bytes);
+            count += page.count;
+            if tx.send(Ok(Bytes::from(zip.take()))).await.is_err() {
+                // The reader went away.
+                return Ok(());
+            }
+            // A short page is the last one.
+            match last.filter(|_| page.count == PAGE_SIZE as u64) {
+                Some(last) => filter.timestamp_min = last + 1,
+                None => break,
+            }
+        }
+        match file.records {
+            Records::Accounts => snapshot.manifest.accounts = count,
+            Records::Transfers => snapshot.manifest.transfers = count,
+        }
+    }
+
+    zip.start("manifest.json");
+    let manifest = serde_json::to_vec_pretty(&snapshot.manifest).expect("API types serialize");
+    zip.write(&manifest);
+    let _ = tx.send(Ok(Bytes::from(zip.finish()))).await;
+    tracing::info!(
+        "Exported a snapshot of {} accounts and {} transfers",
+        snapshot.manifest.accounts,
+        snapshot.manifest.transfers
+    );
+    Ok(())
+}
+
+/// A page of records, encoded.
+struct Page {
+    bytes: Vec<u8>,
+    count: u64,
+}
+
+/// Run `filter`, returning the page it finds and the timestamp of its last
+/// record.
+async fn fetch(
+    state: &AppState,
+    records: Records,
+    filter: QueryFilter,
+    format: SnapshotFormat,
+    currencies: &Currencies,
+) -> Result<(Page, Option<u64>), ClientError> {
+    let client = state.pool.get();
+    Ok(match records {
+        Records::Accounts => {
+            let page = client.query_accounts(filter).await?;
+            let encoded = encode(
+                page.iter()
+                    .map(|a| ApiAccount::from(a).with_display(currencies)),
+                format,
+            );
+            (encoded, page.last().map(|a| a.timestamp))
+        }
+        Records::Transfers => {
+            let page = client.query_transfers(filter).await?;
+            let encoded = encode(
+                page.iter()
+                    .map(|t| ApiTransfer::from(t).with_display(currencies)),
+                format,
+            );
+            (encoded, page.last().map(|t| t.timestamp))
+        }
+    })
+}
+
+/// `records` as NDJSON lines or CSV rows.
+fn encode<T: Serialize>(records: impl Iterator<Item = T>, format: SnapshotFormat) -> Page {
+    let mut bytes = Vec::new();
+    let mut count = 0;
+    match format {
+        SnapshotFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut bytes, &record).expect("API types serialize");
+                bytes.push(b'\n');
+                count += 1;
+            }
+        }
+        SnapshotFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(&mut bytes);
+            for record in records {
+                writer.serialize(record).expect("API types serialize");
+                count += 1;
+            }
+            writer.flush().expect("writing to a Vec succeeds");
+        }
+    }
+    Page { bytes, count }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeCluster};
+    use axum::http::{Request, StatusCode};
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+    use tower::ServiceExt;
+
+    /// Download a snapshot with `query`, returning its status, headers and
+    /// body.
+    async fn snapshot(cluster: &Arc<FakeCluster>, query: &str) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let router = testing::router(testing::config(), cluster);
+        let request = Request::get(format!("/api/v1/export/snapshot{}", query))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, body.to_vec())
+    }
+
+    fn get16(bytes: &[u8], at: usize) -> usize {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap()) as usize
+    }
+
+    fn get64(bytes: &[u8], at: usize) -> usize {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize
+    }
+
+    /// The names and contents of the entries of `archive`, in directory
+    /// order. The writer's own tests check the format; this only reads it.
+    fn unzip(archive: &[u8]) -> Vec<(String, String)> {
+        let zip64_end = get64(archive, archive.len() - 42 + 8);
+        let count = get64(archive, zip64_end + 32);
+        let mut at = get64(archive, zip64_end + 48);
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let name_len = get16(archive, at + 28);
+            let extra_len = get16(archive, at + 30);
+            let name = String::from_utf8(archive[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            let extra = at + 46 + name_len;
+            let compressed = get64(archive, extra + 12);
+            let offset = get64(archive, extra + 20);
+            let data = offset + 30 + get16(archive, offset + 26) + get16(archive, offset + 28);
+            let mut contents = String::new();
+            DeflateDecoder::new(&archive[data..data + compressed])
+                .read_to_string(&mut contents)
+                .unwrap();
+            entries.push((name, contents));
+            at = extra + extra_len;
+        }
+        entries
+    }
+
+    fn manifest(entries: &[(String, String)]) -> serde_json::Value {
+        let (name, contents) = entries.last().unwrap();
+        assert_eq!(name, "manifest.json");
+        serde_json::from_str(contents).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_layout() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[
+                testing::account(1, 700),
+                testing::account(2, 700),
+                testing::account(3, 701),
+            ],
+            &[
+                testing::transfer(10, 1, 2, 5, 700),
+                testing::transfer(11, 2, 1, 3, 700),
+            ],
+        );
+        let (status, headers, body) = snapshot(&cluster, "").await;
+        assert_eq!(status, 200);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/zip");
+        let disposition = headers[header::CONTENT_DISPOSITION].to_str().unwrap();
+        assert!(
+            disposition.starts_with("attachment; filename=\"tb-snapshot-"),
+            "{}",
+            disposition
+        );
+
+        let entries = unzip(&body);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["accounts.ndjson", "transfers.ndjson", "manifest.json"]
+        );
+        let timestamps = |contents: &str| -> Vec<u64> {
+            contents
+                .lines()
+                .map(|line| {
+                    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                    record["timestamp"].as_u64().unwrap()
+                })
+                .collect()
+        };
+        for (records, (_, contents)) in [Records::Accounts, Records::Transfers]
+            .into_iter()
+            .zip(&entries)
+        {
+            let timestamps = timestamps(contents);
+            let range = headers[records.range_header()].to_str().unwrap();
+            assert_eq!(
+                range,
+                format!("{}-{}", timestamps[0], timestamps.last().unwrap())
+            );
+        }
+        assert_eq!(timestamps(&entries[0].1).len(), 3);
+        assert_eq!(timestamps(&entries[1].1).len(), 2);
+
+        let manifest = manifest(&entries);
+        assert_eq!(manifest["format"], "ndjson");
+        assert_eq!(manifest["accounts"], 3);
+        assert_eq!(manifest["transfers"], 2);
+        assert!(manifest.get("ledger").is_none());
+        assert!(DateTime::parse_from_rfc3339(manifest["created_at"].as_str().unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_csv_with_filters() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[
+                testing::account(1, 700),
+                testing::account(2, 700),
+                testing::account(3, 701),
+            ],
+            &[testing::transfer(10, 1, 2, 5, 700)],
+        );
+        let (status, headers, body) = snapshot(&cluster, "?format=csv&ledger=701").await;
+        assert_eq!(status, 200);
+        assert!(headers.contains_key("x-snapshot-accounts-range"));
+        // No transfer matches, so there is no range to give.
+        assert!(!headers.contains_key("x-snapshot-transfers-range"));
+
+        let entries = unzip(&body);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["accounts.csv", "transfers.csv", "manifest.json"]);
+        let accounts: Vec<&str> = entries[0].1.lines().collect();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0], ACCOUNT_COLUMNS.join(","));
+        assert!(
+            accounts[1].starts_with(&format!("{:032x},", 3)),
+            "{}",
+            accounts[1]
+        );
+        assert_eq!(entries[1].1, format!("{}\n", TRANSFER_COLUMNS.join(",")));
+
+        let manifest = manifest(&entries);
+        assert_eq!(manifest["format"], "csv");
+        assert_eq!(manifest["ledger"], 701);
+        assert_eq!(manifest["accounts"], 1);
+        assert_eq!(manifest["transfers"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_pages() {
+        let cluster = FakeCluster::new();
+        let count = PAGE_SIZE as u128 * 2;
+        let accounts: Vec<_> = (1..=count).map(|id| testing::account(id, 700)).collect();
+        cluster.seed(&accounts, &[]);
+        let (_, _, body) = snapshot(&cluster, "").await;
+        let entries = unzip(&body);
+        let ids: Vec<u128> = entries[0]
+            .1
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                u128::from_str_radix(record["id"].as_str().unwrap(), 16).unwrap()
+            })
+            .collect();
+        assert_eq!(ids, (1..=count).collect::<Vec<_>>());
+        assert_eq!(manifest(&entries)["accounts"], count as u64);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_rejects_bad_filters() {
+        let cluster = FakeCluster::new();
+        for query in [
+            "?user_data_128=xyz",
+            "?timestamp_min=10&timestamp_max=5",
+            "?format=xml",
+        ] {
+            let (status, _, _) = snapshot(&cluster, query).await;
+            assert_eq!(status, 422, "{}", query);
+        }
+    }
+}
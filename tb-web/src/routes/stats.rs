@@ -0,0 +1,167 @@
+//! Dashboard statistics handler (see [`crate::stats`]).
+
+use crate::api::{rfc3339, LedgerStats, StatsResponse};
+use crate::auth::Viewer;
+use crate::currency::Currencies;
+use crate::html;
+use crate::state::AppState;
+use crate::stats::LedgerCounts;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Check if request is from HTMX.
+fn is_htmx_request(headers: &HeaderMap) -> bool {
+    headers.contains_key("hx-request")
+}
+
+/// The statistics of `ledger`, whose counts are `counts`.
+pub fn ledger_stats(ledger: u32, counts: &LedgerCounts, currencies: &Currencies) -> LedgerStats {
+    LedgerStats {
+        ledger,
+        accounts: counts.accounts,
+        transfers: counts.transfers,
+        transfers_last_hour: counts.transfers_last_hour,
+        transfers_last_day: counts.transfers_last_day,
+        volume: counts.volume.to_string(),
+        display: None,
+    }
+    .with_display(currencies)
+}
+
+/// Totals by ledger and overall, as of the last refresh.
+///
+/// Before the first refresh is done, or with `--stats-refresh-secs 0`,
+/// every count is zero and `updated_at` is absent.
+pub async fn get_stats(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let snapshot = state.stats.latest();
+    let ledgers: Vec<LedgerStats> = snapshot
+        .iter()
+        .flat_map(|snapshot| &snapshot.ledgers)
+        .map(|(&ledger, counts)| ledger_stats(ledger, counts, &state.config.currencies))
+        .collect();
+    let stats = StatsResponse {
+        updated_at: snapshot.as_ref().map(|snapshot| rfc3339(snapshot.at)),
+        accounts: ledgers.iter().map(|l| l.accounts).sum(),
+        transfers: ledgers.iter().map(|l| l.transfers).sum(),
+        transfers_last_hour: ledgers.iter().map(|l| l.transfers_last_hour).sum(),
+        transfers_last_day: ledgers.iter().map(|l| l.transfers_last_day).sum(),
+        ledgers,
+    };
+
+    if is_htmx_request(&headers) {
+        let updated = snapshot.map(|snapshot| {
+            let since = snapshot.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+            since.as_nanos() as u64
+        });
+        Html(html::render_stats(
+            &stats,
+            updated,
+            !state.config.stats_refresh.is_zero(),
+            &state.config.timestamps,
+        ))
+        .into_response()
+    } else {
+        Json(stats).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::currency::{parse_mapping, Currencies};
+    use crate::testing::{self, account, transfer, FakeCluster};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use std::time::Duration;
+
+    fn cluster() -> std::sync::Arc<FakeCluster> {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[account(1, 1), account(2, 1), account(3, 2), account(4, 2)],
+            &[
+                transfer(10, 1, 2, 150, 1),
+                transfer(11, 2, 1, 25, 1),
+                transfer(12, 3, 4, 7, 2),
+            ],
+        );
+        cluster
+    }
+
+    #[tokio::test]
+    async fn test_stats_before_refresh() {
+        let router = testing::router(testing::config(), &cluster());
+        let (status, body) = testing::get(&router, "/api/v1/stats").await;
+        assert_eq!(status, StatusCode::OK);
+        let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            stats,
+            serde_json::json!({
+                "accounts": 0,
+                "transfers": 0,
+                "transfers_last_hour": 0,
+                "transfers_last_day": 0,
+                "ledgers": [],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let mut config = testing::config();
+        config.stats_refresh = Duration::from_secs(3600);
+        config.currencies = Currencies::new([parse_mapping("1=USD").unwrap()]);
+        let router = testing::router(config, &cluster());
+
+        // The first refresh starts with the server.
+        let stats = loop {
+            let (status, body) = testing::get(&router, "/api/v1/stats").await;
+            assert_eq!(status, StatusCode::OK);
+            let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+            if stats.get("updated_at").is_some() {
+                break stats;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(stats["accounts"], 4);
+        assert_eq!(stats["transfers"], 3);
+        assert_eq!(stats["transfers_last_hour"], 3);
+        assert_eq!(stats["transfers_last_day"], 3);
+        assert_eq!(
+            stats["ledgers"],
+            serde_json::json!([
+                {
+                    "ledger": 1,
+                    "accounts": 2,
+                    "transfers": 2,
+                    "transfers_last_hour": 2,
+                    "transfers_last_day": 2,
+                    "volume": "175",
+                    "display": {"volume": "$1.75"},
+                },
+                {
+                    "ledger": 2,
+                    "accounts": 2,
+                    "transfers": 1,
+                    "transfers_last_hour": 1,
+                    "transfers_last_day": 1,
+                    "volume": "7",
+                },
+            ])
+        );
+
+        let request = Request::get("/api/v1/stats")
+            .header("hx-request", "true")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = testing::send(&router, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("$1.75"), "{}", body);
+    }
+}
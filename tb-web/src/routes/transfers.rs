@@ -1,16 +1,34 @@
 //! Transfer route handlers.
 
-use crate::api::{ApiTransfer, TransfersResponse};
+use crate::api::{
+    codes, ApiPendingTransfer, ApiTransfer, PendingAction, PendingTransfersResponse,
+    TransfersResponse,
+};
+use crate::auth::{Operator, Viewer};
 use crate::error::AppError;
-use crate::html;
+use crate::etag::{self, ETag};
+use crate::html::{self, Pager};
+use crate::paging::Page;
+use crate::recent::{Kind, Session};
+use crate::routes::create_response;
+use crate::routes::export;
 use crate::state::AppState;
-use axum::extract::{Path, Query, State};
-use axum::http::HeaderMap;
+use crate::validate::{self, Events, IdPath, Valid, Validate};
+use axum::body::Bytes;
+use axum::extract::rejection::BytesRejection;
+use axum::extract::State;
+use axum::http::{header, HeaderMap};
 use axum::response::{Html, IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::Write;
 use std::sync::Arc;
-use tb_rs::{QueryFilter, QueryFilterFlags};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tb_rs::{QueryFilter, QueryFilterFlags, Transfer, TransferFlags};
+
+/// Transfers fetched per query.
+const PAGE_SIZE: u32 = 1000;
 
 /// Check if request is from HTMX.
 fn is_htmx_request(headers: &HeaderMap) -> bool {
@@ -29,6 +47,8 @@ pub struct ListTransfersParams {
     pub limit: u32,
     /// Pagination: start after this timestamp.
     pub after_timestamp: Option<u64>,
+    /// Pagination: end before this timestamp.
+    pub before_timestamp: Option<u64>,
     /// Return in reverse chronological order.
     #[serde(default)]
     pub reversed: bool,
@@ -38,14 +58,31 @@ fn default_limit() -> u32 {
     100
 }
 
+impl Validate for ListTransfersParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::ledger("ledger", self.ledger)?;
+        validate::code("code", self.code)?;
+        // A limit over a page is streamed, so it is not capped.
+        validate::limit(&mut self.limit, u32::MAX)?;
+        validate::cursors(self.after_timestamp, self.before_timestamp)
+    }
+}
+
 /// List transfers with optional filters.
 pub async fn list_transfers(
+    _: Viewer,
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Query(params): Query<ListTransfersParams>,
+    Valid(params): Valid<ListTransfersParams>,
 ) -> Result<Response, AppError> {
+    let page = Page::new(
+        params.reversed,
+        params.after_timestamp,
+        params.before_timestamp,
+    );
+    let (timestamp_min, timestamp_max, reversed) = page.range();
     let mut flags = QueryFilterFlags::empty();
-    if params.reversed {
+    if reversed {
         flags |= QueryFilterFlags::REVERSED;
     }
 
@@ -55,59 +92,332 @@ pub async fn list_transfers(
         user_data_32: 0,
         ledger: params.ledger.unwrap_or(0),
         code: params.code.unwrap_or(0),
-        timestamp_min: params.after_timestamp.map(|t| t + 1).unwrap_or(0),
-        timestamp_max: 0,
+        timestamp_min,
+        timestamp_max,
         limit: params.limit,
         flags,
         reserved: [0; 6],
     };
 
-    let transfers = {
-        let client = state.client.lock().await;
-        client.query_transfers(filter).await?
+    if !is_htmx_request(&headers) {
+        if let Some(framing) =
+            export::list_framing(&headers, params.limit, export::Records::Transfers)?
+        {
+            export::check_page(&page)?;
+            return Ok(export::stream(
+                state,
+                export::Paged::Transfers(filter),
+                params.limit as u64,
+                framing,
+            ));
+        }
+    }
+
+    let warm = if page.is_first() && params.code.is_none() {
+        state
+            .warm
+            .transfers(filter.ledger, params.limit, params.reversed)
+    } else {
+        None
+    };
+    let cached = match warm {
+        Some(transfers) => Arc::new(transfers),
+        None => state.cache.transfers(&state.pool, filter).await?,
     };
+    let mut transfers: Vec<&Transfer> = cached.iter().collect();
+    page.order(&mut transfers);
 
     let next_timestamp = transfers.last().map(|t| t.timestamp);
-    let api_transfers: Vec<ApiTransfer> = transfers.iter().map(ApiTransfer::from).collect();
+    let cursors = page.cursors(
+        transfers.first().map(|t| t.timestamp),
+        next_timestamp,
+        transfers.len() == params.limit as usize,
+    );
+    let api_transfers: Vec<ApiTransfer> = transfers
+        .iter()
+        .map(|&t| ApiTransfer::from(t).with_display(&state.config.currencies))
+        .collect();
 
     if is_htmx_request(&headers) {
-        Ok(Html(html::render_transfers_table(&api_transfers, next_timestamp)).into_response())
+        let mut url = format!(
+            "/api/v1/transfers?limit={}&reversed={}",
+            params.limit, params.reversed
+        );
+        if let Some(ledger) = params.ledger {
+            let _ = write!(url, "&ledger={}", ledger);
+        }
+        if let Some(code) = params.code {
+            let _ = write!(url, "&code={}", code);
+        }
+        Ok(Html(html::render_transfers_table(
+            &api_transfers,
+            Some(Pager { url: &url, cursors }),
+            &state.aliases,
+            &state.config.timestamps,
+        ))
+        .into_response())
     } else {
         Ok(Json(TransfersResponse {
             transfers: api_transfers,
             next_timestamp,
+            prev_cursor: cursors.prev,
+            next_cursor: cursors.next,
         })
         .into_response())
     }
 }
 
-/// Get a single transfer by ID.
-pub async fn get_transfer(
+/// Query parameters for listing pending transfers.
+#[derive(Debug, Deserialize)]
+pub struct PendingTransfersParams {
+    /// Filter by ledger.
+    pub ledger: Option<u32>,
+    /// Maximum number of results.
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+impl Validate for PendingTransfersParams {
+    fn validate(&mut self) -> Result<(), String> {
+        validate::ledger("ledger", self.ledger)?;
+        validate::limit(&mut self.limit, PAGE_SIZE)
+    }
+}
+
+/// List the transfers still pending, newest first, with the time each has
+/// left before it expires.
+///
+/// TigerBeetle cannot query by flags or pending state, so this scans
+/// transfers from the newest, remembering the pending IDs that posting and
+/// voiding transfers resolve, for up to `--pending-scan-max` transfers.
+pub async fn list_pending_transfers(
+    _: Viewer,
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Path(id): Path<String>,
+    Valid(params): Valid<PendingTransfersParams>,
 ) -> Result<Response, AppError> {
-    let transfer_id = parse_id(&id)?;
+    let mut filter = QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger: params.ledger.unwrap_or(0),
+        code: 0,
+        timestamp_min: 0,
+        timestamp_max: 0,
+        limit: PAGE_SIZE,
+        flags: QueryFilterFlags::REVERSED,
+        reserved: [0; 6],
+    };
+    // Cluster timestamps are nanoseconds since the epoch, close to wall time.
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+
+    let scan_max = state.config.pending_scan_max;
+    let mut resolved = HashSet::new();
+    let mut pending = Vec::new();
+    let mut scanned = 0u32;
+    let truncated = loop {
+        filter.limit = PAGE_SIZE.min(scan_max - scanned);
+        let page = {
+            let client = state.pool.get();
+            client.query_transfers(filter).await?
+        };
+        scanned += page.len() as u32;
+        for transfer in &page {
+            let flags = transfer.flags;
+            if flags.intersects(
+                TransferFlags::POST_PENDING_TRANSFER | TransferFlags::VOID_PENDING_TRANSFER,
+            ) {
+                resolved.insert(transfer.pending_id);
+            } else if flags.contains(TransferFlags::PENDING) && !resolved.contains(&transfer.id) {
+                let expires_at = (transfer.timeout > 0)
+                    .then(|| transfer.timestamp + transfer.timeout as u64 * 1_000_000_000);
+                if expires_at.is_none_or(|expires_at| expires_at > now) {
+                    pending.push((*transfer, expires_at));
+                }
+            }
+        }
+        if pending.len() >= params.limit as usize {
+            break true;
+        }
 
+        // A short page is the last one; zero would lift the bound.
+        match page.last() {
+            Some(last) if page.len() == filter.limit as usize && last.timestamp > 1 => {
+                if scanned == scan_max {
+                    break true;
+                }
+                filter.timestamp_max = last.timestamp - 1;
+            }
+            _ => break false,
+        }
+    };
+    pending.truncate(params.limit as usize);
+
+    let transfers: Vec<ApiPendingTransfer> = pending
+        .into_iter()
+        .map(|(transfer, expires_at)| ApiPendingTransfer {
+            transfer: ApiTransfer::from(&transfer).with_display(&state.config.currencies),
+            expires_at,
+            expires_in_secs: expires_at.map(|expires_at| (expires_at - now) / 1_000_000_000),
+        })
+        .collect();
+
+    if is_htmx_request(&headers) {
+        Ok(Html(html::render_pending_transfers(
+            &transfers,
+            truncated,
+            !state.config.read_only,
+            &state.aliases,
+            &state.config.timestamps,
+        ))
+        .into_response())
+    } else {
+        Ok(Json(PendingTransfersResponse {
+            transfers,
+            scanned,
+            truncated,
+        })
+        .into_response())
+    }
+}
+
+/// Create transfers from a JSON array, in order.
+///
+/// The array may hold pending transfers and linked chains. Returns 201 when
+/// every transfer was created and 207 with each transfer's result
+/// otherwise. The body is checked as [`Events`] before anything is sent.
+pub async fn create_transfers(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    Events(transfers): Events<Transfer>,
+) -> Result<Response, AppError> {
+    let failures = {
+        let client = state.pool.get();
+        client.create_transfers(&transfers).await?
+    };
+    state.cache.transfers_created();
+
+    let ids: Vec<u128> = transfers.iter().map(|t| t.id).collect();
+    let failures: Vec<(u32, &'static str)> = failures
+        .iter()
+        .map(|f| (f.index, codes::transfer_result(f.result)))
+        .collect();
+    Ok(create_response(&ids, &failures))
+}
+
+/// Post pending transfer `id`, in full or in part.
+///
+/// The optional body is a [`PendingAction`]; with an `amount`, only that
+/// much is posted and the rest of the pending amount is released.
+pub async fn post_pending_transfer(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response, AppError> {
+    let body = body.map_err(|e| validate::body_error(&state, e.status(), e.body_text()))?;
+    resolve_pending(&state, id, &body, TransferFlags::POST_PENDING_TRANSFER).await
+}
+
+/// Void pending transfer `id`.
+///
+/// The optional body is a [`PendingAction`].
+pub async fn void_pending_transfer(
+    _: Operator,
+    State(state): State<Arc<AppState>>,
+    IdPath(id): IdPath,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response, AppError> {
+    let body = body.map_err(|e| validate::body_error(&state, e.status(), e.body_text()))?;
+    resolve_pending(&state, id, &body, TransferFlags::VOID_PENDING_TRANSFER).await
+}
+
+/// Create the transfer that posts or voids transfer `pending_id`, responding
+/// as [`create_transfers`] does.
+async fn resolve_pending(
+    state: &AppState,
+    pending_id: u128,
+    body: &[u8],
+    flags: TransferFlags,
+) -> Result<Response, AppError> {
+    let action: PendingAction = if body.is_empty() {
+        PendingAction::default()
+    } else {
+        serde_json::from_slice(body).map_err(|e| AppError::BadRequest(e.to_string()))?
+    };
+    let transfer = action
+        .transfer(pending_id, flags)
+        .map_err(AppError::Unprocessable)?;
+
+    let failures = {
+        let client = state.pool.get();
+        client.create_transfers(&[transfer]).await?
+    };
+    state.cache.transfers_created();
+
+    let failures: Vec<(u32, &'static str)> = failures
+        .iter()
+        .map(|f| (f.index, codes::transfer_result(f.result)))
+        .collect();
+    Ok(create_response(&[transfer.id], &failures))
+}
+
+/// Get a single transfer by ID, with an ETag (304 if `If-None-Match` has
+/// it). Views in the web UI are recorded for the session (see
+/// [`crate::recent`]).
+pub async fn get_transfer(
+    _: Viewer,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    session: Session,
+    IdPath(transfer_id): IdPath,
+) -> Result<Response, AppError> {
     let transfers = {
-        let client = state.client.lock().await;
+        let client = state.pool.get();
         client.lookup_transfers(&[transfer_id]).await?
     };
 
     let transfer = transfers
         .first()
-        .ok_or_else(|| AppError::NotFound(format!("Transfer {} not found", id)))?;
+        .ok_or_else(|| AppError::NotFound(format!("Transfer {:032x} not found", transfer_id)))?;
 
-    let api_transfer = ApiTransfer::from(transfer);
-
-    if is_htmx_request(&headers) {
-        Ok(Html(html::render_transfer_detail(&api_transfer)).into_response())
+    let htmx = is_htmx_request(&headers);
+    let etag = ETag::transfer(transfer);
+    let etag = if htmx {
+        let aliases = [
+            state.aliases.get(transfer.debit_account_id),
+            state.aliases.get(transfer.credit_account_id),
+        ];
+        etag.fragment(&aliases, &state.config.timestamps)
     } else {
-        Ok(Json(api_transfer).into_response())
-    }
-}
+        Some(etag)
+    };
 
-/// Parse a hex ID string to u128.
-fn parse_id(id: &str) -> Result<u128, AppError> {
-    u128::from_str_radix(id, 16).map_err(|_| AppError::BadRequest(format!("Invalid ID: {}", id)))
+    let mut response = etag::respond(&headers, etag, || {
+        let api_transfer = ApiTransfer::from(transfer).with_display(&state.config.currencies);
+        if htmx {
+            Html(html::render_transfer_detail(
+                &api_transfer,
+                &state.aliases,
+                &state.config.timestamps,
+            ))
+            .into_response()
+        } else {
+            Json(api_transfer).into_response()
+        }
+    });
+    if htmx {
+        let secure = state.config.tls.is_some();
+        if let Some(cookie) = state
+            .recent
+            .record(session, Kind::Transfer, transfer.id, secure)
+            .await
+        {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+    }
+    Ok(response)
 }
@@ -0,0 +1,20 @@
+//! Webhook status handler (see [`crate::webhooks`]).
+
+use crate::api::{ApiWebhook, WebhooksResponse};
+use crate::auth::Admin;
+use crate::state::AppState;
+use axum::extract::State;
+use axum::Json;
+use std::sync::Arc;
+
+/// Each webhook endpoint with its recent deliveries.
+pub async fn list_webhooks(_: Admin, State(state): State<Arc<AppState>>) -> Json<WebhooksResponse> {
+    Json(WebhooksResponse {
+        webhooks: state
+            .webhooks
+            .status()
+            .into_iter()
+            .map(ApiWebhook::from)
+            .collect(),
+    })
+}
@@ -1,16 +1,51 @@
 //! Application state management.
 
+use crate::aliases::Aliases;
+use crate::auth::Oidc;
+use crate::cache::Cache;
 use crate::config::Config;
-use crate::transport::TigerBeetleClient;
+use crate::graphql;
+use crate::live;
+use crate::queries::Queries;
+use crate::recent::Recent;
+use crate::stats::{self, Stats};
+use crate::transport::ClientPool;
+use crate::warm::{self, Warm};
+use crate::webhooks::Webhooks;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::broadcast;
 
 /// Shared application state.
 pub struct AppState {
-    /// TigerBeetle client (mutex for shared access).
-    pub client: Mutex<TigerBeetleClient>,
+    /// TigerBeetle clients, shared round-robin.
+    pub pool: ClientPool,
+    /// Cached hot reads (see [`crate::cache`]).
+    pub cache: Cache,
     /// Application configuration.
     pub config: Config,
+    /// Bearer token validation; `None` lets every request through.
+    pub auth: Option<Oidc>,
+    /// Live update events, as JSON text (see [`live`]).
+    pub live: broadcast::Sender<Arc<str>>,
+    /// Balances changed by new transfers, for long-polls (see [`live`]).
+    pub balances: broadcast::Sender<Arc<[live::BalanceChange]>>,
+    /// Account aliases (see [`crate::aliases`]).
+    pub aliases: Aliases,
+    /// Saved queries (see [`crate::queries`]).
+    pub queries: Queries,
+    /// Recently viewed accounts and transfers (see [`crate::recent`]).
+    pub recent: Recent,
+    /// Outbound webhooks (see [`crate::webhooks`]).
+    pub webhooks: Webhooks,
+    /// Dashboard statistics (see [`stats`]).
+    pub stats: Stats,
+    /// Warm first pages of lists (see [`warm`]).
+    pub warm: Warm,
+    /// GraphQL schema (see [`graphql`]).
+    pub graphql: graphql::Schema,
+    /// When the server started.
+    pub started: Instant,
 }
 
 impl AppState {
@@ -18,16 +53,73 @@ impl AppState {
     pub async fn new(config: Config) -> Result<Arc<Self>, Box<dyn std::error::Error>> {
         tracing::info!("Connecting to TigerBeetle at {}...", config.tb_address);
 
-        let client = TigerBeetleClient::connect(config.cluster_id, config.tb_address).await?;
+        let pool =
+            ClientPool::connect(config.cluster_id, &config.tb_address, config.clients).await?;
 
         tracing::info!(
-            "Connected! Batch size limit: {:?}",
-            client.batch_size_limit()
+            "Connected {} clients! Batch size limit: {:?}",
+            config.clients,
+            pool.batch_size_limit()
         );
 
-        Ok(Arc::new(Self {
-            client: Mutex::new(client),
+        let auth = match &config.oidc {
+            Some(oidc) => Some(Oidc::discover(oidc.clone()).await?),
+            None => None,
+        };
+        Self::start(config, pool, auth)
+    }
+
+    /// Create application state around a connected `pool`, and start its
+    /// background tasks.
+    pub fn start(
+        config: Config,
+        pool: ClientPool,
+        auth: Option<Oidc>,
+    ) -> Result<Arc<Self>, Box<dyn std::error::Error>> {
+        let aliases = match &config.aliases_db {
+            Some(path) => Aliases::open(path)?,
+            None => Aliases::disabled(),
+        };
+
+        let queries = match &config.queries_db {
+            Some(path) => Queries::open(path)?,
+            None => Queries::disabled(),
+        };
+
+        let webhooks = Webhooks::spawn(
+            config.webhooks.clone(),
+            &config.webhook_secret,
+            config.currencies.clone(),
+        );
+
+        let warm = Warm::new(config.warm_accounts, config.warm_transfers);
+        let (live, _) = broadcast::channel(live::CHANNEL_CAPACITY);
+        let (balances, _) = broadcast::channel(live::CHANNEL_CAPACITY);
+        let state = Arc::new(Self {
+            pool,
+            cache: Cache::new(config.accounts_cache_ttl, config.transfers_cache_ttl),
             config,
-        }))
+            auth,
+            live,
+            balances,
+            aliases,
+            queries,
+            recent: Recent::default(),
+            webhooks,
+            stats: Stats::default(),
+            warm,
+            graphql: graphql::schema(),
+            started: Instant::now(),
+        });
+        if !state.config.disable_live || !state.webhooks.is_empty() {
+            live::spawn(state.clone());
+        }
+        if !state.config.stats_refresh.is_zero() {
+            stats::spawn(state.clone(), state.config.stats_refresh);
+        }
+        if !state.config.warm_ledgers.is_empty() {
+            warm::spawn(state.clone(), state.config.warm_ledgers.clone());
+        }
+        Ok(state)
     }
 }
@@ -0,0 +1,290 @@
+//! Dashboard statistics.
+//!
+//! TigerBeetle keeps no totals, so a background task counts them every
+//! `--stats-refresh-secs`: accounts and transfers by ledger, the transfers
+//! of the last hour and day, and the volume moved. Accounts and transfers
+//! are never removed and are listed in timestamp order, so each refresh
+//! pages only through those created since the one before; the first reads
+//! everything.
+//!
+//! The last hour and day are counted by the minute of each transfer's
+//! timestamp, so they are as of the last refresh and to the minute.
+
+use crate::state::AppState;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tb_rs::{ClientError, QueryFilter, QueryFilterFlags};
+
+/// Records fetched per query.
+const PAGE_SIZE: u32 = 1000;
+
+const NANOS_PER_MINUTE: u64 = 60_000_000_000;
+
+/// Minutes in the windows counted.
+const HOUR_MINUTES: u64 = 60;
+const DAY_MINUTES: u64 = 24 * 60;
+
+/// The counts of one ledger.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerCounts {
+    pub accounts: u64,
+    pub transfers: u64,
+    pub transfers_last_hour: u64,
+    pub transfers_last_day: u64,
+    /// Sum of every transfer's amount.
+    pub volume: u128,
+}
+
+/// The counts as of one refresh.
+#[derive(Debug)]
+pub struct Snapshot {
+    /// When the refresh finished.
+    pub at: SystemTime,
+    pub ledgers: BTreeMap<u32, LedgerCounts>,
+}
+
+/// The latest counts, if a refresh finished yet.
+#[derive(Default)]
+pub struct Stats {
+    latest: RwLock<Option<Arc<Snapshot>>>,
+}
+
+impl Stats {
+    pub fn latest(&self) -> Option<Arc<Snapshot>> {
+        self.latest.read().unwrap().clone()
+    }
+
+    fn publish(&self, snapshot: Snapshot) {
+        *self.latest.write().unwrap() = Some(Arc::new(snapshot));
+    }
+}
+
+/// Start counting into `state.stats`, refreshing every `interval`.
+pub fn spawn(state: Arc<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut counter = Counter::default();
+        loop {
+            let started = Instant::now();
+            match counter.catch_up(&state).await {
+                Ok(()) => {
+                    tracing::debug!("Refreshed statistics in {:?}", started.elapsed());
+                    state.stats.publish(counter.snapshot());
+                }
+                Err(e) => tracing::warn!("Statistics refresh failed: {:?}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Counts so far, and where to resume.
+#[derive(Default)]
+struct Counter {
+    /// Timestamp of the last account counted.
+    accounts_after: u64,
+    /// Timestamp of the last transfer counted.
+    transfers_after: u64,
+    /// Every count but the windows.
+    totals: BTreeMap<u32, LedgerCounts>,
+    /// Transfers by ledger for each minute of the last day that has any,
+    /// oldest first.
+    minutes: VecDeque<(u64, BTreeMap<u32, u64>)>,
+}
+
+impl Counter {
+    /// Count the accounts and transfers created since the last call.
+    async fn catch_up(&mut self, state: &AppState) -> Result<(), ClientError> {
+        loop {
+            let accounts = {
+                let client = state.pool.get();
+                client.query_accounts(filter(self.accounts_after)).await?
+            };
+            for account in &accounts {
+                self.totals.entry(account.ledger).or_default().accounts += 1;
+            }
+            if let Some(last) = accounts.last() {
+                self.accounts_after = last.timestamp;
+            }
+            if accounts.len() < PAGE_SIZE as usize {
+                break;
+            }
+        }
+
+        let day_start = now_minute().saturating_sub(DAY_MINUTES - 1);
+        loop {
+            let transfers = {
+                let client = state.pool.get();
+                client.query_transfers(filter(self.transfers_after)).await?
+            };
+            for transfer in &transfers {
+                let totals = self.totals.entry(transfer.ledger).or_default();
+                totals.transfers += 1;
+                totals.volume = totals.volume.saturating_add(transfer.amount);
+
+                let minute = transfer.timestamp / NANOS_PER_MINUTE;
+                if minute < day_start {
+                    continue;
+                }
+                // Transfers come in timestamp order, so a minute is either
+                // the newest one counted or a new one.
+                if self.minutes.back().is_none_or(|&(last, _)| last < minute) {
+                    self.minutes.push_back((minute, BTreeMap::new()));
+                }
+                let (_, by_ledger) = self.minutes.back_mut().expect("a minute was pushed");
+                *by_ledger.entry(transfer.ledger).or_default() += 1;
+            }
+            if let Some(last) = transfers.last() {
+                self.transfers_after = last.timestamp;
+            }
+            if transfers.len() < PAGE_SIZE as usize {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The counts as of now, forgetting minutes older than a day.
+    fn snapshot(&mut self) -> Snapshot {
+        let now = now_minute();
+        let day_start = now.saturating_sub(DAY_MINUTES - 1);
+        let hour_start = now.saturating_sub(HOUR_MINUTES - 1);
+        while self
+            .minutes
+            .front()
+            .is_some_and(|&(minute, _)| minute < day_start)
+        {
+            self.minutes.pop_front();
+        }
+
+        let mut ledgers = self.totals.clone();
+        for (minute, by_ledger) in &self.minutes {
+            for (ledger, &count) in by_ledger {
+                let counts = ledgers.entry(*ledger).or_default();
+                counts.transfers_last_day += count;
+                if *minute >= hour_start {
+                    counts.transfers_last_hour += count;
+                }
+            }
+        }
+        Snapshot {
+            at: SystemTime::now(),
+            ledgers,
+        }
+    }
+}
+
+/// The next page of every record after `timestamp`.
+fn filter(timestamp: u64) -> QueryFilter {
+    QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger: 0,
+        code: 0,
+        timestamp_min: timestamp + 1,
+        timestamp_max: 0,
+        limit: PAGE_SIZE,
+        flags: QueryFilterFlags::empty(),
+        reserved: [0; 6],
+    }
+}
+
+/// The current minute since the epoch. Cluster timestamps are close to
+/// wall time.
+fn now_minute() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() / 60
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, account, transfer, FakeCluster};
+
+    /// Each ledger with its account and transfer counts and volume.
+    fn totals(snapshot: &Snapshot) -> Vec<(u32, u64, u64, u128)> {
+        snapshot
+            .ledgers
+            .iter()
+            .map(|(&ledger, c)| (ledger, c.accounts, c.transfers, c.volume))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_catch_up() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[account(1, 1), account(2, 1), account(3, 2), account(4, 2)],
+            &[transfer(10, 1, 2, 5, 1), transfer(11, 3, 4, 7, 2)],
+        );
+        let state = testing::state(testing::config(), &cluster);
+        let mut counter = Counter::default();
+        counter.catch_up(&state).await.unwrap();
+        let snapshot = counter.snapshot();
+        assert_eq!(totals(&snapshot), [(1, 2, 1, 5), (2, 2, 1, 7)]);
+        // The transfers were just made.
+        let windows: Vec<(u64, u64)> = snapshot
+            .ledgers
+            .values()
+            .map(|c| (c.transfers_last_hour, c.transfers_last_day))
+            .collect();
+        assert_eq!(windows, [(1, 1), (1, 1)]);
+
+        // Only what is new is counted, over more than one page.
+        let accounts: Vec<_> = (100..100 + u128::from(PAGE_SIZE))
+            .map(|id| account(id, 3))
+            .collect();
+        cluster.seed(&accounts, &[transfer(12, 1, 2, 3, 1)]);
+        counter.catch_up(&state).await.unwrap();
+        let snapshot = counter.snapshot();
+        assert_eq!(
+            totals(&snapshot),
+            [(1, 2, 2, 8), (2, 2, 1, 7), (3, u64::from(PAGE_SIZE), 0, 0)]
+        );
+        assert_eq!(snapshot.ledgers[&1].transfers_last_hour, 2);
+    }
+
+    #[test]
+    fn test_snapshot_windows() {
+        // Retry if the minute turns over while counting.
+        let (now, snapshot) = loop {
+            let now = now_minute();
+            let mut counter = Counter::default();
+            counter.totals.insert(3, LedgerCounts::default());
+            for (age, ledger, count) in [
+                (DAY_MINUTES, 1, 100),
+                (DAY_MINUTES - 1, 1, 1),
+                (HOUR_MINUTES, 1, 2),
+                (HOUR_MINUTES - 1, 1, 4),
+                (0, 1, 8),
+                (0, 2, 16),
+            ] {
+                let minute = now - age;
+                if counter
+                    .minutes
+                    .back()
+                    .is_none_or(|&(last, _)| last < minute)
+                {
+                    counter.minutes.push_back((minute, BTreeMap::new()));
+                }
+                counter.minutes.back_mut().unwrap().1.insert(ledger, count);
+            }
+            let snapshot = counter.snapshot();
+            if now_minute() == now {
+                // Minutes older than a day are forgotten.
+                assert_eq!(counter.minutes.len(), 4);
+                break (now, snapshot);
+            }
+        };
+        assert!(now > DAY_MINUTES);
+        let windows: Vec<(u32, u64, u64)> = snapshot
+            .ledgers
+            .iter()
+            .map(|(&ledger, c)| (ledger, c.transfers_last_hour, c.transfers_last_day))
+            .collect();
+        assert_eq!(windows, [(1, 12, 15), (2, 16, 16), (3, 0, 0)]);
+    }
+}
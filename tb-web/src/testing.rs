@@ -0,0 +1,522 @@
+//! Test support: an in-memory cluster and application state around it.
+//!
+//! [`FakeCluster`] keeps accounts and transfers in memory and answers the
+//! client requests tb-web makes, applying the checks and balance changes
+//! the handlers depend on. It is not TigerBeetle: linked chains, balance
+//! limits and expiry are left out.
+
+use crate::config::Config;
+use crate::currency::Currencies;
+use crate::routes;
+use crate::state::AppState;
+use crate::timestamps::Timestamps;
+use crate::transport::ClientPool;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use chrono_tz::Tz;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tb_rs::{
+    Account, AccountBalance, AccountFilter, AccountFilterFlags, AccountFlags, ClientError,
+    CreateAccountResult, CreateAccountsResult, CreateTransferResult, CreateTransfersResult,
+    QueryFilter, QueryFilterFlags, Transfer, TransferFlags,
+};
+use tower::ServiceExt;
+
+/// Batch size limit the fake clients register with.
+pub const BATCH_SIZE_LIMIT: u32 = 1024 * 1024 - 256;
+
+/// An in-memory cluster.
+#[derive(Default)]
+pub struct FakeCluster {
+    ledger: Mutex<Ledger>,
+    /// Outcomes of the next requests; `None` lets one through.
+    errors: Mutex<VecDeque<Option<ClientError>>>,
+}
+
+#[derive(Default)]
+struct Ledger {
+    /// Accounts, oldest first.
+    accounts: Vec<Account>,
+    /// Transfers, oldest first.
+    transfers: Vec<Transfer>,
+    /// Balances of accounts with history, after each of their transfers.
+    balances: Vec<(u128, AccountBalance)>,
+    timestamp: u64,
+}
+
+impl Ledger {
+    /// The next timestamp: now, in nanoseconds, or just after the last.
+    fn tick(&mut self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.timestamp = (self.timestamp + 1).max(now.as_nanos() as u64);
+        self.timestamp
+    }
+
+    fn account(&mut self, id: u128) -> Option<&mut Account> {
+        self.accounts.iter_mut().find(|a| a.id == id)
+    }
+
+    fn create_transfer(&mut self, transfer: &Transfer) -> CreateTransferResult {
+        use CreateTransferResult as R;
+        let mut transfer = *transfer;
+        if transfer.id == 0 {
+            return R::IdMustNotBeZero;
+        }
+        if self.transfers.iter().any(|t| t.id == transfer.id) {
+            return R::Exists;
+        }
+        let resolves = TransferFlags::POST_PENDING_TRANSFER | TransferFlags::VOID_PENDING_TRANSFER;
+        let pending = if transfer.flags.intersects(resolves) {
+            let Some(pending) = self
+                .transfers
+                .iter()
+                .find(|t| t.id == transfer.pending_id)
+                .copied()
+            else {
+                return R::PendingTransferNotFound;
+            };
+            if !pending.flags.contains(TransferFlags::PENDING) {
+                return R::PendingTransferNotPending;
+            }
+            if let Some(resolved) = self.transfers.iter().find(|t| t.pending_id == pending.id) {
+                return if resolved
+                    .flags
+                    .contains(TransferFlags::POST_PENDING_TRANSFER)
+                {
+                    R::PendingTransferAlreadyPosted
+                } else {
+                    R::PendingTransferAlreadyVoided
+                };
+            }
+            for (field, from) in [
+                (&mut transfer.debit_account_id, pending.debit_account_id),
+                (&mut transfer.credit_account_id, pending.credit_account_id),
+            ] {
+                if *field == 0 {
+                    *field = from;
+                }
+            }
+            if transfer.ledger == 0 {
+                transfer.ledger = pending.ledger;
+            }
+            if transfer.code == 0 {
+                transfer.code = pending.code;
+            }
+            if transfer.amount == u128::MAX
+                || transfer
+                    .flags
+                    .contains(TransferFlags::VOID_PENDING_TRANSFER)
+            {
+                transfer.amount = pending.amount;
+            }
+            if transfer.amount > pending.amount {
+                return R::ExceedsPendingTransferAmount;
+            }
+            Some(pending)
+        } else {
+            None
+        };
+        if transfer.ledger == 0 {
+            return R::LedgerMustNotBeZero;
+        }
+        if transfer.code == 0 {
+            return R::CodeMustNotBeZero;
+        }
+        if transfer.debit_account_id == transfer.credit_account_id {
+            return R::AccountsMustBeDifferent;
+        }
+        let Some(debit) = self.account(transfer.debit_account_id).copied() else {
+            return R::DebitAccountNotFound;
+        };
+        let Some(credit) = self.account(transfer.credit_account_id).copied() else {
+            return R::CreditAccountNotFound;
+        };
+        if debit.ledger != credit.ledger {
+            return R::AccountsMustHaveTheSameLedger;
+        }
+        if transfer.ledger != debit.ledger {
+            return R::TransferMustHaveTheSameLedgerAsAccounts;
+        }
+
+        transfer.timestamp = self.tick();
+        let posted = !transfer
+            .flags
+            .intersects(TransferFlags::PENDING | TransferFlags::VOID_PENDING_TRANSFER);
+        for (id, debits) in [(debit.id, true), (credit.id, false)] {
+            let account = self.account(id).expect("looked up above");
+            let (balance_pending, balance_posted) = if debits {
+                (&mut account.debits_pending, &mut account.debits_posted)
+            } else {
+                (&mut account.credits_pending, &mut account.credits_posted)
+            };
+            if let Some(pending) = &pending {
+                *balance_pending -= pending.amount;
+            }
+            if transfer.flags.contains(TransferFlags::PENDING) {
+                *balance_pending += transfer.amount;
+            } else if posted {
+                *balance_posted += transfer.amount;
+            }
+            let account = *account;
+            if account.flags.contains(AccountFlags::HISTORY) {
+                self.balances.push((
+                    id,
+                    AccountBalance {
+                        debits_pending: account.debits_pending,
+                        debits_posted: account.debits_posted,
+                        credits_pending: account.credits_pending,
+                        credits_posted: account.credits_posted,
+                        timestamp: transfer.timestamp,
+                        ..AccountBalance::default()
+                    },
+                ));
+            }
+        }
+        self.transfers.push(transfer);
+        R::Ok
+    }
+}
+
+impl FakeCluster {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Create `accounts` and `transfers`, which must all succeed.
+    pub fn seed(&self, accounts: &[Account], transfers: &[Transfer]) {
+        assert!(self.create_accounts(accounts).unwrap().is_empty());
+        assert!(self.create_transfers(transfers).unwrap().is_empty());
+    }
+
+    /// Fail the next request with `error`.
+    pub fn fail_next(&self, error: ClientError) {
+        self.fail_after(0, error);
+    }
+
+    /// Fail the request after the next `ok` with `error`.
+    pub fn fail_after(&self, ok: usize, error: ClientError) {
+        let mut errors = self.errors.lock().unwrap();
+        errors.extend((0..ok).map(|_| None));
+        errors.push_back(Some(error));
+    }
+
+    fn error(&self) -> Result<(), ClientError> {
+        match self.errors.lock().unwrap().pop_front().flatten() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    pub fn create_accounts(
+        &self,
+        accounts: &[Account],
+    ) -> Result<Vec<CreateAccountsResult>, ClientError> {
+        self.error()?;
+        let mut ledger = self.ledger.lock().unwrap();
+        let mut results = Vec::new();
+        for (index, account) in accounts.iter().enumerate() {
+            let result = if account.id == 0 {
+                CreateAccountResult::IdMustNotBeZero
+            } else if ledger.accounts.iter().any(|a| a.id == account.id) {
+                CreateAccountResult::Exists
+            } else if account.ledger == 0 {
+                CreateAccountResult::LedgerMustNotBeZero
+            } else if account.code == 0 {
+                CreateAccountResult::CodeMustNotBeZero
+            } else {
+                let timestamp = ledger.tick();
+                ledger.accounts.push(Account {
+                    timestamp,
+                    ..*account
+                });
+                continue;
+            };
+            results.push(CreateAccountsResult {
+                index: index as u32,
+                result,
+            });
+        }
+        Ok(results)
+    }
+
+    pub fn create_transfers(
+        &self,
+        transfers: &[Transfer],
+    ) -> Result<Vec<CreateTransfersResult>, ClientError> {
+        self.error()?;
+        let mut ledger = self.ledger.lock().unwrap();
+        Ok(transfers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, transfer)| match ledger.create_transfer(transfer) {
+                CreateTransferResult::Ok => None,
+                result => Some(CreateTransfersResult {
+                    index: index as u32,
+                    result,
+                }),
+            })
+            .collect())
+    }
+
+    pub fn lookup_accounts(&self, ids: &[u128]) -> Result<Vec<Account>, ClientError> {
+        self.error()?;
+        let ledger = self.ledger.lock().unwrap();
+        Ok(ids
+            .iter()
+            .filter_map(|id| ledger.accounts.iter().find(|a| a.id == *id).copied())
+            .collect())
+    }
+
+    pub fn lookup_transfers(&self, ids: &[u128]) -> Result<Vec<Transfer>, ClientError> {
+        self.error()?;
+        let ledger = self.ledger.lock().unwrap();
+        Ok(ids
+            .iter()
+            .filter_map(|id| ledger.transfers.iter().find(|t| t.id == *id).copied())
+            .collect())
+    }
+
+    pub fn query_accounts(&self, filter: QueryFilter) -> Result<Vec<Account>, ClientError> {
+        self.error()?;
+        let ledger = self.ledger.lock().unwrap();
+        let found = ledger.accounts.iter().filter(|a| {
+            query_matches(
+                &filter,
+                (a.user_data_128, a.user_data_64, a.user_data_32),
+                a.ledger,
+                a.code,
+                a.timestamp,
+            )
+        });
+        Ok(take(
+            found,
+            filter.limit,
+            filter.flags.contains(QueryFilterFlags::REVERSED),
+        ))
+    }
+
+    pub fn query_transfers(&self, filter: QueryFilter) -> Result<Vec<Transfer>, ClientError> {
+        self.error()?;
+        let ledger = self.ledger.lock().unwrap();
+        let found = ledger.transfers.iter().filter(|t| {
+            query_matches(
+                &filter,
+                (t.user_data_128, t.user_data_64, t.user_data_32),
+                t.ledger,
+                t.code,
+                t.timestamp,
+            )
+        });
+        Ok(take(
+            found,
+            filter.limit,
+            filter.flags.contains(QueryFilterFlags::REVERSED),
+        ))
+    }
+
+    pub fn get_account_transfers(
+        &self,
+        filter: AccountFilter,
+    ) -> Result<Vec<Transfer>, ClientError> {
+        self.error()?;
+        let ledger = self.ledger.lock().unwrap();
+        let found = ledger.transfers.iter().filter(|t| {
+            let side = (filter.flags.contains(AccountFilterFlags::DEBITS)
+                && t.debit_account_id == filter.account_id)
+                || (filter.flags.contains(AccountFilterFlags::CREDITS)
+                    && t.credit_account_id == filter.account_id);
+            side && (filter.code == 0 || t.code == filter.code)
+                && user_data_matches(
+                    (
+                        filter.user_data_128,
+                        filter.user_data_64,
+                        filter.user_data_32,
+                    ),
+                    (t.user_data_128, t.user_data_64, t.user_data_32),
+                )
+                && in_range(filter.timestamp_min, filter.timestamp_max, t.timestamp)
+        });
+        Ok(take(
+            found,
+            filter.limit,
+            filter.flags.contains(AccountFilterFlags::REVERSED),
+        ))
+    }
+
+    pub fn get_account_balances(
+        &self,
+        filter: AccountFilter,
+    ) -> Result<Vec<AccountBalance>, ClientError> {
+        self.error()?;
+        let ledger = self.ledger.lock().unwrap();
+        let found = ledger
+            .balances
+            .iter()
+            .filter(|(id, balance)| {
+                *id == filter.account_id
+                    && in_range(
+                        filter.timestamp_min,
+                        filter.timestamp_max,
+                        balance.timestamp,
+                    )
+            })
+            .map(|(_, balance)| balance);
+        Ok(take(
+            found,
+            filter.limit,
+            filter.flags.contains(AccountFilterFlags::REVERSED),
+        ))
+    }
+}
+
+fn query_matches(
+    filter: &QueryFilter,
+    user_data: (u128, u64, u32),
+    ledger: u32,
+    code: u16,
+    timestamp: u64,
+) -> bool {
+    user_data_matches(
+        (
+            filter.user_data_128,
+            filter.user_data_64,
+            filter.user_data_32,
+        ),
+        user_data,
+    ) && (filter.ledger == 0 || ledger == filter.ledger)
+        && (filter.code == 0 || code == filter.code)
+        && in_range(filter.timestamp_min, filter.timestamp_max, timestamp)
+}
+
+fn user_data_matches(filter: (u128, u64, u32), record: (u128, u64, u32)) -> bool {
+    (filter.0 == 0 || record.0 == filter.0)
+        && (filter.1 == 0 || record.1 == filter.1)
+        && (filter.2 == 0 || record.2 == filter.2)
+}
+
+fn in_range(min: u64, max: u64, timestamp: u64) -> bool {
+    timestamp >= min && (max == 0 || timestamp <= max)
+}
+
+/// The first `limit` of `found`, from the newest if `reversed`.
+fn take<'a, T: Copy + 'a>(
+    found: impl DoubleEndedIterator<Item = &'a T>,
+    limit: u32,
+    reversed: bool,
+) -> Vec<T> {
+    if reversed {
+        found.rev().take(limit as usize).copied().collect()
+    } else {
+        found.take(limit as usize).copied().collect()
+    }
+}
+
+/// An account on `ledger`, with history.
+pub fn account(id: u128, ledger: u32) -> Account {
+    Account {
+        id,
+        ledger,
+        code: 10,
+        flags: AccountFlags::HISTORY,
+        ..Account::default()
+    }
+}
+
+/// A transfer of `amount` from `debit` to `credit` on `ledger`.
+pub fn transfer(id: u128, debit: u128, credit: u128, amount: u128, ledger: u32) -> Transfer {
+    Transfer {
+        id,
+        debit_account_id: debit,
+        credit_account_id: credit,
+        amount,
+        ledger,
+        code: 1,
+        ..Transfer::default()
+    }
+}
+
+/// The configuration of a server started with default flags, without
+/// background refreshes.
+pub fn config() -> Config {
+    Config {
+        address: "127.0.0.1:8080".parse().unwrap(),
+        tls: None,
+        tb_address: "127.0.0.1:3000".to_string(),
+        replica_count: 1,
+        cluster_id: 0,
+        clients: 2,
+        ready_max_op_age: Duration::from_secs(30),
+        request_timeout: Duration::from_secs(30),
+        accounts_cache_ttl: Duration::ZERO,
+        transfers_cache_ttl: Duration::ZERO,
+        stats_refresh: Duration::ZERO,
+        warm_ledgers: Vec::new(),
+        warm_transfers: 1000,
+        warm_accounts: 1000,
+        oidc: None,
+        currencies: Currencies::new([]),
+        timestamps: Timestamps::new(Tz::UTC, false),
+        webhooks: Vec::new(),
+        webhook_secret: String::new(),
+        graph_transfers_max: 100_000,
+        pending_scan_max: 100_000,
+        chart_points_max: 2000,
+        import_rows_max: 1_000_000,
+        import_report_rows_max: 100_000,
+        import_max_bytes: 1 << 30,
+        max_body_bytes: 4 << 20,
+        max_batch_events: None,
+        aliases_db: Some(":memory:".into()),
+        queries_db: Some(":memory:".into()),
+        read_only: false,
+        disable_live: true,
+        disable_export: false,
+        enable_seeding: true,
+    }
+}
+
+/// Application state with `config`, on `cluster`.
+pub fn state(config: Config, cluster: &Arc<FakeCluster>) -> Arc<AppState> {
+    let pool = ClientPool::fake(cluster, config.clients);
+    AppState::start(config, pool, None).unwrap()
+}
+
+/// The router of a server with `config`, on `cluster`.
+pub fn router(config: Config, cluster: &Arc<FakeCluster>) -> Router {
+    routes::router(state(config, cluster))
+}
+
+/// Send `request` to `router`, returning the status and body.
+pub async fn send(router: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+/// `GET uri` from `router`.
+pub async fn get(router: &Router, uri: &str) -> (StatusCode, String) {
+    send(router, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+/// `method uri` with JSON `body` to `router`.
+pub async fn send_json(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: serde_json::Value,
+) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(router, request).await
+}
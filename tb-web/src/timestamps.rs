@@ -0,0 +1,234 @@
+//! Timestamp display.
+//!
+//! TigerBeetle timestamps are nanoseconds since the Unix epoch. The HTML
+//! fragments show them in the `--display-timezone` (UTC by default), or
+//! with `--relative-timestamps` as the time since, e.g. `3m ago`, with the
+//! full time on hover. API responses keep the raw nanoseconds.
+
+use chrono::{DateTime, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How timestamps are shown.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamps {
+    zone: Tz,
+    relative: bool,
+}
+
+/// Parse an IANA time zone name, e.g. `Europe/Amsterdam` or `UTC`.
+pub fn parse_zone(s: &str) -> Result<Tz, String> {
+    s.parse().map_err(|_| {
+        format!(
+            "Unknown time zone '{}' (an IANA name, e.g. Europe/Amsterdam)",
+            s
+        )
+    })
+}
+
+impl Timestamps {
+    pub fn new(zone: Tz, relative: bool) -> Self {
+        Self { zone, relative }
+    }
+
+    /// Whether timestamps are shown as the time since.
+    pub fn is_relative(&self) -> bool {
+        self.relative
+    }
+
+    /// `timestamp` as e.g. `2024-02-29 13:45:00 CET`, or `-` for zero.
+    pub fn absolute(&self, timestamp: u64) -> String {
+        if timestamp == 0 {
+            return "-".to_string();
+        }
+        let secs = (timestamp / 1_000_000_000) as i64;
+        let nanos = (timestamp % 1_000_000_000) as u32;
+        match DateTime::from_timestamp(secs, nanos) {
+            Some(time) => time
+                .with_timezone(&self.zone)
+                .format("%Y-%m-%d %H:%M:%S %Z")
+                .to_string(),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Parse a time as a browser's `datetime-local` input sends it, e.g.
+    /// `2024-02-29T13:45`, in the display time zone; nanoseconds.
+    pub fn parse_local(&self, s: &str) -> Result<u64, String> {
+        let local = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+            .map_err(|_| format!("invalid time {:?}, e.g. 2024-02-29T13:45", s))?;
+        // A time skipped by a DST change doesn't exist; a repeated one
+        // means its first occurrence.
+        let time = self
+            .zone
+            .from_local_datetime(&local)
+            .earliest()
+            .ok_or_else(|| format!("{} does not exist in {}", s, self.zone))?;
+        time.timestamp_nanos_opt()
+            .and_then(|nanos| u64::try_from(nanos).ok())
+            .ok_or_else(|| format!("time {:?} out of range", s))
+    }
+
+    /// The time since `timestamp`, e.g. `3m ago`, or `-` for zero.
+    pub fn relative(&self, timestamp: u64) -> String {
+        if timestamp == 0 {
+            return "-".to_string();
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        // Cluster time may run slightly ahead of ours.
+        let secs = now.saturating_sub(timestamp) / 1_000_000_000;
+        match secs {
+            0..5 => "just now".to_string(),
+            5..60 => format!("{}s ago", secs),
+            60..3600 => format!("{}m ago", secs / 60),
+            3600..86400 => format!("{}h ago", secs / 3600),
+            _ => format!("{}d ago", secs / 86400),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NANOS: u64 = 1_000_000_000;
+
+    fn zone(name: &str) -> Timestamps {
+        Timestamps::new(parse_zone(name).unwrap(), false)
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        assert_eq!(parse_zone("UTC"), Ok(Tz::UTC));
+        assert_eq!(parse_zone("Europe/Amsterdam"), Ok(Tz::Europe__Amsterdam));
+        assert_eq!(
+            parse_zone("Mars/Olympus").unwrap_err(),
+            "Unknown time zone 'Mars/Olympus' (an IANA name, e.g. Europe/Amsterdam)"
+        );
+    }
+
+    #[test]
+    fn test_absolute() {
+        let utc = zone("UTC");
+        assert_eq!(
+            utc.absolute(1_709_214_300 * NANOS),
+            "2024-02-29 13:45:00 UTC"
+        );
+        assert_eq!(
+            utc.absolute(1_709_214_300 * NANOS + 999),
+            "2024-02-29 13:45:00 UTC"
+        );
+        assert_eq!(utc.absolute(0), "-");
+        // A fixed offset all year.
+        let kolkata = zone("Asia/Kolkata");
+        assert_eq!(
+            kolkata.absolute(1_709_214_300 * NANOS),
+            "2024-02-29 19:15:00 IST"
+        );
+        assert_eq!(
+            kolkata.absolute(1_719_835_200 * NANOS),
+            "2024-07-01 17:30:00 IST"
+        );
+
+        // Summer and winter time.
+        let amsterdam = zone("Europe/Amsterdam");
+        assert_eq!(
+            amsterdam.absolute(1_709_214_300 * NANOS),
+            "2024-02-29 14:45:00 CET"
+        );
+        assert_eq!(
+            amsterdam.absolute(1_719_835_200 * NANOS),
+            "2024-07-01 14:00:00 CEST"
+        );
+        // The clocks skip from 02:00 to 03:00...
+        assert_eq!(
+            amsterdam.absolute(1_711_846_800 * NANOS),
+            "2024-03-31 03:00:00 CEST"
+        );
+        // ...and show 02:30 twice when they go back.
+        assert_eq!(
+            amsterdam.absolute(1_729_989_000 * NANOS),
+            "2024-10-27 02:30:00 CEST"
+        );
+        assert_eq!(
+            amsterdam.absolute(1_729_992_600 * NANOS),
+            "2024-10-27 02:30:00 CET"
+        );
+    }
+
+    #[test]
+    fn test_parse_local() {
+        let utc = zone("UTC");
+        assert_eq!(
+            utc.parse_local("2024-02-29T13:45"),
+            Ok(1_709_214_300 * NANOS)
+        );
+        assert_eq!(
+            utc.parse_local("2024-02-29T13:45:30"),
+            Ok(1_709_214_330 * NANOS)
+        );
+        assert_eq!(
+            zone("Asia/Kolkata").parse_local("2024-02-29T13:45"),
+            Ok(1_709_194_500 * NANOS)
+        );
+
+        let amsterdam = zone("Europe/Amsterdam");
+        assert_eq!(
+            amsterdam.parse_local("2024-02-29T14:45"),
+            Ok(1_709_214_300 * NANOS)
+        );
+        assert_eq!(
+            amsterdam.parse_local("2024-07-01T14:00"),
+            Ok(1_719_835_200 * NANOS)
+        );
+        // Skipped: no such time.
+        assert_eq!(
+            amsterdam.parse_local("2024-03-31T02:30"),
+            Err("2024-03-31T02:30 does not exist in Europe/Amsterdam".to_string())
+        );
+        // Repeated: the first, still in summer time.
+        assert_eq!(
+            amsterdam.parse_local("2024-10-27T02:30"),
+            Ok(1_729_989_000 * NANOS)
+        );
+
+        for s in [
+            "2024-02-29",
+            "2024-02-30T00:00",
+            "13:45",
+            "2024-02-29 13:45",
+        ] {
+            assert_eq!(
+                utc.parse_local(s),
+                Err(format!("invalid time {:?}, e.g. 2024-02-29T13:45", s))
+            );
+        }
+        assert_eq!(
+            utc.parse_local("1969-12-31T23:59"),
+            Err(r#"time "1969-12-31T23:59" out of range"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_relative() {
+        let relative = Timestamps::new(Tz::UTC, true);
+        assert!(relative.is_relative());
+        assert!(!zone("UTC").is_relative());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let ago = |secs: u64| relative.relative(now - secs * NANOS);
+        assert_eq!(ago(0), "just now");
+        assert_eq!(relative.relative(now + 60 * NANOS), "just now");
+        assert_eq!(ago(30), "30s ago");
+        assert_eq!(ago(150), "2m ago");
+        assert_eq!(ago(2 * 3600 + 59), "2h ago");
+        assert_eq!(ago(3 * 86400), "3d ago");
+        assert_eq!(relative.relative(0), "-");
+    }
+}
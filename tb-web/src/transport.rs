@@ -3,15 +3,72 @@
 //! tb-rs uses tokio_uring (io_uring based), while axum uses regular tokio.
 //! This module provides a wrapper that runs tb-rs in a dedicated thread
 //! and communicates via channels.
+//!
+//! A client handles one request at a time, so [`ClientPool`] runs several,
+//! each with its own thread and session, and hands requests to them in
+//! turn. A slow query then holds up only the requests sent to its client.
+//!
+//! Each client thread supervises its session: if the session panics or is
+//! evicted, the thread connects again after an exponential backoff. Requests
+//! arriving while it waits fail straight away with a connection error, and
+//! those arriving while it connects wait for the new session.
+//!
+//! Reads (lookups and queries) change nothing, so a read that fails with a
+//! transient error, such as a timeout or the session reconnecting, is sent
+//! again after a short jittered delay, within a small budget. A brief
+//! hiccup in the cluster then delays a page instead of failing it.
+//!
+//! A request whose caller has gone away (the browser disconnected, or the
+//! request timed out) while it waited in the channel is dropped rather than
+//! sent, so abandoned work does not hold up the requests behind it. One
+//! already sent to the cluster runs to completion, as the session cannot
+//! skip a reply.
+//!
+//! Each request carries the span it was sent from through the channel, and
+//! the client thread runs its operation in a child span, so traces follow a
+//! page into the TigerBeetle calls it made (see [`crate::otel`]).
 
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
 use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use tokio::sync::{mpsc, oneshot};
+use crate::access_log;
+use crate::otel;
 use tb_rs::{
     Account, AccountBalance, AccountFilter, ClientError, CreateAccountsResult,
     CreateTransfersResult, QueryFilter, Transfer,
 };
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{Instrument, Span};
+
+/// First delay before reconnecting; doubled after each failed attempt.
+const BACKOFF_MIN: Duration = Duration::from_millis(100);
+
+/// Longest delay before reconnecting.
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// How often to fail queued requests while waiting to reconnect.
+const BACKOFF_POLL: Duration = Duration::from_millis(10);
+
+/// Most times a read is sent before its error is returned.
+const READ_ATTEMPTS: u32 = 3;
+
+/// Delay before sending a read again; doubled after each retry.
+const READ_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Longest a read may spend retrying, from when it was first sent.
+const READ_RETRY_BUDGET: Duration = Duration::from_secs(2);
+
+/// The connection error for requests that arrive while the session is
+/// waiting to reconnect.
+const RECONNECTING: &str = "reconnecting to TigerBeetle";
 
 /// Request types for the TigerBeetle client thread.
 enum Request {
@@ -53,71 +110,304 @@ enum Request {
     Shutdown,
 }
 
+impl Request {
+    /// The operation's name, for its span.
+    fn operation(&self) -> &'static str {
+        match self {
+            Request::CreateAccounts { .. } => "create_accounts",
+            Request::CreateTransfers { .. } => "create_transfers",
+            Request::LookupAccounts { .. } => "lookup_accounts",
+            Request::LookupTransfers { .. } => "lookup_transfers",
+            Request::GetAccountTransfers { .. } => "get_account_transfers",
+            Request::GetAccountBalances { .. } => "get_account_balances",
+            Request::QueryAccounts { .. } => "query_accounts",
+            Request::QueryTransfers { .. } => "query_transfers",
+            Request::BatchSizeLimit { .. } => "batch_size_limit",
+            Request::Shutdown => "shutdown",
+        }
+    }
+
+    /// Whether nobody is waiting for the reply any more.
+    fn is_abandoned(&self) -> bool {
+        match self {
+            Request::CreateAccounts { reply, .. } => reply.is_closed(),
+            Request::CreateTransfers { reply, .. } => reply.is_closed(),
+            Request::LookupAccounts { reply, .. } => reply.is_closed(),
+            Request::LookupTransfers { reply, .. } => reply.is_closed(),
+            Request::GetAccountTransfers { reply, .. } => reply.is_closed(),
+            Request::GetAccountBalances { reply, .. } => reply.is_closed(),
+            Request::QueryAccounts { reply, .. } => reply.is_closed(),
+            Request::QueryTransfers { reply, .. } => reply.is_closed(),
+            Request::BatchSizeLimit { reply } => reply.is_closed(),
+            Request::Shutdown => false,
+        }
+    }
+
+    /// Reply to the request with `error`.
+    fn fail(self, error: ClientError) {
+        match self {
+            Request::CreateAccounts { reply, .. } => drop(reply.send(Err(error))),
+            Request::CreateTransfers { reply, .. } => drop(reply.send(Err(error))),
+            Request::LookupAccounts { reply, .. } => drop(reply.send(Err(error))),
+            Request::LookupTransfers { reply, .. } => drop(reply.send(Err(error))),
+            Request::GetAccountTransfers { reply, .. } => drop(reply.send(Err(error))),
+            Request::GetAccountBalances { reply, .. } => drop(reply.send(Err(error))),
+            Request::QueryAccounts { reply, .. } => drop(reply.send(Err(error))),
+            Request::QueryTransfers { reply, .. } => drop(reply.send(Err(error))),
+            Request::BatchSizeLimit { reply } => drop(reply.send(None)),
+            Request::Shutdown => {}
+        }
+    }
+}
+
+/// A request in the channel, with the span it was sent from and when.
+struct Queued {
+    request: Request,
+    span: Span,
+    sent: Instant,
+}
+
+impl Queued {
+    fn new(request: Request) -> Self {
+        Self {
+            request,
+            span: Span::current(),
+            sent: Instant::now(),
+        }
+    }
+}
+
 /// TigerBeetle client wrapper that bridges tokio and tokio_uring runtimes.
 ///
 /// This spawns a dedicated thread running tokio_uring for the tb-rs client
 /// and provides an async interface compatible with regular tokio.
 pub struct TigerBeetleClient {
-    tx: mpsc::Sender<Request>,
-    batch_size_limit: Option<u32>,
+    tx: mpsc::Sender<Queued>,
+    cluster_id: u128,
+    stats: Arc<ClientStats>,
+}
+
+/// Session state of a client thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SessionState {
+    /// Connecting, or connected and serving requests.
+    Ready,
+    /// The session ended; waiting to connect again.
+    Reconnecting,
+    /// The thread has exited.
+    Stopped,
+}
+
+impl SessionState {
+    pub fn name(self) -> &'static str {
+        match self {
+            SessionState::Ready => "ready",
+            SessionState::Reconnecting => "reconnecting",
+            SessionState::Stopped => "stopped",
+        }
+    }
+}
+
+/// Request counters and session state for a client.
+struct ClientStats {
+    state: AtomicU8,
+    /// Sessions started again after the first.
+    restarts: AtomicU64,
+    /// Requests sent.
+    requests: AtomicU64,
+    /// Requests that failed with a client error.
+    errors: AtomicU64,
+    /// Reads sent again after a transient error.
+    retries: AtomicU64,
+    /// Requests dropped unsent because their caller went away.
+    abandoned: AtomicU64,
+    /// Requests waiting for a reply.
+    in_flight: AtomicU32,
+    /// When a request last succeeded, in milliseconds since the epoch
+    /// (zero if none has).
+    last_success_ms: AtomicU64,
+    /// The current session, as of its last request.
+    session: Mutex<Option<Session>>,
+    /// The batch size limit the latest session registered with (zero
+    /// before the first).
+    batch_size_limit: AtomicU32,
+}
+
+/// What a client thread knows about its session with the cluster.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub client_id: u128,
+    pub view: u32,
+    /// The address each replica is connected at, if connected.
+    pub replicas: Vec<Option<SocketAddr>>,
+}
+
+impl ClientStats {
+    fn new() -> Self {
+        Self {
+            state: AtomicU8::new(SessionState::Ready as u8),
+            restarts: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            abandoned: AtomicU64::new(0),
+            in_flight: AtomicU32::new(0),
+            last_success_ms: AtomicU64::new(0),
+            session: Mutex::new(None),
+            batch_size_limit: AtomicU32::new(0),
+        }
+    }
+
+    fn state(&self) -> SessionState {
+        match self.state.load(Ordering::Relaxed) {
+            0 => SessionState::Ready,
+            1 => SessionState::Reconnecting,
+            _ => SessionState::Stopped,
+        }
+    }
+
+    fn set_state(&self, state: SessionState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+    }
+
+    fn record_success(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.last_success_ms
+            .store(now.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn last_success(&self) -> Option<SystemTime> {
+        match self.last_success_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+        }
+    }
+
+    fn set_session(&self, client: Option<&tb_rs::Client>) {
+        *self.session.lock().unwrap() = client.map(|client| Session {
+            client_id: client.id(),
+            view: client.view(),
+            replicas: client.replica_addresses(),
+        });
+    }
+
+    fn session(&self) -> Option<Session> {
+        self.session.lock().unwrap().clone()
+    }
+
+    fn set_batch_size_limit(&self, limit: Option<u32>) {
+        self.batch_size_limit
+            .store(limit.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    fn batch_size_limit(&self) -> Option<u32> {
+        match self.batch_size_limit.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
 }
 
 impl TigerBeetleClient {
     /// Connect to a TigerBeetle cluster.
     ///
-    /// Spawns a background thread with tokio_uring runtime.
+    /// Spawns a background thread with tokio_uring runtime, which reconnects
+    /// whenever the session ends. Fails if the first connection does.
     pub async fn connect(
         cluster_id: u128,
-        address: SocketAddr,
+        addresses: &str,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let (tx, rx) = mpsc::channel::<Request>(32);
-        let (ready_tx, ready_rx) = oneshot::channel::<Result<Option<u32>, String>>();
+        let (tx, rx) = mpsc::channel::<Queued>(32);
+        let (ready_tx, ready_rx) = oneshot::channel::<Result<(), String>>();
 
-        let addr_str = address.to_string();
+        let addr_str = addresses.to_string();
+        let stats = Arc::new(ClientStats::new());
 
         // Spawn dedicated thread for tokio_uring runtime
-        thread::spawn(move || {
-            tokio_uring::start(async move {
-                // Connect to TigerBeetle
-                let client_result = tb_rs::Client::connect(cluster_id, &addr_str).await;
-
-                match client_result {
-                    Ok(client) => {
-                        let batch_limit = client.batch_size_limit();
-                        let _ = ready_tx.send(Ok(batch_limit));
-                        run_client_loop(client, rx).await;
-                    }
-                    Err(e) => {
-                        let _ = ready_tx.send(Err(format!("Failed to connect: {:?}", e)));
-                    }
-                }
-            });
-        });
+        let thread_stats = stats.clone();
+        thread::spawn(move || supervise(cluster_id, addr_str, rx, ready_tx, &thread_stats));
 
         // Wait for connection result
-        let batch_size_limit = ready_rx
+        ready_rx
             .await
             .map_err(|_| "Client thread died during startup")?
             .map_err(|e| e)?;
 
         Ok(Self {
             tx,
-            batch_size_limit,
+            cluster_id,
+            stats,
         })
     }
 
-    /// Get the batch size limit (available after registration).
+    /// Send a request to the client thread and wait for its reply.
+    async fn call<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T, ClientError>>) -> Request,
+    ) -> Result<T, ClientError> {
+        self.stats.requests.fetch_add(1, Ordering::Relaxed);
+        access_log::record_tb_op();
+        let _in_flight = InFlight::new(&self.stats.in_flight);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let result = match self.tx.send(Queued::new(request(reply_tx))).await {
+            Ok(()) => reply_rx
+                .await
+                .unwrap_or_else(|_| Err(ClientError::Connection("client thread died".into()))),
+            Err(_) => Err(ClientError::Connection("client thread died".into())),
+        };
+        match result {
+            Ok(_) => self.stats.record_success(),
+            Err(_) => {
+                self.stats.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    /// Send a read request, sending it again while it fails with a
+    /// transient error, up to [`READ_ATTEMPTS`] times and within
+    /// [`READ_RETRY_BUDGET`].
+    async fn read<T>(
+        &self,
+        request: impl Fn(oneshot::Sender<Result<T, ClientError>>) -> Request,
+    ) -> Result<T, ClientError> {
+        let start = Instant::now();
+        let mut delay = READ_RETRY_DELAY;
+        let mut attempts = 1;
+        loop {
+            let result = self.call(&request).await;
+            let error = match &result {
+                Err(error) if attempts < READ_ATTEMPTS && is_transient(error) => error,
+                _ => return result,
+            };
+            let wait = match retry_wait(start.elapsed(), delay, jitter()) {
+                Some(wait) if !self.tx.is_closed() => wait,
+                _ => return result,
+            };
+            tracing::debug!("Read failed: {}; retrying in {:?}", error, wait);
+            self.stats.retries.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(wait).await;
+            delay *= 2;
+            attempts += 1;
+        }
+    }
+
+    /// Get the batch size limit the current session registered with; a
+    /// reconnect may bring a new one after the cluster is upgraded.
     pub fn batch_size_limit(&self) -> Option<u32> {
-        self.batch_size_limit
+        self.stats.batch_size_limit()
     }
 
     /// Check if the client is connected and ready.
     ///
     /// Returns true if the client thread is alive and has successfully registered.
     pub fn is_ready(&self) -> bool {
-        // If we have a client, we've successfully connected and registered.
-        // The channel being open indicates the thread is alive.
-        !self.tx.is_closed()
+        // The thread is alive while the channel is open, and serving
+        // requests unless it is waiting to reconnect.
+        !self.tx.is_closed() && self.stats.state() == SessionState::Ready
     }
 
     /// Create accounts.
@@ -125,18 +415,11 @@ impl TigerBeetleClient {
         &self,
         accounts: &[Account],
     ) -> Result<Vec<CreateAccountsResult>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::CreateAccounts {
-                accounts: accounts.to_vec(),
-                reply: reply_tx,
-            })
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
+        self.call(|reply| Request::CreateAccounts {
+            accounts: accounts.to_vec(),
+            reply,
+        })
+        .await
     }
 
     /// Create transfers.
@@ -144,50 +427,29 @@ impl TigerBeetleClient {
         &self,
         transfers: &[Transfer],
     ) -> Result<Vec<CreateTransfersResult>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::CreateTransfers {
-                transfers: transfers.to_vec(),
-                reply: reply_tx,
-            })
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
+        self.call(|reply| Request::CreateTransfers {
+            transfers: transfers.to_vec(),
+            reply,
+        })
+        .await
     }
 
     /// Lookup accounts by ID.
     pub async fn lookup_accounts(&self, ids: &[u128]) -> Result<Vec<Account>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::LookupAccounts {
-                ids: ids.to_vec(),
-                reply: reply_tx,
-            })
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
+        self.read(|reply| Request::LookupAccounts {
+            ids: ids.to_vec(),
+            reply,
+        })
+        .await
     }
 
     /// Lookup transfers by ID.
     pub async fn lookup_transfers(&self, ids: &[u128]) -> Result<Vec<Transfer>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::LookupTransfers {
-                ids: ids.to_vec(),
-                reply: reply_tx,
-            })
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
+        self.read(|reply| Request::LookupTransfers {
+            ids: ids.to_vec(),
+            reply,
+        })
+        .await
     }
 
     /// Get transfers for an account.
@@ -195,18 +457,8 @@ impl TigerBeetleClient {
         &self,
         filter: AccountFilter,
     ) -> Result<Vec<Transfer>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::GetAccountTransfers {
-                filter,
-                reply: reply_tx,
-            })
+        self.read(|reply| Request::GetAccountTransfers { filter, reply })
             .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
     }
 
     /// Get balance history for an account.
@@ -214,101 +466,805 @@ impl TigerBeetleClient {
         &self,
         filter: AccountFilter,
     ) -> Result<Vec<AccountBalance>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::GetAccountBalances {
-                filter,
-                reply: reply_tx,
-            })
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
+        self.read(|reply| Request::GetAccountBalances { filter, reply })
             .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
     }
 
     /// Query accounts.
     pub async fn query_accounts(&self, filter: QueryFilter) -> Result<Vec<Account>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::QueryAccounts {
-                filter,
-                reply: reply_tx,
-            })
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
+        self.read(|reply| Request::QueryAccounts { filter, reply })
             .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
     }
 
     /// Query transfers.
     pub async fn query_transfers(&self, filter: QueryFilter) -> Result<Vec<Transfer>, ClientError> {
-        let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::QueryTransfers {
-                filter,
-                reply: reply_tx,
-            })
+        self.read(|reply| Request::QueryTransfers { filter, reply })
             .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?;
-
-        reply_rx
-            .await
-            .map_err(|_| ClientError::Connection("client thread died".into()))?
     }
 
     /// Shutdown the client.
     pub async fn shutdown(&self) {
-        let _ = self.tx.send(Request::Shutdown).await;
+        let _ = self.tx.send(Queued::new(Request::Shutdown)).await;
     }
 }
 
-/// Run the client event loop in the tokio_uring thread.
-async fn run_client_loop(mut client: tb_rs::Client, mut rx: mpsc::Receiver<Request>) {
-    while let Some(request) = rx.recv().await {
-        match request {
-            Request::CreateAccounts { accounts, reply } => {
-                let result = client.create_accounts(&accounts).await;
-                let _ = reply.send(result);
-            }
-            Request::CreateTransfers { transfers, reply } => {
-                let result = client.create_transfers(&transfers).await;
-                let _ = reply.send(result);
-            }
-            Request::LookupAccounts { ids, reply } => {
-                let result = client.lookup_accounts(&ids).await;
-                let _ = reply.send(result);
-            }
-            Request::LookupTransfers { ids, reply } => {
-                let result = client.lookup_transfers(&ids).await;
-                let _ = reply.send(result);
-            }
-            Request::GetAccountTransfers { filter, reply } => {
-                let result = client.get_account_transfers(filter).await;
-                let _ = reply.send(result);
+/// Counts a request in flight until dropped, even if the handler waiting
+/// for it is cancelled.
+struct InFlight<'a>(&'a AtomicU32);
+
+impl<'a> InFlight<'a> {
+    fn new(count: &'a AtomicU32) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Clients sharing the requests of the web server, round-robin.
+pub struct ClientPool {
+    clients: Vec<TigerBeetleClient>,
+    next: AtomicU64,
+}
+
+/// Health and counters of one pooled client.
+#[derive(Debug, Clone)]
+pub struct ClientHealth {
+    pub ready: bool,
+    pub state: SessionState,
+    pub restarts: u64,
+    pub requests: u64,
+    pub errors: u64,
+    /// Reads sent again after a transient error.
+    pub retries: u64,
+    /// Requests dropped unsent because their caller went away.
+    pub abandoned: u64,
+    pub in_flight: u32,
+    /// When a request last succeeded, if one has.
+    pub last_success: Option<SystemTime>,
+    /// The current session, unless reconnecting.
+    pub session: Option<Session>,
+}
+
+impl ClientPool {
+    /// Connect `size` clients to a TigerBeetle cluster.
+    pub async fn connect(
+        cluster_id: u128,
+        addresses: &str,
+        size: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        assert!(size > 0, "a client pool needs at least one client");
+        let mut clients = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            clients.push(TigerBeetleClient::connect(cluster_id, addresses).await?);
+        }
+        Ok(Self::new(clients))
+    }
+
+    fn new(clients: Vec<TigerBeetleClient>) -> Self {
+        Self {
+            clients,
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// The client for the next request: the next ready one in turn.
+    pub fn get(&self) -> &TigerBeetleClient {
+        let len = self.clients.len() as u64;
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..len)
+            .map(|i| &self.clients[((start + i) % len) as usize])
+            .find(|client| client.is_ready())
+            // With none ready, any one reports the error.
+            .unwrap_or(&self.clients[(start % len) as usize])
+    }
+
+    /// Get the batch size limit (available after registration).
+    pub fn batch_size_limit(&self) -> Option<u32> {
+        self.clients[0].batch_size_limit()
+    }
+
+    /// The cluster the clients are connected to.
+    pub fn cluster_id(&self) -> u128 {
+        self.clients[0].cluster_id
+    }
+
+    /// Most events of type `T` one request may carry (available after
+    /// registration).
+    pub fn max_batch_count<T>(&self) -> Option<u32> {
+        let limit = self.batch_size_limit()?;
+        let element_size = std::mem::size_of::<T>() as u32;
+        let trailer_size = tb_rs::protocol::multi_batch::trailer_total_size(element_size, 1);
+        Some(limit.saturating_sub(trailer_size) / element_size)
+    }
+
+    /// When a request on any client last succeeded.
+    pub fn last_success(&self) -> Option<SystemTime> {
+        self.clients
+            .iter()
+            .filter_map(|client| client.stats.last_success())
+            .max()
+    }
+
+    /// Health and counters of each client.
+    pub fn health(&self) -> Vec<ClientHealth> {
+        self.clients
+            .iter()
+            .map(|client| ClientHealth {
+                ready: client.is_ready(),
+                state: client.stats.state(),
+                restarts: client.stats.restarts.load(Ordering::Relaxed),
+                requests: client.stats.requests.load(Ordering::Relaxed),
+                errors: client.stats.errors.load(Ordering::Relaxed),
+                retries: client.stats.retries.load(Ordering::Relaxed),
+                abandoned: client.stats.abandoned.load(Ordering::Relaxed),
+                in_flight: client.stats.in_flight.load(Ordering::Relaxed),
+                last_success: client.stats.last_success(),
+                session: client.stats.session(),
+            })
+            .collect()
+    }
+}
+
+/// How a session ended.
+enum SessionEnd {
+    /// The client was dropped or shut down; the thread should exit.
+    Closed,
+    /// The session failed; the thread should connect again.
+    Failed(String),
+}
+
+/// Run sessions until the client is dropped or shut down, connecting again
+/// with backoff whenever one fails. If the first connection fails, report it
+/// on `ready_tx` and give up.
+fn supervise(
+    cluster_id: u128,
+    address: String,
+    rx: mpsc::Receiver<Queued>,
+    ready_tx: oneshot::Sender<Result<(), String>>,
+    stats: &ClientStats,
+) {
+    Supervisor::new(&address, rx, ready_tx, stats).run(|supervisor| {
+        tokio_uring::start(async {
+            // Connect to TigerBeetle
+            let client = match tb_rs::Client::connect(cluster_id, &address).await {
+                Ok(client) => client,
+                Err(e) => return SessionEnd::Failed(format!("Failed to connect: {:?}", e)),
+            };
+            supervisor.connected(client.batch_size_limit());
+            supervisor.stats.set_session(Some(&client));
+            run_client_loop(client, &mut supervisor.rx, supervisor.stats).await
+        })
+    });
+}
+
+/// What a client thread keeps from one session to the next.
+struct Supervisor<'a> {
+    address: &'a str,
+    rx: mpsc::Receiver<Queued>,
+    /// Taken once the first session connects or fails.
+    ready_tx: Option<oneshot::Sender<Result<(), String>>>,
+    stats: &'a ClientStats,
+    /// Delay before the next reconnect.
+    backoff: Duration,
+}
+
+impl<'a> Supervisor<'a> {
+    fn new(
+        address: &'a str,
+        rx: mpsc::Receiver<Queued>,
+        ready_tx: oneshot::Sender<Result<(), String>>,
+        stats: &'a ClientStats,
+    ) -> Self {
+        Self {
+            address,
+            rx,
+            ready_tx: Some(ready_tx),
+            stats,
+            backoff: BACKOFF_MIN,
+        }
+    }
+
+    /// Run `session` again and again, backing off after each that fails or
+    /// panics, until one ends with the client closed or the first fails.
+    fn run(mut self, mut session: impl FnMut(&mut Self) -> SessionEnd) {
+        loop {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| session(&mut self)));
+            let reason = match outcome {
+                Ok(SessionEnd::Closed) => break,
+                Ok(SessionEnd::Failed(reason)) => reason,
+                Err(panic) => format!("Client thread panicked: {}", panic_message(&panic)),
+            };
+            if let Some(ready_tx) = self.ready_tx.take() {
+                let _ = ready_tx.send(Err(reason));
+                break;
             }
-            Request::GetAccountBalances { filter, reply } => {
-                let result = client.get_account_balances(filter).await;
-                let _ = reply.send(result);
+
+            tracing::warn!("{}; reconnecting in {:?}", reason, self.backoff);
+            self.stats.set_state(SessionState::Reconnecting);
+            self.stats.set_session(None);
+            self.stats.restarts.fetch_add(1, Ordering::Relaxed);
+            if !fail_requests_for(&mut self.rx, self.backoff) {
+                break;
             }
-            Request::QueryAccounts { filter, reply } => {
-                let result = client.query_accounts(filter).await;
-                let _ = reply.send(result);
+            self.backoff = next_backoff(self.backoff);
+        }
+        self.stats.set_state(SessionState::Stopped);
+        self.stats.set_session(None);
+    }
+
+    /// Note that a session connected, registering with `batch_size_limit`.
+    fn connected(&mut self, batch_size_limit: Option<u32>) {
+        self.stats.set_batch_size_limit(batch_size_limit);
+        if let Some(ready_tx) = self.ready_tx.take() {
+            let _ = ready_tx.send(Ok(()));
+        } else {
+            tracing::info!("Reconnected to TigerBeetle at {}", self.address);
+        }
+        self.stats.set_state(SessionState::Ready);
+        self.backoff = BACKOFF_MIN;
+    }
+}
+
+/// The delay before the reconnect after one that waited `backoff`.
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(BACKOFF_MAX)
+}
+
+/// Fail the requests that arrive within `delay`. Returns false if the
+/// client was dropped or shut down meanwhile.
+fn fail_requests_for(rx: &mut mpsc::Receiver<Queued>, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        match rx.try_recv() {
+            Ok(Queued {
+                request: Request::Shutdown,
+                ..
+            })
+            | Err(TryRecvError::Disconnected) => return false,
+            Ok(queued) => queued
+                .request
+                .fail(ClientError::Connection(RECONNECTING.into())),
+            Err(TryRecvError::Empty) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return true;
+                }
+                thread::sleep(BACKOFF_POLL.min(deadline - now));
             }
-            Request::QueryTransfers { filter, reply } => {
-                let result = client.query_transfers(filter).await;
-                let _ = reply.send(result);
+        }
+    }
+}
+
+/// Whether a request that failed with `error` may succeed if sent again
+/// shortly: it timed out, or the session is reconnecting.
+///
+/// Other errors are left to the caller: an eviction or a lost registration
+/// ends the session, so the requests behind it already see
+/// [`RECONNECTING`], and a client thread that died will not come back.
+fn is_transient(error: &ClientError) -> bool {
+    match error {
+        ClientError::Timeout => true,
+        ClientError::Connection(message) => message == RECONNECTING,
+        _ => false,
+    }
+}
+
+/// How long to wait before sending a read again, after a retry `delay`
+/// and with `jitter` in `[0, 1)`, or `None` if the read, first sent
+/// `elapsed` ago, would then overrun [`READ_RETRY_BUDGET`].
+fn retry_wait(elapsed: Duration, delay: Duration, jitter: f64) -> Option<Duration> {
+    // Retry after half to all of the delay, so that reads failing
+    // together are not sent again together.
+    let wait = delay / 2 + delay.mul_f64(jitter / 2.0);
+    (elapsed + wait <= READ_RETRY_BUDGET).then_some(wait)
+}
+
+/// A random number in `[0, 1)`.
+fn jitter() -> f64 {
+    // Each `RandomState` is seeded differently.
+    let bits = RandomState::new().hash_one(Instant::now());
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Send `result` to `reply`, returning why the session cannot continue if
+/// the result shows it.
+fn respond<T>(
+    reply: oneshot::Sender<Result<T, ClientError>>,
+    result: Result<T, ClientError>,
+) -> Option<String> {
+    if let Err(error) = &result {
+        let span = Span::current();
+        span.record("otel.status_code", "error");
+        span.record("otel.status_message", tracing::field::display(error));
+    }
+    let end = match &result {
+        Err(ClientError::Evicted(reason)) => Some(format!("Evicted: {:?}", reason)),
+        Err(ClientError::NotRegistered) => Some("Session is not registered".to_string()),
+        _ => None,
+    };
+    let _ = reply.send(result);
+    end
+}
+
+/// The next request someone still waits for, dropping abandoned ones.
+async fn next_request(rx: &mut mpsc::Receiver<Queued>, stats: &ClientStats) -> Option<Queued> {
+    loop {
+        let queued = rx.recv().await?;
+        if !queued.request.is_abandoned() {
+            return Some(queued);
+        }
+        stats.abandoned.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Run the client event loop in the tokio_uring thread.
+async fn run_client_loop(
+    mut client: tb_rs::Client,
+    rx: &mut mpsc::Receiver<Queued>,
+    stats: &ClientStats,
+) -> SessionEnd {
+    while let Some(Queued {
+        request,
+        span: parent,
+        sent,
+    }) = next_request(rx, stats).await
+    {
+        if let Request::Shutdown = request {
+            client.close().await;
+            return SessionEnd::Closed;
+        }
+        let span = tracing::info_span!(
+            target: otel::TARGET,
+            parent: &parent,
+            "tigerbeetle",
+            otel.name = request.operation(),
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            otel.status_message = tracing::field::Empty,
+            db.system = "tigerbeetle",
+            db.operation.name = request.operation(),
+            tb.queued_ms = sent.elapsed().as_secs_f64() * 1000.0,
+        );
+        let end = async {
+            match request {
+                Request::CreateAccounts { accounts, reply } => {
+                    respond(reply, client.create_accounts(&accounts).await)
+                }
+                Request::CreateTransfers { transfers, reply } => {
+                    respond(reply, client.create_transfers(&transfers).await)
+                }
+                Request::LookupAccounts { ids, reply } => {
+                    respond(reply, client.lookup_accounts(&ids).await)
+                }
+                Request::LookupTransfers { ids, reply } => {
+                    respond(reply, client.lookup_transfers(&ids).await)
+                }
+                Request::GetAccountTransfers { filter, reply } => {
+                    respond(reply, client.get_account_transfers(filter).await)
+                }
+                Request::GetAccountBalances { filter, reply } => {
+                    respond(reply, client.get_account_balances(filter).await)
+                }
+                Request::QueryAccounts { filter, reply } => {
+                    respond(reply, client.query_accounts(filter).await)
+                }
+                Request::QueryTransfers { filter, reply } => {
+                    respond(reply, client.query_transfers(filter).await)
+                }
+                Request::BatchSizeLimit { reply } => {
+                    let _ = reply.send(client.batch_size_limit());
+                    None
+                }
+                Request::Shutdown => unreachable!("handled above"),
             }
-            Request::BatchSizeLimit { reply } => {
-                let _ = reply.send(client.batch_size_limit());
+        }
+        .instrument(span)
+        .await;
+        // A reply may bring a new view, or a replica may have reconnected.
+        stats.set_session(Some(&client));
+        if let Some(reason) = end {
+            return SessionEnd::Failed(reason);
+        }
+    }
+    SessionEnd::Closed
+}
+
+/// Clients answered by an in-memory cluster, for tests.
+#[cfg(test)]
+impl ClientPool {
+    /// `size` clients answered by `cluster` on tasks of the current
+    /// runtime.
+    pub fn fake(cluster: &Arc<crate::testing::FakeCluster>, size: u32) -> Self {
+        Self::new(
+            (0..size)
+                .map(|_| TigerBeetleClient::fake(cluster.clone()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+impl TigerBeetleClient {
+    fn fake(cluster: Arc<crate::testing::FakeCluster>) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Queued>(32);
+        let stats = Arc::new(ClientStats::new());
+        stats.set_batch_size_limit(Some(crate::testing::BATCH_SIZE_LIMIT));
+        let task_stats = stats.clone();
+        tokio::spawn(async move {
+            while let Some(Queued { request, .. }) = next_request(&mut rx, &task_stats).await {
+                match request {
+                    Request::CreateAccounts { accounts, reply } => {
+                        drop(reply.send(cluster.create_accounts(&accounts)))
+                    }
+                    Request::CreateTransfers { transfers, reply } => {
+                        drop(reply.send(cluster.create_transfers(&transfers)))
+                    }
+                    Request::LookupAccounts { ids, reply } => {
+                        drop(reply.send(cluster.lookup_accounts(&ids)))
+                    }
+                    Request::LookupTransfers { ids, reply } => {
+                        drop(reply.send(cluster.lookup_transfers(&ids)))
+                    }
+                    Request::GetAccountTransfers { filter, reply } => {
+                        drop(reply.send(cluster.get_account_transfers(filter)))
+                    }
+                    Request::GetAccountBalances { filter, reply } => {
+                        drop(reply.send(cluster.get_account_balances(filter)))
+                    }
+                    Request::QueryAccounts { filter, reply } => {
+                        drop(reply.send(cluster.query_accounts(filter)))
+                    }
+                    Request::QueryTransfers { filter, reply } => {
+                        drop(reply.send(cluster.query_transfers(filter)))
+                    }
+                    Request::BatchSizeLimit { reply } => {
+                        let _ = reply.send(Some(crate::testing::BATCH_SIZE_LIMIT));
+                    }
+                    Request::Shutdown => break,
+                }
             }
-            Request::Shutdown => {
-                client.close().await;
-                break;
+        });
+        Self {
+            tx,
+            cluster_id: 0,
+            stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeCluster;
+
+    #[test]
+    fn test_client_stats_batch_size_limit() {
+        let stats = ClientStats::new();
+        assert_eq!(stats.batch_size_limit(), None);
+        stats.set_batch_size_limit(Some(1024 * 1024));
+        assert_eq!(stats.batch_size_limit(), Some(1024 * 1024));
+        // A reconnect replaces the limit.
+        stats.set_batch_size_limit(Some(8190 * 128));
+        assert_eq!(stats.batch_size_limit(), Some(8190 * 128));
+        stats.set_batch_size_limit(None);
+        assert_eq!(stats.batch_size_limit(), None);
+    }
+
+    /// The clients `pool.get()` hands out over `count` calls, by index.
+    fn picks(pool: &ClientPool, count: usize) -> Vec<usize> {
+        (0..count)
+            .map(|_| {
+                let client = pool.get();
+                pool.clients
+                    .iter()
+                    .position(|c| std::ptr::eq(c, client))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_pool_skips_clients_not_ready() {
+        let cluster = FakeCluster::new();
+        let pool = ClientPool::fake(&cluster, 3);
+        assert_eq!(picks(&pool, 6), [0, 1, 2, 0, 1, 2]);
+
+        // Reconnecting: its turns go to the next ready client.
+        pool.clients[1].stats.set_state(SessionState::Reconnecting);
+        assert_eq!(picks(&pool, 6), [0, 2, 2, 0, 2, 2]);
+
+        // Stopped, its thread gone.
+        pool.clients[1].stats.set_state(SessionState::Ready);
+        pool.clients[2].shutdown().await;
+        while !pool.clients[2].tx.is_closed() {
+            tokio::task::yield_now().await;
+        }
+        assert!(!pool.clients[2].is_ready());
+        assert_eq!(picks(&pool, 6), [0, 1, 0, 0, 1, 0]);
+        assert_eq!(
+            pool.health().iter().map(|h| h.ready).collect::<Vec<_>>(),
+            [true, true, false]
+        );
+
+        // With none ready, each turn's client reports the error.
+        pool.clients[0].stats.set_state(SessionState::Reconnecting);
+        pool.clients[1].stats.set_state(SessionState::Reconnecting);
+        assert_eq!(picks(&pool, 3), [0, 1, 2]);
+        let error = pool.clients[2].lookup_accounts(&[1]).await.unwrap_err();
+        assert!(matches!(error, ClientError::Connection(_)), "{:?}", error);
+    }
+
+    /// A lookup to queue, and where its reply arrives.
+    fn lookup() -> (Queued, oneshot::Receiver<Result<Vec<Account>, ClientError>>) {
+        let (reply, reply_rx) = oneshot::channel();
+        let request = Request::LookupAccounts {
+            ids: vec![1],
+            reply,
+        };
+        (Queued::new(request), reply_rx)
+    }
+
+    fn is_reconnecting(result: Result<Vec<Account>, ClientError>) -> bool {
+        matches!(result, Err(ClientError::Connection(message)) if message == RECONNECTING)
+    }
+
+    #[tokio::test]
+    async fn test_next_request_drops_abandoned() {
+        let stats = ClientStats::new();
+        let (tx, mut rx) = mpsc::channel(32);
+        let (gone, gone_rx) = lookup();
+        drop(gone_rx);
+        let (live, mut live_rx) = lookup();
+        tx.send(gone).await.unwrap();
+        tx.send(live).await.unwrap();
+
+        // The first caller went away: its request is skipped.
+        let Some(Queued { request, .. }) = next_request(&mut rx, &stats).await else {
+            panic!("no request");
+        };
+        assert_eq!(stats.abandoned.load(Ordering::Relaxed), 1);
+        assert!(!request.is_abandoned());
+        request.fail(ClientError::Timeout);
+        assert!(matches!(live_rx.try_recv(), Ok(Err(ClientError::Timeout))));
+
+        // A shutdown has nobody waiting, but is never abandoned.
+        tx.send(Queued::new(Request::Shutdown)).await.unwrap();
+        let queued = next_request(&mut rx, &stats).await.unwrap();
+        assert!(matches!(queued.request, Request::Shutdown));
+        assert_eq!(stats.abandoned.load(Ordering::Relaxed), 1);
+
+        // Abandoned requests up to the end of the channel are all dropped.
+        let (gone, _) = lookup();
+        tx.send(gone).await.unwrap();
+        drop(tx);
+        assert!(next_request(&mut rx, &stats).await.is_none());
+        assert_eq!(stats.abandoned.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_next_backoff() {
+        let mut backoff = BACKOFF_MIN;
+        let mut backoffs = vec![backoff];
+        while backoff < BACKOFF_MAX {
+            backoff = next_backoff(backoff);
+            backoffs.push(backoff);
+        }
+        let millis: Vec<u128> = backoffs.iter().map(Duration::as_millis).collect();
+        assert_eq!(
+            millis,
+            [100, 200, 400, 800, 1600, 3200, 6400, 12800, 25600, 30000]
+        );
+        assert_eq!(next_backoff(BACKOFF_MAX), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_fail_requests_for() {
+        let (tx, mut rx) = mpsc::channel(32);
+
+        // Queued before the wait, and sent during it.
+        let (queued, early) = lookup();
+        tx.blocking_send(queued).unwrap();
+        let (queued, late) = lookup();
+        let sender = tx.clone();
+        let send = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(5));
+            sender.blocking_send(queued).unwrap();
+        });
+        assert!(fail_requests_for(&mut rx, Duration::from_millis(200)));
+        send.join().unwrap();
+        assert!(is_reconnecting(early.blocking_recv().unwrap()));
+        assert!(is_reconnecting(late.blocking_recv().unwrap()));
+
+        // A shutdown ends the wait early.
+        tx.blocking_send(Queued::new(Request::Shutdown)).unwrap();
+        let start = Instant::now();
+        assert!(!fail_requests_for(&mut rx, Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // As does dropping the client.
+        drop(tx);
+        assert!(!fail_requests_for(&mut rx, Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_supervisor_recovers() {
+        let stats = ClientStats::new();
+        let (tx, rx) = mpsc::channel(32);
+        let (ready_tx, mut ready_rx) = oneshot::channel();
+        let mut backoffs = Vec::new();
+        Supervisor::new("test", rx, ready_tx, &stats).run(|supervisor| {
+            backoffs.push(supervisor.backoff);
+            match backoffs.len() {
+                1 => {
+                    supervisor.connected(Some(1024));
+                    panic!("lost the session")
+                }
+                2 => {
+                    supervisor.connected(Some(2048));
+                    SessionEnd::Failed("Evicted: SessionTooLow".to_string())
+                }
+                // Connecting fails, so the backoff keeps growing.
+                3 => SessionEnd::Failed("Failed to connect".to_string()),
+                _ => {
+                    supervisor.connected(Some(4096));
+                    assert_eq!(supervisor.stats.state(), SessionState::Ready);
+                    // Requests are served again.
+                    let (queued, reply) = lookup();
+                    tx.blocking_send(queued).unwrap();
+                    match supervisor.rx.blocking_recv().unwrap().request {
+                        Request::LookupAccounts { reply, .. } => {
+                            let _ = reply.send(Ok(Vec::new()));
+                        }
+                        _ => unreachable!(),
+                    }
+                    assert!(reply.blocking_recv().unwrap().is_ok());
+                    SessionEnd::Closed
+                }
             }
+        });
+        assert_eq!(ready_rx.try_recv().unwrap(), Ok(()));
+        let millis: Vec<u128> = backoffs.iter().map(Duration::as_millis).collect();
+        // Each connected session resets the backoff.
+        assert_eq!(millis, [100, 200, 200, 400]);
+        assert_eq!(stats.restarts.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.batch_size_limit(), Some(4096));
+        assert_eq!(stats.state(), SessionState::Stopped);
+    }
+
+    #[test]
+    fn test_supervisor_gives_up_if_first_connect_fails() {
+        let stats = ClientStats::new();
+        let (_tx, rx) = mpsc::channel(32);
+        let (ready_tx, mut ready_rx) = oneshot::channel();
+        let mut sessions = 0;
+        Supervisor::new("test", rx, ready_tx, &stats).run(|_| {
+            sessions += 1;
+            SessionEnd::Failed("Failed to connect".to_string())
+        });
+        assert_eq!(sessions, 1);
+        assert_eq!(
+            ready_rx.try_recv().unwrap(),
+            Err("Failed to connect".to_string())
+        );
+        assert_eq!(stats.restarts.load(Ordering::Relaxed), 0);
+        assert_eq!(stats.state(), SessionState::Stopped);
+    }
+
+    #[test]
+    fn test_supervisor_stops_when_client_dropped() {
+        let stats = ClientStats::new();
+        let (tx, rx) = mpsc::channel(32);
+        let (ready_tx, _ready_rx) = oneshot::channel();
+        let mut tx = Some(tx);
+        let mut sessions = 0;
+        Supervisor::new("test", rx, ready_tx, &stats).run(|supervisor| {
+            sessions += 1;
+            supervisor.connected(None);
+            // The client goes away, leaving a request, as the session ends.
+            let (queued, _) = lookup();
+            tx.take().unwrap().blocking_send(queued).unwrap();
+            SessionEnd::Failed("Session is not registered".to_string())
+        });
+        assert_eq!(sessions, 1);
+        assert_eq!(stats.restarts.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.state(), SessionState::Stopped);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&ClientError::Timeout));
+        assert!(is_transient(&ClientError::Connection(RECONNECTING.into())));
+        for error in [
+            ClientError::Connection("client thread died".into()),
+            ClientError::NotRegistered,
+            ClientError::Shutdown,
+            ClientError::InvalidOperation,
+        ] {
+            assert!(!is_transient(&error), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_retry_wait() {
+        let delay = Duration::from_millis(100);
+        let wait = |elapsed, jitter| retry_wait(elapsed, delay, jitter);
+        assert_eq!(wait(Duration::ZERO, 0.0), Some(Duration::from_millis(50)));
+        assert_eq!(wait(Duration::ZERO, 0.5), Some(Duration::from_millis(75)));
+        assert!(wait(Duration::ZERO, 0.999).unwrap() < delay);
+
+        // Up to the end of the budget, but not past it.
+        let last = READ_RETRY_BUDGET - Duration::from_millis(50);
+        assert_eq!(wait(last, 0.0), Some(Duration::from_millis(50)));
+        assert_eq!(wait(last, 0.1), None);
+        assert_eq!(wait(READ_RETRY_BUDGET, 0.0), None);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let samples: Vec<f64> = (0..1000).map(|_| jitter()).collect();
+        assert!(samples.iter().all(|j| (0.0..1.0).contains(j)));
+        // Spread over the range rather than stuck at one value.
+        assert!(samples.iter().any(|&j| j < 0.25));
+        assert!(samples.iter().any(|&j| j >= 0.75));
+    }
+
+    #[tokio::test]
+    async fn test_read_retries() {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[crate::testing::account(1, 1)], &[]);
+        let client = TigerBeetleClient::fake(cluster.clone());
+        let retries = || client.stats.retries.load(Ordering::Relaxed);
+
+        // A timeout, then the reconnecting error: sent again each time.
+        cluster.fail_next(ClientError::Timeout);
+        cluster.fail_next(ClientError::Connection(RECONNECTING.into()));
+        assert_eq!(client.lookup_accounts(&[1]).await.unwrap().len(), 1);
+        assert_eq!(retries(), 2);
+
+        // Out of attempts: the last error is returned.
+        for _ in 0..READ_ATTEMPTS {
+            cluster.fail_next(ClientError::Timeout);
         }
+        let error = client.lookup_accounts(&[1]).await.unwrap_err();
+        assert!(matches!(error, ClientError::Timeout), "{:?}", error);
+        assert_eq!(retries(), 2 + u64::from(READ_ATTEMPTS) - 1);
+
+        // Other errors are returned straight away.
+        cluster.fail_next(ClientError::NotRegistered);
+        let error = client.lookup_accounts(&[1]).await.unwrap_err();
+        assert!(matches!(error, ClientError::NotRegistered), "{:?}", error);
+        assert_eq!(retries(), 2 + u64::from(READ_ATTEMPTS) - 1);
+
+        // Writes are never sent again.
+        cluster.fail_next(ClientError::Timeout);
+        let error = client
+            .create_accounts(&[crate::testing::account(2, 1)])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClientError::Timeout), "{:?}", error);
+        assert_eq!(retries(), 2 + u64::from(READ_ATTEMPTS) - 1);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let panic = panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(&panic), "static");
+        let panic = panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(panic_message(&panic), "formatted 1");
+        let panic = panic::catch_unwind(|| panic::panic_any(1)).unwrap_err();
+        assert_eq!(panic_message(&panic), "unknown panic");
     }
 }
@@ -0,0 +1,410 @@
+//! Checking request parameters.
+//!
+//! Handlers take their query string as [`Valid`] rather than `Query`, and
+//! an account, transfer or ledger in their path as [`IdPath`] or
+//! [`LedgerPath`], so a malformed or out-of-range parameter is rejected
+//! the same way everywhere: status 422 with code `invalid_input` and a
+//! message naming the parameter. What is valid for each set of parameters
+//! is its [`Validate`] impl, built from the checks here.
+//!
+//! Hex IDs may be written with or without `0x`. A `limit` of more than a
+//! page is capped to one page where nothing would stream the rest; the
+//! list endpoints stream instead (see [`crate::routes::export`]).
+//!
+//! Create requests take their body as [`Events`]. Bodies over
+//! `--max-body-bytes` and arrays of more events than one request may carry
+//! are rejected with 413 and code `request_too_large`, unparseable JSON
+//! with 400, and an item that does not fit the schema or does not convert
+//! with 422 and a message that starts with its index, e.g. `[3].ledger`.
+//! Nothing is sent to TigerBeetle unless every item is valid.
+
+use crate::api::types::{NewAccount, NewTransfer};
+use crate::error::AppError;
+use crate::state::AppState;
+use axum::async_trait;
+use axum::extract::{FromRequest, FromRequestParts, Path, Query, Request};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use tb_rs::{Account, ClientError, Transfer};
+
+/// Most records a response holds when it is not streamed; one query's
+/// worth.
+pub const LIMIT_MAX: u32 = 1000;
+
+/// Query parameters with checks beyond their types.
+pub trait Validate {
+    /// Check the parameters, capping any that may be capped; an error names
+    /// the parameter at fault.
+    fn validate(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Query string extractor: `Query<T>`, checked by [`Validate`].
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Valid<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, AppError> {
+        let Query(mut params) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::Unprocessable(e.body_text()))?;
+        params.validate().map_err(AppError::Unprocessable)?;
+        Ok(Self(params))
+    }
+}
+
+/// The account or transfer ID in the path, as hex.
+pub struct IdPath(pub u128);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IdPath {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, AppError> {
+        let Path(id) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::Unprocessable(e.body_text()))?;
+        hex_id("id", &id).map(Self).map_err(AppError::Unprocessable)
+    }
+}
+
+/// The ledger in the path.
+pub struct LedgerPath(pub u32);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for LedgerPath {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, AppError> {
+        let Path(id) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::Unprocessable(e.body_text()))?;
+        let ledger = id
+            .parse()
+            .map_err(|_| format!("ledger: invalid number {:?}", id))
+            .and_then(|ledger| self::ledger("ledger", Some(ledger)).map(|()| ledger));
+        ledger.map(Self).map_err(AppError::Unprocessable)
+    }
+}
+
+/// What a create request makes: accounts or transfers.
+pub trait Event: Sized + Send + for<'a> TryFrom<&'a Self::New, Error = String> {
+    /// One item of the request body.
+    type New: DeserializeOwned + Send;
+    /// The plural, for messages.
+    const NAME: &'static str;
+}
+
+impl Event for Account {
+    type New = NewAccount;
+    const NAME: &'static str = "accounts";
+}
+
+impl Event for Transfer {
+    type New = NewTransfer;
+    const NAME: &'static str = "transfers";
+}
+
+/// Create request body extractor: a JSON array of new `E`s, converted.
+pub struct Events<E>(pub Vec<E>);
+
+#[async_trait]
+impl<E: Event> FromRequest<Arc<AppState>> for Events<E> {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &Arc<AppState>) -> Result<Self, AppError> {
+        let Json(items) = Json::<Vec<E::New>>::from_request(req, state)
+            .await
+            .map_err(|e| body_error(state, e.status(), e.body_text()))?;
+        if items.is_empty() {
+            return Err(AppError::BadRequest(format!("No {} given", E::NAME)));
+        }
+        let max = max_events::<E>(state)?;
+        if items.len() > max as usize {
+            return Err(AppError::PayloadTooLarge(format!(
+                "{} {} given, at most {} per request",
+                items.len(),
+                E::NAME,
+                max
+            )));
+        }
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                E::try_from(item).map_err(|e| AppError::Unprocessable(format!("[{}].{}", i, e)))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Most events of type `E` one request may carry: as many as fit in the
+/// cluster's batch size limit, or `--max-batch-events` if fewer.
+pub fn max_events<E>(state: &AppState) -> Result<u32, AppError> {
+    let batch_max = state
+        .pool
+        .max_batch_count::<E>()
+        .ok_or(AppError::Client(ClientError::NotRegistered))?;
+    Ok(state
+        .config
+        .max_batch_events
+        .map_or(batch_max, |max| max.min(batch_max)))
+}
+
+/// The error for a body extractor's rejection with `status` and `text`.
+pub fn body_error(state: &AppState, status: StatusCode, text: String) -> AppError {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(format!(
+            "Request body over {} bytes",
+            state.config.max_body_bytes
+        )),
+        StatusCode::UNPROCESSABLE_ENTITY => AppError::Unprocessable(text),
+        _ => AppError::BadRequest(text),
+    }
+}
+
+/// Parse hex ID parameter `name`, with or without `0x`.
+pub fn hex_id(name: &str, value: &str) -> Result<u128, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    // `from_str_radix` would take a sign.
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("{}: invalid hex ID {:?}", name, value));
+    }
+    u128::from_str_radix(digits, 16).map_err(|_| format!("{}: more than 128 bits", name))
+}
+
+/// Check page size `limit`, capping it at `max`.
+pub fn limit(limit: &mut u32, max: u32) -> Result<(), String> {
+    if *limit == 0 {
+        return Err("limit: must be at least 1".to_string());
+    }
+    *limit = (*limit).min(max);
+    Ok(())
+}
+
+/// Check a ledger filter: ledger zero would filter nothing.
+pub fn ledger(name: &str, ledger: Option<u32>) -> Result<(), String> {
+    match ledger {
+        Some(0) => Err(format!("{}: must be 1 to {}", name, u32::MAX)),
+        _ => Ok(()),
+    }
+}
+
+/// Check a code filter: code zero would filter nothing.
+pub fn code(name: &str, code: Option<u16>) -> Result<(), String> {
+    match code {
+        Some(0) => Err(format!("{}: must be 1 to {}", name, u16::MAX)),
+        _ => Ok(()),
+    }
+}
+
+/// Check that an inclusive timestamp range has room for a record; as in a
+/// TigerBeetle filter, a `max` of zero is no bound.
+pub fn timestamp_range(min: Option<u64>, max: Option<u64>) -> Result<(), String> {
+    match (min, max) {
+        (Some(min), Some(max)) if max != 0 && min > max => {
+            Err("timestamp_min: after timestamp_max".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check the page cursors of a list: one page may be asked for, after a
+/// record or before one.
+pub fn cursors(after: Option<u64>, before: Option<u64>) -> Result<(), String> {
+    if after.is_some() && before.is_some() {
+        return Err("after_timestamp and before_timestamp are mutually exclusive".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeCluster};
+    use axum::body::{to_bytes, Body};
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    #[derive(Deserialize)]
+    struct Params {
+        limit: u32,
+    }
+
+    impl Validate for Params {
+        fn validate(&mut self) -> Result<(), String> {
+            limit(&mut self.limit, LIMIT_MAX)
+        }
+    }
+
+    /// The status and body of a GET of `uri` from the server's routes, so
+    /// the extractors are tested on the paths they are mounted on.
+    async fn get_uri(uri: &str) -> (StatusCode, String) {
+        let cluster = FakeCluster::new();
+        cluster.seed(&[testing::account(0xab, 700)], &[]);
+        testing::get(&testing::router(testing::config(), &cluster), uri).await
+    }
+
+    /// The body of a GET of `uri` from a route taking [`Params`].
+    async fn get_params(uri: &str) -> String {
+        let router = Router::new().route(
+            "/",
+            get(|Valid(params): Valid<Params>| async move { params.limit.to_string() }),
+        );
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_hex_id() {
+        assert_eq!(hex_id("id", "ff"), Ok(0xff));
+        assert_eq!(hex_id("id", "0xff"), Ok(0xff));
+        assert_eq!(hex_id("id", "0XFF"), Ok(0xff));
+        assert_eq!(hex_id("id", "0"), Ok(0));
+        assert_eq!(hex_id("id", &"f".repeat(32)), Ok(u128::MAX));
+        assert_eq!(hex_id("id", &format!("0x{:032x}", 1)), Ok(1));
+    }
+
+    #[test]
+    fn test_hex_id_invalid() {
+        for value in ["", "0x", "xyz", "+ff", "-1", "0x+1", " ff", "ff ", "0xx1"] {
+            assert_eq!(
+                hex_id("account_id", value),
+                Err(format!("account_id: invalid hex ID {:?}", value))
+            );
+        }
+        assert_eq!(
+            hex_id("id", &"f".repeat(33)),
+            Err("id: more than 128 bits".to_string())
+        );
+    }
+
+    #[test]
+    fn test_limit() {
+        for (given, capped) in [
+            (1, 1),
+            (999, 999),
+            (1000, 1000),
+            (1001, 1000),
+            (u32::MAX, 1000),
+        ] {
+            let mut value = given;
+            assert_eq!(limit(&mut value, LIMIT_MAX), Ok(()));
+            assert_eq!(value, capped);
+        }
+        let mut value = 0;
+        assert!(limit(&mut value, LIMIT_MAX).is_err());
+    }
+
+    #[test]
+    fn test_ledger_and_code() {
+        assert_eq!(ledger("ledger", None), Ok(()));
+        assert_eq!(ledger("ledger", Some(1)), Ok(()));
+        assert_eq!(ledger("ledger", Some(u32::MAX)), Ok(()));
+        assert_eq!(
+            ledger("ledger", Some(0)),
+            Err("ledger: must be 1 to 4294967295".to_string())
+        );
+        assert_eq!(code("code", None), Ok(()));
+        assert_eq!(code("code", Some(1)), Ok(()));
+        assert_eq!(code("code", Some(u16::MAX)), Ok(()));
+        assert_eq!(
+            code("code", Some(0)),
+            Err("code: must be 1 to 65535".to_string())
+        );
+    }
+
+    #[test]
+    fn test_timestamp_range() {
+        for (min, max) in [
+            (None, None),
+            (Some(5), None),
+            (None, Some(5)),
+            (Some(5), Some(5)),
+            (Some(5), Some(6)),
+            // A max of zero is no bound.
+            (Some(5), Some(0)),
+        ] {
+            assert_eq!(timestamp_range(min, max), Ok(()), "{:?}", (min, max));
+        }
+        assert!(timestamp_range(Some(6), Some(5)).is_err());
+        assert!(timestamp_range(Some(u64::MAX), Some(1)).is_err());
+    }
+
+    #[test]
+    fn test_cursors() {
+        assert_eq!(cursors(None, None), Ok(()));
+        assert_eq!(cursors(Some(1), None), Ok(()));
+        assert_eq!(cursors(None, Some(1)), Ok(()));
+        assert!(cursors(Some(1), Some(2)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extractors() {
+        let (status, body) = get_uri("/api/v1/accounts/0xAb").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            body.contains(&format!("\"id\":\"{:032x}\"", 0xab)),
+            "{}",
+            body
+        );
+        let (status, body) = get_uri("/api/v1/ledgers/700/summary").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"ledger\":700"), "{}", body);
+        assert_eq!(get_params("/?limit=5000").await, "1000");
+        for uri in [
+            "/api/v1/accounts/xyz",
+            "/api/v1/accounts/xyz/transfers",
+            "/api/v1/transfers/0x",
+            "/api/v1/ledgers/0/summary",
+            "/api/v1/ledgers/-1/summary",
+            "/api/v1/accounts?limit=0",
+            "/api/v1/accounts?limit=x",
+        ] {
+            let (status, _) = get_uri(uri).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unprocessable_response() {
+        let (status, body) = get_uri("/api/v1/accounts/xyz").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "error": "id: invalid hex ID \"xyz\"", "code": "invalid_input" })
+        );
+
+        let response =
+            AppError::Unprocessable("[3].ledger: must be 1 to 4294967295".into()).into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "error": "[3].ledger: must be 1 to 4294967295", "code": "invalid_input" })
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! Warm first pages of the busiest lists.
+//!
+//! The dashboard opens on the newest transfers and the first accounts, and
+//! those pages stall whenever the cluster is slow. For each `--warm-ledger`
+//! (0 for the unfiltered lists), a background task keeps the newest
+//! `--warm-transfers` transfers and the oldest `--warm-accounts` accounts
+//! in memory, and the list handlers serve first pages from them when they
+//! hold every record asked for.
+//!
+//! Each poll asks only for what is new: the transfers after the newest one
+//! held, the accounts after the last one held while there are fewer than
+//! wanted, and the balances of held accounts the new transfers touched.
+//! Pending transfers that expire change balances without a new transfer,
+//! so every so often all held balances are looked up again.
+//!
+//! Warm lists are as of the last poll, even for writes made through
+//! tb-web. Ones not refreshed for [`STALE_AFTER`] are not served, so an
+//! unreachable cluster fails requests instead of showing old data.
+
+use crate::state::AppState;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tb_rs::{Account, ClientError, QueryFilter, QueryFilterFlags, Transfer};
+
+/// Pause between polls.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls between lookups of every held balance.
+const RESYNC_POLLS: u32 = 30;
+
+/// Age past which warm lists are not served.
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// What is held for one ledger.
+#[derive(Debug, Clone, Default)]
+struct Lists {
+    /// The oldest accounts, oldest first.
+    accounts: Vec<Account>,
+    /// The newest transfers, oldest first.
+    transfers: VecDeque<Transfer>,
+    /// Whether `transfers` is every transfer there is.
+    all_transfers: bool,
+}
+
+/// The lists as of one poll.
+#[derive(Debug)]
+struct Polled {
+    at: Instant,
+    lists: Lists,
+}
+
+/// The latest warm lists by ledger, and how many of each to hold.
+pub struct Warm {
+    accounts: usize,
+    transfers: usize,
+    latest: RwLock<BTreeMap<u32, Arc<Polled>>>,
+}
+
+impl Warm {
+    /// Hold up to `accounts` accounts and `transfers` transfers per ledger.
+    pub fn new(accounts: u32, transfers: u32) -> Self {
+        Self {
+            accounts: accounts as usize,
+            transfers: transfers as usize,
+            latest: RwLock::default(),
+        }
+    }
+
+    /// The first `limit` accounts on `ledger` (0 for all), oldest first, if
+    /// warm.
+    pub fn accounts(&self, ledger: u32, limit: u32) -> Option<Vec<Account>> {
+        let polled = self.fresh(ledger)?;
+        let accounts = &polled.lists.accounts;
+        // Fewer than wanted means there are no more.
+        if limit as usize > accounts.len() && accounts.len() == self.accounts {
+            return None;
+        }
+        Some(accounts.iter().take(limit as usize).copied().collect())
+    }
+
+    /// The first `limit` transfers on `ledger` (0 for all), newest first if
+    /// `reversed`, if warm.
+    pub fn transfers(&self, ledger: u32, limit: u32, reversed: bool) -> Option<Vec<Transfer>> {
+        let polled = self.fresh(ledger)?;
+        let lists = &polled.lists;
+        if !reversed {
+            return lists.all_transfers.then(|| {
+                lists
+                    .transfers
+                    .iter()
+                    .take(limit as usize)
+                    .copied()
+                    .collect()
+            });
+        }
+        if limit as usize > lists.transfers.len() && !lists.all_transfers {
+            return None;
+        }
+        Some(
+            lists
+                .transfers
+                .iter()
+                .rev()
+                .take(limit as usize)
+                .copied()
+                .collect(),
+        )
+    }
+
+    fn fresh(&self, ledger: u32) -> Option<Arc<Polled>> {
+        let polled = self.latest.read().unwrap().get(&ledger).cloned()?;
+        (polled.at.elapsed() < STALE_AFTER).then_some(polled)
+    }
+
+    fn publish(&self, ledger: u32, lists: &Lists) {
+        let polled = Arc::new(Polled {
+            at: Instant::now(),
+            lists: lists.clone(),
+        });
+        self.latest.write().unwrap().insert(ledger, polled);
+    }
+}
+
+/// Start keeping `ledgers` warm in `state.warm`.
+pub fn spawn(state: Arc<AppState>, ledgers: Vec<u32>) {
+    tokio::spawn(async move {
+        let mut held: BTreeMap<u32, Lists> = ledgers
+            .into_iter()
+            .map(|ledger| (ledger, Lists::default()))
+            .collect();
+        let mut polls = 0u32;
+        loop {
+            let resync = polls.is_multiple_of(RESYNC_POLLS);
+            for (&ledger, lists) in &mut held {
+                let started = Instant::now();
+                match poll(&state, ledger, lists, resync).await {
+                    Ok(()) => {
+                        tracing::trace!("Warmed ledger {} in {:?}", ledger, started.elapsed());
+                        state.warm.publish(ledger, lists);
+                    }
+                    Err(e) => tracing::warn!("Warming ledger {} failed: {:?}", ledger, e),
+                }
+            }
+            polls = polls.wrapping_add(1);
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// Bring `lists` up to date with the cluster, looking up every held
+/// balance again if `resync`.
+async fn poll(
+    state: &AppState,
+    ledger: u32,
+    lists: &mut Lists,
+    resync: bool,
+) -> Result<(), ClientError> {
+    let (max_accounts, max_transfers) = (state.warm.accounts, state.warm.transfers);
+
+    // The newest transfers since the last poll. As many as are held means
+    // some in between may be missing, so they replace the rest.
+    let since = lists.transfers.back().map_or(0, |t| t.timestamp);
+    let mut new = {
+        let client = state.pool.get();
+        client
+            .query_transfers(filter(
+                ledger,
+                since,
+                max_transfers as u32,
+                QueryFilterFlags::REVERSED,
+            ))
+            .await?
+    };
+    new.reverse();
+    if since == 0 {
+        lists.all_transfers = new.len() < max_transfers;
+    }
+    if new.len() == max_transfers {
+        lists.transfers.clear();
+        lists.all_transfers = false;
+    }
+    lists.transfers.extend(new.iter().copied());
+    while lists.transfers.len() > max_transfers {
+        lists.transfers.pop_front();
+        lists.all_transfers = false;
+    }
+
+    // The balances of held accounts that changed.
+    let ids: Vec<u128> = if resync {
+        lists.accounts.iter().map(|a| a.id).collect()
+    } else {
+        let touched: HashSet<u128> = new
+            .iter()
+            .flat_map(|t| [t.debit_account_id, t.credit_account_id])
+            .collect();
+        lists
+            .accounts
+            .iter()
+            .map(|a| a.id)
+            .filter(|id| touched.contains(id))
+            .collect()
+    };
+    if !ids.is_empty() {
+        let found = {
+            let client = state.pool.get();
+            client.lookup_accounts(&ids).await?
+        };
+        let found: BTreeMap<u128, Account> = found.into_iter().map(|a| (a.id, a)).collect();
+        for account in &mut lists.accounts {
+            if let Some(latest) = found.get(&account.id) {
+                *account = *latest;
+            }
+        }
+    }
+
+    // Accounts created since, while there are fewer than wanted.
+    if lists.accounts.len() < max_accounts {
+        let since = lists.accounts.last().map_or(0, |a| a.timestamp);
+        let more = {
+            let client = state.pool.get();
+            client
+                .query_accounts(filter(
+                    ledger,
+                    since,
+                    (max_accounts - lists.accounts.len()) as u32,
+                    QueryFilterFlags::empty(),
+                ))
+                .await?
+        };
+        lists.accounts.extend(more);
+    }
+    Ok(())
+}
+
+/// Up to `limit` records on `ledger` after `timestamp`.
+fn filter(ledger: u32, timestamp: u64, limit: u32, flags: QueryFilterFlags) -> QueryFilter {
+    QueryFilter {
+        user_data_128: 0,
+        user_data_64: 0,
+        user_data_32: 0,
+        ledger,
+        code: 0,
+        timestamp_min: timestamp + 1,
+        timestamp_max: 0,
+        limit,
+        flags,
+        reserved: [0; 6],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, account, transfer, FakeCluster};
+
+    fn account_ids(accounts: Option<Vec<Account>>) -> Option<Vec<u128>> {
+        accounts.map(|accounts| accounts.iter().map(|a| a.id).collect())
+    }
+
+    fn transfer_ids(transfers: Option<Vec<Transfer>>) -> Option<Vec<u128>> {
+        transfers.map(|transfers| transfers.iter().map(|t| t.id).collect())
+    }
+
+    fn lists(accounts: &[u128], transfers: &[u128], all_transfers: bool) -> Lists {
+        Lists {
+            accounts: accounts.iter().map(|&id| account(id, 1)).collect(),
+            transfers: transfers
+                .iter()
+                .map(|&id| transfer(id, 1, 2, 1, 1))
+                .collect(),
+            all_transfers,
+        }
+    }
+
+    #[test]
+    fn test_accounts() {
+        let warm = Warm::new(3, 3);
+        warm.publish(1, &lists(&[1, 2], &[], true));
+        warm.publish(2, &lists(&[3, 4, 5], &[], true));
+
+        // Fewer held than wanted: that is all there are.
+        assert_eq!(account_ids(warm.accounts(1, 1)), Some(vec![1]));
+        assert_eq!(account_ids(warm.accounts(1, 10)), Some(vec![1, 2]));
+
+        // As many as wanted: more than that may exist.
+        assert_eq!(account_ids(warm.accounts(2, 3)), Some(vec![3, 4, 5]));
+        assert_eq!(account_ids(warm.accounts(2, 4)), None);
+
+        // Ledgers not kept warm miss.
+        assert_eq!(account_ids(warm.accounts(0, 1)), None);
+    }
+
+    #[test]
+    fn test_transfers() {
+        let warm = Warm::new(3, 3);
+        warm.publish(1, &lists(&[], &[10, 11, 12], false));
+        warm.publish(2, &lists(&[], &[20, 21], true));
+
+        // Only the newest are held, so only newest-first pages they
+        // cover hit.
+        assert_eq!(transfer_ids(warm.transfers(1, 2, true)), Some(vec![12, 11]));
+        assert_eq!(
+            transfer_ids(warm.transfers(1, 3, true)),
+            Some(vec![12, 11, 10])
+        );
+        assert_eq!(transfer_ids(warm.transfers(1, 4, true)), None);
+        assert_eq!(transfer_ids(warm.transfers(1, 1, false)), None);
+
+        // Every transfer is held: any page hits, in either order.
+        assert_eq!(
+            transfer_ids(warm.transfers(2, 10, true)),
+            Some(vec![21, 20])
+        );
+        assert_eq!(transfer_ids(warm.transfers(2, 1, false)), Some(vec![20]));
+
+        assert_eq!(transfer_ids(warm.transfers(3, 1, true)), None);
+    }
+
+    #[test]
+    fn test_stale() {
+        let warm = Warm::new(3, 3);
+        warm.publish(1, &lists(&[1], &[10], true));
+        assert!(warm.accounts(1, 1).is_some());
+        let polled = Arc::new(Polled {
+            at: Instant::now() - STALE_AFTER,
+            lists: lists(&[1], &[10], true),
+        });
+        warm.latest.write().unwrap().insert(1, polled);
+        assert!(warm.accounts(1, 1).is_none());
+        assert!(warm.transfers(1, 1, true).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_poll() {
+        let cluster = FakeCluster::new();
+        cluster.seed(
+            &[account(1, 1), account(2, 1), account(3, 1), account(4, 2)],
+            &[transfer(10, 1, 2, 5, 1), transfer(11, 2, 1, 1, 1)],
+        );
+        let mut config = testing::config();
+        config.warm_accounts = 2;
+        config.warm_transfers = 2;
+        let state = testing::state(config, &cluster);
+        let mut lists = Lists::default();
+        poll(&state, 1, &mut lists, false).await.unwrap();
+        state.warm.publish(1, &lists);
+        assert_eq!(account_ids(state.warm.accounts(1, 2)), Some(vec![1, 2]));
+        assert_eq!(account_ids(state.warm.accounts(1, 3)), None);
+        // As many transfers as held may mean there are more.
+        assert_eq!(
+            transfer_ids(state.warm.transfers(1, 2, true)),
+            Some(vec![11, 10])
+        );
+        assert_eq!(transfer_ids(state.warm.transfers(1, 2, false)), None);
+
+        // A new transfer pushes out the oldest and updates the held
+        // balances it touched.
+        cluster.seed(&[], &[transfer(12, 1, 3, 7, 1)]);
+        poll(&state, 1, &mut lists, false).await.unwrap();
+        state.warm.publish(1, &lists);
+        assert_eq!(
+            transfer_ids(state.warm.transfers(1, 2, true)),
+            Some(vec![12, 11])
+        );
+        let accounts = state.warm.accounts(1, 1).unwrap();
+        assert_eq!(accounts[0].debits_posted, 12);
+    }
+}
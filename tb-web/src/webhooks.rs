@@ -0,0 +1,470 @@
+//! Outbound webhooks for new transfers.
+//!
+//! Each `--webhook` endpoint is sent the new transfers matching its filter
+//! as the background poller (see [`crate::live`]) finds them. One POST
+//! carries the matches from one poll:
+//!
+//! ```json
+//! {"delivery": "<hex ID>", "transfers": [{"id": "...", ...}]}
+//! ```
+//!
+//! Requests are signed with `--webhook-secret`: `X-TB-Web-Timestamp` is the
+//! Unix time of the attempt in seconds, and `X-TB-Web-Signature` is
+//! `sha256=` followed by the hex HMAC-SHA256 of the timestamp, a `.` and
+//! the body. Receivers should check both and reject stale timestamps.
+//!
+//! A delivery is retried with exponential backoff until the endpoint
+//! answers 2xx or [`ATTEMPTS_MAX`] attempts have failed. Each endpoint gets
+//! its deliveries in order, one at a time; while it is down, up to
+//! [`QUEUE_CAPACITY`] wait and newer ones are dropped. Deliveries start
+//! from the newest transfer at startup, so transfers created while tb-web
+//! was stopped are not sent. `GET /api/v1/webhooks` shows how each endpoint
+//! is doing.
+
+use crate::api::{ApiTransfer, WebhookPayload};
+use crate::currency::Currencies;
+use ring::hmac;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tb_rs::Transfer;
+use tokio::sync::mpsc;
+
+/// Attempts at one delivery before giving up on it.
+pub const ATTEMPTS_MAX: u32 = 6;
+
+/// Deliveries waiting per endpoint before new ones are dropped.
+pub const QUEUE_CAPACITY: usize = 1000;
+
+/// Wait before the first retry; doubled for each one after.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest an endpoint may take to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Finished deliveries remembered per endpoint for the status endpoint.
+const RECENT_MAX: usize = 50;
+
+/// An endpoint and the transfers it wants.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: reqwest::Url,
+    /// Only transfers on this ledger.
+    pub ledger: Option<u32>,
+    /// Only transfers with this code.
+    pub code: Option<u16>,
+    /// Only transfers debiting or crediting this account.
+    pub account: Option<u128>,
+}
+
+impl WebhookConfig {
+    fn matches(&self, transfer: &Transfer) -> bool {
+        self.ledger.is_none_or(|ledger| transfer.ledger == ledger)
+            && self.code.is_none_or(|code| transfer.code == code)
+            && self.account.is_none_or(|account| {
+                transfer.debit_account_id == account || transfer.credit_account_id == account
+            })
+    }
+}
+
+/// Parse a `URL[;ledger=N][;code=N][;account=HEX]` endpoint.
+pub fn parse_webhook(s: &str) -> Result<WebhookConfig, String> {
+    let mut parts = s.split(';');
+    let url = parts.next().unwrap_or_default().trim();
+    let url = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Invalid URL '{}': not http or https", url));
+    }
+
+    let mut config = WebhookConfig {
+        url,
+        ledger: None,
+        code: None,
+        account: None,
+    };
+    for part in parts {
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("Expected KEY=VALUE, got '{}'", part))?;
+        let value = value.trim();
+        let invalid = || format!("Invalid {} '{}'", key.trim(), value);
+        match key.trim() {
+            "ledger" => config.ledger = Some(value.parse().map_err(|_| invalid())?),
+            "code" => config.code = Some(value.parse().map_err(|_| invalid())?),
+            "account" => {
+                config.account = Some(u128::from_str_radix(value, 16).map_err(|_| invalid())?)
+            }
+            key => {
+                return Err(format!(
+                    "Unknown filter '{}' (ledger, code or account)",
+                    key
+                ))
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// The configured endpoints, each with a task delivering to it.
+pub struct Webhooks {
+    endpoints: Vec<Endpoint>,
+    currencies: Currencies,
+}
+
+struct Endpoint {
+    config: WebhookConfig,
+    queue: mpsc::Sender<Delivery>,
+    status: Arc<Mutex<Status>>,
+}
+
+/// Transfers to send in one request.
+struct Delivery {
+    id: u128,
+    body: String,
+    transfers: usize,
+}
+
+/// How an endpoint is doing.
+#[derive(Default)]
+struct Status {
+    delivered: u64,
+    failed: u64,
+    dropped: u64,
+    last_success: Option<SystemTime>,
+    last_error: Option<String>,
+    /// The delivery being attempted.
+    current: Option<DeliveryStatus>,
+    /// Finished deliveries, newest first.
+    recent: VecDeque<DeliveryStatus>,
+}
+
+/// One delivery's progress.
+#[derive(Debug, Clone)]
+pub struct DeliveryStatus {
+    pub id: u128,
+    pub transfers: usize,
+    pub attempts: u32,
+    /// Whether the endpoint accepted it; `None` while still trying.
+    pub delivered: Option<bool>,
+    pub last_attempt: Option<SystemTime>,
+    pub last_error: Option<String>,
+}
+
+/// Snapshot of an endpoint for the status endpoint.
+#[derive(Debug)]
+pub struct WebhookStatus {
+    pub config: WebhookConfig,
+    pub delivered: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    pub queued: usize,
+    pub last_success: Option<SystemTime>,
+    pub last_error: Option<String>,
+    /// The delivery being attempted, then finished ones, newest first.
+    pub deliveries: Vec<DeliveryStatus>,
+}
+
+impl Webhooks {
+    /// Start a delivery task for each endpoint in `configs`.
+    pub fn spawn(configs: Vec<WebhookConfig>, secret: &str, currencies: Currencies) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("HTTP client builds");
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+
+        let endpoints = configs
+            .into_iter()
+            .map(|config| {
+                let (queue, deliveries) = mpsc::channel(QUEUE_CAPACITY);
+                let status = Arc::new(Mutex::new(Status::default()));
+                tokio::spawn(deliver(
+                    http.clone(),
+                    key.clone(),
+                    config.url.clone(),
+                    deliveries,
+                    status.clone(),
+                ));
+                Endpoint {
+                    config,
+                    queue,
+                    status,
+                }
+            })
+            .collect();
+        Self {
+            endpoints,
+            currencies,
+        }
+    }
+
+    /// Whether any endpoint is configured.
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// Queue new `transfers` for the endpoints they match.
+    pub fn publish(&self, transfers: &[Transfer]) {
+        for endpoint in &self.endpoints {
+            let matching: Vec<ApiTransfer> = transfers
+                .iter()
+                .filter(|t| endpoint.config.matches(t))
+                .map(|t| ApiTransfer::from(t).with_display(&self.currencies))
+                .collect();
+            if matching.is_empty() {
+                continue;
+            }
+
+            let id = tb_rs::id();
+            let payload = WebhookPayload {
+                delivery: format!("{:032x}", id),
+                transfers: matching,
+            };
+            let delivery = Delivery {
+                id,
+                body: serde_json::to_string(&payload).expect("API types serialize"),
+                transfers: payload.transfers.len(),
+            };
+            if endpoint.queue.try_send(delivery).is_err() {
+                let mut status = endpoint.status.lock().unwrap();
+                status.dropped += 1;
+                tracing::warn!(
+                    "Webhook {} is {} deliveries behind, dropping one",
+                    endpoint.config.url,
+                    QUEUE_CAPACITY
+                );
+            }
+        }
+    }
+
+    /// How each endpoint is doing.
+    pub fn status(&self) -> Vec<WebhookStatus> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                let status = endpoint.status.lock().unwrap();
+                WebhookStatus {
+                    config: endpoint.config.clone(),
+                    delivered: status.delivered,
+                    failed: status.failed,
+                    dropped: status.dropped,
+                    queued: QUEUE_CAPACITY - endpoint.queue.capacity(),
+                    last_success: status.last_success,
+                    last_error: status.last_error.clone(),
+                    deliveries: status
+                        .current
+                        .iter()
+                        .chain(&status.recent)
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Send the deliveries queued for `url`, in order, until tb-web stops.
+async fn deliver(
+    http: reqwest::Client,
+    key: hmac::Key,
+    url: reqwest::Url,
+    mut deliveries: mpsc::Receiver<Delivery>,
+    status: Arc<Mutex<Status>>,
+) {
+    while let Some(delivery) = deliveries.recv().await {
+        status.lock().unwrap().current = Some(DeliveryStatus {
+            id: delivery.id,
+            transfers: delivery.transfers,
+            attempts: 0,
+            delivered: None,
+            last_attempt: None,
+            last_error: None,
+        });
+
+        let mut delay = RETRY_DELAY;
+        let mut delivered = false;
+        for attempt in 1..=ATTEMPTS_MAX {
+            if attempt > 1 {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            let result = send(&http, &key, &url, &delivery.body).await;
+
+            let mut status = status.lock().unwrap();
+            let current = status.current.as_mut().expect("delivery in progress");
+            current.attempts = attempt;
+            current.last_attempt = Some(SystemTime::now());
+            match result {
+                Ok(()) => {
+                    status.last_success = Some(SystemTime::now());
+                    delivered = true;
+                    break;
+                }
+                Err(e) => {
+                    tracing::debug!(
+                        "Webhook {} delivery {:032x} attempt {} failed: {}",
+                        url,
+                        delivery.id,
+                        attempt,
+                        e
+                    );
+                    current.last_error = Some(e.clone());
+                    status.last_error = Some(e);
+                }
+            }
+        }
+
+        let mut status = status.lock().unwrap();
+        if delivered {
+            status.delivered += 1;
+        } else {
+            status.failed += 1;
+            tracing::warn!(
+                "Webhook {} delivery {:032x} failed after {} attempts",
+                url,
+                delivery.id,
+                ATTEMPTS_MAX
+            );
+        }
+        let mut finished = status.current.take().expect("delivery in progress");
+        finished.delivered = Some(delivered);
+        status.recent.push_front(finished);
+        status.recent.truncate(RECENT_MAX);
+    }
+}
+
+/// POST `body` to `url`, signed with `key`.
+async fn send(
+    http: &reqwest::Client,
+    key: &hmac::Key,
+    url: &reqwest::Url,
+    body: &str,
+) -> Result<(), String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        .to_string();
+    let response = http
+        .post(url.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-TB-Web-Timestamp", &timestamp)
+        .header("X-TB-Web-Signature", signature(key, &timestamp, body))
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    Ok(())
+}
+
+/// The `X-TB-Web-Signature` of `body` sent at `timestamp`.
+fn signature(key: &hmac::Key, timestamp: &str, body: &str) -> String {
+    let mut context = hmac::Context::with_key(key);
+    context.update(timestamp.as_bytes());
+    context.update(b".");
+    context.update(body.as_bytes());
+    let mut signature = String::from("sha256=");
+    for byte in context.sign().as_ref() {
+        let _ = write!(signature, "{:02x}", byte);
+    }
+    signature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(ledger: u32, code: u16, debit: u128, credit: u128) -> Transfer {
+        Transfer {
+            ledger,
+            code,
+            debit_account_id: debit,
+            credit_account_id: credit,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        // HMAC-SHA256 of "1700000000.<body>" under "whsec_test", as computed
+        // by Python's hmac module.
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"whsec_test");
+        let body = r#"{"delivery":"01","transfers":[]}"#;
+        assert_eq!(
+            signature(&key, "1700000000", body),
+            "sha256=e4a8eb5596c6a3dd935d957d84f30550d42aab577c8125af06f1276bf9ec0e6b"
+        );
+        assert_ne!(
+            signature(&key, "1700000001", body),
+            signature(&key, "1700000000", body)
+        );
+    }
+
+    #[test]
+    fn test_parse_webhook() {
+        let config = parse_webhook("https://example.com/hook").unwrap();
+        assert_eq!(config.url.as_str(), "https://example.com/hook");
+        assert_eq!(
+            (config.ledger, config.code, config.account),
+            (None, None, None)
+        );
+
+        let config =
+            parse_webhook("http://example.com/hook; ledger=700 ;code=10;account=ff").unwrap();
+        assert_eq!(config.ledger, Some(700));
+        assert_eq!(config.code, Some(10));
+        assert_eq!(config.account, Some(0xff));
+    }
+
+    #[test]
+    fn test_parse_webhook_invalid() {
+        for (s, error) in [
+            ("", "Invalid URL"),
+            ("example.com/hook", "Invalid URL"),
+            ("ftp://example.com/hook", "not http or https"),
+            ("https://example.com/hook;ledger", "Expected KEY=VALUE"),
+            ("https://example.com/hook;ledger=-1", "Invalid ledger '-1'"),
+            (
+                "https://example.com/hook;code=65536",
+                "Invalid code '65536'",
+            ),
+            (
+                "https://example.com/hook;account=0xff",
+                "Invalid account '0xff'",
+            ),
+            (
+                "https://example.com/hook;account=xyz",
+                "Invalid account 'xyz'",
+            ),
+            (
+                "https://example.com/hook;amount=1",
+                "Unknown filter 'amount'",
+            ),
+        ] {
+            let result = parse_webhook(s);
+            assert!(
+                matches!(&result, Err(e) if e.contains(error)),
+                "{:?}: {:?}",
+                s,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let all = parse_webhook("https://example.com/hook").unwrap();
+        assert!(all.matches(&transfer(1, 1, 1, 2)));
+
+        let config = parse_webhook("https://example.com/hook;ledger=7;code=3;account=a").unwrap();
+        assert!(config.matches(&transfer(7, 3, 0xa, 2)));
+        assert!(config.matches(&transfer(7, 3, 2, 0xa)));
+        assert!(!config.matches(&transfer(8, 3, 0xa, 2)));
+        assert!(!config.matches(&transfer(7, 4, 0xa, 2)));
+        assert!(!config.matches(&transfer(7, 3, 1, 2)));
+    }
+}
@@ -0,0 +1,465 @@
+//! A streaming ZIP archive writer.
+//!
+//! Entries are deflated as they are written and handed out a chunk at a
+//! time, so an archive of any size can be sent without holding it. Sizes
+//! and checksums are not known until an entry ends, so they follow its data
+//! in a data descriptor, and every size and offset is written in ZIP64 form
+//! in case the archive passes 4 GiB.
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::Write;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const END: u32 = 0x0605_4b50;
+
+/// ZIP 4.5, the first with ZIP64.
+const VERSION: u16 = 45;
+/// Sizes in a data descriptor, and UTF-8 names.
+const FLAGS: u16 = 0x0008 | 0x0800;
+const DEFLATE: u16 = 8;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+/// An archive being written.
+pub struct ZipWriter {
+    /// DOS time and date of every entry.
+    modified: (u16, u16),
+    /// Bytes not yet taken.
+    out: Vec<u8>,
+    /// Bytes written so far, taken or not.
+    offset: u64,
+    entries: Vec<Entry>,
+    current: Option<Current>,
+}
+
+/// A finished entry, for the central directory.
+struct Entry {
+    name: String,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// The entry being written.
+struct Current {
+    name: String,
+    offset: u64,
+    encoder: DeflateEncoder<Vec<u8>>,
+    crc: Crc,
+    size: u64,
+    compressed: u64,
+}
+
+impl ZipWriter {
+    /// An empty archive whose entries were last modified at `modified`.
+    pub fn new(modified: DateTime<Utc>) -> Self {
+        // DOS dates start in 1980.
+        let year = modified.year().clamp(1980, 2107) as u16;
+        let time = ((modified.hour() as u16) << 11)
+            | ((modified.minute() as u16) << 5)
+            | (modified.second() as u16 / 2);
+        let date = ((year - 1980) << 9) | ((modified.month() as u16) << 5) | modified.day() as u16;
+        Self {
+            modified: (time, date),
+            out: Vec::new(),
+            offset: 0,
+            entries: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Start an entry named `name`, ending the one before.
+    pub fn start(&mut self, name: &str) {
+        self.end_entry();
+        let offset = self.offset;
+        let mut header = Vec::with_capacity(50 + name.len());
+        put32(&mut header, LOCAL_HEADER);
+        put16(&mut header, VERSION);
+        put16(&mut header, FLAGS);
+        put16(&mut header, DEFLATE);
+        put16(&mut header, self.modified.0);
+        put16(&mut header, self.modified.1);
+        // Checksum and sizes are in the data descriptor.
+        put32(&mut header, 0);
+        put32(&mut header, u32::MAX);
+        put32(&mut header, u32::MAX);
+        put16(&mut header, name.len() as u16);
+        put16(&mut header, 20);
+        header.extend_from_slice(name.as_bytes());
+        put16(&mut header, ZIP64_EXTRA);
+        put16(&mut header, 16);
+        put64(&mut header, 0);
+        put64(&mut header, 0);
+        self.emit(&header);
+        self.current = Some(Current {
+            name: name.to_string(),
+            offset,
+            encoder: DeflateEncoder::new(Vec::new(), Compression::default()),
+            crc: Crc::new(),
+            size: 0,
+            compressed: 0,
+        });
+    }
+
+    /// Add `data` to the current entry.
+    pub fn write(&mut self, data: &[u8]) {
+        let current = self.current.as_mut().expect("an entry is started");
+        current.crc.update(data);
+        current.size += data.len() as u64;
+        current
+            .encoder
+            .write_all(data)
+            .expect("writing to a Vec succeeds");
+        let compressed = std::mem::take(current.encoder.get_mut());
+        current.compressed += compressed.len() as u64;
+        self.emit(&compressed);
+    }
+
+    /// The bytes written since the last call.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.out)
+    }
+
+    /// End the archive, returning its last bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.end_entry();
+        let directory_offset = self.offset;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            put32(&mut directory, CENTRAL_HEADER);
+            put16(&mut directory, VERSION);
+            put16(&mut directory, VERSION);
+            put16(&mut directory, FLAGS);
+            put16(&mut directory, DEFLATE);
+            put16(&mut directory, self.modified.0);
+            put16(&mut directory, self.modified.1);
+            put32(&mut directory, entry.crc);
+            put32(&mut directory, u32::MAX);
+            put32(&mut directory, u32::MAX);
+            put16(&mut directory, entry.name.len() as u16);
+            put16(&mut directory, 28);
+            // Comment length, disk, internal and external attributes.
+            put16(&mut directory, 0);
+            put16(&mut directory, 0);
+            put16(&mut directory, 0);
+            put32(&mut directory, 0);
+            put32(&mut directory, u32::MAX);
+            directory.extend_from_slice(entry.name.as_bytes());
+            put16(&mut directory, ZIP64_EXTRA);
+            put16(&mut directory, 24);
+            put64(&mut directory, entry.size);
+            put64(&mut directory, entry.compressed);
+            put64(&mut directory, entry.offset);
+        }
+        let directory_size = directory.len() as u64;
+        self.emit(&directory);
+
+        let zip64_end_offset = self.offset;
+        let count = self.entries.len() as u64;
+        let mut end = Vec::with_capacity(98);
+        put32(&mut end, ZIP64_END);
+        put64(&mut end, 44);
+        put16(&mut end, VERSION);
+        put16(&mut end, VERSION);
+        put32(&mut end, 0);
+        put32(&mut end, 0);
+        put64(&mut end, count);
+        put64(&mut end, count);
+        put64(&mut end, directory_size);
+        put64(&mut end, directory_offset);
+
+        put32(&mut end, ZIP64_LOCATOR);
+        put32(&mut end, 0);
+        put64(&mut end, zip64_end_offset);
+        put32(&mut end, 1);
+
+        put32(&mut end, END);
+        put16(&mut end, 0);
+        put16(&mut end, 0);
+        put16(&mut end, count.min(u16::MAX as u64) as u16);
+        put16(&mut end, count.min(u16::MAX as u64) as u16);
+        put32(&mut end, directory_size.min(u32::MAX as u64) as u32);
+        put32(&mut end, directory_offset.min(u32::MAX as u64) as u32);
+        put16(&mut end, 0);
+        self.emit(&end);
+        self.out
+    }
+
+    /// End the current entry, if any, with its data descriptor.
+    fn end_entry(&mut self) {
+        let Some(mut current) = self.current.take() else {
+            return;
+        };
+        let rest = current.encoder.finish().expect("writing to a Vec succeeds");
+        current.compressed += rest.len() as u64;
+        self.emit(&rest);
+
+        let entry = Entry {
+            name: current.name,
+            crc: current.crc.sum(),
+            compressed: current.compressed,
+            size: current.size,
+            offset: current.offset,
+        };
+        let mut descriptor = Vec::with_capacity(24);
+        put32(&mut descriptor, DATA_DESCRIPTOR);
+        put32(&mut descriptor, entry.crc);
+        put64(&mut descriptor, entry.compressed);
+        put64(&mut descriptor, entry.size);
+        self.emit(&descriptor);
+        self.entries.push(entry);
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+        self.offset += bytes.len() as u64;
+    }
+}
+
+fn put16(out: &mut Vec<u8>, n: u16) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    /// Where the bytes taken from a writer went, by offset; bytes skipped
+    /// over are not kept.
+    #[derive(Default)]
+    struct Sink {
+        chunks: Vec<(u64, Vec<u8>)>,
+        len: u64,
+    }
+
+    impl Sink {
+        fn take(&mut self, zip: &mut ZipWriter) {
+            self.put(zip.take());
+        }
+
+        fn put(&mut self, bytes: Vec<u8>) {
+            let len = bytes.len() as u64;
+            self.chunks.push((self.len, bytes));
+            self.len += len;
+        }
+
+        /// Pretend the current entry went on with `size` bytes deflated to
+        /// `compressed`, already taken.
+        fn skip(&mut self, zip: &mut ZipWriter, size: u64, compressed: u64) {
+            self.take(zip);
+            let current = zip.current.as_mut().unwrap();
+            current.size += size;
+            current.compressed += compressed;
+            zip.offset += compressed;
+            self.len += compressed;
+        }
+
+        /// The bytes kept from `offset` to the end of their chunk.
+        fn at(&self, offset: u64) -> &[u8] {
+            let (start, chunk) = self
+                .chunks
+                .iter()
+                .rfind(|(start, _)| *start <= offset)
+                .unwrap();
+            &chunk[(offset - start) as usize..]
+        }
+
+        /// `len` kept bytes from `offset`, across chunks.
+        fn read(&self, offset: u64, len: u64) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            while (bytes.len() as u64) < len {
+                let chunk = self.at(offset + bytes.len() as u64);
+                let rest = (len as usize - bytes.len()).min(chunk.len());
+                bytes.extend_from_slice(&chunk[..rest]);
+            }
+            bytes
+        }
+    }
+
+    fn get16(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn get32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn get64(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    /// The entries in the central directory of the archive in `sink`,
+    /// after checking the end records agree on where it is.
+    fn directory(sink: &Sink) -> Vec<Entry> {
+        let end = sink.read(sink.len - 22, 22);
+        assert_eq!(get32(&end, 0), END);
+        let locator = sink.read(sink.len - 42, 20);
+        assert_eq!(get32(&locator, 0), ZIP64_LOCATOR);
+        let zip64_end_offset = get64(&locator, 8);
+        assert_eq!(zip64_end_offset, sink.len - 98);
+        let zip64_end = sink.read(zip64_end_offset, 56);
+        assert_eq!(get32(&zip64_end, 0), ZIP64_END);
+        assert_eq!(get64(&zip64_end, 4), 44);
+
+        let count = get64(&zip64_end, 32);
+        let size = get64(&zip64_end, 40);
+        let offset = get64(&zip64_end, 48);
+        assert_eq!(offset + size, zip64_end_offset);
+        assert_eq!(get16(&end, 10) as u64, count.min(u16::MAX as u64));
+        assert_eq!(get32(&end, 12) as u64, size.min(u32::MAX as u64));
+        assert_eq!(get32(&end, 16) as u64, offset.min(u32::MAX as u64));
+
+        let directory = sink.read(offset, size);
+        let mut entries = Vec::new();
+        let mut at = 0;
+        for _ in 0..count {
+            assert_eq!(get32(&directory, at), CENTRAL_HEADER);
+            assert_eq!(get32(&directory, at + 20), u32::MAX);
+            assert_eq!(get32(&directory, at + 24), u32::MAX);
+            assert_eq!(get32(&directory, at + 42), u32::MAX);
+            let name_len = get16(&directory, at + 28) as usize;
+            let extra = at + 46 + name_len;
+            assert_eq!(get16(&directory, extra), ZIP64_EXTRA);
+            assert_eq!(get16(&directory, extra + 2), 24);
+            entries.push(Entry {
+                name: String::from_utf8(directory[at + 46..extra].to_vec()).unwrap(),
+                crc: get32(&directory, at + 16),
+                size: get64(&directory, extra + 4),
+                compressed: get64(&directory, extra + 12),
+                offset: get64(&directory, extra + 20),
+            });
+            at = extra + 28;
+        }
+        assert_eq!(at, directory.len());
+        entries
+    }
+
+    /// Check `entry`'s local header and data descriptor, returning where
+    /// its data starts.
+    fn check_local(sink: &Sink, entry: &Entry) -> u64 {
+        let header = sink.read(entry.offset, 30);
+        assert_eq!(get32(&header, 0), LOCAL_HEADER);
+        assert_eq!(get16(&header, 6), FLAGS);
+        assert_eq!(get16(&header, 8), DEFLATE);
+        let name_len = get16(&header, 26) as u64;
+        let name = sink.read(entry.offset + 30, name_len);
+        assert_eq!(name, entry.name.as_bytes());
+        let data = entry.offset + 30 + name_len + 20;
+
+        let descriptor = sink.read(data + entry.compressed, 24);
+        assert_eq!(get32(&descriptor, 0), DATA_DESCRIPTOR);
+        assert_eq!(get32(&descriptor, 4), entry.crc);
+        assert_eq!(get64(&descriptor, 8), entry.compressed);
+        assert_eq!(get64(&descriptor, 16), entry.size);
+        data
+    }
+
+    /// Check `entry` holds `contents`.
+    fn check_entry(sink: &Sink, entry: &Entry, contents: &[u8]) {
+        let data = check_local(sink, entry);
+        let compressed = sink.read(data, entry.compressed);
+        let mut inflated = Vec::new();
+        DeflateDecoder::new(&compressed[..])
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, contents);
+        let mut crc = Crc::new();
+        crc.update(contents);
+        assert_eq!(entry.crc, crc.sum());
+        assert_eq!(entry.size, contents.len() as u64);
+    }
+
+    fn modified() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 17, 13, 45, 31).unwrap()
+    }
+
+    #[test]
+    fn test_new_dos_time() {
+        let zip = ZipWriter::new(modified());
+        assert_eq!(
+            zip.modified,
+            ((13 << 11) | (45 << 5) | 15, (44 << 9) | (5 << 5) | 17)
+        );
+        let zip = ZipWriter::new(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(zip.modified, (0, (1 << 5) | 1));
+    }
+
+    #[test]
+    fn test_entries() {
+        let contents: [(&str, Vec<u8>); 3] = [
+            ("accounts.ndjson", b"{\"id\":\"1\"}\n".repeat(1000)),
+            ("empty.ndjson", Vec::new()),
+            (
+                "transfers.ndjson",
+                (0..100_000u32).flat_map(u32::to_le_bytes).collect(),
+            ),
+        ];
+        let mut sink = Sink::default();
+        let mut zip = ZipWriter::new(modified());
+        for (name, data) in &contents {
+            zip.start(name);
+            for chunk in data.chunks(4096) {
+                zip.write(chunk);
+                sink.take(&mut zip);
+            }
+        }
+        sink.put(zip.finish());
+
+        let entries = directory(&sink);
+        assert_eq!(entries.len(), contents.len());
+        assert_eq!(entries[0].offset, 0);
+        for (entry, (name, data)) in entries.iter().zip(&contents) {
+            assert_eq!(entry.name, *name);
+            check_entry(&sink, entry, data);
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut sink = Sink::default();
+        sink.put(ZipWriter::new(modified()).finish());
+        assert_eq!(sink.len, 98);
+        assert!(directory(&sink).is_empty());
+    }
+
+    #[test]
+    fn test_zip64() {
+        const GIB: u64 = 1 << 30;
+        let mut sink = Sink::default();
+        let mut zip = ZipWriter::new(modified());
+        zip.start("big.ndjson");
+        sink.skip(&mut zip, 6 * GIB, 5 * GIB);
+        zip.start("small.ndjson");
+        zip.write(b"past 4 GiB");
+        sink.take(&mut zip);
+        sink.put(zip.finish());
+
+        let entries = directory(&sink);
+        assert_eq!(entries.len(), 2);
+        let (big, small) = (&entries[0], &entries[1]);
+        assert_eq!(big.name, "big.ndjson");
+        assert_eq!(big.offset, 0);
+        assert_eq!(big.size, 6 * GIB);
+        assert!(big.compressed > 5 * GIB);
+        check_local(&sink, big);
+        assert!(small.offset > 5 * GIB);
+        check_entry(&sink, small, b"past 4 GiB");
+    }
+}